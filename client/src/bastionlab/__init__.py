@@ -9,7 +9,18 @@ __pdoc__ = {"pb": False, "version": False}
 
 from .client import Connection, Client
 from .keys import SigningKey, PublicKey, Identity
-from .errors import RequestRejected, GRPCException
+from .errors import (
+    RequestRejected,
+    GRPCException,
+    SessionError,
+    DTypeError,
+    ConversionError,
+    LabelsNotFound,
+    TrainingDiverged,
+    AccessDenied,
+    LimitExceeded,
+    AuthenticationLockedOut,
+)
 
 # Note that we don't reexport bastionlab.polars and bastionlab.torch here
 #  as doing so would mean importing polars/torch which is not great
@@ -31,5 +42,13 @@ __all__ = [
     "Identity",
     "RequestRejected",
     "GRPCException",
+    "SessionError",
+    "DTypeError",
+    "ConversionError",
+    "LabelsNotFound",
+    "TrainingDiverged",
+    "AccessDenied",
+    "LimitExceeded",
+    "AuthenticationLockedOut",
     "version",
 ]
@@ -1,16 +1,32 @@
 import ssl
-from typing import Any, TYPE_CHECKING, Optional
+from typing import Any, Iterator, List, TYPE_CHECKING, Optional
 from hashlib import sha256
 import grpc
 from .keys import SigningKey
 from .pb.bastionlab_pb2 import Empty
 from .pb.bastionlab_pb2 import ClientInfo
+from .pb.bastionlab_pb2 import (
+    Notification,
+    NotificationFilter,
+    Provenance,
+    ProvenanceArtifact,
+    ServerCapabilities,
+    Webhook,
+    WebhookReference,
+)
 from .version import __version__ as app_version
-from .pb.bastionlab_pb2_grpc import SessionServiceStub
+from .pb.bastionlab_pb2_grpc import (
+    CapabilitiesServiceStub,
+    NotificationServiceStub,
+    ProvenanceServiceStub,
+    SessionServiceStub,
+)
+from .errors import GRPCException
 import platform
 import socket
 import getpass
 import time
+import threading
 import logging
 import sys
 
@@ -81,7 +97,11 @@ class Client:
         """
         self._channel = channel
         self.__session_stub = SessionServiceStub(channel)
+        self.__notification_stub = NotificationServiceStub(channel)
+        self.__provenance_stub = ProvenanceServiceStub(channel)
+        self.__capabilities_stub = CapabilitiesServiceStub(channel)
         self.signing_key = signing_key
+        self._heartbeat_stop: Optional[threading.Event] = None
 
     def _refresh_session_if_needed(self):
         current_time = time.time()
@@ -115,6 +135,103 @@ class Client:
         )
         self._token = res.token
 
+    def start_heartbeat(self, interval: float = 60.0) -> None:
+        """Tells the server the client is still there every `interval` seconds, from a
+        background thread.
+
+        Servers configured to do so cancel the trainings and release the tensors and
+        query results of clients that went silent.
+        """
+        if self._heartbeat_stop is not None:
+            return
+        stop = threading.Event()
+        self._heartbeat_stop = stop
+
+        def beat():
+            while not stop.wait(interval):
+                try:
+                    self._refresh_session_if_needed()
+                    self.__session_stub.Heartbeat(Empty())
+                except grpc.RpcError as e:
+                    logging.debug(f"Heartbeat failed: {e}")
+
+        threading.Thread(target=beat, daemon=True).start()
+
+    def stop_heartbeat(self) -> None:
+        """Stops the heartbeats started with `start_heartbeat`."""
+        if self._heartbeat_stop is not None:
+            self._heartbeat_stop.set()
+            self._heartbeat_stop = None
+
+    def notifications(self, kinds: Optional[List[int]] = None) -> Iterator[Notification]:
+        """Yields the notifications about the runs of the current user as they happen,
+        or all notifications for data owners: trainings started on datasets, fetches
+        waiting for approval and privacy budget thresholds crossed, among others.
+
+        Args:
+            kinds: The `NotificationKind` values to receive, all of them by default.
+        """
+        self._refresh_session_if_needed()
+
+        filter = NotificationFilter(kinds=kinds or [])
+        return GRPCException._map_error(
+            lambda: self.__notification_stub.Subscribe(filter)
+        )
+
+    def register_webhook(
+        self, url: str, kinds: Optional[List[int]] = None
+    ) -> WebhookReference:
+        """Registers an https URL that the server POSTs the notifications to as JSON.
+
+        The URL must be on one of the hosts allowed by the server's `webhook_hosts`
+        config. Webhooks are forgotten when the server restarts.
+
+        Args:
+            url: The URL notifications are sent to.
+            kinds: The `NotificationKind` values to send, all of them by default.
+        """
+        self._refresh_session_if_needed()
+
+        webhook = Webhook(url=url, filter=NotificationFilter(kinds=kinds or []))
+        return GRPCException._map_error(
+            lambda: self.__notification_stub.RegisterWebhook(webhook)
+        )
+
+    def delete_webhook(self, webhook: WebhookReference) -> None:
+        """Stops sending notifications to the given webhook."""
+        self._refresh_session_if_needed()
+
+        GRPCException._map_error(
+            lambda: self.__notification_stub.DeleteWebhook(webhook)
+        )
+
+    def provenance(self, kind: int, identifier: str) -> Provenance:
+        """Returns an artifact and every data frame, array, dataset, model, run and checkpoint
+        it derives from, e.g. the data that influenced a model when given the `CHECKPOINT`
+        kind and the identifier of the model. Only available to data owners.
+
+        Args:
+            kind: The `ProvenanceKind` of the artifact.
+            identifier: The identifier of the artifact.
+        """
+        self._refresh_session_if_needed()
+
+        artifact = ProvenanceArtifact(kind=kind, identifier=identifier)
+        return GRPCException._map_error(
+            lambda: self.__provenance_stub.GetProvenance(artifact)
+        )
+
+    def server_capabilities(self) -> ServerCapabilities:
+        """Returns the versions of LibTorch and CUDA of the server, the dtypes and services
+        it supports, its authentication mode, attestation backend and configured limits,
+        along with the results of the self-tests it ran on startup.
+        """
+        self._refresh_session_if_needed()
+
+        return GRPCException._map_error(
+            lambda: self.__capabilities_stub.GetServerCapabilities(Empty())
+        )
+
     @property
     def torch(self) -> "bastionlab.torch.BastionLabTorch":
         """
@@ -288,6 +405,7 @@ class Connection:
         )
 
         auth_plugin.client = self.client
+        self._client.start_heartbeat()
 
         return self._client
 
@@ -299,6 +417,8 @@ class Connection:
            exc_value: The value of the exception that caused the `with` statement to exit.
            exc_traceback: The traceback of the exception that caused the `with` statement to exit.
         """
+        if self._client is not None:
+            self._client.stop_heartbeat()
         self._client = None
         self.channel.close()
 
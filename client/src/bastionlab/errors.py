@@ -1,11 +1,14 @@
 import grpc  # type: ignore [import]
 from grpc._channel import _InactiveRpcError, _MultiThreadedRendezvous  # type: ignore [import]
 from dataclasses import dataclass
-from typing import Callable, TypeVar, Union
+from typing import Callable, ClassVar, Dict, Optional, TypeVar, Union
 
 
 T = TypeVar("T")
 
+ERROR_CODE_KEY = "bastionlab-error-code"
+ERROR_DETAIL_PREFIX = "bastionlab-detail-"
+
 
 @dataclass
 class RequestRejected(Exception):
@@ -37,6 +40,8 @@ class GRPCException(Exception):
 
     err: Union[grpc._channel._InactiveRpcError, grpc._channel._MultiThreadedRendezvous]
 
+    _prefix: ClassVar[Optional[str]] = None
+
     @property
     def code(self) -> grpc.StatusCode:
         """
@@ -44,7 +49,33 @@ class GRPCException(Exception):
         """
         return self.err._state.code
 
+    @property
+    def error_code(self) -> str:
+        """
+        The name of the BastionLab error code sent by the server, e.g. `SESSION_EXPIRED`,
+        or `UNKNOWN_ERROR` if the server did not classify the error.
+        """
+        for key, value in self.err.trailing_metadata() or ():
+            if key == ERROR_CODE_KEY:
+                return value
+        return "UNKNOWN_ERROR"
+
+    @property
+    def details(self) -> Dict[str, str]:
+        """
+        The details of the error sent by the server, by name, e.g. the epoch at which a
+        training diverged.
+        """
+        return {
+            key[len(ERROR_DETAIL_PREFIX) :]: value
+            for key, value in self.err.trailing_metadata() or ()
+            if key.startswith(ERROR_DETAIL_PREFIX)
+        }
+
     def __str__(self):
+        if self._prefix is not None:
+            return f"{self._prefix}: error_code={self.error_code} message={self.err.details()}"
+
         if self.code == grpc.StatusCode.NOT_FOUND:
             prefix = "Remote resource not found"
         elif self.code == grpc.StatusCode.INVALID_ARGUMENT:
@@ -78,11 +109,133 @@ class GRPCException(Exception):
             The result of calling `f`, if no errors were raised.
 
         Raises:
-            GRPCException: if `f` raised a gRPC error.
+            GRPCException: if `f` raised a gRPC error, as the subclass matching its error
+                code if there is one.
         """
         try:
             return f()
         except _InactiveRpcError as e:
-            raise GRPCException(e)
+            raise GRPCException._from_rpc_error(e)
         except _MultiThreadedRendezvous as e:
-            raise GRPCException(e)
+            raise GRPCException._from_rpc_error(e)
+
+    @staticmethod
+    def _from_rpc_error(
+        err: Union[
+            grpc._channel._InactiveRpcError, grpc._channel._MultiThreadedRendezvous
+        ]
+    ) -> "GRPCException":
+        """
+        Wraps `err` in the subclass of `GRPCException` matching its BastionLab error code,
+        or in a `GRPCException` if there is none.
+        """
+        exception = GRPCException(err)
+        return _EXCEPTIONS.get(exception.error_code, GRPCException)(err)
+
+
+class SessionError(GRPCException):
+    """
+    Raised when the session of the client is not found, expired, or used from another
+    address. Reconnecting to the server creates a new session.
+    """
+
+    _prefix = "Invalid session"
+
+
+class DTypeError(GRPCException):
+    """
+    Raised when data of different dtypes are combined, or when a dtype is not supported
+    by an operation.
+    """
+
+    _prefix = "Unsupported or mismatched dtypes"
+
+
+class ConversionError(GRPCException):
+    """
+    Raised when data could not be converted between data frames, arrays and tensors, e.g.
+    because lists of a column have different shapes or strings were not tokenized.
+    """
+
+    _prefix = "Conversion failed"
+
+
+class LabelsNotFound(GRPCException):
+    """
+    Raised when an operation needs the labels of a dataset which has none.
+    """
+
+    _prefix = "Labels not found"
+
+
+class TrainingDiverged(GRPCException):
+    """
+    Raised when the loss or the weights became NaN or infinite during training. Lowering
+    the learning rate or clipping the gradients usually helps.
+    """
+
+    _prefix = "Training diverged"
+
+    @property
+    def epoch(self) -> Optional[int]:
+        """
+        The epoch at which the training diverged.
+        """
+        epoch = self.details.get("epoch")
+        return int(epoch) if epoch is not None else None
+
+    @property
+    def batch(self) -> Optional[int]:
+        """
+        The batch at which the training diverged.
+        """
+        batch = self.details.get("batch")
+        return int(batch) if batch is not None else None
+
+
+class AccessDenied(GRPCException):
+    """
+    Raised when the policy or license of the data does not allow an operation, or when a
+    data owner rejected a fetch after reviewing it.
+    """
+
+    _prefix = "Access denied"
+
+
+class LimitExceeded(GRPCException):
+    """
+    Raised when the privacy budget of a data frame is exhausted, or when too many results
+    of a data frame were fetched over the window of its fetch limit.
+    """
+
+    _prefix = "Limit exceeded"
+
+
+class AuthenticationLockedOut(GRPCException):
+    """
+    Raised when the key or address of the client is locked out after repeated failed
+    authentications. The lockout expires after the delay given in the message.
+    """
+
+    _prefix = "Authentication locked out"
+
+
+_EXCEPTIONS = {
+    "SESSION_NOT_FOUND": SessionError,
+    "SESSION_EXPIRED": SessionError,
+    "CLIENT_ADDRESS_MISMATCH": SessionError,
+    "CLIENT_ADDRESS_UNAVAILABLE": SessionError,
+    "DTYPE_MISMATCH": DTypeError,
+    "UNSUPPORTED_DTYPE": DTypeError,
+    "SHAPE_MISMATCH": ConversionError,
+    "CONVERSION_FAILED": ConversionError,
+    "TOKENIZATION_REQUIRED": ConversionError,
+    "TOKENIZATION_FAILED": ConversionError,
+    "LABELS_NOT_FOUND": LabelsNotFound,
+    "TRAINING_DIVERGED": TrainingDiverged,
+    "POLICY_DENIED": AccessDenied,
+    "FETCH_REJECTED": AccessDenied,
+    "PRIVACY_BUDGET_EXHAUSTED": LimitExceeded,
+    "FETCH_LIMIT_EXCEEDED": LimitExceeded,
+    "AUTHENTICATION_LOCKED_OUT": AuthenticationLockedOut,
+}
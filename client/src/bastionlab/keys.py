@@ -2,10 +2,15 @@ from typing import Optional
 import os
 from cryptography.hazmat.primitives import serialization, hashes
 from cryptography.hazmat.primitives.asymmetric import ec, types
+from cryptography.hazmat.primitives.ciphers.aead import AESGCM
+from cryptography.hazmat.primitives.kdf.hkdf import HKDF
 
 
 __pdoc__ = {}
 
+_SEALED_INFO = b"bastionlab sealed data"
+_P256_POINT_LEN = 65
+
 
 class PublicKey:
     """A class for representing a public key. This class provides methods for
@@ -127,6 +132,20 @@ class PublicKey:
         """
         return PublicKey(serialization.load_der_public_key(content))
 
+    @staticmethod
+    def from_point(point: bytes) -> "PublicKey":
+        """Load a `PublicKey` instance from an encoded P-256 point.
+
+        Args:
+            point: The encoded point, e.g. as returned by the server for its signing key.
+
+        Returns:
+            The `PublicKey` instance loaded from the given point.
+        """
+        return PublicKey(
+            ec.EllipticCurvePublicKey.from_encoded_point(ec.SECP256R1(), point)
+        )
+
     @staticmethod
     def from_pem_content(content: bytes) -> "PublicKey":
         """Load a `PublicKey` instance from a PEM-encoded byte string.
@@ -172,6 +191,28 @@ class SigningKey:
     def __eq__(self, o: object) -> bool:
         return self._key.__eq__(o)
 
+    def open_sealed(self, data: bytes) -> bytes:
+        """Decrypt data the server sealed to this key's public key.
+
+        Args:
+            data: The sealed data, as laid out by the server: the ephemeral public key
+                followed by the AES-GCM ciphertext.
+
+        Returns:
+            The decrypted data.
+
+        Raises:
+            cryptography.exceptions.InvalidTag: if the data was not sealed to this key.
+        """
+        ephemeral = ec.EllipticCurvePublicKey.from_encoded_point(
+            ec.SECP256R1(), data[:_P256_POINT_LEN]
+        )
+        secret = self._key.exchange(ec.ECDH(), ephemeral)
+        key = HKDF(
+            algorithm=hashes.SHA256(), length=32, salt=None, info=_SEALED_INFO
+        ).derive(secret)
+        return AESGCM(key).decrypt(bytes(12), data[_P256_POINT_LEN:], None)
+
     @property
     def pubkey(self) -> PublicKey:
         """Get the public key associated with this `SigningKey` instance.
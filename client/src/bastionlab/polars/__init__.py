@@ -11,6 +11,12 @@ from .frame import (
     train_test_split,
     Facet,
     RemoteArray,
+    DpAggregation,
+    WindowFunction,
+    Sketch,
+    LinkageField,
+    StringSimilarity,
+    QueryParam,
 )
 
 from . import policy
@@ -32,4 +38,10 @@ __all__ = [
     "train_test_split",
     "Facet",
     "RemoteArray",
+    "DpAggregation",
+    "WindowFunction",
+    "Sketch",
+    "LinkageField",
+    "StringSimilarity",
+    "QueryParam",
 ]
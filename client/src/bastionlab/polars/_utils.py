@@ -1,4 +1,4 @@
-from typing import Iterator, List, Union, TYPE_CHECKING
+from typing import Iterator, List, Optional, Union, TYPE_CHECKING
 import polars as pl
 import io
 from ..pb.bastionlab_polars_pb2 import SendChunk
@@ -86,6 +86,213 @@ class RowCountSegment(CompositePlanSegment):
     row: str
 
 
+@dataclass
+@serde
+class DpAggregation:
+    """
+    Aggregation of a column with differential privacy.
+
+    Args:
+        column : str
+            Name of the aggregated column.
+        kind : str
+            One of `"Count"`, `"Sum"` or `"Mean"`.
+        lower : float
+            Values are clipped to `[lower, upper]` before being summed. Ignored by counts.
+        upper : float
+            See `lower`.
+    """
+
+    column: str
+    kind: str
+    lower: float = 0.0
+    upper: float = 0.0
+
+
+@dataclass
+@serde
+class DpAggregationSegment(CompositePlanSegment):
+    """
+    Composite plan segment class responsible for differentially private aggregations
+    """
+
+    aggs: List[DpAggregation]
+    eps: float
+
+
+@dataclass
+@serde
+class PivotSegment(CompositePlanSegment):
+    """
+    Composite plan segment class responsible for pivot function
+    """
+
+    values: List[str]
+    index: List[str]
+    columns: List[str]
+    agg: str
+
+
+@dataclass
+@serde
+class WindowFunction:
+    """
+    Function of a column computed over partitions, in the order of the rows, and added as a
+    new column named after the column and kind, e.g. `amount_cum_sum`.
+
+    Args:
+        column : str
+            Name of the column the function is computed on.
+        kind : str
+            One of `"CumSum"`, `"RollingMean"`, `"RollingSum"` or `"Rank"`.
+        partition_by : List[str]
+            Columns whose values identify the partitions. The whole DataFrame is a single
+            partition when empty.
+        window_size : int
+            Number of rows of rolling windows. Ignored by the other functions.
+    """
+
+    column: str
+    kind: str
+    partition_by: List[str] = field(default_factory=list)
+    window_size: int = 0
+
+
+@dataclass
+@serde
+class WindowSegment(CompositePlanSegment):
+    """
+    Composite plan segment class responsible for window functions
+    """
+
+    functions: List[WindowFunction]
+
+
+@dataclass
+@serde
+class Sketch:
+    """
+    Approximate aggregation of a column, computed in a single pass and bounded memory.
+    Distinct counts are named after the column, e.g. `user_distinct`, and quantiles after
+    the column and quantile, e.g. `age_q0.5`.
+
+    Args:
+        column : str
+            Name of the column.
+        kind : str
+            `"DistinctCount"` (estimated with HyperLogLog) or `"Quantiles"` (estimated with
+            a t-digest).
+        quantiles : List[float]
+            Quantiles to estimate, between 0 and 1. Ignored by distinct counts.
+        lower : float
+            With differential privacy, values are clipped to `[lower, upper]` before their
+            quantiles are estimated, and the noise is proportional to `upper - lower`.
+        upper : float
+            See `lower`.
+    """
+
+    column: str
+    kind: str
+    quantiles: List[float] = field(default_factory=list)
+    lower: float = 0.0
+    upper: float = 0.0
+
+
+@dataclass
+@serde
+class SketchSegment(CompositePlanSegment):
+    """
+    Composite plan segment class responsible for sketches
+    """
+
+    sketches: List[Sketch]
+    eps: Optional[float] = None
+
+
+@dataclass
+@serde
+class PseudonymizeSegment(CompositePlanSegment):
+    """
+    Composite plan segment class responsible for pseudonymization
+    """
+
+    columns: List[str]
+    key: str
+
+
+@dataclass
+@serde
+class StringSimilarity:
+    """
+    Similarity of the strings of a column with those of another column or with a value,
+    added as a new column named after the column and kind, e.g. `name_jaro_winkler`, unless
+    `alias` is set. Similarities involving missing values are missing.
+
+    Args:
+        column : str
+            Name of the column.
+        kind : str
+            `"Levenshtein"` (edit distance, as an integer) or `"JaroWinkler"` (similarity
+            between 0 and 1, 1 for equal strings).
+        other : Optional[str]
+            Name of the column compared with, `value` is used when unset.
+        value : Optional[str]
+            Value compared with.
+        alias : Optional[str]
+            Name of the new column.
+    """
+
+    column: str
+    kind: str
+    other: Optional[str] = None
+    value: Optional[str] = None
+    alias: Optional[str] = None
+
+
+@dataclass
+@serde
+class StringSimilaritySegment(CompositePlanSegment):
+    """
+    Composite plan segment class responsible for string similarities
+    """
+
+    similarities: List[StringSimilarity]
+
+
+@dataclass
+@serde
+class LinkageField:
+    """
+    Pair of fields, one of each DataFrame, holding pseudonyms of the same identifier.
+
+    Args:
+        left : str
+            Name of the field in the left DataFrame.
+        right : str
+            Name of the field in the right DataFrame.
+        weight : float
+            Share of the score of a pair of rows given by the agreement of the fields,
+            relative to the other fields. Ignored by blocking fields.
+    """
+
+    left: str
+    right: str
+    weight: float = 1.0
+
+
+@dataclass
+@serde
+class LinkageSegment(CompositePlanSegment):
+    """
+    Composite plan segment class responsible for record linkage
+    """
+
+    fields: List[LinkageField]
+    threshold: float
+    output: str
+    blocking: List[LinkageField] = field(default_factory=list)
+
+
 @dataclass
 @serde(tagging=InternalTagging("type"))
 class PlanSegments:
@@ -96,6 +303,13 @@ class PlanSegments:
             EntryPointPlanSegment,
             StackPlanSegment,
             RowCountSegment,
+            DpAggregationSegment,
+            PivotSegment,
+            WindowSegment,
+            SketchSegment,
+            PseudonymizeSegment,
+            StringSimilaritySegment,
+            LinkageSegment,
         ]
     ]
 
@@ -113,8 +327,48 @@ class UdfTransformerPlanSegment(CompositePlanSegment):
         pass
 
 
+@dataclass
+class QueryParam:
+    """
+    Typed parameter of a saved query, given a value at each run with
+    `BastionLabPolars.run_saved_query`.
+
+    Args:
+        name : str
+            Name of the parameter.
+        dtype : str
+            `"int"`, `"float"`, `"string"`, `"bool"`, `"date"` or `"datetime"`.
+    """
+
+    name: str
+    dtype: str = "string"
+
+    def expr(self) -> pl.Expr:
+        """Returns an expression evaluating to the value of the parameter, to be used in the
+        queries saved with `RemoteLazyFrame.save`."""
+        marker = pl.lit(f"__bastionlab_param__:{self.name}")
+        if self.dtype == "int":
+            return marker.cast(pl.Int64)
+        if self.dtype == "float":
+            return marker.cast(pl.Float64)
+        if self.dtype == "string":
+            return marker
+        if self.dtype == "bool":
+            return marker == "true"
+        if self.dtype == "date":
+            return marker.str.strptime(pl.Date, "%Y-%m-%d")
+        if self.dtype == "datetime":
+            return marker.str.strptime(pl.Datetime, "%Y-%m-%dT%H:%M:%S")
+        raise ValueError(f"Unknown parameter type: {self.dtype}")
+
+
 def serialize_dataframe(
-    df: pl.DataFrame, policy: Policy, sanitized_columns: List[str]
+    df: pl.DataFrame,
+    policy: Policy,
+    sanitized_columns: List[str],
+    ttl: Optional[int] = None,
+    pseudonymized_columns: List[str] = [],
+    pseudonym_key: Optional[str] = None,
 ) -> Iterator[SendChunk]:
     """Converts Polars `DataFrame` to BastionLab `SendChunk` protobuf message.
     This currently uses the Apache IPC format.
@@ -127,6 +381,13 @@ def serialize_dataframe(
         sanitized_columns : List[str]
             This field contains the sensitive columns in the DataFrame that will be removed when a Data Scientist
             wishes to fetch a query performed on the DataFrame.
+        ttl : Optional[int]
+            Time-to-live in seconds after which the server deletes the DataFrame, if any.
+        pseudonymized_columns : List[str]
+            Columns the server replaces with their pseudonyms under `pseudonym_key` before
+            storing the DataFrame.
+        pseudonym_key : Optional[str]
+            Identifier of a pseudonymization key of the data owner.
     Returns:
         Iterator[SendChunk]
     """
@@ -145,6 +406,9 @@ def serialize_dataframe(
                 data=data,
                 policy=to_json(policy),
                 sanitized_columns=sanitized_columns,
+                ttl_seconds=ttl or 0,
+                pseudonymized_columns=pseudonymized_columns,
+                pseudonym_key=pseudonym_key or "",
             )
             first = False
         else:
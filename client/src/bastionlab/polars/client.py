@@ -1,8 +1,23 @@
-from typing import List, TYPE_CHECKING, Optional, Iterator
+from datetime import date, datetime
+from typing import Any, List, TYPE_CHECKING, Optional, Iterator
 from grpc import StatusCode
 import polars as pl
 from colorama import Fore
-from ..pb.bastionlab_polars_pb2 import ReferenceRequest, Empty, Query
+from tqdm import tqdm  # type: ignore [import]
+from ..pb.bastionlab_polars_pb2 import (
+    DataFrameQuery,
+    Empty,
+    FetchDecision,
+    PendingFetch,
+    PlanValidation,
+    PseudonymKey,
+    Query,
+    QueryParameter,
+    QuerySchedule,
+    ReferenceRequest,
+    SavedQuery,
+    SavedQueryRun,
+)
 from ..pb.bastionlab_polars_pb2_grpc import PolarsServiceStub
 from ..pb.bastionlab_pb2 import Reference
 from ..errors import GRPCException
@@ -13,6 +28,7 @@ from .policy import Policy, DEFAULT_POLICY
 if TYPE_CHECKING:
     import bastionlab.polars.frame
     from .frame import FetchableLazyFrame, RemoteArray
+    from ._utils import QueryParam
     from ..client import Client
 
 
@@ -35,6 +51,9 @@ class BastionLabPolars:
         df: pl.DataFrame,
         policy: Policy = DEFAULT_POLICY,
         sanitized_columns: List[str] = [],
+        ttl: Optional[int] = None,
+        pseudonymized_columns: List[str] = [],
+        pseudonym_key: Optional[str] = None,
     ) -> "FetchableLazyFrame":
         """
         This method is used to send `pl.DataFrame` to the BastionLab server.
@@ -50,6 +69,13 @@ class BastionLabPolars:
             sanitized_columns (List[str], optional): This field contains (sensitive) columns in the
                 DataFrame that are to be removed when a Data Scientist wishes to fetch a
                 query performed on the DataFrame.
+            ttl (Optional[int], optional): Time-to-live in seconds after which the server
+                deletes the DataFrame, and the DataFrames derived from it.
+            pseudonymized_columns (List[str], optional): Identifier columns the server
+                replaces with their pseudonyms under `pseudonym_key` before storing the
+                DataFrame, so that the identifiers never reach its storage.
+            pseudonym_key (Optional[str], optional): Identifier of a pseudonymization key
+                created with `create_pseudonym_key`.
 
         Returns:
             FetchableLazyFrame
@@ -60,7 +86,14 @@ class BastionLabPolars:
 
         res = GRPCException._map_error(
             lambda: self.stub.SendDataFrame(
-                serialize_dataframe(df, policy, sanitized_columns)
+                serialize_dataframe(
+                    df,
+                    policy,
+                    sanitized_columns,
+                    ttl,
+                    pseudonymized_columns,
+                    pseudonym_key,
+                )
             )
         )
         return FetchableLazyFrame._from_reference(self, res)
@@ -80,6 +113,7 @@ class BastionLabPolars:
 
         def make_chunks_iter() -> Iterator[bytes]:
             blocked = False
+            t = None
 
             for b in self.stub.FetchDataFrame(ReferenceRequest(identifier=ref)):
                 if blocked:
@@ -105,8 +139,23 @@ Reason: {b.warning}
 This incident will be reported to the data owner.{Fore.WHITE}"""
                     )
 
+                if b.total_size != 0:
+                    t = tqdm(
+                        total=b.total_size,
+                        unit="B",
+                        unit_scale=True,
+                        bar_format="{l_bar}{bar:20}{r_bar}",
+                    )
+                    t.set_description("Fetching DataFrame")
+
+                if t is not None:
+                    t.update(len(b.data))
+
                 yield b.data
 
+            if t is not None:
+                t.close()
+
         self.client._refresh_session_if_needed()
 
         try:
@@ -148,10 +197,194 @@ This incident will be reported to the data owner.{Fore.WHITE}"""
         )
         return FetchableLazyFrame._from_reference(self, res)
 
-    def list_dfs(self) -> List["FetchableLazyFrame"]:
+    def _validate_plan(self, composite_plan: str) -> PlanValidation:
+        """
+        Checks a Composite Plan against the schemas and policies of its inputs on the
+        BastionLab server and estimates the size of its result, without running it.
+
+        Args:
+            composite_plan : str
+                Serialized instructions to be checked by BastionLab server.
+
+        Returns:
+            PlanValidation
+        """
+        self.client._refresh_session_if_needed()
+
+        return GRPCException._map_error(
+            lambda: self.stub.ValidatePlan(Query(composite_plan=composite_plan))
+        )
+
+    def _schedule_query(
+        self, name: str, composite_plan: str, schedule: str
+    ) -> QuerySchedule:
+        """
+        Schedules a Composite Plan on the BastionLab server, whose results are registered
+        under `name`.
+
+        Args:
+            name : str
+                Identifier of the results.
+            composite_plan : str
+                Serialized instructions to be executed on BastionLab server.
+            schedule : str
+                Cron expression in UTC.
+
+        Returns:
+            QuerySchedule
+        """
+        self.client._refresh_session_if_needed()
+
+        return GRPCException._map_error(
+            lambda: self.stub.ScheduleQuery(
+                QuerySchedule(
+                    name=name, composite_plan=composite_plan, schedule=schedule
+                )
+            )
+        )
+
+    def scheduled_queries(self) -> List[QuerySchedule]:
+        """
+        Lists the queries scheduled by the current user with `RemoteLazyFrame.schedule`, with
+        the time and error of their last run.
+
+        Returns:
+            List[QuerySchedule]
+        """
+        self.client._refresh_session_if_needed()
+
+        res = GRPCException._map_error(lambda: self.stub.ListScheduledQueries(Empty()))
+        return list(res.list)
+
+    def unschedule_query(self, name: str):
+        """
+        Stops running the query scheduled under `name`. Its last result is kept.
+
+        Args:
+            name (str): The name of the scheduled query.
+        """
+        self.client._refresh_session_if_needed()
+
+        GRPCException._map_error(
+            lambda: self.stub.DeleteScheduledQuery(ReferenceRequest(identifier=name))
+        )
+
+    def _save_query(
+        self, name: str, composite_plan: str, params: List["QueryParam"]
+    ) -> SavedQuery:
+        """
+        Saves a Composite Plan with parameters on the BastionLab server under `name`.
+
+        Args:
+            name : str
+                Name of the query.
+            composite_plan : str
+                Serialized instructions to be executed on BastionLab server.
+            params : List[QueryParam]
+                Parameters used in the instructions.
+
+        Returns:
+            SavedQuery
+        """
+        self.client._refresh_session_if_needed()
+
+        parameters = [QueryParameter(name=p.name, dtype=p.dtype) for p in params]
+        return GRPCException._map_error(
+            lambda: self.stub.SaveQuery(
+                SavedQuery(
+                    name=name, composite_plan=composite_plan, parameters=parameters
+                )
+            )
+        )
+
+    def saved_queries(self) -> List[SavedQuery]:
+        """
+        Lists the queries saved with `RemoteLazyFrame.save`, with their parameters and the
+        data owners who approved them.
+
+        Returns:
+            List[SavedQuery]
+        """
+        self.client._refresh_session_if_needed()
+
+        res = GRPCException._map_error(lambda: self.stub.ListSavedQueries(Empty()))
+        return list(res.list)
+
+    def run_saved_query(self, name: str, **arguments: Any) -> "FetchableLazyFrame":
+        """
+        Runs the query saved under `name` with the given values of its parameters, e.g.
+        `run_saved_query("admissions", start=date(2023, 1, 1))`.
+
+        Args:
+            name (str): The name of the saved query.
+            **arguments: The values of the parameters, by name.
+
+        Returns:
+            FetchableLazyFrame
+        """
+        from .frame import FetchableLazyFrame
+
+        self.client._refresh_session_if_needed()
+
+        def to_str(value: Any) -> str:
+            if isinstance(value, bool):
+                return "true" if value else "false"
+            if isinstance(value, datetime):
+                return value.isoformat(timespec="seconds")
+            if isinstance(value, date):
+                return value.isoformat()
+            return str(value)
+
+        res = GRPCException._map_error(
+            lambda: self.stub.RunSavedQuery(
+                SavedQueryRun(
+                    name=name,
+                    arguments={k: to_str(v) for k, v in arguments.items()},
+                )
+            )
+        )
+        return FetchableLazyFrame._from_reference(self, res)
+
+    def approve_saved_query(self, name: str) -> SavedQuery:
+        """
+        Approves a saved query reading DataFrames of the current data owner. Once approved
+        by all their owners, the results of the query need no review to be fetched.
+
+        Args:
+            name (str): The name of the saved query.
+
+        Returns:
+            SavedQuery: The updated state of the query.
+        """
+        self.client._refresh_session_if_needed()
+
+        return GRPCException._map_error(
+            lambda: self.stub.ApproveSavedQuery(ReferenceRequest(identifier=name))
+        )
+
+    def delete_saved_query(self, name: str):
+        """
+        Deletes the query saved under `name`. Only its author may do so.
+
+        Args:
+            name (str): The name of the saved query.
+        """
+        self.client._refresh_session_if_needed()
+
+        GRPCException._map_error(
+            lambda: self.stub.DeleteSavedQuery(ReferenceRequest(identifier=name))
+        )
+
+    def list_dfs(
+        self, owner: str = "", created_after: Optional[datetime] = None
+    ) -> List["FetchableLazyFrame"]:
         """
         Enlists all the DataFrames available on the BastionLab server.
 
+        Args:
+            owner (str, optional): Only list the DataFrames created by this user.
+            created_after (Optional[datetime], optional): Only list the DataFrames created after this date.
+
         Returns:
             List[FetchableLazyFrame]
         """
@@ -159,7 +392,20 @@ This incident will be reported to the data owner.{Fore.WHITE}"""
 
         self.client._refresh_session_if_needed()
 
-        res = GRPCException._map_error(lambda: self.stub.ListDataFrames(Empty()).list)
+        query = DataFrameQuery(
+            owner=owner,
+            created_after=int(created_after.timestamp())
+            if created_after is not None
+            else 0,
+            page_size=100,
+        )
+        res = []
+        while True:
+            page = GRPCException._map_error(lambda: self.stub.ListDataFrames(query))
+            res.extend(page.list)
+            if page.next_page_token == "":
+                break
+            query.page_token = page.next_page_token
         return [FetchableLazyFrame._from_reference(self, ref) for ref in res]
 
     def get_df(self, identifier: str) -> "FetchableLazyFrame":
@@ -183,6 +429,107 @@ This incident will be reported to the data owner.{Fore.WHITE}"""
         )
         return FetchableLazyFrame._from_reference(self, res)
 
+    def usage_report(self) -> "FetchableLazyFrame":
+        """
+        Summarizes how the DataFrames of the data owner were used, per DataFrame, user and
+        kind of use (`"query"`, `"private_query"`, `"training"`, `"test"`,
+        `"fetch_approved"` or `"fetch_denied"`).
+
+        Uses of the DataFrames derived from the data owner's DataFrames are reported as uses
+        of the latter. Only available to data owners.
+
+        Returns:
+            FetchableLazyFrame: A DataFrame with columns `dataset`, `user_id`, `kind`,
+            `count`, `private` (number of differentially private uses), `eps` (total
+            privacy budget expended, 0 for uses which are not private), `derived`
+            (comma-separated identifiers of the derived DataFrames and models) and
+            `last_used` (Unix timestamp of the last use).
+        """
+        from .frame import FetchableLazyFrame
+
+        self.client._refresh_session_if_needed()
+
+        res = GRPCException._map_error(lambda: self.stub.GetUsageReport(Empty()))
+        return FetchableLazyFrame._from_reference(self, res)
+
+    def pending_fetches(self) -> List[PendingFetch]:
+        """
+        Lists the fetches waiting for the approval of several data owners, with the owners
+        who approved them so far. Only available to data owners.
+
+        Returns:
+            List[PendingFetch]
+        """
+        self.client._refresh_session_if_needed()
+
+        res = GRPCException._map_error(lambda: self.stub.ListPendingFetches(Empty()))
+        return list(res.list)
+
+    def approve_fetch(self, request_id: str) -> PendingFetch:
+        """
+        Approves a pending fetch, which proceeds once approved by as many distinct data
+        owners as its policy requires. Data owners cannot approve their own fetches.
+
+        Args:
+            request_id (str): Identifier of the request, as listed by `pending_fetches`.
+
+        Returns:
+            PendingFetch: The updated state of the request.
+        """
+        self.client._refresh_session_if_needed()
+
+        return GRPCException._map_error(
+            lambda: self.stub.DecideFetch(
+                FetchDecision(request_id=request_id, approve=True)
+            )
+        )
+
+    def reject_fetch(self, request_id: str) -> PendingFetch:
+        """
+        Rejects a pending fetch, which is denied at once.
+
+        Args:
+            request_id (str): Identifier of the request, as listed by `pending_fetches`.
+
+        Returns:
+            PendingFetch: The final state of the request.
+        """
+        self.client._refresh_session_if_needed()
+
+        return GRPCException._map_error(
+            lambda: self.stub.DecideFetch(
+                FetchDecision(request_id=request_id, approve=False)
+            )
+        )
+
+    def create_pseudonym_key(self) -> str:
+        """
+        Creates a key to pseudonymize the identifier columns of the data owner's DataFrames,
+        with `send_df` or `RemoteLazyFrame.pseudonymize`. The key never leaves the server.
+        Only available to data owners.
+
+        Returns:
+            str: The identifier of the key.
+        """
+        self.client._refresh_session_if_needed()
+
+        res = GRPCException._map_error(lambda: self.stub.CreatePseudonymKey(Empty()))
+        return res.identifier
+
+    def delete_pseudonym_key(self, key: str):
+        """
+        Deletes a pseudonymization key of the data owner, after which its pseudonyms cannot
+        be computed anymore.
+
+        Args:
+            key (str): The identifier of the key.
+        """
+        self.client._refresh_session_if_needed()
+
+        GRPCException._map_error(
+            lambda: self.stub.DeletePseudonymKey(PseudonymKey(identifier=key))
+        )
+
     def _persist_df(self, identifier: str):
         """
         Saves a Dataframe on the server from a BastionLab DataFrame identifier.
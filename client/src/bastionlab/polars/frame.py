@@ -8,8 +8,16 @@ import torch
 from ..pb.bastionlab_conversion_pb2 import (
     RemoteArray as PbRemoteArray,
     RemoteDataFrame as PbRemoteDataFrame,
+    ToWindowedDataset,
+)
+from ..pb.bastionlab_polars_pb2 import (
+    PlanValidation,
+    QuerySchedule,
+    ReferenceRequest,
+    SavedQuery,
+    ReferenceResponse,
+    SplitRequest,
 )
-from ..pb.bastionlab_polars_pb2 import ReferenceResponse, SplitRequest, ReferenceRequest
 from .client import BastionLabPolars
 from .utils import ApplyBins, Palettes, ApplyAbs, VisTools
 import matplotlib.pyplot as plt
@@ -26,6 +34,19 @@ from ._utils import (
     StackPlanSegment,
     PlanSegments,
     RowCountSegment,
+    DpAggregation,
+    DpAggregationSegment,
+    PivotSegment,
+    WindowFunction,
+    WindowSegment,
+    Sketch,
+    SketchSegment,
+    PseudonymizeSegment,
+    LinkageField,
+    LinkageSegment,
+    StringSimilarity,
+    StringSimilaritySegment,
+    QueryParam,
 )
 from .._utils import delegate, delegate_properties
 
@@ -35,6 +56,7 @@ LDF = TypeVar("LDF", bound="pl.LazyFrame")
 if TYPE_CHECKING:
     from ..client import Client
     from ..torch import RemoteTensor
+    from ..torch.data import RemoteDataset
     import matplotlib as mat
 
 
@@ -161,6 +183,58 @@ class RemoteLazyFrame:
         """
         return self._meta._polars_client._run_query(self.composite_plan)
 
+    def validate(self: LDF) -> "PlanValidation":
+        """Checks the pending queries/actions on RemoteLazyFrame against the schemas and policies
+        of the DataFrames they use and estimates the size of their result, without running them.
+
+        Returns:
+            PlanValidation: Whether the queries can run (with the error otherwise), the header of
+            the result, estimates of its number of rows and size in bytes, the number of rows and
+            bytes read, and warnings about issues that would prevent fetching the result.
+            Sizes are withheld, as indicated by `sizes_withheld`, when the policy of a DataFrame
+            used does not let the user fetch its rows.
+        """
+        return self._meta._polars_client._validate_plan(self.composite_plan)
+
+    def schedule(self: LDF, name: str, schedule: str) -> "QuerySchedule":
+        """Runs the pending queries/actions on RemoteLazyFrame on a schedule, as the current
+        user, registering every result under `name` in place of the previous one. Results are
+        read with `BastionLabPolars.get_df(name)`.
+
+        Args:
+            name (str): The identifier of the results, made of up to 64 letters, digits,
+                `-` and `_`.
+            schedule (str): A cron expression in UTC: minute, hour, day of month, month and
+                day of week, e.g. `"0 6 * * 1"` for every Monday at 6:00.
+
+        Returns:
+            QuerySchedule: The scheduled query.
+        """
+        return self._meta._polars_client._schedule_query(
+            name, self.composite_plan, schedule
+        )
+
+    def save(
+        self: LDF, name: str, params: Optional[List[QueryParam]] = None
+    ) -> "SavedQuery":
+        """Saves the pending queries/actions on RemoteLazyFrame under `name`, for anyone to run
+        with `BastionLabPolars.run_saved_query`. Parameters are used in the queries through
+        `QueryParam.expr`, and the queries are checked with sample values of them.
+
+        Once approved by all the owners of the DataFrames they use, with
+        `BastionLabPolars.approve_saved_query`, their results need no review to be fetched.
+
+        Args:
+            name (str): The name of the query, made of up to 64 letters, digits, `-` and `_`.
+            params (List[QueryParam]): The parameters of the query.
+
+        Returns:
+            SavedQuery: The saved query.
+        """
+        return self._meta._polars_client._save_query(
+            name, self.composite_plan, params or []
+        )
+
     @staticmethod
     def sql(query: str, *rdfs: LDF) -> LDF:
         """Parses given SQL query and interpolates {} placeholders with given RemoteLazyFrames.
@@ -283,6 +357,279 @@ class RemoteLazyFrame:
         # because if not this leads to panics etc. when we follow this with other operations that use the new column before next using collect()
         return ret.collect()
 
+    def pivot(
+        self: LDF,
+        values: Union[str, List[str]],
+        index: Union[str, List[str]],
+        columns: Union[str, List[str]],
+        aggregate_fn: str = "first",
+    ) -> LDF:
+        """creates a spreadsheet-style pivot table, with a row per group of `index` and a
+        column per value of `columns` and `values` column.
+
+        Pivots whose `aggregate_fn` aggregates rows are subject to the same policy checks as
+        groupbys on `index` and `columns`. `"first"` and `"last"` do not aggregate rows.
+
+        Unless the rows of the DataFrames may be fetched, the values of `columns` shared by
+        fewer rows than the `Aggregation` rule of their policy requires are left out, and
+        DataFrames whose policy has no such rule cannot be pivoted.
+        Args:
+            values (str | List[str]): The columns aggregated in the cells of the table.
+            index (str | List[str]): The columns whose values identify the rows of the table.
+            columns (str | List[str]): The columns whose values name the columns of the table.
+            aggregate_fn (str): One of `"first"`, `"last"`, `"count"`, `"sum"`, `"mean"`, `"median"`, `"min"` or `"max"`.
+        Returns:
+            FetchableLazyFrame: The pivot table
+        """
+
+        def to_list(names: Union[str, List[str]]) -> List[str]:
+            return [names] if isinstance(names, str) else list(names)
+
+        index = to_list(index)
+        # The other columns are named after values of the data: their schema is only
+        # known once the query runs.
+        df = pl.DataFrame(
+            [pl.Series(k, dtype=v) for k, v in self._inner.schema.items() if k in index]
+        )
+        ret = RemoteLazyFrame(
+            df.lazy(),
+            Metadata(
+                self._meta._polars_client,
+                [
+                    *self._meta._prev_segments,
+                    PolarsPlanSegment(self._inner),
+                    PivotSegment(
+                        values=to_list(values),
+                        index=index,
+                        columns=to_list(columns),
+                        agg=aggregate_fn.capitalize(),
+                    ),
+                ],
+            ),
+        )
+        return ret.collect()
+
+    def window(self: LDF, *functions: WindowFunction) -> LDF:
+        """adds columns computed by window functions over partitions of the rows, such as
+        cumulative sums, rolling means or ranks. Functions follow the order of the rows, so
+        time-ordered analyses should sort the RemoteLazyFrame first.
+
+        Results computed over partitions smaller than the minimum aggregation size of the
+        policy cannot be fetched.
+        Args:
+            functions (WindowFunction): The functions to compute.
+        Returns:
+            FetchableLazyFrame: The RemoteLazyFrame with the new columns
+        """
+        df = pl.DataFrame(
+            [pl.Series(k, dtype=v) for k, v in self._inner.schema.items()]
+        )
+        ret = RemoteLazyFrame(
+            df.lazy(),
+            Metadata(
+                self._meta._polars_client,
+                [
+                    *self._meta._prev_segments,
+                    PolarsPlanSegment(self._inner),
+                    WindowSegment(functions=list(functions)),
+                ],
+            ),
+        )
+        # The dtypes of the new columns are only known once the query runs.
+        return ret.collect()
+
+    def private_agg(self: LDF, eps: float, *aggs: DpAggregation) -> LDF:
+        """Aggregates columns into a single row with differential privacy, which is required
+        to fetch results derived from DataFrames whose policy has a `DifferentialPrivacy` rule.
+
+        The budget `eps` is split evenly between the aggregations and is expended from the
+        privacy budget of the DataFrames the RemoteLazyFrame derives from when the query runs.
+        Every aggregation is named after its column and kind, e.g. `age_mean`.
+
+        Args:
+            eps (float): The privacy budget used by the aggregations.
+            aggs (DpAggregation): The aggregations to compute.
+        Returns:
+            RemoteLazyFrame: The RemoteLazyFrame holding the noisy aggregates
+        """
+        df = pl.DataFrame(
+            [
+                pl.Series(f"{agg.column}_{agg.kind.lower()}", dtype=pl.Float64)
+                for agg in aggs
+            ]
+        )
+        return RemoteLazyFrame(
+            df.lazy(),
+            Metadata(
+                self._meta._polars_client,
+                [
+                    *self._meta._prev_segments,
+                    PolarsPlanSegment(self._inner),
+                    DpAggregationSegment(aggs=list(aggs), eps=eps),
+                ],
+            ),
+        )
+
+    def sketch(self: LDF, *sketches: Sketch, eps: Optional[float] = None) -> LDF:
+        """Estimates distinct counts and quantiles of columns with sketches, in a single row,
+        without the exact groupbys that policies may block.
+
+        When `eps` is given, noise is added to the estimates so that results derived from
+        DataFrames whose policy has a `DifferentialPrivacy` rule can be fetched. The budget
+        is split evenly between the estimates and is expended when the query runs. Private
+        distinct counts noise the exact counts rather than the HyperLogLog estimates.
+
+        Args:
+            sketches (Sketch): The estimates to compute.
+            eps (Optional[float]): The privacy budget used by the estimates, if any.
+        Returns:
+            RemoteLazyFrame: The RemoteLazyFrame holding the estimates
+        """
+        names = []
+        for sketch in sketches:
+            if sketch.kind == "DistinctCount":
+                names.append(f"{sketch.column}_distinct")
+            else:
+                # Whole quantiles are formatted without decimals by the server.
+                names.extend(
+                    f"{sketch.column}_q{int(q) if q == int(q) else q}"
+                    for q in sketch.quantiles
+                )
+        df = pl.DataFrame([pl.Series(name, dtype=pl.Float64) for name in names])
+        return RemoteLazyFrame(
+            df.lazy(),
+            Metadata(
+                self._meta._polars_client,
+                [
+                    *self._meta._prev_segments,
+                    PolarsPlanSegment(self._inner),
+                    SketchSegment(sketches=list(sketches), eps=eps),
+                ],
+            ),
+        )
+
+    def pseudonymize(self: LDF, key: str, *columns: str) -> LDF:
+        """replaces the values of identifier columns with their pseudonyms: keyed hashes of
+        the values, formatted as strings, under a key of the data owner held by the server.
+
+        DataFrames pseudonymized under the same key can be joined on these columns without
+        revealing the identifiers. The key only applies to DataFrames of its owner, who must
+        run the query.
+        Args:
+            key (str): The identifier of the pseudonymization key.
+            columns (str): The identifier columns.
+        Returns:
+            RemoteLazyFrame: The RemoteLazyFrame with pseudonymized columns
+        """
+        df = pl.DataFrame(
+            [
+                pl.Series(k, dtype=pl.Utf8 if k in columns else v)
+                for k, v in self._inner.schema.items()
+            ]
+        )
+        return RemoteLazyFrame(
+            df.lazy(),
+            Metadata(
+                self._meta._polars_client,
+                [
+                    *self._meta._prev_segments,
+                    PolarsPlanSegment(self._inner),
+                    PseudonymizeSegment(columns=list(columns), key=key),
+                ],
+            ),
+        )
+
+    def string_similarity(self: LDF, *similarities: StringSimilarity) -> LDF:
+        """adds columns of Levenshtein distances or Jaro-Winkler similarities between strings,
+        e.g. to check the quality of the data or to filter the rows of a cross join into a
+        fuzzy join. Values that are not strings are compared as strings.
+
+        This requires the policies of the DataFrames the RemoteLazyFrame derives from to
+        allow string similarities. Sanitized columns cannot be compared.
+        Args:
+            similarities (StringSimilarity): The similarities to compute.
+        Returns:
+            RemoteLazyFrame: The RemoteLazyFrame with the new columns
+        """
+        schema = dict(self._inner.schema)
+        for s in similarities:
+            if s.kind == "Levenshtein":
+                schema[s.alias or f"{s.column}_levenshtein"] = pl.UInt32
+            else:
+                schema[s.alias or f"{s.column}_jaro_winkler"] = pl.Float64
+        df = pl.DataFrame([pl.Series(k, dtype=v) for k, v in schema.items()])
+        return RemoteLazyFrame(
+            df.lazy(),
+            Metadata(
+                self._meta._polars_client,
+                [
+                    *self._meta._prev_segments,
+                    PolarsPlanSegment(self._inner),
+                    StringSimilaritySegment(similarities=list(similarities)),
+                ],
+            ),
+        )
+
+    def link(
+        self: LDF,
+        other: LDF,
+        fields: List[LinkageField],
+        blocking: List[LinkageField] = [],
+        threshold: float = 1.0,
+        output: str = "Statistics",
+    ) -> LDF:
+        """links the rows of this RemoteLazyFrame and `other` that likely describe the same
+        individuals, e.g. to deduplicate records across sites.
+
+        Rows are compared when their blocking fields are equal, and match when the share of
+        the weights of the `fields` that are equal is at least `threshold`. Only fields
+        pseudonymized under the same key, with `pseudonymize` or when sent, can be compared.
+        Args:
+            other (RemoteLazyFrame): The right RemoteLazyFrame.
+            fields (List[LinkageField]): The fields whose agreement scores pairs of rows.
+            blocking (List[LinkageField]): The fields whose values must be equal for rows to be compared.
+                Every pair of rows is compared when empty, which is only allowed for small DataFrames.
+            threshold (float): The minimum score of matching pairs, between 0 and 1.
+            output (str): `"Statistics"` for a single row counting the rows, candidate pairs,
+                matched pairs and matched rows of both sides, or `"Linked"` for the matched pairs
+                of rows, with a `linkage_score` column. Linked rows are subject to the policies of
+                both DataFrames like the rows of a join.
+        Returns:
+            RemoteLazyFrame: The linkage statistics or linked rows
+        """
+        segments = [
+            *self._meta._prev_segments,
+            PolarsPlanSegment(self._inner),
+            *other._meta._prev_segments,
+            PolarsPlanSegment(other._inner),
+            LinkageSegment(
+                fields=list(fields),
+                threshold=threshold,
+                output=output,
+                blocking=list(blocking),
+            ),
+        ]
+        if output == "Statistics":
+            names = [
+                "left_rows",
+                "right_rows",
+                "candidate_pairs",
+                "matched_pairs",
+                "matched_left_rows",
+                "matched_right_rows",
+            ]
+            df = pl.DataFrame([pl.Series(name, dtype=pl.UInt64) for name in names])
+            return RemoteLazyFrame(
+                df.lazy(), Metadata(self._meta._polars_client, segments)
+            )
+        # The names of the columns of the right side depend on those of the left side.
+        df = pl.DataFrame(
+            [pl.Series(k, dtype=v) for k, v in self._inner.schema.items()]
+        )
+        return RemoteLazyFrame(
+            df.lazy(), Metadata(self._meta._polars_client, segments)
+        ).collect()
+
     def describe(self: LDF) -> pl.DataFrame:
         """
         Provides the following summary statistics for our RemoteLazyFrame:
@@ -1282,6 +1629,71 @@ class FetchableLazyFrame(RemoteLazyFrame):
         )
         return RemoteArray(client, res.identifier)
 
+    def to_windowed_dataset(
+        self: "FetchableLazyFrame",
+        input_columns: List[str],
+        target_columns: List[str],
+        length: int,
+        stride: int = 1,
+        horizon: int = 1,
+        time_column: Optional[str] = None,
+        name: str = "",
+        description: str = "",
+        privacy_limit: float = -1.0,
+    ) -> "RemoteDataset":
+        """
+        Cuts the time series of the dataframe into sliding windows on the server, producing a RemoteDataset
+        for sequence models.
+
+        Args:
+            input_columns: List[str]
+                The columns making the inputs of the windows.
+            target_columns: List[str]
+                The columns making the targets of the windows.
+            length: int
+                Number of steps of the inputs of a window.
+            stride: int
+                Number of steps between the starts of two windows.
+            horizon: int
+                Number of steps following a window used as its targets.
+            time_column: str, optional
+                Column the rows are sorted by before being windowed, they are kept in order if None.
+            name: str
+                Name of the dataset.
+            description: str
+                Description of the dataset.
+            privacy_limit: float
+                Privacy budget of the dataset, -1.0 for no limit.
+
+        Returns:
+            RemoteDataset
+        """
+        from ..torch.data import RemoteDataset, RemoteTensor
+
+        client = self._meta._polars_client.client
+        res = client._converter._stub.WindowDataFrame(
+            ToWindowedDataset(
+                identifier=self._identifier,
+                time_column=time_column or "",
+                input_columns=input_columns,
+                target_columns=target_columns,
+                length=length,
+                stride=stride,
+                horizon=horizon,
+                name=name,
+                description=description,
+                privacy_limit=privacy_limit,
+            )
+        )
+        return RemoteDataset(
+            [RemoteTensor._from_reference(ref, client) for ref in res.inputs],
+            RemoteTensor._from_reference(res.labels, client),
+            name=name,
+            description=description,
+            privacy_limit=privacy_limit,
+            identifier=res.identifier,
+        )
+
     @property
     def identifier(self) -> str:
         """
@@ -1764,6 +2176,9 @@ __all__ = [
     "train_test_split",
     "Facet",
     "RemoteArray",
+    "DpAggregation",
+    "WindowFunction",
+    "Sketch",
 ]
 __pdoc__["RemoteLazyFrame.__init__"] = False
 __pdoc__["RemoteLazyGroupBy.__init__"] = False
@@ -1,9 +1,16 @@
 from dataclasses import dataclass
-from typing import List, Union
+from typing import List, Optional, Union
 from serde import serde, InternalTagging
 
 
-Rule = Union["AtLeastNOf", "Aggregation", "TrueRule", "FalseRule", "UserId"]
+Rule = Union[
+    "AtLeastNOf",
+    "Aggregation",
+    "DifferentialPrivacy",
+    "TrueRule",
+    "FalseRule",
+    "UserId",
+]
 """A Policy Rule."""
 
 
@@ -51,6 +58,25 @@ class Aggregation:
     min_agg_size: int
 
 
+@dataclass
+@serde
+class DifferentialPrivacy:
+    """
+    Specifies a `Rule` requiring results to be computed by differentially private aggregations,
+    see `RemoteLazyFrame.private_agg`.
+
+    Args:
+        max_eps_per_query : float
+            The largest privacy budget a single query may use.
+        budget : float
+            The overall privacy budget of the Remote DataFrame, after which private aggregations
+            are rejected.
+    """
+
+    max_eps_per_query: float
+    budget: float
+
+
 @dataclass
 @serde
 class TrueRule:
@@ -96,6 +122,27 @@ class Reject:
     """
 
 
+@dataclass
+@serde
+class FetchLimit:
+    """
+    Caps what every user may fetch from the results derived from a Remote DataFrame, including
+    results that are safe to fetch. Data owners are not limited.
+
+    Args:
+        window_secs : int
+            Length in seconds of the sliding window the limits apply to.
+        max_rows : Optional[int]
+            The maximum number of rows fetched in a window.
+        max_fetches : Optional[int]
+            The maximum number of fetches in a window.
+    """
+
+    window_secs: int
+    max_rows: Optional[int] = None
+    max_fetches: Optional[int] = None
+
+
 serde(AtLeastNOf)
 
 
@@ -112,11 +159,24 @@ class Policy:
             Describes what operations are considered _safe_ on the RDF.
         unsafe_handling : UnsafeAction
             Describes what should happen if a user violates the `safe_zone`. For example (logging operations)
+        fetch_limit : Optional[FetchLimit]
+            Caps what every user may fetch from the results derived from the RDF.
+        required_approvals : Optional[int]
+            Number of distinct data owners who must approve the fetches sent for review,
+            with `BastionLabPolars.approve_fetch`, instead of the owner reviewing them on the
+            server console. A single rejection denies the fetch.
+        string_similarity : bool
+            Whether string similarities may be computed on the columns of the RDF with
+            `RemoteLazyFrame.string_similarity`. As comparing values with chosen strings
+            probes them, this is disallowed by default.
     """
 
     safe_zone: Rule
     unsafe_handling: UnsafeAction
     savable: bool
+    fetch_limit: Optional[FetchLimit] = None
+    required_approvals: Optional[int] = None
+    string_similarity: bool = False
 
 
 DEFAULT_POLICY = Policy(
@@ -131,12 +191,14 @@ __all__ = [
     "AtLeastNOf",
     "UserId",
     "Aggregation",
+    "DifferentialPrivacy",
     "TrueRule",
     "FalseRule",
     "UnsafeAction",
     "Log",
     "Review",
     "Reject",
+    "FetchLimit",
     "Policy",
     "DEFAULT_POLICY",
 ]
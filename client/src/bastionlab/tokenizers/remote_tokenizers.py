@@ -3,10 +3,11 @@ from typing import TYPE_CHECKING, Tuple, Optional, Dict
 import json
 from tokenizers import Tokenizer
 from .._utils import delegate, delegate_properties
-from ..pb.bastionlab_conversion_pb2 import ToTokenizedArrays
+from ..pb.bastionlab_conversion_pb2 import ToTokenizedArrays, ToTokenizedDataset
 
 if TYPE_CHECKING:
     import bastionlab.polars
+    import bastionlab.torch.data
     from ..polars import RemoteLazyFrame, RemoteArray
     from ..client import Client
 
@@ -105,6 +106,67 @@ class RemoteTokenizer:
 
         return ids, masks
 
+    def encode_to_dataset(
+        self,
+        rdf: "RemoteLazyFrame",
+        text_column: str,
+        labels_column: str,
+        add_special_tokens: bool = True,
+        name: str = "",
+        description: str = "",
+        privacy_limit: float = -1.0,
+    ) -> "bastionlab.torch.data.RemoteDataset":
+        """
+        Tokenizes a text column on the server and registers the result as a RemoteDataset.
+
+        The text never leaves the server: the dataset inputs are the input ids and the attention masks.
+
+        Args:
+            rdf: RemoteLazyFrame
+                The RemoteDataframe containing the text and the labels.
+            text_column: str
+                The column containing string sequences to be tokenized.
+            labels_column: str
+                The column containing the labels.
+            add_special_tokens: bool
+                Whether to add the special tokens
+            name: str
+                Name of the dataset.
+            description: str
+                Description of the dataset.
+            privacy_limit: float
+                Privacy budget of the dataset, -1.0 for no limit.
+
+        Returns:
+            RemoteDataset
+        """
+        res = self._client._converter._stub.TokenizeToDataset(
+            ToTokenizedDataset(
+                identifier=rdf.identifier,
+                text_column=text_column,
+                labels_column=labels_column,
+                add_special_tokens=add_special_tokens,
+                model=self._model_name,
+                config=self._serialize(),
+                revision=self._revision,
+                auth_token=self._auth_token,
+                name=name,
+                description=description,
+                privacy_limit=privacy_limit,
+            )
+        )
+
+        from ..torch.data import RemoteDataset, RemoteTensor
+
+        return RemoteDataset(
+            [RemoteTensor._from_reference(ref, self._client) for ref in res.inputs],
+            RemoteTensor._from_reference(res.labels, self._client),
+            name=name,
+            description=description,
+            privacy_limit=privacy_limit,
+            identifier=res.identifier,
+        )
+
     def __str__(self) -> str:
         return f"RemoteTokenizer(vocabulary_size={self._tokenizer.get_vocab_size()})"
 
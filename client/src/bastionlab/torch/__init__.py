@@ -17,6 +17,7 @@ __pdoc__["RemoteTensor.__init__"] = False
 __pdoc__["RemoteDataset.__init__"] = False
 
 from . import optimizer
+from . import license
 from . import utils
 from . import psg
 
@@ -27,6 +28,7 @@ __all__ = [
     "RemoteDataset",
     "RemoteTensor",
     "optimizer",
+    "license",
     "utils",
     "psg",
 ]
@@ -1,14 +1,18 @@
 import io
-from typing import Callable, Iterator, List, Tuple, TypeVar, Optional, Any
+from hashlib import sha256
+from typing import Callable, Dict, Iterator, List, Tuple, TypeVar, Optional, Any
 import torch
 from torch import Tensor
 from torch.nn import Module
 from torch.nn.parameter import Parameter
 from torch.utils.data import Dataset
 from tqdm import tqdm  # type: ignore [import]
+from cryptography.exceptions import InvalidSignature
 from ..pb.bastionlab_torch_pb2 import Chunk  # type: ignore [import]
 from ..pb.bastionlab_pb2 import Reference
 from .utils import TensorDataset
+from .license import License
+from ..keys import PublicKey, SigningKey
 
 T = TypeVar("T")
 U = TypeVar("U")
@@ -208,6 +212,8 @@ def data_chunks_generator(
     description: str,
     meta: bytes,
     progress: bool = False,
+    ttl: Optional[int] = None,
+    license: Optional[License] = None,
 ) -> Iterator[Chunk]:
     """Converts an iterator of bytes chunks into an iterator of BastionAI gRPC protocol `Chunk` messages.
 
@@ -215,6 +221,8 @@ def data_chunks_generator(
         stream: Iterator of bytes chunks.
         name: A name for the objects being sent.
         description: Description of the objects being sent.
+        ttl: Time-to-live in seconds after which the server deletes the objects, if any.
+        license: Usage terms of the objects, anyone may use them if None.
     """
     first = True
     last_estimate = 0
@@ -238,7 +246,14 @@ def data_chunks_generator(
 
         if first:
             first = False
-            yield Chunk(data=x, name=name, description=description, meta=meta)
+            yield Chunk(
+                data=x,
+                name=name,
+                description=description,
+                meta=meta,
+                ttl_seconds=ttl or 0,
+                license=license.serialize() if license is not None else "",
+            )
         else:
             yield Chunk(data=x, name=name, description="", meta=bytes())
 
@@ -247,6 +262,77 @@ def data_chunks_generator(
         last_estimate = estimate
 
 
+def track_chunks(chunks: Iterator[Chunk], description: str) -> Iterator[Chunk]:
+    """Wraps an iterator of BastionAI gRPC protocol `Chunk` messages received from the server
+    and displays a progress bar based on the total size advertised by the server.
+
+    Args:
+        chunks: Iterator of chunks.
+        description: Description shown next to the progress bar.
+    """
+    t = None
+    for chunk in chunks:
+        if t is None and chunk.total_size != 0:
+            t = tqdm(
+                total=chunk.total_size,
+                unit="B",
+                unit_scale=True,
+                bar_format="{l_bar}{bar:20}{r_bar}",
+            )
+            t.set_description(description)
+        if t is not None:
+            t.update(len(chunk.data))
+        yield chunk
+    if t is not None:
+        t.close()
+
+
+_SIGNED_ARTIFACT_CONTEXT = b"bastionlab signed artifact"
+_SIGNED_MODEL_CARD_CONTEXT = b"bastionlab signed model card"
+
+
+def verify_chunks(chunks: Iterator[Chunk], key: PublicKey) -> Iterator[Chunk]:
+    """Wraps an iterator of BastionAI gRPC protocol `Chunk` messages received from the server
+    and checks, once they are all received, that they were signed with the server's `key`.
+
+    Args:
+        chunks: Iterator of chunks.
+        key: Signing key of the server, as returned by `BastionLabTorch.get_signing_key`.
+
+    Raises:
+        ValueError: if the data was not signed with `key` or was altered.
+    """
+    hash = sha256()
+    expected, signature = "", b""
+    for i, chunk in enumerate(chunks):
+        if i == 0:
+            expected, signature = chunk.sha256, chunk.signature
+        hash.update(chunk.data)
+        yield chunk
+    if not signature:
+        raise ValueError("The server did not sign the fetched data")
+    if hash.hexdigest() != expected:
+        raise ValueError("The fetched data does not match its signed hash")
+    try:
+        key.verify(signature, _SIGNED_ARTIFACT_CONTEXT + expected.encode("ascii"))
+    except InvalidSignature:
+        raise ValueError("The fetched data was not signed by the given key")
+
+
+def verify_model_card(content: str, signature: bytes, key: PublicKey) -> None:
+    """Checks that a model card was signed with the server's `key`.
+
+    Raises:
+        ValueError: if the model card was not signed with `key` or was altered.
+    """
+    if not signature:
+        raise ValueError("The server did not sign the model card")
+    try:
+        key.verify(signature, _SIGNED_MODEL_CARD_CONTEXT + content.encode("utf-8"))
+    except InvalidSignature:
+        raise ValueError("The model card was not signed by the given key")
+
+
 def make_batch(data: List[Tuple[List[Tensor], Tensor]]) -> Tuple[List[Tensor], Tensor]:
     """Aggregates a group of lists of column tensors and label tensors."""
     return (
@@ -266,6 +352,8 @@ def serialize_dataset(
     batch_size: int = 1024,
     train_dataset: Optional[Reference] = None,
     progress: bool = False,
+    ttl: Optional[int] = None,
+    license: Optional[License] = None,
 ) -> Iterator[Chunk]:
     """Coverts a dataset into an iterator of bytes chunks.
 
@@ -280,6 +368,8 @@ def serialize_dataset(
         chunk_size: size of the bytes chunks sent over gRPC.
         batch_size: size of the batches (in number of samples) during the serialization step.
         train_dataset: metadata, True means this dataset is suited for training, False that it should be used for testing/validating only
+        ttl: Time-to-live in seconds after which the server deletes the dataset, if any.
+        license: Usage terms of the dataset, anyone may use it if None.
     """
     return data_chunks_generator(
         stream_artifacts(
@@ -291,6 +381,8 @@ def serialize_dataset(
         description=description,
         meta=bytes(),
         progress=progress,
+        ttl=ttl,
+        license=license,
     )
 
 
@@ -300,6 +392,8 @@ def serialize_model(
     description: str,
     chunk_size: int = 100_000_000,
     progress: bool = False,
+    ttl: Optional[int] = None,
+    license: Optional[License] = None,
 ) -> Iterator[Chunk]:
     """Coverts a model into an iterator of bytes chunks.
 
@@ -311,6 +405,8 @@ def serialize_model(
         name: Name of the model on the server.
         description: Description of the model.
         chunk_size: size of the bytes chunks sent over gRPC.
+        ttl: Time-to-live in seconds after which the server deletes the model, if any.
+        license: Usage terms of the model, anyone may use it if None.
     """
     ts = torch.jit.script(model)
     return data_chunks_generator(
@@ -319,17 +415,26 @@ def serialize_model(
         description=description,
         meta=b"",
         progress=progress,
+        ttl=ttl,
+        license=license,
     )
 
 
-def deserialize_weights_to_model(model: Module, chunks: Iterator[Chunk]) -> None:
+def deserialize_weights_to_model(
+    model: Module, chunks: Iterator[Chunk], decryption_key: Optional[SigningKey] = None
+) -> None:
     """Deserializes weights from an iterator of BastionAI gRPC protocol `Chunks` writes
     them to the passed model.
+
+    If `decryption_key` is given, the weights are first decrypted with it.
     """
-    wrapper = list(
-        unstream_artifacts(
-            (chunk.data for chunk in chunks), deserialization_fn=torch.jit.load
+    load: Callable[[io.BytesIO], Any] = torch.jit.load
+    if decryption_key is not None:
+        load = lambda buff: torch.jit.load(
+            io.BytesIO(decryption_key.open_sealed(buff.read()))
         )
+    wrapper = list(
+        unstream_artifacts((chunk.data for chunk in chunks), deserialization_fn=load)
     )[0]
     for name, value in wrapper.named_parameters():
         param = model
@@ -364,12 +469,21 @@ def bulk_deserialize(b: bytes) -> Any:
 def send_tensor(
     tensor: torch.Tensor, chunk_size: Optional[int] = 32
 ) -> Iterator[Chunk]:
-    buf = io.BytesIO()
-
-    torch.jit.save(torch.jit.script(DataWrapper([tensor], None)), buf)
-    buf.seek(0)
+    buf = io.BytesIO(serialize_tensor(tensor))
     buf_len = len(buf.getvalue())
     while buf.tell() < buf_len:
         data = buf.read(chunk_size)
 
         yield Chunk(data=data, description="", meta=bytes(), name="", secret=bytes())
+
+
+def serialize_tensor(tensor: torch.Tensor) -> bytes:
+    """Serializes a tensor in the format the server reads tensors in."""
+    buf = io.BytesIO()
+    torch.jit.save(torch.jit.script(DataWrapper([tensor], None)), buf)
+    return buf.getvalue()
+
+
+def deserialize_tensors(data: bytes) -> Dict[str, torch.Tensor]:
+    """Deserializes named tensors saved by the server."""
+    return dict(torch.jit.load(io.BytesIO(data)).named_parameters())
@@ -1,19 +1,74 @@
-from typing import List, TYPE_CHECKING, Union, Optional
+from datetime import datetime
+import queue
+from typing import (
+    Any,
+    Callable,
+    Dict,
+    Iterator,
+    List,
+    TYPE_CHECKING,
+    Tuple,
+    Union,
+    Optional,
+)
 from torch.nn import Module
 from torch.utils.data import Dataset
 import torch
-from ..pb.bastionlab_torch_pb2 import Empty, Metric, TestConfig, TrainConfig  # type: ignore [import]
+from ..pb.bastionlab_torch_pb2 import (  # type: ignore [import]
+    AccessGrant,
+    ActivationCacheRequest,
+    ArtifactMetadataUpdate,
+    ArtifactQuery,
+    AudioDatasetChunk,
+    AudioSample,
+    BestRunQuery,
+    CheckpointFetchRequest,
+    DatasetConcatRequest,
+    DatasetPreview,
+    DatasetPreviewRequest,
+    DatasetSplitRequest,
+    Empty,
+    EpochSummary,
+    Experiment,
+    ExperimentConfig,
+    ImageDatasetChunk,
+    ImageSample,
+    LeakageAudit,
+    MelSpectrogramConfig,
+    Metric,
+    MetricDescription,
+    MfccConfig,
+    ModelCardRequest,
+    ModuleFetchRequest,
+    OptimizerDescription,
+    RunQuery,
+    RunResources,
+    RunSummary,
+    SplitTrainRequest,
+    TestConfig,
+    TrainConfig,
+    Watermark,
+    WatermarkReport,
+    WatermarkVerification,
+)
 from ..pb.bastionlab_pb2 import Reference
 from ..pb.bastionlab_torch_pb2_grpc import TorchServiceStub  # type: ignore [import]
 from ..errors import GRPCException
 from .optimizer import *
+from .license import License
+from ..keys import PublicKey, SigningKey
 
 from ._utils import (
     TensorDataset,
     dataset_from_chunks,
+    deserialize_tensors,
     deserialize_weights_to_model,
     serialize_dataset,
     serialize_model,
+    serialize_tensor,
+    track_chunks,
+    verify_chunks,
+    verify_model_card,
 )
 
 # TODO: hide Reference from public API! (protobuf object)
@@ -41,6 +96,8 @@ class BastionLabTorch:
         description: str = "",
         chunk_size: int = 4_194_285,
         progress: bool = False,
+        ttl: Optional[int] = None,
+        license: Optional[License] = None,
     ) -> Reference:
         """Uploads a Pytorch module to the BastionLab Torch server.
 
@@ -52,6 +109,8 @@ class BastionLabTorch:
             name: A name for the module being uploaded.
             description: A string description of the module being uploaded.
             chunk_size: Size of a chunk in the BastionLab Torch gRPC protocol in bytes.
+            ttl: Time-to-live in seconds after which the server deletes the module, if any.
+            license: Usage terms of the module, anyone may use it if None.
 
         Returns:
             BastionLab Torch gRPC protocol's reference object.
@@ -67,6 +126,8 @@ class BastionLabTorch:
                     description=description,
                     chunk_size=chunk_size,
                     progress=progress,
+                    ttl=ttl,
+                    license=license,
                 )
             )
         )
@@ -81,6 +142,8 @@ class BastionLabTorch:
         batch_size: int = 1024,
         train_dataset: Optional[Reference] = None,
         progress: bool = False,
+        ttl: Optional[int] = None,
+        license: Optional[License] = None,
     ) -> Reference:
         """Uploads a Pytorch Dataset to the BastionLab Torch server.
 
@@ -94,6 +157,8 @@ class BastionLabTorch:
                         at the price of a higher memory consumption.
             train_dataset: metadata, True means this dataset is suited for training,
                    False that it should be used for testing/validating only
+            ttl: Time-to-live in seconds after which the server deletes the dataset, if any.
+            license: Usage terms of the dataset, anyone may use it if None.
 
         Returns:
             BastionLab Torch gRPC protocol's reference object.
@@ -112,31 +177,269 @@ class BastionLabTorch:
                     privacy_limit=privacy_limit,
                     train_dataset=train_dataset,
                     progress=progress,
+                    ttl=ttl,
+                    license=license,
                 )
             )
         )
 
-    def fetch_model_weights(self, model: Module, ref: Reference) -> None:
+    def send_image_dataset(
+        self,
+        images: List[bytes],
+        labels: List[int],
+        width: int,
+        height: int,
+        name: str,
+        description: str = "",
+        privacy_limit: float = -1.0,
+        chunk_size: int = 4_194_285,
+        license: Optional[License] = None,
+    ) -> Reference:
+        """Uploads JPEG or PNG files to the BastionLab Torch server, which decodes and resizes them
+        into a dataset of float image tensors. Images and the size they are resized to may
+        not exceed 64 megapixels.
+
+        Args:
+            images: The contents of the image files.
+            labels: The label of each image.
+            width: The width the images are resized to.
+            height: The height the images are resized to.
+            name: A name for the dataset being uploaded.
+            description: A string description of the dataset being uploaded.
+            privacy_limit: Privacy budget of the dataset, -1.0 for no limit.
+            chunk_size: Approximate size of a message of the upload in bytes.
+            license: Usage terms of the dataset, anyone may use it if None.
+
+        Returns:
+            BastionLab Torch gRPC protocol's reference object.
+        """
+        if len(images) != len(labels):
+            raise ValueError("There must be exactly one label per image")
+
+        def chunks():
+            chunk = ImageDatasetChunk(
+                width=width,
+                height=height,
+                name=name,
+                description=description,
+                privacy_limit=privacy_limit,
+                license=license.serialize() if license is not None else "",
+            )
+            size = 0
+            for image, label in zip(images, labels):
+                if size > 0 and size + len(image) > chunk_size:
+                    yield chunk
+                    chunk = ImageDatasetChunk()
+                    size = 0
+                chunk.samples.append(ImageSample(image=image, label=label))
+                size += len(image)
+            yield chunk
+
+        self.client._refresh_session_if_needed()
+
+        return GRPCException._map_error(lambda: self.stub.SendImageDataset(chunks()))
+
+    def send_audio_dataset(
+        self,
+        recordings: List[bytes],
+        labels: List[int],
+        sample_rate: int,
+        name: str,
+        description: str = "",
+        features: Optional[str] = None,
+        n_fft: int = 400,
+        hop_length: int = 160,
+        n_mels: int = 64,
+        n_mfcc: int = 13,
+        privacy_limit: float = -1.0,
+        chunk_size: int = 4_194_285,
+    ) -> Reference:
+        """Uploads WAV or FLAC files to the BastionLab Torch server, which decodes them and extracts
+        their features into a dataset.
+
+        Args:
+            recordings: The contents of the audio files.
+            labels: The label of each recording.
+            sample_rate: The sample rate of all the recordings.
+            name: A name for the dataset being uploaded.
+            description: A string description of the dataset being uploaded.
+            features: `"mel_spectrogram"`, `"mfcc"`, or None to keep the raw waveforms.
+            n_fft: Size of the Fourier transforms of the spectrograms.
+            hop_length: Number of samples between two frames of the spectrograms.
+            n_mels: Number of mel bands of the spectrograms.
+            n_mfcc: Number of MFCCs kept when `features` is `"mfcc"`.
+            privacy_limit: Privacy budget of the dataset, -1.0 for no limit.
+            chunk_size: Approximate size of a message of the upload in bytes.
+
+        Returns:
+            BastionLab Torch gRPC protocol's reference object.
+        """
+        if len(recordings) != len(labels):
+            raise ValueError("There must be exactly one label per recording")
+
+        first = AudioDatasetChunk(
+            sample_rate=sample_rate,
+            name=name,
+            description=description,
+            privacy_limit=privacy_limit,
+        )
+        mel_spectrogram = MelSpectrogramConfig(
+            n_fft=n_fft, hop_length=hop_length, n_mels=n_mels
+        )
+        if features == "mel_spectrogram":
+            first.mel_spectrogram.CopyFrom(mel_spectrogram)
+        elif features == "mfcc":
+            first.mfcc.CopyFrom(
+                MfccConfig(mel_spectrogram=mel_spectrogram, n_mfcc=n_mfcc)
+            )
+        elif features is not None:
+            raise ValueError(f"Unknown audio features: {features}")
+
+        def chunks():
+            chunk = first
+            size = 0
+            for recording, label in zip(recordings, labels):
+                if size > 0 and size + len(recording) > chunk_size:
+                    yield chunk
+                    chunk = AudioDatasetChunk()
+                    size = 0
+                chunk.samples.append(AudioSample(recording=recording, label=label))
+                size += len(recording)
+            yield chunk
+
+        self.client._refresh_session_if_needed()
+
+        return GRPCException._map_error(lambda: self.stub.SendAudioDataset(chunks()))
+
+    def fetch_model_weights(
+        self,
+        model: Module,
+        ref: Reference,
+        progress: bool = True,
+        decryption_key: Optional[SigningKey] = None,
+        verify_with: Optional[PublicKey] = None,
+    ) -> None:
         """Fetches the weights of a distant trained model with a BastionLab Torch gRPC protocol reference
         and loads the weights into the passed model instance.
 
         Args:
             model: The Pytorch's nn.Module whose weights will be replaced by the fetched weights.
             ref: BastionLab Torch gRPC protocol reference object corresponding to the distant trained model.
+            progress: Whether to display a progress bar or not.
+            decryption_key: Key to decrypt the weights with, required when the license of
+                the model or of a dataset it was trained on sets `encrypt_to`.
+            verify_with: Signing key of the server (see `get_signing_key`). If given, the
+                weights are only loaded if they were signed with it.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        chunks = GRPCException._map_error(
+            lambda: self.stub.FetchModule(ModuleFetchRequest(model=ref))
+        )
+        if progress:
+            chunks = track_chunks(chunks, "Fetching weights")
+        if verify_with is not None:
+            chunks = verify_chunks(chunks, verify_with)
+        deserialize_weights_to_model(model, chunks, decryption_key)
+
+    def fetch_checkpoint_weights(
+        self,
+        model: Module,
+        ref: Reference,
+        index: int = -1,
+        progress: bool = True,
+        decryption_key: Optional[SigningKey] = None,
+        verify_with: Optional[PublicKey] = None,
+    ) -> None:
+        """Fetches the weights of one of the checkpoints kept for a distant model
+        and loads them into the passed model instance.
+
+        Args:
+            model: The Pytorch's nn.Module whose weights will be replaced by the fetched weights.
+            ref: BastionLab Torch gRPC protocol reference object corresponding to the distant model.
+            index: Position of the checkpoint among those kept, from 0 for the oldest one.
+                Negative values count from the latest one, which is -1.
+            progress: Whether to display a progress bar or not.
+            decryption_key: Key to decrypt the weights with, required when the license of
+                the model or of a dataset it was trained on sets `encrypt_to`.
+            verify_with: Signing key of the server (see `get_signing_key`). If given, the
+                weights are only loaded if they were signed with it.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        chunks = GRPCException._map_error(
+            lambda: self.stub.FetchCheckpoint(
+                CheckpointFetchRequest(model=ref, index=index)
+            )
+        )
+        if progress:
+            chunks = track_chunks(chunks, "Fetching checkpoint")
+        if verify_with is not None:
+            chunks = verify_chunks(chunks, verify_with)
+        deserialize_weights_to_model(model, chunks, decryption_key)
+
+    def export_model(
+        self,
+        ref: Reference,
+        path: str,
+        format: str = "torchscript",
+        progress: bool = True,
+        decryption_key: Optional[SigningKey] = None,
+        verify_with: Optional[PublicKey] = None,
+    ) -> None:
+        """Downloads a distant model with its last trained weights in a standard format.
+
+        Args:
+            ref: BastionLab Torch gRPC protocol reference object corresponding to the distant model.
+            path: Path of the file to write.
+            format: One of `torchscript` (an archive loadable with `torch.jit.load`),
+                `state_dict` (the weights only, keyed by parameter name, loadable with
+                `torch.jit.load(path).named_parameters()`) or `safetensors`.
+            progress: Whether to display a progress bar or not.
+            decryption_key: Key to decrypt the file with, required when the license of
+                the model or of a dataset it was trained on sets `encrypt_to`.
+            verify_with: Signing key of the server (see `get_signing_key`). If given, the
+                file is only written if it was signed with it.
         """
+        formats = {
+            "torchscript": ModuleFetchRequest.TORCHSCRIPT,
+            "state_dict": ModuleFetchRequest.STATE_DICT,
+            "safetensors": ModuleFetchRequest.SAFETENSORS,
+        }
+        if format not in formats:
+            raise ValueError(
+                f"Unknown format {format}, expected one of {', '.join(formats)}"
+            )
 
         self.client._refresh_session_if_needed()
 
-        chunks = GRPCException._map_error(lambda: self.stub.FetchModule(ref))
-        deserialize_weights_to_model(model, chunks)
+        chunks = GRPCException._map_error(
+            lambda: self.stub.FetchModule(
+                ModuleFetchRequest(model=ref, format=formats[format])
+            )
+        )
+        if progress:
+            chunks = track_chunks(chunks, "Exporting model")
+        if verify_with is not None:
+            chunks = verify_chunks(chunks, verify_with)
+        data = b"".join(chunk.data for chunk in chunks)
+        if decryption_key is not None:
+            data = decryption_key.open_sealed(data)
+        with open(path, "wb") as f:
+            f.write(data)
 
     def fetch_dataset(
-        self, ref: Union["bastionlab.torch.RemoteDataset", Reference]
+        self,
+        ref: Union["bastionlab.torch.RemoteDataset", Reference],
+        progress: bool = True,
     ) -> TensorDataset:
         """Fetches the distant dataset with a BastionLab Torch gRPC protocol reference.
 
         Args:
             ref: BastionLab Torch gRPC protocol reference object corresponding to the distant dataset.
+            progress: Whether to display a progress bar or not.
 
         Returns:
             A dataset instance built from received data.
@@ -148,25 +451,99 @@ class BastionLabTorch:
             ref = Reference(
                 identifier=ref.identifier, name="", description="", meta=bytes()
             )
-        return dataset_from_chunks(
-            GRPCException._map_error(lambda: self.stub.FetchDataset(ref))
-        )
+        chunks = GRPCException._map_error(lambda: self.stub.FetchDataset(ref))
+        if progress:
+            chunks = track_chunks(chunks, "Fetching dataset")
+        return dataset_from_chunks(chunks)
 
-    def get_available_models(self) -> List[Reference]:
-        """Returns the list of BastionLab Torch gRPC protocol references of all available models on the server."""
+    def export_checkpoints(
+        self,
+        ref: Reference,
+        path: str,
+        progress: bool = True,
+        decryption_key: Optional[SigningKey] = None,
+        verify_with: Optional[PublicKey] = None,
+    ) -> None:
+        """Downloads every checkpoint of a distant trained model as a single tar archive.
 
+        The archive contains the model's metadata (`metadata.json`), the final metric of each
+        training run (`metrics.json`) and one TorchScript file per checkpoint (`checkpoints/`).
+
+        Args:
+            ref: BastionLab Torch gRPC protocol reference object corresponding to the distant trained model.
+            path: Path of the archive file to write.
+            progress: Whether to display a progress bar or not.
+            decryption_key: Key to decrypt the archive with, required when the license of
+                the model or of a dataset it was trained on sets `encrypt_to`.
+            verify_with: Signing key of the server (see `get_signing_key`). If given, the
+                archive is only written if it was signed with it.
+        """
         self.client._refresh_session_if_needed()
 
-        return GRPCException._map_error(lambda: self.stub.AvailableModels(Empty())).list
+        chunks = GRPCException._map_error(lambda: self.stub.ExportCheckpoints(ref))
+        if progress:
+            chunks = track_chunks(chunks, "Exporting checkpoints")
+        if verify_with is not None:
+            chunks = verify_chunks(chunks, verify_with)
+        data = b"".join(chunk.data for chunk in chunks)
+        if decryption_key is not None:
+            data = decryption_key.open_sealed(data)
+        with open(path, "wb") as f:
+            f.write(data)
+
+    def _query_artifacts(
+        self, rpc: Callable[[ArtifactQuery], Any], query: ArtifactQuery
+    ) -> List[Reference]:
+        """Runs `query` with `rpc` and collects the references of all the result pages."""
+        self.client._refresh_session_if_needed()
 
-    def get_available_datasets(self) -> List[Reference]:
-        """Returns the list of BastionLab Torch gRPC protocol references of all datasets on the server."""
+        res = []
+        while True:
+            page = GRPCException._map_error(lambda: rpc(query))
+            res.extend(page.list)
+            if page.next_page_token == "":
+                return res
+            query.page_token = page.next_page_token
 
-        self.client._refresh_session_if_needed()
+    def get_available_models(
+        self,
+        name_contains: str = "",
+        tags: Optional[Dict[str, str]] = None,
+        owner: str = "",
+        created_after: Optional[datetime] = None,
+    ) -> List[Reference]:
+        """Returns the list of BastionLab Torch gRPC protocol references of all available models on the server.
 
-        return GRPCException._map_error(
-            lambda: self.stub.AvailableDatasets(Empty())
-        ).list
+        Args:
+            name_contains: Only return models whose name contains this string.
+            tags: Only return models that have all of these tags.
+            owner: Only return models uploaded by this user.
+            created_after: Only return models uploaded after this date.
+        """
+        return self._query_artifacts(
+            self.stub.AvailableModels,
+            _artifact_query(name_contains, tags, owner, created_after),
+        )
+
+    def get_available_datasets(
+        self,
+        name_contains: str = "",
+        tags: Optional[Dict[str, str]] = None,
+        owner: str = "",
+        created_after: Optional[datetime] = None,
+    ) -> List[Reference]:
+        """Returns the list of BastionLab Torch gRPC protocol references of all datasets on the server.
+
+        Args:
+            name_contains: Only return datasets whose name contains this string.
+            tags: Only return datasets that have all of these tags.
+            owner: Only return datasets uploaded by this user.
+            created_after: Only return datasets uploaded after this date.
+        """
+        return self._query_artifacts(
+            self.stub.AvailableDatasets,
+            _artifact_query(name_contains, tags, owner, created_after),
+        )
 
     def get_available_devices(self) -> List[str]:
         """Returns the list of devices available on the server."""
@@ -177,6 +554,18 @@ class BastionLabTorch:
             lambda: self.stub.AvailableDevices(Empty())
         ).list
 
+    def get_reserved_devices(self) -> List[str]:
+        """Returns the CUDA devices currently reserved by a run, on which no other run
+        can be started."""
+
+        self.client._refresh_session_if_needed()
+
+        return list(
+            GRPCException._map_error(
+                lambda: self.stub.AvailableDevices(Empty())
+            ).reserved
+        )
+
     def get_available_optimizers(self) -> List[str]:
         """Returns the list of optimizers supported by the server."""
 
@@ -186,6 +575,36 @@ class BastionLabTorch:
             lambda: self.stub.AvailableOptimizers(Empty())
         ).list
 
+    def get_optimizer_descriptions(self) -> List[OptimizerDescription]:
+        """Returns the optimizers supported by the server, with the type and default value
+        of their parameters."""
+
+        self.client._refresh_session_if_needed()
+
+        return list(
+            GRPCException._map_error(
+                lambda: self.stub.AvailableOptimizers(Empty())
+            ).descriptions
+        )
+
+    def get_available_losses(self) -> List[MetricDescription]:
+        """Returns the losses trainings can minimize, with the labels they expect."""
+
+        self.client._refresh_session_if_needed()
+
+        return list(
+            GRPCException._map_error(lambda: self.stub.AvailableLosses(Empty())).list
+        )
+
+    def get_available_metrics(self) -> List[MetricDescription]:
+        """Returns the metrics tests can report, losses included, with the labels they expect."""
+
+        self.client._refresh_session_if_needed()
+
+        return list(
+            GRPCException._map_error(lambda: self.stub.AvailableMetrics(Empty())).list
+        )
+
     def _train(self, config: TrainConfig) -> Reference:
         """Trains a model with hyperparameters defined in `config` on the BastionLab Torch server.
 
@@ -212,6 +631,7 @@ class BastionLabTorch:
         self, ref: Union["bastionlab.torch.RemoteDataset", Reference]
     ) -> None:
         """Deletes the dataset correponding to the given `ref` reference on the BastionLab Torch server.
+        Only the user who uploaded it may delete it.
 
         Args:
             ref: BastionLab Torch gRPC protocol reference of the dataset to be deleted.
@@ -229,6 +649,7 @@ class BastionLabTorch:
 
     def delete_module(self, ref: Reference) -> None:
         """Deletes the module correponding to the given `ref` reference on the BastionLab Torch server.
+        Only the user who uploaded it may delete it.
 
         Args:
             ref: BastionLab Torch gRPC protocol reference of the module to be deleted.
@@ -238,6 +659,91 @@ class BastionLabTorch:
 
         GRPCException._map_error(lambda: self.stub.DeleteModule(ref))
 
+    def update_metadata(
+        self,
+        ref: Union["bastionlab.torch.RemoteDataset", Reference],
+        name: Optional[str] = None,
+        description: Optional[str] = None,
+        tags: Optional[Dict[str, str]] = None,
+        removed_tags: Optional[List[str]] = None,
+    ) -> Reference:
+        """Renames, edits the description or changes the tags of a model or dataset on the BastionLab Torch server.
+
+        Args:
+            ref: BastionLab Torch gRPC protocol reference of the model or dataset.
+            name: New name of the artifact, unchanged if None.
+            description: New description of the artifact, unchanged if None.
+            tags: Tags to add to the artifact, existing tags with the same keys are overwritten.
+            removed_tags: Keys of the tags to remove from the artifact.
+
+        Returns:
+            The updated BastionLab Torch gRPC protocol reference.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        update = ArtifactMetadataUpdate(
+            identifier=ref.identifier,
+            name=name or "",
+            description=description or "",
+            tags=tags or {},
+            removed_tags=removed_tags or [],
+        )
+        return GRPCException._map_error(
+            lambda: self.stub.UpdateArtifactMetadata(update)
+        )
+
+    def grant_access(
+        self,
+        ref: Union["bastionlab.torch.RemoteDataset", Reference],
+        user_id: str,
+        start: datetime,
+        end: datetime,
+    ) -> Reference:
+        """Restricts the use of a model or dataset by a user to a window between two dates.
+
+        The user may then only use the artifact within the windows they were granted, and only
+        as far as its license rules allow. Only the owner of the artifact may grant access to it.
+
+        Args:
+            ref: BastionLab Torch gRPC protocol reference of the model or dataset.
+            user_id: Identifier of the user.
+            start: Start of the window.
+            end: End of the window, excluded.
+
+        Returns:
+            The BastionLab Torch gRPC protocol reference of the artifact.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        grant = AccessGrant(
+            identifier=ref.identifier,
+            user_id=user_id,
+            start=int(start.timestamp()),
+            end=int(end.timestamp()),
+        )
+        return GRPCException._map_error(lambda: self.stub.GrantAccess(grant))
+
+    def revoke_access(
+        self, ref: Union["bastionlab.torch.RemoteDataset", Reference], user_id: str
+    ) -> Reference:
+        """Removes the access grants of a user to a model or dataset, who is then only subject
+        to its license rules. Only the owner of the artifact may revoke grants.
+
+        Args:
+            ref: BastionLab Torch gRPC protocol reference of the model or dataset.
+            user_id: Identifier of the user.
+
+        Returns:
+            The BastionLab Torch gRPC protocol reference of the artifact.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        grant = AccessGrant(identifier=ref.identifier, user_id=user_id, revoke=True)
+        return GRPCException._map_error(lambda: self.stub.GrantAccess(grant))
+
     def get_metric(self, run: Reference) -> Metric:
         """Returns the value of the metric associated with the given `run` reference.
 
@@ -249,6 +755,518 @@ class BastionLabTorch:
 
         return GRPCException._map_error(lambda: self.stub.GetMetric(run))
 
+    def stream_metrics(self, run: Reference) -> Iterator[Metric]:
+        """Yields every metric reported by the given `run` as it is reported, starting
+        with the latest one, until the run is over.
+
+        Args:
+            run: BastionLab Torch gRPC protocol reference of the run whose metrics are read.
+
+        Raises:
+            GRPCException: if the run failed or was cancelled.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        updates = GRPCException._map_error(lambda: self.stub.StreamMetrics(run))
+        while True:
+            update = GRPCException._map_error(lambda: next(updates, None))
+            if update is None:
+                return
+            # Keepalives only prevent the stream from being closed as idle.
+            if update.HasField("metric"):
+                yield update.metric
+
+    def cancel_run(self, run: Reference) -> None:
+        """Stops the given training `run` at the next batch boundary. Its progress is
+        checkpointed, so that it can be resumed later. Only the user who started the
+        training can cancel it.
+
+        Args:
+            run: BastionLab Torch gRPC protocol reference of the training.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        GRPCException._map_error(lambda: self.stub.CancelRun(run))
+
+    def get_metric_history(self, run: Reference) -> List[Metric]:
+        """Returns every metric reported by the given `run`, oldest first.
+
+        The history is kept after the run is over and across server restarts.
+        This requires the server to be configured with a run database.
+
+        Args:
+            run: BastionLab Torch gRPC protocol reference of the run whose metrics are read.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        return list(
+            GRPCException._map_error(lambda: self.stub.GetMetricHistory(run)).list
+        )
+
+    def get_run_history(self, run: Reference) -> List[EpochSummary]:
+        """Returns a summary of every epoch of the given training `run`, in order, e.g.
+        to plot learning curves.
+
+        Every summary holds the average of the metric over the epoch, its uncertainty, the
+        learning rate and the privacy budget expended by the run until the end of the epoch
+        (negative if the dataset is not private).
+        This requires the server to be configured with a run database.
+
+        Args:
+            run: BastionLab Torch gRPC protocol reference of the training.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        return list(
+            GRPCException._map_error(lambda: self.stub.GetRunHistory(run)).epochs
+        )
+
+    def get_run_resources(self, run: Reference) -> Optional[RunResources]:
+        """Returns the resources consumed by the given `run`: CPU time and bytes of
+        samples read, or `None` while it is going.
+        This requires the server to be configured with a run database.
+
+        Args:
+            run: BastionLab Torch gRPC protocol reference of the run.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        history = GRPCException._map_error(lambda: self.stub.GetRunHistory(run))
+        return history.resources if history.HasField("resources") else None
+
+    def list_runs(self) -> List[RunSummary]:
+        """Returns the runs of the current user, or every run for data owners, oldest
+        first, with their model, dataset, start time, status (`"pending"`, `"running"`,
+        `"ok"`, `"error"` or `"cancelled"`) and the resources they consumed.
+        Without a run database on the server, only the runs started since the server
+        started are listed.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        return list(GRPCException._map_error(lambda: self.stub.ListRuns(Empty())).list)
+
+    def create_experiment(self, name: str, description: str = "") -> Experiment:
+        """Creates an experiment grouping trainings and tests, so that their hyperparameters
+        and final metrics can be compared.
+
+        Runs are added to the experiment by passing it to a `RemoteLearner`.
+        This requires the server to be configured with a run database.
+
+        Args:
+            name: A name for the experiment.
+            description: Provides additional description for the experiment.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        config = ExperimentConfig(name=name, description=description)
+        return GRPCException._map_error(lambda: self.stub.CreateExperiment(config))
+
+    def get_available_experiments(self) -> List[Experiment]:
+        """Returns the experiments created by the current user."""
+
+        self.client._refresh_session_if_needed()
+
+        return list(
+            GRPCException._map_error(lambda: self.stub.AvailableExperiments(Empty())).list
+        )
+
+    def get_experiment_runs(
+        self,
+        experiment: Experiment,
+        model: Optional[Reference] = None,
+        dataset: Optional[Reference] = None,
+        metric: Optional[str] = None,
+    ) -> List[RunSummary]:
+        """Returns the runs of the given `experiment`, oldest first, with their hyperparameters
+        and final metric.
+
+        Args:
+            experiment: The experiment whose runs are listed.
+            model: Only lists the runs of this model, if set.
+            dataset: Only lists the runs on this dataset, if set.
+            metric: Only lists the runs reporting this metric, if set.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        query = _run_query(experiment, model, dataset, metric)
+        return list(
+            GRPCException._map_error(lambda: self.stub.GetExperimentRuns(query)).list
+        )
+
+    def get_best_run(
+        self,
+        experiment: Experiment,
+        metric: str,
+        model: Optional[Reference] = None,
+        dataset: Optional[Reference] = None,
+        minimize: bool = False,
+    ) -> RunSummary:
+        """Returns the successful run of the given `experiment` with the best final `metric`,
+        e.g. the test with the highest `accuracy` on a validation dataset.
+
+        Args:
+            experiment: The experiment whose runs are compared.
+            metric: Name of the metric the runs are compared on.
+            model: Only compares the runs of this model, if set.
+            dataset: Only compares the runs on this dataset, if set.
+            minimize: Whether the best run has the lowest metric (e.g. a loss) instead of the highest.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        query = BestRunQuery(
+            runs=_run_query(experiment, model, dataset, metric), minimize=minimize
+        )
+        return GRPCException._map_error(lambda: self.stub.GetBestRun(query))
+
+    def get_model_card(
+        self,
+        model: Reference,
+        format: str = "markdown",
+        verify_with: Optional[PublicKey] = None,
+    ) -> str:
+        """Returns the model card of the given `model`.
+
+        The card summarizes the architecture, the datasets the model was trained on with their
+        privacy budgets, the hyperparameters and the metrics of the trainings.
+        Training details require the server to be configured with a run database.
+
+        Args:
+            model: BastionLab Torch gRPC protocol reference of the model.
+            format: Either `"markdown"` or `"json"`.
+            verify_with: Signing key of the server (see `get_signing_key`). If given, the
+                card is only returned if it was signed with it.
+        """
+
+        formats = {
+            "json": ModelCardRequest.Format.JSON,
+            "markdown": ModelCardRequest.Format.MARKDOWN,
+        }
+        if format not in formats:
+            raise ValueError(f"Unknown model card format: {format}")
+
+        self.client._refresh_session_if_needed()
+
+        req = ModelCardRequest(model=model, format=formats[format])
+        card = GRPCException._map_error(lambda: self.stub.GetModelCard(req))
+        if verify_with is not None:
+            verify_model_card(card.content, card.signature, verify_with)
+        return card.content
+
+    def set_watermark(
+        self, dataset: Reference, trigger_set: Reference, epochs: int = 1
+    ) -> None:
+        """Embeds a watermark in every model later trained on `dataset`, so that a leaked model
+        can be recognized with `verify_watermark`.
+
+        The models learn the labels of the `trigger_set` during `epochs` passes at the end of
+        each training. Both datasets must belong to the caller, and the trigger set must have
+        been sent without a privacy limit. The watermark is lost if the server restarts.
+
+        Args:
+            dataset: BastionLab Torch gRPC protocol reference of the watermarked dataset.
+            trigger_set: BastionLab Torch gRPC protocol reference of the trigger set.
+            epochs: Number of passes over the trigger set.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        watermark = Watermark(
+            dataset=dataset.identifier,
+            trigger_set=trigger_set.identifier,
+            epochs=epochs,
+        )
+        GRPCException._map_error(lambda: self.stub.SetWatermark(watermark))
+
+    def verify_watermark(self, model: Reference, dataset: Reference) -> WatermarkReport:
+        """Measures how much of the trigger set of the watermark of `dataset` the given `model`
+        labels as the trigger set does.
+
+        A share well above chance shows that the model was derived from a training on `dataset`.
+
+        Args:
+            model: BastionLab Torch gRPC protocol reference of the suspected model.
+            dataset: BastionLab Torch gRPC protocol reference of the watermarked dataset.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        req = WatermarkVerification(model=model, dataset=dataset.identifier)
+        return GRPCException._map_error(lambda: self.stub.VerifyWatermark(req))
+
+    def get_leakage_audit(self, run: Reference) -> LeakageAudit:
+        """Returns the leakage measured with the canaries of the given training `run`.
+
+        The audit is available once a training started with canaries is over, to the user
+        who ran it and the owner of the dataset. Its empirical epsilon may be compared with
+        the epsilon of the training.
+
+        Args:
+            run: BastionLab Torch gRPC protocol reference of the audited training.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        return GRPCException._map_error(lambda: self.stub.GetLeakageAudit(run))
+
+    def preview_dataset(
+        self,
+        dataset: Reference,
+        eps: float,
+        bounds: Tuple[float, float],
+        nb_classes: int = 0,
+    ) -> DatasetPreview:
+        """Returns the shape and type of the inputs and labels of `dataset`, along with
+        differentially private statistics of their features, without fetching any sample.
+
+        Args:
+            dataset: BastionLab Torch gRPC protocol reference of the previewed dataset.
+            eps: Privacy budget expended by the preview.
+            bounds: Input values are clamped to these bounds before computing their mean
+                and standard deviation. Tighter bounds give less noisy statistics.
+            nb_classes: Number of classes of the labels, to also get the number of samples
+                per class. Statistics are noised, so counts may be fractional.
+        """
+
+        self.client._refresh_session_if_needed()
+
+        req = DatasetPreviewRequest(
+            dataset=dataset.identifier,
+            eps=eps,
+            lower_bound=bounds[0],
+            upper_bound=bounds[1],
+            nb_classes=nb_classes,
+        )
+        return GRPCException._map_error(lambda: self.stub.PreviewDataset(req))
+
+    def split_train(
+        self,
+        model: Reference,
+        dataset: Reference,
+        head: Module,
+        head_optimizer: torch.optim.Optimizer,
+        loss_fn: Callable[[torch.Tensor, torch.Tensor], torch.Tensor],
+        optimizer: OptimizerConfig = Adam(lr=1e-3),
+        epochs: int = 1,
+        batch_size: int = 64,
+        device: str = "cpu",
+        resume: bool = False,
+        device_exclusive: bool = False,
+        stratified_batches: bool = False,
+        lr_scheduler: Optional[LrSchedulerConfig] = None,
+        accumulation_steps: int = 1,
+    ) -> Reference:
+        """Trains the backbone `model` on the server and the `head` locally, on the given
+        `dataset`, without sending the head to the server.
+
+        The server sends the activations of the backbone and the labels of every batch, and
+        updates the backbone with the gradients of the loss with respect to these activations.
+        As activations and labels leave the server, the dataset must have no privacy limit
+        and its license must allow the caller to fetch it.
+
+        Args:
+            model: BastionLab Torch gRPC protocol reference of the backbone.
+            dataset: BastionLab Torch gRPC protocol reference of the dataset.
+            head: The module applied to the activations of the backbone.
+            head_optimizer: The optimizer of the parameters of the `head`.
+            loss_fn: Computes the loss from the outputs of the `head` and the labels.
+            optimizer: Configuration of the optimizer of the backbone.
+            epochs: Number of epochs.
+            batch_size: Number of samples per batch.
+            device: Device the backbone is trained on.
+            resume: Whether to resume from the last checkpoint of the backbone.
+            device_exclusive: Whether to reserve the CUDA `device` for the training.
+            stratified_batches: Whether to keep the proportion of every class in each batch
+                close to that of the dataset. Requires integer class labels.
+            lr_scheduler: Schedule of the learning rate of the backbone, constant if `None`.
+            accumulation_steps: Number of batches whose gradients are accumulated before every
+                step of the backbone's optimizer.
+
+        Returns:
+            A reference to the trained backbone.
+        """
+        config = TrainConfig(
+            model=model,
+            dataset=dataset.identifier,
+            batch_size=batch_size,
+            epochs=epochs,
+            device=device,
+            eps=-1.0,
+            resume=resume,
+            device_exclusive=device_exclusive,
+            stratified_batches=stratified_batches,
+            lr_scheduler=lr_scheduler.to_msg() if lr_scheduler is not None else None,
+            accumulation_steps=accumulation_steps,
+            **optimizer.to_msg_dict(),
+        )
+        gradients: "queue.Queue[Optional[bytes]]" = queue.Queue()
+
+        def requests() -> Iterator[SplitTrainRequest]:
+            yield SplitTrainRequest(config=config)
+            while True:
+                data = gradients.get()
+                if data is None:
+                    return
+                yield SplitTrainRequest(gradients=data)
+
+        self.client._refresh_session_if_needed()
+
+        responses = GRPCException._map_error(lambda: self.stub.SplitTrain(requests()))
+        try:
+            for res in responses:
+                if res.HasField("model"):
+                    return res.model
+                tensors = deserialize_tensors(res.batch.tensors)
+                activations = tensors["activations"].detach().requires_grad_()
+                head_optimizer.zero_grad()
+                loss = loss_fn(head(activations), tensors["labels"])
+                loss.backward()
+                head_optimizer.step()
+                gradients.put(serialize_tensor(activations.grad))
+        finally:
+            gradients.put(None)
+        raise RuntimeError("The server closed the stream before training was over")
+
+    def get_signing_key(self) -> PublicKey:
+        """Returns the key with which the server signs fetched models, checkpoint archives
+        and model cards.
+
+        The key is generated at startup unless the server is configured with one, so it
+        should be pinned once obtained from a trusted server.
+        """
+        self.client._refresh_session_if_needed()
+
+        res = GRPCException._map_error(lambda: self.stub.GetSigningKey(Empty()))
+        return PublicKey.from_point(res.public_key)
+
+    def cache_activations(
+        self,
+        model: Reference,
+        dataset: "bastionlab.torch.RemoteDataset",
+        batch_size: int = 64,
+        device: str = "cpu",
+    ) -> "bastionlab.torch.RemoteDataset":
+        """Computes the outputs of the frozen backbone `model` on `dataset` once, and returns
+        them as a dataset on which heads can be trained without running the backbone again.
+
+        The returned dataset has the labels, license and privacy budget of `dataset`. It is
+        kept in memory by the server and returned again as long as the weights of the
+        backbone do not change.
+
+        Args:
+            model: BastionLab Torch gRPC protocol reference of the backbone.
+            dataset: The dataset whose inputs are fed to the backbone.
+            batch_size: Number of samples fed to the backbone at once.
+            device: Device the backbone runs on.
+        """
+        from .data import RemoteDataset, RemoteTensor
+
+        self.client._refresh_session_if_needed()
+
+        req = ActivationCacheRequest(
+            model=model,
+            dataset=dataset.identifier,
+            batch_size=batch_size,
+            device=device,
+        )
+        res = GRPCException._map_error(lambda: self.stub.CacheActivations(req))
+        return RemoteDataset(
+            [RemoteTensor._from_reference(ref, self.client) for ref in res.inputs],
+            RemoteTensor._from_reference(res.labels, self.client),
+            name=f"Activations of {dataset.name}",
+            description=dataset.description,
+            privacy_limit=dataset.privacy_limit,
+            identifier=res.identifier,
+        )
+
+    def concat_datasets(
+        self,
+        datasets: List["bastionlab.torch.RemoteDataset"],
+        name: str = "",
+    ) -> "bastionlab.torch.RemoteDataset":
+        """Concatenates the samples of `datasets`, in order, into a new dataset on the server.
+
+        The new dataset only allows what the licenses of all `datasets` allow. Its privacy
+        budget is the smallest remaining budget of `datasets`, which is expended from each
+        of them.
+
+        Args:
+            datasets: The datasets to concatenate, whose inputs and labels must have the same
+                shapes and types.
+            name: A name for the new dataset.
+        """
+        from .data import RemoteDataset, RemoteTensor
+
+        self.client._refresh_session_if_needed()
+
+        req = DatasetConcatRequest(
+            datasets=[dataset.identifier for dataset in datasets], name=name
+        )
+        res = GRPCException._map_error(lambda: self.stub.ConcatDatasets(req))
+        limits = [
+            dataset.privacy_limit
+            for dataset in datasets
+            if dataset.privacy_limit is not None and dataset.privacy_limit >= 0
+        ]
+        return RemoteDataset(
+            [RemoteTensor._from_reference(ref, self.client) for ref in res.inputs],
+            RemoteTensor._from_reference(res.labels, self.client),
+            name=name if name else "Concatenation",
+            description="",
+            privacy_limit=min(limits) if limits else -1.0,
+            identifier=res.identifier,
+        )
+
+    def split_dataset(
+        self,
+        dataset: "bastionlab.torch.RemoteDataset",
+        fractions: List[float],
+        shuffle: bool = True,
+    ) -> List["bastionlab.torch.RemoteDataset"]:
+        """Splits `dataset` into new datasets on the server, e.g. to build train and
+        validation folds without uploading the data again.
+
+        The parts have the license of `dataset`. As they hold distinct samples, each of them
+        gets the remaining privacy budget of `dataset`, which is expended from it. Only the
+        owner of `dataset` and the users its license allows to train on it may split it.
+
+        Args:
+            dataset: The dataset to split.
+            fractions: The fraction of the samples of `dataset` in every part, which must sum
+                to at most 1.
+            shuffle: Whether samples are assigned to parts at random rather than in order.
+        """
+        from .data import RemoteDataset, RemoteTensor
+
+        self.client._refresh_session_if_needed()
+
+        req = DatasetSplitRequest(
+            dataset=dataset.identifier, fractions=fractions, shuffle=shuffle
+        )
+        res = GRPCException._map_error(lambda: self.stub.SplitDataset(req))
+        return [
+            RemoteDataset(
+                [
+                    RemoteTensor._from_reference(ref, self.client)
+                    for ref in part.inputs
+                ],
+                RemoteTensor._from_reference(part.labels, self.client),
+                name=f"{dataset.name} (part {i + 1})",
+                description=dataset.description,
+                privacy_limit=dataset.privacy_limit,
+                identifier=part.identifier,
+            )
+            for i, part in enumerate(res.list)
+        ]
+
     def RemoteDataset(self, *args, **kwargs) -> "bastionlab.torch.RemoteDataset":
         """Returns a RemoteDataset object encapsulating a training and testing dataloaders
         on the remote server that uses this client to communicate with the server.
@@ -295,3 +1313,37 @@ class BastionLabTorch:
 
 __pdoc__ = {}
 __pdoc__["BastionLabTorch.__init__"] = False
+
+
+_PAGE_SIZE = 100
+
+
+def _artifact_query(
+    name_contains: str,
+    tags: Optional[Dict[str, str]],
+    owner: str,
+    created_after: Optional[datetime],
+) -> ArtifactQuery:
+    return ArtifactQuery(
+        name_contains=name_contains,
+        tags=tags or {},
+        owner=owner,
+        created_after=int(created_after.timestamp())
+        if created_after is not None
+        else 0,
+        page_size=_PAGE_SIZE,
+    )
+
+
+def _run_query(
+    experiment: Experiment,
+    model: Optional[Reference],
+    dataset: Optional[Reference],
+    metric: Optional[str],
+) -> RunQuery:
+    return RunQuery(
+        experiment=experiment.identifier,
+        model=model.identifier if model is not None else "",
+        dataset=dataset.identifier if dataset is not None else "",
+        metric=metric or "",
+    )
@@ -6,9 +6,11 @@ from ..pb.bastionlab_torch_pb2 import UpdateTensor, RemoteDatasetReference
 from ..pb.bastionlab_pb2 import Reference
 from torch.utils.data import Dataset
 from ..pb.bastionlab_pb2 import Reference, TensorMetaData
+from ..pb.bastionlab_conversion_pb2 import TensorToDataFrame
 
 if TYPE_CHECKING:
     from .client import BastionLabTorch
+    from ..polars import FetchableLazyFrame
 
 
 @dataclass
@@ -73,6 +75,30 @@ class RemoteTensor:
         )
         return RemoteTensor._from_reference(res, self._client)
 
+    def to_dataframe(self, col_names: List[str]) -> "FetchableLazyFrame":
+        """
+        Converts the tensor into a dataframe on the server, with a column per column of the tensor.
+
+        The dataframe is protected by the policies of the dataframes the tensor was computed from,
+        as recorded by the server.
+
+        Args:
+            col_names: List[str]
+                The names of the columns of the dataframe.
+
+        Returns:
+            FetchableLazyFrame
+        """
+        from ..polars import FetchableLazyFrame
+
+        res = self._client._converter._stub.ConvTensorToDataFrame(
+            TensorToDataFrame(
+                identifier=self.identifier,
+                col_names=col_names,
+            )
+        )
+        return FetchableLazyFrame._from_reference(self._client.polars, res)
+
 
 torch_dtypes = {
     "Int8": torch.uint8,
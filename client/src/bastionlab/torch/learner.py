@@ -1,11 +1,11 @@
-from typing import Optional, Union, List, Callable, TYPE_CHECKING
+from typing import Dict, Optional, Union, List, Callable, TYPE_CHECKING
 from time import sleep
 from tqdm import tqdm  # type: ignore [import]
 from grpc import StatusCode
 from torch.nn import Module
 from torch.utils.data import Dataset
 import torch
-from ..pb.bastionlab_torch_pb2 import Metric, TestConfig, TrainConfig  # type: ignore [import]
+from ..pb.bastionlab_torch_pb2 import Experiment, LeakageAudit, Metric, TestConfig, TrainConfig  # type: ignore [import]
 from ..pb.bastionlab_pb2 import Reference
 from ..errors import GRPCException
 from .psg import expand_weights
@@ -38,6 +38,14 @@ class RemoteLearner:
         model_description: Provides additional description for the uploaded model.
         expand: Whether to expand model's weights prior to uploading it, or not.
         progress: Whether to display a tqdm progress bar or not.
+        experiment: The experiment the trainings and tests of the learner are added to, if any,
+                    as returned by the `create_experiment` endpoint of the `BastionLabTorch` object.
+        device_exclusive: Whether runs reserve the CUDA `device`, so that no other run is placed on it
+                          meanwhile. Runs fail to start if the device is in use.
+        stratified_batches: Whether trainings keep the proportion of every class in each batch close to
+                            that of the dataset. Requires a single integer class label per sample.
+        lr_scheduler: The schedule of the learning rate during trainings, constant if `None`,
+                      refer to the documentation of `LrSchedulerConfig`.
     """
 
     def __init__(
@@ -55,6 +63,10 @@ class RemoteLearner:
         model_description: str = "",
         expand: bool = False,
         progress: bool = True,
+        experiment: Optional[Experiment] = None,
+        device_exclusive: bool = False,
+        stratified_batches: bool = False,
+        lr_scheduler: Optional[LrSchedulerConfig] = None,
     ) -> None:
         if isinstance(model, Module):
             model_class_name = type(model).__name__
@@ -84,6 +96,9 @@ class RemoteLearner:
         self.loss = loss
         self.optimizer = optimizer
         self.device = device
+        self.device_exclusive = device_exclusive
+        self.stratified_batches = stratified_batches
+        self.lr_scheduler = lr_scheduler
         self.max_batch_size = max_batch_size
         self.max_grad_norm = max_grad_norm
         self.metric_eps_per_batch = (
@@ -93,7 +108,9 @@ class RemoteLearner:
             else (metric_eps_per_batch if metric_eps_per_batch is not None else -1.0)
         )
         self.progress = progress
+        self.experiment = experiment.identifier if experiment is not None else ""
         self.log: List[Metric] = []
+        self.last_run: Optional[Reference] = None
 
     def _train_config(
         self,
@@ -106,6 +123,12 @@ class RemoteLearner:
         per_n_epochs_checkpoint: int = 0,
         per_n_steps_checkpoint: int = 0,
         resume: bool = False,
+        canaries: int = 0,
+        early_stopping_patience: int = 0,
+        early_stopping_metric: str = "",
+        accumulation_steps: int = 1,
+        max_checkpoints_kept: int = 0,
+        validation_ratio: float = 0.0,
     ) -> TrainConfig:
         batch_size = batch_size if batch_size is not None else self.max_batch_size
         return TrainConfig(
@@ -114,10 +137,19 @@ class RemoteLearner:
             batch_size=batch_size,
             epochs=nb_epochs,
             device=self.device,
+            device_exclusive=self.device_exclusive,
+            stratified_batches=self.stratified_batches,
             metric=self.loss,
             per_n_steps_checkpoint=per_n_steps_checkpoint,
             per_n_epochs_checkpoint=per_n_epochs_checkpoint,
             resume=resume,
+            experiment=self.experiment,
+            canaries=canaries,
+            early_stopping_patience=early_stopping_patience,
+            early_stopping_metric=early_stopping_metric,
+            accumulation_steps=accumulation_steps,
+            max_checkpoints_kept=max_checkpoints_kept,
+            validation_ratio=validation_ratio,
             eps=eps if eps is not None else -1.0,
             max_grad_norm=max_grad_norm if max_grad_norm else self.max_grad_norm,
             metric_eps=metric_eps
@@ -125,6 +157,9 @@ class RemoteLearner:
             else self.metric_eps_per_batch
             * float(nb_epochs)
             * float(self.remote_dataset.nb_samples / batch_size),
+            lr_scheduler=self.lr_scheduler.to_msg()
+            if self.lr_scheduler is not None
+            else None,
             **self.optimizer.to_msg_dict(lr),
         )
 
@@ -140,7 +175,9 @@ class RemoteLearner:
             dataset=self.remote_dataset.identifier,
             batch_size=batch_size,
             device=self.device,
+            device_exclusive=self.device_exclusive,
             metric=metric if metric is not None else self.loss,
+            experiment=self.experiment,
             metric_eps=metric_eps
             if metric_eps
             else self.metric_eps_per_batch
@@ -161,6 +198,15 @@ class RemoteLearner:
         )
         return t
 
+    @staticmethod
+    def _postfix(name: str, metric: Metric) -> Dict[str, str]:
+        postfix = {name: "{:.4f} (+/- {:.4f})".format(metric.value, metric.uncertainty)}
+        if metric.HasField("validation"):
+            postfix["validation"] = "{:.4f} (+/- {:.4f})".format(
+                metric.validation.value, metric.validation.uncertainty
+            )
+        return postfix
+
     def _poll_metric(
         self,
         run: Reference,
@@ -192,9 +238,7 @@ class RemoteLearner:
                 metric.epoch + 1, metric.nb_epochs, metric.nb_batches, train
             )
             t.update(metric.batch + 1)
-            t.set_postfix(
-                **{name: "{:.4f} (+/- {:.4f})".format(metric.value, metric.uncertainty)}
-            )
+            t.set_postfix(**RemoteLearner._postfix(name, metric))
         else:
             self.log.append(metric)
 
@@ -221,13 +265,7 @@ class RemoteLearner:
                     t.update(metric.batch + 1)
                 else:
                     t.update(metric.batch - prev_batch)
-                t.set_postfix(
-                    **{
-                        name: "{:.4f} (+/- {:.4f})".format(
-                            metric.value, metric.uncertainty
-                        )
-                    }
-                )
+                t.set_postfix(**RemoteLearner._postfix(name, metric))
             else:
                 self.log.append(metric)
 
@@ -250,6 +288,12 @@ class RemoteLearner:
         per_n_epochs_checkpoint: int = 0,
         per_n_steps_checkpoint: int = 0,
         resume: bool = False,
+        canaries: int = 0,
+        early_stopping_patience: int = 0,
+        early_stopping_metric: str = "",
+        accumulation_steps: int = 1,
+        max_checkpoints_kept: int = 0,
+        validation_ratio: float = 0.0,
     ) -> None:
         """Fits the uploaded model to the training dataset with given hyperparameters.
 
@@ -263,6 +307,23 @@ class RemoteLearner:
             timeout: Timeout in seconds between two updates of the loss on the server side. When elapsed without updates,
                         polling ends and the progress bar is terminated.
             poll_delay: Delay in seconds between two polling requests for the loss.
+            canaries: Number of canaries generated to audit the leakage of the training, half of
+                        which are inserted into the dataset. It cannot exceed the number of
+                        samples of the dataset. The audit is read with `leakage_audit`.
+            early_stopping_patience: Stops the training once the monitored metric has not improved
+                        for this many epochs, keeping the weights of the best epoch. 0 disables it.
+            early_stopping_metric: The metric averaged over every epoch to monitor, the training loss
+                        if empty. Other metrics expend the privacy budget of the loss again.
+            accumulation_steps: Number of batches whose gradients are accumulated before every step
+                        of the optimizer, for steps on `batch_size * accumulation_steps` samples
+                        without the memory they would take at once.
+            max_checkpoints_kept: Number of checkpoints of the model kept on the server, the oldest
+                        ones being dropped as `per_n_epochs_checkpoint` and `per_n_steps_checkpoint`
+                        add new ones. 0 keeps all of them.
+            validation_ratio: Fraction of the dataset held out from the training, on which the
+                        loss is evaluated at the end of every epoch and reported as `validation` in
+                        the metrics. Early stopping then monitors it unless `early_stopping_metric`
+                        is set. 0 disables it.
         """
         run = self.client._train(
             self._train_config(
@@ -275,8 +336,15 @@ class RemoteLearner:
                 per_n_epochs_checkpoint,
                 per_n_steps_checkpoint,
                 resume,
+                canaries,
+                early_stopping_patience,
+                early_stopping_metric,
+                accumulation_steps,
+                max_checkpoints_kept,
+                validation_ratio,
             )
         )
+        self.last_run = run
         self._poll_metric(
             run,
             name=self.loss,
@@ -314,6 +382,12 @@ class RemoteLearner:
             poll_delay=poll_delay,
         )
 
+    def leakage_audit(self) -> LeakageAudit:
+        """Returns the leakage measured with the canaries of the last call to `fit`."""
+        if self.last_run is None:
+            raise ValueError("The model has not been trained with this learner")
+        return self.client.get_leakage_audit(self.last_run)
+
     def get_model(self) -> Module:
         """Returns the model passed to the constructor with its weights
         updated with the weights obtained by training on the server.
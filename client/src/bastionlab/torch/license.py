@@ -0,0 +1,118 @@
+from dataclasses import dataclass, field
+from typing import List, Optional, Union
+from serde import serde, InternalTagging
+from serde.json import to_json
+
+
+Rule = Union["Anyone", "Owner", "UserIds", "Nobody"]
+"""Users allowed to perform an operation on an artifact."""
+
+
+@dataclass
+@serde
+class Anyone:
+    """
+    Allows any user with a valid session.
+    """
+
+
+@dataclass
+@serde
+class Owner:
+    """
+    Only allows the user who uploaded the artifact.
+    """
+
+
+@dataclass
+@serde
+class UserIds:
+    """
+    Only allows the listed users.
+
+    Args:
+        ids : List[str]
+            User identifiers.
+    """
+
+    ids: List[str]
+
+
+@dataclass
+@serde
+class Nobody:
+    """
+    Allows no one.
+    """
+
+
+@dataclass
+@serde
+class Grant:
+    """
+    Access of a user to an artifact, limited to a time window. Users with grants may only
+    use the artifact within them, whatever the rules say.
+
+    Args:
+        user_id : str
+            User identifier.
+        start : int
+            Start of the window, in seconds since the Unix epoch.
+        end : int
+            End of the window, excluded, in seconds since the Unix epoch.
+    """
+
+    user_id: str
+    start: int
+    end: int
+
+
+@serde(tagging=InternalTagging("type"))
+@dataclass
+class License:
+    """
+    BastionLab Torch License class.
+
+    Usage terms of a model or dataset, checked by the server on every access.
+
+    Args:
+        fetch : Rule
+            Who may fetch the artifact.
+        train : Rule
+            Who may train or test models with the artifact.
+        require_dp : bool
+            Whether training with the artifact must be differentially private.
+        test_only : bool
+            Whether the artifact is an evaluation dataset, on which models can be tested
+            but never trained, e.g. a benchmark that must stay untouched. Only its owner
+            may fetch it.
+        encrypt_to : Optional[str]
+            Hex-encoded DER public key to which any fetched checkpoint is encrypted,
+            e.g. `signing_key.pubkey.as_bytes().hex()`. Models trained on a dataset
+            with this license can then only be opened with
+            `bastionlab.keys.SigningKey.open_sealed`.
+        grants : List[Grant]
+            Time windows within which the listed users may fetch, train and test with the
+            artifact. Grants can also be added later with `BastionLabTorch.grant_access`.
+    """
+
+    fetch: Rule = field(default_factory=Anyone)
+    train: Rule = field(default_factory=Anyone)
+    require_dp: bool = False
+    test_only: bool = False
+    encrypt_to: Optional[str] = None
+    grants: List[Grant] = field(default_factory=list)
+
+    def serialize(self) -> str:
+        return to_json(self)
+
+
+__all__ = [
+    "Rule",
+    "Anyone",
+    "Owner",
+    "UserIds",
+    "Nobody",
+    "Grant",
+    "License",
+]
@@ -1,6 +1,6 @@
-from dataclasses import dataclass
-from typing import Any, Dict, Optional, Tuple
-from ..pb.bastionlab_torch_pb2 import TrainConfig  # type: ignore [import]
+from dataclasses import dataclass, field
+from typing import Any, Dict, List, Optional, Tuple, Union
+from ..pb.bastionlab_torch_pb2 import LrScheduler, OptimizerParameter, TrainConfig  # type: ignore [import]
 
 
 @dataclass
@@ -70,4 +70,135 @@ class Adam(OptimizerConfig):
         }
 
 
-__all__ = ["OptimizerConfig", "SGD", "Adam"]
+@dataclass
+class NamedOptimizer(OptimizerConfig):
+    """Configuration of any optimizer supported by the server, chosen by name.
+
+    The supported optimizers and the parameters they accept are returned by
+    `BastionLabTorch.get_optimizer_descriptions`. Parameters that are not given take
+    their default value.
+
+    Args:
+        name: Name of the optimizer, e.g. `"SGD"`.
+        parameters: Values of the parameters of the optimizer, other than the learning rate.
+    """
+
+    name: str = "SGD"
+    parameters: Dict[str, Union[float, bool]] = field(default_factory=dict)
+
+    def to_msg_dict(self, lr: Optional[float] = None) -> Dict[str, Any]:
+        """Please refer to the base class."""
+        parameters = {
+            name: OptimizerParameter(bool_value=value)
+            if isinstance(value, bool)
+            else OptimizerParameter(float_value=value)
+            for name, value in self.parameters.items()
+        }
+        parameters["learning_rate"] = OptimizerParameter(
+            float_value=lr if lr is not None else self.lr
+        )
+        return {"optimizer_name": self.name, "optimizer_parameters": parameters}
+
+
+class LrSchedulerConfig:
+    """Base class for learning rate scheduler configs.
+
+    Schedulers update the learning rate of the optimizer every epoch, except `OneCycleLR`
+    which updates it every step.
+    """
+
+    def to_msg(self) -> LrScheduler:
+        """Returns the gRPC message of the config."""
+        raise NotImplementedError
+
+
+@dataclass
+class StepLR(LrSchedulerConfig):
+    """Multiplies the learning rate by `gamma` every `step_size` epochs.
+
+    Parameters are the same as in Pytorch: https://pytorch.org/docs/stable/generated/torch.optim.lr_scheduler.StepLR.html
+    """
+
+    step_size: int
+    gamma: float = 0.1
+
+    def to_msg(self) -> LrScheduler:
+        """Please refer to the base class."""
+        return LrScheduler(
+            step=LrScheduler.Step(step_size=self.step_size, gamma=self.gamma)
+        )
+
+
+@dataclass
+class MultiStepLR(LrSchedulerConfig):
+    """Multiplies the learning rate by `gamma` at every epoch of `milestones`.
+
+    Parameters are the same as in Pytorch: https://pytorch.org/docs/stable/generated/torch.optim.lr_scheduler.MultiStepLR.html
+    """
+
+    milestones: List[int]
+    gamma: float = 0.1
+
+    def to_msg(self) -> LrScheduler:
+        """Please refer to the base class."""
+        return LrScheduler(
+            multi_step=LrScheduler.MultiStep(
+                milestones=self.milestones, gamma=self.gamma
+            )
+        )
+
+
+@dataclass
+class CosineAnnealingLR(LrSchedulerConfig):
+    """Anneals the learning rate down to `eta_min` along a cosine over `t_max` epochs,
+    every epoch of the training if `t_max` is 0.
+
+    Parameters are the same as in Pytorch: https://pytorch.org/docs/stable/generated/torch.optim.lr_scheduler.CosineAnnealingLR.html
+    """
+
+    t_max: int = 0
+    eta_min: float = 0.0
+
+    def to_msg(self) -> LrScheduler:
+        """Please refer to the base class."""
+        return LrScheduler(
+            cosine=LrScheduler.Cosine(t_max=self.t_max, eta_min=self.eta_min)
+        )
+
+
+@dataclass
+class OneCycleLR(LrSchedulerConfig):
+    """Raises the learning rate up to `max_lr` then anneals it, over every step of the
+    training. The learning rate of the optimizer is ignored.
+
+    Parameters are the same as in Pytorch, with cosine annealing: https://pytorch.org/docs/stable/generated/torch.optim.lr_scheduler.OneCycleLR.html
+    """
+
+    max_lr: float
+    pct_start: float = 0.3
+    div_factor: float = 25.0
+    final_div_factor: float = 1e4
+
+    def to_msg(self) -> LrScheduler:
+        """Please refer to the base class."""
+        return LrScheduler(
+            one_cycle=LrScheduler.OneCycle(
+                max_lr=self.max_lr,
+                pct_start=self.pct_start,
+                div_factor=self.div_factor,
+                final_div_factor=self.final_div_factor,
+            )
+        )
+
+
+__all__ = [
+    "OptimizerConfig",
+    "SGD",
+    "Adam",
+    "NamedOptimizer",
+    "LrSchedulerConfig",
+    "StepLR",
+    "MultiStepLR",
+    "CosineAnnealingLR",
+    "OneCycleLR",
+]
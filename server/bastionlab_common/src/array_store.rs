@@ -2,6 +2,7 @@ use ndarray::{Array, ArrayView, Axis, Dim, IxDynImpl};
 use tonic::Status;
 
 use crate::common_conversions::to_status_error;
+use crate::session_proto::ErrorCode;
 
 // FIXME: Try to update several impls with macros or generics to simplify implementation
 
@@ -58,7 +59,10 @@ where
         };
         out_arrays.push(view.clone());
     }
-    to_status_error(ndarray::stack::<A, Dim<IxDynImpl>>(axis, &out_arrays[..]))
+    to_status_error(
+        ndarray::stack::<A, Dim<IxDynImpl>>(axis, &out_arrays[..]),
+        ErrorCode::ShapeMismatch,
+    )
 }
 impl ArrayStore {
     pub fn height(&self) -> usize {
@@ -117,11 +121,11 @@ impl ArrayStore {
 
     pub fn append(&mut self, other: &Self, axis: Axis) -> Result<ArrayStore, Status> {
         let cannot_append =
-            |a, b| return Err(Status::aborted(format!("Cannot append {a:?} to {b:?}")));
+            |a, b| Err(ErrorCode::DtypeMismatch.status(format!("Cannot append {a:?} to {b:?}")));
         let res = match self {
             ArrayStore::AxdynI64(a) => match other {
                 Self::AxdynI64(b) => {
-                    to_status_error(a.append(axis, b.view()))?;
+                    to_status_error(a.append(axis, b.view()), ErrorCode::ShapeMismatch)?;
                     Self::AxdynI64(a.clone())
                 }
                 _ => {
@@ -130,7 +134,7 @@ impl ArrayStore {
             },
             ArrayStore::AxdynF64(a) => match other {
                 Self::AxdynF64(b) => {
-                    to_status_error(a.append(axis, b.view()))?;
+                    to_status_error(a.append(axis, b.view()), ErrorCode::ShapeMismatch)?;
                     Self::AxdynF64(a.clone())
                 }
                 _ => {
@@ -139,7 +143,7 @@ impl ArrayStore {
             },
             ArrayStore::AxdynF32(a) => match other {
                 Self::AxdynF32(b) => {
-                    to_status_error(a.append(axis, b.view()))?;
+                    to_status_error(a.append(axis, b.view()), ErrorCode::ShapeMismatch)?;
                     Self::AxdynF32(a.clone())
                 }
                 _ => {
@@ -148,7 +152,7 @@ impl ArrayStore {
             },
             ArrayStore::AxdynI32(a) => match other {
                 Self::AxdynI32(b) => {
-                    to_status_error(a.append(axis, b.view()))?;
+                    to_status_error(a.append(axis, b.view()), ErrorCode::ShapeMismatch)?;
                     Self::AxdynI32(a.clone())
                 }
                 _ => {
@@ -157,7 +161,7 @@ impl ArrayStore {
             },
             ArrayStore::AxdynI16(a) => match other {
                 Self::AxdynI16(b) => {
-                    to_status_error(a.append(axis, b.view()))?;
+                    to_status_error(a.append(axis, b.view()), ErrorCode::ShapeMismatch)?;
                     Self::AxdynI16(a.clone())
                 }
                 _ => {
@@ -183,9 +187,8 @@ impl ArrayStore {
                         .map(|v| match v {
                             ArrayStore::AxdynI64(a) => Ok(a.view()),
                             _ => {
-                                return Err(Status::aborted(
-                                    "DataTypes for all columns should be the same",
-                                ));
+                                return Err(ErrorCode::DtypeMismatch
+                                    .status("DataTypes for all columns should be the same"));
                             }
                         })
                         .collect::<Vec<_>>()[..],
@@ -200,9 +203,8 @@ impl ArrayStore {
                         .map(|v| match v {
                             ArrayStore::AxdynI32(a) => Ok(a.view()),
                             _ => {
-                                return Err(Status::aborted(
-                                    "DataTypes for all columns should be the same",
-                                ));
+                                return Err(ErrorCode::DtypeMismatch
+                                    .status("DataTypes for all columns should be the same"));
                             }
                         })
                         .collect::<Vec<_>>()[..],
@@ -217,9 +219,8 @@ impl ArrayStore {
                         .map(|v| match v {
                             ArrayStore::AxdynF64(a) => Ok(a.view()),
                             _ => {
-                                return Err(Status::aborted(
-                                    "DataTypes for all columns should be the same",
-                                ));
+                                return Err(ErrorCode::DtypeMismatch
+                                    .status("DataTypes for all columns should be the same"));
                             }
                         })
                         .collect::<Vec<_>>()[..],
@@ -234,9 +235,8 @@ impl ArrayStore {
                         .map(|v| match v {
                             ArrayStore::AxdynF32(a) => Ok(a.view()),
                             _ => {
-                                return Err(Status::aborted(
-                                    "DataTypes for all columns should be the same",
-                                ));
+                                return Err(ErrorCode::DtypeMismatch
+                                    .status("DataTypes for all columns should be the same"));
                             }
                         })
                         .collect::<Vec<_>>()[..],
@@ -251,9 +251,8 @@ impl ArrayStore {
                         .map(|v| match v {
                             ArrayStore::AxdynI16(a) => Ok(a.view()),
                             _ => {
-                                return Err(Status::aborted(
-                                    "DataTypes for all columns should be the same",
-                                ));
+                                return Err(ErrorCode::DtypeMismatch
+                                    .status("DataTypes for all columns should be the same"));
                             }
                         })
                         .collect::<Vec<_>>()[..],
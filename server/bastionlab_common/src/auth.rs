@@ -1,6 +1,10 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
 use std::{collections::HashMap, fs, path::Path};
 
 use crate::prelude::*;
+use crate::session_proto::ErrorCode;
 use ring::{
     digest::{digest, SHA256},
     signature,
@@ -10,6 +14,14 @@ use x509_parser::prelude::Pem;
 
 pub type PubKey = Vec<u8>;
 
+/// Consecutive failed signature verifications after which an offender is locked out.
+const MAX_FAILURES: u32 = 5;
+/// Lockout after `MAX_FAILURES` failures, doubled by every further failure.
+const BASE_LOCKOUT: Duration = Duration::from_secs(30);
+const MAX_LOCKOUT: Duration = Duration::from_secs(3600);
+/// How long failures are remembered after the last one, unless the offender is locked out.
+const FAILURE_MEMORY: Duration = Duration::from_secs(3600);
+
 #[derive(Debug, Default, Clone)]
 pub struct KeyManagement {
     owners: HashMap<String, PubKey>,
@@ -41,21 +53,23 @@ impl KeyManagement {
 
     pub fn load_from_dir(path: &Path) -> Result<Self, Status> {
         if !Path::new(&path).is_dir() {
-            Err(Status::aborted("Please provide a public keys directory!"))?
+            Err(ErrorCode::InvalidKeyDirectory.status("Please provide a public keys directory!"))?
         }
         let owners_path = &path.join("owners");
-        let owners =
-            fs::read_dir(owners_path).map_err(|_| Status::aborted("No owners directory found!"))?;
+        let owners = fs::read_dir(owners_path)
+            .map_err(|_| ErrorCode::InvalidKeyDirectory.status("No owners directory found!"))?;
 
         let users_path = &path.join("users");
-        let users =
-            fs::read_dir(users_path).map_err(|_| Status::aborted("No users directory found!"))?;
+        let users = fs::read_dir(users_path)
+            .map_err(|_| ErrorCode::InvalidKeyDirectory.status("No users directory found!"))?;
 
-        let owners = KeyManagement::get_hash_and_keys(owners)
-            .map_err(|_| Status::aborted("There is an issue with the owner's key!"))?;
+        let owners = KeyManagement::get_hash_and_keys(owners).map_err(|_| {
+            ErrorCode::InvalidKeyDirectory.status("There is an issue with the owner's key!")
+        })?;
 
-        let users = KeyManagement::get_hash_and_keys(users)
-            .map_err(|_| Status::aborted("There is an issue with the user's key!"))?;
+        let users = KeyManagement::get_hash_and_keys(users).map_err(|_| {
+            ErrorCode::InvalidKeyDirectory.status("There is an issue with the user's key!")
+        })?;
 
         Ok(Self { owners, users })
     }
@@ -118,6 +132,11 @@ impl KeyManagement {
         }
     }
 
+    /// Returns true if `public_key_hash` is the hash of the key of an owner or a user.
+    pub fn is_known(&self, public_key_hash: &str) -> bool {
+        self.owners.contains_key(public_key_hash) || self.users.contains_key(public_key_hash)
+    }
+
     pub fn verify_owner(&self, public_key_hash: &str) -> bool {
         /*
             For authentication, we check if the provided public key exists in the list of owner public keys provided at start-up.
@@ -128,3 +147,191 @@ impl KeyManagement {
         return false;
     }
 }
+
+/// A key or an address failing signature verifications.
+///
+/// Keys are tracked along with the address they are used from, so that failures sent
+/// with the key of another client do not lock that client out.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Offender {
+    Key(String, IpAddr),
+    Ip(IpAddr),
+}
+
+impl Offender {
+    pub fn identifier(&self) -> String {
+        match self {
+            Offender::Key(hash, _) => hash.clone(),
+            Offender::Ip(ip) => ip.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Offender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Offender::Key(hash, ip) => write!(f, "public key {} from address {}", hash, ip),
+            Offender::Ip(ip) => write!(f, "address {}", ip),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FailureRecord {
+    failures: u32,
+    last_failure: SystemTime,
+    locked_until: Option<SystemTime>,
+}
+
+/// Consecutive failed signature verifications of the keys and addresses of the clients,
+/// which are locked out with exponential backoff to resist online key guessing and
+/// replayed challenges.
+#[derive(Debug, Default)]
+pub struct FailureTracker {
+    records: Mutex<HashMap<Offender, FailureRecord>>,
+}
+
+impl FailureTracker {
+    /// Fails with `ResourceExhausted` while `offender` is locked out.
+    pub fn check(&self, offender: &Offender) -> Result<(), Status> {
+        self.check_at(offender, SystemTime::now())
+    }
+
+    fn check_at(&self, offender: &Offender, now: SystemTime) -> Result<(), Status> {
+        let records = self.records.lock().expect("Poisoned lock");
+        let locked_until = records.get(offender).and_then(|record| record.locked_until);
+        match locked_until.and_then(|until| until.duration_since(now).ok()) {
+            Some(remaining) => Err(ErrorCode::AuthenticationLockedOut.status(format!(
+                "Too many failed authentication attempts for {}, retry in {} seconds",
+                offender,
+                remaining.as_secs() + 1
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Records a failed verification of `offender`. Returns the lockout it starts, if any.
+    pub fn record_failure(&self, offender: Offender) -> Option<Duration> {
+        self.record_failure_at(offender, SystemTime::now())
+    }
+
+    fn record_failure_at(&self, offender: Offender, now: SystemTime) -> Option<Duration> {
+        let mut records = self.records.lock().expect("Poisoned lock");
+        records.retain(|_, record| {
+            let locked = record.locked_until.map_or(false, |until| until > now);
+            let recent = now
+                .duration_since(record.last_failure)
+                .map_or(true, |elapsed| elapsed < FAILURE_MEMORY);
+            locked || recent
+        });
+        let record = records.entry(offender).or_insert(FailureRecord {
+            failures: 0,
+            last_failure: now,
+            locked_until: None,
+        });
+        record.failures += 1;
+        record.last_failure = now;
+        if record.failures < MAX_FAILURES {
+            return None;
+        }
+        let doublings = (record.failures - MAX_FAILURES).min(16);
+        let lockout = (BASE_LOCKOUT * 2u32.pow(doublings)).min(MAX_LOCKOUT);
+        record.locked_until = Some(now + lockout);
+        Some(lockout)
+    }
+
+    /// Forgets the failures of `offender`, once it verified a signature.
+    pub fn clear(&self, offender: &Offender) {
+        self.records.lock().expect("Poisoned lock").remove(offender);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use tonic::Code;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, last))
+    }
+
+    #[test]
+    fn locks_out_after_max_failures_with_backoff() {
+        let tracker = FailureTracker::default();
+        let offender = Offender::Ip(ip(1));
+        let now = SystemTime::now();
+        for _ in 1..MAX_FAILURES {
+            assert_eq!(tracker.record_failure_at(offender.clone(), now), None);
+            assert!(tracker.check_at(&offender, now).is_ok());
+        }
+        assert_eq!(
+            tracker.record_failure_at(offender.clone(), now),
+            Some(BASE_LOCKOUT)
+        );
+        let err = tracker.check_at(&offender, now).unwrap_err();
+        assert_eq!(err.code(), Code::ResourceExhausted);
+        assert_eq!(
+            tracker.record_failure_at(offender.clone(), now),
+            Some(BASE_LOCKOUT * 2)
+        );
+        for _ in 0..20 {
+            tracker.record_failure_at(offender.clone(), now);
+        }
+        assert_eq!(
+            tracker.record_failure_at(offender.clone(), now),
+            Some(MAX_LOCKOUT)
+        );
+    }
+
+    #[test]
+    fn lockouts_expire() {
+        let tracker = FailureTracker::default();
+        let offender = Offender::Ip(ip(1));
+        let now = SystemTime::now();
+        for _ in 0..MAX_FAILURES {
+            tracker.record_failure_at(offender.clone(), now);
+        }
+        assert!(tracker.check_at(&offender, now).is_err());
+        assert!(tracker
+            .check_at(&offender, now + BASE_LOCKOUT + Duration::from_secs(1))
+            .is_ok());
+    }
+
+    #[test]
+    fn old_failures_are_forgotten() {
+        let tracker = FailureTracker::default();
+        let offender = Offender::Ip(ip(1));
+        let now = SystemTime::now();
+        for _ in 1..MAX_FAILURES {
+            tracker.record_failure_at(offender.clone(), now);
+        }
+        let later = now + FAILURE_MEMORY;
+        assert_eq!(tracker.record_failure_at(offender.clone(), later), None);
+    }
+
+    #[test]
+    fn clearing_forgets_failures() {
+        let tracker = FailureTracker::default();
+        let offender = Offender::Ip(ip(1));
+        let now = SystemTime::now();
+        for _ in 0..MAX_FAILURES {
+            tracker.record_failure_at(offender.clone(), now);
+        }
+        tracker.clear(&offender);
+        assert!(tracker.check_at(&offender, now).is_ok());
+    }
+
+    #[test]
+    fn keys_are_locked_out_per_address() {
+        let tracker = FailureTracker::default();
+        let attacker = Offender::Key(String::from("key"), ip(1));
+        let client = Offender::Key(String::from("key"), ip(2));
+        let now = SystemTime::now();
+        for _ in 0..MAX_FAILURES {
+            tracker.record_failure_at(attacker.clone(), now);
+        }
+        assert!(tracker.check_at(&attacker, now).is_err());
+        assert!(tracker.check_at(&client, now).is_ok());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::prelude::*;
+use serde::Deserialize;
+use tonic::Status;
+
+/// Role of an authenticated user, given by the directory of their public key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    DataOwner,
+    DataScientist,
+}
+
+impl Role {
+    fn name(&self) -> &'static str {
+        match self {
+            Role::DataOwner => "data owners",
+            Role::DataScientist => "data scientists",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Allow,
+    Deny,
+}
+
+/// Permissions of every role for a method. Roles without permission are allowed.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MethodPermissions {
+    #[serde(default)]
+    pub data_owner: Option<Permission>,
+    #[serde(default)]
+    pub data_scientist: Option<Permission>,
+}
+
+impl MethodPermissions {
+    fn get(&self, role: Role) -> Option<Permission> {
+        match role {
+            Role::DataOwner => self.data_owner,
+            Role::DataScientist => self.data_scientist,
+        }
+    }
+}
+
+/// Matrix of the gRPC methods each role may call, on top of the checks of the services.
+///
+/// Methods are named in snake case, as in the service traits, e.g. `fetch_dataset`. Method
+/// names are unique across the services. Methods missing from the policy are allowed.
+#[derive(Debug, Clone, Default)]
+pub struct AuthorizationPolicy {
+    methods: HashMap<String, MethodPermissions>,
+}
+
+impl AuthorizationPolicy {
+    pub fn new(methods: HashMap<String, MethodPermissions>) -> Self {
+        AuthorizationPolicy { methods }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.methods.is_empty()
+    }
+
+    pub fn allows(&self, method: &str, role: Role) -> bool {
+        let permission = self
+            .methods
+            .get(method)
+            .and_then(|permissions| permissions.get(role));
+        permission != Some(Permission::Deny)
+    }
+
+    /// Returns the methods of the policy which are not among the `served` methods, sorted.
+    pub fn unknown_methods<'a>(&'a self, served: &[String]) -> Vec<&'a str> {
+        let mut unknown: Vec<&str> = self
+            .methods
+            .keys()
+            .filter(|method| !served.contains(method))
+            .map(String::as_str)
+            .collect();
+        unknown.sort();
+        unknown
+    }
+
+    /// Fails with `PermissionDenied` when `role` may not call `method`.
+    pub fn check(&self, method: &str, role: Role) -> Result<(), Status> {
+        if self.allows(method, role) {
+            Ok(())
+        } else {
+            Err(Status::permission_denied(format!(
+                "The authorization policy of the server does not allow {} to call {}",
+                role.name(),
+                method
+            )))
+        }
+    }
+}
+
+/// Returns whether `name` is written like the methods of the policy.
+pub fn is_method_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Returns the method called on the gRPC `path`, such as
+/// `/bastionlab_torch.TorchService/FetchDataset`, in snake case like the service traits.
+pub fn method_of_path(path: &str) -> Option<String> {
+    let (_, method) = path.trim_start_matches('/').split_once('/')?;
+    Some(method_name(method))
+}
+
+/// Returns the name of the gRPC method `method`, such as `FetchDataset`, in snake case like
+/// the service traits.
+pub fn method_name(method: &str) -> String {
+    let chars: Vec<char> = method.chars().collect();
+    let mut name = String::with_capacity(chars.len() + 4);
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).map_or(false, |n| n.is_ascii_lowercase());
+            // Acronyms make a single word, e.g. GetDBInfo is get_db_info.
+            if !prev.is_ascii_uppercase() || next_lower {
+                name.push('_');
+            }
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    fn policy(toml: &str) -> AuthorizationPolicy {
+        AuthorizationPolicy::new(toml::from_str(toml).unwrap())
+    }
+
+    #[test]
+    fn method_of_path_is_in_snake_case() {
+        assert_eq!(
+            method_of_path("/bastionlab_torch.TorchService/FetchDataset").as_deref(),
+            Some("fetch_dataset")
+        );
+        assert_eq!(
+            method_of_path("/bastionlab.SessionService/GetDBInfo").as_deref(),
+            Some("get_db_info")
+        );
+        assert_eq!(
+            method_of_path("/bastionlab_polars.PolarsService/Run").as_deref(),
+            Some("run")
+        );
+        assert_eq!(method_of_path("/FetchDataset"), None);
+    }
+
+    #[test]
+    fn policy_denies_listed_roles_only() {
+        let policy = policy(
+            r#"fetch_dataset = { data_scientist = "deny", data_owner = "allow" }
+            delete_dataset = { data_owner = "deny" }"#,
+        );
+        assert!(policy.allows("fetch_dataset", Role::DataOwner));
+        assert!(!policy.allows("fetch_dataset", Role::DataScientist));
+        assert!(!policy.allows("delete_dataset", Role::DataOwner));
+        assert!(policy.allows("delete_dataset", Role::DataScientist));
+        assert!(policy.allows("train", Role::DataScientist));
+
+        let err = policy
+            .check("fetch_dataset", Role::DataScientist)
+            .unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+    }
+
+    #[test]
+    fn policy_lists_unknown_methods() {
+        let policy = policy(
+            r#"fetch_dataset = { data_scientist = "deny" }
+            fetch_datset = { data_scientist = "deny" }"#,
+        );
+        let served = vec![String::from("fetch_dataset"), String::from("train")];
+        assert_eq!(policy.unknown_methods(&served), vec!["fetch_datset"]);
+        assert!(AuthorizationPolicy::default()
+            .unknown_methods(&served)
+            .is_empty());
+    }
+}
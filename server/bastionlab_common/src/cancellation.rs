@@ -0,0 +1,102 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tonic::{Request, Status};
+
+/// Tells long-running work done for a request that the client gave up on it, because
+/// the request deadline passed or the client went away.
+///
+/// The work calls [`Cancellation::check`] at safe points and aborts when it fails.
+#[derive(Clone, Debug, Default)]
+pub struct Cancellation {
+    deadline: Option<Instant>,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Cancels the work when the handler future that owns it is dropped, which tonic does
+/// when the client cancels the call.
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Cancellation {
+    /// Returns the cancellation of `request`, with the deadline set by the client in the
+    /// `grpc-timeout` header, if any.
+    pub fn of_request<T>(request: &Request<T>) -> Result<Self, Status> {
+        let deadline = request
+            .metadata()
+            .get("grpc-timeout")
+            .map(|value| {
+                value
+                    .to_str()
+                    .ok()
+                    .and_then(parse_grpc_timeout)
+                    .ok_or_else(|| Status::invalid_argument("Invalid grpc-timeout header"))
+            })
+            .transpose()?
+            .map(|timeout| Instant::now() + timeout);
+        Ok(Cancellation {
+            deadline,
+            cancelled: Arc::default(),
+        })
+    }
+
+    /// Fails if the work should stop.
+    pub fn check(&self) -> Result<(), Status> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(Status::cancelled("Request was cancelled"));
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return Err(Status::deadline_exceeded("Request deadline exceeded"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `work` on the blocking thread pool and waits for it until the deadline.
+    ///
+    /// The work is cancelled if the deadline passes or the returned future is dropped.
+    pub async fn run_blocking<R, F>(self, work: F) -> Result<R, Status>
+    where
+        F: FnOnce(&Cancellation) -> Result<R, Status> + Send + 'static,
+        R: Send + 'static,
+    {
+        let _cancel_on_drop = CancelOnDrop(Arc::clone(&self.cancelled));
+        let deadline = self.deadline;
+        let task = tokio::task::spawn_blocking(move || {
+            self.check()?;
+            work(&self)
+        });
+        let res = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline.into(), task)
+                .await
+                .map_err(|_| Status::deadline_exceeded("Request deadline exceeded"))?,
+            None => task.await,
+        };
+        res.map_err(|e| Status::internal(format!("Request processing failed: {}", e)))?
+    }
+}
+
+/// Parses the value of a `grpc-timeout` header, e.g. `100m` for 100 milliseconds.
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    let amount: u64 = amount.parse().ok()?;
+    Some(match unit {
+        "H" => Duration::from_secs(amount * 60 * 60),
+        "M" => Duration::from_secs(amount * 60),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    })
+}
@@ -0,0 +1,56 @@
+use crate::prelude::*;
+use crate::session_proto::{
+    capabilities_service_server::CapabilitiesService, Empty, SelfTestResult, ServerCapabilities,
+};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::time::Instant;
+use tonic::{Request, Response, Status};
+
+/// Runs the startup check `name` and returns its outcome. Panics, as raised by LibTorch on
+/// some failures, count as failures.
+pub fn run_self_test(name: &str, test: impl FnOnce() -> Result<()>) -> SelfTestResult {
+    let start = Instant::now();
+    let error = match catch_unwind(AssertUnwindSafe(test)) {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(format!("{e:#}")),
+        Err(panic) => Some(
+            panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| panic.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_else(|| String::from("Panicked")),
+        ),
+    };
+    let duration_ms = start.elapsed().as_millis() as u64;
+    match &error {
+        Some(error) => error!("Self-test {name} failed: {error}"),
+        None => info!("Self-test {name} passed in {duration_ms} ms."),
+    }
+    SelfTestResult {
+        name: name.to_string(),
+        passed: error.is_none(),
+        error: error.unwrap_or_default(),
+        duration_ms,
+    }
+}
+
+/// Serves the capabilities of the server, gathered when it started.
+pub struct CapabilitiesGrpcService {
+    capabilities: ServerCapabilities,
+}
+
+impl CapabilitiesGrpcService {
+    pub fn new(capabilities: ServerCapabilities) -> Self {
+        Self { capabilities }
+    }
+}
+
+#[tonic::async_trait]
+impl CapabilitiesService for CapabilitiesGrpcService {
+    async fn get_server_capabilities(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<ServerCapabilities>, Status> {
+        Ok(Response::new(self.capabilities.clone()))
+    }
+}
@@ -1,4 +1,4 @@
-use std::{error::Error, io::Cursor, sync::Mutex};
+use std::{error::Error, io::Cursor};
 
 use ndarray::{prelude::*, Data, IxDynImpl, RawData};
 use polars::{export::rayon::prelude::ParallelIterator, prelude::*};
@@ -7,13 +7,17 @@ use tch::{kind::Element, CModule, Tensor};
 use tokenizers::{FromPretrainedParameters, PaddingParams, Tokenizer, TruncationParams};
 use tonic::Status;
 
+use crate::session_proto::ErrorCode;
+
 pub fn list_dtype_to_tensor(series: &Series) -> Result<Vec<Tensor>, Status> {
-    let rows = to_status_error(series.list())?;
+    let rows = to_status_error(series.list(), ErrorCode::DtypeMismatch)?;
     let mut out = vec![];
     for s in rows.into_iter() {
         match s.as_ref() {
             Some(s) => out.push(series_to_tensor(s)?),
-            None => return Err(Status::aborted("Could not iterate over series.")),
+            None => {
+                return Err(ErrorCode::ConversionFailed.status("Could not iterate over series."))
+            }
         }
     }
 
@@ -28,7 +32,7 @@ pub fn series_to_tensor(series: &Series) -> Result<Tensor, Status> {
         DataType::Int16 => chunked_array_to_tensor(series.i16().unwrap())?,
         DataType::Int8 => chunked_array_to_tensor(series.i8().unwrap())?,
         DataType::UInt32 => {
-            let s = to_status_error(series.cast(&DataType::Int64))?;
+            let s = to_status_error(series.cast(&DataType::Int64), ErrorCode::UnsupportedDtype)?;
             chunked_array_to_tensor(s.i64().unwrap())?
         }
         DataType::List(_) => {
@@ -84,12 +88,19 @@ where
     T: Data,
     T::Elem: Element,
 {
-    let tensor = Tensor::try_from(data)
-        .map_err(|e| Status::aborted(format!("Could not convert ArrayBase to Tensor: {}", e)))?;
+    let tensor = Tensor::try_from(data).map_err(|e| {
+        ErrorCode::ConversionFailed.status(format!("Could not convert ArrayBase to Tensor: {}", e))
+    })?;
 
     Ok(tensor)
 }
 
+pub fn tensor_to_ndarray<T: Element>(tensor: &Tensor) -> Result<ArrayD<T>, Status> {
+    ArrayD::<T>::try_from(tensor).map_err(|e| {
+        ErrorCode::ConversionFailed.status(format!("Could not convert Tensor to ArrayBase: {}", e))
+    })
+}
+
 pub fn tensor_to_series(name: &str, dtype: &DataType, tensor: Tensor) -> Result<Series, Status> {
     Ok(match dtype {
         DataType::Float32 => Series::from(tensor_to_chunked_array::<Float32Type>(&name, tensor)),
@@ -113,8 +124,8 @@ pub fn lazy_frame_from_logical_plan(plan: LogicalPlan) -> LazyFrame {
     ldf
 }
 
-pub fn to_status_error<T, E: Error>(input: Result<T, E>) -> Result<T, Status> {
-    input.map_err(|err| Status::aborted(err.to_string()))
+pub fn to_status_error<T, E: Error>(input: Result<T, E>, code: ErrorCode) -> Result<T, Status> {
+    input.map_err(|err| code.status(err.to_string()))
 }
 
 pub fn load_udf(udf: String) -> Result<CModule, Status> {
@@ -128,7 +139,7 @@ pub fn load_udf(udf: String) -> Result<CModule, Status> {
     )
 }
 
-fn get_tokenizer(
+pub fn get_tokenizer(
     model: &str,
     config: &str,
     revision: &str,
@@ -166,7 +177,7 @@ where
     T: Clone,
 {
     let arr = Array::from_shape_vec(shape, v)
-        .map_err(|e| Status::aborted(format!("{e}")))?
+        .map_err(|e| ErrorCode::ShapeMismatch.status(format!("{e}")))?
         .as_standard_layout()
         .to_owned();
 
@@ -183,45 +194,42 @@ pub fn series_to_tokenized_arrays<'a>(
 ) -> Result<(Array<i64, Dim<IxDynImpl>>, Array<i64, Dim<IxDynImpl>>), Status> {
     let tokenizer = get_tokenizer(model, config, revision, auth_token)?;
 
-    let out_ids = Arc::new(Mutex::new(Vec::new()));
-    let ids_shape = Arc::new(Mutex::new(Vec::new()));
-    let out_masks = Arc::new(Mutex::new(Vec::new()));
-    let masks_shape = Arc::new(Mutex::new(Vec::new()));
-
     // We use the `par_iter` for parallel processing of the rows since there are no dependence.
-    s.utf8().unwrap().par_iter().for_each(|row| match row {
-        Some(s) => {
-            let encoded = tokenizer
-                .encode(s, add_special_tokens)
-                .map_err(|e| Status::aborted(format!("Failed to tokenize string: {e}")))
-                .unwrap();
-            let mut ids = encoded
+    let rows = s
+        .utf8()
+        .map_err(|e| ErrorCode::DtypeMismatch.status(format!("Could not tokenize series: {e}")))?
+        .par_iter()
+        .flatten()
+        .map(|row| {
+            let encoded = tokenizer.encode(row, add_special_tokens).map_err(|e| {
+                ErrorCode::TokenizationFailed.status(format!("Failed to tokenize string: {e}"))
+            })?;
+            let ids = encoded
                 .get_ids()
-                .to_vec()
                 .iter()
                 .map(|v| *v as i64)
                 .collect::<Vec<_>>();
-            let mut mask = encoded
+            let mask = encoded
                 .get_attention_mask()
-                .to_vec()
                 .iter()
                 .map(|v| *v as i64)
                 .collect::<Vec<_>>();
+            Ok((ids, mask))
+        })
+        .collect::<Result<Vec<_>, Status>>()?;
 
-            ids_shape.lock().unwrap().push(ids.len());
-            masks_shape.lock().unwrap().push(mask.len());
-            out_ids.lock().unwrap().append(&mut ids);
-            out_masks.lock().unwrap().append(&mut mask);
-        }
-        None => (),
-    });
+    let mut out_ids = Vec::new();
+    let mut ids_shape = Vec::new();
+    let mut out_masks = Vec::new();
+    let mut masks_shape = Vec::new();
+    for (mut ids, mut mask) in rows {
+        ids_shape.push(ids.len());
+        masks_shape.push(mask.len());
+        out_ids.append(&mut ids);
+        out_masks.append(&mut mask);
+    }
 
     let get_shape = |shape_vec: &[usize]| vec![shape_vec.len(), shape_vec[0]];
-    let out_ids = out_ids.lock().unwrap().to_vec();
-    let ids_shape = ids_shape.lock().unwrap().to_vec();
-    let masks_shape = masks_shape.lock().unwrap().to_vec();
-    let out_masks = out_masks.lock().unwrap().to_vec();
-
     let ids_shape = get_shape(&ids_shape);
     let masks_shape = get_shape(&masks_shape[..]);
 
@@ -0,0 +1,153 @@
+use std::io::Read;
+use tonic::{metadata::MetadataValue, Request, Response, Status};
+
+/// Request metadata key giving the encoding of the chunks sent by the client.
+pub const CHUNK_ENCODING_KEY: &str = "chunk-encoding";
+/// Request metadata key giving the encoding the client accepts for the chunks it fetches.
+/// The server echoes the encoding it uses in the `chunk-encoding` response metadata.
+pub const ACCEPT_CHUNK_ENCODING_KEY: &str = "accept-chunk-encoding";
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Largest payload zstd uploads may decode to when the upload size is unlimited, so that
+/// small compressed payloads cannot fill memory.
+pub const DEFAULT_MAX_DECODED_SIZE: usize = 4 << 30;
+
+/// Encoding of the payload carried by a stream of chunks.
+///
+/// The payload is encoded as a whole before being split into chunks, and decoded
+/// once all the chunks have been received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkEncoding {
+    Identity,
+    Zstd,
+}
+
+impl ChunkEncoding {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "identity" => Some(ChunkEncoding::Identity),
+            "zstd" => Some(ChunkEncoding::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChunkEncoding::Identity => "identity",
+            ChunkEncoding::Zstd => "zstd",
+        }
+    }
+
+    /// Returns the encoding of the chunks uploaded with `request`.
+    pub fn of_request<T>(request: &Request<T>) -> Result<Self, Status> {
+        match request.metadata().get(CHUNK_ENCODING_KEY) {
+            None => Ok(ChunkEncoding::Identity),
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(Self::parse)
+                .ok_or_else(|| Status::invalid_argument("Unsupported chunk encoding")),
+        }
+    }
+
+    /// Returns the preferred encoding among those accepted by the sender of `request`.
+    pub fn accepted_by<T>(request: &Request<T>) -> Self {
+        let accepted = request
+            .metadata()
+            .get(ACCEPT_CHUNK_ENCODING_KEY)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+        if accepted
+            .split(',')
+            .any(|e| Self::parse(e) == Some(ChunkEncoding::Zstd))
+        {
+            ChunkEncoding::Zstd
+        } else {
+            ChunkEncoding::Identity
+        }
+    }
+
+    /// Advertises this encoding in the metadata of `response`.
+    pub fn set_on<T>(&self, response: &mut Response<T>) {
+        response.metadata_mut().insert(
+            CHUNK_ENCODING_KEY,
+            MetadataValue::from_static(self.as_str()),
+        );
+    }
+
+    pub fn encode(&self, data: Vec<u8>) -> Result<Vec<u8>, Status> {
+        match self {
+            ChunkEncoding::Identity => Ok(data),
+            ChunkEncoding::Zstd => zstd::stream::encode_all(&data[..], ZSTD_LEVEL)
+                .map_err(|e| Status::internal(format!("Could not compress data: {}", e))),
+        }
+    }
+
+    /// Decodes an uploaded payload, failing if it decodes to more than `max_size` bytes,
+    /// or [`DEFAULT_MAX_DECODED_SIZE`] for compressed payloads when unlimited.
+    pub fn decode(&self, data: Vec<u8>, max_size: Option<usize>) -> Result<Vec<u8>, Status> {
+        match self {
+            ChunkEncoding::Identity => {
+                check_upload_size(data.len(), max_size)?;
+                Ok(data)
+            }
+            ChunkEncoding::Zstd => decode_zstd(&data, max_size.unwrap_or(DEFAULT_MAX_DECODED_SIZE)),
+        }
+    }
+}
+
+fn decode_zstd(data: &[u8], max_size: usize) -> Result<Vec<u8>, Status> {
+    let decoder = zstd::stream::read::Decoder::new(data)
+        .map_err(|e| Status::invalid_argument(format!("Could not decompress data: {}", e)))?;
+    // Read one byte past the limit to detect oversized payloads without
+    // decompressing them entirely.
+    let mut decoded = Vec::new();
+    decoder
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|e| Status::invalid_argument(format!("Could not decompress data: {}", e)))?;
+    check_upload_size(decoded.len(), Some(max_size))?;
+    Ok(decoded)
+}
+
+/// Fails if an upload of `size` bytes is larger than `max_size`.
+///
+/// Receivers call this after every chunk so that oversized uploads are rejected
+/// before they are held in memory.
+pub fn check_upload_size(size: usize, max_size: Option<usize>) -> Result<(), Status> {
+    match max_size {
+        Some(max_size) if size > max_size => Err(Status::resource_exhausted(format!(
+            "Upload is larger than the limit of {} bytes",
+            max_size
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    #[test]
+    fn zstd_payloads_round_trip() {
+        let data = vec![7u8; 1000];
+        let encoded = ChunkEncoding::Zstd.encode(data.clone()).unwrap();
+        assert_eq!(ChunkEncoding::Zstd.decode(encoded, None).unwrap(), data);
+    }
+
+    #[test]
+    fn zstd_payloads_decoding_past_the_limit_are_rejected() {
+        let encoded = ChunkEncoding::Zstd.encode(vec![0u8; 1 << 20]).unwrap();
+        assert!(encoded.len() < 1 << 10);
+
+        let err = ChunkEncoding::Zstd
+            .decode(encoded.clone(), Some(1 << 19))
+            .unwrap_err();
+        assert_eq!(err.code(), Code::ResourceExhausted);
+        let err = decode_zstd(&encoded, (1 << 20) - 1).unwrap_err();
+        assert_eq!(err.code(), Code::ResourceExhausted);
+        assert_eq!(decode_zstd(&encoded, 1 << 20).unwrap().len(), 1 << 20);
+    }
+}
@@ -12,13 +12,66 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+use std::fs;
 use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::time::Duration;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use http::Uri;
 use serde::{de::Error, Deserialize, Deserializer};
 
+use crate::authorization::{is_method_name, AuthorizationPolicy, MethodPermissions};
+
+/// Prefix of the environment variables overriding config values,
+/// e.g. `BASTIONLAB_SESSION_EXPIRY_IN_SECS=600`.
+const ENV_PREFIX: &str = "BASTIONLAB_";
+
+/// Config values that can be overridden from the environment.
+const ENV_OVERRIDABLE: &[&str] = &[
+    "client_to_enclave_untrusted_url",
+    "public_keys_directory",
+    "session_expiry_in_secs",
+    "tls_cert_file",
+    "tls_key_file",
+    "artifacts_directory",
+    "at_rest_key_file",
+    "signing_key_file",
+    "runs_database",
+    "usage_log",
+    "provenance_log",
+    "torch_memory_budget_in_mb",
+    "training_threads",
+    "max_dataset_upload_size_in_mb",
+    "max_model_upload_size_in_mb",
+    "max_dataframe_upload_size_in_mb",
+    "chunk_size_in_bytes",
+    "max_chunk_size_in_bytes",
+    "artifact_reap_interval_in_secs",
+    "shutdown_grace_period_in_secs",
+    "rest_gateway_address",
+    "grpc_max_receive_message_size_in_bytes",
+    "grpc_max_send_message_size_in_bytes",
+    "grpc_max_concurrent_streams",
+    "grpc_keepalive_interval_in_secs",
+    "grpc_keepalive_timeout_in_secs",
+    "grpc_request_timeout_in_secs",
+    "orphaned_session_timeout_in_secs",
+];
+
+/// Overridable config values without a unit suffix that are parsed as numbers.
+const ENV_COUNTS: &[&str] = &["grpc_max_concurrent_streams", "training_threads"];
+
+/// Services that can be mounted on the server listener, besides sessions, health and reflection.
+pub const OPTIONAL_SERVICES: &[&str] = &["torch", "polars", "conversion"];
+
+/// Boolean config values, which are enabled by setting their environment variable to
+/// anything but `0` or `false`.
+const ENV_FLAGS: &[&str] = &["disable_authentication", "disable_telemetry"];
+
 #[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct BastionLabConfig {
     //  Connection for Client -> Enclave communication
     #[serde(deserialize_with = "deserialize_uri")]
@@ -26,6 +79,134 @@ pub struct BastionLabConfig {
 
     pub public_keys_directory: String,
     pub session_expiry_in_secs: u64,
+
+    // TLS identity of the server. Defaults to tls/host_server.pem and tls/host_server.key.
+    #[serde(default)]
+    pub tls_cert_file: Option<String>,
+    #[serde(default)]
+    pub tls_key_file: Option<String>,
+
+    // Also set by the legacy DISABLE_AUTHENTICATION environment variable.
+    #[serde(default)]
+    pub disable_authentication: bool,
+    #[serde(default)]
+    pub disable_telemetry: bool,
+
+    // Directory where Torch artifacts are persisted. Artifacts are kept in memory only if unset.
+    #[serde(default)]
+    pub artifacts_directory: Option<String>,
+
+    // S3-compatible bucket where Torch artifacts are persisted. Takes precedence over artifacts_directory.
+    #[serde(default)]
+    pub artifacts_s3: Option<S3Config>,
+
+    // File holding the key used to encrypt persisted artifacts, dataframes and runs.
+    #[serde(default)]
+    pub at_rest_key_file: Option<String>,
+
+    // File holding the PKCS#8 key used to sign fetched models. Generated at startup if unset.
+    #[serde(default)]
+    pub signing_key_file: Option<String>,
+
+    // Embedded database where run records and metric histories are kept. In memory only if unset.
+    #[serde(default)]
+    pub runs_database: Option<String>,
+
+    // File where the uses of datasets and dataframes are appended, for usage reports. In memory
+    // only if unset.
+    #[serde(default)]
+    pub usage_log: Option<String>,
+
+    // File where the derivations of artifacts are appended, to trace the data models derive
+    // from. In memory only if unset.
+    #[serde(default)]
+    pub provenance_log: Option<String>,
+
+    // Memory the Torch service may use for persisted artifacts before evicting them.
+    #[serde(default)]
+    pub torch_memory_budget_in_mb: Option<usize>,
+
+    // Largest uploads accepted per artifact type, checked as chunks arrive. Unlimited if unset,
+    // except that compressed uploads may not decode to more than 4 GiB.
+    #[serde(default)]
+    pub max_dataset_upload_size_in_mb: Option<usize>,
+    #[serde(default)]
+    pub max_model_upload_size_in_mb: Option<usize>,
+    #[serde(default)]
+    pub max_dataframe_upload_size_in_mb: Option<usize>,
+
+    // Threads running trainings and tests, apart from the request handlers. One per CPU if unset.
+    #[serde(default)]
+    pub training_threads: Option<usize>,
+
+    // Size of the chunks streamed to clients, and the largest size clients may ask for.
+    #[serde(default)]
+    pub chunk_size_in_bytes: Option<usize>,
+    #[serde(default)]
+    pub max_chunk_size_in_bytes: Option<usize>,
+
+    // How often artifacts whose time-to-live elapsed are deleted. Defaults to a minute.
+    #[serde(default)]
+    pub artifact_reap_interval_in_secs: Option<u64>,
+
+    // How long active trainings are given to checkpoint on shutdown. Defaults to 30 seconds.
+    #[serde(default)]
+    pub shutdown_grace_period_in_secs: Option<u64>,
+
+    // Services served next to sessions, among "torch", "polars" and "conversion". All if unset.
+    #[serde(default)]
+    pub services: Option<Vec<String>>,
+
+    // Hosts notification webhooks may be registered on, e.g. "hooks.example.com". Webhooks
+    // are refused if unset, so that users cannot make the server reach internal services.
+    #[serde(default)]
+    pub webhook_hosts: Option<Vec<String>>,
+
+    // Address of the HTTP/JSON gateway, e.g. "0.0.0.0:50057". Disabled if unset.
+    #[serde(default)]
+    pub rest_gateway_address: Option<String>,
+
+    // Largest gRPC message the server accepts from clients, and sends to them. Unlimited if unset.
+    #[serde(default)]
+    pub grpc_max_receive_message_size_in_bytes: Option<usize>,
+    #[serde(default)]
+    pub grpc_max_send_message_size_in_bytes: Option<usize>,
+
+    // Streams a single client connection may have open at once. Unlimited if unset.
+    #[serde(default)]
+    pub grpc_max_concurrent_streams: Option<u32>,
+
+    // How often idle connections are pinged, and how long a ping may stay unanswered before
+    // the connection is closed. Idle connections are kept forever if unset.
+    #[serde(default)]
+    pub grpc_keepalive_interval_in_secs: Option<u64>,
+    #[serde(default)]
+    pub grpc_keepalive_timeout_in_secs: Option<u64>,
+
+    // How long the server may take to start answering a request. Unlimited if unset.
+    #[serde(default)]
+    pub grpc_request_timeout_in_secs: Option<u64>,
+
+    // How long a session may go without heartbeat or request before its runs are cancelled
+    // and its temporary tensors and dataframes released. Never if unset.
+    #[serde(default)]
+    pub orphaned_session_timeout_in_secs: Option<u64>,
+
+    // Roles allowed or denied each gRPC method, e.g. `fetch_dataset = { data_scientist = "deny" }`.
+    // Applies when authentication is enabled. Every method is allowed to every role if unset.
+    #[serde(default)]
+    pub authorization: Option<HashMap<String, MethodPermissions>>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct S3Config {
+    pub bucket: String,
+    pub region: String,
+    // Defaults to the AWS endpoint of the region.
+    pub endpoint: Option<String>,
+    pub prefix: Option<String>,
+    // KMS key used for server-side encryption. S3-managed keys are used if unset.
+    pub sse_kms_key_id: Option<String>,
 }
 
 fn uri_to_socket(uri: &Uri) -> Result<SocketAddr> {
@@ -38,10 +219,180 @@ fn uri_to_socket(uri: &Uri) -> Result<SocketAddr> {
 }
 
 impl BastionLabConfig {
+    /// Reads the config file at `path`, applies the environment overrides and validates
+    /// the result, reporting every invalid value at once.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| anyhow!("Reading the config file {path:?}"))?;
+        let mut table: toml::value::Table = toml::from_str(&contents)
+            .with_context(|| anyhow!("Parsing the config file {path:?}"))?;
+        apply_env_overrides(&mut table, std::env::vars());
+        let config: BastionLabConfig = toml::Value::Table(table)
+            .try_into()
+            .with_context(|| anyhow!("Parsing the config file {path:?}"))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+        if let Err(e) = self.client_to_enclave_untrusted_socket() {
+            errors.push(format!("client_to_enclave_untrusted_url: {e}"));
+        }
+        if let Some(services) = &self.services {
+            for service in services {
+                if !OPTIONAL_SERVICES.contains(&service.as_str()) {
+                    errors.push(format!(
+                        "services: unknown service {service}, expected one of {}",
+                        OPTIONAL_SERVICES.join(", ")
+                    ));
+                }
+            }
+        }
+        if self.service_enabled("conversion")
+            && !(self.service_enabled("torch") && self.service_enabled("polars"))
+        {
+            errors.push(String::from(
+                "services: conversion requires both torch and polars",
+            ));
+        }
+        if let Err(e) = self.rest_gateway_socket() {
+            errors.push(format!("rest_gateway_address: {e}"));
+        }
+        if self.session_expiry_in_secs == 0 {
+            errors.push(String::from("session_expiry_in_secs: must be positive"));
+        }
+        if !self.disable_authentication && !Path::new(&self.public_keys_directory).is_dir() {
+            errors.push(format!(
+                "public_keys_directory: {} is not a directory",
+                self.public_keys_directory
+            ));
+        }
+        for (key, path) in [
+            ("tls_cert_file", Some(self.tls_cert_file())),
+            ("tls_key_file", Some(self.tls_key_file())),
+            ("at_rest_key_file", self.at_rest_key_file()),
+            ("signing_key_file", self.signing_key_file()),
+        ] {
+            if let Some(path) = path {
+                if !Path::new(&path).is_file() {
+                    errors.push(format!("{key}: {path} is not a file"));
+                }
+            }
+        }
+        if self.torch_memory_budget_in_mb == Some(0) {
+            errors.push(String::from("torch_memory_budget_in_mb: must be positive"));
+        }
+        for (key, value) in [
+            (
+                "max_dataset_upload_size_in_mb",
+                self.max_dataset_upload_size_in_mb,
+            ),
+            (
+                "max_model_upload_size_in_mb",
+                self.max_model_upload_size_in_mb,
+            ),
+            (
+                "max_dataframe_upload_size_in_mb",
+                self.max_dataframe_upload_size_in_mb,
+            ),
+        ] {
+            if value == Some(0) {
+                errors.push(format!("{key}: must be positive"));
+            }
+        }
+        if self.training_threads == Some(0) {
+            errors.push(String::from("training_threads: must be positive"));
+        }
+        if self.chunk_size_in_bytes == Some(0) {
+            errors.push(String::from("chunk_size_in_bytes: must be positive"));
+        }
+        if let (Some(chunk_size), Some(max_chunk_size)) =
+            (self.chunk_size_in_bytes, self.max_chunk_size_in_bytes)
+        {
+            if max_chunk_size < chunk_size {
+                errors.push(String::from(
+                    "max_chunk_size_in_bytes: must not be smaller than chunk_size_in_bytes",
+                ));
+            }
+        }
+        for (key, value) in [
+            (
+                "grpc_max_receive_message_size_in_bytes",
+                self.grpc_max_receive_message_size_in_bytes,
+            ),
+            (
+                "grpc_max_send_message_size_in_bytes",
+                self.grpc_max_send_message_size_in_bytes,
+            ),
+            (
+                "grpc_max_concurrent_streams",
+                self.grpc_max_concurrent_streams.map(|n| n as usize),
+            ),
+            (
+                "grpc_keepalive_interval_in_secs",
+                self.grpc_keepalive_interval_in_secs.map(|n| n as usize),
+            ),
+            (
+                "grpc_request_timeout_in_secs",
+                self.grpc_request_timeout_in_secs.map(|n| n as usize),
+            ),
+            (
+                "orphaned_session_timeout_in_secs",
+                self.orphaned_session_timeout_in_secs.map(|n| n as usize),
+            ),
+        ] {
+            if value == Some(0) {
+                errors.push(format!("{key}: must be positive"));
+            }
+        }
+        if self.grpc_keepalive_timeout_in_secs.is_some()
+            && self.grpc_keepalive_interval_in_secs.is_none()
+        {
+            errors.push(String::from(
+                "grpc_keepalive_timeout_in_secs: requires grpc_keepalive_interval_in_secs",
+            ));
+        }
+        for method in self.authorization.iter().flat_map(|methods| methods.keys()) {
+            if !is_method_name(method) {
+                errors.push(format!(
+                    "authorization: {method} is not a method name in snake case, e.g. fetch_dataset"
+                ));
+            }
+        }
+
+        if !errors.is_empty() {
+            bail!("Invalid configuration:\n  - {}", errors.join("\n  - "));
+        }
+        Ok(())
+    }
+
     pub fn client_to_enclave_untrusted_socket(&self) -> Result<SocketAddr> {
         uri_to_socket(&self.client_to_enclave_untrusted_url)
     }
 
+    pub fn service_enabled(&self, service: &str) -> bool {
+        match &self.services {
+            Some(services) => services.iter().any(|s| s == service),
+            None => true,
+        }
+    }
+
+    pub fn webhook_hosts(&self) -> Vec<String> {
+        self.webhook_hosts.clone().unwrap_or_default()
+    }
+
+    pub fn rest_gateway_socket(&self) -> Result<Option<SocketAddr>> {
+        self.rest_gateway_address
+            .as_ref()
+            .map(|addr| {
+                addr.to_socket_addrs()?
+                    .next()
+                    .context("Address could not be resolved")
+            })
+            .transpose()
+    }
+
     pub fn public_keys_directory(&self) -> Result<String> {
         Ok(self.public_keys_directory.clone())
     }
@@ -49,6 +400,162 @@ impl BastionLabConfig {
     pub fn session_expiry(&self) -> Result<u64> {
         Ok(self.session_expiry_in_secs)
     }
+
+    pub fn tls_cert_file(&self) -> String {
+        self.tls_cert_file
+            .clone()
+            .unwrap_or_else(|| String::from("tls/host_server.pem"))
+    }
+
+    pub fn tls_key_file(&self) -> String {
+        self.tls_key_file
+            .clone()
+            .unwrap_or_else(|| String::from("tls/host_server.key"))
+    }
+
+    pub fn authentication_enabled(&self) -> bool {
+        !self.disable_authentication
+    }
+
+    pub fn telemetry_enabled(&self) -> bool {
+        !self.disable_telemetry
+    }
+
+    pub fn artifacts_directory(&self) -> Option<String> {
+        self.artifacts_directory.clone()
+    }
+
+    pub fn artifacts_s3(&self) -> Option<S3Config> {
+        self.artifacts_s3.clone()
+    }
+
+    pub fn at_rest_key_file(&self) -> Option<String> {
+        self.at_rest_key_file.clone()
+    }
+
+    pub fn signing_key_file(&self) -> Option<String> {
+        self.signing_key_file.clone()
+    }
+
+    pub fn runs_database(&self) -> Option<String> {
+        self.runs_database.clone()
+    }
+
+    pub fn usage_log(&self) -> Option<String> {
+        self.usage_log.clone()
+    }
+
+    pub fn provenance_log(&self) -> Option<String> {
+        self.provenance_log.clone()
+    }
+
+    pub fn torch_memory_budget(&self) -> Option<usize> {
+        self.torch_memory_budget_in_mb.map(|mb| mb * 1024 * 1024)
+    }
+
+    pub fn max_dataset_upload_size(&self) -> Option<usize> {
+        self.max_dataset_upload_size_in_mb
+            .map(|mb| mb * 1024 * 1024)
+    }
+
+    pub fn max_model_upload_size(&self) -> Option<usize> {
+        self.max_model_upload_size_in_mb.map(|mb| mb * 1024 * 1024)
+    }
+
+    pub fn max_dataframe_upload_size(&self) -> Option<usize> {
+        self.max_dataframe_upload_size_in_mb
+            .map(|mb| mb * 1024 * 1024)
+    }
+
+    pub fn training_threads(&self) -> Option<usize> {
+        self.training_threads
+    }
+
+    pub fn chunk_size(&self) -> Option<usize> {
+        self.chunk_size_in_bytes
+    }
+
+    pub fn max_chunk_size(&self) -> Option<usize> {
+        self.max_chunk_size_in_bytes
+    }
+
+    pub fn artifact_reap_interval(&self) -> Duration {
+        Duration::from_secs(self.artifact_reap_interval_in_secs.unwrap_or(60).max(1))
+    }
+
+    pub fn shutdown_grace_period(&self) -> Duration {
+        Duration::from_secs(self.shutdown_grace_period_in_secs.unwrap_or(30))
+    }
+
+    pub fn grpc_max_receive_message_size(&self) -> Option<usize> {
+        self.grpc_max_receive_message_size_in_bytes
+    }
+
+    pub fn grpc_max_send_message_size(&self) -> Option<usize> {
+        self.grpc_max_send_message_size_in_bytes
+    }
+
+    pub fn grpc_max_concurrent_streams(&self) -> Option<u32> {
+        self.grpc_max_concurrent_streams
+    }
+
+    pub fn grpc_keepalive_interval(&self) -> Option<Duration> {
+        self.grpc_keepalive_interval_in_secs
+            .map(Duration::from_secs)
+    }
+
+    pub fn grpc_keepalive_timeout(&self) -> Option<Duration> {
+        self.grpc_keepalive_timeout_in_secs.map(Duration::from_secs)
+    }
+
+    pub fn grpc_request_timeout(&self) -> Option<Duration> {
+        self.grpc_request_timeout_in_secs.map(Duration::from_secs)
+    }
+
+    pub fn orphaned_session_timeout(&self) -> Option<Duration> {
+        self.orphaned_session_timeout_in_secs
+            .map(Duration::from_secs)
+    }
+
+    pub fn authorization_policy(&self) -> AuthorizationPolicy {
+        AuthorizationPolicy::new(self.authorization.clone().unwrap_or_default())
+    }
+}
+
+/// Overrides the values of `table` with the `BASTIONLAB_*` variables of `vars`.
+/// Unrelated variables sharing the prefix are ignored.
+fn apply_env_overrides(
+    table: &mut toml::value::Table,
+    vars: impl Iterator<Item = (String, String)>,
+) {
+    for (name, value) in vars {
+        if name == "DISABLE_AUTHENTICATION" {
+            table.insert(
+                String::from("disable_authentication"),
+                toml::Value::Boolean(true),
+            );
+            continue;
+        }
+        let key = match name.strip_prefix(ENV_PREFIX) {
+            Some(key) => key.to_lowercase(),
+            None => continue,
+        };
+        let value = if ENV_FLAGS.contains(&key.as_str()) {
+            toml::Value::Boolean(!matches!(value.as_str(), "0" | "false"))
+        } else if ENV_OVERRIDABLE.contains(&key.as_str()) {
+            let numeric = ["_in_secs", "_in_mb", "_in_bytes"]
+                .iter()
+                .any(|unit| key.ends_with(unit))
+                || ENV_COUNTS.contains(&key.as_str());
+            match value.parse::<i64>() {
+                Ok(n) if numeric => toml::Value::Integer(n),
+                _ => toml::Value::String(value),
+            }
+        } else {
+            continue;
+        };
+        table.insert(key, value);
+    }
 }
 
 fn deserialize_uri<'de, D>(deserializer: D) -> Result<Uri, D::Error>
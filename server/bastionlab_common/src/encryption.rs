@@ -0,0 +1,190 @@
+use std::{fmt, fs, path::Path};
+
+use crate::prelude::*;
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, ECDH_P256},
+    hkdf,
+    rand::{SecureRandom, SystemRandom},
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+};
+use tonic::Status;
+
+/// Key used to encrypt data persisted by the server (artifacts, dataframes).
+///
+/// The key is a raw 32-byte AES-256-GCM key read from a file, which is expected to be
+/// provisioned by a KMS or unsealed by the enclave at boot. Sealed data is laid out as
+/// `[nonce: 12 bytes | ciphertext | tag: 16 bytes]`.
+#[derive(Clone)]
+pub struct AtRestKey(Arc<LessSafeKey>);
+
+impl fmt::Debug for AtRestKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AtRestKey")
+    }
+}
+
+impl AtRestKey {
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).with_context(|| anyhow!("Reading key file: {path:?}"))?;
+        Self::from_bytes(&bytes).with_context(|| anyhow!("Invalid key in {path:?}"))
+    }
+
+    /// Builds a key from its 32 raw bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let key =
+            UnboundKey::new(&AES_256_GCM, bytes).map_err(|_| anyhow!("expected 32 raw bytes"))?;
+        Ok(AtRestKey(Arc::new(LessSafeKey::new(key))))
+    }
+
+    /// Encrypts `data`, binding it to `aad` (typically the identifier of the stored object)
+    /// so that encrypted files cannot be swapped with one another.
+    pub fn seal(&self, aad: &[u8], mut data: Vec<u8>) -> Result<Vec<u8>, Status> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| Status::internal("Could not generate nonce"))?;
+        self.0
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(aad),
+                &mut data,
+            )
+            .map_err(|_| Status::internal("Could not encrypt data"))?;
+
+        let mut res = Vec::with_capacity(NONCE_LEN + data.len());
+        res.extend_from_slice(&nonce);
+        res.append(&mut data);
+        Ok(res)
+    }
+
+    /// Decrypts data produced by [`AtRestKey::seal`] with the same `aad`.
+    pub fn open(&self, aad: &[u8], mut data: Vec<u8>) -> Result<Vec<u8>, Status> {
+        if data.len() < NONCE_LEN {
+            return Err(Status::data_loss("Encrypted data is truncated"));
+        }
+        let mut ciphertext = data.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&data)
+            .map_err(|_| Status::data_loss("Invalid nonce"))?;
+        let len = self
+            .0
+            .open_in_place(nonce, Aad::from(aad), &mut ciphertext)
+            .map_err(|_| Status::data_loss("Could not decrypt data: wrong key or corrupted data"))?
+            .len();
+        ciphertext.truncate(len);
+        Ok(ciphertext)
+    }
+}
+
+const SEALED_INFO: &[u8] = b"bastionlab sealed data";
+const P256_POINT_LEN: usize = 65;
+
+/// P-256 public key of a party to which data can be sealed, so that only the
+/// holder of the matching private key can read it.
+///
+/// Sealed data is laid out as `[ephemeral public key: 65 bytes | ciphertext | tag: 16 bytes]`.
+/// The AES-256-GCM key is derived with HKDF-SHA256 from an ECDH agreement between a
+/// fresh ephemeral key and the recipient key, and is only used once, hence the zero nonce.
+#[derive(Debug, Clone)]
+pub struct RecipientKey(Vec<u8>);
+
+impl RecipientKey {
+    /// Parses a DER-encoded SubjectPublicKeyInfo, as sent by clients.
+    pub fn from_der(der: &[u8]) -> Result<Self, Status> {
+        let info = spki::SubjectPublicKeyInfo::try_from(der)
+            .map_err(|_| Status::invalid_argument("Invalid SubjectPublicKeyInfo"))?;
+        let point = info.subject_public_key;
+        if point.len() != P256_POINT_LEN || point[0] != 0x04 {
+            return Err(Status::invalid_argument(
+                "Recipient key must be an uncompressed P-256 public key",
+            ));
+        }
+        Ok(RecipientKey(point.to_vec()))
+    }
+
+    pub fn seal(&self, mut data: Vec<u8>) -> Result<Vec<u8>, Status> {
+        let rng = SystemRandom::new();
+        let ephemeral = EphemeralPrivateKey::generate(&ECDH_P256, &rng)
+            .map_err(|_| Status::internal("Could not generate ephemeral key"))?;
+        let ephemeral_public = ephemeral
+            .compute_public_key()
+            .map_err(|_| Status::internal("Could not generate ephemeral key"))?;
+        let key = agreement::agree_ephemeral(
+            ephemeral,
+            &UnparsedPublicKey::new(&ECDH_P256, &self.0),
+            Status::invalid_argument("Invalid recipient key"),
+            |secret| {
+                let okm = hkdf::Salt::new(hkdf::HKDF_SHA256, &[])
+                    .extract(secret)
+                    .expand(&[SEALED_INFO], &AES_256_GCM)
+                    .map_err(|_| Status::internal("Could not derive encryption key"))?;
+                Ok(LessSafeKey::new(UnboundKey::from(okm)))
+            },
+        )?;
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key([0u8; NONCE_LEN]),
+            Aad::empty(),
+            &mut data,
+        )
+        .map_err(|_| Status::internal("Could not encrypt data"))?;
+
+        let mut res = Vec::with_capacity(P256_POINT_LEN + data.len());
+        res.extend_from_slice(ephemeral_public.as_ref());
+        res.append(&mut data);
+        Ok(res)
+    }
+}
+
+/// Context prepended to the hex-encoded SHA-256 of fetched artifacts before signing them.
+pub const SIGNED_ARTIFACT_CONTEXT: &[u8] = b"bastionlab signed artifact";
+/// Context prepended to model cards before signing them.
+pub const SIGNED_MODEL_CARD_CONTEXT: &[u8] = b"bastionlab signed model card";
+
+/// Key with which the server signs the models and model cards it hands out, so that
+/// anyone holding its public part can check that they were produced by this server.
+///
+/// The key is a PKCS#8-encoded P-256 key read from a file, or generated at startup, in which
+/// case signatures cannot be checked across restarts. Signatures are ASN.1-encoded
+/// ECDSA-SHA256 signatures of a context string followed by the signed data.
+#[derive(Clone)]
+pub struct ServerSigningKey(Arc<EcdsaKeyPair>);
+
+impl fmt::Debug for ServerSigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ServerSigningKey")
+    }
+}
+
+impl ServerSigningKey {
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).with_context(|| anyhow!("Reading key file: {path:?}"))?;
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &bytes)
+            .map_err(|_| anyhow!("Invalid key in {path:?}: expected a PKCS#8 P-256 key"))?;
+        Ok(ServerSigningKey(Arc::new(key)))
+    }
+
+    pub fn generate() -> Result<Self> {
+        let pkcs8 =
+            EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &SystemRandom::new())
+                .map_err(|_| anyhow!("Could not generate signing key"))?;
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref())
+            .map_err(|_| anyhow!("Could not generate signing key"))?;
+        Ok(ServerSigningKey(Arc::new(key)))
+    }
+
+    /// Returns the public key, as an uncompressed P-256 point.
+    pub fn public_key(&self) -> &[u8] {
+        self.0.public_key().as_ref()
+    }
+
+    pub fn sign(&self, context: &[u8], data: &[u8]) -> Result<Vec<u8>, Status> {
+        let mut message = Vec::with_capacity(context.len() + data.len());
+        message.extend_from_slice(context);
+        message.extend_from_slice(data);
+        let signature = self
+            .0
+            .sign(&SystemRandom::new(), &message)
+            .map_err(|_| Status::internal("Could not sign data"))?;
+        Ok(signature.as_ref().to_vec())
+    }
+}
@@ -0,0 +1,141 @@
+use crate::session_proto::ErrorCode;
+use tonic::metadata::{AsciiMetadataKey, MetadataValue};
+use tonic::{Code, Status};
+
+/// Metadata key holding the name of the [`ErrorCode`] of a failed request.
+pub const ERROR_CODE_KEY: &str = "bastionlab-error-code";
+
+/// Prefix of the metadata keys holding the details of a failed request, e.g.
+/// `bastionlab-detail-epoch`.
+pub const ERROR_DETAIL_PREFIX: &str = "bastionlab-detail-";
+
+impl ErrorCode {
+    /// Name of the code, as in the protobuf definition and in the metadata of the status.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorCode::UnknownError => "UNKNOWN_ERROR",
+            ErrorCode::SessionNotFound => "SESSION_NOT_FOUND",
+            ErrorCode::SessionExpired => "SESSION_EXPIRED",
+            ErrorCode::ClientAddressMismatch => "CLIENT_ADDRESS_MISMATCH",
+            ErrorCode::ClientAddressUnavailable => "CLIENT_ADDRESS_UNAVAILABLE",
+            ErrorCode::InvalidKeyDirectory => "INVALID_KEY_DIRECTORY",
+            ErrorCode::DtypeMismatch => "DTYPE_MISMATCH",
+            ErrorCode::UnsupportedDtype => "UNSUPPORTED_DTYPE",
+            ErrorCode::ShapeMismatch => "SHAPE_MISMATCH",
+            ErrorCode::ConversionFailed => "CONVERSION_FAILED",
+            ErrorCode::TokenizationRequired => "TOKENIZATION_REQUIRED",
+            ErrorCode::TokenizationFailed => "TOKENIZATION_FAILED",
+            ErrorCode::LabelsNotFound => "LABELS_NOT_FOUND",
+            ErrorCode::TrainingDiverged => "TRAINING_DIVERGED",
+            ErrorCode::PolicyDenied => "POLICY_DENIED",
+            ErrorCode::FetchRejected => "FETCH_REJECTED",
+            ErrorCode::PrivacyBudgetExhausted => "PRIVACY_BUDGET_EXHAUSTED",
+            ErrorCode::FetchLimitExceeded => "FETCH_LIMIT_EXCEEDED",
+            ErrorCode::AuthenticationLockedOut => "AUTHENTICATION_LOCKED_OUT",
+        }
+    }
+
+    /// gRPC code of the statuses with this error code.
+    pub fn grpc_code(&self) -> Code {
+        match self {
+            ErrorCode::UnknownError => Code::Unknown,
+            ErrorCode::SessionNotFound
+            | ErrorCode::SessionExpired
+            | ErrorCode::ClientAddressMismatch
+            | ErrorCode::ClientAddressUnavailable => Code::Unauthenticated,
+            ErrorCode::InvalidKeyDirectory => Code::FailedPrecondition,
+            ErrorCode::DtypeMismatch
+            | ErrorCode::UnsupportedDtype
+            | ErrorCode::ShapeMismatch
+            | ErrorCode::ConversionFailed
+            | ErrorCode::TokenizationFailed => Code::InvalidArgument,
+            ErrorCode::TokenizationRequired => Code::FailedPrecondition,
+            ErrorCode::LabelsNotFound => Code::NotFound,
+            ErrorCode::TrainingDiverged => Code::Aborted,
+            ErrorCode::PolicyDenied
+            | ErrorCode::FetchRejected
+            | ErrorCode::PrivacyBudgetExhausted => Code::PermissionDenied,
+            ErrorCode::FetchLimitExceeded | ErrorCode::AuthenticationLockedOut => {
+                Code::ResourceExhausted
+            }
+        }
+    }
+
+    /// Failed request status carrying this error code in its metadata.
+    pub fn status(self, message: impl Into<String>) -> Status {
+        self.status_with_details(message, [])
+    }
+
+    /// Same as [`ErrorCode::status`], with `details` set in the metadata under their name,
+    /// prefixed with [`ERROR_DETAIL_PREFIX`]. Names must be lowercase, and details which are
+    /// not valid metadata values are left out.
+    pub fn status_with_details<'a>(
+        self,
+        message: impl Into<String>,
+        details: impl IntoIterator<Item = (&'a str, String)>,
+    ) -> Status {
+        let mut status = Status::new(self.grpc_code(), message);
+        let metadata = status.metadata_mut();
+        metadata.insert(ERROR_CODE_KEY, MetadataValue::from_static(self.name()));
+        for (name, value) in details {
+            let key = format!("{ERROR_DETAIL_PREFIX}{name}");
+            if let (Ok(key), Ok(value)) =
+                (AsciiMetadataKey::from_bytes(key.as_bytes()), value.parse())
+            {
+                metadata.insert(key, value);
+            }
+        }
+        status
+    }
+}
+
+/// Name of the [`ErrorCode`] of `status`, `UNKNOWN_ERROR` if it carries none.
+pub fn error_code_name(status: &Status) -> &str {
+    status
+        .metadata()
+        .get(ERROR_CODE_KEY)
+        .and_then(|code| code.to_str().ok())
+        .unwrap_or_else(|| ErrorCode::UnknownError.name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_carries_code_and_details() {
+        let status = ErrorCode::TrainingDiverged.status_with_details(
+            "Training diverged",
+            [
+                ("epoch", String::from("3")),
+                ("batch", String::from("12")),
+                ("invalid", String::from("new\nline")),
+            ],
+        );
+        assert_eq!(status.code(), Code::Aborted);
+        assert_eq!(status.message(), "Training diverged");
+        assert_eq!(error_code_name(&status), "TRAINING_DIVERGED");
+        let metadata = status.metadata();
+        assert_eq!(metadata.get("bastionlab-detail-epoch").unwrap(), "3");
+        assert_eq!(metadata.get("bastionlab-detail-batch").unwrap(), "12");
+        assert!(metadata.get("bastionlab-detail-invalid").is_none());
+    }
+
+    #[test]
+    fn codes_set_their_grpc_code() {
+        let status = ErrorCode::FetchLimitExceeded.status("Too many fetches");
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(error_code_name(&status), "FETCH_LIMIT_EXCEEDED");
+        let status = ErrorCode::PolicyDenied.status("Denied");
+        assert_eq!(status.code(), Code::PermissionDenied);
+        assert_eq!(error_code_name(&status), "POLICY_DENIED");
+    }
+
+    #[test]
+    fn statuses_without_code_are_unknown() {
+        assert_eq!(
+            error_code_name(&Status::permission_denied("Denied")),
+            "UNKNOWN_ERROR"
+        );
+    }
+}
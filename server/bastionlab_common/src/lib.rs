@@ -1,10 +1,20 @@
 pub mod array_store;
 pub mod auth;
+pub mod authorization;
+pub mod cancellation;
+pub mod capabilities;
 pub mod common_conversions;
+pub mod compression;
 pub mod config;
+pub mod encryption;
+pub mod errors;
+pub mod notifications;
 pub mod prelude;
+pub mod provenance;
+pub mod remote_array;
 pub mod session;
 pub mod telemetry;
+pub mod usage;
 
 pub mod session_proto {
     tonic::include_proto!("bastionlab");
@@ -0,0 +1,332 @@
+use crate::prelude::*;
+use crate::session::SessionManager;
+use crate::session_proto::{
+    notification_service_server::NotificationService, Empty, Notification, NotificationFilter,
+    NotificationKind, Webhook, WebhookReference,
+};
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+/// Fractions of the privacy budget of datasets and data frames whose crossing is notified.
+pub const PRIVACY_BUDGET_THRESHOLDS: [f32; 4] = [0.5, 0.75, 0.9, 1.0];
+
+/// Notifications kept for slow subscribers before they miss some.
+const CHANNEL_CAPACITY: usize = 256;
+const MAX_WEBHOOKS_PER_USER: usize = 16;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+struct Event {
+    notification: Notification,
+    /// The user the notification is about, if any.
+    user_id: Option<String>,
+    /// Whether every data owner receives the notification, on top of `user_id`.
+    to_owners: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Recipient {
+    user_id: String,
+    is_owner: bool,
+    kinds: Vec<i32>,
+}
+
+impl Recipient {
+    fn receives(&self, event: &Event) -> bool {
+        ((self.is_owner && event.to_owners) || event.user_id.as_deref() == Some(&self.user_id))
+            && (self.kinds.is_empty() || self.kinds.contains(&event.notification.kind))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct WebhookEntry {
+    url: reqwest::Url,
+    recipient: Recipient,
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    kind: &'static str,
+    identifier: &'a str,
+    message: &'a str,
+    time: u64,
+}
+
+fn kind_name(kind: i32) -> &'static str {
+    match NotificationKind::from_i32(kind) {
+        Some(NotificationKind::RunCompleted) => "run_completed",
+        Some(NotificationKind::RunFailed) => "run_failed",
+        Some(NotificationKind::PrivacyBudgetThreshold) => "privacy_budget_threshold",
+        Some(NotificationKind::FetchApprovalPending) => "fetch_approval_pending",
+        Some(NotificationKind::DatasetUsed) => "dataset_used",
+        Some(NotificationKind::AuthenticationLockout) => "authentication_lockout",
+        None => "unknown",
+    }
+}
+
+/// Delivers notifications about runs, privacy budgets and fetches to the clients that
+/// subscribed to them with a stream or a webhook, so that they do not have to poll.
+///
+/// Webhooks are kept in memory and must be registered again after a restart.
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    events: broadcast::Sender<Event>,
+    webhooks: Arc<RwLock<HashMap<String, WebhookEntry>>>,
+    /// Hosts webhooks may be registered on, none if empty.
+    webhook_hosts: Arc<Vec<String>>,
+}
+
+impl Notifier {
+    /// Creates the notifier and starts delivering webhooks to `webhook_hosts`. Must be
+    /// called from within a Tokio runtime.
+    pub fn start(webhook_hosts: Vec<String>) -> Self {
+        let (events, receiver) = broadcast::channel(CHANNEL_CAPACITY);
+        let notifier = Notifier {
+            events,
+            webhooks: Arc::default(),
+            webhook_hosts: Arc::new(webhook_hosts),
+        };
+        tokio::spawn(deliver_webhooks(receiver, Arc::clone(&notifier.webhooks)));
+        notifier
+    }
+
+    /// Sends a notification of the given kind about `identifier`, to the data owners and to
+    /// `user_id`, if any. This does not block and may be called from any thread.
+    pub fn notify(
+        &self,
+        kind: NotificationKind,
+        identifier: &str,
+        message: String,
+        user_id: Option<&str>,
+    ) {
+        self.send(kind, identifier, message, user_id, true);
+    }
+
+    /// Sends a notification of the given kind about `identifier` to `user_id` only, e.g.
+    /// about the use of their own dataset, which other data owners may not see.
+    pub fn notify_user(
+        &self,
+        kind: NotificationKind,
+        identifier: &str,
+        message: String,
+        user_id: &str,
+    ) {
+        self.send(kind, identifier, message, Some(user_id), false);
+    }
+
+    fn send(
+        &self,
+        kind: NotificationKind,
+        identifier: &str,
+        message: String,
+        user_id: Option<&str>,
+        to_owners: bool,
+    ) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        // Fails only when nobody listens.
+        let _ = self.events.send(Event {
+            notification: Notification {
+                kind: kind as i32,
+                identifier: identifier.to_string(),
+                message,
+                time,
+            },
+            user_id: user_id.map(String::from),
+            to_owners,
+        });
+    }
+
+    /// Fails unless `url` is on one of the allowed hosts, which
+    /// keeps users from making the server send requests to internal services.
+    fn check_webhook_host(&self, url: &reqwest::Url) -> Result<(), Status> {
+        if self.webhook_hosts.is_empty() {
+            return Err(Status::failed_precondition(
+                "Webhooks are disabled on this server",
+            ));
+        }
+        let allowed = url.host_str().map_or(false, |host| {
+            self.webhook_hosts
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(host))
+        });
+        if !allowed {
+            return Err(Status::permission_denied(format!(
+                "Webhooks may not be sent to {}",
+                url.host_str().unwrap_or_default()
+            )));
+        }
+        Ok(())
+    }
+
+    fn register_webhook(&self, url: reqwest::Url, recipient: Recipient) -> Result<String, Status> {
+        self.check_webhook_host(&url)?;
+        let mut webhooks = self.webhooks.write().unwrap();
+        let registered = webhooks
+            .values()
+            .filter(|webhook| webhook.recipient.user_id == recipient.user_id)
+            .count();
+        if registered >= MAX_WEBHOOKS_PER_USER {
+            return Err(Status::resource_exhausted(format!(
+                "A user may not register more than {} webhooks",
+                MAX_WEBHOOKS_PER_USER
+            )));
+        }
+        let identifier = Uuid::new_v4().to_string();
+        webhooks.insert(identifier.clone(), WebhookEntry { url, recipient });
+        Ok(identifier)
+    }
+}
+
+async fn deliver_webhooks(
+    mut receiver: broadcast::Receiver<Event>,
+    webhooks: Arc<RwLock<HashMap<String, WebhookEntry>>>,
+) {
+    // Redirects could lead webhooks away from the allowed hosts.
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("Could not create the webhook client");
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("{} notifications were not sent to webhooks", missed);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let urls: Vec<_> = webhooks
+            .read()
+            .unwrap()
+            .values()
+            .filter(|webhook| webhook.recipient.receives(&event))
+            .map(|webhook| webhook.url.clone())
+            .collect();
+        for url in urls {
+            let notification = &event.notification;
+            let request = client
+                .post(url.clone())
+                .timeout(WEBHOOK_TIMEOUT)
+                .json(&WebhookPayload {
+                    kind: kind_name(notification.kind),
+                    identifier: &notification.identifier,
+                    message: &notification.message,
+                    time: notification.time,
+                });
+            tokio::spawn(async move {
+                match request.send().await.and_then(|res| res.error_for_status()) {
+                    Ok(_) => debug!("Sent notification to webhook {}", url),
+                    Err(e) => warn!("Could not send notification to webhook {}: {}", url, e),
+                }
+            });
+        }
+    }
+}
+
+pub struct NotificationGrpcService {
+    sess_manager: Arc<SessionManager>,
+    notifier: Notifier,
+}
+
+impl NotificationGrpcService {
+    pub fn new(sess_manager: Arc<SessionManager>, notifier: Notifier) -> Self {
+        Self {
+            sess_manager,
+            notifier,
+        }
+    }
+
+    fn recipient<T>(&self, request: &Request<T>, kinds: Vec<i32>) -> Result<Recipient, Status> {
+        let token = self.sess_manager.get_token(request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        // Without authentication, the only user is the data owner.
+        let is_owner =
+            !self.sess_manager.auth_enabled() || self.sess_manager.verify_if_owner(&user_id)?;
+        Ok(Recipient {
+            user_id,
+            is_owner,
+            kinds,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl NotificationService for NotificationGrpcService {
+    type SubscribeStream = ReceiverStream<Result<Notification, Status>>;
+
+    async fn subscribe(
+        &self,
+        request: Request<NotificationFilter>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let recipient = self.recipient(&request, request.get_ref().kinds.clone())?;
+        let mut events = self.notifier.events.subscribe();
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        tokio::spawn(async move {
+            loop {
+                let res = match events.recv().await {
+                    Ok(event) if recipient.receives(&event) => Ok(event.notification),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => Err(Status::data_loss(format!(
+                        "{} notifications were missed",
+                        missed
+                    ))),
+                    Err(RecvError::Closed) => return,
+                };
+                // The client went away.
+                if tx.send(res).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn register_webhook(
+        &self,
+        request: Request<Webhook>,
+    ) -> Result<Response<WebhookReference>, Status> {
+        let kinds = request
+            .get_ref()
+            .filter
+            .as_ref()
+            .map(|filter| filter.kinds.clone())
+            .unwrap_or_default();
+        let recipient = self.recipient(&request, kinds)?;
+        let url = reqwest::Url::parse(&request.get_ref().url)
+            .map_err(|e| Status::invalid_argument(format!("Invalid webhook URL: {}", e)))?;
+        // Notifications leave the server, so they must be encrypted.
+        if url.scheme() != "https" {
+            return Err(Status::invalid_argument("Webhook URLs must use https"));
+        }
+
+        let identifier = self.notifier.register_webhook(url, recipient)?;
+        info!("Registered webhook {}", identifier);
+        Ok(Response::new(WebhookReference { identifier }))
+    }
+
+    async fn delete_webhook(
+        &self,
+        request: Request<WebhookReference>,
+    ) -> Result<Response<Empty>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let identifier = &request.get_ref().identifier;
+
+        let mut webhooks = self.notifier.webhooks.write().unwrap();
+        match webhooks.get(identifier) {
+            Some(webhook) if webhook.recipient.user_id == user_id => {
+                webhooks.remove(identifier);
+                Ok(Response::new(Empty {}))
+            }
+            _ => Err(Status::not_found("Webhook not found")),
+        }
+    }
+}
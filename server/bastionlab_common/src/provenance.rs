@@ -0,0 +1,290 @@
+use crate::prelude::*;
+use crate::session::SessionManager;
+use crate::session_proto::{
+    provenance_service_server::ProvenanceService, Provenance, ProvenanceArtifact, ProvenanceKind,
+    ProvenanceNode,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tonic::{Request, Response, Status};
+
+/// Kind of the artifacts tracked by the provenance graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NodeKind {
+    DataFrame,
+    /// Arrays and tensors share their identifiers.
+    Array,
+    Dataset,
+    Model,
+    Run,
+    /// Weights of a model, registered under the identifier of the model.
+    CheckPoint,
+}
+
+impl From<NodeKind> for ProvenanceKind {
+    fn from(kind: NodeKind) -> Self {
+        match kind {
+            NodeKind::DataFrame => ProvenanceKind::DataFrame,
+            NodeKind::Array => ProvenanceKind::Array,
+            NodeKind::Dataset => ProvenanceKind::Dataset,
+            NodeKind::Model => ProvenanceKind::Model,
+            NodeKind::Run => ProvenanceKind::Run,
+            NodeKind::CheckPoint => ProvenanceKind::Checkpoint,
+        }
+    }
+}
+
+impl From<ProvenanceKind> for NodeKind {
+    fn from(kind: ProvenanceKind) -> Self {
+        match kind {
+            ProvenanceKind::DataFrame => NodeKind::DataFrame,
+            ProvenanceKind::Array => NodeKind::Array,
+            ProvenanceKind::Dataset => NodeKind::Dataset,
+            ProvenanceKind::Model => NodeKind::Model,
+            ProvenanceKind::Run => NodeKind::Run,
+            ProvenanceKind::Checkpoint => NodeKind::CheckPoint,
+        }
+    }
+}
+
+/// An artifact of one of the services.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub identifier: String,
+}
+
+impl Node {
+    pub fn new(kind: NodeKind, identifier: impl Into<String>) -> Self {
+        Node {
+            kind,
+            identifier: identifier.into(),
+        }
+    }
+
+    pub fn data_frame(identifier: impl Into<String>) -> Self {
+        Self::new(NodeKind::DataFrame, identifier)
+    }
+
+    pub fn array(identifier: impl Into<String>) -> Self {
+        Self::new(NodeKind::Array, identifier)
+    }
+
+    pub fn dataset(identifier: impl Into<String>) -> Self {
+        Self::new(NodeKind::Dataset, identifier)
+    }
+
+    pub fn model(identifier: impl Into<String>) -> Self {
+        Self::new(NodeKind::Model, identifier)
+    }
+
+    pub fn run(identifier: impl Into<String>) -> Self {
+        Self::new(NodeKind::Run, identifier)
+    }
+
+    pub fn checkpoint(identifier: impl Into<String>) -> Self {
+        Self::new(NodeKind::CheckPoint, identifier)
+    }
+
+    fn to_message(&self) -> ProvenanceArtifact {
+        ProvenanceArtifact {
+            kind: ProvenanceKind::from(self.kind) as i32,
+            identifier: self.identifier.clone(),
+        }
+    }
+}
+
+/// The creation of `node` from `parents`, or an update of `node` using them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Derivation {
+    node: Node,
+    parents: Vec<Node>,
+    activity: String,
+    /// Unix timestamp, in seconds.
+    time: u64,
+}
+
+#[derive(Debug, Clone)]
+struct NodeInfo {
+    activity: String,
+    created_at: u64,
+    parents: Vec<Node>,
+}
+
+/// Graph of the artifacts of the Polars, conversion and Torch services and of the artifacts
+/// they derive from, shared by the services so that auditors can trace the data that
+/// influenced a model.
+///
+/// Derivations are appended to a file as JSON lines when the graph is opened with
+/// [`ProvenanceGraph::open`], and kept in memory only otherwise. Deleted artifacts stay in
+/// the graph.
+#[derive(Debug, Clone, Default)]
+pub struct ProvenanceGraph {
+    nodes: Arc<RwLock<HashMap<Node, NodeInfo>>>,
+    file: Option<Arc<Mutex<File>>>,
+}
+
+impl ProvenanceGraph {
+    /// Opens the graph saved at `path`, creating it if needed.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)
+            .with_context(|| anyhow!("Opening provenance log {}", path.display()))?;
+        let graph = ProvenanceGraph {
+            nodes: Arc::default(),
+            file: None,
+        };
+        for line in BufReader::new(&file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(derivation) => graph.insert(derivation),
+                // A line may have been cut by a crash.
+                Err(e) => warn!("Skipping invalid provenance log entry: {}", e),
+            }
+        }
+        Ok(ProvenanceGraph {
+            file: Some(Arc::new(Mutex::new(file))),
+            ..graph
+        })
+    }
+
+    /// Records that `node` was created, or updated, by `activity` from `parents`.
+    pub fn record(&self, node: Node, parents: Vec<Node>, activity: &str) {
+        let derivation = Derivation {
+            node,
+            parents,
+            activity: activity.to_string(),
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        };
+        if let Some(file) = &self.file {
+            let res = serde_json::to_string(&derivation)
+                .map_err(anyhow::Error::from)
+                .and_then(|line| Ok(writeln!(file.lock().unwrap(), "{}", line)?));
+            if let Err(e) = res {
+                error!("Could not write to the provenance log: {}", e);
+            }
+        }
+        self.insert(derivation);
+    }
+
+    fn insert(&self, derivation: Derivation) {
+        let mut nodes = self.nodes.write().unwrap();
+        let info = nodes.entry(derivation.node).or_insert_with(|| NodeInfo {
+            activity: derivation.activity,
+            created_at: derivation.time,
+            parents: Vec::new(),
+        });
+        for parent in derivation.parents {
+            if !info.parents.contains(&parent) {
+                info.parents.push(parent);
+            }
+        }
+    }
+
+    /// Returns `node` and the artifacts it derives from, directly or not, closest first.
+    /// Artifacts without any record, such as those created before the graph, are listed
+    /// without activity.
+    pub fn ancestors(&self, node: &Node) -> Result<Vec<ProvenanceNode>, Status> {
+        let nodes = self.nodes.read().unwrap();
+        if !nodes.contains_key(node) {
+            return Err(Status::not_found(format!(
+                "No provenance recorded for {:?} {}",
+                node.kind, node.identifier
+            )));
+        }
+        let mut seen = HashSet::from([node.clone()]);
+        let mut queue = VecDeque::from([node.clone()]);
+        let mut res = Vec::new();
+        // Updates, such as trainings resumed from a checkpoint, can make cycles.
+        while let Some(node) = queue.pop_front() {
+            let info = nodes.get(&node);
+            let parents = info.map(|info| info.parents.clone()).unwrap_or_default();
+            for parent in parents.iter() {
+                if seen.insert(parent.clone()) {
+                    queue.push_back(parent.clone());
+                }
+            }
+            res.push(ProvenanceNode {
+                artifact: Some(node.to_message()),
+                activity: info.map(|info| info.activity.clone()).unwrap_or_default(),
+                created_at: info.map(|info| info.created_at).unwrap_or_default(),
+                parents: parents.iter().map(Node::to_message).collect(),
+            });
+        }
+        Ok(res)
+    }
+
+    /// Returns the identifiers of the artifacts of kind `kind` that `node` derives from,
+    /// directly or not, closest first.
+    pub fn ancestors_of_kind(&self, node: &Node, kind: NodeKind) -> Vec<String> {
+        let nodes = self.nodes.read().unwrap();
+        let mut seen = HashSet::from([node.clone()]);
+        let mut queue = VecDeque::from([node.clone()]);
+        let mut res = Vec::new();
+        while let Some(node) = queue.pop_front() {
+            for parent in nodes
+                .get(&node)
+                .map(|info| &info.parents[..])
+                .unwrap_or_default()
+            {
+                if seen.insert(parent.clone()) {
+                    if parent.kind == kind {
+                        res.push(parent.identifier.clone());
+                    }
+                    queue.push_back(parent.clone());
+                }
+            }
+        }
+        res
+    }
+}
+
+pub struct ProvenanceGrpcService {
+    sess_manager: Arc<SessionManager>,
+    graph: ProvenanceGraph,
+}
+
+impl ProvenanceGrpcService {
+    pub fn new(sess_manager: Arc<SessionManager>, graph: ProvenanceGraph) -> Self {
+        Self {
+            sess_manager,
+            graph,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl ProvenanceService for ProvenanceGrpcService {
+    async fn get_provenance(
+        &self,
+        request: Request<ProvenanceArtifact>,
+    ) -> Result<Response<Provenance>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        if self.sess_manager.auth_enabled() && !self.sess_manager.verify_if_owner(&user_id)? {
+            return Err(Status::permission_denied(
+                "Only data owners can trace the provenance of artifacts.",
+            ));
+        }
+        let artifact = request.get_ref();
+        let kind = ProvenanceKind::from_i32(artifact.kind)
+            .ok_or_else(|| Status::invalid_argument("Invalid artifact kind"))?;
+        let node = Node::new(kind.into(), artifact.identifier.clone());
+        Ok(Response::new(Provenance {
+            nodes: self.graph.ancestors(&node)?,
+        }))
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use ndarray::{Dim, IxDynImpl, OwnedRepr};
+use tch::{Kind, Tensor};
+use tonic::Status;
+use uuid::Uuid;
+
+use crate::array_store::ArrayStore;
+use crate::common_conversions::{ndarray_to_tensor, tensor_to_ndarray};
+
+/// An array registered once and usable by every service, as an ndarray or as a tensor.
+///
+/// The other form is computed the first time it is asked for and kept with the entry.
+#[derive(Default)]
+struct RemoteArray {
+    array: Option<ArrayStore>,
+    tensor: Option<Arc<Mutex<Tensor>>>,
+}
+
+/// Registry of the arrays shared by the Polars, Torch and conversion services.
+///
+/// Arrays are referenced by a single identifier whatever the service that created them,
+/// so that a tokenized or converted column can be used directly as a tensor and the other
+/// way around.
+#[derive(Clone, Default)]
+pub struct RemoteArrayRegistry {
+    arrays: Arc<RwLock<HashMap<String, RemoteArray>>>,
+}
+
+impl RemoteArrayRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert_array(&self, array: ArrayStore) -> String {
+        self.insert(RemoteArray {
+            array: Some(array),
+            tensor: None,
+        })
+    }
+
+    pub fn insert_tensor(&self, tensor: Arc<Mutex<Tensor>>) -> String {
+        self.insert(RemoteArray {
+            array: None,
+            tensor: Some(tensor),
+        })
+    }
+
+    fn insert(&self, array: RemoteArray) -> String {
+        let identifier = Uuid::new_v4().to_string();
+        self.arrays
+            .write()
+            .unwrap()
+            .insert(identifier.clone(), array);
+        identifier
+    }
+
+    /// Returns the array `identifier` as an ndarray, converting it from a tensor if needed.
+    pub fn get_array(&self, identifier: &str) -> Result<ArrayStore, Status> {
+        if let Some(array) = self.get(identifier, |entry| entry.array.clone())? {
+            return Ok(array);
+        }
+        let mut arrays = self.arrays.write().unwrap();
+        let entry = arrays
+            .get_mut(identifier)
+            .ok_or_else(|| not_found(identifier))?;
+        if entry.array.is_none() {
+            let tensor = entry.tensor.as_ref().ok_or_else(|| not_found(identifier))?;
+            entry.array = Some(tensor_to_array_store(&tensor.lock().unwrap())?);
+        }
+        Ok(entry.array.clone().unwrap())
+    }
+
+    /// Returns the array `identifier` as a tensor, converting it from an ndarray if needed.
+    pub fn get_tensor(&self, identifier: &str) -> Result<Arc<Mutex<Tensor>>, Status> {
+        if let Some(tensor) = self.get(identifier, |entry| entry.tensor.clone())? {
+            return Ok(tensor);
+        }
+        let mut arrays = self.arrays.write().unwrap();
+        let entry = arrays
+            .get_mut(identifier)
+            .ok_or_else(|| not_found(identifier))?;
+        if entry.tensor.is_none() {
+            let array = entry.array.clone().ok_or_else(|| not_found(identifier))?;
+            entry.tensor = Some(Arc::new(Mutex::new(array_store_to_tensor(array)?)));
+        }
+        Ok(Arc::clone(entry.tensor.as_ref().unwrap()))
+    }
+
+    fn get<T>(
+        &self,
+        identifier: &str,
+        f: impl FnOnce(&RemoteArray) -> Option<T>,
+    ) -> Result<Option<T>, Status> {
+        let arrays = self.arrays.read().unwrap();
+        let entry = arrays
+            .get(identifier)
+            .ok_or_else(|| not_found(identifier))?;
+        Ok(f(entry))
+    }
+
+    /// Applies `f` to the tensor form of the array `identifier`, and drops its ndarray
+    /// form which is now outdated.
+    pub fn update_tensor<R>(
+        &self,
+        identifier: &str,
+        f: impl FnOnce(&mut Tensor) -> Result<R, Status>,
+    ) -> Result<R, Status> {
+        let tensor = self.get_tensor(identifier)?;
+        let res = f(&mut tensor.lock().unwrap())?;
+        if let Some(entry) = self.arrays.write().unwrap().get_mut(identifier) {
+            entry.array = None;
+        }
+        Ok(res)
+    }
+
+    pub fn remove(&self, identifier: &str) {
+        self.arrays.write().unwrap().remove(identifier);
+    }
+
+    /// Takes the registry lock once. This blocks if it is deadlocked.
+    pub fn probe(&self) {
+        drop(self.arrays.read().unwrap());
+    }
+}
+
+fn not_found(identifier: &str) -> Status {
+    Status::not_found(format!("Could not find array: {identifier}"))
+}
+
+fn array_store_to_tensor(array: ArrayStore) -> Result<Tensor, Status> {
+    match array {
+        ArrayStore::AxdynI64(a) => ndarray_to_tensor::<OwnedRepr<i64>, Dim<IxDynImpl>>(a),
+        ArrayStore::AxdynF64(a) => ndarray_to_tensor::<OwnedRepr<f64>, Dim<IxDynImpl>>(a),
+        ArrayStore::AxdynF32(a) => ndarray_to_tensor::<OwnedRepr<f32>, Dim<IxDynImpl>>(a),
+        ArrayStore::AxdynI32(a) => ndarray_to_tensor::<OwnedRepr<i32>, Dim<IxDynImpl>>(a),
+        ArrayStore::AxdynI16(a) => ndarray_to_tensor::<OwnedRepr<i16>, Dim<IxDynImpl>>(a),
+    }
+}
+
+fn tensor_to_array_store(tensor: &Tensor) -> Result<ArrayStore, Status> {
+    Ok(match tensor.kind() {
+        Kind::Int64 => ArrayStore::AxdynI64(tensor_to_ndarray(tensor)?),
+        Kind::Double => ArrayStore::AxdynF64(tensor_to_ndarray(tensor)?),
+        Kind::Float => ArrayStore::AxdynF32(tensor_to_ndarray(tensor)?),
+        Kind::Int => ArrayStore::AxdynI32(tensor_to_ndarray(tensor)?),
+        Kind::Int16 => ArrayStore::AxdynI16(tensor_to_ndarray(tensor)?),
+        kind => {
+            return Err(Status::invalid_argument(format!(
+                "Tensors of type {kind:?} cannot be used as arrays"
+            )))
+        }
+    })
+}
@@ -1,5 +1,6 @@
 use std::collections::HashSet;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, SystemTime};
 
 use bytes::Bytes;
@@ -7,8 +8,9 @@ use prost::Message;
 use tonic::metadata::KeyRef;
 use tonic::{Request, Response, Status};
 
-use crate::auth::KeyManagement;
-use crate::session_proto::{ClientInfo, SessionInfo};
+use crate::auth::{FailureTracker, KeyManagement, Offender};
+use crate::notifications::Notifier;
+use crate::session_proto::{ClientInfo, ErrorCode, NotificationKind, SessionInfo};
 use crate::{prelude::*, session_proto};
 
 fn get_message<T: Message>(
@@ -26,20 +28,36 @@ fn get_message<T: Message>(
     Ok(res)
 }
 
+/// Temporary server state created by a session, released when the session expires or is
+/// orphaned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionResource {
+    Run(String),
+    Tensor(String),
+    DataFrame(String),
+}
+
 #[derive(Debug)]
 pub struct Session {
     pub pubkey: String,
     pub user_ip: SocketAddr,
     pub expiry: SystemTime,
     pub client_info: ClientInfo,
+    /// Last heartbeat or request of the client.
+    pub last_seen: SystemTime,
+    pub resources: Vec<SessionResource>,
 }
 
 #[derive(Debug)]
 pub struct SessionManager {
     keys: Option<Mutex<KeyManagement>>,
     pub sessions: Arc<RwLock<HashMap<[u8; 32], Session>>>,
-    session_expiry: u64,
+    session_expiry: AtomicU64,
     challenges: Mutex<HashSet<[u8; 32]>>,
+    failures: FailureTracker,
+    notifier: Option<Notifier>,
+    /// Resources of the sessions removed since the last [`SessionManager::reap_sessions`].
+    ended: Mutex<Vec<SessionResource>>,
 }
 
 impl SessionManager {
@@ -47,15 +65,36 @@ impl SessionManager {
         Self {
             keys: keys.map(Mutex::new),
             sessions: Default::default(),
-            session_expiry,
+            session_expiry: AtomicU64::new(session_expiry),
             challenges: Default::default(),
+            failures: Default::default(),
+            notifier: None,
+            ended: Default::default(),
         }
     }
 
+    /// Notifies the data owners of the keys and addresses locked out after failed
+    /// authentications.
+    pub fn with_notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Sets the lifetime of sessions created from now on, in seconds.
+    pub fn set_session_expiry(&self, session_expiry: u64) {
+        self.session_expiry.store(session_expiry, Ordering::Relaxed);
+    }
+
     pub fn auth_enabled(&self) -> bool {
         self.keys.is_some()
     }
 
+    /// Takes the session locks once. This blocks if they are deadlocked.
+    pub fn probe(&self) {
+        drop(self.sessions.read().expect("Poisoned lock"));
+        drop(self.challenges.lock().expect("Poisoned lock"));
+    }
+
     /// Returns the access token in the request
     pub fn get_token<T>(&self, req: &Request<T>) -> Result<Option<Bytes>, Status> {
         if !self.auth_enabled() {
@@ -83,12 +122,88 @@ impl SessionManager {
         let sessions = self.sessions.read().expect("Poisoned lock");
         let session = sessions
             .get(token_bytes)
-            .ok_or_else(|| Status::aborted("Session not found!"))?;
+            .ok_or_else(|| ErrorCode::SessionNotFound.status("Session not found!"))?;
 
         let user_id = session.pubkey.clone();
         Ok(user_id)
     }
 
+    /// Records that the client of the session `token` is still there.
+    pub fn heartbeat(&self, token: Option<Bytes>) -> Result<(), Status> {
+        let token = match &token {
+            Some(v) => &v[..],
+            None => &[0u8; 32],
+        };
+        let mut sessions = self.sessions.write().expect("Poisoned lock");
+        let session = sessions
+            .get_mut(token)
+            .ok_or_else(|| ErrorCode::SessionNotFound.status("Session not found!"))?;
+        session.last_seen = SystemTime::now();
+        Ok(())
+    }
+
+    /// Ties `resource` to the session `token`, so that it is released if the session's
+    /// client goes silent.
+    pub fn track(&self, token: &Option<Bytes>, resource: SessionResource) {
+        let token = match token {
+            Some(v) => &v[..],
+            None => &[0u8; 32],
+        };
+        if let Some(session) = self.sessions.write().expect("Poisoned lock").get_mut(token) {
+            session.resources.push(resource);
+        }
+    }
+
+    /// Unties `resource` from its session once it is released by other means, e.g. a run
+    /// that finished.
+    pub fn untrack(&self, resource: &SessionResource) {
+        for session in self.sessions.write().expect("Poisoned lock").values_mut() {
+            session.resources.retain(|r| r != resource);
+        }
+    }
+
+    /// Removes the session `token`, e.g. once it expired. Its resources are returned by the
+    /// next [`SessionManager::reap_sessions`].
+    pub fn end_session(&self, token: &[u8]) {
+        let session = self.sessions.write().expect("Poisoned lock").remove(token);
+        if let Some(session) = session {
+            info!(
+                "Session of {} ended, releasing {} resources",
+                session.pubkey,
+                session.resources.len()
+            );
+            self.ended
+                .lock()
+                .expect("Poisoned lock")
+                .extend(session.resources);
+        }
+    }
+
+    /// Removes the sessions whose client has been silent for longer than `orphaned_timeout`,
+    /// if any, and returns the resources they created along with those of the sessions
+    /// ended since the last call.
+    pub fn reap_sessions(&self, orphaned_timeout: Option<Duration>) -> Vec<SessionResource> {
+        if let Some(timeout) = orphaned_timeout {
+            let now = SystemTime::now();
+            let orphaned: Vec<_> = self
+                .sessions
+                .read()
+                .expect("Poisoned lock")
+                .iter()
+                .filter(|(_, session)| {
+                    now.duration_since(session.last_seen)
+                        .map(|silence| silence > timeout)
+                        .unwrap_or(false)
+                })
+                .map(|(token, _)| *token)
+                .collect();
+            for token in orphaned {
+                self.end_session(&token);
+            }
+        }
+        std::mem::take(&mut *self.ended.lock().expect("Poisoned lock"))
+    }
+
     /// When no token is given and auth is disabled, this will give the ClientInfo of the last
     /// session created
     pub fn get_client_info(&self, token: Option<Bytes>) -> Result<ClientInfo, Status> {
@@ -99,7 +214,7 @@ impl SessionManager {
         };
         let session = sessions
             .get(token)
-            .ok_or_else(|| Status::aborted("Session not found!"))?;
+            .ok_or_else(|| ErrorCode::SessionNotFound.status("Session not found!"))?;
         Ok(session.client_info.clone())
     }
 
@@ -146,16 +261,38 @@ impl SessionManager {
         return Ok(false);
     }
 
+    fn notify_lockout(&self, offender: &Offender, lockout: Duration) {
+        let message = format!(
+            "{} is locked out for {} seconds after repeated failed authentications",
+            offender,
+            lockout.as_secs()
+        );
+        warn!("{}", message);
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(
+                NotificationKind::AuthenticationLockout,
+                &offender.identifier(),
+                message,
+                None,
+            );
+        }
+    }
+
     // TODO: move grpc specific things to the grpc service and not the session manager
     fn create_session(&self, request: Request<ClientInfo>) -> Result<SessionInfo, Status> {
-        let user_ip = request
-            .remote_addr()
-            .ok_or_else(|| Status::aborted("Could not fetch IP Address from request"))?;
+        let user_ip = request.remote_addr().ok_or_else(|| {
+            ErrorCode::ClientAddressUnavailable.status("Could not fetch IP Address from request")
+        })?;
         let mut sessions = self.sessions.write().unwrap();
 
         if !self.auth_enabled() {
             // auth disabled
             let (token, expiry) = ([0u8; 32], SystemTime::now());
+            // All clients share the session, keep the resources of the others.
+            let resources = sessions
+                .remove(&token)
+                .map(|session| session.resources)
+                .unwrap_or_default();
             sessions.insert(
                 token.clone(),
                 Session {
@@ -163,11 +300,13 @@ impl SessionManager {
                     user_ip,
                     expiry,
                     client_info: request.into_inner(),
+                    last_seen: SystemTime::now(),
+                    resources,
                 },
             );
             return Ok(SessionInfo {
                 token: token.to_vec(),
-                expiry_time: self.session_expiry * 1000,
+                expiry_time: self.session_expiry.load(Ordering::Relaxed) * 1000,
             });
         }
         // auth enabled
@@ -175,8 +314,6 @@ impl SessionManager {
         // unwrap: self.keys is not None since auth is enabled
         let keys_lock = self.keys.as_ref().unwrap().lock().expect("Poisoned lock");
 
-        let challenge = self.check_challenge(&request)?;
-
         // stripped key hash from the request metadata
         let pubkey_hash = request
             .metadata()
@@ -195,14 +332,44 @@ impl SessionManager {
                 Status::unauthenticated("You are not authenticated. Please provide an identity.")
             })?;
 
-        // verify signature
-        let message = get_message(b"create-session", &request, challenge.clone())?;
-        keys_lock.verify_signature(pubkey_hash, &message[..], request.metadata())?;
+        // Unknown keys are tracked through the address only, so that they cannot fill memory.
+        let mut offenders = vec![Offender::Ip(user_ip.ip())];
+        if keys_lock.is_known(pubkey_hash) {
+            offenders.push(Offender::Key(pubkey_hash.to_string(), user_ip.ip()));
+        }
+        for offender in offenders.iter() {
+            self.failures.check(offender)?;
+        }
+
+        // verify challenge and signature
+        let verified = self.check_challenge(&request).and_then(|challenge| {
+            let message = get_message(b"create-session", &request, challenge)?;
+            keys_lock.verify_signature(pubkey_hash, &message[..], request.metadata())
+        });
+        if let Err(e) = verified {
+            warn!(
+                "Failed authentication of public key {} from {}: {}",
+                pubkey_hash,
+                user_ip.ip(),
+                e.message()
+            );
+            for offender in offenders {
+                if let Some(lockout) = self.failures.record_failure(offender.clone()) {
+                    self.notify_lockout(&offender, lockout);
+                }
+            }
+            return Err(e);
+        }
+        for offender in offenders.iter() {
+            self.failures.clear(offender);
+        }
 
         let (token, expiry) = {
             let time = SystemTime::now();
             let expiry = time
-                .checked_add(Duration::from_secs(self.session_expiry))
+                .checked_add(Duration::from_secs(
+                    self.session_expiry.load(Ordering::Relaxed),
+                ))
                 .unwrap_or(time);
             (self.new_challenge(), expiry)
         };
@@ -213,11 +380,13 @@ impl SessionManager {
                 user_ip,
                 expiry,
                 client_info: request.into_inner(),
+                last_seen: SystemTime::now(),
+                resources: Vec::new(),
             },
         );
         Ok(SessionInfo {
             token: token.to_vec(),
-            expiry_time: self.session_expiry * 1000,
+            expiry_time: self.session_expiry.load(Ordering::Relaxed) * 1000,
         })
     }
 }
@@ -251,4 +420,79 @@ impl session_proto::session_service_server::SessionService for SessionGrpcServic
         let session = self.sess_manager.create_session(request)?;
         Ok(Response::new(session))
     }
+
+    async fn heartbeat(
+        &self,
+        request: Request<session_proto::Empty>,
+    ) -> Result<Response<session_proto::Empty>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        self.sess_manager.heartbeat(token)?;
+        Ok(Response::new(session_proto::Empty {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager_with_session(token: [u8; 32], resources: Vec<SessionResource>) -> SessionManager {
+        let manager = SessionManager::new(None, 60);
+        manager.sessions.write().unwrap().insert(
+            token,
+            Session {
+                pubkey: String::from("user"),
+                user_ip: "127.0.0.1:50056".parse().unwrap(),
+                expiry: SystemTime::now(),
+                client_info: ClientInfo::default(),
+                last_seen: SystemTime::now(),
+                resources,
+            },
+        );
+        manager
+    }
+
+    #[test]
+    fn ended_sessions_release_their_resources() {
+        let token = [1u8; 32];
+        let manager = manager_with_session(
+            token,
+            vec![
+                SessionResource::Run(String::from("run")),
+                SessionResource::Tensor(String::from("tensor")),
+            ],
+        );
+        manager.untrack(&SessionResource::Run(String::from("run")));
+        manager.end_session(&token);
+
+        assert!(manager.sessions.read().unwrap().is_empty());
+        assert_eq!(
+            manager.reap_sessions(None),
+            vec![SessionResource::Tensor(String::from("tensor"))]
+        );
+        assert!(manager.reap_sessions(None).is_empty());
+    }
+
+    #[test]
+    fn orphaned_sessions_are_reaped() {
+        let token = [1u8; 32];
+        let manager =
+            manager_with_session(token, vec![SessionResource::DataFrame(String::from("df"))]);
+        assert!(manager
+            .reap_sessions(Some(Duration::from_secs(60)))
+            .is_empty());
+        assert_eq!(manager.sessions.read().unwrap().len(), 1);
+
+        manager
+            .sessions
+            .write()
+            .unwrap()
+            .get_mut(&token)
+            .unwrap()
+            .last_seen = SystemTime::now() - Duration::from_secs(120);
+        assert_eq!(
+            manager.reap_sessions(Some(Duration::from_secs(60))),
+            vec![SessionResource::DataFrame(String::from("df"))]
+        );
+        assert!(manager.sessions.read().unwrap().is_empty());
+    }
 }
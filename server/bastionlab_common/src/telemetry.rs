@@ -2,10 +2,12 @@ use crate::prelude::*;
 use crate::session_proto::ClientInfo;
 use once_cell::sync::OnceCell;
 use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc::{self, UnboundedSender};
 
 static TELEMETRY_CHANNEL: OnceCell<UnboundedSender<TelemetryEvent>> = OnceCell::new();
+static TELEMETRY_ENABLED: AtomicBool = AtomicBool::new(true);
 
 #[derive(Debug, Clone, Serialize)]
 pub enum TelemetryEventProps {
@@ -34,6 +36,10 @@ pub enum TelemetryEventProps {
     DeleteDataframe {
         dataset_name: Option<String>,
     },
+    ArtifactExpired {
+        artifact_kind: String,
+        artifact_name: Option<String>,
+    },
     // Torch
     SendModel {
         model_name: Option<String>,
@@ -74,6 +80,7 @@ impl TelemetryEventProps {
             TelemetryEventProps::GetDataFrameHeader { .. } => "get_data_frame_header",
             TelemetryEventProps::SaveDataframe { .. } => "save_data_frame",
             TelemetryEventProps::DeleteDataframe { .. } => "delete_data_frame",
+            TelemetryEventProps::ArtifactExpired { .. } => "artifact_expired",
             // torch
             TelemetryEventProps::SendModel { .. } => "send_model",
             TelemetryEventProps::SendDataset { .. } => "send_dataset",
@@ -83,6 +90,9 @@ impl TelemetryEventProps {
 }
 
 pub fn add_event(event: TelemetryEventProps, client_info: Option<ClientInfo>) {
+    if !TELEMETRY_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
     if let Some(sender) = TELEMETRY_CHANNEL.get() {
         let _ = sender.send(TelemetryEvent {
             event_type: event.event_type(),
@@ -118,6 +128,15 @@ struct RequestUserProperties<'a> {
     is_colab: bool,
 }
 
+/// Pauses or resumes sending events, once [`setup`] has been called.
+pub fn set_enabled(enabled: bool) {
+    TELEMETRY_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_setup() -> bool {
+    TELEMETRY_CHANNEL.get().is_some()
+}
+
 pub fn setup(platform: String, uid: String, tee: String) -> Result<()> {
     let (sender, mut receiver) = mpsc::unbounded_channel::<TelemetryEvent>();
 
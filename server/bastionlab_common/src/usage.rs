@@ -0,0 +1,173 @@
+use crate::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How a dataset or dataframe was used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum UsageKind {
+    Query,
+    PrivateQuery,
+    Training,
+    Test,
+    FetchApproved,
+    FetchDenied,
+}
+
+impl UsageKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            UsageKind::Query => "query",
+            UsageKind::PrivateQuery => "private_query",
+            UsageKind::Training => "training",
+            UsageKind::Test => "test",
+            UsageKind::FetchApproved => "fetch_approved",
+            UsageKind::FetchDenied => "fetch_denied",
+        }
+    }
+}
+
+/// A use of the dataset or dataframe `dataset` by `user_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageEvent {
+    pub dataset: String,
+    pub user_id: String,
+    pub kind: UsageKind,
+    /// Owner of the dataset at the time of use, to whom the event is reported.
+    pub owner: Option<String>,
+    /// Privacy budget expended on the dataset, 0 if none.
+    #[serde(deserialize_with = "deserialize_eps")]
+    pub eps: f64,
+    /// Whether the use was differentially private.
+    #[serde(default)]
+    pub private: bool,
+    /// Identifier of the artifact derived from the dataset, if any.
+    pub derived: Option<String>,
+    /// Unix timestamp, in seconds.
+    pub time: u64,
+}
+
+/// Older logs recorded non-private uses of private datasets with an infinite budget, written
+/// as `null` in JSON. These uses expended no budget.
+fn deserialize_eps<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Ok(Option::<f64>::deserialize(deserializer)?
+        .filter(|eps| eps.is_finite())
+        .unwrap_or_default())
+}
+
+impl UsageEvent {
+    pub fn new(dataset: &str, user_id: &str, kind: UsageKind) -> Self {
+        UsageEvent {
+            dataset: dataset.to_string(),
+            user_id: user_id.to_string(),
+            kind,
+            owner: None,
+            eps: 0.0,
+            private: false,
+            derived: None,
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn with_owner(mut self, owner: Option<&str>) -> Self {
+        self.owner = owner.map(String::from);
+        self
+    }
+
+    pub fn with_eps(mut self, eps: f64) -> Self {
+        self.eps = eps;
+        self
+    }
+
+    pub fn with_private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+
+    pub fn with_derived(mut self, derived: &str) -> Self {
+        self.derived = Some(derived.to_string());
+        self
+    }
+}
+
+/// Audit log of the uses of datasets and dataframes, shared by the services so that data
+/// owners can get reports of how their data is used.
+///
+/// Events are appended to a file as JSON lines when the log is opened with
+/// [`UsageLog::open`], and kept in memory only otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct UsageLog {
+    events: Arc<RwLock<Vec<UsageEvent>>>,
+    file: Option<Arc<Mutex<File>>>,
+}
+
+impl UsageLog {
+    /// Opens the log at `path`, creating it if needed and loading the events it holds.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)
+            .with_context(|| anyhow!("Opening usage log {}", path.display()))?;
+        let mut events = Vec::new();
+        for line in BufReader::new(&file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(event) => events.push(event),
+                // A line may have been cut by a crash.
+                Err(e) => warn!("Skipping invalid usage log entry: {}", e),
+            }
+        }
+        Ok(UsageLog {
+            events: Arc::new(RwLock::new(events)),
+            file: Some(Arc::new(Mutex::new(file))),
+        })
+    }
+
+    pub fn record(&self, event: UsageEvent) {
+        if let Some(file) = &self.file {
+            let res = serde_json::to_string(&event)
+                .map_err(anyhow::Error::from)
+                .and_then(|line| Ok(writeln!(file.lock().unwrap(), "{}", line)?));
+            if let Err(e) = res {
+                error!("Could not write to the usage log: {}", e);
+            }
+        }
+        self.events.write().unwrap().push(event);
+    }
+
+    /// Returns every event, oldest first.
+    pub fn events(&self) -> Vec<UsageEvent> {
+        self.events.read().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infinite_budgets_of_older_logs_are_read_as_zero() {
+        let line = r#"{"dataset":"d","user_id":"u","kind":"Training","owner":null,"eps":null,"derived":null,"time":1}"#;
+        let event: UsageEvent = serde_json::from_str(line).unwrap();
+        assert_eq!(event.eps, 0.0);
+        assert!(!event.private);
+
+        let event = UsageEvent::new("d", "u", UsageKind::PrivateQuery)
+            .with_eps(0.5)
+            .with_private(true);
+        let event: UsageEvent =
+            serde_json::from_str(&serde_json::to_string(&event).unwrap()).unwrap();
+        assert_eq!(event.eps, 0.5);
+        assert!(event.private);
+    }
+}
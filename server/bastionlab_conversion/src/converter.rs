@@ -1,45 +1,184 @@
 use std::sync::{Arc, Mutex};
 
-use bastionlab_common::{array_store::ArrayStore, common_conversions::*};
+use bastionlab_common::{
+    array_store::ArrayStore,
+    cancellation::Cancellation,
+    common_conversions::*,
+    provenance::{Node, NodeKind, ProvenanceGraph},
+    session::SessionManager,
+    session_proto::ErrorCode,
+};
+use bastionlab_learning::data::{windows::sliding_windows, Dataset};
 use bastionlab_polars::BastionLabPolars;
+use bastionlab_torch::license::{License, Rule};
+use bastionlab_torch::torch_proto::RemoteDatasetReference;
 use bastionlab_torch::BastionLabTorch;
-use ndarray::{Axis, Dim, IxDynImpl, OwnedRepr};
+use ndarray::Axis;
 use polars::export::ahash::HashSet;
 use polars::prelude::*;
-use tch::Tensor;
+use tch::{Device, Kind, Tensor};
 use tonic::{Request, Response, Status};
 
 use crate::conversion_proto::{conversion_service_server::ConversionService, ToTokenizedArrays};
-use crate::conversion_proto::{RemoteArray, RemoteArrays, RemoteDataFrame};
+use crate::conversion_proto::{
+    ConvertedDataFrame, ConvertedDataset, RemoteArray, RemoteArrays, RemoteDataFrame,
+    TensorToDataFrame, ToTokenizedDataset, ToWindowedDataset,
+};
+use crate::tokenization::{tokenize_series, Tokenizers};
 
 use crate::bastionlab::Reference;
 pub struct Converter {
     torch: Arc<BastionLabTorch>,
     polars: Arc<BastionLabPolars>,
+    sess_manager: Arc<SessionManager>,
+    tokenizers: Arc<Tokenizers>,
+    provenance: ProvenanceGraph,
+}
+
+fn to_reference(reference: bastionlab_torch::bastionlab::Reference) -> Reference {
+    Reference {
+        identifier: reference.identifier,
+        name: reference.name,
+        description: reference.description,
+        meta: reference.meta,
+        tags: reference.tags,
+    }
 }
 
 impl Converter {
-    pub fn new(torch: Arc<BastionLabTorch>, polars: Arc<BastionLabPolars>) -> Self {
-        Self { torch, polars }
+    pub fn new(
+        torch: Arc<BastionLabTorch>,
+        polars: Arc<BastionLabPolars>,
+        sess_manager: Arc<SessionManager>,
+    ) -> Self {
+        Self {
+            torch,
+            polars,
+            sess_manager,
+            tokenizers: Arc::default(),
+            provenance: ProvenanceGraph::default(),
+        }
     }
-    pub fn ndarray_to_tensor(&self, arr: ArrayStore) -> Result<Tensor, Status> {
-        let tensor = match arr {
-            ArrayStore::AxdynI64(a) => ndarray_to_tensor::<OwnedRepr<i64>, Dim<IxDynImpl>>(a),
-            ArrayStore::AxdynF64(a) => ndarray_to_tensor::<OwnedRepr<f64>, Dim<IxDynImpl>>(a),
-            ArrayStore::AxdynF32(a) => ndarray_to_tensor::<OwnedRepr<f32>, Dim<IxDynImpl>>(a),
-            ArrayStore::AxdynI32(a) => ndarray_to_tensor::<OwnedRepr<i32>, Dim<IxDynImpl>>(a),
-            ArrayStore::AxdynI16(a) => ndarray_to_tensor::<OwnedRepr<i16>, Dim<IxDynImpl>>(a),
+
+    /// Records the artifacts converted from one another in `graph`.
+    pub fn with_provenance(mut self, graph: ProvenanceGraph) -> Self {
+        self.provenance = graph;
+        self
+    }
+
+    /// Returns the license of the datasets `user_id` converts from the dataframe `source`,
+    /// and checks `privacy_limit` against its policy.
+    ///
+    /// Rows the user cannot fetch may only be fetched by, and trained with by, the owner of
+    /// the data. Datasets of dataframes whose policy requires differential privacy must be
+    /// trained with privately, within a limit expended from the privacy budget of the
+    /// dataframe once the dataset is created.
+    fn dataset_license(
+        &self,
+        source: &str,
+        user_id: &str,
+        privacy_limit: f64,
+    ) -> Result<License, Status> {
+        let terms = self.polars.dataset_terms(source, user_id)?;
+        if terms.private && privacy_limit < 0.0 {
+            return Err(Status::permission_denied(format!(
+                "The policy of DataFrame {} requires differential privacy: a privacy limit must be set",
+                source
+            )));
+        }
+        let owners = match &terms.owners[..] {
+            [Some(owner)] => Rule::UserIds {
+                ids: vec![owner.clone()],
+            },
+            _ => Rule::Nobody,
+        };
+        let fetch = if terms.rows_fetchable {
+            Rule::Owner
+        } else {
+            owners.clone()
         };
+        let train = if terms.rows_fetchable || terms.private {
+            Rule::Anyone
+        } else {
+            owners
+        };
+        Ok(License {
+            fetch,
+            train,
+            require_dp: terms.private,
+            ..License::default()
+        })
+    }
 
-        tensor
+    /// Records that the registered `dataset` was computed by `activity` from the dataframe
+    /// `source`, and returns a reference to it.
+    fn converted_dataset(
+        &self,
+        dataset: RemoteDatasetReference,
+        source: &str,
+        activity: &str,
+    ) -> ConvertedDataset {
+        self.provenance.record(
+            Node::dataset(&dataset.identifier),
+            vec![Node::data_frame(source)],
+            activity,
+        );
+        ConvertedDataset {
+            identifier: dataset.identifier,
+            inputs: dataset.inputs.into_iter().map(to_reference).collect(),
+            labels: dataset.labels.map(to_reference),
+        }
     }
 
+    /// Converts a tensor of one or two dimensions into a dataframe with a column per
+    /// column of the tensor.
+    pub fn tensor_to_df(&self, tensor: &Tensor, col_names: &[String]) -> Result<DataFrame, Status> {
+        let dtype = match tensor.kind() {
+            Kind::Float => DataType::Float32,
+            Kind::Double => DataType::Float64,
+            Kind::Int64 => DataType::Int64,
+            Kind::Int => DataType::Int32,
+            Kind::Int16 => DataType::Int16,
+            Kind::Int8 => DataType::Int8,
+            kind => {
+                return Err(Status::invalid_argument(format!(
+                    "Tensors of type {kind:?} cannot be converted to DataFrame"
+                )))
+            }
+        };
+        let tensor = tensor.to_device(Device::Cpu);
+        let columns = match tensor.size()[..] {
+            [_] => vec![tensor],
+            [_, n] => (0..n).map(|i| tensor.select(1, i).contiguous()).collect(),
+            _ => {
+                return Err(Status::invalid_argument(format!(
+                    "Only tensors of one or two dimensions can be converted to DataFrame, got shape {:?}",
+                    tensor.size()
+                )))
+            }
+        };
+        if columns.len() != col_names.len() {
+            return Err(Status::invalid_argument(format!(
+                "Expected {} column names, got {}",
+                columns.len(),
+                col_names.len()
+            )));
+        }
+
+        let series = col_names
+            .iter()
+            .zip(columns)
+            .map(|(name, column)| tensor_to_series(name, &dtype, column))
+            .collect::<Result<Vec<_>, _>>()?;
+        DataFrame::new(series)
+            .map_err(|e| Status::invalid_argument(format!("Could not create DataFrame: {e}")))
+    }
     pub fn df_to_ndarray(&self, df: &DataFrame) -> Result<String, Status> {
         let set = HashSet::from_iter(df.dtypes().iter().map(|dtype| dtype.to_string()));
         if set.len() > 1 {
-            return Err(Status::aborted(
-                "DataTypes for all columns should be the same",
-            ));
+            return Err(
+                ErrorCode::DtypeMismatch.status("DataTypes for all columns should be the same")
+            );
         }
 
         let dtype = &df.dtypes()[0];
@@ -49,7 +188,8 @@ impl Converter {
                 let arr = df
                     .to_ndarray::<Float32Type>()
                     .map_err(|e| {
-                        Status::aborted(format!("Cound not convert DataFrame to ndarray: {}", e))
+                        ErrorCode::ConversionFailed
+                            .status(format!("Could not convert DataFrame to ndarray: {}", e))
                     })?
                     .as_standard_layout()
                     .to_owned()
@@ -60,7 +200,8 @@ impl Converter {
                 let arr = df
                     .to_ndarray::<Float64Type>()
                     .map_err(|e| {
-                        Status::aborted(format!("Cound not convert DataFrame to ndarray: {}", e))
+                        ErrorCode::ConversionFailed
+                            .status(format!("Could not convert DataFrame to ndarray: {}", e))
                     })?
                     .as_standard_layout()
                     .to_owned()
@@ -72,7 +213,8 @@ impl Converter {
                 let arr = df
                     .to_ndarray::<Int64Type>()
                     .map_err(|e| {
-                        Status::aborted(format!("Cound not convert DataFrame to ndarray: {}", e))
+                        ErrorCode::ConversionFailed
+                            .status(format!("Could not convert DataFrame to ndarray: {}", e))
                     })?
                     .as_standard_layout()
                     .to_owned()
@@ -83,7 +225,8 @@ impl Converter {
                 let arr = df
                     .to_ndarray::<Int32Type>()
                     .map_err(|e| {
-                        Status::aborted(format!("Cound not convert DataFrame to ndarray: {}", e))
+                        ErrorCode::ConversionFailed
+                            .status(format!("Could not convert DataFrame to ndarray: {}", e))
                     })?
                     .as_standard_layout()
                     .to_owned()
@@ -91,7 +234,9 @@ impl Converter {
                 ArrayStore::AxdynI32(arr)
             }
             _ => {
-                return Err(Status::aborted(format!("{:?} not support ", dtype)));
+                return Err(
+                    ErrorCode::UnsupportedDtype.status(format!("{:?} not supported", dtype))
+                );
             }
         };
         let identifier = self.polars.insert_array(arr);
@@ -126,71 +271,63 @@ where {
                 */
                 let res = match inner.as_ref() {
                     DataType::Float64 => {
-                        let shape = get_shape(series).ok_or(Status::aborted(
-                            "Only List Series are supported in get_shape",
-                        ))?;
-                        let exploded = to_status_error(series.explode())?;
-                        let arr = to_status_error(exploded.f64())?;
-                        let slice = to_status_error(arr.cont_slice())?;
-                        ArrayStore::AxdynF64(to_status_error(list_to_ndarray(
-                            slice.to_vec(),
-                            shape,
-                        ))?)
+                        let shape = get_shape(series).ok_or_else(|| {
+                            ErrorCode::UnsupportedDtype
+                                .status("Only List Series are supported in get_shape")
+                        })?;
+                        let exploded = to_status_error(series.explode(), ErrorCode::DtypeMismatch)?;
+                        let arr = to_status_error(exploded.f64(), ErrorCode::DtypeMismatch)?;
+                        let slice = to_status_error(arr.cont_slice(), ErrorCode::ConversionFailed)?;
+                        ArrayStore::AxdynF64(list_to_ndarray(slice.to_vec(), shape)?)
                     }
 
                     DataType::Float32 => {
-                        let shape = get_shape(series).ok_or(Status::aborted(
-                            "Only List Series are supported in get_shape",
-                        ))?;
-                        let exploded = to_status_error(series.explode())?;
-                        let arr = to_status_error(exploded.f32())?;
-                        let slice = to_status_error(arr.cont_slice())?;
-                        ArrayStore::AxdynF32(to_status_error(list_to_ndarray(
-                            slice.to_vec(),
-                            shape,
-                        ))?)
+                        let shape = get_shape(series).ok_or_else(|| {
+                            ErrorCode::UnsupportedDtype
+                                .status("Only List Series are supported in get_shape")
+                        })?;
+                        let exploded = to_status_error(series.explode(), ErrorCode::DtypeMismatch)?;
+                        let arr = to_status_error(exploded.f32(), ErrorCode::DtypeMismatch)?;
+                        let slice = to_status_error(arr.cont_slice(), ErrorCode::ConversionFailed)?;
+                        ArrayStore::AxdynF32(list_to_ndarray(slice.to_vec(), shape)?)
                     }
 
                     DataType::Int64 => {
-                        let shape = get_shape(series).ok_or(Status::aborted(
-                            "Only List Series are supported in get_shape",
-                        ))?;
-                        let exploded = to_status_error(series.explode())?;
-                        let arr = to_status_error(exploded.i64())?;
-                        let slice = to_status_error(arr.cont_slice())?;
-                        ArrayStore::AxdynI64(to_status_error(list_to_ndarray(
-                            slice.to_vec(),
-                            shape,
-                        ))?)
+                        let shape = get_shape(series).ok_or_else(|| {
+                            ErrorCode::UnsupportedDtype
+                                .status("Only List Series are supported in get_shape")
+                        })?;
+                        let exploded = to_status_error(series.explode(), ErrorCode::DtypeMismatch)?;
+                        let arr = to_status_error(exploded.i64(), ErrorCode::DtypeMismatch)?;
+                        let slice = to_status_error(arr.cont_slice(), ErrorCode::ConversionFailed)?;
+                        ArrayStore::AxdynI64(list_to_ndarray(slice.to_vec(), shape)?)
                     }
 
                     DataType::Int32 => {
-                        let shape = get_shape(series).ok_or(Status::aborted(
-                            "Only List Series are supported in get_shape",
-                        ))?;
-                        let exploded = to_status_error(series.explode())?;
-                        let arr = to_status_error(exploded.i32())?;
-                        let slice = to_status_error(arr.cont_slice())?;
-                        ArrayStore::AxdynI32(to_status_error(list_to_ndarray(
-                            slice.to_vec(),
-                            shape,
-                        ))?)
+                        let shape = get_shape(series).ok_or_else(|| {
+                            ErrorCode::UnsupportedDtype
+                                .status("Only List Series are supported in get_shape")
+                        })?;
+                        let exploded = to_status_error(series.explode(), ErrorCode::DtypeMismatch)?;
+                        let arr = to_status_error(exploded.i32(), ErrorCode::DtypeMismatch)?;
+                        let slice = to_status_error(arr.cont_slice(), ErrorCode::ConversionFailed)?;
+                        ArrayStore::AxdynI32(list_to_ndarray(slice.to_vec(), shape)?)
                     }
 
                     DataType::Int16 => {
-                        let shape = get_shape(series).ok_or(Status::aborted(
-                            "Only List Series are supported in get_shape",
-                        ))?;
-                        let exploded = to_status_error(series.explode())?;
-                        let arr = to_status_error(exploded.i16())?;
-                        let slice = to_status_error(arr.cont_slice())?;
-                        ArrayStore::AxdynI16(to_status_error(list_to_ndarray(
-                            slice.to_vec(),
-                            shape,
-                        ))?)
+                        let shape = get_shape(series).ok_or_else(|| {
+                            ErrorCode::UnsupportedDtype
+                                .status("Only List Series are supported in get_shape")
+                        })?;
+                        let exploded = to_status_error(series.explode(), ErrorCode::DtypeMismatch)?;
+                        let arr = to_status_error(exploded.i16(), ErrorCode::DtypeMismatch)?;
+                        let slice = to_status_error(arr.cont_slice(), ErrorCode::ConversionFailed)?;
+                        ArrayStore::AxdynI16(list_to_ndarray(slice.to_vec(), shape)?)
                     }
                     _ => {
-                        return Err(Status::aborted(format!("{inner:?} not supported")));
+                        return Err(
+                            ErrorCode::UnsupportedDtype.status(format!("{inner:?} not supported"))
+                        );
                     }
                 };
                 res
@@ -214,18 +351,9 @@ impl ConversionService for Converter {
     ) -> Result<Response<Reference>, Status> {
         let identifier = &request.get_ref().identifier;
 
-        let df = self.polars.get_array(&identifier)?;
-
-        let tensor = self.ndarray_to_tensor(df)?;
-
-        let (_, tensor_ref) = self.torch.insert_tensor(Arc::new(Mutex::new(tensor)));
-
-        let tensor_ref = Reference {
-            identifier: tensor_ref.identifier,
-            meta: tensor_ref.meta,
-            ..Default::default()
-        };
-        Ok(Response::new(tensor_ref))
+        // Arrays and tensors share their identifiers, so the array is converted in place.
+        let tensor_ref = self.torch.tensor_reference(identifier)?;
+        Ok(Response::new(to_reference(tensor_ref)))
     }
 
     async fn conv_to_array(
@@ -260,13 +388,14 @@ impl ConversionService for Converter {
                The idea would be to convert columns into ArrayBase -> merge Vec<ArrayBase> -> ArrayBase
             */
             let col_names = df.get_column_names();
-            let vec_series = df
-                .columns(&col_names[..])
-                .map_err(|e| Status::aborted(format!("Could not get Series in DataFrame: {e}")))?;
+            let vec_series = df.columns(&col_names[..]).map_err(|e| {
+                ErrorCode::ConversionFailed
+                    .status(format!("Could not get Series in DataFrame: {e}"))
+            })?;
 
             let mut out = vec![];
             for series in vec_series {
-                out.push(to_status_error(self.list_series_to_array_store(series))?);
+                out.push(self.list_series_to_array_store(series)?);
             }
 
             /*
@@ -278,8 +407,13 @@ impl ConversionService for Converter {
             }
         } else {
             return Err(
-                Status::aborted("DataFrame with str columns cannot be converted directly to RemoteArray. Please tokenize strings first"));
+                ErrorCode::TokenizationRequired.status("DataFrame with str columns cannot be converted directly to RemoteArray. Please tokenize strings first"));
         };
+        self.provenance.record(
+            Node::array(&arr.identifier),
+            vec![Node::data_frame(&identifier)],
+            "conversion",
+        );
 
         Ok(Response::new(arr))
     }
@@ -322,9 +456,9 @@ impl ConversionService for Converter {
                     auth_token,
                 )?
             } else {
-                return Err(Status::aborted(format!(
-                    "Non-string columns cannot be tokenized"
-                )));
+                return Err(
+                    ErrorCode::UnsupportedDtype.status("Non-string columns cannot be tokenized")
+                );
             };
 
             let ids = RemoteArray {
@@ -334,9 +468,179 @@ impl ConversionService for Converter {
                 identifier: self.polars.insert_array(ArrayStore::AxdynI64(masks)),
             };
 
+            for array in [&ids, &masks] {
+                self.provenance.record(
+                    Node::array(&array.identifier),
+                    vec![Node::data_frame(&identifier)],
+                    "tokenization",
+                );
+            }
             identifiers.append(&mut vec![ids, masks]);
         }
 
         Ok(Response::new(RemoteArrays { list: identifiers }))
     }
+
+    async fn conv_tensor_to_data_frame(
+        &self,
+        request: Request<TensorToDataFrame>,
+    ) -> Result<Response<ConvertedDataFrame>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let owner = self.sess_manager.get_user_id(token)?;
+        let TensorToDataFrame {
+            identifier,
+            col_names,
+        } = request.into_inner();
+
+        let tensor = self.torch.get_tensor(&identifier)?;
+        let df = self.tensor_to_df(&tensor.lock().unwrap(), &col_names)?;
+        // The policies of the dataframes the tensor was computed from apply, as recorded
+        // by the server rather than as claimed by the client.
+        let sources = self
+            .provenance
+            .ancestors_of_kind(&Node::array(&identifier), NodeKind::DataFrame);
+        let (derived, header) = self.polars.insert_derived_df(&sources, df, owner)?;
+        self.provenance.record(
+            Node::data_frame(&derived),
+            vec![Node::array(&identifier)],
+            "conversion",
+        );
+
+        Ok(Response::new(ConvertedDataFrame {
+            identifier: derived,
+            header,
+        }))
+    }
+
+    async fn tokenize_to_dataset(
+        &self,
+        request: Request<ToTokenizedDataset>,
+    ) -> Result<Response<ConvertedDataset>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let owner = self.sess_manager.get_user_id(token)?;
+        let cancellation = Cancellation::of_request(&request)?;
+        let request = request.into_inner();
+
+        let license = self.dataset_license(&request.identifier, &owner, request.privacy_limit)?;
+        let df = self.polars.get_df_unchecked(&request.identifier)?;
+        let column = |name: &str| {
+            df.column(name).map(Series::clone).map_err(|_| {
+                Status::invalid_argument(format!("Could not find column `{name}` in data frame"))
+            })
+        };
+        let text = column(&request.text_column)?;
+        let labels = column(&request.labels_column)?;
+
+        // Downloading the tokenizer and tokenizing are both slow, so they are kept off
+        // the request handlers.
+        let tokenizers = Arc::clone(&self.tokenizers);
+        let auth_token = request.auth_token().to_string();
+        let (model, config, revision) = (request.model, request.config, request.revision);
+        let add_special_tokens = request.add_special_tokens;
+        let (ids, masks, labels) = cancellation
+            .run_blocking(move |cancellation| {
+                let tokenizer = tokenizers.get(&model, &config, &revision, &auth_token)?;
+                cancellation.check()?;
+                let (ids, masks) = tokenize_series(&tokenizer, &text, add_special_tokens)?;
+                Ok((ids, masks, series_to_tensor(&labels)?))
+            })
+            .await?;
+
+        let data = Dataset::new(
+            vec![Arc::new(Mutex::new(ids)), Arc::new(Mutex::new(masks))],
+            Arc::new(Mutex::new(labels)),
+            request.privacy_limit,
+        );
+        if license.require_dp {
+            self.polars
+                .expend_dp_budget(std::iter::once(&request.identifier), request.privacy_limit)?;
+        }
+        let dataset = self.torch.insert_dataset_data(
+            data,
+            request.name,
+            request.description,
+            Vec::new(),
+            Some(owner),
+            license,
+        );
+        Ok(Response::new(self.converted_dataset(
+            dataset,
+            &request.identifier,
+            "tokenization",
+        )))
+    }
+
+    async fn window_data_frame(
+        &self,
+        request: Request<ToWindowedDataset>,
+    ) -> Result<Response<ConvertedDataset>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let owner = self.sess_manager.get_user_id(token)?;
+        let cancellation = Cancellation::of_request(&request)?;
+        let request = request.into_inner();
+
+        let license = self.dataset_license(&request.identifier, &owner, request.privacy_limit)?;
+        let df = self.polars.get_df_unchecked(&request.identifier)?;
+        let (length, stride, horizon) = (request.length, request.stride, request.horizon);
+        let (time_column, input_columns, target_columns) = (
+            request.time_column,
+            request.input_columns,
+            request.target_columns,
+        );
+        let (inputs, labels) = cancellation
+            .run_blocking(move |_| {
+                let df = if time_column.is_empty() {
+                    df
+                } else {
+                    df.sort(vec![time_column], vec![false]).map_err(|e| {
+                        Status::invalid_argument(format!("Could not sort data frame: {e}"))
+                    })?
+                };
+                let inputs = columns_to_tensor(&df, &input_columns)?;
+                let targets = columns_to_tensor(&df, &target_columns)?;
+                sliding_windows(&inputs, &targets, length, stride, horizon)
+                    .map_err(|e| Status::invalid_argument(format!("Could not window series: {e}")))
+            })
+            .await?;
+
+        let data = Dataset::new(
+            vec![Arc::new(Mutex::new(inputs))],
+            Arc::new(Mutex::new(labels)),
+            request.privacy_limit,
+        );
+        if license.require_dp {
+            self.polars
+                .expend_dp_budget(std::iter::once(&request.identifier), request.privacy_limit)?;
+        }
+        let dataset = self.torch.insert_dataset_data(
+            data,
+            request.name,
+            request.description,
+            Vec::new(),
+            Some(owner),
+            license,
+        );
+        Ok(Response::new(self.converted_dataset(
+            dataset,
+            &request.identifier,
+            "windowing",
+        )))
+    }
+}
+
+/// Stacks the numeric columns `names` of `df` into a float tensor of shape `[rows, columns]`.
+fn columns_to_tensor(df: &DataFrame, names: &[String]) -> Result<Tensor, Status> {
+    if names.is_empty() {
+        return Err(Status::invalid_argument("No column was given"));
+    }
+    let columns = names
+        .iter()
+        .map(|name| {
+            let series = df.column(name).map_err(|_| {
+                Status::invalid_argument(format!("Could not find column `{name}` in data frame"))
+            })?;
+            Ok(series_to_tensor(series)?.to_kind(Kind::Float))
+        })
+        .collect::<Result<Vec<_>, Status>>()?;
+    Ok(Tensor::stack(&columns, 1))
 }
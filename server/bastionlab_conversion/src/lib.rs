@@ -1,4 +1,5 @@
 pub mod converter;
+pub mod tokenization;
 
 pub mod conversion_proto {
     tonic::include_proto!("bastionlab_conversion");
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use bastionlab_common::common_conversions::get_tokenizer;
+use polars::prelude::*;
+use tch::Tensor;
+use tokenizers::Tokenizer;
+use tonic::Status;
+
+/// Identifies a tokenizer by its Hugging Face model, revision and padding and truncation
+/// configuration.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct TokenizerKey {
+    model: String,
+    revision: String,
+    config: String,
+}
+
+/// Hugging Face tokenizers loaded by the server, kept so that each is only downloaded once.
+#[derive(Default)]
+pub struct Tokenizers {
+    loaded: RwLock<HashMap<TokenizerKey, Arc<Tokenizer>>>,
+}
+
+impl Tokenizers {
+    pub fn get(
+        &self,
+        model: &str,
+        config: &str,
+        revision: &str,
+        auth_token: &str,
+    ) -> Result<Arc<Tokenizer>, Status> {
+        let key = TokenizerKey {
+            model: model.to_string(),
+            revision: revision.to_string(),
+            config: config.to_string(),
+        };
+        if let Some(tokenizer) = self.loaded.read().unwrap().get(&key) {
+            return Ok(Arc::clone(tokenizer));
+        }
+        let tokenizer = Arc::new(get_tokenizer(model, config, revision, auth_token)?);
+        self.loaded
+            .write()
+            .unwrap()
+            .insert(key, Arc::clone(&tokenizer));
+        Ok(tokenizer)
+    }
+}
+
+/// Tokenizes every row of the text column `series` and returns the input ids and the
+/// attention masks, as tensors of shape `[rows, longest sequence]`.
+///
+/// Shorter sequences are padded with zeros, which their attention mask hides.
+pub fn tokenize_series(
+    tokenizer: &Tokenizer,
+    series: &Series,
+    add_special_tokens: bool,
+) -> Result<(Tensor, Tensor), Status> {
+    let texts = series
+        .utf8()
+        .map_err(|_| {
+            Status::invalid_argument(format!("Column `{}` does not hold text", series.name()))
+        })?
+        .into_iter()
+        .map(|row| {
+            row.ok_or_else(|| {
+                Status::invalid_argument(format!(
+                    "Column `{}` contains empty values",
+                    series.name()
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let encodings = tokenizer
+        .encode_batch(texts, add_special_tokens)
+        .map_err(|e| Status::invalid_argument(format!("Failed to tokenize text: {e}")))?;
+
+    let len = encodings.iter().map(|e| e.len()).max().unwrap_or(0);
+    let mut ids = Vec::with_capacity(encodings.len() * len);
+    let mut masks = Vec::with_capacity(encodings.len() * len);
+    for encoding in encodings.iter() {
+        let padding = len - encoding.len();
+        ids.extend(encoding.get_ids().iter().map(|&id| id as i64));
+        ids.extend(std::iter::repeat(0).take(padding));
+        masks.extend(encoding.get_attention_mask().iter().map(|&m| m as i64));
+        masks.extend(std::iter::repeat(0).take(padding));
+    }
+
+    let shape = [encodings.len() as i64, len as i64];
+    Ok((
+        Tensor::of_slice(&ids).reshape(&shape),
+        Tensor::of_slice(&masks).reshape(&shape),
+    ))
+}
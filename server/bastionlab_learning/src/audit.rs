@@ -0,0 +1,208 @@
+use crate::data::Dataset;
+use rand::{seq::SliceRandom, thread_rng, Rng};
+use std::sync::{Arc, Mutex};
+use tch::{Device, Kind, TchError, Tensor};
+
+/// Random samples shaped like those of a dataset, half of which are inserted into the
+/// dataset for a training run.
+///
+/// How much better the trained model fits the inserted canaries than the held out ones
+/// gives an empirical measure of how much the run leaks about individual samples, to be
+/// compared with its theoretical differential privacy guarantee.
+pub struct Canaries {
+    inputs: Vec<Tensor>,
+    labels: Tensor,
+    /// Whether each canary is inserted into the training data.
+    inserted: Vec<bool>,
+}
+
+/// How well a model separates the canaries it was trained on from the held out ones.
+#[derive(Debug, Clone, Copy)]
+pub struct LeakageScore {
+    /// Area under the ROC curve of a membership inference attack thresholding the loss
+    /// of the canaries. 0.5 means that the attack does no better than chance.
+    pub auc: f32,
+    /// Largest privacy loss observed at any threshold of the attack. This is a point
+    /// estimate rather than a bound, and may fall below the epsilon of the run.
+    pub empirical_epsilon: f32,
+}
+
+fn is_floating_point(kind: Kind) -> bool {
+    matches!(
+        kind,
+        Kind::Half | Kind::Float | Kind::Double | Kind::BFloat16
+    )
+}
+
+impl Canaries {
+    /// Generates `count` canaries for `dataset`, which must hold at least `count` samples.
+    ///
+    /// Floating point inputs are drawn from a normal distribution with the mean and standard
+    /// deviation of the corresponding input of the dataset, other inputs uniformly within
+    /// its range. Labels are drawn among the labels of the dataset.
+    pub fn generate(dataset: &Dataset, count: usize) -> Result<Self, TchError> {
+        if count < 2 || count > dataset.len() {
+            return Err(TchError::Kind(format!(
+                "Auditing requires between two canaries and the {} samples of the dataset",
+                dataset.len()
+            )));
+        }
+        let mut inputs = Vec::with_capacity(dataset.samples_inputs.len());
+        for samples_input in dataset.samples_inputs.iter() {
+            let samples_input = samples_input.lock().unwrap();
+            let mut shape = samples_input.size();
+            shape[0] = count as i64;
+            let kind = samples_input.kind();
+            let input = if is_floating_point(kind) {
+                let samples_input = samples_input.f_to_kind(Kind::Float)?;
+                let mean = samples_input.f_mean(Kind::Float)?.f_double_value(&[])?;
+                let std = samples_input.f_std(true)?.f_double_value(&[])?;
+                Tensor::f_randn(&shape, (Kind::Float, Device::Cpu))?
+                    .f_mul_scalar(std)?
+                    .f_add_scalar(mean)?
+            } else {
+                let min = samples_input.f_min()?.f_double_value(&[])?;
+                let max = samples_input.f_max()?.f_double_value(&[])?;
+                Tensor::f_rand(&shape, (Kind::Float, Device::Cpu))?
+                    .f_mul_scalar(max - min + 1.0)?
+                    .f_add_scalar(min)?
+                    .f_floor()?
+                    .f_clamp(min, max)?
+            };
+            inputs.push(input.f_to_kind(kind)?);
+        }
+
+        let mut rng = thread_rng();
+        let picked: Vec<i64> = (0..count)
+            .map(|_| rng.gen_range(0..dataset.len() as i64))
+            .collect();
+        let labels = dataset
+            .labels
+            .lock()
+            .unwrap()
+            .f_index_select(0, &Tensor::of_slice(&picked))?;
+
+        let mut inserted: Vec<bool> = (0..count).map(|i| i < count / 2).collect();
+        inserted.shuffle(&mut rng);
+        Ok(Canaries {
+            inputs,
+            labels,
+            inserted,
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.inserted.len()
+    }
+
+    /// Returns a copy of `dataset` with the inserted canaries appended.
+    pub fn inject(&self, dataset: &Dataset) -> Result<Dataset, TchError> {
+        let indexes: Vec<i64> = (0..self.count() as i64)
+            .filter(|&i| self.inserted[i as usize])
+            .collect();
+        let indexes = Tensor::of_slice(&indexes);
+        let mut inputs = Vec::with_capacity(self.inputs.len());
+        for input in self.inputs.iter() {
+            inputs.push(input.f_index_select(0, &indexes)?);
+        }
+        dataset.with_samples(&inputs, &self.labels.f_index_select(0, &indexes)?)
+    }
+
+    /// Returns every canary, inserted or not, as a dataset without privacy limit.
+    pub fn to_dataset(&self) -> Dataset {
+        Dataset::new(
+            self.inputs
+                .iter()
+                .map(|input| Arc::new(Mutex::new(input.shallow_clone())))
+                .collect(),
+            Arc::new(Mutex::new(self.labels.shallow_clone())),
+            -1.0,
+        )
+    }
+
+    /// Scores the leakage of a run from the loss of its model on each canary, in order.
+    pub fn score(&self, losses: &[f32]) -> LeakageScore {
+        let (inserted, held_out): (Vec<_>, Vec<_>) = losses
+            .iter()
+            .zip(self.inserted.iter())
+            .partition(|(_, inserted)| **inserted);
+        let inserted: Vec<f32> = inserted.into_iter().map(|(&loss, _)| loss).collect();
+        let held_out: Vec<f32> = held_out.into_iter().map(|(&loss, _)| loss).collect();
+
+        // The attack guesses that canaries with a lower loss were trained on.
+        let mut auc = 0.0;
+        for a in inserted.iter() {
+            for b in held_out.iter() {
+                auc += match a.total_cmp(b) {
+                    std::cmp::Ordering::Less => 1.0,
+                    std::cmp::Ordering::Equal => 0.5,
+                    std::cmp::Ordering::Greater => 0.0,
+                };
+            }
+        }
+        let auc = auc / (inserted.len() * held_out.len()) as f32;
+
+        let mut empirical_epsilon = 0.0f32;
+        for &threshold in losses {
+            let rate = |losses: &[f32]| {
+                losses.iter().filter(|&&loss| loss <= threshold).count() as f32
+                    / losses.len() as f32
+            };
+            let (tpr, fpr) = (rate(&inserted), rate(&held_out));
+            for (a, b) in [(tpr, fpr), (1.0 - fpr, 1.0 - tpr)] {
+                if a > 0.0 && b > 0.0 {
+                    empirical_epsilon = empirical_epsilon.max((a / b).ln());
+                }
+            }
+        }
+        LeakageScore {
+            auc,
+            empirical_epsilon,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canaries(inserted: &[bool]) -> Canaries {
+        Canaries {
+            inputs: Vec::new(),
+            labels: Tensor::new(),
+            inserted: inserted.to_vec(),
+        }
+    }
+
+    #[test]
+    fn separated_losses_score_full_auc() {
+        let canaries = canaries(&[true, false, true, false]);
+        let score = canaries.score(&[0.1, 0.8, 0.2, 0.9]);
+        assert_eq!(score.auc, 1.0);
+        assert!((score.empirical_epsilon - 2f32.ln()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn identical_losses_score_chance() {
+        let canaries = canaries(&[true, false, false, true]);
+        let score = canaries.score(&[0.5; 4]);
+        assert_eq!(score.auc, 0.5);
+        assert_eq!(score.empirical_epsilon, 0.0);
+    }
+
+    #[test]
+    fn inverted_losses_score_zero_auc() {
+        let canaries = canaries(&[false, true, false, true]);
+        let score = canaries.score(&[0.1, 0.8, 0.2, 0.9]);
+        assert_eq!(score.auc, 0.0);
+    }
+
+    #[test]
+    fn auc_counts_ties_as_half() {
+        let canaries = canaries(&[true, false, true, false]);
+        let score = canaries.score(&[0.1, 0.1, 0.9, 0.5]);
+        // Pairs (inserted, held out): (0.1, 0.1) ties, (0.1, 0.5) wins, (0.9, 0.1) and
+        // (0.9, 0.5) lose.
+        assert_eq!(score.auc, 0.375);
+    }
+}
@@ -0,0 +1,290 @@
+use super::transform::Transform;
+use std::f64::consts::PI;
+use std::io::Cursor;
+use tch::{Kind, TchError, Tensor};
+
+fn format_error(err: impl std::fmt::Display) -> TchError {
+    TchError::FileFormat(format!("Could not decode recording: {}", err))
+}
+
+/// Decodes a WAV or FLAC recording into a mono waveform with values between -1 and 1,
+/// and returns it with its sample rate.
+///
+/// The channels of multi-channel recordings are averaged.
+pub fn decode_audio(data: &[u8]) -> Result<(Tensor, u32), TchError> {
+    let (interleaved, channels, sample_rate) = if data.starts_with(b"RIFF") {
+        decode_wav(data)?
+    } else if data.starts_with(b"fLaC") {
+        decode_flac(data)?
+    } else {
+        return Err(TchError::FileFormat(String::from(
+            "Unsupported recording format, expected WAV or FLAC",
+        )));
+    };
+    let waveform = Tensor::of_slice(&interleaved)
+        .f_view(&[-1, channels as i64])?
+        .f_mean_dim(&[1], false, Kind::Float)?;
+    Ok((waveform, sample_rate))
+}
+
+fn decode_wav(data: &[u8]) -> Result<(Vec<f32>, u16, u32), TchError> {
+    let mut reader = hound::WavReader::new(Cursor::new(data)).map_err(format_error)?;
+    let spec = reader.spec();
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(format_error)?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / scale))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(format_error)?
+        }
+    };
+    Ok((samples, spec.channels, spec.sample_rate))
+}
+
+fn decode_flac(data: &[u8]) -> Result<(Vec<f32>, u16, u32), TchError> {
+    let mut reader = claxon::FlacReader::new(Cursor::new(data)).map_err(format_error)?;
+    let info = reader.streaminfo();
+    let scale = (1i64 << (info.bits_per_sample - 1)) as f32;
+    let samples = reader
+        .samples()
+        .map(|sample| sample.map(|sample| sample as f32 / scale))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(format_error)?;
+    Ok((samples, info.channels as u16, info.sample_rate))
+}
+
+/// Pads the waveforms with silence to the length of the longest and stacks them.
+pub fn stack_waveforms(waveforms: &[Tensor]) -> Result<Tensor, TchError> {
+    let len = waveforms.iter().map(|w| w.size()[0]).max().unwrap_or(0);
+    let padded = waveforms
+        .iter()
+        .map(|w| w.f_constant_pad_nd(&[0, len - w.size()[0]]))
+        .collect::<Result<Vec<_>, _>>()?;
+    Tensor::f_stack(&padded, 0)
+}
+
+fn hz_to_mel(hz: f64) -> f64 {
+    2595. * (1. + hz / 700.).log10()
+}
+
+fn mel_to_hz(mel: f64) -> f64 {
+    700. * (10f64.powf(mel / 2595.) - 1.)
+}
+
+/// Turns waveforms into mel-scaled power spectrograms of shape `[n_mels, frames]`.
+#[derive(Debug, Clone)]
+pub struct MelSpectrogram {
+    pub sample_rate: u32,
+    pub n_fft: i64,
+    pub hop_length: i64,
+    pub n_mels: i64,
+}
+
+impl MelSpectrogram {
+    /// Triangular filters of shape `[n_mels, n_fft / 2 + 1]`, evenly spaced on the mel
+    /// scale between 0 and the Nyquist frequency.
+    fn filterbank(&self) -> Tensor {
+        let n_freqs = self.n_fft / 2 + 1;
+        let nyquist = self.sample_rate as f64 / 2.;
+        let max_mel = hz_to_mel(nyquist);
+        let points: Vec<f64> = (0..self.n_mels + 2)
+            .map(|i| mel_to_hz(max_mel * i as f64 / (self.n_mels + 1) as f64))
+            .collect();
+
+        let mut weights = Vec::with_capacity((self.n_mels * n_freqs) as usize);
+        for m in 0..self.n_mels as usize {
+            let (left, center, right) = (points[m], points[m + 1], points[m + 2]);
+            for f in 0..n_freqs {
+                let hz = nyquist * f as f64 / (n_freqs - 1) as f64;
+                let weight = if hz <= left || hz >= right {
+                    0.
+                } else if hz <= center {
+                    (hz - left) / (center - left)
+                } else {
+                    (right - hz) / (right - center)
+                };
+                weights.push(weight as f32);
+            }
+        }
+        Tensor::of_slice(&weights).view(&[self.n_mels, n_freqs])
+    }
+}
+
+impl Transform for MelSpectrogram {
+    fn apply(&self, waveform: &Tensor) -> Result<Tensor, TchError> {
+        let window = Tensor::f_hann_window(self.n_fft, (Kind::Float, waveform.device()))?;
+        let power = waveform
+            .f_stft(
+                self.n_fft,
+                self.hop_length,
+                self.n_fft,
+                Some(&window),
+                false,
+                true,
+                true,
+            )?
+            .f_abs()?
+            .f_pow_tensor_scalar(2)?;
+        self.filterbank()
+            .to_device(waveform.device())
+            .f_matmul(&power)
+    }
+}
+
+/// Turns waveforms into mel-frequency cepstral coefficients of shape `[n_mfcc, frames]`.
+#[derive(Debug, Clone)]
+pub struct Mfcc {
+    pub mel_spectrogram: MelSpectrogram,
+    pub n_mfcc: i64,
+}
+
+impl Mfcc {
+    /// Orthonormal DCT-II matrix of shape `[n_mfcc, n_mels]`.
+    fn dct(&self) -> Tensor {
+        let n_mels = self.mel_spectrogram.n_mels;
+        let mut weights = Vec::with_capacity((self.n_mfcc * n_mels) as usize);
+        for k in 0..self.n_mfcc {
+            let scale = if k == 0 {
+                (1. / n_mels as f64).sqrt()
+            } else {
+                (2. / n_mels as f64).sqrt()
+            };
+            for n in 0..n_mels {
+                let angle = PI * k as f64 * (2 * n + 1) as f64 / (2 * n_mels) as f64;
+                weights.push((scale * angle.cos()) as f32);
+            }
+        }
+        Tensor::of_slice(&weights).view(&[self.n_mfcc, n_mels])
+    }
+}
+
+impl Transform for Mfcc {
+    fn apply(&self, waveform: &Tensor) -> Result<Tensor, TchError> {
+        if self.n_mfcc <= 0 || self.n_mfcc > self.mel_spectrogram.n_mels {
+            return Err(TchError::Kind(String::from(
+                "The number of MFCCs must be positive and at most the number of mel bands",
+            )));
+        }
+        let log_mel = self
+            .mel_spectrogram
+            .apply(waveform)?
+            .f_clamp_min(1e-10)?
+            .f_log10()?
+            .f_mul_scalar(10.)?;
+        self.dct().to_device(waveform.device()).f_matmul(&log_mel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mel_spectrogram(n_mels: i64) -> MelSpectrogram {
+        MelSpectrogram {
+            sample_rate: 16000,
+            n_fft: 64,
+            hop_length: 16,
+            n_mels,
+        }
+    }
+
+    fn wav(channels: u16, samples: &[i16]) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut data = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut data, spec).unwrap();
+        for &sample in samples {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn mel_scale_round_trips() {
+        for hz in [0., 440., 1000., 8000.] {
+            assert!((mel_to_hz(hz_to_mel(hz)) - hz).abs() < 1e-6);
+        }
+        assert!((hz_to_mel(1000.) - 1000.).abs() < 1.);
+    }
+
+    #[test]
+    fn filterbank_has_one_triangle_per_band() {
+        let filters = mel_spectrogram(8).filterbank();
+        assert_eq!(filters.size(), vec![8, 33]);
+        assert!(filters.min().double_value(&[]) >= 0.);
+        assert!(filters.max().double_value(&[]) <= 1.);
+        let peaks = filters.amax(&[1], false);
+        assert!(peaks.min().double_value(&[]) > 0.);
+    }
+
+    #[test]
+    fn dct_is_orthonormal() {
+        let mfcc = Mfcc {
+            mel_spectrogram: mel_spectrogram(12),
+            n_mfcc: 12,
+        };
+        let dct = mfcc.dct();
+        let identity = Tensor::eye(12, (Kind::Float, tch::Device::Cpu));
+        let error = (dct.matmul(&dct.transpose(0, 1)) - identity).abs().max();
+        assert!(error.double_value(&[]) < 1e-5);
+    }
+
+    #[test]
+    fn mfcc_outputs_coefficients_per_frame() {
+        let mfcc = Mfcc {
+            mel_spectrogram: mel_spectrogram(16),
+            n_mfcc: 4,
+        };
+        let waveform = Tensor::randn(&[256], (Kind::Float, tch::Device::Cpu));
+        let coefficients = mfcc.apply(&waveform).unwrap();
+        let frames = mfcc.mel_spectrogram.apply(&waveform).unwrap().size()[1];
+        assert_eq!(coefficients.size(), vec![4, frames]);
+    }
+
+    #[test]
+    fn mfcc_rejects_invalid_counts() {
+        let waveform = Tensor::zeros(&[256], (Kind::Float, tch::Device::Cpu));
+        for n_mfcc in [-1, 0, 17] {
+            let mfcc = Mfcc {
+                mel_spectrogram: mel_spectrogram(16),
+                n_mfcc,
+            };
+            assert!(mfcc.apply(&waveform).is_err());
+        }
+    }
+
+    #[test]
+    fn decodes_wav_channels_to_mono() {
+        let data = wav(2, &[16384, 0, -16384, -16384]);
+        let (waveform, sample_rate) = decode_audio(&data).unwrap();
+        assert_eq!(sample_rate, 8000);
+        assert_eq!(Vec::<f32>::from(&waveform), vec![0.25, -0.5]);
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        assert!(decode_audio(b"OggS").is_err());
+    }
+
+    #[test]
+    fn stacks_waveforms_padded_with_silence() {
+        let waveforms = [Tensor::of_slice(&[1f32, 2., 3.]), Tensor::of_slice(&[4f32])];
+        let stacked = stack_waveforms(&waveforms).unwrap();
+        assert_eq!(stacked.size(), vec![2, 3]);
+        assert_eq!(
+            Vec::<f32>::from(&stacked.view(&[-1])),
+            vec![1., 2., 3., 4., 0., 0.]
+        );
+    }
+}
@@ -1,11 +1,12 @@
 use super::privacy_guard::{BatchDependence, PrivacyBudget, PrivacyContext, PrivacyGuard};
 use crate::serialization::SizedObjectsBytes;
-use rand::{seq::SliceRandom, thread_rng};
+use rand::{seq::SliceRandom, thread_rng, Rng};
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::io::Cursor;
 use std::ops::Deref;
 use std::sync::{Arc, Mutex, RwLock};
-use tch::{Device, IndexOp, TchError, Tensor};
+use tch::{Device, IndexOp, Kind, TchError, Tensor};
 
 /// Simple in-memory dataset that keeps track of its usage in terms of privacy budget
 #[derive(Debug)]
@@ -15,6 +16,36 @@ pub struct Dataset {
     privacy_context: Arc<RwLock<PrivacyContext>>,
 }
 
+/// Parts of a dataset split by [`Dataset::holdout`].
+#[derive(Debug)]
+pub struct Holdout {
+    pub train: Dataset,
+    pub validation: Dataset,
+    /// Budget already expended from the dataset for the parts.
+    charged: Mutex<f32>,
+}
+
+impl Holdout {
+    /// Expends from `dataset`, the dataset split into the parts, the budget they expended
+    /// since the last call. This is the largest budget expended by either of them, as they
+    /// hold distinct samples.
+    pub fn charge(&self, dataset: &Dataset) {
+        let expended = [&self.train, &self.validation]
+            .iter()
+            .filter_map(|part| match part.privacy_context().expended() {
+                PrivacyBudget::Private(eps) => Some(eps),
+                // Only possible when the dataset has no privacy limit either.
+                PrivacyBudget::NotPrivate => None,
+            })
+            .fold(0.0, f32::max);
+        let mut charged = self.charged.lock().unwrap();
+        if expended > *charged {
+            dataset.expend(PrivacyBudget::Private(expended - *charged));
+            *charged = expended;
+        }
+    }
+}
+
 /// Simple iterator over [`Dataset`].
 pub struct DatasetIter<'a> {
     dataset: &'a Dataset,
@@ -126,6 +157,55 @@ impl Dataset {
             batch_id: 0,
         }
     }
+    /// Returns an iterator over shuffled batches that approximately preserve the proportion
+    /// of every class of the dataset, so that rare classes are not missing from most of them.
+    ///
+    /// Labels must be integer class indexes, one per sample.
+    pub fn iter_stratified<'a>(&'a self, batch_size: usize) -> Result<DatasetIter<'a>, TchError> {
+        let classes = {
+            let labels = self.labels.lock().unwrap();
+            let nb_samples = labels.size()[0] as usize;
+            match labels.f_kind()? {
+                Kind::Uint8 | Kind::Int8 | Kind::Int16 | Kind::Int | Kind::Int64
+                    if labels.numel() == nb_samples => {}
+                _ => {
+                    return Err(TchError::Kind(String::from(
+                        "Stratified batches require a single integer class label per sample",
+                    )))
+                }
+            }
+            let mut classes = vec![0i64; nb_samples];
+            labels
+                .f_to_device(Device::Cpu)?
+                .f_to_kind(Kind::Int64)?
+                .f_contiguous()?
+                .f_copy_data(&mut classes, nb_samples)?;
+            classes
+        };
+        let mut strata: HashMap<i64, Vec<i64>> = HashMap::new();
+        for (idx, class) in classes.iter().enumerate() {
+            strata.entry(*class).or_default().push(idx as i64);
+        }
+
+        // The samples of every class are spread evenly over the epoch, at jittered positions,
+        // so that any run of consecutive samples holds about the same proportions.
+        let mut rng = thread_rng();
+        let mut positions = Vec::with_capacity(classes.len());
+        for (_, mut indexes) in strata {
+            indexes.shuffle(&mut rng);
+            let count = indexes.len() as f64;
+            for (rank, idx) in indexes.into_iter().enumerate() {
+                positions.push(((rank as f64 + rng.gen::<f64>()) / count, idx));
+            }
+        }
+        positions.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        Ok(DatasetIter {
+            dataset: self,
+            indexes: positions.into_iter().map(|(_, idx)| idx).collect(),
+            batch_size,
+            batch_id: 0,
+        })
+    }
     pub fn iter<'a>(&'a self, batch_size: usize) -> DatasetIter<'a> {
         let indexes: Vec<_> = (0..self.len() as i64).collect();
         DatasetIter {
@@ -139,6 +219,223 @@ impl Dataset {
     pub fn len(&self) -> usize {
         self.labels.lock().unwrap().size()[0] as usize
     }
+
+    /// Returns the privacy budget limit and expenditure of the dataset.
+    pub fn privacy_context(&self) -> PrivacyContext {
+        *self.privacy_context.read().unwrap()
+    }
+
+    /// Wraps a value computed from the whole dataset in a guard sharing its privacy context.
+    pub(crate) fn guard<T>(&self, value: T) -> PrivacyGuard<T> {
+        PrivacyGuard::new(
+            value,
+            BatchDependence::Dependent,
+            Arc::clone(&self.privacy_context),
+        )
+    }
+
+    /// Returns a dataset with the labels and privacy budget of this one, whose inputs are
+    /// `inputs`, e.g. features computed from the inputs of this dataset.
+    pub fn derive(&self, inputs: Vec<Tensor>) -> Self {
+        Dataset {
+            samples_inputs: inputs
+                .into_iter()
+                .map(|input| Arc::new(Mutex::new(input)))
+                .collect(),
+            labels: Arc::clone(&self.labels),
+            privacy_context: Arc::clone(&self.privacy_context),
+        }
+    }
+
+    /// Splits the dataset into parts of `sizes` samples, picked at random if `shuffle` is
+    /// true and in order otherwise.
+    ///
+    /// The parts hold distinct samples, so each of them gets the remaining privacy budget
+    /// of this dataset, which is expended from it once.
+    pub fn split(&self, sizes: &[usize], shuffle: bool) -> Result<Vec<Dataset>, TchError> {
+        let nb_samples = self.len();
+        if sizes.iter().sum::<usize>() > nb_samples {
+            return Err(TchError::Shape(format!(
+                "Cannot split {} samples into parts of {:?} samples",
+                nb_samples, sizes
+            )));
+        }
+        let mut indexes: Vec<_> = (0..nb_samples as i64).collect();
+        if shuffle {
+            indexes.shuffle(&mut thread_rng());
+        }
+        let budget = self.privacy_context().remaining();
+        let mut parts = Vec::with_capacity(sizes.len());
+        let mut start = 0;
+        for size in sizes {
+            parts.push(self.select(&indexes[start..start + size], budget)?);
+            start += size;
+        }
+        self.expend(budget);
+        Ok(parts)
+    }
+
+    /// Splits the dataset at random into a part of about `1 - ratio` of the samples to train
+    /// on and a held-out part of the others to validate on.
+    ///
+    /// Each part gets the remaining privacy budget of this dataset. As they hold distinct
+    /// samples, this dataset is then charged the largest budget either of them expended, see
+    /// [`Holdout::charge`].
+    pub fn holdout(&self, ratio: f32) -> Result<Holdout, TchError> {
+        let nb_samples = self.len();
+        let nb_validation = (nb_samples as f32 * ratio).round() as usize;
+        if nb_validation == 0 || nb_validation >= nb_samples {
+            return Err(TchError::Shape(format!(
+                "Cannot hold out {} of {} samples for validation",
+                ratio, nb_samples
+            )));
+        }
+        let mut indexes: Vec<_> = (0..nb_samples as i64).collect();
+        indexes.shuffle(&mut thread_rng());
+        let (validation, train) = indexes.split_at(nb_validation);
+        let budget = self.privacy_context().remaining();
+        Ok(Holdout {
+            train: self.select(train, budget)?,
+            validation: self.select(validation, budget)?,
+            charged: Mutex::new(0.0),
+        })
+    }
+
+    /// Returns the concatenation of `datasets`, whose inputs and labels must have the same
+    /// shapes and kinds.
+    ///
+    /// The result gets the smallest remaining privacy budget of the datasets, which is
+    /// expended from each of them. Datasets sharing a privacy budget, e.g. derived from the
+    /// same one, may hold the same individuals, so that budget is divided between them.
+    pub fn concat(datasets: &[&Dataset]) -> Result<Dataset, TchError> {
+        let first = datasets.first().ok_or_else(|| {
+            TchError::Shape(String::from("Cannot concatenate an empty list of datasets"))
+        })?;
+        let nb_inputs = first.samples_inputs.len();
+        if let Some(dataset) = datasets
+            .iter()
+            .find(|dataset| dataset.samples_inputs.len() != nb_inputs)
+        {
+            return Err(TchError::Shape(format!(
+                "Cannot concatenate datasets with {} and {} inputs per sample",
+                nb_inputs,
+                dataset.samples_inputs.len()
+            )));
+        }
+
+        // Tensors are locked one at a time, as derived datasets may share them.
+        let mut samples_inputs = Vec::with_capacity(nb_inputs);
+        for i in 0..nb_inputs {
+            let inputs: Vec<_> = datasets
+                .iter()
+                .map(|dataset| dataset.samples_inputs[i].lock().unwrap().shallow_clone())
+                .collect();
+            samples_inputs.push(Arc::new(Mutex::new(concat_samples(&inputs, "inputs")?)));
+        }
+        let labels: Vec<_> = datasets
+            .iter()
+            .map(|dataset| dataset.labels.lock().unwrap().shallow_clone())
+            .collect();
+        let labels = concat_samples(&labels, "labels")?;
+
+        let mut budget = PrivacyBudget::NotPrivate;
+        for dataset in datasets {
+            let sharing = datasets
+                .iter()
+                .filter(|other| Arc::ptr_eq(&other.privacy_context, &dataset.privacy_context))
+                .count();
+            if let PrivacyBudget::Private(remaining) = dataset.privacy_context().remaining() {
+                let share = remaining / sharing as f32;
+                budget = match budget {
+                    PrivacyBudget::Private(eps) if eps <= share => budget,
+                    _ => PrivacyBudget::Private(share),
+                };
+            }
+        }
+        for dataset in datasets {
+            dataset.expend(budget);
+        }
+
+        let nb_samples = labels.size()[0] as usize;
+        Ok(Dataset {
+            samples_inputs,
+            labels: Arc::new(Mutex::new(labels)),
+            privacy_context: Arc::new(RwLock::new(PrivacyContext::new(budget, nb_samples))),
+        })
+    }
+
+    /// Returns a dataset with the samples of this one at `indexes` and a privacy limit of `limit`.
+    fn select(&self, indexes: &[i64], limit: PrivacyBudget) -> Result<Dataset, TchError> {
+        let indexes = Tensor::of_slice(indexes);
+        let select = |tensor: &Mutex<Tensor>| -> Result<Arc<Mutex<Tensor>>, TchError> {
+            let tensor = tensor.lock().unwrap();
+            let selected = tensor.f_index_select(0, &indexes.f_to_device(tensor.device())?)?;
+            Ok(Arc::new(Mutex::new(selected)))
+        };
+        let samples_inputs = self
+            .samples_inputs
+            .iter()
+            .map(|input| select(input))
+            .collect::<Result<Vec<_>, TchError>>()?;
+        let labels = select(&self.labels)?;
+        let nb_samples = labels.lock().unwrap().size()[0] as usize;
+        Ok(Dataset {
+            samples_inputs,
+            labels,
+            privacy_context: Arc::new(RwLock::new(PrivacyContext::new(limit, nb_samples))),
+        })
+    }
+
+    /// Expends `budget` from the privacy budget of this dataset, e.g. when it is moved to
+    /// datasets derived from this one.
+    fn expend(&self, budget: PrivacyBudget) {
+        if let PrivacyBudget::Private(_) = budget {
+            self.privacy_context.write().unwrap().update_budget(budget);
+        }
+    }
+
+    /// Returns a copy of this dataset with the given samples appended, whose privacy
+    /// budget is expended from this dataset's.
+    pub fn with_samples(&self, inputs: &[Tensor], labels: &Tensor) -> Result<Self, TchError> {
+        if inputs.len() != self.samples_inputs.len() {
+            return Err(TchError::Shape(format!(
+                "Expected {} inputs per sample, got {}",
+                self.samples_inputs.len(),
+                inputs.len()
+            )));
+        }
+        let mut samples_inputs = Vec::with_capacity(inputs.len());
+        for (samples_input, input) in self.samples_inputs.iter().zip(inputs) {
+            let samples_input = Tensor::f_cat(&[&*samples_input.lock().unwrap(), input], 0)?;
+            samples_inputs.push(Arc::new(Mutex::new(samples_input)));
+        }
+        let labels = Tensor::f_cat(&[&*self.labels.lock().unwrap(), labels], 0)?;
+        Ok(Dataset {
+            samples_inputs,
+            labels: Arc::new(Mutex::new(labels)),
+            privacy_context: Arc::clone(&self.privacy_context),
+        })
+    }
+}
+
+/// Concatenates the samples of several datasets along the first dimension.
+fn concat_samples(tensors: &[Tensor], what: &str) -> Result<Tensor, TchError> {
+    let (first, rest) = tensors
+        .split_first()
+        .ok_or_else(|| TchError::Shape(format!("No {} to concatenate", what)))?;
+    for tensor in rest {
+        if tensor.size()[1..] != first.size()[1..] || tensor.kind() != first.kind() {
+            return Err(TchError::Shape(format!(
+                "Cannot concatenate {} of shape {:?} ({:?}) and {:?} ({:?})",
+                what,
+                &first.size()[1..],
+                first.kind(),
+                &tensor.size()[1..],
+                tensor.kind()
+            )));
+        }
+    }
+    Tensor::f_cat(tensors, 0)
 }
 
 impl TryFrom<SizedObjectsBytes> for Dataset {
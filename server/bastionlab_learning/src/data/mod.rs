@@ -1,4 +1,9 @@
+pub mod audio;
 mod dataset;
+mod preview;
 pub mod privacy_guard;
+pub mod transform;
+pub mod windows;
 
-pub use dataset::{Dataset, DatasetIter, DatasetMetadata};
+pub use dataset::{Dataset, DatasetIter, DatasetMetadata, Holdout};
+pub use preview::{DatasetPreview, TensorPreview};
@@ -0,0 +1,133 @@
+use super::privacy_guard::{PrivacyBudget, Sensibility};
+use super::Dataset;
+use tch::{Kind, TchError, Tensor};
+
+/// Shape and type of a tensor of a dataset, with noised statistics of its features.
+///
+/// Features are the entries of the last dimension of the samples, e.g. the columns of
+/// tabular data.
+#[derive(Debug, Clone)]
+pub struct TensorPreview {
+    /// Shape of the whole tensor, the first dimension being the number of samples.
+    pub shape: Vec<i64>,
+    pub kind: Kind,
+    pub mean: Vec<f32>,
+    pub std: Vec<f32>,
+}
+
+/// What a dataset looks like, computed with differential privacy so that it can be shown
+/// to users who cannot fetch the samples.
+#[derive(Debug, Clone)]
+pub struct DatasetPreview {
+    pub inputs: Vec<TensorPreview>,
+    /// The labels have no statistics, see `label_distribution`.
+    pub labels: TensorPreview,
+    /// Noised number of samples per class, empty if the labels are not classes.
+    pub label_distribution: Vec<f32>,
+}
+
+fn is_class(kind: Kind) -> bool {
+    matches!(
+        kind,
+        Kind::Int64 | Kind::Int | Kind::Int16 | Kind::Int8 | Kind::Uint8
+    )
+}
+
+impl Dataset {
+    /// Previews this dataset, expending `eps` of its privacy budget.
+    ///
+    /// Input values are clamped to `bounds` before computing their mean and standard
+    /// deviation, which bounds the influence of a sample. When `nb_classes` is positive and
+    /// labels are integers, one per sample, the number of samples per class is also released.
+    /// The budget is split evenly between every released statistic.
+    pub fn preview(
+        &self,
+        eps: f32,
+        bounds: (f64, f64),
+        nb_classes: usize,
+    ) -> Result<DatasetPreview, TchError> {
+        let (lower, upper) = bounds;
+        if eps <= 0.0 || !lower.is_finite() || !upper.is_finite() || lower >= upper {
+            return Err(TchError::Kind(String::from(
+                "A preview requires a positive budget and valid bounds",
+            )));
+        }
+        if !self
+            .privacy_context()
+            .within_bounds(PrivacyBudget::Private(eps))
+        {
+            return Err(TchError::Kind(String::from("Privacy limit violation.")));
+        }
+        let labels = self.labels.lock().unwrap().shallow_clone();
+        let with_distribution = nb_classes > 0 && labels.dim() == 1 && is_class(labels.kind());
+        let nb_releases = 2 * self.samples_inputs.len() + with_distribution as usize;
+        let budget = PrivacyBudget::Private(eps / nb_releases as f32);
+
+        let nb_samples = self.len().max(1) as f64;
+        let mut inputs = Vec::with_capacity(self.samples_inputs.len());
+        for input in self.samples_inputs.iter() {
+            let input = input.lock().unwrap().shallow_clone();
+            let features = match input.size().last() {
+                Some(&features) if input.dim() > 1 => features,
+                _ => 1,
+            };
+            // Every sample contributes the same number of values to each feature, so that
+            // changing a sample moves the mean of a feature by at most the bounds' width
+            // divided by the number of samples.
+            let values = input
+                .f_to_kind(Kind::Float)?
+                .f_clamp(lower, upper)?
+                .f_reshape(&[-1, features])?;
+            let mean = self
+                .guard(values.f_mean_dim(&[0], false, Kind::Float)?)
+                .with_sensibility(Sensibility::LInfinity(
+                    ((upper - lower) / nb_samples) as f32,
+                ))
+                .get_private(budget)?
+                .f_clamp(lower, upper)?;
+            let square_bound = lower.powi(2).max(upper.powi(2));
+            let second_moment = self
+                .guard(values.f_square()?.f_mean_dim(&[0], false, Kind::Float)?)
+                .with_sensibility(Sensibility::LInfinity((square_bound / nb_samples) as f32))
+                .get_private(budget)?;
+            let std = second_moment
+                .f_sub(&mean.f_square()?)?
+                .f_clamp_min(0.0)?
+                .f_sqrt()?;
+            inputs.push(TensorPreview {
+                shape: input.size(),
+                kind: input.kind(),
+                mean: Vec::<f32>::from(&mean),
+                std: Vec::<f32>::from(&std),
+            });
+        }
+
+        let label_distribution = if with_distribution {
+            let counts = labels
+                .f_flatten(0, -1)?
+                .f_clamp(0, nb_classes as i64 - 1)?
+                .f_bincount::<Tensor>(None, nb_classes as i64)?
+                .f_to_kind(Kind::Float)?;
+            // Changing a sample moves it from a class to another.
+            let counts = self
+                .guard(counts)
+                .with_sensibility(Sensibility::L2(2f32.sqrt()))
+                .get_private(budget)?
+                .f_clamp_min(0.0)?;
+            Vec::<f32>::from(&counts)
+        } else {
+            Vec::new()
+        };
+
+        Ok(DatasetPreview {
+            inputs,
+            labels: TensorPreview {
+                shape: labels.size(),
+                kind: labels.kind(),
+                mean: Vec::new(),
+                std: Vec::new(),
+            },
+            label_distribution,
+        })
+    }
+}
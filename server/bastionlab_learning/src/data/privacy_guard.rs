@@ -136,7 +136,26 @@ impl PrivacyContext {
         self.nb_samples
     }
 
-    fn update_budget(&mut self, budget: PrivacyBudget) {
+    pub fn limit(&self) -> PrivacyBudget {
+        self.limit
+    }
+
+    pub fn expended(&self) -> PrivacyBudget {
+        self.expended
+    }
+
+    /// Returns the budget that may still be expended, `NotPrivate` if there is no limit.
+    pub fn remaining(&self) -> PrivacyBudget {
+        match (self.limit, self.expended) {
+            (PrivacyBudget::NotPrivate, _) => PrivacyBudget::NotPrivate,
+            (PrivacyBudget::Private(_), PrivacyBudget::NotPrivate) => PrivacyBudget::Private(0.0),
+            (PrivacyBudget::Private(limit), PrivacyBudget::Private(expended)) => {
+                PrivacyBudget::Private((limit - expended).max(0.0))
+            }
+        }
+    }
+
+    pub(crate) fn update_budget(&mut self, budget: PrivacyBudget) {
         match (&mut self.expended, budget) {
             (PrivacyBudget::NotPrivate, _) => (),
             (PrivacyBudget::Private(_), PrivacyBudget::NotPrivate) => {
@@ -214,6 +233,12 @@ impl<T> PrivacyGuard<T> {
     pub fn within_bounds(&self, budget: PrivacyBudget) -> bool {
         self.context.read().unwrap().within_bounds(budget)
     }
+    /// Sets the sensibility of a value computed outside of the guard, whose bound is
+    /// known to the caller.
+    pub(crate) fn with_sensibility(mut self, sensibility: Sensibility) -> Self {
+        self.sensibility = sensibility;
+        self
+    }
 }
 
 impl PrivacyGuard<f32> {
@@ -447,6 +472,11 @@ impl PrivacyGuard<Tensor> {
         }
     }
 
+    /// Returns the size of the data of the tensor.
+    pub fn size_in_bytes(&self) -> Result<u64, TchError> {
+        Ok((self.value.numel() * self.value.f_kind()?.elt_size_in_bytes()) as u64)
+    }
+
     pub fn expand_batch_dim(&self, n: i64) -> Result<Self, TchError> {
         let mut repeats = vec![1; self.value.dim()];
         repeats[0] = n;
@@ -0,0 +1,24 @@
+use super::Dataset;
+use tch::{TchError, Tensor};
+
+/// Transformation of the samples of a dataset input, applied on the server.
+pub trait Transform {
+    /// Transforms a single sample, without its batch dimension.
+    fn apply(&self, sample: &Tensor) -> Result<Tensor, TchError>;
+}
+
+impl Dataset {
+    /// Replaces every sample of the input at index `input` by its transformation.
+    pub fn transform_input(&self, input: usize, transform: &dyn Transform) -> Result<(), TchError> {
+        let samples = self
+            .samples_inputs
+            .get(input)
+            .ok_or_else(|| TchError::Kind(format!("Dataset has no input at index {}", input)))?;
+        let mut samples = samples.lock().unwrap();
+        let transformed = (0..samples.size()[0])
+            .map(|i| transform.apply(&samples.f_get(i)?))
+            .collect::<Result<Vec<_>, _>>()?;
+        *samples = Tensor::f_stack(&transformed, 0)?;
+        Ok(())
+    }
+}
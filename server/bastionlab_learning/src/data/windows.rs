@@ -0,0 +1,50 @@
+use tch::{TchError, Tensor};
+
+/// Cuts the time series `inputs` and `targets`, of shapes `[time, ...]`, into sliding
+/// windows for sequence models.
+///
+/// A window starts every `stride` steps. Its inputs are the `length` steps of `inputs` from
+/// its start and its targets are the `horizon` steps of `targets` that follow. Returns the
+/// inputs and targets of all the windows, of shapes `[windows, length, ...]` and
+/// `[windows, horizon, ...]`.
+pub fn sliding_windows(
+    inputs: &Tensor,
+    targets: &Tensor,
+    length: i64,
+    stride: i64,
+    horizon: i64,
+) -> Result<(Tensor, Tensor), TchError> {
+    if length <= 0 || stride <= 0 || horizon <= 0 {
+        return Err(TchError::Kind(String::from(
+            "Window length, stride and horizon must be positive",
+        )));
+    }
+    let steps = inputs.size().first().copied().unwrap_or(0);
+    if targets.size().first().copied().unwrap_or(0) != steps {
+        return Err(TchError::Shape(String::from(
+            "Inputs and targets must have the same number of time steps",
+        )));
+    }
+    if steps < length + horizon {
+        return Err(TchError::Shape(format!(
+            "Series of {} steps is shorter than a window of {} steps and its horizon of {}",
+            steps, length, horizon
+        )));
+    }
+
+    let windows = |series: &Tensor, start: i64, size: i64| -> Result<Tensor, TchError> {
+        // `unfold` appends the window dimension, which is moved right after the windows.
+        let unfolded = series.f_unfold(0, length + horizon, stride)?;
+        let dims = unfolded.dim() as i64;
+        let mut order = vec![0, dims - 1];
+        order.extend(1..dims - 1);
+        unfolded
+            .f_permute(&order)?
+            .f_narrow(1, start, size)?
+            .f_contiguous()
+    };
+    Ok((
+        windows(inputs, 0, length)?,
+        windows(targets, length, horizon)?,
+    ))
+}
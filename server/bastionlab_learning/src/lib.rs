@@ -1,3 +1,4 @@
+pub mod audit;
 pub mod data;
 pub mod nn;
 pub mod optim;
@@ -13,8 +14,9 @@ mod tests {
     use crate::data::privacy_guard::{
         BatchDependence, PrivacyBudget, PrivacyContext, PrivacyGuard,
     };
+    use crate::data::Dataset;
     use crate::nn::{LossType, Module};
-    use crate::optim::{Optimizer, SGD};
+    use crate::optim::{LrScheduler, Optimizer, SGD};
 
     fn l2_loss(output: &Tensor, target: &Tensor) -> Result<Tensor, TchError> {
         output
@@ -94,6 +96,96 @@ mod tests {
         assert!((w - Tensor::of_slice::<f32>(&[2.])).abs().double_value(&[]) < 0.1);
     }
 
+    #[test]
+    fn accumulated_sgd() {
+        let mut module = Module::load_from_file("lreg_base.pt", Device::Cpu).unwrap();
+        let (forward, parameters) = module.parameters();
+        let mut optimizer = SGD::new(parameters, 0.1);
+
+        let data = vec![
+            Tensor::of_slice::<f32>(&[0.]),
+            Tensor::of_slice::<f32>(&[1.]),
+        ];
+        let target = vec![
+            Tensor::of_slice::<f32>(&[0.]),
+            Tensor::of_slice::<f32>(&[2.]),
+        ];
+
+        let context = Arc::new(RwLock::new(PrivacyContext::new(
+            PrivacyBudget::NotPrivate,
+            4,
+        )));
+
+        // Both samples make up a single step.
+        for _ in 0..200 {
+            optimizer.zero_grad().unwrap();
+            for (i, (x, t)) in data.iter().zip(target.iter()).enumerate() {
+                let x = PrivacyGuard::new(x.copy(), BatchDependence::Dependent, context.clone());
+                let t = PrivacyGuard::new(t.copy(), BatchDependence::Dependent, context.clone());
+                let y = forward.forward(vec![x]).unwrap();
+                let loss = y
+                    .f_mse_loss(&t, (0.0, 10.0), tch::Reduction::Mean)
+                    .unwrap()
+                    .0;
+                loss.backward();
+                if i + 1 < data.len() {
+                    optimizer.accumulate_grad().unwrap();
+                }
+            }
+            optimizer.step().unwrap();
+        }
+        let w = &optimizer.parameters.into_inner().unwrap()[0];
+        assert!((w - Tensor::of_slice::<f32>(&[2.])).abs().double_value(&[]) < 0.1);
+    }
+
+    #[test]
+    fn lr_schedules() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+        let step = LrScheduler::Step {
+            step_size: 2,
+            gamma: 0.5,
+        };
+        let rates: Vec<_> = (0..5).map(|t| step.learning_rate(1.0, t)).collect();
+        assert_eq!(rates, vec![1.0, 1.0, 0.5, 0.5, 0.25]);
+
+        let multi_step = LrScheduler::MultiStep {
+            milestones: vec![1, 3],
+            gamma: 0.1,
+        };
+        assert!(close(multi_step.learning_rate(1.0, 0), 1.0));
+        assert!(close(multi_step.learning_rate(1.0, 2), 0.1));
+        assert!(close(multi_step.learning_rate(1.0, 3), 0.01));
+
+        let cosine = LrScheduler::Cosine {
+            t_max: 10,
+            eta_min: 0.1,
+        };
+        assert!(close(cosine.learning_rate(1.0, 0), 1.0));
+        assert!(close(cosine.learning_rate(1.0, 5), 0.55));
+        assert!(close(cosine.learning_rate(1.0, 10), 0.1));
+        assert!(close(cosine.learning_rate(1.0, 20), 0.1));
+
+        let one_cycle = LrScheduler::OneCycle {
+            max_lr: 1.0,
+            total_steps: 10,
+            pct_start: 0.5,
+            div_factor: 10.0,
+            final_div_factor: 100.0,
+        };
+        assert!(one_cycle.per_step());
+        assert!(close(one_cycle.learning_rate(0.5, 0), 0.1));
+        assert!(close(one_cycle.learning_rate(0.5, 4), 1.0));
+        assert!(close(one_cycle.learning_rate(0.5, 10), 0.001));
+        assert!(one_cycle.validate().is_ok());
+
+        let invalid = LrScheduler::Step {
+            step_size: 0,
+            gamma: 0.5,
+        };
+        assert!(invalid.validate().is_err());
+    }
+
     #[test]
     fn private_sgd() {
         let mut module = Module::load_from_file("lreg.pt", Device::Cpu).unwrap();
@@ -140,4 +232,32 @@ mod tests {
                 < 0.1
         );
     }
+
+    #[test]
+    fn holdout_charges_largest_expenditure() {
+        let inputs = Tensor::of_slice::<f32>(&[0., 1., 2., 3., 4., 5., 6., 7., 8., 9.]);
+        let labels = inputs.copy();
+        let dataset = Dataset::new(
+            vec![Arc::new(std::sync::Mutex::new(inputs))],
+            Arc::new(std::sync::Mutex::new(labels)),
+            10.0,
+        );
+        let holdout = dataset.holdout(0.3).unwrap();
+        assert_eq!(holdout.train.len(), 7);
+        assert_eq!(holdout.validation.len(), 3);
+
+        // Splits expend the whole remaining budget of the parts.
+        holdout.train.split(&[1], false).unwrap();
+        holdout.charge(&dataset);
+        assert_eq!(
+            dataset.privacy_context().expended(),
+            PrivacyBudget::Private(10.0)
+        );
+        holdout.validation.split(&[1], false).unwrap();
+        holdout.charge(&dataset);
+        assert_eq!(
+            dataset.privacy_context().expended(),
+            PrivacyBudget::Private(10.0)
+        );
+    }
 }
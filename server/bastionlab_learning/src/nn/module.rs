@@ -1,3 +1,4 @@
+use std::io::Write;
 use std::sync::{Arc, RwLock};
 
 use super::{LossType, Parameters};
@@ -137,10 +138,32 @@ impl Module {
             ),
         )
     }
+    /// Returns the name and shape of every parameter of the module, sorted by name.
+    pub fn parameter_shapes(&self) -> Vec<(String, Vec<i64>)> {
+        let mut shapes: Vec<_> = self
+            .var_store
+            .variables()
+            .into_iter()
+            .map(|(name, tensor)| (name, tensor.size()))
+            .collect();
+        shapes.sort();
+        shapes
+    }
+
     /// Moves all the parameters to the specified device.
     pub fn set_device(&mut self, device: Device) {
         self.var_store.set_device(device);
     }
+
+    /// Returns the parameters of the module under their PyTorch names, e.g. `fc1.weight`.
+    pub fn named_parameters(&self) -> Result<Vec<(String, Tensor)>, TchError> {
+        self.c_module.inner.named_parameters()
+    }
+
+    /// Writes the module with its current parameters as a TorchScript archive to `stream`.
+    pub fn save_to_stream<W: Write>(&self, stream: W) -> Result<(), TchError> {
+        self.c_module.inner.save_to_stream(stream)
+    }
 }
 
 impl TryFrom<SizedObjectsBytes> for Module {
@@ -208,7 +231,7 @@ impl CheckPoint {
     /// Creates a new checkpoint for a model and appends the current [`OptimizerStateType`] state.
     pub fn log_chkpt(
         &mut self,
-        chkpt_bytes: &Vec<u8>,
+        chkpt_bytes: &[u8],
         optim_state: OptimizerStateType,
     ) -> Result<(), TchError> {
         self.data.push(chkpt_bytes.to_vec());
@@ -216,6 +239,14 @@ impl CheckPoint {
         Ok(())
     }
 
+    /// Drops the oldest checkpoints so that at most `max_kept` remain, the latest one at least.
+    pub fn prune(&mut self, max_kept: usize) {
+        let excess = self.data.len().saturating_sub(max_kept.max(1));
+        self.data.drain(..excess);
+        let excess = self.optimizer_state.len().saturating_sub(max_kept.max(1));
+        self.optimizer_state.drain(..excess);
+    }
+
     /// Fetch latest checkpoint for a checkpoint object.
     pub fn get_chkpt(&self) -> (&Option<OptimizerStateType>, &[u8]) {
         let optimizer_state = &self.optimizer_state;
@@ -239,3 +270,71 @@ impl TryFrom<&Vec<u8>> for SizedObjectsBytes {
         Ok(object)
     }
 }
+
+/// Serializes a [`CheckPoint`] in the following format:
+///
+/// `[private: 1 byte | (weights, optimizer state)...]`
+///
+/// where the optimizer state is a tag object (`0` for none, `1` for SGD
+/// and `2` for Adam) followed by the statistics of the optimizer.
+impl TryFrom<&CheckPoint> for SizedObjectsBytes {
+    type Error = TchError;
+
+    fn try_from(value: &CheckPoint) -> Result<Self, Self::Error> {
+        let mut object = SizedObjectsBytes::new();
+        object.append_back(vec![value.private as u8]);
+        for (weights, state) in value.data.iter().zip(value.optimizer_state.iter()) {
+            object.append_back(weights.clone());
+            match state {
+                None => object.append_back(vec![0]),
+                Some(OptimizerStateType::SGD { statistics }) => {
+                    object.append_back(vec![1]);
+                    object.append_back(statistics.clone());
+                }
+                Some(OptimizerStateType::Adam { m, v, v_hat_max, t }) => {
+                    object.append_back(vec![2]);
+                    object.append_back(m.clone());
+                    object.append_back(v.clone());
+                    object.append_back(v_hat_max.clone());
+                    object.append_back(t.to_le_bytes().to_vec());
+                }
+            }
+        }
+        Ok(object)
+    }
+}
+
+impl TryFrom<SizedObjectsBytes> for CheckPoint {
+    type Error = TchError;
+
+    fn try_from(mut value: SizedObjectsBytes) -> Result<Self, Self::Error> {
+        let invalid = || TchError::FileFormat(String::from("Invalid checkpoint data."));
+        let header = value.next().ok_or_else(invalid)?;
+        let mut chkpt = CheckPoint::new(header.first().copied().ok_or_else(invalid)? != 0);
+        while let Some(weights) = value.next() {
+            let tag = value.next().ok_or_else(invalid)?;
+            let state = match tag[..] {
+                [0] => None,
+                [1] => Some(OptimizerStateType::SGD {
+                    statistics: value.next().ok_or_else(invalid)?,
+                }),
+                [2] => {
+                    let m = value.next().ok_or_else(invalid)?;
+                    let v = value.next().ok_or_else(invalid)?;
+                    let v_hat_max = value.next().ok_or_else(invalid)?;
+                    let t = value.next().ok_or_else(invalid)?;
+                    Some(OptimizerStateType::Adam {
+                        m,
+                        v,
+                        v_hat_max,
+                        t: i32::from_le_bytes(t[..].try_into().map_err(|_| invalid())?),
+                    })
+                }
+                _ => return Err(invalid()),
+            };
+            chkpt.data.push(weights);
+            chkpt.optimizer_state.push(state);
+        }
+        Ok(chkpt)
+    }
+}
@@ -21,6 +21,44 @@ fn save_to_stream(params: &HashMap<String, Tensor>) -> Result<Vec<u8>, TchError>
     Ok(stream.clone())
 }
 
+/// Returns true if none of the parameters is NaN or infinite.
+fn all_finite(params: &HashMap<String, Tensor>) -> Result<bool, TchError> {
+    for (_, p) in params.iter() {
+        if p.f_isfinite()?.f_all()?.f_int64_value(&[])? == 0 {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Returns the per-sample gradients of `parameters` clipped to `max_grad_norm` and summed
+/// over the batch, which requires expanded weights.
+fn clipped_grads(
+    parameters: &HashMap<String, Tensor>,
+    max_grad_norm: f32,
+) -> Result<HashMap<String, Tensor>, TchError> {
+    let mut per_param_norms = Vec::with_capacity(parameters.len());
+    for (_, param) in parameters.iter() {
+        let per_sample_grad = param.grad();
+        let dims: Vec<i64> = (1..per_sample_grad.dim()).map(|x| x as i64).collect();
+        per_param_norms.push(per_sample_grad.f_norm_scalaropt_dim(2, &dims, false)?);
+    }
+    let per_sample_norms =
+        Tensor::f_stack(&per_param_norms, 1).map_err(|e| TchError::Shape(format!("Failed to stack per-sample gradients, are you using a model with expanded weights? Initial error: {}", e)))?.f_norm_scalaropt_dim(2, &[1], false)?;
+    let max_grad_norm_t =
+        Tensor::of_slice(&[max_grad_norm]).f_to_device(per_sample_norms.device())?;
+    let per_sample_clip_factor = max_grad_norm_t
+        .f_div(&per_sample_norms.f_add_scalar(1e-6)?)?
+        .f_clamp(0., 1.)?;
+
+    let mut grads = HashMap::with_capacity(parameters.len());
+    for (name, param) in parameters.iter() {
+        let grad = Tensor::f_einsum("i,i...", &[&per_sample_clip_factor, &param.grad()], None)?;
+        grads.insert(name.clone(), grad);
+    }
+    Ok(grads)
+}
+
 /// Type of batch aggregation used by a loss function
 ///
 /// The `Mean` variant contains the number of samples in a batch.
@@ -33,6 +71,8 @@ pub enum LossType {
 /// Contains the trainable parameters of a model to be used by an optimizer
 ///
 /// The standard variant provides standard parameter update, the private variant performs DP-SGD.
+/// Gradients of several batches may be accumulated with [`Parameters::accumulate_grad`] before
+/// an update, which then uses their average.
 /// Note that the private variant requires the model to use expanded weights. In the Python API,
 /// layers with expanded weights may be found under `bastionlab.torch.psg.nn`. A standard model may also
 /// be turned into an expanded one using the `bastionlab.torch.psg.expand` function.
@@ -41,6 +81,8 @@ pub enum Parameters<'a> {
     Standard {
         parameters: HashMap<String, Tensor>,
         dp_sgd_context: Arc<RwLock<Option<DpSGDContext>>>,
+        /// Number of batches whose gradients were accumulated since the last update.
+        accumulated_batches: usize,
         _phantom: PhantomData<&'a mut Module>,
     },
     Private {
@@ -50,6 +92,9 @@ pub enum Parameters<'a> {
         loss_type: LossType,
         dp_sgd_context: Arc<RwLock<Option<DpSGDContext>>>,
         steps: usize,
+        /// Clipped gradients accumulated since the last update, summed over their samples.
+        accumulated_grads: HashMap<String, Tensor>,
+        accumulated_batches: usize,
         _phantom: PhantomData<&'a mut Module>,
     },
 }
@@ -63,6 +108,7 @@ impl<'a> Parameters<'a> {
         Parameters::Standard {
             parameters: vs.variables(),
             dp_sgd_context,
+            accumulated_batches: 0,
             _phantom: PhantomData,
         }
     }
@@ -86,6 +132,8 @@ impl<'a> Parameters<'a> {
             loss_type,
             dp_sgd_context,
             steps: 0,
+            accumulated_grads: HashMap::new(),
+            accumulated_batches: 0,
             _phantom: PhantomData,
         }
     }
@@ -116,6 +164,14 @@ impl<'a> Parameters<'a> {
         }
     }
 
+    /// Returns true if none of the contained parameters is NaN or infinite.
+    pub fn all_finite(&self) -> Result<bool, TchError> {
+        match self {
+            Parameters::Standard { parameters, .. } => all_finite(parameters),
+            Parameters::Private { parameters, .. } => all_finite(parameters),
+        }
+    }
+
     /// Returns the number of contained parameters.
     pub fn len(&self) -> usize {
         match self {
@@ -127,17 +183,67 @@ impl<'a> Parameters<'a> {
     /// Sets all accumulated gradients to zero.
     pub fn zero_grad(&mut self) {
         match self {
-            Parameters::Standard { parameters, .. } => {
+            Parameters::Standard {
+                parameters,
+                accumulated_batches,
+                ..
+            } => {
                 for (_, param) in parameters.iter_mut() {
                     param.zero_grad();
                 }
+                *accumulated_batches = 0;
             }
-            Parameters::Private { parameters, .. } => {
+            Parameters::Private {
+                parameters,
+                accumulated_grads,
+                accumulated_batches,
+                ..
+            } => {
                 for (_, param) in parameters.iter_mut() {
                     param.zero_grad();
                 }
+                accumulated_grads.clear();
+                *accumulated_batches = 0;
+            }
+        }
+    }
+
+    /// Keeps the gradients of the current batch for the next update rather than updating the
+    /// parameters with them.
+    ///
+    /// Standard gradients keep accumulating in the parameters. Private per-sample gradients
+    /// cannot, as the samples of different batches would share their rows, so they are
+    /// clipped and summed right away, the noise being added once by the update.
+    pub fn accumulate_grad(&mut self) -> Result<(), TchError> {
+        match self {
+            Parameters::Standard {
+                accumulated_batches,
+                ..
+            } => {
+                *accumulated_batches += 1;
             }
+            Parameters::Private {
+                parameters,
+                max_grad_norm,
+                accumulated_grads,
+                accumulated_batches,
+                ..
+            } => tch::no_grad(|| -> Result<(), TchError> {
+                for (name, grad) in clipped_grads(parameters, *max_grad_norm)? {
+                    let grad = match accumulated_grads.remove(&name) {
+                        Some(accumulated) => accumulated.f_add(&grad)?,
+                        None => grad,
+                    };
+                    accumulated_grads.insert(name, grad);
+                }
+                for (_, param) in parameters.iter_mut() {
+                    param.zero_grad();
+                }
+                *accumulated_batches += 1;
+                Ok(())
+            })?,
         }
+        Ok(())
     }
     /// Overrides model parameters with saved update.
     pub fn override_parameters(&mut self, params: Vec<(String, Tensor)>) -> Result<(), TchError> {
@@ -191,8 +297,10 @@ impl<'a> Parameters<'a> {
             Parameters::Standard {
                 parameters,
                 dp_sgd_context,
+                accumulated_batches,
                 ..
             } => tch::no_grad(|| {
+                let nb_batches = std::mem::take(accumulated_batches) + 1;
                 if !dp_sgd_context
                     .read()
                     .expect("Poisoned lock")
@@ -204,7 +312,11 @@ impl<'a> Parameters<'a> {
                     return Err(TchError::Kind(String::from("Privacy limit violation.")));
                 }
                 for (name, param) in parameters.iter_mut() {
-                    let update = update_fn(name, param, param.f_grad()?)?;
+                    let mut grad = param.f_grad()?;
+                    if nb_batches > 1 {
+                        grad = grad.f_div_scalar(nb_batches as f64)?;
+                    }
+                    let update = update_fn(name, param, grad)?;
                     let _ = param.f_sub_(&update)?;
                     dp_sgd_context
                         .write()
@@ -224,8 +336,11 @@ impl<'a> Parameters<'a> {
                 loss_type,
                 dp_sgd_context,
                 steps,
+                accumulated_grads,
+                accumulated_batches,
                 ..
             } => tch::no_grad(|| {
+                let nb_batches = std::mem::take(accumulated_batches) + 1;
                 let t = *steps as f32;
                 *steps += 1;
                 let delta = dp_sgd_context.read().unwrap().as_ref().unwrap().delta();
@@ -234,7 +349,8 @@ impl<'a> Parameters<'a> {
                     .unwrap()
                     .as_ref()
                     .unwrap()
-                    .batch_sampling_rate();
+                    .batch_sampling_rate()
+                    * nb_batches as f32;
                 let budget_update = *eps * batch_sampling_rate * ((t + 1.0).sqrt() - t.sqrt());
                 let sigma = compute_sigma(*eps, delta, *max_grad_norm) as f64;
 
@@ -249,34 +365,20 @@ impl<'a> Parameters<'a> {
                     return Err(TchError::Kind(String::from("Privacy limit violation.")));
                 }
 
-                let mut per_param_norms = Vec::with_capacity(parameters.len());
-                for (_, param) in parameters.iter() {
-                    let per_sample_grad = param.grad();
-                    let dims: Vec<i64> = (1..per_sample_grad.dim()).map(|x| x as i64).collect();
-                    per_param_norms.push(per_sample_grad.f_norm_scalaropt_dim(2, &dims, false)?);
-                }
-                let per_sample_norms =
-                    Tensor::f_stack(&per_param_norms, 1).map_err(|e| TchError::Shape(format!("Failed to stack per-sample gradients, are you using a model with expanded weights? Initial error: {}", e)))?.f_norm_scalaropt_dim(2, &[1], false)?;
-                let max_grad_norm_t = Tensor::of_slice(&[*max_grad_norm as f32])
-                    .f_to_device(per_sample_norms.device())?;
-                let per_sample_clip_factor = max_grad_norm_t
-                    .f_div(&per_sample_norms.f_add_scalar(1e-6)?)?
-                    .f_clamp(0., 1.)?;
-
+                let mut grads = clipped_grads(parameters, *max_grad_norm)?;
                 for (i, (name, param)) in parameters.iter_mut().enumerate() {
                     let per_sample_grad = param.grad();
                     let mut update_size = per_sample_grad.size();
                     update_size.remove(0);
-                    let grad = Tensor::f_einsum(
-                        "i,i...",
-                        &[&per_sample_clip_factor, &per_sample_grad],
-                        None,
-                    )?;
+                    let mut grad = grads.remove(name).unwrap();
+                    if let Some(accumulated) = accumulated_grads.remove(name) {
+                        grad = grad.f_add(&accumulated)?;
+                    }
                     let mut grad = grad
                         .f_add(&generate_noise_like(&grad, sigma)?)?
                         .f_view(&update_size[..])?;
                     if let LossType::Mean(batch_size) = loss_type {
-                        let _ = grad.f_div_scalar_(*batch_size)?;
+                        let _ = grad.f_div_scalar_(*batch_size * nb_batches as i64)?;
                     }
                     let update = update_fn(name, &param.i(0), grad)?;
                     let _ = param.i(0).f_sub_(&update)?;
@@ -44,7 +44,7 @@ impl<'a> Adam<'a> {
     }
     /// Restores an Optimizer to the latest training checkpoint with `optimizer_state` and
     pub fn load_from_checkpoint(
-        optimizer_state: &'a Option<OptimizerStateType>,
+        optimizer_state: &Option<OptimizerStateType>,
         weights: &[u8],
         learning_rate: f64,
         mut parameters: Parameters<'a>,
@@ -115,6 +115,10 @@ impl<'a> Optimizer for Adam<'a> {
         Ok(())
     }
 
+    fn accumulate_grad(&mut self) -> Result<(), TchError> {
+        self.parameters.accumulate_grad()
+    }
+
     fn step(&mut self) -> Result<(), TchError> {
         self.parameters.update(|name, x, mut grad| {
             if self.weight_decay != 0. {
@@ -212,6 +216,10 @@ impl<'a> Optimizer for Adam<'a> {
         self.parameters.into_bytes()
     }
 
+    fn parameters_finite(&self) -> Result<bool, TchError> {
+        self.parameters.all_finite()
+    }
+
     fn get_state(&mut self) -> Result<OptimizerStateType, TchError> {
         let m = stats_to_bytes(&self.m)?;
         let v = stats_to_bytes(&self.v)?;
@@ -223,4 +231,12 @@ impl<'a> Optimizer for Adam<'a> {
             t: self.t,
         })
     }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
 }
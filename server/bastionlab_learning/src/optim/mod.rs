@@ -6,6 +6,8 @@ use tch::Tensor;
 
 mod adam;
 mod optimizer;
+mod registry;
+mod scheduler;
 mod sgd;
 
 fn initialize_statistics() -> HashMap<String, Option<Tensor>> {
@@ -45,4 +47,9 @@ fn bytes_to_stats(bytes: &[u8]) -> Result<HashMap<String, Option<Tensor>>, TchEr
 pub use adam::Adam;
 pub use optimizer::Optimizer;
 pub use optimizer::OptimizerStateType;
+pub use registry::{
+    find_optimizer, OptimizerCheckpoint, OptimizerInfo, OptimizerParameters, ParameterKind,
+    ParameterSchema, ParameterValue, OPTIMIZERS,
+};
+pub use scheduler::LrScheduler;
 pub use sgd::SGD;
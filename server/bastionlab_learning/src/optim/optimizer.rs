@@ -20,10 +20,19 @@ pub enum OptimizerStateType {
 pub trait Optimizer {
     /// Sets the accumulated gradients of all trained parameters to zero.
     fn zero_grad(&mut self) -> Result<(), TchError>;
+    /// Keeps the gradients of the current batch for the next step, which then uses their
+    /// average over the batches accumulated.
+    fn accumulate_grad(&mut self) -> Result<(), TchError>;
     /// Performs a single training step using the accumulated gradients.
     fn step(&mut self) -> Result<(), TchError>;
     /// Returns contained parameters as [`Vec<u8>`].
     fn into_bytes(&mut self) -> Result<Vec<u8>, TchError>;
     /// Saves the latest state of the [`Optimizer`] as [`OptimizerStateType`]
     fn get_state(&mut self) -> Result<OptimizerStateType, TchError>;
+    /// Returns true if none of the trained parameters is NaN or infinite.
+    fn parameters_finite(&self) -> Result<bool, TchError>;
+    /// Returns the learning rate of the next steps.
+    fn learning_rate(&self) -> f64;
+    /// Sets the learning rate of the next steps, as learning rate schedulers do.
+    fn set_learning_rate(&mut self, learning_rate: f64);
 }
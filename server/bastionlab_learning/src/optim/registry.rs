@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+
+use super::{Adam, Optimizer, OptimizerStateType, SGD};
+use crate::nn::Parameters;
+use tch::TchError;
+
+/// Type of the value of an optimizer parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterKind {
+    Float,
+    Bool,
+}
+
+/// Value of an optimizer parameter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParameterValue {
+    Float(f64),
+    Bool(bool),
+}
+
+impl ParameterValue {
+    pub fn kind(&self) -> ParameterKind {
+        match self {
+            ParameterValue::Float(_) => ParameterKind::Float,
+            ParameterValue::Bool(_) => ParameterKind::Bool,
+        }
+    }
+}
+
+/// Description of a parameter an optimizer is configured with.
+#[derive(Debug, Clone, Copy)]
+pub struct ParameterSchema {
+    pub name: &'static str,
+    pub kind: ParameterKind,
+    /// `None` when the parameter has to be given.
+    pub default: Option<ParameterValue>,
+}
+
+/// Values of all the parameters of an optimizer, checked against its schema.
+#[derive(Debug, Clone)]
+pub struct OptimizerParameters(HashMap<&'static str, ParameterValue>);
+
+impl OptimizerParameters {
+    pub fn float(&self, name: &str) -> Result<f64, TchError> {
+        match self.0.get(name) {
+            Some(ParameterValue::Float(value)) => Ok(*value),
+            _ => Err(TchError::Kind(format!("Missing float parameter {}", name))),
+        }
+    }
+
+    pub fn bool(&self, name: &str) -> Result<bool, TchError> {
+        match self.0.get(name) {
+            Some(ParameterValue::Bool(value)) => Ok(*value),
+            _ => Err(TchError::Kind(format!(
+                "Missing boolean parameter {}",
+                name
+            ))),
+        }
+    }
+}
+
+/// Checkpointed state of the optimizer and weights of the model a training resumes from.
+pub type OptimizerCheckpoint<'c> = (&'c Option<OptimizerStateType>, &'c [u8]);
+
+type Builder = for<'a> fn(
+    Parameters<'a>,
+    &OptimizerParameters,
+    Option<OptimizerCheckpoint>,
+) -> Result<Box<dyn Optimizer + 'a>, TchError>;
+
+/// Description of an optimizer, of the parameters it is configured with and of how to
+/// build it.
+///
+/// New optimizers only need an entry in [`OPTIMIZERS`] to be usable by trainings.
+#[derive(Clone, Copy)]
+pub struct OptimizerInfo {
+    pub name: &'static str,
+    pub schema: &'static [ParameterSchema],
+    builder: Builder,
+}
+
+impl OptimizerInfo {
+    /// Checks `values` against the schema of the optimizer and fills in the defaults
+    /// of the parameters that are not given.
+    pub fn resolve(
+        &self,
+        values: &HashMap<String, ParameterValue>,
+    ) -> Result<OptimizerParameters, TchError> {
+        if let Some(name) = values
+            .keys()
+            .find(|name| self.schema.iter().all(|param| param.name != name.as_str()))
+        {
+            return Err(TchError::Kind(format!(
+                "Unknown parameter {} for optimizer {}",
+                name, self.name
+            )));
+        }
+        let mut resolved = HashMap::new();
+        for param in self.schema {
+            let value = values
+                .get(param.name)
+                .copied()
+                .or(param.default)
+                .ok_or_else(|| {
+                    TchError::Kind(format!(
+                        "Missing parameter {} for optimizer {}",
+                        param.name, self.name
+                    ))
+                })?;
+            if value.kind() != param.kind {
+                return Err(TchError::Kind(format!(
+                    "Parameter {} of optimizer {} must be a {}",
+                    param.name,
+                    self.name,
+                    match param.kind {
+                        ParameterKind::Float => "float",
+                        ParameterKind::Bool => "boolean",
+                    }
+                )));
+            }
+            if let ParameterValue::Float(f) = value {
+                if !f.is_finite() {
+                    return Err(TchError::Kind(format!(
+                        "Parameter {} of optimizer {} must be finite",
+                        param.name, self.name
+                    )));
+                }
+            }
+            resolved.insert(param.name, value);
+        }
+        Ok(OptimizerParameters(resolved))
+    }
+
+    /// Returns the optimizer of `parameters` configured with `values`, restored from
+    /// `checkpoint` if any.
+    pub fn build<'a>(
+        &self,
+        parameters: Parameters<'a>,
+        values: &OptimizerParameters,
+        checkpoint: Option<OptimizerCheckpoint>,
+    ) -> Result<Box<dyn Optimizer + 'a>, TchError> {
+        (self.builder)(parameters, values, checkpoint)
+    }
+}
+
+const fn float(name: &'static str, default: Option<f64>) -> ParameterSchema {
+    ParameterSchema {
+        name,
+        kind: ParameterKind::Float,
+        default: match default {
+            Some(value) => Some(ParameterValue::Float(value)),
+            None => None,
+        },
+    }
+}
+
+const fn boolean(name: &'static str, default: bool) -> ParameterSchema {
+    ParameterSchema {
+        name,
+        kind: ParameterKind::Bool,
+        default: Some(ParameterValue::Bool(default)),
+    }
+}
+
+/// Every optimizer that trainings can use.
+pub const OPTIMIZERS: &[OptimizerInfo] = &[
+    OptimizerInfo {
+        name: "SGD",
+        schema: &[
+            float("learning_rate", None),
+            float("weight_decay", Some(0.)),
+            float("momentum", Some(0.)),
+            float("dampening", Some(0.)),
+            boolean("nesterov", false),
+        ],
+        builder: build_sgd,
+    },
+    OptimizerInfo {
+        name: "Adam",
+        schema: &[
+            float("learning_rate", Some(1e-3)),
+            float("beta_1", Some(0.9)),
+            float("beta_2", Some(0.999)),
+            float("epsilon", Some(1e-8)),
+            float("weight_decay", Some(0.)),
+            boolean("amsgrad", false),
+        ],
+        builder: build_adam,
+    },
+];
+
+/// Returns the optimizer called `name`, ignoring case.
+pub fn find_optimizer(name: &str) -> Option<&'static OptimizerInfo> {
+    OPTIMIZERS
+        .iter()
+        .find(|info| info.name.eq_ignore_ascii_case(name))
+}
+
+fn build_sgd<'a>(
+    parameters: Parameters<'a>,
+    values: &OptimizerParameters,
+    checkpoint: Option<OptimizerCheckpoint>,
+) -> Result<Box<dyn Optimizer + 'a>, TchError> {
+    let learning_rate = values.float("learning_rate")?;
+    let sgd = match checkpoint {
+        Some((state, weights)) => {
+            SGD::load_from_checkpoint(state, weights, learning_rate, parameters)?
+        }
+        None => SGD::new(parameters, learning_rate),
+    };
+    Ok(Box::new(
+        sgd.weight_decay(values.float("weight_decay")?)
+            .momentum(values.float("momentum")?)
+            .dampening(values.float("dampening")?)
+            .nesterov(values.bool("nesterov")?),
+    ))
+}
+
+fn build_adam<'a>(
+    parameters: Parameters<'a>,
+    values: &OptimizerParameters,
+    checkpoint: Option<OptimizerCheckpoint>,
+) -> Result<Box<dyn Optimizer + 'a>, TchError> {
+    let learning_rate = values.float("learning_rate")?;
+    let adam = match checkpoint {
+        Some((state, weights)) => {
+            Adam::load_from_checkpoint(state, weights, learning_rate, parameters)?
+        }
+        None => Adam::new(parameters, learning_rate),
+    };
+    Ok(Box::new(
+        adam.beta_1(values.float("beta_1")?)
+            .beta_2(values.float("beta_2")?)
+            .epsilon(values.float("epsilon")?)
+            .weight_decay(values.float("weight_decay")?)
+            .amsgrad(values.bool("amsgrad")?),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(values: &[(&str, ParameterValue)]) -> HashMap<String, ParameterValue> {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect()
+    }
+
+    fn resolve_error(optimizer: &str, given: &[(&str, ParameterValue)]) -> String {
+        match find_optimizer(optimizer).unwrap().resolve(&values(given)) {
+            Err(TchError::Kind(message)) => message,
+            res => panic!("Expected a parameter error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn optimizers_are_found_ignoring_case() {
+        assert_eq!(find_optimizer("sgd").unwrap().name, "SGD");
+        assert_eq!(find_optimizer("ADAM").unwrap().name, "Adam");
+        assert!(find_optimizer("rmsprop").is_none());
+    }
+
+    #[test]
+    fn missing_parameters_get_their_default() {
+        let resolved = find_optimizer("SGD")
+            .unwrap()
+            .resolve(&values(&[
+                ("learning_rate", ParameterValue::Float(0.1)),
+                ("nesterov", ParameterValue::Bool(true)),
+            ]))
+            .unwrap();
+        assert_eq!(resolved.float("learning_rate").unwrap(), 0.1);
+        assert_eq!(resolved.float("momentum").unwrap(), 0.);
+        assert!(resolved.bool("nesterov").unwrap());
+
+        let resolved = find_optimizer("Adam")
+            .unwrap()
+            .resolve(&HashMap::new())
+            .unwrap();
+        assert_eq!(resolved.float("learning_rate").unwrap(), 1e-3);
+        assert!(!resolved.bool("amsgrad").unwrap());
+    }
+
+    #[test]
+    fn unknown_parameters_are_rejected() {
+        let message = resolve_error("Adam", &[("nesterov", ParameterValue::Bool(true))]);
+        assert_eq!(message, "Unknown parameter nesterov for optimizer Adam");
+    }
+
+    #[test]
+    fn parameters_without_default_are_required() {
+        let message = resolve_error("SGD", &[("momentum", ParameterValue::Float(0.9))]);
+        assert_eq!(message, "Missing parameter learning_rate for optimizer SGD");
+    }
+
+    #[test]
+    fn mistyped_parameters_are_rejected() {
+        let message = resolve_error(
+            "SGD",
+            &[
+                ("learning_rate", ParameterValue::Float(0.1)),
+                ("nesterov", ParameterValue::Float(1.)),
+            ],
+        );
+        assert_eq!(
+            message,
+            "Parameter nesterov of optimizer SGD must be a boolean"
+        );
+
+        let message = resolve_error("Adam", &[("learning_rate", ParameterValue::Bool(true))]);
+        assert_eq!(
+            message,
+            "Parameter learning_rate of optimizer Adam must be a float"
+        );
+    }
+
+    #[test]
+    fn non_finite_parameters_are_rejected() {
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let message = resolve_error("SGD", &[("learning_rate", ParameterValue::Float(value))]);
+            assert_eq!(
+                message,
+                "Parameter learning_rate of optimizer SGD must be finite"
+            );
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::f64::consts::PI;
+
+use tch::TchError;
+
+/// Learning rate schedule of a training, updating the learning rate of the optimizer every
+/// epoch, or every step for [`LrScheduler::OneCycle`].
+///
+/// The schedules are reimplementations of Pytorch's [schedulers] in their closed form.
+///
+/// [schedulers]: https://pytorch.org/docs/stable/optim.html#how-to-adjust-learning-rate
+#[derive(Debug, Clone, PartialEq)]
+pub enum LrScheduler {
+    /// Multiplies the learning rate by `gamma` every `step_size` epochs.
+    Step { step_size: usize, gamma: f64 },
+    /// Multiplies the learning rate by `gamma` at every epoch of `milestones`.
+    MultiStep { milestones: Vec<usize>, gamma: f64 },
+    /// Anneals the learning rate down to `eta_min` along a cosine over `t_max` epochs, after
+    /// which it stays at `eta_min`.
+    Cosine { t_max: usize, eta_min: f64 },
+    /// Raises the learning rate from `max_lr / div_factor` to `max_lr` over the first
+    /// `pct_start` of the `total_steps` steps, then anneals it down to
+    /// `max_lr / (div_factor * final_div_factor)`, both along a cosine. The learning rate of
+    /// the optimizer is ignored.
+    OneCycle {
+        max_lr: f64,
+        total_steps: usize,
+        pct_start: f64,
+        div_factor: f64,
+        final_div_factor: f64,
+    },
+}
+
+impl LrScheduler {
+    /// Fails when the parameters of the schedule are out of range.
+    pub fn validate(&self) -> Result<(), TchError> {
+        let positive = |name: &str, value: f64| {
+            if value.is_finite() && value > 0. {
+                Ok(())
+            } else {
+                Err(TchError::Kind(format!(
+                    "Learning rate scheduler parameter {} must be positive",
+                    name
+                )))
+            }
+        };
+        match self {
+            LrScheduler::Step { step_size, gamma } => {
+                positive("step_size", *step_size as f64)?;
+                positive("gamma", *gamma)
+            }
+            LrScheduler::MultiStep { gamma, .. } => positive("gamma", *gamma),
+            LrScheduler::Cosine { t_max, eta_min } => {
+                positive("t_max", *t_max as f64)?;
+                if eta_min.is_finite() && *eta_min >= 0. {
+                    Ok(())
+                } else {
+                    Err(TchError::Kind(String::from(
+                        "Learning rate scheduler parameter eta_min must not be negative",
+                    )))
+                }
+            }
+            LrScheduler::OneCycle {
+                max_lr,
+                total_steps,
+                pct_start,
+                div_factor,
+                final_div_factor,
+            } => {
+                positive("max_lr", *max_lr)?;
+                positive("total_steps", *total_steps as f64)?;
+                positive("div_factor", *div_factor)?;
+                positive("final_div_factor", *final_div_factor)?;
+                if *pct_start > 0. && *pct_start < 1. {
+                    Ok(())
+                } else {
+                    Err(TchError::Kind(String::from(
+                        "Learning rate scheduler parameter pct_start must be between 0 and 1",
+                    )))
+                }
+            }
+        }
+    }
+
+    /// Whether the learning rate is updated every step rather than every epoch.
+    pub fn per_step(&self) -> bool {
+        matches!(self, LrScheduler::OneCycle { .. })
+    }
+
+    /// Returns the learning rate of epoch `t`, or of step `t` if [`LrScheduler::per_step`],
+    /// both counted from 0, given the learning rate `base_lr` the optimizer started with.
+    pub fn learning_rate(&self, base_lr: f64, t: usize) -> f64 {
+        match self {
+            LrScheduler::Step { step_size, gamma } => {
+                base_lr * gamma.powi((t / (*step_size).max(1)) as i32)
+            }
+            LrScheduler::MultiStep { milestones, gamma } => {
+                let passed = milestones.iter().filter(|&&m| m <= t).count();
+                base_lr * gamma.powi(passed as i32)
+            }
+            LrScheduler::Cosine { t_max, eta_min } => {
+                let progress = t.min(*t_max) as f64 / (*t_max).max(1) as f64;
+                cosine_annealing(base_lr, *eta_min, progress)
+            }
+            LrScheduler::OneCycle {
+                max_lr,
+                total_steps,
+                pct_start,
+                div_factor,
+                final_div_factor,
+            } => {
+                let initial_lr = max_lr / div_factor;
+                let min_lr = initial_lr / final_div_factor;
+                let last_step = total_steps.saturating_sub(1) as f64;
+                let peak = (pct_start * *total_steps as f64 - 1.).max(0.);
+                let t = (t as f64).min(last_step);
+                if t <= peak {
+                    let progress = if peak > 0. { t / peak } else { 1. };
+                    cosine_annealing(initial_lr, *max_lr, progress)
+                } else {
+                    let progress = (t - peak) / (last_step - peak);
+                    cosine_annealing(*max_lr, min_lr, progress)
+                }
+            }
+        }
+    }
+}
+
+/// Goes from `start` to `end` along half a cosine as `progress` goes from 0 to 1.
+fn cosine_annealing(start: f64, end: f64, progress: f64) -> f64 {
+    end + (start - end) * (1. + (PI * progress).cos()) / 2.
+}
@@ -97,6 +97,10 @@ impl<'a> Optimizer for SGD<'a> {
         Ok(())
     }
 
+    fn accumulate_grad(&mut self) -> Result<(), TchError> {
+        self.parameters.accumulate_grad()
+    }
+
     fn step(&mut self) -> Result<(), TchError> {
         self.parameters.update(|name, x, mut grad| {
             if self.weight_decay != 0. {
@@ -143,8 +147,20 @@ impl<'a> Optimizer for SGD<'a> {
     fn into_bytes(&mut self) -> Result<Vec<u8>, TchError> {
         self.parameters.into_bytes()
     }
+
+    fn parameters_finite(&self) -> Result<bool, TchError> {
+        self.parameters.all_finite()
+    }
     fn get_state(&mut self) -> Result<OptimizerStateType, TchError> {
         let statistics = stats_to_bytes(&self.statistics)?;
         Ok(OptimizerStateType::SGD { statistics })
     }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
 }
@@ -1,7 +1,7 @@
 use crate::data::privacy_guard::{PrivacyBudget, PrivacyGuard};
 use crate::data::{Dataset, DatasetIter};
 use crate::nn::{CheckPoint, Forward};
-use crate::optim::Optimizer;
+use crate::optim::{LrScheduler, Optimizer, OptimizerStateType};
 use tch::{Device, Kind, TchError, Tensor};
 
 fn inputs_to_device(
@@ -15,6 +15,99 @@ fn inputs_to_device(
     Ok(inputs_)
 }
 
+/// A trigger set embedded into a model at the end of its training, so that the model
+/// can later be recognized by its predictions on the trigger set.
+pub struct Watermark<'a> {
+    pub trigger_set: &'a Dataset,
+    /// Number of passes over the trigger set.
+    pub epochs: usize,
+}
+
+/// Stops a training once the metric it monitors has not improved for `patience` epochs,
+/// keeping the weights of the best epoch.
+pub struct EarlyStopping {
+    patience: usize,
+    /// Metric computed along with the training metric, with the budget of its value at the
+    /// end of every epoch. The training metric is monitored if `None`.
+    metric: Option<(Metric, PrivacyBudget)>,
+    higher_is_better: bool,
+    best: Option<f32>,
+    stale_epochs: usize,
+    /// Weights and optimizer state at the end of the best epoch.
+    best_checkpoint: Option<(Vec<u8>, OptimizerStateType)>,
+}
+
+impl EarlyStopping {
+    /// Monitors the training metric, which is to be minimized, or its value on the validation
+    /// samples of trainers that have some.
+    pub fn new(patience: usize) -> Self {
+        EarlyStopping {
+            patience,
+            metric: None,
+            higher_is_better: false,
+            best: None,
+            stale_epochs: 0,
+            best_checkpoint: None,
+        }
+    }
+
+    /// Monitors `metric` instead, computed on the training batches, whose average over every
+    /// epoch is disclosed to the trainer with `budget`.
+    pub fn with_metric(mut self, metric: Metric, budget: PrivacyBudget, is_loss: bool) -> Self {
+        self.metric = Some((metric, budget));
+        self.higher_is_better = !is_loss;
+        self
+    }
+}
+
+/// Runs `forward` over every sample of `dataset` and returns a dataset of its outputs
+/// with the same labels, whose privacy budget is expended from `dataset`'s.
+///
+/// Outputs are computed once, without gradients, so that heads can be trained on the
+/// outputs of a frozen backbone without running the backbone again.
+pub fn cache_activations(
+    forward: &Forward,
+    dataset: &Dataset,
+    batch_size: usize,
+    device: Device,
+) -> Result<Dataset, TchError> {
+    let nb_samples = dataset.len() as i64;
+    if nb_samples == 0 || batch_size == 0 {
+        return Err(TchError::Kind(String::from(
+            "Caching activations requires a non-empty dataset and batches",
+        )));
+    }
+    let _no_grad = tch::no_grad_guard();
+    let inputs: Vec<Tensor> = dataset
+        .samples_inputs
+        .iter()
+        .map(|input| input.lock().unwrap().shallow_clone())
+        .collect();
+    let mut outputs = Vec::new();
+    let mut start = 0;
+    while start < nb_samples {
+        let size = (batch_size as i64).min(nb_samples - start);
+        let mut batch = Vec::with_capacity(inputs.len());
+        for input in inputs.iter() {
+            batch.push(input.f_narrow(0, start, size)?.f_to(device)?);
+        }
+        outputs.push(forward.forward_inner(&batch)?.f_to(Device::Cpu)?);
+        start += size;
+    }
+    Ok(dataset.derive(vec![Tensor::f_cat(&outputs, 0)?]))
+}
+
+fn batch_bytes(
+    inputs: &[PrivacyGuard<Tensor>],
+    labels: &PrivacyGuard<Tensor>,
+) -> Result<u64, TchError> {
+    inputs
+        .iter()
+        .chain(std::iter::once(labels))
+        .map(|tensor| tensor.size_in_bytes())
+        .sum()
+}
+
 /// A basic parametrizable loop for training a model.
 ///
 /// This struct implements [`std::Iter::Iterator`] and yields
@@ -28,11 +121,33 @@ pub struct Trainer<'a> {
     device: Device,
     epochs: usize,
     batch_size: usize,
-    dataloader: std::iter::Enumerate<DatasetIter<'a>>,
+    /// Batches of the current epoch, sampled once the epoch starts.
+    dataloader: Option<std::iter::Enumerate<DatasetIter<'a>>>,
+    stratified: bool,
     current_epoch: usize,
     chkpt: &'a mut CheckPoint,
     per_n_epochs_chkpt: i32,
     per_n_steps_chkpt: i32,
+    /// Number of checkpoints kept, older ones being dropped. All of them if `None`.
+    max_chkpts: Option<usize>,
+    watermark: Option<Watermark<'a>>,
+    /// Learning rate schedule, with the learning rate the optimizer started with.
+    lr_scheduler: Option<(LrScheduler, f64)>,
+    early_stopping: Option<EarlyStopping>,
+    /// Held-out samples the model is evaluated on at the end of every epoch, with the
+    /// metric and the budget of its value.
+    validation: Option<(&'a Dataset, Metric, PrivacyBudget)>,
+    /// Value and standard deviation of the validation metric at the end of the last epoch.
+    last_validation: Option<(f32, f32)>,
+    /// Number of batches whose gradients make up every step.
+    accumulation_steps: usize,
+    /// Number of batches trained on since the last step.
+    accumulated: usize,
+    /// Value of the training metric last disclosed, the average over the epoch so far.
+    last_value: f32,
+    /// Number of optimizer steps taken so far, over every epoch.
+    steps: usize,
+    bytes_read: u64,
 }
 
 impl<'a> Trainer<'a> {
@@ -58,28 +173,226 @@ impl<'a> Trainer<'a> {
             device,
             epochs,
             batch_size,
-            dataloader: dataset.iter_shuffle(batch_size).enumerate(),
+            dataloader: None,
+            stratified: false,
             current_epoch: 0,
             chkpt,
             per_n_epochs_chkpt,
             per_n_steps_chkpt,
+            max_chkpts: None,
+            watermark: None,
+            lr_scheduler: None,
+            early_stopping: None,
+            validation: None,
+            last_validation: None,
+            accumulation_steps: 1,
+            accumulated: 0,
+            last_value: 0.0,
+            steps: 0,
+            bytes_read: 0,
+        }
+    }
+
+    /// Updates the learning rate of the optimizer along `scheduler`, starting from its
+    /// current learning rate.
+    pub fn with_lr_scheduler(mut self, scheduler: LrScheduler) -> Self {
+        let base_lr = self.optimizer.learning_rate();
+        self.lr_scheduler = Some((scheduler, base_lr));
+        self
+    }
+
+    /// Embeds `watermark` once every epoch on the dataset is over.
+    ///
+    /// The watermark is embedded after the last epoch rather than along with it, so that
+    /// its steps do not interfere with the privacy accounting of the dataset's steps. No
+    /// checkpoint is kept before then, as its weights would not hold the watermark.
+    pub fn with_watermark(mut self, watermark: Watermark<'a>) -> Self {
+        self.watermark = Some(watermark);
+        self
+    }
+
+    /// Samples batches that approximately preserve the proportion of every class of the
+    /// dataset rather than uniformly, which requires integer class labels.
+    pub fn with_stratified_batches(mut self) -> Self {
+        self.stratified = true;
+        self
+    }
+
+    /// Stops the training along `early_stopping`, and checkpoints the weights of the best
+    /// epoch at the end of the training instead of the last ones.
+    ///
+    /// Watermarked trainings still checkpoint the last weights, which hold the watermark.
+    pub fn with_early_stopping(mut self, early_stopping: EarlyStopping) -> Self {
+        self.early_stopping = Some(early_stopping);
+        self
+    }
+
+    /// Evaluates the model with `metric` on the held-out samples of `dataset` at the end of
+    /// every epoch, disclosing the value with `budget`. Early stopping then monitors this
+    /// value rather than the training metric, unless it monitors another metric.
+    pub fn with_validation(
+        mut self,
+        dataset: &'a Dataset,
+        metric: Metric,
+        budget: PrivacyBudget,
+    ) -> Self {
+        self.validation = Some((dataset, metric, budget));
+        self
+    }
+
+    /// Keeps only the `max_kept` latest checkpoints of the model, so that periodic
+    /// checkpoints of long trainings do not fill memory.
+    pub fn with_max_checkpoints(mut self, max_kept: usize) -> Self {
+        self.max_chkpts = Some(max_kept);
+        self
+    }
+
+    /// Accumulates the gradients of `steps` batches before every step of the optimizer, so
+    /// that steps use batches larger than fit in memory. The last step of an epoch may
+    /// accumulate fewer batches.
+    pub fn with_accumulation_steps(mut self, steps: usize) -> Self {
+        self.accumulation_steps = steps.max(1);
+        self
+    }
+
+    /// Updates the early stopping with the epoch that just ended. Returns whether the
+    /// training should stop.
+    fn end_epoch(&mut self) -> Result<bool, TchError> {
+        let early_stopping = match &mut self.early_stopping {
+            Some(early_stopping) => early_stopping,
+            None => return Ok(false),
+        };
+        let value = match &mut early_stopping.metric {
+            Some((metric, budget)) => {
+                let (value, _) = metric.value(*budget)?;
+                metric.reset();
+                value
+            }
+            // Already disclosed, so that monitoring it expends no more budget.
+            None => self
+                .last_validation
+                .map(|(value, _)| value)
+                .unwrap_or(self.last_value),
+        };
+        let improved = match early_stopping.best {
+            Some(best) if early_stopping.higher_is_better => value > best,
+            Some(best) => value < best,
+            None => true,
+        };
+        if improved {
+            early_stopping.best = Some(value);
+            early_stopping.stale_epochs = 0;
+            let weights = self.optimizer.into_bytes()?;
+            let state = self.optimizer.get_state()?;
+            early_stopping.best_checkpoint = Some((weights, state));
+            Ok(false)
+        } else {
+            early_stopping.stale_epochs += 1;
+            Ok(early_stopping.stale_epochs >= early_stopping.patience)
         }
     }
 
+    /// Evaluates the model on the validation samples, if any.
+    fn validate(&mut self) -> Result<(), TchError> {
+        let (dataset, metric, budget) = match &mut self.validation {
+            Some(validation) => validation,
+            None => return Ok(()),
+        };
+        let batch_size = self.batch_size.min(dataset.len());
+        let forward = &self.forward;
+        let device = self.device;
+        let bytes_read = tch::no_grad(|| -> Result<u64, TchError> {
+            let mut bytes_read = 0;
+            for (inputs, labels) in dataset.iter_shuffle(batch_size) {
+                bytes_read += batch_bytes(&inputs, &labels)?;
+                let inputs = inputs_to_device(inputs, device)?;
+                let labels = labels.f_to(device)?;
+                let outputs = forward.forward(inputs)?;
+                metric.compute(&outputs, &labels)?;
+            }
+            Ok(bytes_read)
+        })?;
+        self.bytes_read += bytes_read;
+        self.last_validation = Some(metric.value(*budget)?);
+        metric.reset();
+        Ok(())
+    }
+
+    fn epoch_batches(&self) -> Result<std::iter::Enumerate<DatasetIter<'a>>, TchError> {
+        let batches = if self.stratified {
+            self.dataset.iter_stratified(self.batch_size)?
+        } else {
+            self.dataset.iter_shuffle(self.batch_size)
+        };
+        Ok(batches.enumerate())
+    }
+
+    fn embed_watermark(&mut self) -> Result<(), TchError> {
+        let watermark = match self.watermark.take() {
+            Some(watermark) => watermark,
+            None => return Ok(()),
+        };
+        let batch_size = self.batch_size.min(watermark.trigger_set.len());
+        for _ in 0..watermark.epochs {
+            for (inputs, labels) in watermark.trigger_set.iter_shuffle(batch_size) {
+                self.bytes_read += batch_bytes(&inputs, &labels)?;
+                let inputs = inputs_to_device(inputs, self.device)?;
+                let labels = labels.f_to(self.device)?;
+                let outputs = self.forward.forward(inputs)?;
+                let loss = self.metric.compute(&outputs, &labels)?;
+                self.optimizer.zero_grad()?;
+                loss.backward();
+                self.optimizer.step()?;
+            }
+        }
+        self.metric.reset();
+        Ok(())
+    }
+
     pub fn train_on_batch(
         &mut self,
         i: usize,
         inputs: Vec<PrivacyGuard<Tensor>>,
         labels: PrivacyGuard<Tensor>,
     ) -> Result<(i32, i32, f32, f32), TchError> {
+        self.bytes_read += batch_bytes(&inputs, &labels)?;
         let inputs = inputs_to_device(inputs, self.device)?;
         let labels = labels.f_to(self.device)?;
         let outputs = self.forward.forward(inputs)?;
         let loss = self.metric.compute(&outputs, &labels)?;
-        self.optimizer.zero_grad()?;
+        if let Some((metric, _)) = self
+            .early_stopping
+            .as_mut()
+            .and_then(|early_stopping| early_stopping.metric.as_mut())
+        {
+            metric.compute(&outputs, &labels)?;
+        }
+        if self.accumulated == 0 {
+            if let Some((scheduler, base_lr)) = &self.lr_scheduler {
+                let t = if scheduler.per_step() {
+                    self.steps
+                } else {
+                    self.current_epoch
+                };
+                self.optimizer
+                    .set_learning_rate(scheduler.learning_rate(*base_lr, t));
+            }
+            self.optimizer.zero_grad()?;
+        }
         loss.backward();
-        self.optimizer.step()?;
+        self.accumulated += 1;
+        if self.accumulated == self.accumulation_steps || i + 1 == self.nb_batches() {
+            self.steps += 1;
+            self.accumulated = 0;
+            self.optimizer.step()?;
+        } else {
+            self.optimizer.accumulate_grad()?;
+        }
+        if i + 1 == self.nb_batches() {
+            self.validate()?;
+        }
         let (value, std) = self.metric.value(self.metric_budget)?;
+        self.last_value = value;
         Ok((self.current_epoch as i32, i as i32, value, std))
     }
 
@@ -87,14 +400,42 @@ impl<'a> Trainer<'a> {
         self.epochs
     }
 
+    /// Returns true if none of the trained weights is NaN or infinite.
+    pub fn weights_finite(&self) -> Result<bool, TchError> {
+        self.optimizer.parameters_finite()
+    }
+
     pub fn nb_batches(&self) -> usize {
         self.dataset.len() / self.batch_size
     }
 
-    fn checkpoint(&mut self) -> Result<(), TchError> {
+    /// Returns the number of bytes of samples trained on, or validated on, so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Returns the value and standard deviation of the validation metric at the end of the
+    /// last epoch, if any.
+    pub fn last_validation(&self) -> Option<(f32, f32)> {
+        self.last_validation
+    }
+
+    /// Saves the current weights and optimizer state to the checkpoint.
+    pub fn checkpoint(&mut self) -> Result<(), TchError> {
         let params = self.optimizer.into_bytes()?; // Fix later with more detailed errors.
         let optim_state = self.optimizer.get_state()?;
-        self.chkpt.log_chkpt(&params, optim_state)?;
+        self.log_checkpoint(&params, optim_state)
+    }
+
+    fn log_checkpoint(
+        &mut self,
+        weights: &[u8],
+        optim_state: OptimizerStateType,
+    ) -> Result<(), TchError> {
+        self.chkpt.log_chkpt(weights, optim_state)?;
+        if let Some(max_kept) = self.max_chkpts {
+            self.chkpt.prune(max_kept);
+        }
         Ok(())
     }
 }
@@ -103,32 +444,66 @@ impl<'a> Iterator for Trainer<'a> {
     type Item = Result<(i32, i32, f32, f32), TchError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some((i, (inputs, labels))) = self.dataloader.next() {
+        if self.dataloader.is_none() {
+            match self.epoch_batches() {
+                Ok(batches) => self.dataloader = Some(batches),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        let batch = self.dataloader.as_mut().and_then(|batches| batches.next());
+        if let Some((i, (inputs, labels))) = batch {
             let v = Some(self.train_on_batch(i, inputs, labels));
 
             // Per n-step checkpointing.
-            if self.per_n_steps_chkpt > 0 && i % self.per_n_steps_chkpt as usize == 0 {
+            if self.per_n_steps_chkpt > 0
+                && i % self.per_n_steps_chkpt as usize == 0
+                && self.watermark.is_none()
+            {
                 self.checkpoint().unwrap()
             }
             v
         } else {
             self.current_epoch += 1;
             self.metric.reset();
-            if self.current_epoch < self.epochs {
-                self.dataloader = self.dataset.iter_shuffle(self.batch_size).enumerate();
+            let stop = match self.end_epoch() {
+                Ok(stop) => stop,
+                Err(e) => return Some(Err(e)),
+            };
+            if self.current_epoch < self.epochs && !stop {
+                self.dataloader = None;
                 let v = self.next();
 
                 // Per n-epoch checkpointing.
                 if self.per_n_epochs_chkpt > 0
                     && self.current_epoch % self.per_n_epochs_chkpt as usize == 0
+                    && self.watermark.is_none()
                 {
                     self.checkpoint().unwrap()
                 }
                 v
             } else {
-                // Default checkpointing.
-                if self.per_n_epochs_chkpt == 0 && self.per_n_steps_chkpt == 0 {
-                    self.checkpoint().unwrap()
+                let watermarked = self.watermark.is_some();
+                if let Err(e) = self.embed_watermark() {
+                    return Some(Err(e));
+                }
+                let best = self
+                    .early_stopping
+                    .as_mut()
+                    .and_then(|early_stopping| early_stopping.best_checkpoint.take());
+                match best {
+                    // The best weights become the last checkpoint, from which models are read.
+                    Some((weights, state)) if !watermarked => {
+                        if let Err(e) = self.log_checkpoint(&weights, state) {
+                            return Some(Err(e));
+                        }
+                    }
+                    // Default checkpointing. Watermarked weights are always checkpointed.
+                    _ if watermarked
+                        || (self.per_n_epochs_chkpt == 0 && self.per_n_steps_chkpt == 0) =>
+                    {
+                        self.checkpoint().unwrap()
+                    }
+                    _ => {}
                 }
                 None
             }
@@ -147,6 +522,7 @@ pub struct Tester<'a> {
     device: Device,
     dataloader: std::iter::Enumerate<DatasetIter<'a>>,
     nb_batches: usize,
+    bytes_read: u64,
 }
 
 impl<'a> Tester<'a> {
@@ -166,6 +542,7 @@ impl<'a> Tester<'a> {
             device,
             dataloader: dataset.iter_shuffle(batch_size).enumerate(),
             nb_batches,
+            bytes_read: 0,
         }
     }
 
@@ -175,6 +552,7 @@ impl<'a> Tester<'a> {
         inputs: Vec<PrivacyGuard<Tensor>>,
         labels: PrivacyGuard<Tensor>,
     ) -> Result<(i32, f32, f32), TchError> {
+        self.bytes_read += batch_bytes(&inputs, &labels)?;
         let inputs = inputs_to_device(inputs, self.device)?;
         let labels = labels.f_to(self.device)?;
         let outputs = self.forward.forward(inputs)?;
@@ -186,6 +564,11 @@ impl<'a> Tester<'a> {
     pub fn nb_batches(&self) -> usize {
         self.nb_batches
     }
+
+    /// Returns the number of bytes of samples tested on so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
 }
 
 impl<'a> Iterator for Tester<'a> {
@@ -200,15 +583,165 @@ impl<'a> Iterator for Tester<'a> {
     }
 }
 
+/// Computes a loss or metric from the outputs and the labels, with the clipping range of
+/// the metric. Returns the value to backpropagate and the value to report.
+pub type LossFn = fn(
+    &PrivacyGuard<Tensor>,
+    &PrivacyGuard<Tensor>,
+    (f64, f64),
+) -> Result<(PrivacyGuard<Tensor>, PrivacyGuard<Tensor>), TchError>;
+
+/// Description of a loss or metric supported by [`Metric::try_from_name`].
+#[derive(Debug, Clone, Copy)]
+pub struct MetricInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Values are clipped to this range before being averaged and disclosed.
+    pub clipping: (f64, f64),
+    /// Whether the metric can be minimized by a training.
+    pub is_loss: bool,
+    /// What labels are expected.
+    pub labels: &'static str,
+    pub loss_fn: LossFn,
+}
+
+/// Every loss and metric supported by [`Metric::try_from_name`].
+pub const METRICS: &[MetricInfo] = &[
+    MetricInfo {
+        name: "accuracy",
+        description: "Share of the samples whose predicted class, the largest output, is the label",
+        clipping: (0.0, 1.0),
+        is_loss: false,
+        labels: "class indexes",
+        loss_fn: accuracy,
+    },
+    MetricInfo {
+        name: "l2",
+        description: "Mean squared error between the outputs and the labels",
+        clipping: (0.0, 10.0),
+        is_loss: true,
+        labels: "tensors shaped like the outputs",
+        loss_fn: l2,
+    },
+    MetricInfo {
+        name: "cross_entropy",
+        description: "Cross entropy between the softmax of the outputs and the labels",
+        clipping: (0.0, 10.0),
+        is_loss: true,
+        labels: "class indexes",
+        loss_fn: cross_entropy,
+    },
+];
+
+fn accuracy(
+    output: &PrivacyGuard<Tensor>,
+    label: &PrivacyGuard<Tensor>,
+    _clipping: (f64, f64),
+) -> Result<(PrivacyGuard<Tensor>, PrivacyGuard<Tensor>), TchError> {
+    let prediction = output
+        .f_argmax(-1, false)?
+        .f_sub(label)?
+        .f_abs()?
+        .f_clamp(0.0, 1.0)?
+        .f_sum(Kind::Float)?
+        .f_mul_scalar(-1.0 / label.batch_size()? as f64)?
+        .f_add_scalar(1.0)?;
+    Ok((prediction.f_clone()?, prediction))
+}
+
+fn l2(
+    output: &PrivacyGuard<Tensor>,
+    label: &PrivacyGuard<Tensor>,
+    clipping: (f64, f64),
+) -> Result<(PrivacyGuard<Tensor>, PrivacyGuard<Tensor>), TchError> {
+    output.f_mse_loss(label, clipping, tch::Reduction::Mean)
+}
+
+fn cross_entropy(
+    output: &PrivacyGuard<Tensor>,
+    label: &PrivacyGuard<Tensor>,
+    clipping: (f64, f64),
+) -> Result<(PrivacyGuard<Tensor>, PrivacyGuard<Tensor>), TchError> {
+    let weight: Option<Tensor> = None;
+    output.f_cross_entropy_loss(label, clipping, weight, tch::Reduction::Mean, -100, 0.)
+}
+
+/// Returns the first dimension, after the batch one, where `a` and `b` differ.
+fn first_mismatch(a: &[i64], b: &[i64]) -> Option<usize> {
+    (1..a.len().max(b.len())).find(|&i| a.get(i) != b.get(i))
+}
+
+fn describe_dim(shape: &[i64], dim: usize) -> String {
+    match shape.get(dim) {
+        Some(size) => format!("{}", size),
+        None => String::from("missing"),
+    }
+}
+
+/// Checks that `forward` accepts the inputs of `dataset` and that its outputs fit the labels
+/// for `metric`, with a dry forward pass on zero samples of the shapes and kinds of the inputs.
+///
+/// Fails with a [`TchError::Shape`] describing the mismatch, to be reported before training.
+/// Only shapes and kinds are read, so that the model cannot encode samples in the error or
+/// the outputs, and no privacy budget is expended.
+pub fn check_shapes(forward: &Forward, dataset: &Dataset, metric: &str) -> Result<(), TchError> {
+    let nb_samples = 2;
+    if dataset.len() == 0 {
+        return Err(TchError::Shape(String::from("The dataset is empty")));
+    }
+    let _no_grad = tch::no_grad_guard();
+    let mut inputs = Vec::with_capacity(dataset.samples_inputs.len());
+    for input in dataset.samples_inputs.iter() {
+        let input = input.lock().unwrap();
+        let mut shape = input.size();
+        shape[0] = nb_samples;
+        inputs.push(Tensor::f_zeros(&shape, (input.kind(), input.device()))?);
+    }
+    let labels = dataset.labels.lock().unwrap().size();
+    let output = forward.forward_inner(&inputs).map_err(|e| {
+        let shapes: Vec<_> = inputs
+            .iter()
+            .map(|input| input.size()[1..].to_vec())
+            .collect();
+        TchError::Shape(format!(
+            "The model does not accept samples with inputs of shapes {:?}: {}",
+            shapes,
+            e.to_string().lines().next().unwrap_or_default()
+        ))
+    })?;
+    let output = output.size();
+
+    if output.is_empty() || output[0] % nb_samples != 0 {
+        return Err(TchError::Shape(format!(
+            "The model outputs a tensor of shape {:?} for a batch of {} samples, \
+            dimension 0 should be the batch",
+            output, nb_samples
+        )));
+    }
+    // Class predictions are compared with labels without the last dimension of the outputs.
+    let expected = match metric {
+        "l2" => output.clone(),
+        "cross_entropy" | "accuracy" => output[..output.len() - 1].to_vec(),
+        _ => return Ok(()),
+    };
+    match first_mismatch(&expected, &labels) {
+        Some(dim) => Err(TchError::Shape(format!(
+            "Labels of shape {:?} do not fit outputs of shape {:?} for {}: dimension {} of the \
+            labels of a sample is {} but should be {}",
+            &labels[1..],
+            &output[1..],
+            metric,
+            dim - 1,
+            describe_dim(&labels, dim),
+            describe_dim(&expected, dim)
+        ))),
+        None => Ok(()),
+    }
+}
+
 /// A loss (or metric) function with average statistics
 pub struct Metric {
-    loss_fn: Box<
-        dyn Fn(
-                &PrivacyGuard<Tensor>,
-                &PrivacyGuard<Tensor>,
-            ) -> Result<(PrivacyGuard<Tensor>, PrivacyGuard<Tensor>), TchError>
-            + Send,
-    >,
+    loss_fn: LossFn,
     value: Option<PrivacyGuard<Tensor>>,
     clipping: (f64, f64),
     nb_samples: usize,
@@ -217,62 +750,16 @@ pub struct Metric {
 impl Metric {
     /// Returns a `Metric` corresponding to given name, if not available raises an error.
     pub fn try_from_name(loss_name: &str) -> Result<Self, TchError> {
-        let (loss_fn, clipping): (
-            Box<
-                dyn Fn(
-                        &PrivacyGuard<Tensor>,
-                        &PrivacyGuard<Tensor>,
-                    )
-                        -> Result<(PrivacyGuard<Tensor>, PrivacyGuard<Tensor>), TchError>
-                    + Send,
-            >,
-            (f64, f64),
-        ) = match loss_name {
-            "accuracy" => (
-                Box::new(|output, label| {
-                    let prediction = output
-                        .f_argmax(-1, false)?
-                        .f_sub(label)?
-                        .f_abs()?
-                        .f_clamp(0.0, 1.0)?
-                        .f_sum(Kind::Float)?
-                        .f_mul_scalar(-1.0 / label.batch_size()? as f64)?
-                        .f_add_scalar(1.0)?;
-                    Ok((prediction.f_clone()?, prediction))
-                }),
-                (0.0, 1.0),
-            ),
-            "l2" => (
-                Box::new(|output, label| {
-                    output.f_mse_loss(label, (0.0, 10.0), tch::Reduction::Mean)
-                }),
-                (0.0, 10.0),
-            ),
-            "cross_entropy" => (
-                Box::new(|output, label| {
-                    let weight: Option<Tensor> = None;
-                    output.f_cross_entropy_loss(
-                        label,
-                        (0.0, 10.0),
-                        weight,
-                        tch::Reduction::Mean,
-                        -100,
-                        0.,
-                    )
-                }),
-                (0.0, 10.0),
-            ),
-            s => {
-                return Err(TchError::FileFormat(String::from(format!(
-                    "Invalid loss name, unknown loss {}.",
-                    s
-                ))))
-            }
-        };
+        let info = METRICS
+            .iter()
+            .find(|info| info.name == loss_name)
+            .ok_or_else(|| {
+                TchError::FileFormat(format!("Invalid loss name, unknown loss {}.", loss_name))
+            })?;
         Ok(Metric {
-            loss_fn,
+            loss_fn: info.loss_fn,
             value: None,
-            clipping,
+            clipping: info.clipping,
             nb_samples: 0,
         })
     }
@@ -285,9 +772,9 @@ impl Metric {
     ) -> Result<PrivacyGuard<Tensor>, TchError> {
         let expansion = output.batch_size()? / label.batch_size()?;
         let loss = if expansion != 1 {
-            (self.loss_fn)(output, &label.expand_batch_dim(expansion)?)?
+            (self.loss_fn)(output, &label.expand_batch_dim(expansion)?, self.clipping)?
         } else {
-            (self.loss_fn)(output, label)?
+            (self.loss_fn)(output, label, self.clipping)?
         };
         // let loss = (self.loss_fn)(output, label)?;
         let detached_loss = loss.1.f_clone()?;
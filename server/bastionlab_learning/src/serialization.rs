@@ -1,6 +1,8 @@
 use std::io::Cursor;
 
-use tch::{Device, TchError, Tensor};
+use tch::{Device, Kind, TchError, Tensor};
+
+use crate::nn::Module;
 
 fn read_le_usize(input: &mut &[u8]) -> usize {
     let (int_bytes, rest) = input.split_at(std::mem::size_of::<usize>());
@@ -102,3 +104,109 @@ impl TryFrom<&SizedObjectsBytes> for tch::Tensor {
         Ok(data)
     }
 }
+
+/// Formats in which a module can be exported, for use outside of BastionLab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleFormat {
+    /// A TorchScript archive of the module and its weights, loadable with `torch.jit.load`.
+    TorchScript,
+    /// The weights only, as a named tensor archive keyed by their PyTorch names
+    /// (e.g. `fc1.weight`).
+    StateDict,
+    /// The weights only, in the safetensors format.
+    SafeTensors,
+}
+
+/// Serializes `module`, with its current weights, in `format`.
+pub fn export_module(module: &Module, format: ModuleFormat) -> Result<Vec<u8>, TchError> {
+    match format {
+        ModuleFormat::TorchScript => torchscript_bytes(module),
+        ModuleFormat::StateDict => {
+            let parameters = cpu_parameters(module)?;
+            let mut buf = Vec::new();
+            Tensor::save_multi_to_stream(&parameters, &mut buf)?;
+            Ok(buf)
+        }
+        ModuleFormat::SafeTensors => safetensors_bytes(cpu_parameters(module)?),
+    }
+}
+
+/// Returns detached, contiguous copies of the parameters of `module` on the CPU, sorted by name.
+fn cpu_parameters(module: &Module) -> Result<Vec<(String, Tensor)>, TchError> {
+    let mut parameters = module
+        .named_parameters()?
+        .into_iter()
+        .map(|(name, tensor)| {
+            let tensor = tensor
+                .f_detach()?
+                .f_to_device(Device::Cpu)?
+                .f_contiguous()?;
+            Ok((name, tensor))
+        })
+        .collect::<Result<Vec<_>, TchError>>()?;
+    parameters.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(parameters)
+}
+
+fn torchscript_bytes(module: &Module) -> Result<Vec<u8>, TchError> {
+    // Trained weights are serialized in memory, so that they never reach the disk unsealed.
+    let mut buf = Vec::new();
+    module.save_to_stream(&mut buf)?;
+    Ok(buf)
+}
+
+fn safetensors_dtype(kind: Kind) -> Result<&'static str, TchError> {
+    Ok(match kind {
+        Kind::Bool => "BOOL",
+        Kind::Uint8 => "U8",
+        Kind::Int8 => "I8",
+        Kind::Int16 => "I16",
+        Kind::Int => "I32",
+        Kind::Int64 => "I64",
+        Kind::Half => "F16",
+        Kind::BFloat16 => "BF16",
+        Kind::Float => "F32",
+        Kind::Double => "F64",
+        kind => {
+            return Err(TchError::Kind(format!(
+                "Cannot export {:?} tensors to safetensors",
+                kind
+            )))
+        }
+    })
+}
+
+/// Serializes `tensors` in the safetensors format:
+///
+/// `[header length: 8 bytes, little-endian | JSON header | data]`
+///
+/// where the header maps every name to the dtype, shape and byte range of its tensor in
+/// the data. Tensors must be contiguous and on the CPU.
+fn safetensors_bytes(tensors: Vec<(String, Tensor)>) -> Result<Vec<u8>, TchError> {
+    let mut entries = Vec::with_capacity(tensors.len());
+    let mut data = Vec::new();
+    for (name, tensor) in tensors.iter() {
+        let kind = tensor.f_kind()?;
+        let numel = tensor.numel();
+        let start = data.len();
+        data.resize(start + numel * kind.elt_size_in_bytes(), 0);
+        tensor.f_copy_data_u8(&mut data[start..], numel)?;
+        let shape: Vec<_> = tensor.size().iter().map(|d| d.to_string()).collect();
+        entries.push(format!(
+            "\"{}\":{{\"dtype\":\"{}\",\"shape\":[{}],\"data_offsets\":[{},{}]}}",
+            name.replace('\\', "\\\\").replace('"', "\\\""),
+            safetensors_dtype(kind)?,
+            shape.join(","),
+            start,
+            data.len()
+        ));
+    }
+    let mut header = format!("{{{}}}", entries.join(",")).into_bytes();
+    // The data is aligned on 8 bytes, as the format recommends.
+    header.resize((header.len() + 7) / 8 * 8, b' ');
+
+    let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+    bytes.append(&mut header);
+    bytes.append(&mut data);
+    Ok(bytes)
+}
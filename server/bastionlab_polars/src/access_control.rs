@@ -1,4 +1,8 @@
+use bastionlab_common::session_proto::ErrorCode;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tonic::Status;
 
 use crate::composite_plan::StatsEntry;
@@ -8,6 +12,44 @@ pub struct Policy {
     safe_zone: Rule,
     unsafe_handling: UnsafeAction,
     savable: bool,
+    /// Caps what every user may fetch from the results derived from the data frame.
+    #[serde(default)]
+    fetch_limit: Option<FetchLimit>,
+    /// Number of distinct data owners who must approve the fetches sent for review, through
+    /// the DecideFetch RPC rather than the server console.
+    #[serde(default)]
+    required_approvals: Option<usize>,
+    /// Whether string similarities may be computed on the columns of the data frame, which
+    /// can probe their values.
+    #[serde(default)]
+    string_similarity: bool,
+}
+
+/// Maximum number of rows and of fetches every user may get from the results derived
+/// from a data frame, over any period of `window_secs` seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FetchLimit {
+    window_secs: u64,
+    #[serde(default)]
+    max_rows: Option<usize>,
+    #[serde(default)]
+    max_fetches: Option<usize>,
+}
+
+impl FetchLimit {
+    fn validate(&self) -> Result<(), Status> {
+        if self.window_secs == 0 {
+            return Err(Status::invalid_argument(
+                "The window of a fetch limit must last at least one second",
+            ));
+        }
+        if self.max_rows.is_none() && self.max_fetches.is_none() {
+            return Err(Status::invalid_argument(
+                "A fetch limit must cap the number of rows or of fetches",
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl Policy {
@@ -29,6 +71,10 @@ impl Policy {
             },
             unsafe_handling: self.unsafe_handling.merge(other.unsafe_handling),
             savable: self.savable && other.savable,
+            // Limits are enforced against the data frames they are set on.
+            fetch_limit: None,
+            required_approvals: self.required_approvals.max(other.required_approvals),
+            string_similarity: self.string_similarity && other.string_similarity,
         }
     }
 
@@ -37,20 +83,69 @@ impl Policy {
             safe_zone: Rule::TrueRule,
             unsafe_handling: UnsafeAction::Log,
             savable: true,
+            fetch_limit: None,
+            required_approvals: None,
+            string_similarity: true,
+        }
+    }
+
+    /// Fails with `InvalidArgument` when the policy cannot be enforced.
+    pub fn validate(&self) -> Result<(), Status> {
+        if let Some(limit) = &self.fetch_limit {
+            limit.validate()?;
         }
+        Ok(())
     }
 
     pub fn check_savable(&self) -> bool {
         return self.savable;
     }
+
+    pub fn fetch_limit(&self) -> Option<FetchLimit> {
+        self.fetch_limit
+    }
+
+    /// Returns the number of data owners who must approve the fetches sent for review, if
+    /// they are not reviewed on the server console.
+    pub fn required_approvals(&self) -> Option<usize> {
+        self.required_approvals.filter(|&n| n > 0)
+    }
+
+    pub fn allows_string_similarity(&self) -> bool {
+        self.string_similarity
+    }
+
+    /// Returns the per-query cap and the overall privacy budget of differentially private
+    /// aggregations, if the policy requires them.
+    pub fn dp_limits(&self) -> Option<(f64, f64)> {
+        self.safe_zone.dp_limits()
+    }
+
+    /// Returns the largest minimum size of aggregations required by the policy, if any.
+    pub fn min_agg_size(&self) -> Option<usize> {
+        self.safe_zone.min_agg_size()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum Rule {
-    AtLeastNOf { n: usize, of: Vec<Rule> },
-    UserId { id: String },
-    Aggregation { min_agg_size: usize },
+    AtLeastNOf {
+        n: usize,
+        of: Vec<Rule>,
+    },
+    UserId {
+        id: String,
+    },
+    Aggregation {
+        min_agg_size: usize,
+    },
+    /// Results must be computed by differentially private aggregations using at most
+    /// `max_eps_per_query`, out of an overall `budget`.
+    DifferentialPrivacy {
+        max_eps_per_query: f64,
+        budget: f64,
+    },
     TrueRule,
     FalseRule,
 }
@@ -95,22 +190,64 @@ impl Rule {
                 min_agg_size: min_allowed_agg_size,
             } => {
                 let min_allowed_agg_size = *min_allowed_agg_size * ctx.stats.join_scaling;
-                Ok(if ctx.stats.agg_size >= min_allowed_agg_size {
-                    RuleMatch::Match
-                } else {
+                Ok(if ctx.stats.agg_size < min_allowed_agg_size {
                     RuleMatch::Mismatch(format!(
                         "Cannot fetch a result DataFrame that does not aggregate at least {} rows of DataFrame {}.",
                         min_allowed_agg_size,
                         ctx.df_identifier,
                     ))
+                } else if ctx.stats.min_partition_size < *min_allowed_agg_size {
+                    RuleMatch::Mismatch(format!(
+                        "Cannot fetch a result DataFrame computed over window partitions of less than {} rows of DataFrame {}.",
+                        min_allowed_agg_size,
+                        ctx.df_identifier,
+                    ))
+                } else {
+                    RuleMatch::Match
                 })
             }
+            Rule::DifferentialPrivacy {
+                max_eps_per_query, ..
+            } => Ok(match ctx.stats.dp_eps {
+                Some(eps) if eps <= *max_eps_per_query => RuleMatch::Match,
+                Some(eps) => RuleMatch::Mismatch(format!(
+                    "Cannot fetch a result of DataFrame {} that uses ε={} when at most ε={} is allowed per query.",
+                    ctx.df_identifier, eps, max_eps_per_query,
+                )),
+                None => RuleMatch::Mismatch(format!(
+                    "Cannot fetch a result of DataFrame {} that is not computed by differentially private aggregations.",
+                    ctx.df_identifier,
+                )),
+            }),
             Rule::TrueRule => Ok(RuleMatch::Match),
             Rule::FalseRule => Ok(RuleMatch::Mismatch(String::from(
                 "Operation denied by the data owner's policy.",
             ))),
         }
     }
+
+    /// Returns the strictest limits of the differential privacy rules in the rule.
+    fn dp_limits(&self) -> Option<(f64, f64)> {
+        match self {
+            Rule::AtLeastNOf { of, .. } => of.iter().filter_map(Rule::dp_limits).reduce(
+                |(cap_a, budget_a), (cap_b, budget_b)| (cap_a.min(cap_b), budget_a.min(budget_b)),
+            ),
+            Rule::DifferentialPrivacy {
+                max_eps_per_query,
+                budget,
+            } => Some((*max_eps_per_query, *budget)),
+            _ => None,
+        }
+    }
+
+    /// Returns the largest minimum size of the aggregation rules in the rule.
+    fn min_agg_size(&self) -> Option<usize> {
+        match self {
+            Rule::AtLeastNOf { of, .. } => of.iter().filter_map(Rule::min_agg_size).max(),
+            Rule::Aggregation { min_agg_size } => Some(*min_agg_size),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -170,3 +307,61 @@ impl VerificationResult {
         }
     }
 }
+
+/// Fetches of every user from the results derived from the data frames with a
+/// [`FetchLimit`], kept in memory.
+#[derive(Debug, Default)]
+pub struct FetchLedger {
+    /// Times and numbers of rows of the fetches, by data frame and user.
+    fetches: Mutex<HashMap<(String, String), Vec<(SystemTime, usize)>>>,
+}
+
+impl FetchLedger {
+    /// Records a fetch of `rows` rows by `user_id` from a result derived from the data
+    /// frames `limits`, unless it exceeds the limit of one of them.
+    pub fn record(
+        &self,
+        user_id: &str,
+        limits: &[(String, FetchLimit)],
+        rows: usize,
+    ) -> Result<(), Status> {
+        let now = SystemTime::now();
+        let mut fetches = self.fetches.lock().unwrap();
+        for (identifier, limit) in limits {
+            // Windows reaching before the epoch hold every fetch.
+            let window_start = now
+                .checked_sub(Duration::from_secs(limit.window_secs))
+                .unwrap_or(UNIX_EPOCH);
+            let history = fetches
+                .entry((identifier.clone(), user_id.to_string()))
+                .or_default();
+            history.retain(|(time, _)| *time > window_start);
+            if let Some(max_fetches) = limit.max_fetches {
+                if history.len() + 1 > max_fetches {
+                    return Err(ErrorCode::FetchLimitExceeded.status(format!(
+                        "Cannot fetch more than {} results derived from DataFrame {} every {} seconds",
+                        max_fetches, identifier, limit.window_secs
+                    )));
+                }
+            }
+            if let Some(max_rows) = limit.max_rows {
+                let fetched: usize = history.iter().map(|(_, rows)| rows).sum();
+                if fetched + rows > max_rows {
+                    return Err(ErrorCode::FetchLimitExceeded.status(format!(
+                        "Cannot fetch more than {} rows derived from DataFrame {} every {} seconds: {} remain",
+                        max_rows,
+                        identifier,
+                        limit.window_secs,
+                        max_rows.saturating_sub(fetched)
+                    )));
+                }
+            }
+        }
+        for (identifier, _) in limits {
+            if let Some(history) = fetches.get_mut(&(identifier.clone(), user_id.to_string())) {
+                history.push((now, rows));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use bastionlab_common::prelude::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use tonic::Status;
+use uuid::Uuid;
+
+use crate::polars_proto::PendingFetch;
+
+/// Fetch waiting for the approvals of data owners, with the channel its decision is sent on.
+struct PendingRequest {
+    summary: PendingFetch,
+    decision: watch::Sender<Option<bool>>,
+}
+
+/// Fetches waiting for the approvals of several data owners, kept in memory.
+///
+/// Requests are forgotten once their user stops waiting for the decision, i.e. when the
+/// receiver returned by [`ApprovalBoard::submit`] is dropped.
+#[derive(Default)]
+pub struct ApprovalBoard {
+    pending: Mutex<HashMap<String, PendingRequest>>,
+}
+
+/// Forgets the requests nobody waits for anymore.
+fn prune(pending: &mut HashMap<String, PendingRequest>) {
+    pending.retain(|_, request| !request.decision.is_closed());
+}
+
+impl ApprovalBoard {
+    /// Registers a fetch of the data frame `identifier` by `user_id` that needs `required`
+    /// approvals. Returns the identifier of the request and a receiver of its decision,
+    /// `true` once enough data owners approved it and `false` once one rejected it.
+    pub fn submit(
+        &self,
+        identifier: &str,
+        user_id: &str,
+        reason: &str,
+        query_details: &str,
+        required: usize,
+    ) -> (String, watch::Receiver<Option<bool>>) {
+        let request_id = format!("{}", Uuid::new_v4());
+        let (decision, receiver) = watch::channel(None);
+        let summary = PendingFetch {
+            request_id: request_id.clone(),
+            identifier: identifier.to_string(),
+            user_id: user_id.to_string(),
+            reason: reason.to_string(),
+            query_details: query_details.to_string(),
+            approvals: Vec::new(),
+            required_approvals: required as u32,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        };
+        let mut pending = self.pending.lock().unwrap();
+        prune(&mut pending);
+        pending.insert(request_id.clone(), PendingRequest { summary, decision });
+        (request_id, receiver)
+    }
+
+    /// Returns the pending fetches, oldest first.
+    pub fn list(&self) -> Vec<PendingFetch> {
+        let mut pending = self.pending.lock().unwrap();
+        prune(&mut pending);
+        let mut list: Vec<_> = pending
+            .values()
+            .map(|request| request.summary.clone())
+            .collect();
+        list.sort_by_key(|fetch| fetch.created_at);
+        list
+    }
+
+    /// Records the decision of the data owner `owner_id` on the fetch `request_id` and
+    /// returns its updated state. The request is settled, and forgotten, once rejected or
+    /// approved by enough distinct owners.
+    pub fn decide(
+        &self,
+        request_id: &str,
+        owner_id: &str,
+        approve: bool,
+    ) -> Result<PendingFetch, Status> {
+        let mut pending = self.pending.lock().unwrap();
+        prune(&mut pending);
+        let request = pending.get_mut(request_id).ok_or_else(|| {
+            Status::not_found(format!("Could not find pending fetch: {}", request_id))
+        })?;
+        if request.summary.user_id == owner_id {
+            return Err(Status::permission_denied(
+                "Data owners cannot decide on their own fetches.",
+            ));
+        }
+
+        let settled = if approve {
+            if !request.summary.approvals.iter().any(|id| id == owner_id) {
+                request.summary.approvals.push(owner_id.to_string());
+            }
+            request.summary.approvals.len() >= request.summary.required_approvals as usize
+        } else {
+            true
+        };
+        if !settled {
+            return Ok(request.summary.clone());
+        }
+
+        let request = pending.remove(request_id).unwrap();
+        // The user may have stopped waiting for the data frame.
+        let _ignored = request.decision.send(Some(approve));
+        Ok(request.summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    #[test]
+    fn abandoned_requests_are_forgotten() {
+        let board = ApprovalBoard::default();
+        let (kept, _receiver) = board.submit("df", "alice", "unsafe", "", 1);
+        let (abandoned, receiver) = board.submit("df", "alice", "unsafe", "", 1);
+        drop(receiver);
+
+        let pending: Vec<_> = board.list().into_iter().map(|f| f.request_id).collect();
+        assert_eq!(pending, vec![kept.clone()]);
+        let err = board.decide(&abandoned, "bob", true).unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+        assert!(board.decide(&kept, "bob", true).is_ok());
+        assert!(board.list().is_empty());
+    }
+}
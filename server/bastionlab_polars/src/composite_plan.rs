@@ -1,16 +1,30 @@
 use base64;
+use bastionlab_common::cancellation::Cancellation;
 use bastionlab_common::common_conversions::{
     lazy_frame_from_logical_plan, series_to_tensor, tensor_to_series,
 };
-use polars::{lazy::dsl::Expr, prelude::*};
+use bastionlab_common::session_proto::ErrorCode;
+use polars::{
+    lazy::dsl::Expr,
+    prelude::{
+        pivot::{pivot, PivotAgg},
+        *,
+    },
+};
+use rand::{thread_rng, Rng};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, io::Cursor};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+};
 use tch::CModule;
 use tonic::Status;
 
 use crate::{
     access_control::{Context, Policy, VerificationResult},
+    similarity::{jaro_winkler, levenshtein},
+    sketches::{HyperLogLog, TDigest},
     visitable::{Visitable, VisitableMut},
     BastionLabPolars, DataFrameArtifact,
 };
@@ -23,17 +37,362 @@ pub struct CompositePlan {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum CompositePlanSegment {
-    PolarsPlanSegment { plan: LogicalPlan },
-    UdfPlanSegment { columns: Vec<String>, udf: String },
-    EntryPointPlanSegment { identifier: String },
+    PolarsPlanSegment {
+        plan: LogicalPlan,
+    },
+    UdfPlanSegment {
+        columns: Vec<String>,
+        udf: String,
+    },
+    EntryPointPlanSegment {
+        identifier: String,
+    },
     StackPlanSegment,
-    RowCountSegment { row: String },
+    RowCountSegment {
+        row: String,
+    },
+    DpAggregationSegment {
+        aggs: Vec<DpAggregation>,
+        eps: f64,
+    },
+    PivotSegment {
+        values: Vec<String>,
+        index: Vec<String>,
+        columns: Vec<String>,
+        agg: PivotAggregation,
+    },
+    WindowSegment {
+        functions: Vec<WindowFunction>,
+    },
+    SketchSegment {
+        sketches: Vec<Sketch>,
+        /// Privacy budget of the noise added to the estimates, if any.
+        #[serde(default)]
+        eps: Option<f64>,
+    },
+    /// Replaces identifiers with their pseudonyms under a key of the data owner, so that
+    /// data frames can be joined on them.
+    PseudonymizeSegment {
+        columns: Vec<String>,
+        key: String,
+    },
+    /// Adds columns of similarities between strings, if the policies of the inputs allow it.
+    StringSimilaritySegment {
+        similarities: Vec<StringSimilarity>,
+    },
+    /// Links the rows of two data frames that likely describe the same individuals, by
+    /// comparing pseudonymized fields.
+    LinkageSegment {
+        /// Fields whose values must be equal for rows to be compared, all pairs of rows
+        /// are compared when empty. Their weights are ignored.
+        #[serde(default)]
+        blocking: Vec<LinkageField>,
+        fields: Vec<LinkageField>,
+        /// Minimum score, between 0 and 1, of the pairs of rows that match.
+        threshold: f64,
+        output: LinkageOutput,
+    },
+}
+
+/// Approximate aggregation of a column computed with a sketch, in a single pass and
+/// bounded memory.
+///
+/// With differential privacy, values of quantiles are clipped to `[lower, upper]` and the
+/// noise is proportional to the width of these bounds, which should be tight. The noise of
+/// distinct counts is calibrated to the sensitivity of the exact count.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Sketch {
+    column: String,
+    kind: SketchKind,
+    /// Quantiles to estimate, ignored by distinct counts.
+    #[serde(default)]
+    quantiles: Vec<f64>,
+    #[serde(default)]
+    lower: f64,
+    #[serde(default)]
+    upper: f64,
+}
+
+impl Sketch {
+    fn output_names(&self) -> Vec<String> {
+        match self.kind {
+            SketchKind::DistinctCount => vec![format!("{}_distinct", self.column)],
+            SketchKind::Quantiles => self
+                .quantiles
+                .iter()
+                .map(|q| format!("{}_q{}", self.column, q))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum SketchKind {
+    /// Estimated with HyperLogLog.
+    DistinctCount,
+    /// Estimated with a t-digest.
+    Quantiles,
+}
+
+/// Compression of the t-digests of quantile sketches.
+const TDIGEST_COMPRESSION: f64 = 100.0;
+
+/// Function of a column computed over the partitions of a data frame, in the order of
+/// its rows, and added as a new column.
+///
+/// The whole data frame is a single partition when `partition_by` is empty.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WindowFunction {
+    column: String,
+    kind: WindowFunctionKind,
+    #[serde(default)]
+    partition_by: Vec<String>,
+    /// Number of rows of rolling windows, ignored by the other functions.
+    #[serde(default)]
+    window_size: usize,
+}
+
+impl WindowFunction {
+    fn output_name(&self) -> String {
+        let suffix = match self.kind {
+            WindowFunctionKind::CumSum => "cum_sum",
+            WindowFunctionKind::RollingMean => "rolling_mean",
+            WindowFunctionKind::RollingSum => "rolling_sum",
+            WindowFunctionKind::Rank => "rank",
+        };
+        format!("{}_{}", self.column, suffix)
+    }
+
+    fn expr(&self) -> Result<Expr, Status> {
+        let rolling_options = || -> Result<RollingOptions, Status> {
+            if self.window_size == 0 {
+                return Err(Status::invalid_argument(format!(
+                    "Rolling windows of column `{}` must have at least one row",
+                    self.column
+                )));
+            }
+            Ok(RollingOptions {
+                window_size: Duration::new(self.window_size as i64),
+                min_periods: self.window_size,
+                ..Default::default()
+            })
+        };
+        let input = col(&self.column);
+        let expr = match self.kind {
+            WindowFunctionKind::CumSum => input.cumsum(false),
+            WindowFunctionKind::RollingMean => input.rolling_mean(rolling_options()?),
+            WindowFunctionKind::RollingSum => input.rolling_sum(rolling_options()?),
+            WindowFunctionKind::Rank => input.rank(RankOptions {
+                method: RankMethod::Average,
+                descending: false,
+            }),
+        };
+        let expr = if self.partition_by.is_empty() {
+            expr
+        } else {
+            let partition_by: Vec<Expr> = self.partition_by.iter().map(|name| col(name)).collect();
+            expr.over(partition_by)
+        };
+        Ok(expr.alias(&self.output_name()))
+    }
+
+    /// Returns the number of rows of its output a single row may contribute to, given the
+    /// size of the largest partition.
+    fn scaling(&self, max_partition_size: usize) -> usize {
+        match self.kind {
+            // Every row contributes to the sums of the rows after it and to the ranks of
+            // the whole partition.
+            WindowFunctionKind::CumSum | WindowFunctionKind::Rank => max_partition_size,
+            WindowFunctionKind::RollingMean | WindowFunctionKind::RollingSum => {
+                self.window_size.min(max_partition_size)
+            }
+        }
+        .max(1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum WindowFunctionKind {
+    CumSum,
+    RollingMean,
+    RollingSum,
+    Rank,
+}
+
+/// Aggregation of the values of the cells of a pivot.
+///
+/// `First` and `Last` return the value of a single row, so they do not count as
+/// aggregations for policies.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum PivotAggregation {
+    First,
+    Last,
+    Count,
+    Sum,
+    Mean,
+    Median,
+    Min,
+    Max,
+}
+
+impl PivotAggregation {
+    fn is_aggregation(&self) -> bool {
+        !matches!(self, PivotAggregation::First | PivotAggregation::Last)
+    }
+}
+
+impl From<PivotAggregation> for PivotAgg {
+    fn from(agg: PivotAggregation) -> Self {
+        match agg {
+            PivotAggregation::First => PivotAgg::First,
+            PivotAggregation::Last => PivotAgg::Last,
+            PivotAggregation::Count => PivotAgg::Count,
+            PivotAggregation::Sum => PivotAgg::Sum,
+            PivotAggregation::Mean => PivotAgg::Mean,
+            PivotAggregation::Median => PivotAgg::Median,
+            PivotAggregation::Min => PivotAgg::Min,
+            PivotAggregation::Max => PivotAgg::Max,
+        }
+    }
+}
+
+/// Similarity of the values of a column with those of another column or with a value,
+/// added as a new column named after the column and kind unless `alias` is set, e.g.
+/// `name_jaro_winkler`. Similarities involving missing values are missing.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StringSimilarity {
+    column: String,
+    kind: StringSimilarityKind,
+    /// Column compared with, `value` is used when unset.
+    #[serde(default)]
+    other: Option<String>,
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    alias: Option<String>,
+}
+
+impl StringSimilarity {
+    fn output_name(&self) -> String {
+        match &self.alias {
+            Some(alias) => alias.clone(),
+            None => {
+                let suffix = match self.kind {
+                    StringSimilarityKind::Levenshtein => "levenshtein",
+                    StringSimilarityKind::JaroWinkler => "jaro_winkler",
+                };
+                format!("{}_{}", self.column, suffix)
+            }
+        }
+    }
+
+    /// Returns the columns the similarity reads.
+    fn columns(&self) -> impl Iterator<Item = &String> + Clone {
+        std::iter::once(&self.column).chain(self.other.iter())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum StringSimilarityKind {
+    /// Edit distance, as an integer.
+    Levenshtein,
+    /// Similarity between 0 and 1, 1 for equal strings.
+    JaroWinkler,
+}
+
+/// Pair of fields, one of each data frame, holding pseudonyms of the same identifier.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LinkageField {
+    left: String,
+    right: String,
+    /// Share of the score of a pair of rows given by the agreement of the fields, relative
+    /// to the other fields.
+    #[serde(default = "default_linkage_weight")]
+    weight: f64,
+}
+
+fn default_linkage_weight() -> f64 {
+    1.0
+}
+
+/// Result of a record linkage.
+///
+/// Statistics aggregate every row of both data frames, whereas linked rows are subject to
+/// the policies of both data frames like the rows of a join.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum LinkageOutput {
+    /// A single row with the numbers of rows, candidate pairs, matched pairs and matched
+    /// rows of each data frame.
+    Statistics,
+    /// The matched pairs of rows, with the columns of both data frames, those of the right
+    /// one suffixed with `_right` when their names clash, and the score of the pair.
+    Linked,
+}
+
+/// Maximum number of pairs of rows compared by a record linkage, past which blocking
+/// fields are required.
+const MAX_LINKAGE_CANDIDATES: usize = 50_000_000;
+
+/// Matched pairs of rows of a record linkage.
+struct Linkage {
+    /// Indices of the left and right rows, with their score.
+    pairs: Vec<(usize, usize, f64)>,
+    candidates: usize,
+}
+
+impl Linkage {
+    /// Returns the largest number of pairs a row of the left or right data frame is part of.
+    fn max_matches(&self, left: bool) -> usize {
+        let mut counts: HashMap<usize, usize> = HashMap::new();
+        for &(i, j, _) in self.pairs.iter() {
+            *counts.entry(if left { i } else { j }).or_default() += 1;
+        }
+        counts.into_values().max().unwrap_or(1)
+    }
+}
+
+/// Aggregation of a column with differential privacy.
+///
+/// Values are clipped to `[lower, upper]` before being summed, which bounds the
+/// contribution of every row. Bounds are ignored by counts.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DpAggregation {
+    column: String,
+    kind: DpAggregationKind,
+    #[serde(default)]
+    lower: f64,
+    #[serde(default)]
+    upper: f64,
+}
+
+impl DpAggregation {
+    fn output_name(&self) -> String {
+        let suffix = match self.kind {
+            DpAggregationKind::Count => "count",
+            DpAggregationKind::Sum => "sum",
+            DpAggregationKind::Mean => "mean",
+        };
+        format!("{}_{}", self.column, suffix)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum DpAggregationKind {
+    Count,
+    Sum,
+    Mean,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct StatsEntry {
     pub agg_size: usize,
     pub join_scaling: usize,
+    /// Privacy budget used by the differentially private aggregations the data frame
+    /// results from, if it does.
+    pub dp_eps: Option<f64>,
+    /// Number of rows of the smallest partition window functions were computed over, if
+    /// any.
+    pub min_partition_size: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -42,10 +401,43 @@ pub struct DataFrameStats(HashMap<String, StatsEntry>);
 struct StackFrame {
     df: DataFrame,
     stats: DataFrameStats,
+    /// Columns holding pseudonyms computed by the server.
+    pseudonymized: Vec<String>,
 }
 
 impl CompositePlan {
-    pub fn run(self, state: &BastionLabPolars, user_id: &str) -> Result<DataFrameArtifact, Status> {
+    /// Returns the privacy budget expended by the private aggregations of the plan.
+    pub fn dp_eps(&self) -> f64 {
+        self.segments
+            .iter()
+            .map(|seg| match seg {
+                CompositePlanSegment::DpAggregationSegment { eps, .. } => *eps,
+                CompositePlanSegment::SketchSegment { eps, .. } => eps.unwrap_or(0.0),
+                _ => 0.0,
+            })
+            .sum()
+    }
+
+    /// Returns the identifiers of the data frames read by the plan.
+    pub fn inputs(&self) -> Vec<&str> {
+        self.segments
+            .iter()
+            .filter_map(|seg| match seg {
+                CompositePlanSegment::EntryPointPlanSegment { identifier } => {
+                    Some(identifier.as_str())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Runs the plan segment by segment, stopping between segments if `cancellation` fires.
+    pub fn run(
+        self,
+        state: &BastionLabPolars,
+        user_id: &str,
+        cancellation: &Cancellation,
+    ) -> Result<DataFrameArtifact, Status> {
         let mut stack = Vec::new();
         let plan_str = serde_json::to_string(&self.segments).map_err(|e| {
             Status::invalid_argument(format!("Could not parse composite plan: {e}"))
@@ -53,10 +445,12 @@ impl CompositePlan {
         let mut blacklist_hashmap = HashMap::new();
 
         for seg in self.segments {
+            cancellation.check()?;
             match seg {
                 CompositePlanSegment::PolarsPlanSegment { mut plan } => {
-                    let stats = initialize_plan(&mut plan, &mut stack)?;
+                    let (stats, inputs) = initialize_plan(&mut plan, &mut stack)?;
                     let df = run_logical_plan(plan.clone())?;
+                    let pseudonymized = carried_pseudonyms(&df, &inputs);
 
                     let polars_plan_str = format!("{:?}", plan);
                     let re =
@@ -70,26 +464,19 @@ impl CompositePlan {
                         );
                     }
 
-                    stack.push(StackFrame { df, stats });
+                    stack.push(StackFrame {
+                        df,
+                        stats,
+                        pseudonymized,
+                    });
                 }
                 CompositePlanSegment::UdfPlanSegment { columns, udf } => {
-                    let module =
-                        CModule::load_data(&mut Cursor::new(base64::decode(udf).map_err(|e| {
-                            Status::invalid_argument(format!(
-                                "Could not decode base64-encoded udf: {}",
-                                e
-                            ))
-                        })?))
-                        .map_err(|e| {
-                            Status::invalid_argument(format!(
-                                "Could not deserialize udf from bytes: {}",
-                                e
-                            ))
-                        })?;
+                    let module = load_udf(&udf)?;
 
                     let mut frame = stack.pop().ok_or_else(|| {
                         Status::invalid_argument("Could not apply udf: no input data frame")
                     })?;
+                    frame.pseudonymized.retain(|name| !columns.contains(name));
                     for name in columns {
                         let idx = frame
                             .df
@@ -113,8 +500,15 @@ impl CompositePlan {
                 }
                 CompositePlanSegment::EntryPointPlanSegment { identifier } => {
                     let df = state.get_df_unchecked(&identifier)?;
+                    let pseudonymized = state.with_df_artifact_ref(&identifier, |artifact| {
+                        artifact.pseudonymized.clone()
+                    })?;
                     let stats = DataFrameStats::new(identifier);
-                    stack.push(StackFrame { df, stats });
+                    stack.push(StackFrame {
+                        df,
+                        stats,
+                        pseudonymized,
+                    });
                 }
                 CompositePlanSegment::StackPlanSegment => {
                     let frame1 = stack.pop().ok_or_else(|| {
@@ -130,7 +524,13 @@ impl CompositePlan {
                     })?;
                     let mut stats = frame1.stats;
                     stats.merge(frame2.stats);
-                    stack.push(StackFrame { df, stats });
+                    let mut pseudonymized = frame1.pseudonymized;
+                    pseudonymized.retain(|name| frame2.pseudonymized.contains(name));
+                    stack.push(StackFrame {
+                        df,
+                        stats,
+                        pseudonymized,
+                    });
                 }
                 CompositePlanSegment::RowCountSegment { row: name } => {
                     let frame = stack.pop().ok_or(Status::invalid_argument(
@@ -143,7 +543,233 @@ impl CompositePlan {
                         ))
                     })?;
                     let stats = frame.stats;
-                    stack.push(StackFrame { df, stats });
+                    let mut pseudonymized = frame.pseudonymized;
+                    pseudonymized.retain(|column| column != &name);
+                    stack.push(StackFrame {
+                        df,
+                        stats,
+                        pseudonymized,
+                    });
+                }
+                CompositePlanSegment::DpAggregationSegment { aggs, eps } => {
+                    let frame = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument(
+                            "Could not apply private aggregation: no input data frame",
+                        )
+                    })?;
+                    if !(eps > 0.0 && eps.is_finite()) || aggs.is_empty() {
+                        return Err(Status::invalid_argument(
+                            "Private aggregations require columns to aggregate and a positive ε",
+                        ));
+                    }
+                    let sensitivity = frame.stats.row_sensitivity()?;
+                    let df = dp_aggregate(&frame.df, &aggs, eps, sensitivity)?;
+                    // Only expended once the aggregation succeeded.
+                    state.expend_dp_budget(frame.stats.0.keys(), eps)?;
+                    for agg in aggs.iter() {
+                        blacklist_hashmap.insert(agg.column.clone(), agg.output_name());
+                    }
+                    let mut stats = frame.stats;
+                    stats.update_dp_eps(eps);
+                    stack.push(StackFrame {
+                        df,
+                        stats,
+                        pseudonymized: Vec::new(),
+                    });
+                }
+                CompositePlanSegment::PivotSegment {
+                    values,
+                    index,
+                    columns,
+                    agg,
+                } => {
+                    let frame = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument("Could not apply pivot: no input data frame")
+                    })?;
+                    // The values of `columns` name the columns of the result.
+                    check_unsanitized(
+                        state,
+                        &frame.stats,
+                        values.iter().chain(columns.iter()),
+                        "pivot",
+                    )?;
+                    let threshold = pivot_threshold(state, &frame.stats, user_id)?;
+                    let input = if threshold > 1 {
+                        drop_small_pivot_groups(&frame.df, &columns, threshold)?
+                    } else {
+                        frame.df
+                    };
+                    let df = pivot(&input, &values, &index, &columns, agg.into(), true).map_err(
+                        |e| Status::invalid_argument(format!("Error while running pivot: {}", e)),
+                    )?;
+                    let mut stats = frame.stats;
+                    // Empty inputs are left unfetchable rather than having an undefined group size.
+                    if agg.is_aggregation() && input.height() > 0 {
+                        let keys: Vec<Expr> = index
+                            .iter()
+                            .chain(columns.iter())
+                            .map(|name| col(name))
+                            .collect();
+                        stats.update_agg_size(min_group_size(input.lazy(), &keys)?);
+                    }
+                    stack.push(StackFrame {
+                        df,
+                        stats,
+                        pseudonymized: Vec::new(),
+                    });
+                }
+                CompositePlanSegment::SketchSegment { sketches, eps } => {
+                    let frame = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument("Could not apply sketches: no input data frame")
+                    })?;
+                    check_unsanitized(
+                        state,
+                        &frame.stats,
+                        sketches.iter().map(|sketch| &sketch.column),
+                        "sketch",
+                    )?;
+                    let noise = match eps {
+                        Some(eps) => Some((eps, frame.stats.row_sensitivity()?)),
+                        None => None,
+                    };
+                    let df = sketch_aggregate(&frame.df, &sketches, noise)?;
+                    let mut stats = frame.stats;
+                    stats.update_agg_size(frame.df.height());
+                    if let Some(eps) = eps {
+                        // Only expended once the sketches succeeded.
+                        state.expend_dp_budget(stats.0.keys(), eps)?;
+                        stats.update_dp_eps(eps);
+                    }
+                    stack.push(StackFrame {
+                        df,
+                        stats,
+                        pseudonymized: Vec::new(),
+                    });
+                }
+                CompositePlanSegment::WindowSegment { functions } => {
+                    let frame = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument(
+                            "Could not apply window functions: no input data frame",
+                        )
+                    })?;
+                    let df = run_window_functions(&frame.df, &functions)?;
+                    let mut stats = frame.stats;
+                    let mut scaling = 1;
+                    for function in functions.iter() {
+                        let (min_size, max_size) =
+                            partition_sizes(&frame.df, &function.partition_by)?;
+                        stats.update_min_partition_size(min_size);
+                        scaling = scaling.max(function.scaling(max_size));
+                    }
+                    stats.update_join_scaling(scaling);
+                    let mut pseudonymized = frame.pseudonymized;
+                    pseudonymized.retain(|name| {
+                        !functions
+                            .iter()
+                            .any(|function| &function.output_name() == name)
+                    });
+                    stack.push(StackFrame {
+                        df,
+                        stats,
+                        pseudonymized,
+                    });
+                }
+                CompositePlanSegment::PseudonymizeSegment { columns, key } => {
+                    let mut frame = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument("Could not pseudonymize: no input data frame")
+                    })?;
+                    state.pseudonymize(
+                        &mut frame.df,
+                        &columns,
+                        &key,
+                        frame.stats.0.keys(),
+                        user_id,
+                    )?;
+                    for name in columns {
+                        if !frame.pseudonymized.contains(&name) {
+                            frame.pseudonymized.push(name);
+                        }
+                    }
+                    stack.push(frame);
+                }
+                CompositePlanSegment::StringSimilaritySegment { similarities } => {
+                    let frame = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument(
+                            "Could not compute string similarities: no input data frame",
+                        )
+                    })?;
+                    check_string_similarity(state, &frame.stats)?;
+                    check_unsanitized(
+                        state,
+                        &frame.stats,
+                        similarities
+                            .iter()
+                            .flat_map(|similarity| similarity.columns()),
+                        "compare the strings of",
+                    )?;
+                    let df = run_string_similarities(&frame.df, &similarities)?;
+                    let mut pseudonymized = frame.pseudonymized;
+                    pseudonymized.retain(|name| {
+                        !similarities
+                            .iter()
+                            .any(|similarity| &similarity.output_name() == name)
+                    });
+                    stack.push(StackFrame {
+                        df,
+                        stats: frame.stats,
+                        pseudonymized,
+                    });
+                }
+                CompositePlanSegment::LinkageSegment {
+                    blocking,
+                    fields,
+                    threshold,
+                    output,
+                } => {
+                    let right = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument("Could not link records: no input data frame")
+                    })?;
+                    let left = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument("Could not link records: no left data frame")
+                    })?;
+                    for field in blocking.iter().chain(fields.iter()) {
+                        check_pseudonymized(&left.pseudonymized, &field.left)?;
+                        check_pseudonymized(&right.pseudonymized, &field.right)?;
+                    }
+                    let linkage = link_records(&left.df, &right.df, &blocking, &fields, threshold)?;
+                    let df = linkage_output(&left.df, &right.df, &linkage, output)?;
+                    let (mut stats, mut right_stats) = (left.stats, right.stats);
+                    let mut pseudonymized = Vec::new();
+                    match output {
+                        LinkageOutput::Statistics => {
+                            stats.merge(right_stats);
+                            stats.update_agg_size(left.df.height().min(right.df.height()));
+                        }
+                        LinkageOutput::Linked => {
+                            for name in right.df.get_column_names() {
+                                if left.df.column(name).is_ok() {
+                                    blacklist_hashmap
+                                        .insert(name.to_string(), format!("{}_right", name));
+                                }
+                            }
+                            stats.update_join_scaling(linkage.max_matches(true));
+                            right_stats.update_join_scaling(linkage.max_matches(false));
+                            stats.merge(right_stats);
+                            pseudonymized = left.pseudonymized;
+                            for name in right.pseudonymized {
+                                if left.df.column(&name).is_ok() {
+                                    pseudonymized.push(format!("{}_right", name));
+                                } else {
+                                    pseudonymized.push(name);
+                                }
+                            }
+                        }
+                    }
+                    stack.push(StackFrame {
+                        df,
+                        stats,
+                        pseudonymized,
+                    });
                 }
             }
         }
@@ -154,11 +780,20 @@ impl CompositePlan {
             ));
         }
 
-        let StackFrame { df, stats } = stack.pop().unwrap();
+        let StackFrame {
+            df,
+            stats,
+            pseudonymized,
+        } = stack.pop().unwrap();
 
         let mut policy = Policy::allow_by_default();
+        let mut dp_sources = Vec::new();
+        let mut fetch_sources = Vec::new();
+        let mut origins = Vec::new();
         let mut blacklist = Vec::new();
         let mut fetchable = VerificationResult::Safe;
+        // Results expire with the first of their inputs to expire.
+        let mut expires_at = None;
 
         for (identifier, stats) in stats.0.into_iter() {
             state.with_df_artifact_ref(&identifier, |artifact| -> Result<(), Status> {
@@ -170,8 +805,14 @@ impl CompositePlan {
 
                 if let VerificationResult::Unsafe { .. } = check {
                     policy = policy.merge(&artifact.policy);
+                    if artifact.policy.dp_limits().is_some() {
+                        dp_sources.extend(artifact.budget_sources(&identifier));
+                    }
                 }
                 fetchable.merge(check);
+                // Safe results count against fetch limits too.
+                fetch_sources.extend(artifact.limit_sources(&identifier));
+                origins.extend(artifact.origins(&identifier));
 
                 for (key, val) in blacklist_hashmap.iter() {
                     if artifact.blacklist[..].contains(&key.to_string()) {
@@ -179,21 +820,826 @@ impl CompositePlan {
                     }
                 }
                 blacklist.extend_from_slice(&artifact.blacklist[..]);
+                expires_at = match (expires_at, artifact.expires_at) {
+                    (Some(a), Some(b)) => Some(std::cmp::min(a, b)),
+                    (a, b) => a.or(b),
+                };
 
                 Ok(())
             })??;
         }
 
+        dp_sources.sort();
+        dp_sources.dedup();
+        fetch_sources.sort();
+        fetch_sources.dedup();
+        origins.sort();
+        origins.dedup();
+
         Ok(DataFrameArtifact {
             dataframe: df,
             fetchable,
             policy,
+            dp_expended: 0.0,
+            dp_sources,
+            fetch_sources,
+            origins,
             blacklist,
             query_details: plan_str,
+            expires_at,
+            owner: None,
+            created_at: Some(std::time::SystemTime::now()),
+            pseudonymized,
+        })
+    }
+}
+
+/// Static view of a data frame of the stack of a plan being validated.
+struct EstimateFrame {
+    /// Empty data frame with the schema of the data frame.
+    df: DataFrame,
+    /// Estimated number of rows.
+    rows: usize,
+    stats: DataFrameStats,
+}
+
+/// Outcome of the static analysis of a plan, see [`CompositePlan::validate`].
+#[derive(Debug)]
+pub struct PlanReport {
+    /// Empty data frame with the schema of the result.
+    pub schema: DataFrame,
+    pub estimated_rows: usize,
+    pub estimated_bytes: usize,
+    pub scanned_rows: usize,
+    pub scanned_bytes: usize,
+    pub warnings: Vec<String>,
+    /// Whether the sizes are withheld, see [`CompositePlan::validate`].
+    pub sizes_withheld: bool,
+}
+
+impl CompositePlan {
+    /// Checks the plan against the schemas and policies of its inputs and estimates the
+    /// size of its result, without running it.
+    ///
+    /// Estimates assume that join keys are unique on one side and that groupbys do not
+    /// reduce the number of rows. Policies are checked assuming that aggregations are
+    /// large enough, so warnings only report issues that running the plan would hit.
+    ///
+    /// Sizes count the rows of the inputs, so they are withheld unless the policies of all
+    /// the inputs let `user_id` fetch their rows as they are.
+    pub fn validate(&self, state: &BastionLabPolars, user_id: &str) -> Result<PlanReport, Status> {
+        let mut stack = Vec::new();
+        let mut scanned_rows = 0;
+        let mut scanned_bytes = 0;
+        let mut sizes_withheld = false;
+        // Average size of the values of the columns of the inputs.
+        let mut value_sizes = HashMap::new();
+        let mut warnings = Vec::new();
+
+        for seg in self.segments.iter() {
+            match seg {
+                CompositePlanSegment::PolarsPlanSegment { plan } => {
+                    let mut plan = plan.clone();
+                    let (stats, rows) = estimate_plan(&mut plan, &mut stack)?;
+                    let df = lazy_frame_from_logical_plan(plan).collect().map_err(|e| {
+                        Status::invalid_argument(format!("Could not run logical plan: {}", e))
+                    })?;
+                    stack.push(EstimateFrame { df, rows, stats });
+                }
+                CompositePlanSegment::UdfPlanSegment { columns, udf } => {
+                    load_udf(udf)?;
+                    let frame = stack.last().ok_or_else(|| {
+                        Status::invalid_argument("Could not apply udf: no input data frame")
+                    })?;
+                    for name in columns {
+                        frame.df.column(name).map_err(|_| {
+                            Status::invalid_argument(format!(
+                                "Could not apply udf: no column `{}` in data frame",
+                                name
+                            ))
+                        })?;
+                    }
+                }
+                CompositePlanSegment::EntryPointPlanSegment { identifier } => {
+                    let df = state.get_df_unchecked(identifier)?;
+                    if !rows_fetchable(state, identifier, user_id)? {
+                        sizes_withheld = true;
+                    }
+                    scanned_rows += df.height();
+                    scanned_bytes += df.estimated_size();
+                    if df.height() > 0 {
+                        for series in df.get_columns() {
+                            value_sizes.insert(
+                                series.name().to_string(),
+                                series.estimated_size() / df.height(),
+                            );
+                        }
+                    }
+                    stack.push(EstimateFrame {
+                        rows: df.height(),
+                        df: df.slice(0, 0),
+                        stats: DataFrameStats::new(identifier.clone()),
+                    });
+                }
+                CompositePlanSegment::StackPlanSegment => {
+                    let frame1 = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument("Could not apply stack: no input data frame")
+                    })?;
+                    let frame2 = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument("Could not apply stack: no df2 input data frame")
+                    })?;
+                    let df = frame1.df.vstack(&frame2.df).map_err(|e| {
+                        Status::invalid_argument(format!("Error while running vstack: {}", e))
+                    })?;
+                    let mut stats = frame1.stats;
+                    stats.merge(frame2.stats);
+                    stack.push(EstimateFrame {
+                        df,
+                        rows: frame1.rows + frame2.rows,
+                        stats,
+                    });
+                }
+                CompositePlanSegment::RowCountSegment { row: name } => {
+                    let frame = stack.pop().ok_or(Status::invalid_argument(
+                        "Could not apply with_row_count: no input data frame",
+                    ))?;
+                    let df = frame.df.with_row_count(name, Some(0)).map_err(|e| {
+                        Status::invalid_argument(format!(
+                            "Error while running with_row_count: {}",
+                            e
+                        ))
+                    })?;
+                    stack.push(EstimateFrame { df, ..frame });
+                }
+                CompositePlanSegment::DpAggregationSegment { aggs, eps } => {
+                    let frame = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument(
+                            "Could not apply private aggregation: no input data frame",
+                        )
+                    })?;
+                    if !(*eps > 0.0 && eps.is_finite()) || aggs.is_empty() {
+                        return Err(Status::invalid_argument(
+                            "Private aggregations require columns to aggregate and a positive ε",
+                        ));
+                    }
+                    frame.stats.row_sensitivity()?;
+                    // Checks the columns and bounds on the empty data frame.
+                    let df = dp_aggregate(&frame.df, aggs, *eps, 1.0)?;
+                    if let Err(e) = state.check_dp_budget(frame.stats.0.keys(), *eps) {
+                        warnings.push(e.message().to_string());
+                    }
+                    let mut stats = frame.stats;
+                    stats.update_dp_eps(*eps);
+                    stack.push(EstimateFrame { df, rows: 1, stats });
+                }
+                CompositePlanSegment::PivotSegment {
+                    values,
+                    index,
+                    columns,
+                    agg,
+                } => {
+                    let frame = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument("Could not apply pivot: no input data frame")
+                    })?;
+                    for name in values.iter().chain(index.iter()).chain(columns.iter()) {
+                        frame.df.column(name).map_err(|_| {
+                            Status::invalid_argument(format!(
+                                "Could not apply pivot: no column `{}` in data frame",
+                                name
+                            ))
+                        })?;
+                    }
+                    check_unsanitized(
+                        state,
+                        &frame.stats,
+                        values.iter().chain(columns.iter()),
+                        "pivot",
+                    )?;
+                    pivot_threshold(state, &frame.stats, user_id)?;
+                    // The other columns are named after values of the data.
+                    let df = frame.df.select(index).map_err(|e| {
+                        Status::invalid_argument(format!("Error while running pivot: {}", e))
+                    })?;
+                    warnings.push(String::from(
+                        "The columns of a pivot depend on the data and are not part of the header",
+                    ));
+                    let mut stats = frame.stats;
+                    if agg.is_aggregation() {
+                        stats.update_agg_size(usize::MAX);
+                    }
+                    stack.push(EstimateFrame {
+                        df,
+                        rows: frame.rows,
+                        stats,
+                    });
+                }
+                CompositePlanSegment::SketchSegment { sketches, eps } => {
+                    let frame = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument("Could not apply sketches: no input data frame")
+                    })?;
+                    check_unsanitized(
+                        state,
+                        &frame.stats,
+                        sketches.iter().map(|sketch| &sketch.column),
+                        "sketch",
+                    )?;
+                    let noise = match eps {
+                        Some(eps) => Some((*eps, frame.stats.row_sensitivity()?)),
+                        None => None,
+                    };
+                    // Checks the columns and parameters on the empty data frame.
+                    let df = sketch_aggregate(&frame.df, sketches, noise)?;
+                    let mut stats = frame.stats;
+                    stats.update_agg_size(frame.rows);
+                    if let Some(eps) = eps {
+                        if let Err(e) = state.check_dp_budget(stats.0.keys(), *eps) {
+                            warnings.push(e.message().to_string());
+                        }
+                        stats.update_dp_eps(*eps);
+                    }
+                    stack.push(EstimateFrame { df, rows: 1, stats });
+                }
+                CompositePlanSegment::WindowSegment { functions } => {
+                    let frame = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument(
+                            "Could not apply window functions: no input data frame",
+                        )
+                    })?;
+                    let df = run_window_functions(&frame.df, functions)?;
+                    // Partitions are at most as large as the data frame.
+                    let scaling = functions
+                        .iter()
+                        .map(|function| function.scaling(frame.rows))
+                        .max()
+                        .unwrap_or(1);
+                    let mut stats = frame.stats;
+                    stats.update_join_scaling(scaling);
+                    stack.push(EstimateFrame {
+                        df,
+                        rows: frame.rows,
+                        stats,
+                    });
+                }
+                CompositePlanSegment::PseudonymizeSegment { columns, key } => {
+                    let mut frame = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument("Could not pseudonymize: no input data frame")
+                    })?;
+                    // Checks the key and columns on the empty data frame.
+                    state.pseudonymize(
+                        &mut frame.df,
+                        columns,
+                        key,
+                        frame.stats.0.keys(),
+                        user_id,
+                    )?;
+                    stack.push(frame);
+                }
+                CompositePlanSegment::StringSimilaritySegment { similarities } => {
+                    let frame = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument(
+                            "Could not compute string similarities: no input data frame",
+                        )
+                    })?;
+                    check_string_similarity(state, &frame.stats)?;
+                    check_unsanitized(
+                        state,
+                        &frame.stats,
+                        similarities
+                            .iter()
+                            .flat_map(|similarity| similarity.columns()),
+                        "compare the strings of",
+                    )?;
+                    // Checks the columns on the empty data frame.
+                    let df = run_string_similarities(&frame.df, similarities)?;
+                    stack.push(EstimateFrame { df, ..frame });
+                }
+                CompositePlanSegment::LinkageSegment {
+                    blocking,
+                    fields,
+                    threshold,
+                    output,
+                } => {
+                    let right = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument("Could not link records: no input data frame")
+                    })?;
+                    let left = stack.pop().ok_or_else(|| {
+                        Status::invalid_argument("Could not link records: no left data frame")
+                    })?;
+                    // Checks the fields and parameters on the empty data frames.
+                    let linkage = link_records(&left.df, &right.df, blocking, fields, *threshold)?;
+                    let df = linkage_output(&left.df, &right.df, &linkage, *output)?;
+                    let mut stats = left.stats;
+                    stats.merge(right.stats);
+                    let rows = match output {
+                        LinkageOutput::Statistics => {
+                            stats.update_agg_size(left.rows.min(right.rows));
+                            1
+                        }
+                        LinkageOutput::Linked => left.rows.min(right.rows),
+                    };
+                    stack.push(EstimateFrame { df, rows, stats });
+                }
+            }
+        }
+
+        if stack.len() != 1 {
+            return Err(Status::invalid_argument(
+                "Wrong number of input data frames",
+            ));
+        }
+
+        let EstimateFrame { df, rows, stats } = stack.pop().unwrap();
+
+        for (identifier, stats) in stats.0.into_iter() {
+            state.with_df_artifact_ref(&identifier, |artifact| -> Result<(), Status> {
+                let check = artifact.policy.verify(&Context {
+                    stats,
+                    user_id: String::from(user_id),
+                    df_identifier: identifier.clone(),
+                })?;
+                if let VerificationResult::Unsafe { action, reason } = check {
+                    warnings.push(format!(
+                        "Fetching the result will be handled with action {:?}: {}",
+                        action, reason
+                    ));
+                }
+                for name in artifact.blacklist.iter() {
+                    if df.column(name).is_ok() {
+                        warnings.push(format!("Column `{}` of the result will be sanitized", name));
+                    }
+                }
+                Ok(())
+            })??;
+        }
+
+        if sizes_withheld {
+            warnings.push(String::from(
+                "Sizes are withheld: the policy of an input does not allow fetching its rows",
+            ));
+            return Ok(PlanReport {
+                schema: df,
+                estimated_rows: 0,
+                estimated_bytes: 0,
+                scanned_rows: 0,
+                scanned_bytes: 0,
+                warnings,
+                sizes_withheld,
+            });
+        }
+
+        // Values computed by the plan are counted as 8 bytes.
+        let row_size: usize = df
+            .get_column_names()
+            .iter()
+            .map(|name| value_sizes.get(*name).copied().unwrap_or(8))
+            .sum();
+
+        Ok(PlanReport {
+            schema: df,
+            estimated_rows: rows,
+            estimated_bytes: rows.saturating_mul(row_size),
+            scanned_rows,
+            scanned_bytes,
+            warnings,
+            sizes_withheld,
         })
     }
 }
 
+/// Returns whether the policy of the data frame `identifier` lets `user_id` fetch its rows
+/// as they are, without aggregation nor differential privacy.
+pub fn rows_fetchable(
+    state: &BastionLabPolars,
+    identifier: &str,
+    user_id: &str,
+) -> Result<bool, Status> {
+    let stats = DataFrameStats::new(identifier.to_string());
+    state.with_df_artifact_ref(identifier, |artifact| {
+        let check = artifact.policy.verify(&Context {
+            stats: stats.0[identifier],
+            user_id: String::from(user_id),
+            df_identifier: identifier.to_string(),
+        })?;
+        Ok(check == VerificationResult::Safe)
+    })?
+}
+
+/// Returns the number of rows below which the groups of values of the pivoted columns are
+/// dropped, as the values name the columns of the result whether or not it is fetched.
+///
+/// Inputs whose rows `user_id` may fetch require no threshold, and inputs whose policy
+/// requires no minimum aggregation size cannot be pivoted.
+fn pivot_threshold(
+    state: &BastionLabPolars,
+    stats: &DataFrameStats,
+    user_id: &str,
+) -> Result<usize, Status> {
+    let mut threshold = 0;
+    for (identifier, entry) in stats.0.iter() {
+        if rows_fetchable(state, identifier, user_id)? {
+            continue;
+        }
+        let min_agg_size = state
+            .with_df_artifact_ref(identifier, |artifact| artifact.policy.min_agg_size())?
+            .ok_or_else(|| {
+                ErrorCode::PolicyDenied.status(format!(
+                    "Cannot pivot DataFrame {}: its policy protects the values naming the columns of the result",
+                    identifier
+                ))
+            })?;
+        threshold = threshold.max(min_agg_size.saturating_mul(entry.join_scaling.max(1)));
+    }
+    Ok(threshold)
+}
+
+/// Drops the rows of `df` whose values of `columns` are shared by fewer than `threshold`
+/// rows.
+fn drop_small_pivot_groups(
+    df: &DataFrame,
+    columns: &[String],
+    threshold: usize,
+) -> Result<DataFrame, Status> {
+    let keys: Vec<Expr> = columns.iter().map(|name| col(name)).collect();
+    let first = keys
+        .first()
+        .cloned()
+        .ok_or_else(|| Status::invalid_argument("Could not apply pivot: no pivoted column"))?;
+    df.clone()
+        .lazy()
+        .with_column(
+            first
+                .count()
+                .over(keys)
+                .cast(DataType::UInt64)
+                .alias("__pivot_count"),
+        )
+        .filter(col("__pivot_count").gt_eq(lit(threshold as u64)))
+        .drop_columns(["__pivot_count"])
+        .collect()
+        .map_err(|e| Status::invalid_argument(format!("Error while running pivot: {}", e)))
+}
+
+/// Checks that none of the columns `names` is sanitized in the inputs, for operations
+/// whose results would leak them under other names.
+fn check_unsanitized<'a>(
+    state: &BastionLabPolars,
+    stats: &DataFrameStats,
+    names: impl Iterator<Item = &'a String> + Clone,
+    operation: &str,
+) -> Result<(), Status> {
+    for identifier in stats.0.keys() {
+        state.with_df_artifact_ref(identifier, |artifact| {
+            match names.clone().find(|name| artifact.blacklist.contains(name)) {
+                Some(name) => Err(Status::invalid_argument(format!(
+                    "Cannot {} column `{}`: it is sanitized in DataFrame {}",
+                    operation, name, identifier
+                ))),
+                None => Ok(()),
+            }
+        })??;
+    }
+    Ok(())
+}
+
+/// Fails unless the policies of the data frames `stats` derives from allow string
+/// similarities.
+fn check_string_similarity(state: &BastionLabPolars, stats: &DataFrameStats) -> Result<(), Status> {
+    for identifier in stats.0.keys() {
+        state.with_df_artifact_ref(identifier, |artifact| {
+            if artifact.policy.allows_string_similarity() {
+                Ok(())
+            } else {
+                Err(ErrorCode::PolicyDenied.status(format!(
+                    "The policy of DataFrame {} does not allow string similarities",
+                    identifier
+                )))
+            }
+        })??;
+    }
+    Ok(())
+}
+
+fn run_string_similarities(
+    df: &DataFrame,
+    similarities: &[StringSimilarity],
+) -> Result<DataFrame, Status> {
+    if similarities.is_empty() {
+        return Err(Status::invalid_argument(
+            "String similarity segments require similarities to compute",
+        ));
+    }
+    let strings = |name: &str| -> Result<Series, Status> {
+        df.column(name)
+            .and_then(|series| series.cast(&DataType::Utf8))
+            .map_err(|e| {
+                Status::invalid_argument(format!(
+                    "Could not compare the strings of column `{}`: {}",
+                    name, e
+                ))
+            })
+    };
+    let mut df = df.clone();
+    for similarity in similarities {
+        let values = strings(&similarity.column)?;
+        let others = match (&similarity.other, &similarity.value) {
+            (Some(other), _) => strings(other)?,
+            (None, Some(value)) => Series::new("", vec![value.as_str(); values.len()]),
+            (None, None) => {
+                return Err(Status::invalid_argument(format!(
+                    "The strings of column `{}` must be compared with a column or a value",
+                    similarity.column
+                )))
+            }
+        };
+        let pairs = values
+            .utf8()
+            .unwrap()
+            .into_iter()
+            .zip(others.utf8().unwrap().into_iter());
+        let name = similarity.output_name();
+        let series = match similarity.kind {
+            StringSimilarityKind::Levenshtein => {
+                let distances: Vec<Option<u32>> = pairs
+                    .map(|pair| match pair {
+                        (Some(a), Some(b)) => Some(levenshtein(a, b) as u32),
+                        _ => None,
+                    })
+                    .collect();
+                Series::new(&name, distances)
+            }
+            StringSimilarityKind::JaroWinkler => {
+                let similarities: Vec<Option<f64>> = pairs
+                    .map(|pair| match pair {
+                        (Some(a), Some(b)) => Some(jaro_winkler(a, b)),
+                        _ => None,
+                    })
+                    .collect();
+                Series::new(&name, similarities)
+            }
+        };
+        df.with_column(series).map_err(|e| {
+            Status::invalid_argument(format!("Error while computing string similarities: {}", e))
+        })?;
+    }
+    Ok(df)
+}
+
+fn run_window_functions(df: &DataFrame, functions: &[WindowFunction]) -> Result<DataFrame, Status> {
+    if functions.is_empty() {
+        return Err(Status::invalid_argument(
+            "Window segments require functions to compute",
+        ));
+    }
+    let exprs = functions
+        .iter()
+        .map(WindowFunction::expr)
+        .collect::<Result<Vec<_>, _>>()?;
+    df.clone()
+        .lazy()
+        .with_columns(exprs)
+        .collect()
+        .map_err(|e| {
+            Status::invalid_argument(format!("Error while running window functions: {}", e))
+        })
+}
+
+/// Returns the number of rows of the smallest and largest partitions of `df` by
+/// `partition_by`.
+fn partition_sizes(df: &DataFrame, partition_by: &[String]) -> Result<(usize, usize), Status> {
+    if partition_by.is_empty() || df.height() == 0 {
+        return Ok((df.height(), df.height()));
+    }
+    let keys: Vec<Expr> = partition_by.iter().map(|name| col(name)).collect();
+    let sizes = df
+        .clone()
+        .lazy()
+        .with_row_count("__count", None)
+        .groupby(keys)
+        .agg([col("__count").count()])
+        .cache();
+    Ok((
+        usize_item(sizes.clone().select([col("__count").min()]).collect())?,
+        usize_item(sizes.select([col("__count").max()]).collect())?,
+    ))
+}
+
+/// Fails unless the column `name` is among the `pseudonymized` columns of its data frame.
+fn check_pseudonymized(pseudonymized: &[String], name: &str) -> Result<(), Status> {
+    if pseudonymized.iter().any(|column| column == name) {
+        Ok(())
+    } else {
+        Err(Status::invalid_argument(format!(
+            "Record linkage only compares pseudonymized fields: column `{}` was not pseudonymized by the server",
+            name
+        )))
+    }
+}
+
+/// Returns the string columns of `df` whose values are all pseudonyms held by the
+/// pseudonymized columns of the `inputs` it was computed from, with their pseudonymized
+/// columns.
+fn carried_pseudonyms(df: &DataFrame, inputs: &[(DataFrame, Vec<String>)]) -> Vec<String> {
+    let mut known = HashSet::new();
+    for (input, names) in inputs {
+        for name in names {
+            if let Ok(values) = input.column(name).and_then(|series| series.utf8()) {
+                known.extend(values.into_iter().flatten());
+            }
+        }
+    }
+    if known.is_empty() {
+        return Vec::new();
+    }
+    df.get_columns()
+        .iter()
+        .filter(|series| match series.utf8() {
+            Ok(values) => values
+                .into_iter()
+                .flatten()
+                .all(|value| known.contains(value)),
+            Err(_) => false,
+        })
+        .map(|series| series.name().to_string())
+        .collect()
+}
+
+/// Reads the values of the column `name` of `df`, which must hold strings.
+fn pseudonym_column<'a>(df: &'a DataFrame, name: &str) -> Result<Vec<Option<&'a str>>, Status> {
+    let series = df.column(name).map_err(|_| {
+        Status::invalid_argument(format!(
+            "Could not link records: no column `{}` in data frame",
+            name
+        ))
+    })?;
+    let values = series.utf8().map_err(|_| {
+        Status::invalid_argument(format!(
+            "Record linkage only compares pseudonymized fields: column `{}` holds other values",
+            name
+        ))
+    })?;
+    Ok(values.into_iter().collect())
+}
+
+/// Matches the rows of `left` and `right` whose `blocking` fields are equal and whose
+/// score, the share of the weights of the `fields` that are equal, is at least
+/// `threshold`. Missing values never match.
+fn link_records(
+    left: &DataFrame,
+    right: &DataFrame,
+    blocking: &[LinkageField],
+    fields: &[LinkageField],
+    threshold: f64,
+) -> Result<Linkage, Status> {
+    if fields.is_empty() {
+        return Err(Status::invalid_argument(
+            "Record linkage requires fields to compare",
+        ));
+    }
+    if fields
+        .iter()
+        .any(|field| !(field.weight > 0.0 && field.weight.is_finite()))
+    {
+        return Err(Status::invalid_argument(
+            "The weights of linkage fields must be positive",
+        ));
+    }
+    if !(threshold > 0.0 && threshold <= 1.0) {
+        return Err(Status::invalid_argument(
+            "The threshold of a record linkage must be in (0, 1]",
+        ));
+    }
+
+    let columns = |fields: &[LinkageField]| -> Result<Vec<_>, Status> {
+        fields
+            .iter()
+            .map(|field| {
+                Ok((
+                    pseudonym_column(left, &field.left)?,
+                    pseudonym_column(right, &field.right)?,
+                ))
+            })
+            .collect()
+    };
+    let blocking = columns(blocking)?;
+    let compared = columns(fields)?;
+
+    // Right rows per value of the blocking fields, all in one block without blocking fields.
+    let key = |row: usize, left_side: bool| {
+        blocking
+            .iter()
+            .map(|(l, r)| if left_side { l[row] } else { r[row] })
+            .collect::<Option<Vec<_>>>()
+    };
+    let mut blocks = HashMap::new();
+    for row in 0..right.height() {
+        if let Some(key) = key(row, false) {
+            blocks.entry(key).or_insert_with(Vec::new).push(row);
+        }
+    }
+    // Right rows compared with every left row.
+    let no_rows = Vec::new();
+    let candidates_of: Vec<&Vec<usize>> = (0..left.height())
+        .map(|row| {
+            key(row, true)
+                .and_then(|key| blocks.get(&key))
+                .unwrap_or(&no_rows)
+        })
+        .collect();
+
+    let candidates: usize = candidates_of.iter().map(|rows| rows.len()).sum();
+    if candidates > MAX_LINKAGE_CANDIDATES {
+        return Err(Status::invalid_argument(format!(
+            "Record linkage would compare {} pairs of rows, more than {}: use blocking fields",
+            candidates, MAX_LINKAGE_CANDIDATES
+        )));
+    }
+
+    let total_weight: f64 = fields.iter().map(|field| field.weight).sum();
+    let mut pairs = Vec::new();
+    for i in 0..left.height() {
+        for &j in candidates_of[i].iter() {
+            let agreement: f64 = fields
+                .iter()
+                .zip(compared.iter())
+                .filter(|(_, (l, r))| l[i].is_some() && l[i] == r[j])
+                .map(|(field, _)| field.weight)
+                .sum();
+            let score = agreement / total_weight;
+            if score >= threshold {
+                pairs.push((i, j, score));
+            }
+        }
+    }
+    Ok(Linkage { pairs, candidates })
+}
+
+/// Builds the `output` of the record linkage of `left` and `right`.
+fn linkage_output(
+    left: &DataFrame,
+    right: &DataFrame,
+    linkage: &Linkage,
+    output: LinkageOutput,
+) -> Result<DataFrame, Status> {
+    let polars_error =
+        |e: PolarsError| Status::internal(format!("Error while linking records: {}", e));
+    match output {
+        LinkageOutput::Statistics => {
+            let distinct = |left_side: bool| {
+                let mut rows: Vec<_> = linkage
+                    .pairs
+                    .iter()
+                    .map(|&(i, j, _)| if left_side { i } else { j })
+                    .collect();
+                rows.sort_unstable();
+                rows.dedup();
+                rows.len() as u64
+            };
+            DataFrame::new(vec![
+                Series::new("left_rows", [left.height() as u64]),
+                Series::new("right_rows", [right.height() as u64]),
+                Series::new("candidate_pairs", [linkage.candidates as u64]),
+                Series::new("matched_pairs", [linkage.pairs.len() as u64]),
+                Series::new("matched_left_rows", [distinct(true)]),
+                Series::new("matched_right_rows", [distinct(false)]),
+            ])
+            .map_err(polars_error)
+        }
+        LinkageOutput::Linked => {
+            let indices = |left_side: bool| {
+                let rows: Vec<IdxSize> = linkage
+                    .pairs
+                    .iter()
+                    .map(|&(i, j, _)| (if left_side { i } else { j }) as IdxSize)
+                    .collect();
+                IdxCa::from_vec("", rows)
+            };
+            let linked_left = left.take(&indices(true)).map_err(polars_error)?;
+            let mut linked_right = right.take(&indices(false)).map_err(polars_error)?;
+            for name in right.get_column_names() {
+                if left.column(name).is_ok() {
+                    linked_right
+                        .rename(name, &format!("{}_right", name))
+                        .map_err(polars_error)?;
+                }
+            }
+            let scores: Vec<f64> = linkage.pairs.iter().map(|&(_, _, score)| score).collect();
+            let mut df = linked_left
+                .hstack(linked_right.get_columns())
+                .map_err(polars_error)?;
+            df.with_column(Series::new("linkage_score", scores))
+                .map_err(polars_error)?;
+            Ok(df)
+        }
+    }
+}
+
+fn load_udf(udf: &str) -> Result<CModule, Status> {
+    CModule::load_data(&mut Cursor::new(base64::decode(udf).map_err(|e| {
+        Status::invalid_argument(format!("Could not decode base64-encoded udf: {}", e))
+    })?))
+    .map_err(|e| Status::invalid_argument(format!("Could not deserialize udf from bytes: {}", e)))
+}
+
 fn expr_agg_check(expr: &Expr) -> Result<bool, Status> {
     let mut state = Vec::new();
     expr.visit(&mut state, |expr, state| {
@@ -213,11 +1659,18 @@ fn expr_agg_check(expr: &Expr) -> Result<bool, Status> {
                 let left_agg = state.pop().unwrap();
                 state.push(right_agg && left_agg);
             }
-            Expr::Take { .. } | Expr::SortBy { .. } | Expr::Filter { .. } | Expr::Window { .. } => {
+            Expr::Take { .. } | Expr::SortBy { .. } | Expr::Filter { .. } => {
                 state.pop().unwrap();
                 let expr_agg = state.pop().unwrap();
                 state.push(expr_agg);
             }
+            // Window expressions output a value per row, even when they aggregate partitions.
+            Expr::Window { partition_by, .. } => {
+                for _ in 0..=partition_by.len() {
+                    state.pop().unwrap();
+                }
+                state.push(false);
+            }
             Expr::Ternary { .. } => {
                 let falsy_agg = state.pop().unwrap();
                 let truthy_agg = state.pop().unwrap();
@@ -249,6 +1702,197 @@ fn exprs_agg_check(exprs: &[Expr]) -> Result<bool, Status> {
     Ok(true)
 }
 
+/// Aggregates the columns of `df` into a single row with differential privacy, splitting
+/// `eps` evenly between the aggregations.
+///
+/// `sensitivity` is the number of rows of `df` a single input row may contribute to, which
+/// scales the noise.
+fn dp_aggregate(
+    df: &DataFrame,
+    aggs: &[DpAggregation],
+    eps: f64,
+    sensitivity: f64,
+) -> Result<DataFrame, Status> {
+    let eps = eps / aggs.len() as f64;
+    let mut columns = Vec::with_capacity(aggs.len());
+    for agg in aggs {
+        let series = df.column(&agg.column).map_err(|e| {
+            Status::invalid_argument(format!(
+                "Could not apply private aggregation on column `{}`: {}",
+                agg.column, e
+            ))
+        })?;
+        let count = (series.len() - series.null_count()) as f64;
+        let bound = agg.lower.abs().max(agg.upper.abs());
+        let sum = || -> Result<f64, Status> {
+            if !(agg.lower <= agg.upper && bound.is_finite()) {
+                return Err(Status::invalid_argument(format!(
+                    "Invalid bounds for the private aggregation of column `{}`",
+                    agg.column
+                )));
+            }
+            let values = series.cast(&DataType::Float64).map_err(|e| {
+                Status::invalid_argument(format!(
+                    "Could not privately sum column `{}`: {}",
+                    agg.column, e
+                ))
+            })?;
+            let values = values
+                .f64()
+                .map_err(|e| Status::internal(format!("Could not read column: {}", e)))?;
+            Ok(values
+                .into_iter()
+                .flatten()
+                .filter(|value| !value.is_nan())
+                .map(|value| value.clamp(agg.lower, agg.upper))
+                .sum())
+        };
+        let value = match agg.kind {
+            DpAggregationKind::Count => count + laplace(sensitivity / eps),
+            DpAggregationKind::Sum => sum()? + laplace(sensitivity * bound / eps),
+            DpAggregationKind::Mean => {
+                let sum = sum()? + laplace(2.0 * sensitivity * bound / eps);
+                let count = count + laplace(2.0 * sensitivity / eps);
+                sum / count.max(1.0)
+            }
+        };
+        columns.push(Series::new(&agg.output_name(), &[value]));
+    }
+    DataFrame::new(columns).map_err(|e| {
+        Status::invalid_argument(format!("Could not apply private aggregation: {}", e))
+    })
+}
+
+/// Samples the Laplace distribution centered on 0 with given `scale`.
+fn laplace(scale: f64) -> f64 {
+    let u: f64 = thread_rng().gen_range(-0.5..0.5);
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).max(f64::MIN_POSITIVE).ln()
+}
+
+/// Estimates the distinct counts and quantiles of the columns of `df` with sketches, in a
+/// single row.
+///
+/// When `noise` holds a privacy budget and the number of rows of `df` a single input row
+/// may contribute to, the budget is split evenly between the estimates and Laplace noise
+/// is added to them.
+fn sketch_aggregate(
+    df: &DataFrame,
+    sketches: &[Sketch],
+    noise: Option<(f64, f64)>,
+) -> Result<DataFrame, Status> {
+    let outputs: usize = sketches
+        .iter()
+        .map(|sketch| sketch.output_names().len())
+        .sum();
+    if outputs == 0 {
+        return Err(Status::invalid_argument(
+            "Sketches require columns to estimate and quantiles to compute",
+        ));
+    }
+    if let Some((eps, _)) = noise {
+        if !(eps > 0.0 && eps.is_finite()) {
+            return Err(Status::invalid_argument(
+                "Private sketches require a positive ε",
+            ));
+        }
+    }
+    let noise = noise.map(|(eps, sensitivity)| (eps / outputs as f64, sensitivity));
+
+    let mut columns = Vec::with_capacity(outputs);
+    for sketch in sketches {
+        let series = df.column(&sketch.column).map_err(|e| {
+            Status::invalid_argument(format!(
+                "Could not sketch column `{}`: {}",
+                sketch.column, e
+            ))
+        })?;
+        let names = sketch.output_names();
+        match sketch.kind {
+            SketchKind::DistinctCount => {
+                let values = series.cast(&DataType::Utf8).map_err(|e| {
+                    Status::invalid_argument(format!(
+                        "Could not count the distinct values of column `{}`: {}",
+                        sketch.column, e
+                    ))
+                })?;
+                let estimate = match noise {
+                    // The error of the sketch depends on the values in a way the noise does
+                    // not account for: private counts noise the exact count instead, to which
+                    // a row adds at most one distinct value.
+                    Some((eps, sensitivity)) => {
+                        let count = values.drop_nulls().n_unique().map_err(|e| {
+                            Status::internal(format!("Could not count distinct values: {}", e))
+                        })?;
+                        (count as f64 + laplace(sensitivity / eps)).max(0.0)
+                    }
+                    None => {
+                        let values = values.utf8().map_err(|e| {
+                            Status::internal(format!("Could not read column: {}", e))
+                        })?;
+                        let mut hll = HyperLogLog::new();
+                        for value in values.into_iter().flatten() {
+                            hll.add(value);
+                        }
+                        hll.estimate()
+                    }
+                };
+                columns.push(Series::new(&names[0], &[estimate]));
+            }
+            SketchKind::Quantiles => {
+                if sketch.quantiles.iter().any(|q| !(0.0..=1.0).contains(q)) {
+                    return Err(Status::invalid_argument(format!(
+                        "Quantiles of column `{}` must be between 0 and 1",
+                        sketch.column
+                    )));
+                }
+                let bounded = sketch.lower < sketch.upper
+                    && sketch.lower.is_finite()
+                    && sketch.upper.is_finite();
+                if noise.is_some() && !bounded {
+                    return Err(Status::invalid_argument(format!(
+                        "Invalid bounds for the private quantiles of column `{}`",
+                        sketch.column
+                    )));
+                }
+                let values = series.cast(&DataType::Float64).map_err(|e| {
+                    Status::invalid_argument(format!(
+                        "Could not compute the quantiles of column `{}`: {}",
+                        sketch.column, e
+                    ))
+                })?;
+                let values = values
+                    .f64()
+                    .map_err(|e| Status::internal(format!("Could not read column: {}", e)))?;
+                let mut digest = TDigest::new(TDIGEST_COMPRESSION);
+                for value in values.into_iter().flatten().filter(|value| !value.is_nan()) {
+                    match noise {
+                        Some(_) => digest.add(value.clamp(sketch.lower, sketch.upper)),
+                        None => digest.add(value),
+                    }
+                }
+                for (q, name) in sketch.quantiles.iter().zip(names.iter()) {
+                    let estimate = match noise {
+                        // Empty inputs are estimated around the middle of the bounds, so
+                        // that they cannot be told apart.
+                        Some((eps, sensitivity)) => {
+                            let width = sketch.upper - sketch.lower;
+                            let value = digest.quantile(*q).unwrap_or(sketch.lower + width / 2.0);
+                            Some(
+                                (value + laplace(sensitivity * width / eps))
+                                    .clamp(sketch.lower, sketch.upper),
+                            )
+                        }
+                        None => digest.quantile(*q),
+                    };
+                    columns.push(Series::new(name, &[estimate]));
+                }
+            }
+        }
+    }
+    DataFrame::new(columns)
+        .map_err(|e| Status::invalid_argument(format!("Could not apply sketches: {}", e)))
+}
+
 fn run_logical_plan(plan: LogicalPlan) -> Result<DataFrame, Status> {
     let ldf = lazy_frame_from_logical_plan(plan);
     ldf.collect()
@@ -263,6 +1907,8 @@ impl DataFrameStats {
             StatsEntry {
                 agg_size: 1,
                 join_scaling: 1,
+                dp_eps: None,
+                min_partition_size: usize::MAX,
             },
         );
         DataFrameStats(stats)
@@ -270,7 +1916,7 @@ impl DataFrameStats {
 
     fn update_agg_size(&mut self, agg_size: usize) {
         for stats in self.0.values_mut() {
-            stats.agg_size *= agg_size;
+            stats.agg_size = stats.agg_size.saturating_mul(agg_size);
         }
     }
 
@@ -280,11 +1926,57 @@ impl DataFrameStats {
         }
     }
 
+    fn update_min_partition_size(&mut self, partition_size: usize) {
+        for stats in self.0.values_mut() {
+            stats.min_partition_size = stats.min_partition_size.min(partition_size);
+        }
+    }
+
+    /// Marks the data frame as the result of private aggregations using `eps`.
+    fn update_dp_eps(&mut self, eps: f64) {
+        for stats in self.0.values_mut() {
+            stats.dp_eps = Some(stats.dp_eps.unwrap_or(0.0) + eps);
+        }
+    }
+
+    /// Returns the number of rows of the data frame a single row of its inputs may
+    /// contribute to.
+    fn row_sensitivity(&self) -> Result<f64, Status> {
+        let mut sensitivity = 1;
+        for (identifier, stats) in self.0.iter() {
+            // The rows of the right side of semi and anti joins select rows of the left
+            // side, in unbounded numbers.
+            if stats.join_scaling == 0 {
+                return Err(Status::invalid_argument(format!(
+                    "Cannot privately aggregate a data frame filtered by a semi or anti join on DataFrame {}",
+                    identifier
+                )));
+            }
+            sensitivity = sensitivity.max(stats.join_scaling);
+        }
+        Ok(sensitivity as f64)
+    }
+
     fn merge(&mut self, other: DataFrameStats) {
         for (identifier, stats_left) in self.0.iter_mut() {
             if let Some(stats_right) = other.0.get(identifier) {
                 stats_left.agg_size = stats_left.agg_size.min(stats_right.agg_size);
-                stats_left.join_scaling = stats_left.join_scaling.max(stats_right.join_scaling);
+                // Rows of a data frame on both sides, e.g. stacked with itself, contribute to
+                // the rows of both. Semi and anti joins keep their unbounded scaling.
+                stats_left.join_scaling = match (stats_left.join_scaling, stats_right.join_scaling)
+                {
+                    (0, _) | (_, 0) => 0,
+                    (left, right) => left.saturating_add(right),
+                };
+                stats_left.min_partition_size = stats_left
+                    .min_partition_size
+                    .min(stats_right.min_partition_size);
+                // Private results combined with each other use both budgets, and combined
+                // with rows that are not private they are not private anymore.
+                stats_left.dp_eps = match (stats_left.dp_eps, stats_right.dp_eps) {
+                    (Some(left), Some(right)) => Some(left + right),
+                    _ => None,
+                };
             }
         }
 
@@ -299,9 +1991,9 @@ impl DataFrameStats {
 fn initialize_plan(
     plan: &mut LogicalPlan,
     stack: &mut Vec<StackFrame>,
-) -> Result<DataFrameStats, Status> {
-    let mut state = (stack, Vec::new());
-    plan.visit_mut(&mut state, |plan, (main_stack, stats_stack)| {
+) -> Result<(DataFrameStats, Vec<(DataFrame, Vec<String>)>), Status> {
+    let mut state = (stack, Vec::new(), Vec::new());
+    plan.visit_mut(&mut state, |plan, (main_stack, stats_stack, inputs)| {
         match plan {
             LogicalPlan::DataFrameScan { .. } => {
                 let frame = main_stack.pop().ok_or_else(|| {
@@ -310,6 +2002,7 @@ fn initialize_plan(
                     )
                 })?;
                 stats_stack.push(frame.stats);
+                inputs.push((frame.df.clone(), frame.pseudonymized));
                 *plan = frame.df.lazy().logical_plan;
             }
             LogicalPlan::Join {
@@ -379,19 +2072,16 @@ fn initialize_plan(
                     stats_stack.last_mut().unwrap().update_agg_size(usize::MAX);
                 }
             }
+            LogicalPlan::Melt { input, args, .. } => {
+                let scaling = melt_scaling(input, args)?;
+                stats_stack.last_mut().unwrap().update_join_scaling(scaling);
+            }
             LogicalPlan::Aggregate {
                 input, keys, aggs, ..
             } => {
                 let keys = &(**keys)[..];
                 let ldf = lazy_frame_from_logical_plan((&**input).clone());
-                let agg_size = usize_item(
-                    ldf.cache()
-                        .with_row_count("__count", None)
-                        .groupby(keys)
-                        .agg([col("__count").count()])
-                        .select([col("__count").min()])
-                        .collect(),
-                )?;
+                let agg_size = min_group_size(ldf, keys)?;
 
                 if exprs_agg_check(aggs)? {
                     stats_stack.last_mut().unwrap().update_agg_size(agg_size);
@@ -402,9 +2092,108 @@ fn initialize_plan(
         Ok(())
     })?;
 
+    Ok((state.1.pop().unwrap(), state.2))
+}
+
+/// Replaces the scans of `plan` by the empty data frames of `stack` and estimates the
+/// number of rows of its result, see [`CompositePlan::validate`].
+fn estimate_plan(
+    plan: &mut LogicalPlan,
+    stack: &mut Vec<EstimateFrame>,
+) -> Result<(DataFrameStats, usize), Status> {
+    let mut state = (stack, Vec::new());
+    plan.visit_mut(&mut state, |plan, (main_stack, estimates)| {
+        match plan {
+            LogicalPlan::DataFrameScan { .. } => {
+                let frame = main_stack.pop().ok_or_else(|| {
+                    Status::invalid_argument(
+                        "Could not run logical plan: not enough input data frames",
+                    )
+                })?;
+                estimates.push((frame.stats, frame.rows));
+                *plan = frame.df.lazy().logical_plan;
+            }
+            LogicalPlan::Join { options, .. } => {
+                let (mut right, right_rows) = estimates.pop().unwrap();
+                let (mut left, left_rows) = estimates.pop().unwrap();
+                let rows = match options.how {
+                    JoinType::Anti | JoinType::Semi => {
+                        right.update_join_scaling(0);
+                        left_rows
+                    }
+                    _ => left_rows.max(right_rows),
+                };
+                left.merge(right);
+                estimates.push((left, rows));
+            }
+            LogicalPlan::Projection { expr, .. } => {
+                if exprs_agg_check(expr)? {
+                    let (stats, rows) = estimates.last_mut().unwrap();
+                    stats.update_agg_size(usize::MAX);
+                    *rows = 1;
+                }
+            }
+            LogicalPlan::LocalProjection { expr, .. } => {
+                if exprs_agg_check(expr)? {
+                    let (stats, rows) = estimates.last_mut().unwrap();
+                    stats.update_agg_size(usize::MAX);
+                    *rows = 1;
+                }
+            }
+            LogicalPlan::Aggregate { keys, aggs, .. } => {
+                let (stats, rows) = estimates.last_mut().unwrap();
+                if exprs_agg_check(aggs)? {
+                    stats.update_agg_size(usize::MAX);
+                }
+                if keys.is_empty() {
+                    *rows = 1;
+                }
+            }
+            LogicalPlan::Melt { input, args, .. } => {
+                let scaling = melt_scaling(input, args)?;
+                let (stats, rows) = estimates.last_mut().unwrap();
+                stats.update_join_scaling(scaling);
+                *rows = rows.saturating_mul(scaling);
+            }
+            LogicalPlan::Slice { len, .. } => {
+                let (_, rows) = estimates.last_mut().unwrap();
+                *rows = (*rows).min(*len as usize);
+            }
+            _ => (),
+        }
+        Ok(())
+    })?;
+
     Ok(state.1.pop().unwrap())
 }
 
+/// Returns the number of rows of the smallest group of `ldf` by `keys`.
+fn min_group_size(ldf: LazyFrame, keys: &[Expr]) -> Result<usize, Status> {
+    usize_item(
+        ldf.cache()
+            .with_row_count("__count", None)
+            .groupby(keys)
+            .agg([col("__count").count()])
+            .select([col("__count").min()])
+            .collect(),
+    )
+}
+
+/// Returns the number of rows a melt outputs for every row of its input, which is the
+/// number of melted columns.
+fn melt_scaling(input: &LogicalPlan, args: &MeltArgs) -> Result<usize, Status> {
+    if !args.value_vars.is_empty() {
+        return Ok(args.value_vars.len());
+    }
+    // All the columns that are not identifiers are melted.
+    let width = lazy_frame_from_logical_plan(input.clone())
+        .limit(0)
+        .collect()
+        .map_err(|e| Status::invalid_argument(format!("Could not run logical plan: {}", e)))?
+        .width();
+    Ok(width.saturating_sub(args.id_vars.len()).max(1))
+}
+
 fn usize_item(df_res: Result<DataFrame, PolarsError>) -> Result<usize, Status> {
     Ok(df_res
         .map_err(|e| Status::internal(format!("Could not get usize item from DataFrame: {}", e)))?
@@ -413,3 +2202,91 @@ fn usize_item(df_res: Result<DataFrame, PolarsError>) -> Result<usize, Status> {
         .try_extract()
         .unwrap())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(agg_size: usize, join_scaling: usize, dp_eps: Option<f64>) -> StatsEntry {
+        StatsEntry {
+            agg_size,
+            join_scaling,
+            dp_eps,
+            min_partition_size: usize::MAX,
+        }
+    }
+
+    #[test]
+    fn merge_sums_the_scaling_of_shared_inputs() {
+        let mut stats = DataFrameStats::new("a".into());
+        stats.merge(DataFrameStats::new("a".into()));
+        assert_eq!(stats.0["a"].join_scaling, 2);
+        assert_eq!(stats.row_sensitivity().unwrap(), 2.0);
+
+        let mut stats = DataFrameStats(HashMap::from([("a".into(), entry(1, 3, None))]));
+        stats.merge(DataFrameStats(HashMap::from([(
+            "a".into(),
+            entry(1, 2, None),
+        )])));
+        assert_eq!(stats.0["a"].join_scaling, 5);
+    }
+
+    #[test]
+    fn merge_keeps_the_scaling_of_distinct_inputs() {
+        let mut stats = DataFrameStats(HashMap::from([("a".into(), entry(1, 3, None))]));
+        stats.merge(DataFrameStats(HashMap::from([(
+            "b".into(),
+            entry(1, 2, None),
+        )])));
+        assert_eq!(stats.0["a"].join_scaling, 3);
+        assert_eq!(stats.0["b"].join_scaling, 2);
+    }
+
+    #[test]
+    fn merge_keeps_semi_joins_unbounded() {
+        let mut stats = DataFrameStats(HashMap::from([("a".into(), entry(1, 0, None))]));
+        stats.merge(DataFrameStats::new("a".into()));
+        assert_eq!(stats.0["a"].join_scaling, 0);
+        assert!(stats.row_sensitivity().is_err());
+    }
+
+    #[test]
+    fn merge_combines_sizes_and_budgets() {
+        let mut stats = DataFrameStats(HashMap::from([("a".into(), entry(10, 1, Some(0.5)))]));
+        stats.merge(DataFrameStats(HashMap::from([(
+            "a".into(),
+            entry(4, 1, Some(0.25)),
+        )])));
+        assert_eq!(stats.0["a"].agg_size, 4);
+        assert_eq!(stats.0["a"].dp_eps, Some(0.75));
+
+        stats.merge(DataFrameStats::new("a".into()));
+        assert_eq!(stats.0["a"].dp_eps, None);
+    }
+
+    #[test]
+    fn pseudonyms_are_carried_by_their_values() {
+        let input = df!(
+            "id" => ["p1", "p2", "p3"],
+            "raw" => ["p1", "x", "y"],
+        )
+        .unwrap();
+        let output = df!(
+            "renamed" => [Some("p2"), None],
+            "id" => ["p1", "x"],
+            "count" => [1u32, 2],
+        )
+        .unwrap();
+        let inputs = vec![(input.clone(), vec![String::from("id")])];
+        assert_eq!(carried_pseudonyms(&output, &inputs), vec!["renamed"]);
+        assert!(carried_pseudonyms(&output, &[(input, Vec::new())]).is_empty());
+    }
+
+    #[test]
+    fn linkage_requires_pseudonymized_fields() {
+        let pseudonymized = vec![String::from("id")];
+        assert!(check_pseudonymized(&pseudonymized, "id").is_ok());
+        let err = check_pseudonymized(&pseudonymized, "name").unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+}
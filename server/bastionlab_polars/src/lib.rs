@@ -1,9 +1,16 @@
 use bastionlab_common::prelude::*;
 use bastionlab_common::{
     array_store::ArrayStore,
-    session::SessionManager,
-    session_proto::ClientInfo,
+    cancellation::Cancellation,
+    compression::ChunkEncoding,
+    encryption::AtRestKey,
+    notifications::{Notifier, PRIVACY_BUDGET_THRESHOLDS},
+    provenance::{Node, ProvenanceGraph},
+    remote_array::RemoteArrayRegistry,
+    session::{SessionManager, SessionResource},
+    session_proto::{ClientInfo, ErrorCode, NotificationKind},
     telemetry::{self, TelemetryEventProps},
+    usage::{UsageEvent, UsageKind, UsageLog},
 };
 
 use polars::prelude::*;
@@ -12,11 +19,12 @@ use rand::seq::SliceRandom;
 use rand::{thread_rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::fs::{create_dir, read_dir, OpenOptions};
+use std::fs::{create_dir, read_dir};
 use std::io::{Error, ErrorKind};
-use std::{future::Future, pin::Pin, time::Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{future::Future, pin::Pin};
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::{Request, Response, Status, Streaming};
+use tonic::{Code, Request, Response, Status, Streaming};
 use utils::sanitize_df;
 use uuid::Uuid;
 
@@ -25,8 +33,10 @@ pub mod polars_proto {
 }
 
 use polars_proto::{
-    polars_service_server::PolarsService, Empty, FetchChunk, Query, ReferenceList,
-    ReferenceRequest, ReferenceResponse, SendChunk, SplitRequest,
+    polars_service_server::PolarsService, DataFrameQuery, Empty, FetchChunk, FetchDecision,
+    PendingFetch, PendingFetchList, PlanValidation, PseudonymKey, Query, QuerySchedule,
+    QueryScheduleList, ReferenceList, ReferenceRequest, ReferenceResponse, SavedQuery,
+    SavedQueryList, SavedQueryRun, SendChunk, SplitRequest,
 };
 
 mod serialization;
@@ -35,6 +45,22 @@ use serialization::*;
 mod composite_plan;
 use composite_plan::*;
 
+mod sketches;
+
+mod similarity;
+
+mod approvals;
+use approvals::ApprovalBoard;
+
+mod pseudonyms;
+use pseudonyms::PseudonymKeys;
+
+mod schedules;
+use schedules::QuerySchedules;
+
+mod saved_queries;
+use saved_queries::SavedQueries;
+
 mod visitable;
 
 pub mod access_control;
@@ -59,13 +85,47 @@ pub struct DelayedDataFrame {
     fetch_status: FetchStatus,
 }
 
+/// Terms of the datasets converted from a data frame, derived from its policy.
+#[derive(Debug, Clone)]
+pub struct DatasetTerms {
+    /// Owners of the uploaded data frames it derives from, `None` when unknown.
+    pub owners: Vec<Option<String>>,
+    /// Whether the user converting the data frame may fetch its rows.
+    pub rows_fetchable: bool,
+    /// Whether its policy requires differential privacy.
+    pub private: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DataFrameArtifact {
     dataframe: DataFrame,
     policy: Policy,
+    /// Privacy budget used by differentially private aggregations, when the policy
+    /// requires them.
+    #[serde(default)]
+    dp_expended: f64,
+    /// Data frames whose privacy budget pays for the private aggregations of this one,
+    /// when it derives from them.
+    #[serde(default)]
+    dp_sources: Vec<String>,
+    /// Data frames with a fetch limit this one derives from.
+    #[serde(default)]
+    fetch_sources: Vec<String>,
+    /// Uploaded data frames this one derives from, to whose owners its uses are reported.
+    #[serde(default)]
+    origins: Vec<String>,
     fetchable: VerificationResult,
     blacklist: Vec<String>,
     query_details: String,
+    #[serde(default)]
+    expires_at: Option<SystemTime>,
+    #[serde(default)]
+    owner: Option<String>,
+    #[serde(default)]
+    created_at: Option<SystemTime>,
+    /// Columns holding pseudonyms computed by the server, which record linkage compares.
+    #[serde(default)]
+    pseudonymized: Vec<String>,
 }
 
 impl DataFrameArtifact {
@@ -73,12 +133,20 @@ impl DataFrameArtifact {
         DataFrameArtifact {
             dataframe: df,
             policy,
+            dp_expended: 0.0,
+            dp_sources: Vec::new(),
+            fetch_sources: Vec::new(),
+            origins: Vec::new(),
             fetchable: VerificationResult::Unsafe {
                 action: UnsafeAction::Reject,
                 reason: String::from("DataFrames uploaded by the Data Owner are protected."),
             },
             blacklist,
             query_details: String::from("uploaded dataframe"),
+            expires_at: None,
+            owner: None,
+            created_at: Some(SystemTime::now()),
+            pseudonymized: Vec::new(),
         }
     }
 
@@ -87,13 +155,75 @@ impl DataFrameArtifact {
         self
     }
 
+    /// Records that `columns` hold pseudonyms computed by the server.
+    pub fn with_pseudonymized(mut self, columns: Vec<String>) -> Self {
+        self.pseudonymized = columns;
+        self
+    }
+
+    /// Records the identifier of the user who created the dataframe.
+    pub fn with_owner(mut self, owner: String) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    /// Deletes the dataframe once `ttl` has elapsed.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.expires_at = Some(SystemTime::now() + ttl);
+        self
+    }
+
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at.map(|t| t <= now).unwrap_or(false)
+    }
+
+    /// Returns the data frames whose privacy budget pays for the private aggregations of
+    /// this one, registered as `identifier`.
+    pub fn budget_sources(&self, identifier: &str) -> Vec<String> {
+        if self.dp_sources.is_empty() {
+            vec![identifier.to_string()]
+        } else {
+            self.dp_sources.clone()
+        }
+    }
+
+    /// Returns the data frames whose fetch limit applies to this one, registered as
+    /// `identifier`.
+    pub fn limit_sources(&self, identifier: &str) -> Vec<String> {
+        if self.policy.fetch_limit().is_some() {
+            let mut sources = self.fetch_sources.clone();
+            sources.push(identifier.to_string());
+            sources
+        } else {
+            self.fetch_sources.clone()
+        }
+    }
+
+    /// Returns the uploaded data frames this one, registered as `identifier`, derives from.
+    pub fn origins(&self, identifier: &str) -> Vec<String> {
+        if self.origins.is_empty() {
+            vec![identifier.to_string()]
+        } else {
+            self.origins.clone()
+        }
+    }
+
     pub fn inherit(&self, df: DataFrame) -> Self {
         Self {
             dataframe: df,
             policy: self.policy.clone(),
+            dp_expended: 0.0,
+            dp_sources: self.dp_sources.clone(),
+            fetch_sources: self.fetch_sources.clone(),
+            origins: self.origins.clone(),
             blacklist: self.blacklist.clone(),
             fetchable: self.fetchable.clone(),
             query_details: self.query_details.clone(),
+            expires_at: self.expires_at,
+            owner: self.owner.clone(),
+            created_at: Some(SystemTime::now()),
+            // The columns of `df` are unrelated to those of this data frame.
+            pseudonymized: Vec::new(),
         }
     }
 }
@@ -101,22 +231,102 @@ impl DataFrameArtifact {
 #[derive(Clone)]
 pub struct BastionLabPolars {
     dataframes: Arc<RwLock<HashMap<String, DataFrameArtifact>>>,
-    arrays: Arc<RwLock<HashMap<String, ArrayStore>>>,
+    arrays: RemoteArrayRegistry,
     sess_manager: Arc<SessionManager>,
+    at_rest_key: Option<AtRestKey>,
+    max_upload_size: Option<usize>,
+    notifier: Option<Notifier>,
+    fetch_ledger: Arc<FetchLedger>,
+    usage_log: Option<UsageLog>,
+    approvals: Arc<ApprovalBoard>,
+    pseudonym_keys: Arc<PseudonymKeys>,
+    schedules: Arc<QuerySchedules>,
+    saved_queries: Arc<SavedQueries>,
+    provenance: ProvenanceGraph,
+}
+
+/// Directory where the saved data frames are kept, one file per data frame.
+const DATA_FRAMES_DIR: &str = "data_frames";
+
+/// Returns the file where the data frame `identifier` is saved.
+fn data_frame_path(identifier: &str) -> String {
+    format!("{}/{}.json", DATA_FRAMES_DIR, identifier)
 }
 
+/// Where the pseudonymization keys are saved, next to the saved data frames.
+const PSEUDONYM_KEYS_PATH: &str = "pseudonym_keys.json";
+
+/// Where the scheduled queries are saved, next to the saved data frames.
+const QUERY_SCHEDULES_PATH: &str = "query_schedules.json";
+
+/// Where the saved queries are saved, next to the saved data frames.
+const SAVED_QUERIES_PATH: &str = "saved_queries.json";
+
 impl BastionLabPolars {
-    pub fn new(sess_manager: Arc<SessionManager>) -> Self {
+    /// Creates the service, sharing `arrays` with the other services.
+    pub fn new(sess_manager: Arc<SessionManager>, arrays: RemoteArrayRegistry) -> Self {
         Self {
             dataframes: Arc::new(RwLock::new(HashMap::new())),
-            arrays: Arc::new(RwLock::new(HashMap::new())),
+            arrays,
             sess_manager,
+            at_rest_key: None,
+            max_upload_size: None,
+            notifier: None,
+            fetch_ledger: Arc::new(FetchLedger::default()),
+            usage_log: None,
+            approvals: Arc::new(ApprovalBoard::default()),
+            pseudonym_keys: Arc::new(PseudonymKeys::default()),
+            schedules: Arc::new(QuerySchedules::default()),
+            saved_queries: Arc::new(SavedQueries::default()),
+            provenance: ProvenanceGraph::default(),
         }
     }
 
+    /// Encrypts persisted dataframes with `key`.
+    pub fn with_at_rest_key(mut self, key: AtRestKey) -> Self {
+        self.at_rest_key = Some(key);
+        self
+    }
+
+    /// Rejects dataframe uploads larger than `max_size` bytes, as soon as they grow past it.
+    pub fn with_max_upload_size(mut self, max_size: usize) -> Self {
+        self.max_upload_size = Some(max_size);
+        self
+    }
+
+    /// Notifies the data owners of the fetches waiting for their approval.
+    pub fn with_notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Records the queries and fetches in `log`, for the usage reports of data owners.
+    pub fn with_usage_log(mut self, log: UsageLog) -> Self {
+        self.usage_log = Some(log);
+        self
+    }
+
+    /// Records the data frames and arrays derived by queries and splits in `graph`.
+    pub fn with_provenance(mut self, graph: ProvenanceGraph) -> Self {
+        self.provenance = graph;
+        self
+    }
+
+    fn record_usage(&self, events: Vec<UsageEvent>) {
+        if let Some(log) = &self.usage_log {
+            for event in events {
+                log.record(event);
+            }
+        }
+    }
+
+    /// Returns the dataframe `identifier` as fetched by `user_id`, who is exempt from fetch
+    /// limits unless `limited`.
     fn get_df(
         &self,
         identifier: &str,
+        user_id: &str,
+        limited: bool,
         client_info: Option<ClientInfo>,
     ) -> Result<DelayedDataFrame, Status> {
         let dfs = self.dataframes.read().unwrap();
@@ -126,6 +336,30 @@ impl BastionLabPolars {
                 identifier
             ))
         })?;
+        let rejected = matches!(
+            artifact.fetchable,
+            VerificationResult::Unsafe {
+                action: UnsafeAction::Reject,
+                ..
+            }
+        );
+        if limited && !rejected {
+            // Limits of deleted data frames cannot be known anymore.
+            let limits: Vec<_> = artifact
+                .limit_sources(identifier)
+                .into_iter()
+                .filter_map(|source| {
+                    let limit = dfs.get(&source)?.policy.fetch_limit()?;
+                    Some((source, limit))
+                })
+                .collect();
+            if !limits.is_empty() {
+                self.fetch_ledger
+                    .record(user_id, &limits, artifact.dataframe.height())?;
+            }
+        }
+        let origins = artifact.origins(identifier);
+        let usage = |kind| usage_events(&dfs, &origins, user_id, kind);
         if let VerificationResult::Unsafe { reason, .. } = &artifact.fetchable {
             println!(
                 "Safe zone violation: a DataFrame has been non-privately fetched.
@@ -148,6 +382,7 @@ Reason: {}",
                     },
                     client_info,
                 );
+                self.record_usage(usage(UsageKind::FetchApproved));
                 DelayedDataFrame {
                     future: Box::pin(async { Ok(df) }),
                     fetch_status: if let VerificationResult::Unsafe {
@@ -166,9 +401,10 @@ Reason: {}",
                 reason,
             } => {
                 let reason = reason.clone();
+                self.record_usage(usage(UsageKind::FetchDenied));
                 DelayedDataFrame {
                     future: Box::pin(async move {
-                        Err(Status::permission_denied(format!(
+                        Err(ErrorCode::PolicyDenied.status(format!(
                         "Cannot fetch this DataFrame: operation denied by the data owner's policy
 Reason: {}",
                         reason,
@@ -184,7 +420,77 @@ Reason: {}",
                 let reason = reason.clone();
                 let identifier = String::from(identifier);
                 let query_details = artifact.query_details.clone();
-                let dfs = Arc::clone(&self.dataframes);
+                let (approved, denied) = (
+                    usage(UsageKind::FetchApproved),
+                    usage(UsageKind::FetchDenied),
+                );
+                let polars = self.clone();
+                let request = artifact.policy.required_approvals().map(|required| {
+                    let (request_id, decision) = self.approvals.submit(
+                        &identifier,
+                        user_id,
+                        &reason,
+                        &query_details,
+                        required,
+                    );
+                    (required, request_id, decision)
+                });
+                if let Some(notifier) = &self.notifier {
+                    let message = match &request {
+                        Some((required, request_id, _)) => format!(
+                            "A fetch of dataframe {} waits for the approval of {} data owners in request {}: {}",
+                            identifier, required, request_id, reason
+                        ),
+                        None => format!(
+                            "A fetch of dataframe {} waits for approval: {}",
+                            identifier, reason
+                        ),
+                    };
+                    notifier.notify(
+                        NotificationKind::FetchApprovalPending,
+                        &identifier,
+                        message,
+                        None,
+                    );
+                }
+                if let Some((required, request_id, mut decision)) = request {
+                    return Ok(DelayedDataFrame {
+                        fetch_status: FetchStatus::Pending(format!(
+                            "{}\nRequest {} waits for the approval of {} data owners.",
+                            reason, request_id, required
+                        )),
+                        future: Box::pin(async move {
+                            let accepted = loop {
+                                let decided = *decision.borrow();
+                                match decided {
+                                    Some(accepted) => break accepted,
+                                    None => {
+                                        if decision.changed().await.is_err() {
+                                            break false;
+                                        }
+                                    }
+                                }
+                            };
+                            telemetry::add_event(
+                                TelemetryEventProps::FetchDataFrame {
+                                    dataset_name: Some(identifier.to_owned()),
+                                    request_accepted: accepted,
+                                },
+                                client_info,
+                            );
+                            if !accepted {
+                                polars.record_usage(denied);
+                                return Err(ErrorCode::FetchRejected.status(format!(
+                                    "A data owner rejected the fetch operation.
+Reason: {}",
+                                    reason
+                                )));
+                            }
+                            polars.record_usage(approved);
+                            polars.sanitized_df(&identifier)
+                        }),
+                    });
+                }
                 DelayedDataFrame {
                     fetch_status: FetchStatus::Pending(reason.clone()),
                     future: Box::pin(async move {
@@ -221,7 +527,8 @@ Reason the request is unsafe:
                                         },
                                         client_info,
                                     );
-                                    return Err(Status::permission_denied(format!(
+                                    polars.record_usage(denied);
+                                    return Err(ErrorCode::FetchRejected.status(format!(
                                         "The data owner rejected the fetch operation.
 Fetching a dataframe obtained with a non privacy-preserving query requires the approval of the data owner.
 This dataframe was obtained in a non privacy-preserving fashion.
@@ -239,24 +546,28 @@ Reason: {}",
                             },
                             client_info,
                         );
-                        Ok({
-                            let guard = dfs.read().unwrap();
-                            let artifact = guard.get(&identifier).ok_or_else(|| {
-                                Status::not_found(format!(
-                                    "Could not find dataframe: identifier={}",
-                                    identifier
-                                ))
-                            })?;
-                            let mut df = artifact.dataframe.clone();
-                            sanitize_df(&mut df, &artifact.blacklist);
-                            df
-                        })
+                        polars.record_usage(approved);
+                        polars.sanitized_df(&identifier)
                     }),
                 }
             }
         })
     }
 
+    /// Returns the dataframe `identifier` without its sanitized columns.
+    fn sanitized_df(&self, identifier: &str) -> Result<DataFrame, Status> {
+        let dfs = self.dataframes.read().unwrap();
+        let artifact = dfs.get(identifier).ok_or_else(|| {
+            Status::not_found(format!(
+                "Could not find dataframe: identifier={}",
+                identifier
+            ))
+        })?;
+        let mut df = artifact.dataframe.clone();
+        sanitize_df(&mut df, &artifact.blacklist);
+        Ok(df)
+    }
+
     pub fn get_df_unchecked(&self, identifier: &str) -> Result<DataFrame, Status> {
         let dfs = self.dataframes.read().unwrap();
         Ok(dfs
@@ -302,14 +613,43 @@ Reason: {}",
         )?)
     }
 
-    fn get_headers(&self) -> Result<Vec<(String, String)>, Status> {
+    /// Returns the identifiers and headers of the dataframes that match `query`, sorted by identifier,
+    /// along with the token of the next page (empty on the last page).
+    fn get_headers(
+        &self,
+        query: &DataFrameQuery,
+    ) -> Result<(Vec<(String, String)>, String), Status> {
+        let created_after = UNIX_EPOCH + Duration::from_secs(query.created_after);
         let dataframes = self.dataframes.read().unwrap();
-        let mut res = Vec::with_capacity(dataframes.len());
-        for (k, v) in dataframes.iter() {
+        let mut matches: Vec<(&String, &DataFrameArtifact)> = dataframes
+            .iter()
+            .filter(|(identifier, artifact)| {
+                identifier.as_str() > query.page_token.as_str()
+                    && (query.owner.is_empty() || artifact.owner.as_ref() == Some(&query.owner))
+                    && (query.created_after == 0
+                        || artifact
+                            .created_at
+                            .map(|t| t > created_after)
+                            .unwrap_or(false))
+            })
+            .collect();
+        matches.sort_by(|a, b| a.0.cmp(b.0));
+
+        let page_size = match query.page_size {
+            0 => matches.len(),
+            page_size => page_size as usize,
+        };
+        let next_page_token = if matches.len() > page_size {
+            matches[page_size - 1].0.clone()
+        } else {
+            String::new()
+        };
+        let mut res = Vec::with_capacity(page_size.min(matches.len()));
+        for (k, v) in matches.into_iter().take(page_size) {
             let header = get_df_header(&v.dataframe)?;
             res.push((k.clone(), header));
         }
-        Ok(res)
+        Ok((res, next_page_token))
     }
 
     pub fn insert_df(&self, df: DataFrameArtifact) -> String {
@@ -319,22 +659,381 @@ Reason: {}",
         identifier
     }
 
+    /// Registers `df`, computed outside of Polars from the dataframes `sources`, under the
+    /// policies of `sources`. Returns its identifier and header. Sources which were deleted
+    /// are skipped, but at least one must remain.
+    ///
+    /// As the server does not know how `df` was computed, it can only be fetched through
+    /// queries accepted by the policies.
+    pub fn insert_derived_df(
+        &self,
+        sources: &[String],
+        df: DataFrame,
+        owner: String,
+    ) -> Result<(String, String), Status> {
+        let mut artifact = {
+            let dfs = self.dataframes.read().unwrap();
+            let mut found = sources
+                .iter()
+                .filter_map(|source| Some((source, dfs.get(source)?)));
+            let (first, first_artifact) = found.next().ok_or_else(|| {
+                Status::failed_precondition("The tensor does not derive from any DataFrame")
+            })?;
+            let mut derived = first_artifact.inherit(df);
+            derived.dp_sources = first_artifact.budget_sources(first);
+            derived.fetch_sources = first_artifact.limit_sources(first);
+            derived.origins = first_artifact.origins(first);
+            for (source, artifact) in found {
+                derived.policy = derived.policy.merge(&artifact.policy);
+                derived.dp_sources.extend(artifact.budget_sources(source));
+                derived.fetch_sources.extend(artifact.limit_sources(source));
+                derived.origins.extend(artifact.origins(source));
+                derived.blacklist.extend_from_slice(&artifact.blacklist);
+                derived.expires_at = match (derived.expires_at, artifact.expires_at) {
+                    (Some(a), Some(b)) => Some(std::cmp::min(a, b)),
+                    (a, b) => a.or(b),
+                };
+            }
+            derived
+        };
+        for list in [
+            &mut artifact.dp_sources,
+            &mut artifact.fetch_sources,
+            &mut artifact.origins,
+        ] {
+            list.sort();
+            list.dedup();
+        }
+        artifact.query_details = format!(
+            "conversion of a tensor computed from {}",
+            sources.join(", ")
+        );
+        let artifact = artifact
+            .with_owner(owner)
+            .with_fetchable(VerificationResult::Unsafe {
+                action: UnsafeAction::Reject,
+                reason: String::from("DataFrames converted from tensors are protected."),
+            });
+        let header = get_df_header(&artifact.dataframe)?;
+        Ok((self.insert_df(artifact), header))
+    }
+
+    /// Returns the terms of the datasets `user_id` converts from the data frame `identifier`.
+    pub fn dataset_terms(&self, identifier: &str, user_id: &str) -> Result<DatasetTerms, Status> {
+        let rows_fetchable = self.with_df_artifact_ref(identifier, |artifact| {
+            artifact.fetchable == VerificationResult::Safe
+        })? || rows_fetchable(self, identifier, user_id)?;
+        let dfs = self.dataframes.read().unwrap();
+        let artifact = dfs.get(identifier).ok_or_else(|| {
+            Status::not_found(format!(
+                "Could not find dataframe: identifier={}",
+                identifier
+            ))
+        })?;
+        let mut owners: Vec<Option<String>> = artifact
+            .origins(identifier)
+            .iter()
+            .map(|origin| dfs.get(origin).and_then(|origin| origin.owner.clone()))
+            .collect();
+        owners.sort();
+        owners.dedup();
+        Ok(DatasetTerms {
+            owners,
+            rows_fetchable,
+            private: artifact.policy.dp_limits().is_some(),
+        })
+    }
+
+    /// Checks that `eps` can be expended from the privacy budget of the data frames
+    /// `identifiers`, without expending it.
+    pub fn check_dp_budget<'a>(
+        &self,
+        identifiers: impl Iterator<Item = &'a String>,
+        eps: f64,
+    ) -> Result<(), Status> {
+        let dfs = self.dataframes.read().unwrap();
+        dp_budget_sources(&dfs, identifiers, eps)?;
+        Ok(())
+    }
+
+    /// Expends `eps` from the privacy budget of the data frames `identifiers` whose policy
+    /// requires differential privacy, or of the data frames they derive from.
+    ///
+    /// Fails without expending anything if `eps` exceeds the per-query cap or the remaining
+    /// budget of one of them.
+    pub fn expend_dp_budget<'a>(
+        &self,
+        identifiers: impl Iterator<Item = &'a String>,
+        eps: f64,
+    ) -> Result<(), Status> {
+        let mut dfs = self.dataframes.write().unwrap();
+        let sources = dp_budget_sources(&dfs, identifiers, eps)?;
+        let mut crossed = Vec::new();
+        for identifier in sources.iter() {
+            if let Some(artifact) = dfs.get_mut(identifier) {
+                if let Some((_, budget)) = artifact.policy.dp_limits() {
+                    let before = (artifact.dp_expended / budget) as f32;
+                    artifact.dp_expended += eps;
+                    let after = (artifact.dp_expended / budget) as f32;
+                    for threshold in PRIVACY_BUDGET_THRESHOLDS {
+                        if before < threshold && after >= threshold {
+                            crossed.push((identifier.clone(), artifact.owner.clone(), threshold));
+                        }
+                    }
+                }
+            }
+        }
+        drop(dfs);
+
+        if let Some(notifier) = &self.notifier {
+            for (identifier, owner, threshold) in crossed {
+                let owner = match owner {
+                    Some(owner) => owner,
+                    None => continue,
+                };
+                notifier.notify_user(
+                    NotificationKind::PrivacyBudgetThreshold,
+                    &identifier,
+                    format!(
+                        "Private aggregations expended {:.0}% of the privacy budget of DataFrame {}",
+                        threshold * 100.,
+                        identifier
+                    ),
+                    &owner,
+                );
+            }
+        }
+
+        // Saved data frames must not get their budget back on restart.
+        for identifier in sources.iter() {
+            if self.is_saved(identifier) {
+                self.persist_df(identifier)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the values of `columns` in `df`, computed from the data frames `identifiers`,
+    /// with their pseudonyms under the key `key`.
+    ///
+    /// With authentication, the data frames must derive from the data of `user_id` only, who
+    /// must own the key.
+    pub fn pseudonymize<'a>(
+        &self,
+        df: &mut DataFrame,
+        columns: &[String],
+        key: &str,
+        identifiers: impl Iterator<Item = &'a String>,
+        user_id: &str,
+    ) -> Result<(), Status> {
+        let owner = if self.sess_manager.auth_enabled() {
+            let dfs = self.dataframes.read().unwrap();
+            let mut owners = Vec::new();
+            for identifier in identifiers {
+                let artifact = dfs.get(identifier).ok_or_else(|| {
+                    Status::not_found(format!(
+                        "Could not find dataframe: identifier={}",
+                        identifier
+                    ))
+                })?;
+                for origin in artifact.origins(identifier) {
+                    // The owner of a deleted data frame cannot be known anymore.
+                    owners.push(dfs.get(&origin).and_then(|origin| origin.owner.clone()));
+                }
+            }
+            owners.sort();
+            owners.dedup();
+            match &owners[..] {
+                [Some(owner)] if owner == user_id => Some(owner.clone()),
+                _ => {
+                    return Err(Status::permission_denied(
+                        "Only data owners can pseudonymize their own data frames",
+                    ))
+                }
+            }
+        } else {
+            None
+        };
+        self.pseudonym_keys
+            .pseudonymize(df, columns, key, owner.as_deref())
+    }
+
+    /// Saves `data` to `path`, encrypted with the at-rest key if any.
+    fn persist_state(&self, path: &str, data: Vec<u8>) -> Result<(), Status> {
+        let data = match &self.at_rest_key {
+            Some(key) => key.seal(path.as_bytes(), data)?,
+            None => data,
+        };
+        std::fs::write(path, data)
+            .map_err(|_| Status::internal(format!("Unable to save {}!", path)))
+    }
+
+    /// Reads the data saved to `path` with [`BastionLabPolars::persist_state`].
+    fn load_state(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let data = std::fs::read(path)?;
+        match &self.at_rest_key {
+            Some(key) => key
+                .open(path.as_bytes(), data)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e.message())),
+            None => Ok(data),
+        }
+    }
+
+    fn persist_pseudonym_keys(&self) -> Result<(), Status> {
+        self.persist_state(PSEUDONYM_KEYS_PATH, self.pseudonym_keys.to_vec()?)
+    }
+
+    pub fn load_pseudonym_keys(&self) -> Result<(), Error> {
+        let data = self.load_state(PSEUDONYM_KEYS_PATH)?;
+        self.pseudonym_keys
+            .load(&data)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e.message()))
+    }
+
+    fn persist_query_schedules(&self) -> Result<(), Status> {
+        self.persist_state(QUERY_SCHEDULES_PATH, self.schedules.to_vec()?)
+    }
+
+    pub fn load_query_schedules(&self) -> Result<(), Error> {
+        let data = self.load_state(QUERY_SCHEDULES_PATH)?;
+        self.schedules
+            .load(&data)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e.message()))
+    }
+
+    fn persist_saved_queries(&self) -> Result<(), Status> {
+        self.persist_state(SAVED_QUERIES_PATH, self.saved_queries.to_vec()?)
+    }
+
+    pub fn load_saved_queries(&self) -> Result<(), Error> {
+        let data = self.load_state(SAVED_QUERIES_PATH)?;
+        self.saved_queries
+            .load(&data)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e.message()))
+    }
+
+    /// Returns the owners of the uploaded data frames `composite_plan` derives from.
+    fn plan_owners(&self, composite_plan: &CompositePlan) -> Vec<String> {
+        let dfs = self.dataframes.read().unwrap();
+        let mut owners: Vec<String> = composite_plan
+            .inputs()
+            .into_iter()
+            .filter_map(|input| dfs.get(input).map(|artifact| artifact.origins(input)))
+            .flatten()
+            .filter_map(|origin| dfs.get(&origin)?.owner.clone())
+            .collect();
+        owners.sort();
+        owners.dedup();
+        owners
+    }
+
+    /// Runs the queries scheduled at the current minute, each replacing the data frame
+    /// registered under its name with its result.
+    ///
+    /// This is meant to be called more than once a minute.
+    pub async fn run_scheduled_queries(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let due = self.schedules.due(now);
+        if due.is_empty() {
+            return;
+        }
+        if let Err(e) = self.persist_query_schedules() {
+            error!("Could not save scheduled queries: {}", e.message());
+        }
+        for (name, composite_plan, owner) in due {
+            let outcome = self
+                .run_scheduled_query(&name, &composite_plan, owner)
+                .await;
+            match &outcome {
+                Ok(()) => info!("Ran scheduled query {}", name),
+                Err(e) => error!("Scheduled query {} failed: {}", name, e.message()),
+            }
+            self.schedules.record_outcome(&name, &outcome);
+        }
+        if let Err(e) = self.persist_query_schedules() {
+            error!("Could not save scheduled queries: {}", e.message());
+        }
+    }
+
+    async fn run_scheduled_query(
+        &self,
+        name: &str,
+        composite_plan: &str,
+        owner: String,
+    ) -> Result<(), Status> {
+        let composite_plan: CompositePlan = serde_json::from_str(composite_plan).map_err(|e| {
+            Status::invalid_argument(format!("Could not deserialize composite plan: {}", e))
+        })?;
+        let eps = composite_plan.dp_eps();
+        let inputs = plan_inputs(&composite_plan);
+        let polars = self.clone();
+        let user_id = owner.clone();
+        // Scheduled queries have no client to give up on them.
+        let res = Cancellation::default()
+            .run_blocking(move |cancellation| {
+                Ok(composite_plan
+                    .run(&polars, &user_id, cancellation)?
+                    .with_owner(user_id))
+            })
+            .await?;
+        let origins = res.origins.clone();
+        self.dataframes
+            .write()
+            .unwrap()
+            .insert(name.to_string(), res);
+        self.record_query_usage(&origins, &owner, name, eps);
+        self.provenance
+            .record(Node::data_frame(name), inputs, "scheduled query");
+
+        // Saved results stay saved.
+        if self.is_saved(name) {
+            self.persist_df(name)?;
+        }
+        Ok(())
+    }
+
+    /// Records the uses of the data frames `origins` by a query of `user_id` expending `eps`,
+    /// whose result is registered as `identifier`.
+    fn record_query_usage(&self, origins: &[String], user_id: &str, identifier: &str, eps: f64) {
+        if self.usage_log.is_none() {
+            return;
+        }
+        let private = eps > 0.0;
+        let kind = if private {
+            UsageKind::PrivateQuery
+        } else {
+            UsageKind::Query
+        };
+        let dfs = self.dataframes.read().unwrap();
+        let events = usage_events(&dfs, origins, user_id, kind)
+            .into_iter()
+            .map(|event| {
+                // Only data frames requiring differential privacy pay for it.
+                let requires_dp = dfs
+                    .get(&event.dataset)
+                    .map(|artifact| artifact.policy.dp_limits().is_some())
+                    .unwrap_or(false);
+                let event = event.with_derived(identifier).with_private(private);
+                if requires_dp {
+                    event.with_eps(eps)
+                } else {
+                    event
+                }
+            })
+            .collect();
+        drop(dfs);
+        self.record_usage(events);
+    }
+
     pub fn insert_array(&self, array: ArrayStore) -> String {
-        let mut arrays = self.arrays.write().unwrap();
-        let identifier = format!("{}", Uuid::new_v4());
-        arrays.insert(identifier.clone(), array);
-        identifier
+        self.arrays.insert_array(array)
     }
 
     pub fn get_array(&self, identifier: &str) -> Result<ArrayStore, Status> {
-        let arrays = self.arrays.read().unwrap();
-        let arr = arrays
-            .get(identifier)
-            .cloned()
-            .ok_or(Status::invalid_argument(format!(
-                "Could not find Array: {identifier}"
-            )))?;
-        Ok(arr)
+        self.arrays.get_array(identifier)
     }
 
     fn persist_df(&self, identifier: &str) -> Result<(), Status> {
@@ -351,7 +1050,7 @@ Reason: {}",
             return Err(Status::unknown("Dataframe is not savable"));
         }
 
-        let error = create_dir("data_frames");
+        let error = create_dir(DATA_FRAMES_DIR);
         match error {
             Ok(_) => {}
             Err(err) => {
@@ -361,31 +1060,35 @@ Reason: {}",
             }
         }
 
-        let path = format!("data_frames/{}.json", identifier);
-        let df_store = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(path)
-            .map_err(|_| Status::internal("Unable to find or create storage file!"))?;
-
-        serde_json::to_writer(df_store, df_artifact)
+        let path = data_frame_path(identifier);
+        let data = serde_json::to_vec(df_artifact)
             .map_err(|_| Status::internal("Could not serialize dataframe artifact!"))?;
+        let data = match &self.at_rest_key {
+            Some(key) => key.seal(identifier.as_bytes(), data)?,
+            None => data,
+        };
+
+        std::fs::write(path, data)
+            .map_err(|_| Status::internal("Unable to find or create storage file!"))?;
 
         Ok(())
     }
 
     pub fn load_dfs(&self) -> Result<(), Error> {
-        let files = read_dir("data_frames")?;
+        let files = read_dir(DATA_FRAMES_DIR)?;
 
         for file in files {
             let file = file?;
             let identifier = file.file_name().to_str().unwrap().replace(".json", "");
 
-            let file = std::fs::OpenOptions::new()
-                .read(true)
-                .open(file.path().to_str().unwrap())?;
-            let reader = std::io::BufReader::new(file);
-            let df: DataFrameArtifact = serde_json::from_reader(reader)?;
+            let data = std::fs::read(file.path())?;
+            let data = match &self.at_rest_key {
+                Some(key) => key
+                    .open(identifier.as_bytes(), data)
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e.message()))?,
+                None => data,
+            };
+            let df: DataFrameArtifact = serde_json::from_slice(&data)?;
 
             let mut dfs = self.dataframes.write().unwrap();
             dfs.insert(identifier, df);
@@ -393,16 +1096,192 @@ Reason: {}",
         Ok(())
     }
 
+    /// Takes the dataframe and array locks once. This blocks if they are deadlocked.
+    pub fn probe(&self) {
+        drop(self.dataframes.read().unwrap());
+        self.arrays.probe();
+    }
+
+    /// Deletes the dataframes whose time-to-live has elapsed, including persisted ones.
+    ///
+    /// This is meant to be called periodically.
+    pub fn reap_expired(&self) -> Result<(), Error> {
+        let now = SystemTime::now();
+        let expired: Vec<String> = self
+            .dataframes
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, df)| df.is_expired(now))
+            .map(|(identifier, _)| identifier.clone())
+            .collect();
+
+        for identifier in expired {
+            self.delete_dfs(&identifier)?;
+            info!("Deleted expired dataframe {}", identifier);
+            telemetry::add_event(
+                TelemetryEventProps::ArtifactExpired {
+                    artifact_kind: String::from("dataframes"),
+                    artifact_name: Some(identifier),
+                },
+                None,
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns whether the data frame `identifier` was saved with
+    /// [`BastionLabPolars::persist_df`].
+    fn is_saved(&self, identifier: &str) -> bool {
+        std::path::Path::new(&data_frame_path(identifier)).exists()
+    }
+
+    /// Releases the query results of a session that expired or whose client went silent,
+    /// unless they were saved.
+    pub fn release_session_resources(&self, resources: &[SessionResource]) {
+        for resource in resources {
+            if let SessionResource::DataFrame(identifier) = resource {
+                if self.is_saved(identifier) {
+                    continue;
+                }
+                self.dataframes.write().unwrap().remove(identifier);
+                info!("Released dataframe {} of an ended session", identifier);
+            }
+        }
+    }
+
     pub fn delete_dfs(&self, identifier: &str) -> Result<(), Error> {
         let mut dfs = self.dataframes.write().unwrap();
         dfs.remove(identifier);
 
-        let path = "data_frames/".to_owned() + identifier + ".json";
-        std::fs::remove_file(path).unwrap_or(());
+        std::fs::remove_file(data_frame_path(identifier)).unwrap_or(());
         Ok(())
     }
 }
 
+/// Returns the data frames whose privacy budget pays for private aggregations of the data
+/// frames `identifiers` using `eps`, failing if `eps` exceeds the per-query cap or the
+/// remaining budget of one of them.
+fn dp_budget_sources<'a>(
+    dfs: &HashMap<String, DataFrameArtifact>,
+    identifiers: impl Iterator<Item = &'a String>,
+    eps: f64,
+) -> Result<Vec<String>, Status> {
+    let mut sources = Vec::new();
+    for identifier in identifiers {
+        let artifact = dfs.get(identifier).ok_or_else(|| {
+            Status::not_found(format!(
+                "Could not find dataframe: identifier={}",
+                identifier
+            ))
+        })?;
+        if artifact.policy.dp_limits().is_some() {
+            sources.extend(artifact.budget_sources(identifier));
+        }
+    }
+    sources.sort();
+    sources.dedup();
+
+    for identifier in sources.iter() {
+        // Deleting the data frame a result derives from does not free its budget.
+        let artifact = dfs.get(identifier).ok_or_else(|| {
+            ErrorCode::PrivacyBudgetExhausted.status(format!(
+                "The privacy budget of DataFrame {} is not available anymore",
+                identifier
+            ))
+        })?;
+        let (max_eps_per_query, budget) = match artifact.policy.dp_limits() {
+            Some(limits) => limits,
+            None => continue,
+        };
+        if eps > max_eps_per_query {
+            return Err(ErrorCode::PolicyDenied.status(format!(
+                "Private aggregations of DataFrame {} may not use more than ε={} per query",
+                identifier, max_eps_per_query
+            )));
+        }
+        if artifact.dp_expended + eps > budget {
+            return Err(ErrorCode::PrivacyBudgetExhausted.status(format!(
+                "The privacy budget of DataFrame {} is exhausted: ε={} remains",
+                identifier,
+                (budget - artifact.dp_expended).max(0.0)
+            )));
+        }
+    }
+    Ok(sources)
+}
+
+/// Returns the data frames read by `composite_plan`, as provenance nodes.
+fn plan_inputs(composite_plan: &CompositePlan) -> Vec<Node> {
+    composite_plan
+        .inputs()
+        .into_iter()
+        .map(Node::data_frame)
+        .collect()
+}
+
+/// Returns the events recording the use of the data frames `origins` by `user_id`, reported
+/// to their owners.
+fn usage_events(
+    dfs: &HashMap<String, DataFrameArtifact>,
+    origins: &[String],
+    user_id: &str,
+    kind: UsageKind,
+) -> Vec<UsageEvent> {
+    origins
+        .iter()
+        .map(|origin| {
+            let owner = dfs
+                .get(origin)
+                .and_then(|artifact| artifact.owner.as_deref());
+            UsageEvent::new(origin, user_id, kind).with_owner(owner)
+        })
+        .collect()
+}
+
+/// Summarizes `events` per data frame, user and kind of use, sorted in this order.
+fn usage_report(events: &[UsageEvent]) -> Result<DataFrame, Status> {
+    // Number of uses, of private uses, budget expended, derived artifacts and time of
+    // the last use.
+    let mut rows: std::collections::BTreeMap<_, (u64, u64, f64, Vec<&str>, u64)> =
+        Default::default();
+    for event in events {
+        let row = rows
+            .entry((&event.dataset, &event.user_id, event.kind.name()))
+            .or_default();
+        row.0 += 1;
+        row.1 += u64::from(event.private);
+        row.2 += event.eps;
+        row.3.extend(event.derived.as_deref());
+        row.4 = row.4.max(event.time);
+    }
+
+    let (mut datasets, mut users, mut kinds) = (Vec::new(), Vec::new(), Vec::new());
+    let (mut counts, mut private, mut eps, mut derived, mut last_used) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for ((dataset, user_id, kind), row) in rows {
+        datasets.push(dataset.as_str());
+        users.push(user_id.as_str());
+        kinds.push(kind);
+        counts.push(row.0);
+        private.push(row.1);
+        eps.push(row.2);
+        derived.push(row.3.join(","));
+        last_used.push(row.4);
+    }
+    DataFrame::new(vec![
+        Series::new("dataset", datasets),
+        Series::new("user_id", users),
+        Series::new("kind", kinds),
+        Series::new("count", counts),
+        Series::new("private", private),
+        Series::new("eps", eps),
+        Series::new("derived", derived),
+        Series::new("last_used", last_used),
+    ])
+    .map_err(|e| Status::internal(format!("Could not build the usage report: {}", e)))
+}
+
 fn get_df_header(df: &DataFrame) -> Result<String, Status> {
     serde_json::to_string(&df.schema())
         .map_err(|e| Status::internal(format!("Could not serialize data frame header: {}", e)))
@@ -430,13 +1309,31 @@ impl PolarsService for BastionLabPolars {
 
         let start_time = Instant::now();
 
-        let mut res = composite_plan.run(self, &user_id)?;
+        let cancellation = Cancellation::of_request(&request)?;
+        let eps = composite_plan.dp_eps();
+        let inputs = plan_inputs(&composite_plan);
+        let polars = self.clone();
+        let owner = user_id.clone();
+        let mut res = cancellation
+            .run_blocking(move |cancellation| {
+                Ok(composite_plan
+                    .run(&polars, &owner, cancellation)?
+                    .with_owner(owner))
+            })
+            .await?;
         // TODO: this isn't really great.. this does a full serialization under the hood
         let hash = hash_dataset(&mut res.dataframe)
             .map_err(|e| Status::internal(format!("Polars error: {e}")))?;
 
         let header = get_df_header(&res.dataframe)?;
+        let origins = res.origins.clone();
         let identifier = self.insert_df(res);
+        self.sess_manager
+            .track(&token, SessionResource::DataFrame(identifier.clone()));
+
+        self.record_query_usage(&origins, &user_id, &identifier, eps);
+        self.provenance
+            .record(Node::data_frame(&identifier), inputs, "query");
 
         let elapsed = start_time.elapsed();
 
@@ -454,6 +1351,45 @@ impl PolarsService for BastionLabPolars {
         Ok(Response::new(ReferenceResponse { identifier, header }))
     }
 
+    async fn validate_plan(
+        &self,
+        request: Request<Query>,
+    ) -> Result<Response<PlanValidation>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+
+        let report = serde_json::from_str::<CompositePlan>(&request.get_ref().composite_plan)
+            .map_err(|e| {
+                Status::invalid_argument(format!("Could not deserialize composite plan: {}", e))
+            })
+            .and_then(|composite_plan| composite_plan.validate(self, &user_id));
+
+        let res = match report {
+            Ok(report) => PlanValidation {
+                valid: true,
+                error: String::new(),
+                header: get_df_header(&report.schema)?,
+                estimated_rows: report.estimated_rows as u64,
+                estimated_bytes: report.estimated_bytes as u64,
+                scanned_rows: report.scanned_rows as u64,
+                scanned_bytes: report.scanned_bytes as u64,
+                warnings: report.warnings,
+                sizes_withheld: report.sizes_withheld,
+            },
+            // The plan is wrong, as opposed to the server failing to check it.
+            Err(e) if matches!(e.code(), Code::InvalidArgument | Code::NotFound) => {
+                PlanValidation {
+                    valid: false,
+                    error: e.message().to_string(),
+                    ..Default::default()
+                }
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(Response::new(res))
+    }
+
     async fn send_data_frame(
         &self,
         request: Request<Streaming<SendChunk>>,
@@ -461,10 +1397,25 @@ impl PolarsService for BastionLabPolars {
         let start_time = Instant::now();
 
         let token = self.sess_manager.get_token(&request)?;
-        let client_info = self.sess_manager.get_client_info(token)?;
-        let (df, hash) = unserialize_dataframe(request.into_inner()).await?;
+        let client_info = self.sess_manager.get_client_info(token.clone())?;
+        let encoding = ChunkEncoding::of_request(&request)?;
+        let owner = self.sess_manager.get_user_id(token)?;
+        let key_owner = self.sess_manager.auth_enabled().then_some(owner.as_str());
+        let (df, hash) = unserialize_dataframe(
+            request.into_inner(),
+            encoding,
+            self.max_upload_size,
+            |df, columns, key| {
+                self.pseudonym_keys
+                    .pseudonymize(df, columns, key, key_owner)
+            },
+        )
+        .await?;
+        let df = df.with_owner(owner);
         let header = get_df_header(&df.dataframe)?;
         let identifier = self.insert_df(df);
+        self.provenance
+            .record(Node::data_frame(&identifier), Vec::new(), "upload");
 
         let elapsed = start_time.elapsed();
         telemetry::add_event(
@@ -489,25 +1440,31 @@ impl PolarsService for BastionLabPolars {
         request: Request<ReferenceRequest>,
     ) -> Result<Response<Self::FetchDataFrameStream>, Status> {
         let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token.clone())?;
+        // Data owners are not limited.
+        let limited =
+            !(self.sess_manager.auth_enabled() && self.sess_manager.verify_if_owner(&user_id)?);
 
         let fut = {
             let df = self.get_df(
                 &request.get_ref().identifier,
+                &user_id,
+                limited,
                 Some(self.sess_manager.get_client_info(token)?),
             )?;
-            serialize_delayed_dataframe(df)
+            serialize_delayed_dataframe(df, ChunkEncoding::accepted_by(&request))
         };
         Ok(fut.await)
     }
 
     async fn list_data_frames(
         &self,
-        request: Request<Empty>,
+        request: Request<DataFrameQuery>,
     ) -> Result<Response<ReferenceList>, Status> {
         let token = self.sess_manager.get_token(&request)?;
 
-        let list = self
-            .get_headers()?
+        let (headers, next_page_token) = self.get_headers(request.get_ref())?;
+        let list = headers
             .into_iter()
             .map(|(identifier, header)| ReferenceResponse { identifier, header })
             .collect();
@@ -515,7 +1472,10 @@ impl PolarsService for BastionLabPolars {
             TelemetryEventProps::ListDataFrame {},
             Some(self.sess_manager.get_client_info(token)?),
         );
-        Ok(Response::new(ReferenceList { list }))
+        Ok(Response::new(ReferenceList {
+            list,
+            next_page_token,
+        }))
     }
 
     async fn get_data_frame_header(
@@ -615,7 +1575,7 @@ impl PolarsService for BastionLabPolars {
         let mut shuffled = false;
         let mut indices = vec![];
 
-        for array in out_arrays_store.iter() {
+        for (source, array) in arrays.iter().zip(out_arrays_store.iter()) {
             if !shuffled {
                 indices = (0..array.height()).collect();
                 indices.shuffle(&mut rng);
@@ -629,18 +1589,326 @@ impl PolarsService for BastionLabPolars {
             };
 
             let (upper, lower) = array.split((train_size, test_size));
-            out_arrays.append(&mut vec![
-                ReferenceResponse {
-                    identifier: self.insert_array(upper),
-                    header: String::default(),
-                },
-                ReferenceResponse {
-                    identifier: self.insert_array(lower),
+            for split in [upper, lower] {
+                let identifier = self.insert_array(split);
+                self.provenance.record(
+                    Node::array(&identifier),
+                    vec![Node::array(&source.identifier)],
+                    "split",
+                );
+                out_arrays.push(ReferenceResponse {
+                    identifier,
                     header: String::default(),
-                },
-            ])
+                });
+            }
+        }
+
+        Ok(Response::new(ReferenceList {
+            list: out_arrays,
+            next_page_token: String::new(),
+        }))
+    }
+
+    async fn get_usage_report(
+        &self,
+        request: Request<Empty>,
+    ) -> Result<Response<ReferenceResponse>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token.clone())?;
+        let auth_enabled = self.sess_manager.auth_enabled();
+        if auth_enabled && !self.sess_manager.verify_if_owner(&user_id)? {
+            return Err(Status::permission_denied(
+                "Only data owners can get usage reports.",
+            ));
+        }
+        let log = self.usage_log.as_ref().ok_or_else(|| {
+            Status::failed_precondition("Uses of data frames are not recorded on this server")
+        })?;
+
+        // Without authentication, every user owns every data frame.
+        let events: Vec<_> = log
+            .events()
+            .into_iter()
+            .filter(|event| !auth_enabled || event.owner.as_deref() == Some(user_id.as_str()))
+            .collect();
+        let df = usage_report(&events)?;
+        let artifact = DataFrameArtifact::new(df, Policy::allow_by_default(), Vec::new())
+            .with_fetchable(VerificationResult::Safe)
+            .with_owner(user_id);
+        let header = get_df_header(&artifact.dataframe)?;
+        let identifier = self.insert_df(artifact);
+        self.sess_manager
+            .track(&token, SessionResource::DataFrame(identifier.clone()));
+
+        info!("Generated usage report {}", identifier);
+
+        Ok(Response::new(ReferenceResponse { identifier, header }))
+    }
+
+    async fn list_pending_fetches(
+        &self,
+        request: Request<Empty>,
+    ) -> Result<Response<PendingFetchList>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        if self.sess_manager.auth_enabled() && !self.sess_manager.verify_if_owner(&user_id)? {
+            return Err(Status::permission_denied(
+                "Only data owners can list pending fetches.",
+            ));
+        }
+        Ok(Response::new(PendingFetchList {
+            list: self.approvals.list(),
+        }))
+    }
+
+    async fn decide_fetch(
+        &self,
+        request: Request<FetchDecision>,
+    ) -> Result<Response<PendingFetch>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        // Approvals are counted per owner key, which requires authentication.
+        if !self.sess_manager.verify_if_owner(&user_id)? {
+            return Err(Status::permission_denied(
+                "Only data owners can decide on fetches.",
+            ));
+        }
+        let FetchDecision {
+            request_id,
+            approve,
+        } = request.into_inner();
+        let fetch = self.approvals.decide(&request_id, &user_id, approve)?;
+        info!(
+            "Data owner {} {} fetch request {}",
+            user_id,
+            if approve { "approved" } else { "rejected" },
+            request_id
+        );
+        Ok(Response::new(fetch))
+    }
+
+    async fn create_pseudonym_key(
+        &self,
+        request: Request<Empty>,
+    ) -> Result<Response<PseudonymKey>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        if self.sess_manager.auth_enabled() && !self.sess_manager.verify_if_owner(&user_id)? {
+            return Err(Status::permission_denied(
+                "Only data owners can create pseudonymization keys.",
+            ));
+        }
+        let identifier = self.pseudonym_keys.create(&user_id)?;
+        self.persist_pseudonym_keys()?;
+        info!("Created pseudonymization key {}", identifier);
+        Ok(Response::new(PseudonymKey { identifier }))
+    }
+
+    async fn delete_pseudonym_key(
+        &self,
+        request: Request<PseudonymKey>,
+    ) -> Result<Response<Empty>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let owner = self.sess_manager.auth_enabled().then_some(user_id.as_str());
+        let identifier = &request.get_ref().identifier;
+        self.pseudonym_keys.delete(identifier, owner)?;
+        self.persist_pseudonym_keys()?;
+        info!("Deleted pseudonymization key {}", identifier);
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn schedule_query(
+        &self,
+        request: Request<QuerySchedule>,
+    ) -> Result<Response<QuerySchedule>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let schedule = request.into_inner();
+
+        // Plans that cannot run are rejected now rather than at every run.
+        let composite_plan: CompositePlan = serde_json::from_str(&schedule.composite_plan)
+            .map_err(|e| {
+                Status::invalid_argument(format!("Could not deserialize composite plan: {}", e))
+            })?;
+        composite_plan.validate(self, &user_id)?;
+        if self.dataframes.read().unwrap().contains_key(&schedule.name) {
+            return Err(Status::already_exists(format!(
+                "A dataframe is already registered under the name {}",
+                schedule.name
+            )));
+        }
+
+        self.schedules.insert(
+            &schedule.name,
+            &schedule.composite_plan,
+            &schedule.schedule,
+            &user_id,
+        )?;
+        self.persist_query_schedules()?;
+        info!(
+            "Scheduled query {} at `{}`",
+            schedule.name, schedule.schedule
+        );
+        Ok(Response::new(QuerySchedule {
+            owner: user_id,
+            last_run: 0,
+            last_error: String::new(),
+            ..schedule
+        }))
+    }
+
+    async fn list_scheduled_queries(
+        &self,
+        request: Request<Empty>,
+    ) -> Result<Response<QueryScheduleList>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let owner = self.sess_manager.auth_enabled().then_some(user_id.as_str());
+        Ok(Response::new(QueryScheduleList {
+            list: self.schedules.list(owner),
+        }))
+    }
+
+    async fn delete_scheduled_query(
+        &self,
+        request: Request<ReferenceRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let owner = self.sess_manager.auth_enabled().then_some(user_id.as_str());
+        let name = &request.get_ref().identifier;
+        self.schedules.delete(name, owner)?;
+        self.persist_query_schedules()?;
+        info!("Unscheduled query {}", name);
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn save_query(
+        &self,
+        request: Request<SavedQuery>,
+    ) -> Result<Response<SavedQuery>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let query = request.into_inner();
+
+        // Templates are checked with sample arguments, their runs are checked again.
+        let sample_plan = SavedQueries::sample_plan(&query)?;
+        let composite_plan: CompositePlan = serde_json::from_str(&sample_plan).map_err(|e| {
+            Status::invalid_argument(format!("Could not deserialize composite plan: {}", e))
+        })?;
+        composite_plan.validate(self, &user_id)?;
+        // Approvals are counted per owner key, which requires authentication.
+        let approvers = if self.sess_manager.auth_enabled() {
+            self.plan_owners(&composite_plan)
+        } else {
+            Vec::new()
+        };
+
+        let saved = self.saved_queries.insert(&query, &user_id, approvers)?;
+        self.persist_saved_queries()?;
+        info!("Saved query {}", saved.name);
+        Ok(Response::new(saved))
+    }
+
+    async fn list_saved_queries(
+        &self,
+        request: Request<Empty>,
+    ) -> Result<Response<SavedQueryList>, Status> {
+        self.sess_manager.get_token(&request)?;
+        Ok(Response::new(SavedQueryList {
+            list: self.saved_queries.list(),
+        }))
+    }
+
+    async fn approve_saved_query(
+        &self,
+        request: Request<ReferenceRequest>,
+    ) -> Result<Response<SavedQuery>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        if !self.sess_manager.verify_if_owner(&user_id)? {
+            return Err(Status::permission_denied(
+                "Only data owners can approve saved queries.",
+            ));
         }
+        let name = &request.get_ref().identifier;
+        let saved = self.saved_queries.approve(name, &user_id)?;
+        self.persist_saved_queries()?;
+        info!("Saved query {} approved by {}", name, user_id);
+        Ok(Response::new(saved))
+    }
+
+    async fn run_saved_query(
+        &self,
+        request: Request<SavedQueryRun>,
+    ) -> Result<Response<ReferenceResponse>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token.clone())?;
+        let SavedQueryRun { name, arguments } = request.get_ref();
 
-        Ok(Response::new(ReferenceList { list: out_arrays }))
+        let (plan, approvals) = self.saved_queries.bind(name, arguments)?;
+        let composite_plan: CompositePlan = serde_json::from_str(&plan).map_err(|e| {
+            Status::invalid_argument(format!("Could not deserialize composite plan: {}", e))
+        })?;
+        // The approvals only cover the data of the owners who gave them.
+        let owners = self.plan_owners(&composite_plan);
+        let approved = !owners.is_empty() && owners.iter().all(|owner| approvals.contains(owner));
+
+        let cancellation = Cancellation::of_request(&request)?;
+        let eps = composite_plan.dp_eps();
+        let inputs = plan_inputs(&composite_plan);
+        let polars = self.clone();
+        let owner = user_id.clone();
+        let mut res = cancellation
+            .run_blocking(move |cancellation| {
+                Ok(composite_plan
+                    .run(&polars, &owner, cancellation)?
+                    .with_owner(owner))
+            })
+            .await?;
+        // The owners approved every run of the query beforehand, rejections and budgets
+        // still apply.
+        if approved
+            && matches!(
+                res.fetchable,
+                VerificationResult::Unsafe {
+                    action: UnsafeAction::Review,
+                    ..
+                }
+            )
+        {
+            res.fetchable = VerificationResult::Safe;
+        }
+
+        let header = get_df_header(&res.dataframe)?;
+        let origins = res.origins.clone();
+        let identifier = self.insert_df(res);
+        self.sess_manager
+            .track(&token, SessionResource::DataFrame(identifier.clone()));
+        self.record_query_usage(&origins, &user_id, &identifier, eps);
+        self.provenance.record(
+            Node::data_frame(&identifier),
+            inputs,
+            &format!("saved query {}", name),
+        );
+
+        info!("Succesfully ran saved query {} on {}", name, identifier);
+
+        Ok(Response::new(ReferenceResponse { identifier, header }))
+    }
+
+    async fn delete_saved_query(
+        &self,
+        request: Request<ReferenceRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let author = self.sess_manager.auth_enabled().then_some(user_id.as_str());
+        let name = &request.get_ref().identifier;
+        self.saved_queries.delete(name, author)?;
+        self.persist_saved_queries()?;
+        info!("Deleted saved query {}", name);
+        Ok(Response::new(Empty {}))
     }
 }
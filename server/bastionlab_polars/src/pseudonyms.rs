@@ -0,0 +1,127 @@
+use bastionlab_common::prelude::*;
+use polars::prelude::*;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use tonic::Status;
+use uuid::Uuid;
+
+/// Secret key of a data owner, used to pseudonymize the identifier columns of their data.
+#[derive(Clone, Serialize, Deserialize)]
+struct PseudonymKey {
+    owner: String,
+    /// Hex-encoded HMAC-SHA256 key.
+    secret: String,
+}
+
+/// Keys replacing identifiers with keyed hashes, so that data frames can be joined on
+/// pseudonyms without revealing the identifiers they were computed from.
+///
+/// Keys never leave the server and only apply to the data of their owner: other users
+/// could otherwise pseudonymize guessed identifiers to re-identify the rows.
+#[derive(Default)]
+pub struct PseudonymKeys {
+    keys: RwLock<HashMap<String, PseudonymKey>>,
+}
+
+impl PseudonymKeys {
+    /// Generates a key for `owner` and returns its identifier.
+    pub fn create(&self, owner: &str) -> Result<String, Status> {
+        let mut secret = [0u8; 32];
+        SystemRandom::new()
+            .fill(&mut secret)
+            .map_err(|_| Status::internal("Could not generate pseudonymization key"))?;
+        let identifier = format!("{}", Uuid::new_v4());
+        self.keys.write().unwrap().insert(
+            identifier.clone(),
+            PseudonymKey {
+                owner: owner.to_string(),
+                secret: hex::encode(secret),
+            },
+        );
+        Ok(identifier)
+    }
+
+    /// Deletes the key `identifier`, after which its pseudonyms cannot be computed anymore.
+    /// Only its owner may delete it, unless `owner` is `None`.
+    pub fn delete(&self, identifier: &str, owner: Option<&str>) -> Result<(), Status> {
+        let mut keys = self.keys.write().unwrap();
+        check_owner(&keys, identifier, owner)?;
+        keys.remove(identifier);
+        Ok(())
+    }
+
+    /// Replaces the values of `columns` in `df` with their pseudonyms under the key
+    /// `identifier`: the hex-encoded HMAC-SHA256 of the values formatted as strings. Nulls
+    /// are left as they are.
+    ///
+    /// `owner` is the owner of the data, who must own the key, unless `None`.
+    pub fn pseudonymize(
+        &self,
+        df: &mut DataFrame,
+        columns: &[String],
+        identifier: &str,
+        owner: Option<&str>,
+    ) -> Result<(), Status> {
+        let key = {
+            let keys = self.keys.read().unwrap();
+            let key = check_owner(&keys, identifier, owner)?;
+            let secret = hex::decode(&key.secret)
+                .map_err(|_| Status::internal("Corrupted pseudonymization key"))?;
+            hmac::Key::new(hmac::HMAC_SHA256, &secret)
+        };
+
+        for name in columns {
+            let series = df.column(name).map_err(|_| {
+                Status::invalid_argument(format!(
+                    "Could not pseudonymize: no column `{}` in data frame",
+                    name
+                ))
+            })?;
+            let values = series.cast(&DataType::Utf8).map_err(|e| {
+                Status::invalid_argument(format!("Could not pseudonymize `{}`: {}", name, e))
+            })?;
+            let mut pseudonyms: Utf8Chunked = values
+                .utf8()
+                .map_err(|e| Status::internal(format!("Polars error: {}", e)))?
+                .into_iter()
+                .map(|value| value.map(|v| hex::encode(hmac::sign(&key, v.as_bytes()))))
+                .collect();
+            pseudonyms.rename(name);
+            df.replace(name, pseudonyms.into_series())
+                .map_err(|e| Status::internal(format!("Polars error: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>, Status> {
+        serde_json::to_vec(&*self.keys.read().unwrap())
+            .map_err(|_| Status::internal("Could not serialize pseudonymization keys"))
+    }
+
+    pub fn load(&self, data: &[u8]) -> Result<(), Status> {
+        let keys: HashMap<String, PseudonymKey> = serde_json::from_slice(data)
+            .map_err(|_| Status::data_loss("Could not parse pseudonymization keys"))?;
+        self.keys.write().unwrap().extend(keys);
+        Ok(())
+    }
+}
+
+fn check_owner<'a>(
+    keys: &'a HashMap<String, PseudonymKey>,
+    identifier: &str,
+    owner: Option<&str>,
+) -> Result<&'a PseudonymKey, Status> {
+    let key = keys.get(identifier).ok_or_else(|| {
+        Status::not_found(format!(
+            "Could not find pseudonymization key: {}",
+            identifier
+        ))
+    })?;
+    match owner {
+        Some(owner) if owner != key.owner => Err(Status::permission_denied(
+            "Pseudonymization keys only apply to the data of their owner",
+        )),
+        _ => Ok(key),
+    }
+}
@@ -0,0 +1,400 @@
+use bastionlab_common::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tonic::Status;
+
+use crate::{
+    polars_proto::{QueryParameter, SavedQuery as SavedQueryMessage},
+    schedules::check_name,
+};
+
+/// Prefix of the string literals standing for the parameters in the plans of saved queries,
+/// followed by the name of the parameter.
+const PARAMETER_MARKER: &str = "__bastionlab_param__:";
+
+/// Type of a parameter of a saved query, which arguments are checked against before being
+/// bound. Plans convert the bound strings to the type themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum ParameterType {
+    Int,
+    Float,
+    String,
+    Bool,
+    /// `YYYY-MM-DD`.
+    Date,
+    /// `YYYY-MM-DDTHH:MM:SS`.
+    Datetime,
+}
+
+impl ParameterType {
+    fn parse(dtype: &str) -> Result<Self, Status> {
+        match dtype {
+            "int" => Ok(ParameterType::Int),
+            "float" => Ok(ParameterType::Float),
+            "string" => Ok(ParameterType::String),
+            "bool" => Ok(ParameterType::Bool),
+            "date" => Ok(ParameterType::Date),
+            "datetime" => Ok(ParameterType::Datetime),
+            _ => Err(Status::invalid_argument(format!(
+                "Invalid parameter type {}: expected int, float, string, bool, date or datetime",
+                dtype
+            ))),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ParameterType::Int => "int",
+            ParameterType::Float => "float",
+            ParameterType::String => "string",
+            ParameterType::Bool => "bool",
+            ParameterType::Date => "date",
+            ParameterType::Datetime => "datetime",
+        }
+    }
+
+    /// Value bound to check the plan when the query is saved.
+    fn sample(&self) -> &'static str {
+        match self {
+            ParameterType::Int | ParameterType::Float => "0",
+            ParameterType::String => "",
+            ParameterType::Bool => "false",
+            ParameterType::Date => "1970-01-01",
+            ParameterType::Datetime => "1970-01-01T00:00:00",
+        }
+    }
+
+    fn accepts(&self, value: &str) -> bool {
+        match self {
+            ParameterType::Int => value.parse::<i64>().is_ok(),
+            ParameterType::Float => value.parse::<f64>().map(f64::is_finite).unwrap_or(false),
+            ParameterType::String => true,
+            ParameterType::Bool => value == "true" || value == "false",
+            ParameterType::Date => is_date(value),
+            ParameterType::Datetime => match value.split_once('T') {
+                Some((date, time)) => is_date(date) && is_time(time),
+                None => false,
+            },
+        }
+    }
+}
+
+/// Whether `value` is a valid `YYYY-MM-DD` date.
+fn is_date(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    let numbers: Vec<u32> = match parts[..] {
+        [year, month, day] if year.len() == 4 && month.len() == 2 && day.len() == 2 => {
+            match parts.iter().map(|part| part.parse()).collect() {
+                Ok(numbers) => numbers,
+                Err(_) => return false,
+            }
+        }
+        _ => return false,
+    };
+    let (year, month, day) = (numbers[0], numbers[1], numbers[2]);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
+
+/// Whether `value` is a valid `HH:MM:SS` time.
+fn is_time(value: &str) -> bool {
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() != 3 || parts.iter().any(|part| part.len() != 2) {
+        return false;
+    }
+    match parts
+        .iter()
+        .map(|part| part.parse())
+        .collect::<Result<Vec<u32>, _>>()
+    {
+        Ok(numbers) => numbers[0] < 24 && numbers[1] < 60 && numbers[2] < 60,
+        Err(_) => false,
+    }
+}
+
+/// Plan template saved under a name, whose runs only differ by the values of its parameters.
+#[derive(Clone, Serialize, Deserialize)]
+struct SavedQuery {
+    composite_plan: String,
+    parameters: Vec<(String, ParameterType)>,
+    author: String,
+    /// Owners of the data frames read by the query, who must all approve it.
+    approvers: Vec<String>,
+    approvals: Vec<String>,
+}
+
+impl SavedQuery {
+    /// Whether every owner of the data read by the query approved it.
+    fn approved(&self) -> bool {
+        !self.approvers.is_empty()
+            && self
+                .approvers
+                .iter()
+                .all(|approver| self.approvals.contains(approver))
+    }
+
+    fn to_message(&self, name: &str) -> SavedQueryMessage {
+        SavedQueryMessage {
+            name: name.to_string(),
+            composite_plan: self.composite_plan.clone(),
+            parameters: self
+                .parameters
+                .iter()
+                .map(|(name, dtype)| QueryParameter {
+                    name: name.clone(),
+                    dtype: dtype.name().to_string(),
+                })
+                .collect(),
+            author: self.author.clone(),
+            approvers: self.approvers.clone(),
+            approvals: self.approvals.clone(),
+        }
+    }
+}
+
+/// Binds `arguments` to the parameters of `composite_plan`, replacing the string literals
+/// standing for them. Every parameter must be used by the plan and given an argument of
+/// its type.
+fn bind(
+    composite_plan: &str,
+    parameters: &[(String, ParameterType)],
+    arguments: &HashMap<String, String>,
+) -> Result<String, Status> {
+    for name in arguments.keys() {
+        if !parameters.iter().any(|(parameter, _)| parameter == name) {
+            return Err(Status::invalid_argument(format!(
+                "Unknown parameter {}",
+                name
+            )));
+        }
+    }
+    let mut values = HashMap::new();
+    for (name, dtype) in parameters {
+        let value = arguments
+            .get(name)
+            .ok_or_else(|| Status::invalid_argument(format!("Missing parameter {}", name)))?;
+        if !dtype.accepts(value) {
+            return Err(Status::invalid_argument(format!(
+                "Invalid value {:?} for parameter {} of type {}",
+                value,
+                name,
+                dtype.name()
+            )));
+        }
+        values.insert(name.as_str(), value.as_str());
+    }
+
+    let mut plan: Value = serde_json::from_str(composite_plan).map_err(|e| {
+        Status::invalid_argument(format!("Could not deserialize composite plan: {}", e))
+    })?;
+    let mut used = Vec::new();
+    bind_value(&mut plan, &values, &mut used, false)?;
+    for (name, _) in parameters {
+        if !used.contains(&name.as_str()) {
+            return Err(Status::invalid_argument(format!(
+                "Parameter {} is not used by the plan",
+                name
+            )));
+        }
+    }
+    serde_json::to_string(&plan)
+        .map_err(|e| Status::internal(format!("Could not serialize composite plan: {}", e)))
+}
+
+/// Replaces the parameter markers in `value`, which is the content of a literal expression
+/// if `literal` is set. Markers are only accepted as string literals, so that arguments
+/// cannot name the data frames or the columns the plan reads once it is approved.
+fn bind_value<'a>(
+    value: &mut Value,
+    values: &HashMap<&'a str, &'a str>,
+    used: &mut Vec<&'a str>,
+    literal: bool,
+) -> Result<(), Status> {
+    match value {
+        Value::String(s) => {
+            if let Some(name) = s.strip_prefix(PARAMETER_MARKER) {
+                return Err(Status::invalid_argument(format!(
+                    "Parameter {} can only be used as a literal value",
+                    name
+                )));
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                bind_value(item, values, used, false)?;
+            }
+        }
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                match field {
+                    Value::String(s) if literal && key == "Utf8" => {
+                        if let Some(name) = s.strip_prefix(PARAMETER_MARKER) {
+                            let (&name, &bound) = values.get_key_value(name).ok_or_else(|| {
+                                Status::invalid_argument(format!("Undeclared parameter {}", name))
+                            })?;
+                            *s = bound.to_string();
+                            used.push(name);
+                        }
+                    }
+                    _ => bind_value(field, values, used, key == "Literal")?,
+                }
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Saved queries, by name.
+#[derive(Default)]
+pub struct SavedQueries {
+    queries: RwLock<HashMap<String, SavedQuery>>,
+}
+
+impl SavedQueries {
+    /// Checks the parameters of `query` and binds them to sample values, returning the
+    /// resulting plan for the caller to check against the data it reads.
+    pub fn sample_plan(query: &SavedQueryMessage) -> Result<String, Status> {
+        let parameters = parse_parameters(&query.parameters)?;
+        let arguments = parameters
+            .iter()
+            .map(|(name, dtype)| (name.clone(), dtype.sample().to_string()))
+            .collect();
+        bind(&query.composite_plan, &parameters, &arguments)
+    }
+
+    /// Saves `query` under its name on behalf of `author`, to be approved by `approvers`.
+    /// An author who is an approver approves the query by saving it.
+    pub fn insert(
+        &self,
+        query: &SavedQueryMessage,
+        author: &str,
+        approvers: Vec<String>,
+    ) -> Result<SavedQueryMessage, Status> {
+        check_name(&query.name)?;
+        let parameters = parse_parameters(&query.parameters)?;
+        let mut queries = self.queries.write().unwrap();
+        if queries.contains_key(&query.name) {
+            return Err(Status::already_exists(format!(
+                "A query is already saved under the name {}",
+                query.name
+            )));
+        }
+        let approvals = approvers
+            .iter()
+            .filter(|approver| *approver == author)
+            .cloned()
+            .collect();
+        let saved = SavedQuery {
+            composite_plan: query.composite_plan.clone(),
+            parameters,
+            author: author.to_string(),
+            approvers,
+            approvals,
+        };
+        let message = saved.to_message(&query.name);
+        queries.insert(query.name.clone(), saved);
+        Ok(message)
+    }
+
+    /// Returns the plan of the query `name` with `arguments` bound to its parameters, and
+    /// the owners who approved the query if all its approvers did.
+    pub fn bind(
+        &self,
+        name: &str,
+        arguments: &HashMap<String, String>,
+    ) -> Result<(String, Vec<String>), Status> {
+        let queries = self.queries.read().unwrap();
+        let query = queries
+            .get(name)
+            .ok_or_else(|| Status::not_found(format!("Could not find saved query: {}", name)))?;
+        let plan = bind(&query.composite_plan, &query.parameters, arguments)?;
+        let approvals = if query.approved() {
+            query.approvals.clone()
+        } else {
+            Vec::new()
+        };
+        Ok((plan, approvals))
+    }
+
+    /// Records the approval of the query `name` by the data owner `owner_id`.
+    pub fn approve(&self, name: &str, owner_id: &str) -> Result<SavedQueryMessage, Status> {
+        let mut queries = self.queries.write().unwrap();
+        let query = queries
+            .get_mut(name)
+            .ok_or_else(|| Status::not_found(format!("Could not find saved query: {}", name)))?;
+        if !query.approvers.iter().any(|approver| approver == owner_id) {
+            return Err(Status::permission_denied(
+                "Only the owners of the data frames read by a query can approve it.",
+            ));
+        }
+        if !query.approvals.iter().any(|approval| approval == owner_id) {
+            query.approvals.push(owner_id.to_string());
+        }
+        Ok(query.to_message(name))
+    }
+
+    /// Deletes the query `name`. Only its author may do so, unless `author` is `None`.
+    pub fn delete(&self, name: &str, author: Option<&str>) -> Result<(), Status> {
+        let mut queries = self.queries.write().unwrap();
+        let query = queries
+            .get(name)
+            .ok_or_else(|| Status::not_found(format!("Could not find saved query: {}", name)))?;
+        if author.map(|author| author != query.author).unwrap_or(false) {
+            return Err(Status::permission_denied(
+                "Only the user who saved a query can delete it",
+            ));
+        }
+        queries.remove(name);
+        Ok(())
+    }
+
+    /// Returns the saved queries, by name.
+    pub fn list(&self) -> Vec<SavedQueryMessage> {
+        let mut list: Vec<_> = self
+            .queries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, query)| query.to_message(name))
+            .collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>, Status> {
+        serde_json::to_vec(&*self.queries.read().unwrap())
+            .map_err(|_| Status::internal("Could not serialize saved queries"))
+    }
+
+    pub fn load(&self, data: &[u8]) -> Result<(), Status> {
+        let queries: HashMap<String, SavedQuery> = serde_json::from_slice(data)
+            .map_err(|_| Status::data_loss("Could not parse saved queries"))?;
+        self.queries.write().unwrap().extend(queries);
+        Ok(())
+    }
+}
+
+fn parse_parameters(parameters: &[QueryParameter]) -> Result<Vec<(String, ParameterType)>, Status> {
+    let mut parsed: Vec<(String, ParameterType)> = Vec::new();
+    for parameter in parameters {
+        if parameter.name.is_empty() || parsed.iter().any(|(name, _)| *name == parameter.name) {
+            return Err(Status::invalid_argument(format!(
+                "Parameter names must be distinct and non-empty: {:?}",
+                parameter.name
+            )));
+        }
+        parsed.push((
+            parameter.name.clone(),
+            ParameterType::parse(&parameter.dtype)?,
+        ));
+    }
+    Ok(parsed)
+}
@@ -0,0 +1,268 @@
+use bastionlab_common::prelude::*;
+use serde::{Deserialize, Serialize};
+use tonic::Status;
+use uuid::Uuid;
+
+use crate::polars_proto::QuerySchedule;
+
+/// Cron-like schedule of a recurring query, in UTC: minute, hour, day of month, month and
+/// day of week, e.g. `0 6 * * 1` for every Monday at 6:00.
+///
+/// Fields are `*`, values, ranges such as `1-5` or lists of them, with an optional step
+/// such as `*/15`. As with cron, when both the day of month and the day of week are
+/// restricted, either of them matching is enough.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, Status> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(Status::invalid_argument(format!(
+                "Invalid schedule `{}`: expected minute, hour, day of month, month and day of week",
+                expression
+            )));
+        }
+        // Sunday is either 0 or 7.
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    /// Whether the minute of the Unix timestamp `time`, in seconds, is scheduled.
+    pub fn matches(&self, time: u64) -> bool {
+        let days = time / 86_400;
+        let minute = time / 60 % 60;
+        let hour = time / 3_600 % 24;
+        // 1970-01-01 was a Thursday.
+        let weekday = (days + 4) % 7;
+        let (month, day) = month_and_day(days);
+
+        let has = |set: u64, value: u64| set & (1 << value) != 0;
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => has(self.days, day) || has(self.weekdays, weekday),
+            _ => has(self.days, day) && has(self.weekdays, weekday),
+        };
+        has(self.minutes, minute) && has(self.hours, hour) && has(self.months, month) && day_matches
+    }
+}
+
+/// Parses a field of a schedule into the set of its values, as a bit set.
+fn parse_field(field: &str, min: u64, max: u64) -> Result<u64, Status> {
+    let invalid = || {
+        Status::invalid_argument(format!(
+            "Invalid schedule field `{}`: values must be between {} and {}",
+            field, min, max
+        ))
+    };
+    let mut set = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse().map_err(|_| invalid())?),
+            None => (item, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (
+                    start.parse().map_err(|_| invalid())?,
+                    end.parse().map_err(|_| invalid())?,
+                ),
+                None => {
+                    let value = range.parse().map_err(|_| invalid())?;
+                    // As with cron, `5/10` starts at 5 and runs to the maximum.
+                    (value, if step > 1 { max } else { value })
+                }
+            },
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+/// Returns the month, from 1, and the day of month of the day `days` after 1970-01-01.
+fn month_and_day(days: u64) -> (u64, u64) {
+    // Counts from 0000-03-01 so that leap days end the years.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    (month, day)
+}
+
+/// Query run on a schedule by the user who created it, whose result replaces the data frame
+/// registered under the name of the query.
+#[derive(Clone, Serialize, Deserialize)]
+struct ScheduledQuery {
+    composite_plan: String,
+    schedule: String,
+    owner: String,
+    /// Unix timestamp of the last run, in seconds.
+    last_run: Option<u64>,
+    last_error: Option<String>,
+}
+
+/// Recurring queries, by name.
+#[derive(Default)]
+pub struct QuerySchedules {
+    queries: RwLock<HashMap<String, ScheduledQuery>>,
+}
+
+impl QuerySchedules {
+    /// Schedules `composite_plan` to run as `owner` under the name `name`.
+    pub fn insert(
+        &self,
+        name: &str,
+        composite_plan: &str,
+        schedule: &str,
+        owner: &str,
+    ) -> Result<(), Status> {
+        check_name(name)?;
+        CronSchedule::parse(schedule)?;
+        let mut queries = self.queries.write().unwrap();
+        if queries.contains_key(name) {
+            return Err(Status::already_exists(format!(
+                "A query is already scheduled under the name {}",
+                name
+            )));
+        }
+        queries.insert(
+            name.to_string(),
+            ScheduledQuery {
+                composite_plan: composite_plan.to_string(),
+                schedule: schedule.to_string(),
+                owner: owner.to_string(),
+                last_run: None,
+                last_error: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Unschedules the query `name`. Only its owner may do so, unless `owner` is `None`.
+    pub fn delete(&self, name: &str, owner: Option<&str>) -> Result<(), Status> {
+        let mut queries = self.queries.write().unwrap();
+        let query = queries.get(name).ok_or_else(|| {
+            Status::not_found(format!("Could not find scheduled query: {}", name))
+        })?;
+        if owner.map(|owner| owner != query.owner).unwrap_or(false) {
+            return Err(Status::permission_denied(
+                "Only the user who scheduled a query can unschedule it",
+            ));
+        }
+        queries.remove(name);
+        Ok(())
+    }
+
+    /// Returns the queries scheduled by `owner`, or every query if `None`, by name.
+    pub fn list(&self, owner: Option<&str>) -> Vec<QuerySchedule> {
+        let mut list: Vec<_> = self
+            .queries
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, query)| owner.map(|owner| owner == query.owner).unwrap_or(true))
+            .map(|(name, query)| QuerySchedule {
+                name: name.clone(),
+                composite_plan: query.composite_plan.clone(),
+                schedule: query.schedule.clone(),
+                owner: query.owner.clone(),
+                last_run: query.last_run.unwrap_or_default(),
+                last_error: query.last_error.clone().unwrap_or_default(),
+            })
+            .collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
+    /// Returns the name, plan and owner of the queries scheduled at the minute of `time`,
+    /// a Unix timestamp in seconds, and marks them as run so that they run once per minute.
+    pub fn due(&self, time: u64) -> Vec<(String, String, String)> {
+        let mut due = Vec::new();
+        for (name, query) in self.queries.write().unwrap().iter_mut() {
+            let ran = query.last_run.map(|t| t / 60 == time / 60).unwrap_or(false);
+            let scheduled = CronSchedule::parse(&query.schedule)
+                .map(|schedule| schedule.matches(time))
+                .unwrap_or(false);
+            if scheduled && !ran {
+                query.last_run = Some(time);
+                due.push((
+                    name.clone(),
+                    query.composite_plan.clone(),
+                    query.owner.clone(),
+                ));
+            }
+        }
+        due
+    }
+
+    /// Records the outcome of the last run of the query `name`.
+    pub fn record_outcome(&self, name: &str, outcome: &Result<(), Status>) {
+        if let Some(query) = self.queries.write().unwrap().get_mut(name) {
+            query.last_error = outcome.as_ref().err().map(|e| e.message().to_string());
+        }
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>, Status> {
+        serde_json::to_vec(&*self.queries.read().unwrap())
+            .map_err(|_| Status::internal("Could not serialize scheduled queries"))
+    }
+
+    pub fn load(&self, data: &[u8]) -> Result<(), Status> {
+        let queries: HashMap<String, ScheduledQuery> = serde_json::from_slice(data)
+            .map_err(|_| Status::data_loss("Could not parse scheduled queries"))?;
+        self.queries.write().unwrap().extend(queries);
+        Ok(())
+    }
+}
+
+/// Names are used as data frame identifiers, and file names once saved, so they are kept
+/// short, made of safe characters and distinct from generated identifiers.
+pub fn check_name(name: &str) -> Result<(), Status> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && Uuid::parse_str(name).is_err();
+    if valid {
+        Ok(())
+    } else {
+        Err(Status::invalid_argument(format!(
+            "Invalid name {}: names have up to 64 letters, digits, `-` and `_` and cannot be UUIDs",
+            name
+        )))
+    }
+}
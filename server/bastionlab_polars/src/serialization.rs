@@ -1,8 +1,11 @@
 use super::polars_proto::{fetch_chunk, FetchChunk, SendChunk};
+use crate::access_control::Policy;
 use crate::prelude::*;
 use crate::{DataFrameArtifact, DelayedDataFrame, FetchStatus};
+use bastionlab_common::compression::{check_upload_size, ChunkEncoding};
 use polars::prelude::*;
 use ring::digest;
+use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 use tonic::{Response, Status};
@@ -14,39 +17,60 @@ const CHUNK_SIZE: usize = 32 * 1024;
 // which means, we have to do a full copy to a buffer and we cannot parse it as we go
 // also: polar's IpcStreamReader requires the underlying stream to be Seek; which is weird & does not make sense
 
+/// Reads an uploaded data frame, whose identifier columns are replaced with their pseudonyms
+/// by `pseudonymize` when requested, before it is stored.
 pub async fn unserialize_dataframe(
     mut stream: tonic::Streaming<SendChunk>,
+    encoding: ChunkEncoding,
+    max_size: Option<usize>,
+    pseudonymize: impl FnOnce(&mut DataFrame, &[String], &str) -> Result<(), Status>,
 ) -> Result<(DataFrameArtifact, String), Status> {
     let mut buf: Vec<u8> = Vec::new();
     let mut first = true;
     let mut policy = String::new();
     let mut sanitized_columns = Vec::new();
-
-    let mut hasher = digest::Context::new(&digest::SHA256);
+    let mut ttl_seconds = 0;
+    let mut pseudonymized_columns = Vec::new();
+    let mut pseudonym_key = String::new();
 
     while let Some(chunk) = stream.next().await {
         let mut chunk = chunk?;
-        hasher.update(&chunk.data);
+        check_upload_size(buf.len() + chunk.data.len(), max_size)?;
         buf.append(&mut chunk.data);
         if first {
             policy = chunk.policy;
             sanitized_columns = chunk.sanitized_columns;
+            ttl_seconds = chunk.ttl_seconds;
+            pseudonymized_columns = chunk.pseudonymized_columns;
+            pseudonym_key = chunk.pseudonym_key;
             first = false;
         }
     }
 
-    let hash = hex::encode(hasher.finish().as_ref());
+    let buf = encoding.decode(buf, max_size)?;
+    let hash = hex::encode(digest::digest(&digest::SHA256, &buf).as_ref());
 
-    let policy = serde_json::from_str(&policy).map_err(|err| {
+    let policy: Policy = serde_json::from_str(&policy).map_err(|err| {
         Status::invalid_argument(format!("Error during the parsing of the policy: {err}"))
     })?;
+    policy.validate()?;
 
     let view = std::io::Cursor::new(&buf);
-    let df = polars::io::ipc::IpcReader::new(view)
+    let mut df = polars::io::ipc::IpcReader::new(view)
         .finish()
         .map_err(|err| Status::invalid_argument(format!("Polars error: {err}")))?;
+    if !pseudonymized_columns.is_empty() {
+        pseudonymize(&mut df, &pseudonymized_columns, &pseudonym_key)?;
+    }
 
-    Ok((DataFrameArtifact::new(df, policy, sanitized_columns), hash))
+    let artifact = DataFrameArtifact::new(df, policy, sanitized_columns)
+        .with_pseudonymized(pseudonymized_columns);
+    let artifact = if ttl_seconds > 0 {
+        artifact.with_ttl(Duration::from_secs(ttl_seconds))
+    } else {
+        artifact
+    };
+    Ok((artifact, hash))
 }
 
 // so, to hash a dataset, this does a full serialization; that's kinda bad
@@ -68,23 +92,18 @@ fn dataframe_ser_helper(df: &mut DataFrame) -> Result<Vec<u8>, PolarsError> {
 
 pub async fn serialize_delayed_dataframe(
     df: DelayedDataFrame,
+    encoding: ChunkEncoding,
 ) -> Response<ReceiverStream<Result<FetchChunk, Status>>> {
     let (tx, rx) = mpsc::channel(4);
 
-    match df.fetch_status {
-        FetchStatus::Pending(reason) => tx
-            .send(Ok(FetchChunk {
-                body: Some(fetch_chunk::Body::Pending(reason)),
-            }))
-            .await
-            .unwrap(),
-        FetchStatus::Warning(reason) => tx
-            .send(Ok(FetchChunk {
-                body: Some(fetch_chunk::Body::Warning(reason)),
-            }))
-            .await
-            .unwrap(),
-        _ => (),
+    // The receiver is not returned yet, so the channel cannot be closed and has room.
+    let notice = match df.fetch_status {
+        FetchStatus::Pending(reason) => Some(fetch_chunk::Body::Pending(reason)),
+        FetchStatus::Warning(reason) => Some(fetch_chunk::Body::Warning(reason)),
+        _ => None,
+    };
+    if let Some(body) = notice {
+        let _ignored = tx.try_send(Ok(FetchChunk { body: Some(body) }));
     }
 
     tokio::spawn(async move {
@@ -103,7 +122,8 @@ pub async fn serialize_delayed_dataframe(
         };
 
         let res = dataframe_ser_helper(&mut df)
-            .map_err(|err| Status::internal(format!("Polars error: {err}"))); // this is an internal error
+            .map_err(|err| Status::internal(format!("Polars error: {err}"))) // this is an internal error
+            .and_then(|buf| encoding.encode(buf));
 
         let buf = match res {
             Ok(buf) => buf,
@@ -114,6 +134,13 @@ pub async fn serialize_delayed_dataframe(
             }
         };
 
+        let total_size = FetchChunk {
+            body: Some(fetch_chunk::Body::TotalSize(buf.len() as u64)),
+        };
+        if let Err(_ignored) = tx.send(Ok(total_size)).await {
+            return;
+        }
+
         for chunk in buf.chunks(CHUNK_SIZE) {
             let data = FetchChunk {
                 body: Some(fetch_chunk::Body::Data(chunk.into())),
@@ -127,5 +154,7 @@ pub async fn serialize_delayed_dataframe(
         }
     });
 
-    Response::new(ReceiverStream::new(rx))
+    let mut response = Response::new(ReceiverStream::new(rx));
+    encoding.set_on(&mut response);
+    response
 }
@@ -0,0 +1,149 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Number of bits of the hashes used to pick a register, for a standard error of about 0.8%.
+const HLL_PRECISION: u32 = 14;
+
+/// HyperLogLog sketch, estimating the number of distinct values added to it in constant
+/// memory.
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        HyperLogLog {
+            registers: vec![0; 1 << HLL_PRECISION],
+        }
+    }
+
+    pub fn add<T: Hash + ?Sized>(&mut self, value: &T) {
+        // The keys of the default hasher are fixed, so equal values have equal hashes.
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let index = (hash >> (64 - HLL_PRECISION)) as usize;
+        // The sentinel bit bounds the rank when the remaining bits are all zeros.
+        let rest = (hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|&rank| 2f64.powi(-(rank as i32)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&rank| rank == 0).count();
+        // Linear counting is more accurate for small cardinalities.
+        if estimate <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            estimate
+        }
+    }
+}
+
+/// Number of values buffered before they are merged into the centroids of a [`TDigest`].
+const TDIGEST_BUFFER_SIZE: usize = 4096;
+
+/// Merging t-digest, estimating the quantiles of the values added to it with a number of
+/// centroids bounded by `compression`.
+///
+/// Centroids are small near the extreme quantiles and large near the median, which keeps
+/// the relative error on the extreme quantiles low.
+pub struct TDigest {
+    compression: f64,
+    /// Means and weights of the centroids, sorted by mean.
+    centroids: Vec<(f64, f64)>,
+    buffer: Vec<f64>,
+    min: f64,
+    max: f64,
+}
+
+impl TDigest {
+    pub fn new(compression: f64) -> Self {
+        TDigest {
+            compression,
+            centroids: Vec::new(),
+            buffer: Vec::with_capacity(TDIGEST_BUFFER_SIZE),
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Adds `value`, which must not be NaN.
+    pub fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.buffer.push(value);
+        if self.buffer.len() >= TDIGEST_BUFFER_SIZE {
+            self.merge_buffer();
+        }
+    }
+
+    fn merge_buffer(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut items = std::mem::take(&mut self.centroids);
+        items.extend(self.buffer.drain(..).map(|value| (value, 1.0)));
+        items.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let total: f64 = items.iter().map(|(_, weight)| weight).sum();
+        let mut merged = Vec::with_capacity(items.len().min(self.compression as usize * 2));
+        let mut items = items.into_iter();
+        let mut current = items.next().unwrap();
+        let mut before = 0.0;
+        for (mean, weight) in items {
+            let q = (before + (current.1 + weight) / 2.0) / total;
+            let max_weight = (4.0 * total * q * (1.0 - q) / self.compression).max(1.0);
+            if current.1 + weight <= max_weight {
+                let sum = current.1 + weight;
+                current.0 += (mean - current.0) * weight / sum;
+                current.1 = sum;
+            } else {
+                before += current.1;
+                merged.push(current);
+                current = (mean, weight);
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+
+    /// Returns the estimated `q`-quantile, `None` if no value was added.
+    pub fn quantile(&mut self, q: f64) -> Option<f64> {
+        self.merge_buffer();
+        if self.centroids.is_empty() {
+            return None;
+        }
+        let total: f64 = self.centroids.iter().map(|(_, weight)| weight).sum();
+        let target = q.clamp(0.0, 1.0) * total;
+
+        // Interpolates between the centers of the centroids, and between the extreme
+        // centroids and the extreme values.
+        let mut previous = (0.0, self.min);
+        let mut cumulative = 0.0;
+        for &(mean, weight) in self.centroids.iter() {
+            let center = cumulative + weight / 2.0;
+            if target < center {
+                let (previous_center, previous_mean) = previous;
+                let t = (target - previous_center) / (center - previous_center);
+                return Some(previous_mean + t * (mean - previous_mean));
+            }
+            previous = (center, mean);
+            cumulative += weight;
+        }
+        let (previous_center, previous_mean) = previous;
+        if total <= previous_center {
+            return Some(self.max);
+        }
+        let t = (target - previous_center) / (total - previous_center);
+        Some(previous_mean + t * (self.max - previous_mean))
+    }
+}
@@ -10,3 +10,27 @@ pub fn sanitize_df(df: &mut DataFrame, blacklist: &Vec<String>) {
         *series = Series::full_null(name, series.len(), series.dtype());
     }
 }
+
+/// Runs a tiny lazy query, with a filter and an aggregation, and checks its result. Checks
+/// that Polars can run queries before clients send any.
+pub fn query_self_test() -> PolarsResult<()> {
+    let df = df!(
+        "group" => &["a", "b", "a", "b"],
+        "value" => &[1i64, 2, 3, 4]
+    )?;
+    let res = df
+        .lazy()
+        .filter(col("value").gt(lit(1i64)))
+        .groupby([col("group")])
+        .agg([col("value").sum()])
+        .sort("group", Default::default())
+        .collect()?;
+    let sums: Vec<Option<i64>> = res.column("value")?.i64()?.into_iter().collect();
+    if sums == [Some(3), Some(6)] {
+        Ok(())
+    } else {
+        Err(PolarsError::ComputeError(
+            format!("Unexpected query result: {res}").into(),
+        ))
+    }
+}
@@ -0,0 +1,81 @@
+use crate::storage::{to_unix_secs, Artifact};
+use crate::torch_proto::Metric;
+use bastionlab_learning::nn::CheckPoint;
+use serde_json::json;
+use std::time::SystemTime;
+use tonic::Status;
+
+/// Packages every checkpoint of a model into a single tar archive.
+///
+/// The archive contains `metadata.json`, `metrics.json` with the final metric of
+/// each training run, and one TorchScript file per checkpoint under `checkpoints/`,
+/// numbered in the order they were taken.
+pub fn checkpoint_archive(
+    artifact: &Artifact<CheckPoint>,
+    metrics: &[Metric],
+) -> Result<Vec<u8>, Status> {
+    let chkpt = artifact.data.read().unwrap();
+    let metadata = json!({
+        "name": artifact.name,
+        "description": artifact.description,
+        "tags": artifact.tags,
+        "owner": artifact.owner,
+        "created_at": artifact.created_at.map(to_unix_secs),
+        "private": chkpt.private,
+        "nb_checkpoints": chkpt.data.len(),
+    });
+    let metrics: Vec<_> = metrics
+        .iter()
+        .map(|m| {
+            json!({
+                "epoch": m.epoch,
+                "batch": m.batch,
+                "value": m.value,
+                "uncertainty": m.uncertainty,
+                "nb_epochs": m.nb_epochs,
+                "nb_batches": m.nb_batches,
+            })
+        })
+        .collect();
+
+    let mtime = to_unix_secs(SystemTime::now());
+    let mut builder = tar::Builder::new(Vec::new());
+    append(
+        &mut builder,
+        "metadata.json",
+        &metadata.to_string().into_bytes(),
+        mtime,
+    )?;
+    append(
+        &mut builder,
+        "metrics.json",
+        &json!(metrics).to_string().into_bytes(),
+        mtime,
+    )?;
+    for (i, bytes) in chkpt.data.iter().enumerate() {
+        append(
+            &mut builder,
+            &format!("checkpoints/{:04}.pt", i),
+            bytes,
+            mtime,
+        )?;
+    }
+    builder
+        .into_inner()
+        .map_err(|e| Status::internal(format!("Could not build checkpoint archive: {}", e)))
+}
+
+fn append(
+    builder: &mut tar::Builder<Vec<u8>>,
+    path: &str,
+    data: &[u8],
+    mtime: u64,
+) -> Result<(), Status> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    builder
+        .append_data(&mut header, path, data)
+        .map_err(|e| Status::internal(format!("Could not build checkpoint archive: {}", e)))
+}
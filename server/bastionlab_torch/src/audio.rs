@@ -0,0 +1,121 @@
+use crate::torch_proto::{
+    audio_dataset_chunk::Features, AudioDatasetChunk, AudioSample, MelSpectrogramConfig,
+};
+use crate::utils::tcherror_to_status;
+use bastionlab_common::cancellation::Cancellation;
+use bastionlab_common::compression::check_upload_size;
+use bastionlab_learning::data::audio::{decode_audio, stack_waveforms, MelSpectrogram, Mfcc};
+use bastionlab_learning::data::transform::Transform;
+use bastionlab_learning::data::Dataset;
+use std::sync::{Arc, Mutex};
+use tch::Tensor;
+use tokio_stream::StreamExt;
+use tonic::{Status, Streaming};
+
+/// Receives an audio dataset, and returns its first message holding all the samples
+/// along with the total size of the recordings.
+pub async fn receive_recordings(
+    mut stream: Streaming<AudioDatasetChunk>,
+    max_size: Option<usize>,
+) -> Result<(AudioDatasetChunk, usize), Status> {
+    let mut first: Option<AudioDatasetChunk> = None;
+    let mut size = 0;
+    while let Some(chunk) = stream.next().await {
+        let mut chunk = chunk?;
+        size += chunk
+            .samples
+            .iter()
+            .map(|sample| sample.recording.len())
+            .sum::<usize>();
+        check_upload_size(size, max_size)?;
+        match &mut first {
+            Some(first) => first.samples.append(&mut chunk.samples),
+            None => first = Some(chunk),
+        }
+    }
+    let first = first.ok_or_else(|| Status::invalid_argument("Received no recording"))?;
+    Ok((first, size))
+}
+
+fn mel_spectrogram(
+    sample_rate: u32,
+    config: &MelSpectrogramConfig,
+) -> Result<MelSpectrogram, Status> {
+    if config.n_fft <= 0 || config.hop_length <= 0 || config.n_mels <= 0 {
+        return Err(Status::invalid_argument(
+            "Mel spectrogram sizes must be positive",
+        ));
+    }
+    Ok(MelSpectrogram {
+        sample_rate,
+        n_fft: config.n_fft,
+        hop_length: config.hop_length,
+        n_mels: config.n_mels,
+    })
+}
+
+fn transform(sample_rate: u32, features: &Features) -> Result<Box<dyn Transform>, Status> {
+    Ok(match features {
+        Features::MelSpectrogram(config) => Box::new(mel_spectrogram(sample_rate, config)?),
+        Features::Mfcc(config) => {
+            let mel_config = config
+                .mel_spectrogram
+                .as_ref()
+                .ok_or_else(|| Status::invalid_argument("Missing MFCC mel spectrogram"))?;
+            if config.n_mfcc <= 0 || config.n_mfcc > mel_config.n_mels {
+                return Err(Status::invalid_argument(
+                    "The number of MFCCs must be positive and at most the number of mel bands",
+                ));
+            }
+            Box::new(Mfcc {
+                mel_spectrogram: mel_spectrogram(sample_rate, mel_config)?,
+                n_mfcc: config.n_mfcc,
+            })
+        }
+    })
+}
+
+/// Decodes `samples` into a dataset whose single input holds the features of the
+/// recordings, padded with silence to the longest one, and whose labels are the labels
+/// of the samples.
+pub fn audio_dataset(
+    samples: &[AudioSample],
+    sample_rate: u32,
+    features: Option<&Features>,
+    privacy_limit: f64,
+    cancellation: &Cancellation,
+) -> Result<Dataset, Status> {
+    if samples.is_empty() {
+        return Err(Status::invalid_argument("Received no recording"));
+    }
+    let transform = features
+        .map(|features| transform(sample_rate, features))
+        .transpose()?;
+
+    let mut waveforms = Vec::with_capacity(samples.len());
+    for sample in samples {
+        cancellation.check()?;
+        let (waveform, rate) =
+            decode_audio(&sample.recording).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        if rate != sample_rate {
+            return Err(Status::invalid_argument(format!(
+                "Recording has a sample rate of {rate}Hz instead of {sample_rate}Hz"
+            )));
+        }
+        waveforms.push(waveform);
+    }
+    let labels: Vec<i64> = samples.iter().map(|sample| sample.label).collect();
+
+    let dataset = Dataset::new(
+        vec![Arc::new(Mutex::new(tcherror_to_status(stack_waveforms(
+            &waveforms,
+        ))?))],
+        Arc::new(Mutex::new(Tensor::of_slice(&labels))),
+        privacy_limit,
+    );
+    if let Some(transform) = transform {
+        cancellation.check()?;
+        tcherror_to_status(dataset.transform_input(0, transform.as_ref()))?;
+    }
+    Ok(dataset)
+}
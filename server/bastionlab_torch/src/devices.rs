@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tch::Device;
+use tonic::Status;
+
+#[derive(Debug, Default)]
+struct Occupancy {
+    runs: usize,
+    exclusive: bool,
+}
+
+/// Keeps track of the runs using each CUDA device, so that a run may reserve a device
+/// for itself and no other run is placed on it in the meantime.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceScheduler {
+    devices: Arc<Mutex<HashMap<usize, Occupancy>>>,
+}
+
+/// Use of a device by a run, released when dropped.
+#[derive(Debug)]
+pub struct DeviceReservation {
+    scheduler: DeviceScheduler,
+    index: Option<usize>,
+}
+
+impl DeviceScheduler {
+    /// Places a run on `device`, reserving it for the run if `exclusive` is true.
+    ///
+    /// Fails if the device is reserved by another run, or if an exclusive reservation is
+    /// asked for a device other runs are using.
+    pub fn reserve(&self, device: Device, exclusive: bool) -> Result<DeviceReservation, Status> {
+        let index = match device {
+            Device::Cuda(index) => index,
+            Device::Cpu if exclusive => {
+                return Err(Status::invalid_argument(
+                    "Only CUDA devices can be reserved exclusively",
+                ))
+            }
+            _ => {
+                return Ok(DeviceReservation {
+                    scheduler: self.clone(),
+                    index: None,
+                })
+            }
+        };
+        let mut devices = self.devices.lock().unwrap();
+        let occupancy = devices.entry(index).or_default();
+        if occupancy.exclusive {
+            return Err(Status::resource_exhausted(format!(
+                "Device cuda:{} is reserved by another run",
+                index
+            )));
+        }
+        if exclusive && occupancy.runs > 0 {
+            return Err(Status::resource_exhausted(format!(
+                "Device cuda:{} cannot be reserved while other runs use it",
+                index
+            )));
+        }
+        occupancy.runs += 1;
+        occupancy.exclusive = exclusive;
+        Ok(DeviceReservation {
+            scheduler: self.clone(),
+            index: Some(index),
+        })
+    }
+
+    /// Returns the CUDA devices reserved by a run, as `cuda:<index>`.
+    pub fn reserved(&self) -> Vec<String> {
+        let mut reserved: Vec<_> = self
+            .devices
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, occupancy)| occupancy.exclusive)
+            .map(|(index, _)| *index)
+            .collect();
+        reserved.sort_unstable();
+        reserved
+            .into_iter()
+            .map(|index| format!("cuda:{}", index))
+            .collect()
+    }
+}
+
+impl Drop for DeviceReservation {
+    fn drop(&mut self) {
+        let index = match self.index {
+            Some(index) => index,
+            None => return,
+        };
+        let mut devices = self.scheduler.devices.lock().unwrap();
+        if let Some(occupancy) = devices.get_mut(&index) {
+            occupancy.runs -= 1;
+            if occupancy.runs == 0 {
+                devices.remove(&index);
+            }
+        }
+    }
+}
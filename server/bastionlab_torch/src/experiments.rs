@@ -0,0 +1,110 @@
+use crate::resources::ResourceUsage;
+use crate::runs::{RunKind, RunRecord, RunStatus};
+use crate::storage::to_unix_secs;
+use crate::torch_proto::{self, Metric, RunParameters, RunQuery, RunResources, RunSummary};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// A named group of runs, so that their configs and outcomes can be compared.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Experiment {
+    pub name: String,
+    pub description: String,
+    /// Only the owner may add runs to the experiment and query them.
+    pub owner: String,
+    /// Unix timestamp, in seconds.
+    pub created_at: u64,
+}
+
+impl Experiment {
+    pub fn new(name: String, description: String, owner: &str) -> Self {
+        Experiment {
+            name,
+            description,
+            owner: owner.to_string(),
+            created_at: to_unix_secs(SystemTime::now()),
+        }
+    }
+
+    pub fn to_proto(&self, identifier: Uuid) -> torch_proto::Experiment {
+        torch_proto::Experiment {
+            identifier: identifier.to_string(),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            owner: self.owner.clone(),
+            created_at: self.created_at,
+        }
+    }
+}
+
+/// Whether `record` passes the filters of `query` that are set.
+pub fn matches(query: &RunQuery, record: &RunRecord) -> bool {
+    (query.model.is_empty() || query.model == record.model)
+        && (query.dataset.is_empty() || query.dataset == record.dataset)
+        && (query.metric.is_empty() || query.metric == record.metric)
+}
+
+pub fn run_summary(run: Uuid, record: RunRecord, final_metric: Option<Metric>) -> RunSummary {
+    let (status, error) = match record.status {
+        RunStatus::Running => ("running", String::new()),
+        RunStatus::Ok => ("ok", String::new()),
+        RunStatus::Error(e) => ("error", e),
+        RunStatus::Cancelled => ("cancelled", String::new()),
+    };
+    RunSummary {
+        identifier: run.to_string(),
+        kind: match record.kind {
+            RunKind::Train => "train",
+            RunKind::Test => "test",
+        }
+        .to_string(),
+        model: record.model,
+        dataset: record.dataset,
+        user_id: record.user_id,
+        metric: record.metric,
+        final_metric,
+        status: status.to_string(),
+        error,
+        started_at: record.started_at,
+        finished_at: record.finished_at.unwrap_or_default(),
+        parameters: record.config.map(|config| RunParameters {
+            batch_size: config.batch_size,
+            epochs: config.epochs,
+            device: config.device,
+            eps: config.eps,
+            max_grad_norm: config.max_grad_norm,
+            optimizer: config.optimizer,
+            learning_rate: config.learning_rate,
+        }),
+        resources: record.resources.map(run_resources),
+    }
+}
+
+pub fn run_resources(usage: ResourceUsage) -> RunResources {
+    RunResources {
+        cpu_time_ms: usage.cpu_time_ms,
+        bytes_read: usage.bytes_read,
+    }
+}
+
+/// Returns the successful run of `runs` with the highest final metric, or the lowest if
+/// `minimize` is set.
+pub fn best_run(
+    runs: impl IntoIterator<Item = (Uuid, RunRecord, Option<Metric>)>,
+    minimize: bool,
+) -> Option<(Uuid, RunRecord, Metric)> {
+    runs.into_iter()
+        .filter_map(|(run, record, metric)| match (&record.status, metric) {
+            (RunStatus::Ok, Some(metric)) if !metric.value.is_nan() => Some((run, record, metric)),
+            _ => None,
+        })
+        .max_by(|(_, _, a), (_, _, b)| {
+            let ordering = a.value.total_cmp(&b.value);
+            if minimize {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        })
+}
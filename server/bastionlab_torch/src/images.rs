@@ -0,0 +1,173 @@
+use crate::torch_proto::{ImageDatasetChunk, ImageSample};
+use bastionlab_common::cancellation::Cancellation;
+use bastionlab_common::compression::check_upload_size;
+use bastionlab_learning::data::Dataset;
+use std::sync::{Arc, Mutex};
+use tch::{vision::image, Kind, TchError, Tensor};
+use tokio_stream::StreamExt;
+use tonic::{Status, Streaming};
+
+/// Receives an image dataset, and returns its first message holding all the samples
+/// along with the total size of the images.
+pub async fn receive_images(
+    mut stream: Streaming<ImageDatasetChunk>,
+    max_size: Option<usize>,
+) -> Result<(ImageDatasetChunk, usize), Status> {
+    let mut first: Option<ImageDatasetChunk> = None;
+    let mut size = 0;
+    while let Some(chunk) = stream.next().await {
+        let mut chunk = chunk?;
+        size += chunk
+            .samples
+            .iter()
+            .map(|sample| sample.image.len())
+            .sum::<usize>();
+        check_upload_size(size, max_size)?;
+        match &mut first {
+            Some(first) => first.samples.append(&mut chunk.samples),
+            None => first = Some(chunk),
+        }
+    }
+    let first = first.ok_or_else(|| Status::invalid_argument("Received no image"))?;
+    Ok((first, size))
+}
+
+/// Largest number of pixels of the uploaded images and of the size they are resized to, so
+/// that decoding a small file cannot exhaust memory.
+pub const MAX_IMAGE_PIXELS: u64 = 1 << 26;
+
+/// Returns the width and height of a PNG or JPEG file, read from its header.
+fn image_dimensions(data: &[u8]) -> Option<(u64, u64)> {
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    let be = |bytes: &[u8]| bytes.iter().fold(0u64, |n, &b| (n << 8) | b as u64);
+    if data.starts_with(PNG_SIGNATURE) {
+        // The IHDR chunk comes first, with the width and height after its length and type.
+        let ihdr = data.get(8..24)?;
+        return (ihdr[4..8] == *b"IHDR").then(|| (be(&ihdr[8..12]), be(&ihdr[12..16])));
+    }
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    // JPEG segments start with a marker and their length. The start of frame segments,
+    // C0 to CF except C4, C8 and CC, hold the precision, height and width.
+    let mut pos = 2;
+    loop {
+        let segment = data.get(pos..pos + 4)?;
+        if segment[0] != 0xff {
+            return None;
+        }
+        let marker = segment[1];
+        if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+            let frame = data.get(pos + 5..pos + 9)?;
+            return Some((be(&frame[2..4]), be(&frame[0..2])));
+        }
+        pos += 2 + be(&segment[2..4]) as usize;
+    }
+}
+
+fn check_pixels(width: u64, height: u64) -> Result<(), Status> {
+    if width.saturating_mul(height) > MAX_IMAGE_PIXELS {
+        return Err(Status::invalid_argument(format!(
+            "Images of {width}x{height} pixels exceed the limit of {MAX_IMAGE_PIXELS} pixels"
+        )));
+    }
+    Ok(())
+}
+
+fn decode_error(err: TchError) -> Status {
+    Status::invalid_argument(format!("Could not decode image: {}", err))
+}
+
+/// Decodes a JPEG or PNG file into a float tensor of shape `[3, height, width]`, with
+/// values between 0 and 1.
+///
+/// The size of the image is read from its header and checked against [`MAX_IMAGE_PIXELS`]
+/// before it is decoded.
+pub fn decode_image(data: &[u8], width: i64, height: i64) -> Result<Tensor, Status> {
+    let (file_width, file_height) = image_dimensions(data).ok_or_else(|| {
+        Status::invalid_argument("Could not decode image: not a PNG or JPEG file")
+    })?;
+    check_pixels(file_width, file_height)?;
+    let image = image::load_from_memory(data).map_err(decode_error)?;
+    let image = match image.size()[0] {
+        1 => image.repeat(&[3, 1, 1]),
+        3 => image,
+        // Drops the alpha channel.
+        4 => image.narrow(0, 0, 3),
+        channels => {
+            return Err(Status::invalid_argument(format!(
+                "Images with {channels} channels are not supported"
+            )))
+        }
+    };
+    let image = image::resize(&image, width, height).map_err(decode_error)?;
+    Ok(image.to_kind(Kind::Float) / 255.)
+}
+
+/// Decodes `samples` into a dataset whose single input is the stack of the images and
+/// whose labels are the labels of the samples.
+pub fn image_dataset(
+    samples: &[ImageSample],
+    width: i64,
+    height: i64,
+    privacy_limit: f64,
+    cancellation: &Cancellation,
+) -> Result<Dataset, Status> {
+    if width <= 0 || height <= 0 {
+        return Err(Status::invalid_argument(format!(
+            "Invalid image size: {width}x{height}"
+        )));
+    }
+    check_pixels(width as u64, height as u64)?;
+    if samples.is_empty() {
+        return Err(Status::invalid_argument("Received no image"));
+    }
+
+    let mut images = Vec::with_capacity(samples.len());
+    for sample in samples {
+        cancellation.check()?;
+        images.push(decode_image(&sample.image, width, height)?);
+    }
+    let labels: Vec<i64> = samples.iter().map(|sample| sample.label).collect();
+
+    Ok(Dataset::new(
+        vec![Arc::new(Mutex::new(Tensor::stack(&images, 0)))],
+        Arc::new(Mutex::new(Tensor::of_slice(&labels))),
+        privacy_limit,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_png_dimensions() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&[0, 0, 1, 0, 0, 0, 0, 200, 8, 2, 0, 0, 0]);
+        assert_eq!(image_dimensions(&png), Some((256, 200)));
+    }
+
+    #[test]
+    fn reads_jpeg_dimensions_after_other_segments() {
+        let jpeg = [
+            0xff, 0xd8, // Start of image
+            0xff, 0xe0, 0, 4, 0, 0, // APP0 segment with 2 bytes of data
+            0xff, 0xc2, 0, 11, 8, 0x01, 0x00, 0x02, 0x00, 3, // Progressive frame
+        ];
+        assert_eq!(image_dimensions(&jpeg), Some((512, 256)));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert_eq!(image_dimensions(b"GIF89a"), None);
+        assert_eq!(image_dimensions(&[0xff, 0xd8, 0xff]), None);
+    }
+
+    #[test]
+    fn limits_pixels() {
+        assert!(check_pixels(8192, 8192).is_ok());
+        assert!(check_pixels(8192, 8193).is_err());
+        assert!(check_pixels(u64::MAX, 2).is_err());
+    }
+}
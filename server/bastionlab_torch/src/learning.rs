@@ -1,27 +1,42 @@
+use crate::resources::{ResourceMeter, ResourceUsage};
 use crate::telemetry::{self, TelemetryEventProps};
-use crate::torch_proto::{train_config, Metric, TestConfig, TrainConfig};
+use crate::torch_proto::{
+    lr_scheduler, optimizer_parameter, split_train_response, train_config, CutLayerBatch, Metric,
+    SplitTrainResponse, TestConfig, TrainConfig, ValidationMetric,
+};
 use crate::utils::tcherror_to_status;
 use crate::CheckPoint;
-use bastionlab_common::session_proto::ClientInfo;
-use bastionlab_learning::data::privacy_guard::PrivacyBudget;
+use bastionlab_common::session_proto::{ClientInfo, ErrorCode};
+use bastionlab_learning::audit::{Canaries, LeakageScore};
+use bastionlab_learning::data::privacy_guard::{PrivacyBudget, PrivacyGuard};
 use bastionlab_learning::data::Dataset;
 use bastionlab_learning::nn::{Forward, LossType, Module, Parameters};
-use bastionlab_learning::optim::{Adam, Optimizer, OptimizerStateType, SGD};
-use bastionlab_learning::procedures::{self, Tester, Trainer};
-use bastionlab_learning::serialization::BinaryModule;
+use bastionlab_learning::optim::{
+    find_optimizer, LrScheduler, Optimizer, OptimizerInfo, OptimizerParameters, OptimizerStateType,
+    ParameterValue, OPTIMIZERS,
+};
+use bastionlab_learning::procedures::{self, EarlyStopping, Tester, Trainer, Watermark};
+use bastionlab_learning::serialization::{self, BinaryModule, ModuleFormat, SizedObjectsBytes};
 
-use log::info;
+use log::{info, warn};
+use rayon::ThreadPool;
+use std::collections::HashMap;
+use std::future::Future;
 use std::io::Cursor;
 use std::sync::{Arc, RwLock};
-use std::time::{Instant, SystemTime, UNIX_EPOCH};
-use tch::{Device, TchError, Tensor};
-use tonic::Status;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tch::{Device, Kind, Reduction, TchError, Tensor};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+use tonic::{Code, Status};
 
 #[derive(Debug)]
 pub enum Run {
     Ok(Metric),
     Error(Status),
     Pending,
+    /// Stopped at the request of a client, after checkpointing.
+    Cancelled,
 }
 
 /// Returns a metric by name from config and computes per step privacy budget for metrics
@@ -43,6 +58,45 @@ fn build_shared_context(
     Ok((metric, metric_budget))
 }
 
+/// Returns the early stopping chosen in `config`, if any.
+fn build_early_stopping(config: &TrainConfig) -> Result<Option<EarlyStopping>, TchError> {
+    if config.early_stopping_patience <= 0 {
+        return Ok(None);
+    }
+    let early_stopping = EarlyStopping::new(config.early_stopping_patience as usize);
+    let name = &config.early_stopping_metric;
+    if name.is_empty() || *name == config.metric {
+        return Ok(Some(early_stopping));
+    }
+    let is_loss = procedures::METRICS
+        .iter()
+        .any(|info| info.name == name.as_str() && info.is_loss);
+    let metric = procedures::Metric::try_from_name(name)?;
+    Ok(Some(early_stopping.with_metric(
+        metric,
+        per_epoch_budget(config),
+        is_loss,
+    )))
+}
+
+/// Returns the metric the model is validated with at the end of every epoch, with the
+/// budget of its value.
+fn build_validation_metric(
+    config: &TrainConfig,
+) -> Result<(procedures::Metric, PrivacyBudget), TchError> {
+    let metric = procedures::Metric::try_from_name(&config.metric)?;
+    Ok((metric, per_epoch_budget(config)))
+}
+
+/// Returns the budget of a metric disclosed once per epoch rather than once per batch.
+fn per_epoch_budget(config: &TrainConfig) -> PrivacyBudget {
+    if config.metric_eps < 0.0 {
+        PrivacyBudget::NotPrivate
+    } else {
+        PrivacyBudget::Private(config.metric_eps / config.epochs.max(1) as f32)
+    }
+}
+
 /// Returns a forward pass, a metric and a metric budget from config.
 fn build_test_context<'a>(
     module: &'a mut Module,
@@ -102,64 +156,7 @@ fn build_train_context<'a>(
         )
     };
 
-    let optimizer = match config
-        .clone()
-        .optimizer
-        .ok_or_else(|| TchError::FileFormat(String::from("Invalid optimizer")))?
-    {
-        train_config::Optimizer::Sgd(train_config::Sgd {
-            learning_rate,
-            weight_decay,
-            momentum,
-            dampening,
-            nesterov,
-        }) => {
-            if config.resume && optimizer_state.is_some() {
-                Box::new(SGD::load_from_checkpoint(
-                    optimizer_state,
-                    weights,
-                    learning_rate as f64,
-                    parameters,
-                )?) as Box<dyn Optimizer + 'a>
-            } else {
-                Box::new(
-                    SGD::new(parameters, learning_rate as f64)
-                        .weight_decay(weight_decay as f64)
-                        .momentum(momentum as f64)
-                        .dampening(dampening as f64)
-                        .nesterov(nesterov),
-                ) as Box<dyn Optimizer + 'a>
-            }
-        }
-        train_config::Optimizer::Adam(train_config::Adam {
-            learning_rate,
-            beta_1,
-            beta_2,
-            epsilon,
-            weight_decay,
-            amsgrad,
-        }) => {
-            if config.resume {
-                Box::new(
-                    Adam::new(parameters, learning_rate as f64)
-                        .beta_1(beta_1 as f64)
-                        .beta_2(beta_2 as f64)
-                        .epsilon(epsilon as f64)
-                        .weight_decay(weight_decay as f64)
-                        .amsgrad(amsgrad),
-                ) as Box<dyn Optimizer + 'a>
-            } else {
-                Box::new(
-                    Adam::new(parameters, learning_rate as f64)
-                        .beta_1(beta_1 as f64)
-                        .beta_2(beta_2 as f64)
-                        .epsilon(epsilon as f64)
-                        .weight_decay(weight_decay as f64)
-                        .amsgrad(amsgrad),
-                ) as Box<dyn Optimizer + 'a>
-            }
-        }
-    };
+    let optimizer = build_optimizer(parameters, &config, optimizer_state, weights)?;
 
     let (metric, metric_budget) = build_shared_context(
         &config.metric,
@@ -172,8 +169,185 @@ fn build_train_context<'a>(
     Ok((forward, optimizer, metric, metric_budget))
 }
 
+/// Returns the optimizer chosen in `config` with the values of its parameters, checked
+/// against its schema.
+pub fn optimizer_choice(
+    config: &TrainConfig,
+) -> Result<(&'static OptimizerInfo, OptimizerParameters), TchError> {
+    let (name, values) = if !config.optimizer_name.is_empty() {
+        let mut values = HashMap::new();
+        for (name, param) in config.optimizer_parameters.iter() {
+            let value = match param.value {
+                Some(optimizer_parameter::Value::FloatValue(f)) => ParameterValue::Float(f),
+                Some(optimizer_parameter::Value::BoolValue(b)) => ParameterValue::Bool(b),
+                None => continue,
+            };
+            values.insert(name.clone(), value);
+        }
+        (config.optimizer_name.as_str(), values)
+    } else {
+        match config
+            .optimizer
+            .clone()
+            .ok_or_else(|| TchError::Kind(String::from("No optimizer given")))?
+        {
+            train_config::Optimizer::Sgd(train_config::Sgd {
+                learning_rate,
+                weight_decay,
+                momentum,
+                dampening,
+                nesterov,
+            }) => (
+                "SGD",
+                HashMap::from([
+                    float_value("learning_rate", learning_rate),
+                    float_value("weight_decay", weight_decay),
+                    float_value("momentum", momentum),
+                    float_value("dampening", dampening),
+                    (String::from("nesterov"), ParameterValue::Bool(nesterov)),
+                ]),
+            ),
+            train_config::Optimizer::Adam(train_config::Adam {
+                learning_rate,
+                beta_1,
+                beta_2,
+                epsilon,
+                weight_decay,
+                amsgrad,
+            }) => (
+                "Adam",
+                HashMap::from([
+                    float_value("learning_rate", learning_rate),
+                    float_value("beta_1", beta_1),
+                    float_value("beta_2", beta_2),
+                    float_value("epsilon", epsilon),
+                    float_value("weight_decay", weight_decay),
+                    (String::from("amsgrad"), ParameterValue::Bool(amsgrad)),
+                ]),
+            ),
+        }
+    };
+    let info = find_optimizer(name).ok_or_else(|| {
+        TchError::Kind(format!(
+            "Unknown optimizer {}, supported ones are: {}",
+            name,
+            OPTIMIZERS
+                .iter()
+                .map(|info| info.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    })?;
+    let values = info.resolve(&values)?;
+    Ok((info, values))
+}
+
+fn float_value(name: &str, value: f32) -> (String, ParameterValue) {
+    (name.to_string(), ParameterValue::Float(value as f64))
+}
+
+/// Returns the learning rate schedule chosen in `config`, if any, for a training of
+/// `nb_steps` optimizer steps per epoch.
+pub fn lr_scheduler_choice(
+    config: &TrainConfig,
+    nb_steps: usize,
+) -> Result<Option<LrScheduler>, TchError> {
+    let scheduler = match config
+        .lr_scheduler
+        .as_ref()
+        .and_then(|s| s.scheduler.as_ref())
+    {
+        Some(scheduler) => scheduler,
+        None => return Ok(None),
+    };
+    let count = |name: &str, value: i32| {
+        usize::try_from(value).map_err(|_| {
+            TchError::Kind(format!(
+                "Learning rate scheduler parameter {} must not be negative",
+                name
+            ))
+        })
+    };
+    let scheduler = match scheduler {
+        lr_scheduler::Scheduler::Step(lr_scheduler::Step { step_size, gamma }) => {
+            LrScheduler::Step {
+                step_size: count("step_size", *step_size)?,
+                gamma: *gamma as f64,
+            }
+        }
+        lr_scheduler::Scheduler::MultiStep(lr_scheduler::MultiStep { milestones, gamma }) => {
+            LrScheduler::MultiStep {
+                milestones: milestones
+                    .iter()
+                    .map(|&m| count("milestones", m))
+                    .collect::<Result<_, _>>()?,
+                gamma: *gamma as f64,
+            }
+        }
+        lr_scheduler::Scheduler::Cosine(lr_scheduler::Cosine { t_max, eta_min }) => {
+            LrScheduler::Cosine {
+                t_max: count("t_max", if *t_max == 0 { config.epochs } else { *t_max })?,
+                eta_min: *eta_min as f64,
+            }
+        }
+        lr_scheduler::Scheduler::OneCycle(lr_scheduler::OneCycle {
+            max_lr,
+            pct_start,
+            div_factor,
+            final_div_factor,
+        }) => LrScheduler::OneCycle {
+            max_lr: *max_lr as f64,
+            total_steps: count("epochs", config.epochs)? * nb_steps,
+            pct_start: *pct_start as f64,
+            div_factor: *div_factor as f64,
+            final_div_factor: *final_div_factor as f64,
+        },
+    };
+    scheduler.validate()?;
+    Ok(Some(scheduler))
+}
+
+/// Returns the optimizer of `parameters` described by `config`, restored from the given
+/// checkpointed state when resuming.
+fn build_optimizer<'a>(
+    parameters: Parameters<'a>,
+    config: &TrainConfig,
+    optimizer_state: &Option<OptimizerStateType>,
+    weights: &[u8],
+) -> Result<Box<dyn Optimizer + 'a>, TchError> {
+    let (info, values) = optimizer_choice(config)?;
+    let checkpoint = if config.resume && optimizer_state.is_some() {
+        Some((optimizer_state, weights))
+    } else {
+        None
+    };
+    info.build(parameters, &values, checkpoint)
+}
+
+/// Runs `task` on `pool` within the async runtime of the caller, whose handle is captured
+/// here: the callbacks of trainings and tests persist artifacts through storage backends
+/// which block on futures.
+fn spawn_on(pool: &ThreadPool, task: impl FnOnce() + Send + 'static) {
+    let handle = Handle::current();
+    pool.spawn(move || {
+        let _runtime = handle.enter();
+        task()
+    });
+}
+
 /// Trains `module` on `dataset` outputing metrics to `run` with given `config` on `device`.
+///
+/// `on_metric` is called with every metric reported during training, and `on_finish` with
+/// the resources used once training is over and the checkpoint lock has been released.
+/// When `config` asks for canaries, half of them are inserted into the training data and
+/// `on_audit` is called with the leakage measured on the last checkpoint of a successful run.
+/// When `config` sets a validation ratio, that fraction of `dataset` is held out from the
+/// training and the model is evaluated on it at the end of every epoch.
+/// When `interrupt` returns an error, the model is checkpointed after the current step and
+/// training stops with that error, or as [`Run::Cancelled`] if its code is `Cancelled`.
+/// Training runs on `pool`, off the async runtime serving requests.
 pub fn module_train(
+    pool: &ThreadPool,
     binary: Arc<RwLock<BinaryModule>>,
     dataset: Arc<RwLock<Dataset>>,
     run: Arc<RwLock<Run>>,
@@ -183,32 +357,101 @@ pub fn module_train(
     dataset_hash: String,
     client_info: Option<ClientInfo>,
     chkpt: Arc<RwLock<CheckPoint>>,
+    watermark: Option<(Arc<RwLock<Dataset>>, usize)>,
+    interrupt: impl Fn() -> Option<Status> + Send + 'static,
+    on_metric: impl Fn(&Metric) + Send + 'static,
+    on_audit: impl FnOnce(LeakageScore) + Send + 'static,
+    on_finish: impl FnOnce(ResourceUsage) + Send + 'static,
 ) {
-    tokio::spawn(async move {
+    spawn_on(pool, move || {
         let start_time = Instant::now();
+        let mut meter = ResourceMeter::start();
         let epochs = config.epochs;
         let batch_size = config.batch_size;
         let per_epoch_checkpoint = config.per_n_epochs_checkpoint;
         let per_n_step_checkpoint = config.per_n_steps_checkpoint;
+        let max_checkpoints_kept = config.max_checkpoints_kept;
+        let stratified_batches = config.stratified_batches;
+        let accumulation_steps = config.accumulation_steps.max(1) as usize;
         let binary = binary.read().unwrap();
-        let dataset = dataset.read().unwrap();
+        let source = dataset.read().unwrap();
+        let holdout = if config.validation_ratio > 0.0 {
+            let holdout = source.holdout(config.validation_ratio).and_then(|holdout| {
+                let (metric, budget) = build_validation_metric(&config)?;
+                Ok((holdout, metric, budget))
+            });
+            match tcherror_to_status(holdout) {
+                Ok(holdout) => Some(holdout),
+                Err(e) => {
+                    *run.write().unwrap() = Run::Error(e);
+                    on_finish(meter.finish());
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+        let (holdout, validation_metric) = match holdout {
+            Some((holdout, metric, budget)) => (Some(holdout), Some((metric, budget))),
+            None => (None, None),
+        };
+        let train_set = holdout.as_ref().map_or(&*source, |holdout| &holdout.train);
+        let (canaries, injected) = match tcherror_to_status(canaries_of(train_set, config.canaries))
+        {
+            Ok(canaries) => canaries.unzip(),
+            Err(e) => {
+                *run.write().unwrap() = Run::Error(e);
+                on_finish(meter.finish());
+                return;
+            }
+        };
+        let dataset = injected.as_ref().unwrap_or(train_set);
+        let nb_batches = dataset.len() / batch_size as usize;
+        let nb_steps = (nb_batches + accumulation_steps - 1) / accumulation_steps;
+        let lr_scheduler = match tcherror_to_status(lr_scheduler_choice(&config, nb_steps)) {
+            Ok(scheduler) => scheduler,
+            Err(e) => {
+                *run.write().unwrap() = Run::Error(e);
+                on_finish(meter.finish());
+                return;
+            }
+        };
+        let early_stopping = match tcherror_to_status(build_early_stopping(&config)) {
+            Ok(early_stopping) => early_stopping,
+            Err(e) => {
+                *run.write().unwrap() = Run::Error(e);
+                on_finish(meter.finish());
+                return;
+            }
+        };
+        let watermark = watermark
+            .as_ref()
+            .map(|(trigger_set, epochs)| (trigger_set.read().unwrap(), *epochs));
 
         let mut chkpt_guard = chkpt.write().unwrap();
 
         let (optimizer_state, weights) = chkpt_guard.get_chkpt();
-        let mut module: Module = (&*binary).try_into().unwrap();
+        let mut module = match tcherror_to_status(Module::try_from(&*binary)) {
+            Ok(module) => module,
+            Err(e) => {
+                *run.write().unwrap() = Run::Error(e);
+                drop(chkpt_guard);
+                on_finish(meter.finish());
+                return;
+            }
+        };
         module.set_device(device);
         match tcherror_to_status(build_train_context(
             &mut module,
-            &dataset,
+            dataset,
             config,
             &optimizer_state,
             weights,
         )) {
             Ok((forward, optimizer, metric, metric_budget)) => {
-                let trainer = Trainer::new(
+                let mut trainer = Trainer::new(
                     forward,
-                    &dataset,
+                    dataset,
                     optimizer,
                     metric,
                     metric_budget,
@@ -219,6 +462,30 @@ pub fn module_train(
                     per_epoch_checkpoint,
                     per_n_step_checkpoint,
                 );
+                if let Some((trigger_set, epochs)) = &watermark {
+                    trainer = trainer.with_watermark(Watermark {
+                        trigger_set,
+                        epochs: *epochs,
+                    });
+                }
+                if stratified_batches {
+                    trainer = trainer.with_stratified_batches();
+                }
+                if let Some(scheduler) = lr_scheduler {
+                    trainer = trainer.with_lr_scheduler(scheduler);
+                }
+                if let Some(early_stopping) = early_stopping {
+                    trainer = trainer.with_early_stopping(early_stopping);
+                }
+                if accumulation_steps > 1 {
+                    trainer = trainer.with_accumulation_steps(accumulation_steps);
+                }
+                if max_checkpoints_kept > 0 {
+                    trainer = trainer.with_max_checkpoints(max_checkpoints_kept as usize);
+                }
+                if let (Some(holdout), Some((metric, budget))) = (&holdout, validation_metric) {
+                    trainer = trainer.with_validation(&holdout.validation, metric, budget);
+                }
                 let nb_epochs = trainer.nb_epochs() as i32;
                 let nb_batches = trainer.nb_batches() as i32;
 
@@ -234,21 +501,53 @@ pub fn module_train(
                     },
                     client_info.clone(),
                 );
-                for res in trainer {
-                    match tcherror_to_status(res.map(|(epoch, batch, value, std)| Metric {
-                        epoch,
-                        batch,
-                        value,
-                        nb_epochs,
-                        nb_batches,
-                        uncertainty: 2.0 * std,
+                while let Some(res) = trainer.next() {
+                    // Expenditures of the parts are charged to the dataset as they happen.
+                    if let Some(holdout) = &holdout {
+                        holdout.charge(&source);
+                    }
+                    match tcherror_to_status(res.map(|(epoch, batch, value, std)| {
+                        Metric {
+                            epoch,
+                            batch,
+                            value,
+                            nb_epochs,
+                            nb_batches,
+                            uncertainty: 2.0 * std,
+                            validation: trainer
+                                .last_validation()
+                                .filter(|_| batch + 1 == nb_batches)
+                                .map(|(value, std)| ValidationMetric {
+                                    value,
+                                    uncertainty: 2.0 * std,
+                                }),
+                        }
                     })) {
-                        Ok(m) => *run.write().unwrap() = Run::Ok(m),
+                        Ok(m) => {
+                            if let Err(e) = check_finite(&trainer, &m) {
+                                *run.write().unwrap() = Run::Error(e);
+                                break;
+                            }
+                            on_metric(&m);
+                            *run.write().unwrap() = Run::Ok(m);
+                        }
                         Err(e) => {
                             *run.write().unwrap() = Run::Error(e);
                             break;
                         }
                     }
+                    if let Some(status) = interrupt() {
+                        *run.write().unwrap() = match tcherror_to_status(trainer.checkpoint()) {
+                            Ok(()) if status.code() == Code::Cancelled => Run::Cancelled,
+                            Ok(()) => Run::Error(status),
+                            Err(e) => Run::Error(e),
+                        };
+                        break;
+                    }
+                }
+                meter.add_read(trainer.bytes_read());
+                if let Some(holdout) = &holdout {
+                    holdout.charge(&source);
                 }
                 telemetry::add_event(
                     TelemetryEventProps::TrainerLog {
@@ -272,6 +571,10 @@ pub fn module_train(
                         start_time.elapsed().as_millis(),
                         e
                     ),
+                    Run::Cancelled => info!(
+                        "Model training cancelled in {}ms",
+                        start_time.elapsed().as_millis()
+                    ),
                     _ => info!(
                         "Model training failed in {}ms",
                         start_time.elapsed().as_millis()
@@ -280,11 +583,258 @@ pub fn module_train(
             }
             Err(e) => *run.write().unwrap() = Run::Error(e),
         };
+        if let Some(canaries) = canaries {
+            if matches!(*run.read().unwrap(), Run::Ok(_)) {
+                match canary_losses(&binary, &chkpt_guard, &canaries.to_dataset()) {
+                    Ok(losses) => on_audit(canaries.score(&losses)),
+                    Err(e) => warn!("Could not audit the leakage of the training: {}", e),
+                }
+            }
+        }
+        drop(chkpt_guard);
+        on_finish(meter.finish());
+    });
+}
+
+/// How long split training waits for the client to take a batch or send its gradients.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(300);
+/// How often split training checks for interruptions while it waits for the client.
+const INTERRUPT_POLL: Duration = Duration::from_secs(1);
+
+/// Runs `future`, which exchanges messages with the client of a split training, until it
+/// completes, `interrupt` returns an error or [`CLIENT_TIMEOUT`] elapses, so that a stalled
+/// client does not hold the thread, the dataset, the checkpoint and the device forever.
+fn wait_for_client<F: Future>(
+    future: F,
+    interrupt: &impl Fn() -> Option<Status>,
+) -> Result<F::Output, Status> {
+    let handle = Handle::current();
+    let start = Instant::now();
+    tokio::pin!(future);
+    loop {
+        if let Ok(output) = handle.block_on(tokio::time::timeout(INTERRUPT_POLL, &mut future)) {
+            return Ok(output);
+        }
+        if let Some(status) = interrupt() {
+            return Err(status);
+        }
+        if start.elapsed() >= CLIENT_TIMEOUT {
+            return Err(Status::deadline_exceeded(format!(
+                "The client did not answer for {} seconds",
+                CLIENT_TIMEOUT.as_secs()
+            )));
+        }
+    }
+}
+
+/// Trains the backbone `binary` on `dataset` with a head held by the client, with given
+/// `config` on `device`.
+///
+/// The activations of the backbone and the labels of every batch are sent to `batches`,
+/// then the backbone is updated with the gradient of the loss with respect to these
+/// activations, received from `gradients`. When `interrupt` returns an error, the backbone
+/// is checkpointed and training stops with that error, as it does when the client does not
+/// answer within [`CLIENT_TIMEOUT`]. Once training is over, the backbone is checkpointed and
+/// `on_finish` is called with the outcome.
+/// Training runs on `pool`, off the async runtime serving requests.
+pub fn module_split_train(
+    pool: &ThreadPool,
+    binary: Arc<RwLock<BinaryModule>>,
+    dataset: Arc<RwLock<Dataset>>,
+    chkpt: Arc<RwLock<CheckPoint>>,
+    config: TrainConfig,
+    device: Device,
+    batches: mpsc::Sender<Result<SplitTrainResponse, Status>>,
+    mut gradients: mpsc::Receiver<Result<Vec<u8>, Status>>,
+    interrupt: impl Fn() -> Option<Status> + Send + 'static,
+    on_finish: impl FnOnce(Result<(), Status>) + Send + 'static,
+) {
+    spawn_on(pool, move || {
+        let start_time = Instant::now();
+        let binary = binary.read().unwrap();
+        let dataset = dataset.read().unwrap();
+        let mut chkpt = chkpt.write().unwrap();
+
+        let res = (|| -> Result<(), Status> {
+            let (optimizer_state, weights) = chkpt.get_chkpt();
+            let mut module = tcherror_to_status(Module::try_from(&*binary))?;
+            module.set_device(device);
+            let (forward, parameters) = module.parameters();
+            let mut optimizer = tcherror_to_status(build_optimizer(
+                parameters,
+                &config,
+                optimizer_state,
+                weights,
+            ))?;
+            let nb_batches = dataset.len() / config.batch_size as usize;
+            let accumulation_steps = config.accumulation_steps.max(1) as usize;
+            let nb_steps = (nb_batches + accumulation_steps - 1) / accumulation_steps;
+            let lr_scheduler = tcherror_to_status(lr_scheduler_choice(&config, nb_steps))?;
+            let base_lr = optimizer.learning_rate();
+            let mut steps = 0;
+            let mut accumulated = 0;
+
+            let mut outcome = Ok(());
+            'epochs: for epoch in 0..config.epochs {
+                let batches_iter = if config.stratified_batches {
+                    tcherror_to_status(dataset.iter_stratified(config.batch_size as usize))?
+                } else {
+                    dataset.iter_shuffle(config.batch_size as usize)
+                };
+                for (batch, (inputs, labels)) in batches_iter.enumerate() {
+                    let activations = tcherror_to_status(split_forward(&forward, inputs, device))?;
+                    let mut tensors = Vec::new();
+                    tcherror_to_status(Tensor::save_multi_to_stream(
+                        &[
+                            ("activations", &activations.detach()),
+                            ("labels", &labels.get_non_private()),
+                        ],
+                        &mut tensors,
+                    ))?;
+                    let message = split_train_response::Message::Batch(CutLayerBatch {
+                        epoch,
+                        batch: batch as i32,
+                        tensors,
+                    });
+                    let response = SplitTrainResponse {
+                        message: Some(message),
+                    };
+                    let sent = match wait_for_client(batches.send(Ok(response)), &interrupt) {
+                        Ok(sent) => sent,
+                        Err(status) => {
+                            outcome = Err(status);
+                            break 'epochs;
+                        }
+                    };
+                    sent.map_err(|_| Status::cancelled("The client closed the stream"))?;
+
+                    let received = match wait_for_client(gradients.recv(), &interrupt) {
+                        Ok(received) => received,
+                        Err(status) => {
+                            outcome = Err(status);
+                            break 'epochs;
+                        }
+                    };
+                    let received = received.unwrap_or_else(|| {
+                        Err(Status::cancelled("The client closed the stream"))
+                    })?;
+                    if accumulated == 0 {
+                        if let Some(scheduler) = &lr_scheduler {
+                            let t = if scheduler.per_step() {
+                                steps
+                            } else {
+                                epoch as usize
+                            };
+                            optimizer.set_learning_rate(scheduler.learning_rate(base_lr, t));
+                        }
+                        tcherror_to_status(optimizer.zero_grad())?;
+                    }
+                    tcherror_to_status(split_backward(&activations, received, device))?;
+                    accumulated += 1;
+                    if accumulated == accumulation_steps || batch + 1 == nb_batches {
+                        steps += 1;
+                        accumulated = 0;
+                        tcherror_to_status(optimizer.step())?;
+                        if !tcherror_to_status(optimizer.parameters_finite())? {
+                            return Err(diverged(epoch, batch as i32));
+                        }
+                    } else {
+                        tcherror_to_status(optimizer.accumulate_grad())?;
+                    }
+
+                    if let Some(status) = interrupt() {
+                        outcome = Err(status);
+                        break 'epochs;
+                    }
+                }
+            }
+
+            let params = tcherror_to_status(optimizer.into_bytes())?;
+            let optimizer_state = tcherror_to_status(optimizer.get_state())?;
+            tcherror_to_status(chkpt.log_chkpt(&params, optimizer_state))?;
+            if config.max_checkpoints_kept > 0 {
+                chkpt.prune(config.max_checkpoints_kept as usize);
+            }
+            outcome
+        })();
+
+        match &res {
+            Ok(()) => info!(
+                "Backbone trained successfully in {}ms",
+                start_time.elapsed().as_millis()
+            ),
+            Err(e) => info!(
+                "Backbone training failed in {}ms: {}",
+                start_time.elapsed().as_millis(),
+                e
+            ),
+        }
+        drop(chkpt);
+        on_finish(res);
     });
 }
 
+/// Fails if the reported loss or the weights of a training stopped being finite, as
+/// further steps would only waste the device.
+///
+/// Both are released to the user anyway, so checking them does not leak anything more
+/// about the dataset.
+fn check_finite(trainer: &Trainer, metric: &Metric) -> Result<(), Status> {
+    if metric.value.is_finite() && tcherror_to_status(trainer.weights_finite())? {
+        Ok(())
+    } else {
+        Err(diverged(metric.epoch, metric.batch))
+    }
+}
+
+fn diverged(epoch: i32, batch: i32) -> Status {
+    ErrorCode::TrainingDiverged.status_with_details(
+        format!(
+            "Training diverged at epoch {}, batch {}: the loss or the weights are NaN or infinite",
+            epoch, batch
+        ),
+        [("epoch", epoch.to_string()), ("batch", batch.to_string())],
+    )
+}
+
+/// Returns the activations of the backbone for `inputs`, which leave the server as is.
+fn split_forward(
+    forward: &Forward,
+    inputs: Vec<PrivacyGuard<Tensor>>,
+    device: Device,
+) -> Result<Tensor, TchError> {
+    let mut inputs_ = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        inputs_.push(input.f_to(device)?);
+    }
+    Ok(forward.forward(inputs_)?.get_non_private())
+}
+
+/// Backpropagates the gradient `received` from the client through the backbone.
+fn split_backward(activations: &Tensor, received: Vec<u8>, device: Device) -> Result<(), TchError> {
+    let gradients = Tensor::try_from(&SizedObjectsBytes::from(received))?.f_to(device)?;
+    if gradients.size() != activations.size() {
+        return Err(TchError::Shape(format!(
+            "Expected gradients of shape {:?}, received {:?}",
+            activations.size(),
+            gradients.size()
+        )));
+    }
+    // The gradient of <activations, gradients> with respect to the activations is `gradients`.
+    activations
+        .f_mul(&gradients)?
+        .f_sum(Kind::Float)?
+        .backward();
+    Ok(())
+}
+
 /// Tests `module` on `dataset` outputing metrics to `run` with given `config` on `device`.
+///
+/// `on_metric` is called with every metric reported during testing, and `on_finish` with
+/// the resources used once testing is over. Testing runs on `pool`, off the async runtime
+/// serving requests.
 pub fn module_test(
+    pool: &ThreadPool,
     chkpt: Arc<RwLock<CheckPoint>>,
     binary: Arc<RwLock<BinaryModule>>,
     dataset: Arc<RwLock<Dataset>>,
@@ -294,27 +844,42 @@ pub fn module_test(
     model_hash: String,
     dataset_hash: String,
     client_info: Option<ClientInfo>,
+    on_metric: impl Fn(&Metric) + Send + 'static,
+    on_finish: impl FnOnce(ResourceUsage) + Send + 'static,
 ) {
-    tokio::spawn(async move {
+    spawn_on(pool, move || {
+        let mut meter = ResourceMeter::start();
         let dataset = dataset.read().unwrap();
         let batch_size = config.batch_size as usize;
         let chkpt = &chkpt.read().unwrap();
-        let chkpts_data = &chkpt.data;
-        let last_chkpt = &chkpts_data[chkpts_data.len() - 1];
-
-        let loaded_chkpt = Tensor::load_multi_from_stream(Cursor::new(last_chkpt)).unwrap(); // Fix later with more detailed errors.
-
-        let mut module: Module = (&*binary.read().unwrap()).try_into().unwrap(); // Fix later with more detailed errors.
+        let loaded = chkpt
+            .data
+            .last()
+            .ok_or_else(|| TchError::FileFormat(String::from("Model has no checkpoint")))
+            .and_then(|last_chkpt| Tensor::load_multi_from_stream(Cursor::new(last_chkpt)))
+            .and_then(|loaded_chkpt| {
+                let module = Module::try_from(&*binary.read().unwrap())?;
+                Ok((loaded_chkpt, module))
+            });
+        let (loaded_chkpt, mut module) = match tcherror_to_status(loaded) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                *run.write().unwrap() = Run::Error(e);
+                on_finish(meter.finish());
+                return;
+            }
+        };
 
-        match tcherror_to_status(build_test_context(
-            &mut module,
-            &dataset,
-            config,
-            chkpt.private,
-        )) {
-            Ok((forward, metric, metric_budget, mut params)) => {
-                params.override_parameters(loaded_chkpt).unwrap(); // Fix later with more detailed errors.
-                let tester =
+        match tcherror_to_status(
+            build_test_context(&mut module, &dataset, config, chkpt.private).and_then(
+                |(forward, metric, metric_budget, mut params)| {
+                    params.override_parameters(loaded_chkpt)?;
+                    Ok((forward, metric, metric_budget))
+                },
+            ),
+        ) {
+            Ok((forward, metric, metric_budget)) => {
+                let mut tester =
                     Tester::new(forward, &dataset, metric, metric_budget, device, batch_size);
                 let nb_batches = tester.nb_batches() as i32;
 
@@ -331,7 +896,7 @@ pub fn module_test(
                     },
                     client_info.clone(),
                 );
-                for res in tester {
+                for res in &mut tester {
                     *run.write().unwrap() =
                         match tcherror_to_status(res.map(|(batch, value, std)| Metric {
                             epoch: 0,
@@ -340,11 +905,16 @@ pub fn module_test(
                             nb_epochs: 1,
                             nb_batches,
                             uncertainty: 2.0 * std,
+                            validation: None,
                         })) {
-                            Ok(m) => Run::Ok(m),
+                            Ok(m) => {
+                                on_metric(&m);
+                                Run::Ok(m)
+                            }
                             Err(e) => Run::Error(e),
                         };
                 }
+                meter.add_read(tester.bytes_read());
                 telemetry::add_event(
                     TelemetryEventProps::TrainerLog {
                         log_type: Some("end_testing".to_string()),
@@ -364,5 +934,125 @@ pub fn module_test(
             }
             Err(e) => *run.write().unwrap() = Run::Error(e),
         }
+        on_finish(meter.finish());
     });
 }
+
+/// Returns the share of the samples of `trigger_set` that `binary`, with the last weights
+/// of `chkpt` if any, labels as the trigger set does.
+///
+/// The trigger set must not be private as its samples and the predictions are read as is.
+pub fn trigger_set_accuracy(
+    binary: &BinaryModule,
+    chkpt: Option<&CheckPoint>,
+    trigger_set: &Dataset,
+) -> Result<f32, TchError> {
+    let mut module = Module::try_from(binary)?;
+    let (forward, mut params) = module.parameters();
+    if let Some(last_chkpt) = chkpt.and_then(|chkpt| chkpt.data.last()) {
+        params.override_parameters(Tensor::load_multi_from_stream(Cursor::new(last_chkpt))?)?;
+    }
+
+    let mut matching = 0;
+    for (inputs, labels) in trigger_set.iter(trigger_set.len()) {
+        let predictions = forward
+            .forward(inputs)?
+            .get_non_private()
+            .f_argmax(-1, false)?;
+        matching += predictions
+            .f_eq_tensor(&labels.get_non_private())?
+            .f_sum(Kind::Int64)?
+            .int64_value(&[]);
+    }
+    Ok(matching as f32 / trigger_set.len() as f32)
+}
+
+/// Checks that `binary` accepts the inputs of `dataset` and that its outputs fit the labels
+/// for `metric`, failing with an invalid argument error describing any mismatch.
+pub fn check_shapes(binary: &BinaryModule, dataset: &Dataset, metric: &str) -> Result<(), Status> {
+    let res = Module::try_from(binary).and_then(|mut module| {
+        let (forward, _) = module.parameters();
+        procedures::check_shapes(&forward, dataset, metric)
+    });
+    match res {
+        Err(TchError::Shape(message)) => Err(Status::invalid_argument(message)),
+        res => tcherror_to_status(res),
+    }
+}
+
+/// Returns the dataset of the outputs of `binary`, with the last weights of `chkpt` if any,
+/// on `dataset`, computed on `device`.
+pub fn backbone_activations(
+    binary: &BinaryModule,
+    chkpt: Option<&CheckPoint>,
+    dataset: &Dataset,
+    batch_size: usize,
+    device: Device,
+) -> Result<Dataset, TchError> {
+    let mut module = Module::try_from(binary)?;
+    // Checkpoints are loaded on the CPU.
+    if let Some(last_chkpt) = chkpt.and_then(|chkpt| chkpt.data.last()) {
+        let (_, mut params) = module.parameters();
+        params.override_parameters(Tensor::load_multi_from_stream(Cursor::new(last_chkpt))?)?;
+    }
+    module.set_device(device);
+    let (forward, _) = module.parameters();
+    procedures::cache_activations(&forward, dataset, batch_size, device)
+}
+
+/// Serializes `binary`, with the serialized `weights` of one of its checkpoints if any, in `format`.
+pub fn export_module(
+    binary: &BinaryModule,
+    weights: Option<&[u8]>,
+    format: ModuleFormat,
+) -> Result<Vec<u8>, TchError> {
+    let mut module = Module::try_from(binary)?;
+    if let Some(weights) = weights {
+        let (_, mut params) = module.parameters();
+        params.override_parameters(Tensor::load_multi_from_stream(Cursor::new(weights))?)?;
+    }
+    serialization::export_module(&module, format)
+}
+
+/// Generates `count` canaries for `dataset` and returns them along with the dataset they
+/// are inserted into, if auditing is enabled.
+fn canaries_of(dataset: &Dataset, count: i32) -> Result<Option<(Canaries, Dataset)>, TchError> {
+    if count <= 0 {
+        return Ok(None);
+    }
+    let canaries = Canaries::generate(dataset, count as usize)?;
+    let injected = canaries.inject(dataset)?;
+    Ok(Some((canaries, injected)))
+}
+
+/// Returns the loss of `binary`, with the last weights of `chkpt`, on every sample of
+/// `canaries`, in order.
+///
+/// Integer labels are scored with the cross entropy, other labels with the mean squared error.
+fn canary_losses(
+    binary: &BinaryModule,
+    chkpt: &CheckPoint,
+    canaries: &Dataset,
+) -> Result<Vec<f32>, TchError> {
+    let last_chkpt = chkpt
+        .data
+        .last()
+        .ok_or_else(|| TchError::FileFormat(String::from("Model has no checkpoint")))?;
+    let mut module = Module::try_from(binary)?;
+    let (forward, mut params) = module.parameters();
+    params.override_parameters(Tensor::load_multi_from_stream(Cursor::new(last_chkpt))?)?;
+
+    let mut losses = Vec::with_capacity(canaries.len());
+    for (inputs, labels) in canaries.iter(1) {
+        let output = forward.forward(inputs)?.get_non_private();
+        let labels = labels.get_non_private();
+        let loss = match labels.kind() {
+            Kind::Int64 | Kind::Int | Kind::Int16 | Kind::Int8 | Kind::Uint8 => {
+                output.f_cross_entropy_loss::<Tensor>(&labels, None, Reduction::Mean, -100, 0.)?
+            }
+            _ => output.f_mse_loss(&labels.f_view_as(&output)?, Reduction::Mean)?,
+        };
+        losses.push(loss.f_double_value(&[])? as f32);
+    }
+    Ok(losses)
+}
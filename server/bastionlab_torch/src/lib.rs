@@ -1,13 +1,30 @@
+use bastionlab_common::cancellation::Cancellation;
+use bastionlab_common::compression::ChunkEncoding;
+use bastionlab_common::encryption::{ServerSigningKey, SIGNED_MODEL_CARD_CONTEXT};
+use bastionlab_common::notifications::{Notifier, PRIVACY_BUDGET_THRESHOLDS};
 use bastionlab_common::prelude::*;
-use bastionlab_common::session::SessionManager;
+use bastionlab_common::provenance::{Node, ProvenanceGraph};
+use bastionlab_common::remote_array::RemoteArrayRegistry;
+use bastionlab_common::session::{SessionManager, SessionResource};
+use bastionlab_common::session_proto::{ClientInfo, ErrorCode, NotificationKind};
 use bastionlab_common::telemetry::{self, TelemetryEventProps};
+use bastionlab_common::usage::{UsageEvent, UsageKind, UsageLog};
+use bastionlab_learning::audit::LeakageScore;
+use bastionlab_learning::data::privacy_guard::PrivacyBudget;
 use bastionlab_learning::nn::Module;
+use bastionlab_learning::optim::{ParameterKind, ParameterValue, OPTIMIZERS};
+use bastionlab_learning::procedures;
 use bastionlab_learning::{data::Dataset, nn::CheckPoint};
+use once_cell::sync::OnceCell;
 use prost::Message;
-use ring::{digest, hmac};
-use std::time::Instant;
-use tch::Tensor;
-use tokio_stream::wrappers::ReceiverStream;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use ring::digest;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tch::{TchError, Tensor};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 use tonic::{Request, Response, Status, Streaming};
 use uuid::Uuid;
 
@@ -19,26 +36,180 @@ pub mod bastionlab {
     tonic::include_proto!("bastionlab");
 }
 
+use torch_proto::model_card_request::Format;
+use torch_proto::module_fetch_request::Format as ModuleFetchFormat;
 use torch_proto::torch_service_server::TorchService;
 use torch_proto::{
-    Chunk, Devices, Empty, Metric, Optimizers, References, RemoteDatasetReference, TestConfig,
-    TrainConfig, UpdateTensor,
+    metric_update, optimizer_parameter, optimizer_parameter_schema, split_train_request,
+    split_train_response,
+};
+use torch_proto::{
+    AccessGrant, ActivationCacheRequest, ArtifactMetadataUpdate, ArtifactQuery, AudioDatasetChunk,
+    BestRunQuery, CheckpointFetchRequest, Chunk, DatasetConcatRequest, DatasetPreview,
+    DatasetPreviewRequest, DatasetSplitRequest, Devices, Empty, EpochSummary, ExperimentConfig,
+    Experiments, ImageDatasetChunk, LeakageAudit, Metric, MetricDescription, MetricDescriptions,
+    MetricUpdate, Metrics, ModelCardRequest, ModuleFetchRequest, OptimizerDescription,
+    OptimizerParameter, OptimizerParameterSchema, Optimizers, References, RemoteDatasetReference,
+    RemoteDatasetReferences, RunHistory, RunQuery, RunSummaries, RunSummary, SigningPublicKey,
+    SplitTrainRequest, SplitTrainResponse, TestConfig, TrainConfig, UpdateTensor, UploadReference,
+    UploadStatus, Watermark, WatermarkReport, WatermarkVerification,
 };
 
-use bastionlab::{Reference, TensorMetaData};
+use bastionlab::Reference;
+pub mod license;
+use license::{Grant, License};
+
 pub mod storage;
-use storage::Artifact;
+use storage::{Artifact, ArtifactKind, StorageBackend};
 
 mod utils;
 use utils::*;
 
+mod memory;
+use memory::MemoryAccountant;
+
+mod devices;
+use devices::DeviceScheduler;
+
+mod images;
+use images::{image_dataset, receive_images};
+
+mod audio;
+use audio::{audio_dataset, receive_recordings};
+
+mod upload;
+use upload::{upload_id, UploadManager};
+
 mod learning;
 use learning::*;
 
+pub mod runs;
+use runs::{RunConfig, RunKind, RunRecord, RunStatus, RunStore};
+
+mod archive;
+use archive::checkpoint_archive;
+
+mod resources;
+use resources::ResourceUsage;
+
+mod experiments;
+use experiments::{best_run, matches, run_resources, run_summary, Experiment};
+
+mod model_card;
+use model_card::{DatasetSummary, MetricSummary, ModelCard, ParameterSummary, TrainingSummary};
+
 mod serialization;
+pub use serialization::DEFAULT_CHUNK_SIZE;
 use serialization::*;
+pub use utils::{torch_capabilities, training_self_test};
+
+use bastionlab_learning::serialization::{BinaryModule, ModuleFormat, SizedObjectsBytes};
+
+/// Fails with the supported names when `name` is not a supported metric, or not a loss
+/// if `loss` is true, so that clients do not learn it from a failed run.
+fn check_metric(name: &str, loss: bool) -> Result<(), Status> {
+    let supported: Vec<_> = procedures::METRICS
+        .iter()
+        .filter(|info| info.is_loss || !loss)
+        .map(|info| info.name)
+        .collect();
+    if supported.contains(&name) {
+        return Ok(());
+    }
+    Err(Status::invalid_argument(format!(
+        "Unknown {} {}, supported ones are: {}",
+        if loss { "loss" } else { "metric" },
+        name,
+        supported.join(", ")
+    )))
+}
+
+/// Fails when `config` does not choose a supported optimizer with valid parameters, or an
+/// invalid learning rate schedule.
+fn check_optimizer(config: &TrainConfig) -> Result<(), Status> {
+    // The learning rate schedule is checked along with the optimizer it drives.
+    match optimizer_choice(config).and_then(|_| lr_scheduler_choice(config, 1)) {
+        Ok(_) => Ok(()),
+        Err(TchError::Kind(msg)) => Err(Status::invalid_argument(msg)),
+        Err(e) => tcherror_to_status(Err(e)),
+    }
+}
+
+fn metric_descriptions(loss: bool) -> MetricDescriptions {
+    MetricDescriptions {
+        list: procedures::METRICS
+            .iter()
+            .filter(|info| info.is_loss || !loss)
+            .map(|info| MetricDescription {
+                name: info.name.to_string(),
+                description: info.description.to_string(),
+                min: info.clipping.0 as f32,
+                max: info.clipping.1 as f32,
+                labels: info.labels.to_string(),
+            })
+            .collect(),
+    }
+}
+
+/// Returns the fraction of its privacy budget `data` expended, `None` if it is not private.
+fn expended_fraction(data: &Dataset) -> Option<f32> {
+    let context = data.privacy_context();
+    match (context.expended(), context.limit()) {
+        (PrivacyBudget::Private(expended), PrivacyBudget::Private(limit)) if limit > 0. => {
+            Some(expended / limit)
+        }
+        // Non-private accesses expend an infinite budget.
+        (PrivacyBudget::NotPrivate, PrivacyBudget::Private(_)) => Some(f32::INFINITY),
+        _ => None,
+    }
+}
+
+/// Returns the privacy budget `data` expended, `None` if it is not private.
+fn expended_eps(data: &Dataset) -> Option<f32> {
+    let context = data.privacy_context();
+    match (context.expended(), context.limit()) {
+        (_, PrivacyBudget::NotPrivate) => None,
+        (PrivacyBudget::Private(expended), _) => Some(expended),
+        // Non-private accesses expend an infinite budget.
+        (PrivacyBudget::NotPrivate, _) => Some(f32::INFINITY),
+    }
+}
+
+/// Returns the identifier of an uploaded artifact, the hash of its data, license and owner.
+///
+/// Uploads of other users are never deduplicated, so that identifiers do not reveal whether
+/// someone else uploaded the same data.
+fn upload_identifier(artifact: &Artifact<SizedObjectsBytes>) -> Result<String, Status> {
+    let terms = serde_json::to_vec(&(&artifact.license, &artifact.owner))
+        .map_err(|e| Status::internal(format!("Could not serialize license: {}", e)))?;
+    let mut context = digest::Context::new(&digest::SHA256);
+    context.update(artifact.data.read().unwrap().get());
+    context.update(&terms);
+    Ok(hex::encode(context.finish().as_ref()))
+}
 
-use bastionlab_learning::serialization::{BinaryModule, SizedObjectsBytes};
+fn build_training_pool(threads: Option<usize>) -> ThreadPool {
+    ThreadPoolBuilder::new()
+        .num_threads(threads.unwrap_or(0))
+        .thread_name(|i| format!("torch-training-{}", i))
+        .build()
+        .expect("Could not start the training threads")
+}
+
+/// Leakage measured with the canaries of a training, readable by the user who ran it and
+/// the owner of the dataset.
+struct AuditRecord {
+    audit: LeakageAudit,
+    user_id: String,
+    dataset_owner: Option<String>,
+}
+
+/// Number of metrics kept for the clients streaming them, past which slow clients skip
+/// metrics.
+const METRIC_STREAM_CAPACITY: usize = 256;
+
+/// Delay without metrics after which the streams of metrics send a keepalive.
+const METRIC_KEEPALIVE: Duration = Duration::from_secs(15);
 
 /// The server's state
 #[derive(Clone)]
@@ -47,63 +218,756 @@ pub struct BastionLabTorch {
     checkpoints: Arc<RwLock<HashMap<String, Artifact<CheckPoint>>>>,
     datasets: Arc<RwLock<HashMap<String, Artifact<Dataset>>>>,
     runs: Arc<RwLock<HashMap<Uuid, Arc<RwLock<Run>>>>>,
+    /// Records of the runs started by this process, to list them without the run database.
+    run_records: Arc<RwLock<HashMap<Uuid, RunRecord>>>,
+    /// Channels of the metrics of the active runs, for the clients streaming them.
+    metric_streams: Arc<RwLock<HashMap<Uuid, broadcast::Sender<Metric>>>>,
+    /// Final metric of every training run, per model.
+    metrics_history: Arc<RwLock<HashMap<String, Vec<Metric>>>>,
+    run_store: Option<RunStore>,
+    notifier: Option<Notifier>,
+    usage_log: Option<UsageLog>,
+    provenance: ProvenanceGraph,
+    /// Signs fetched models and model cards.
+    signing_key: Option<ServerSigningKey>,
+    /// Watermarks embedded by the trainings on a dataset, per dataset. Kept in memory only.
+    watermarks: Arc<RwLock<HashMap<String, Watermark>>>,
+    /// Tensors registered for the inputs and labels of each dataset, released with it.
+    dataset_tensors: Arc<RwLock<HashMap<String, Vec<String>>>>,
+    /// Leakage audits of the trainings with canaries, per run. Kept in memory only.
+    leakage_audits: Arc<RwLock<HashMap<Uuid, AuditRecord>>>,
+    /// Threads running trainings and tests, so that they do not starve request handlers.
+    /// Started with the first training, on `training_threads` threads or one per CPU.
+    training_pool: Arc<OnceCell<ThreadPool>>,
+    training_threads: Option<usize>,
+    /// Runs placed on each CUDA device.
+    devices: DeviceScheduler,
     sess_manager: Arc<SessionManager>,
-    tensors: Arc<RwLock<HashMap<String, Arc<Mutex<Tensor>>>>>,
+    tensors: RemoteArrayRegistry,
+    storage: Option<Arc<dyn StorageBackend>>,
+    memory: Option<Arc<Mutex<MemoryAccountant>>>,
+    uploads: Arc<UploadManager>,
+    max_dataset_upload_size: Option<usize>,
+    max_model_upload_size: Option<usize>,
+    chunk_size: Arc<AtomicUsize>,
+    max_chunk_size: Arc<AtomicUsize>,
+    /// Set once the server starts shutting down, to refuse new runs and interrupt training.
+    shutting_down: Arc<AtomicBool>,
+    active_trainings: Arc<AtomicUsize>,
+    /// Cancellation flags of the active trainings, with the users who started them.
+    cancelled_runs: Arc<RwLock<HashMap<Uuid, (String, Arc<AtomicBool>)>>>,
 }
 
 impl BastionLabTorch {
-    pub fn new(sess_manager: Arc<SessionManager>) -> Self {
+    /// Creates the service, sharing `tensors` with the other services.
+    pub fn new(sess_manager: Arc<SessionManager>, tensors: RemoteArrayRegistry) -> Self {
         BastionLabTorch {
             binaries: Arc::new(RwLock::new(HashMap::new())),
             checkpoints: Arc::new(RwLock::new(HashMap::new())),
             datasets: Arc::new(RwLock::new(HashMap::new())),
             runs: Arc::new(RwLock::new(HashMap::new())),
-            tensors: Arc::new(RwLock::new(HashMap::new())),
+            run_records: Arc::new(RwLock::new(HashMap::new())),
+            metric_streams: Arc::new(RwLock::new(HashMap::new())),
+            metrics_history: Arc::new(RwLock::new(HashMap::new())),
+            run_store: None,
+            notifier: None,
+            usage_log: None,
+            provenance: ProvenanceGraph::default(),
+            signing_key: None,
+            watermarks: Arc::new(RwLock::new(HashMap::new())),
+            dataset_tensors: Arc::new(RwLock::new(HashMap::new())),
+            leakage_audits: Arc::new(RwLock::new(HashMap::new())),
+            training_pool: Arc::new(OnceCell::new()),
+            training_threads: None,
+            devices: DeviceScheduler::default(),
+            tensors,
             sess_manager,
+            storage: None,
+            memory: None,
+            uploads: Arc::new(UploadManager::default()),
+            max_dataset_upload_size: None,
+            max_model_upload_size: None,
+            chunk_size: Arc::new(AtomicUsize::new(DEFAULT_CHUNK_SIZE)),
+            max_chunk_size: Arc::new(AtomicUsize::new(DEFAULT_CHUNK_SIZE)),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            active_trainings: Arc::new(AtomicUsize::new(0)),
+            cancelled_runs: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
-    pub fn insert_tensor(&self, tensor: Arc<Mutex<Tensor>>) -> (String, Reference) {
-        let identifier = Uuid::new_v4().to_string();
-        let create_tensor_ref = |tensor: &Mutex<Tensor>, identifier: &str| -> Reference {
-            let tensor = tensor.lock().unwrap();
-            let meta = create_tensor_meta(&tensor);
+    /// Sets the size of the chunks sent to clients, and the largest size clients may request
+    /// with the `chunk-size` request metadata. `max_chunk_size` should stay below the maximum
+    /// gRPC message size accepted by clients.
+    pub fn with_chunk_size(self, chunk_size: usize, max_chunk_size: usize) -> Self {
+        self.set_chunk_size(chunk_size, max_chunk_size);
+        self
+    }
 
-            Reference {
-                identifier: identifier.to_string(),
-                meta: meta.encode_to_vec(),
-                ..Default::default()
+    /// Same as [`BastionLabTorch::with_chunk_size`], on a running service.
+    pub fn set_chunk_size(&self, chunk_size: usize, max_chunk_size: usize) {
+        let max_chunk_size = max_chunk_size.max(1);
+        self.max_chunk_size.store(max_chunk_size, Ordering::Relaxed);
+        self.chunk_size
+            .store(chunk_size.clamp(1, max_chunk_size), Ordering::Relaxed);
+    }
+
+    /// Returns the size of the chunks to send in response to `request`.
+    fn chunk_size<T>(&self, request: &Request<T>) -> Result<usize, Status> {
+        let max_chunk_size = self.max_chunk_size.load(Ordering::Relaxed);
+        Ok(requested_chunk_size(request)?
+            .map(|size| size.min(max_chunk_size))
+            .unwrap_or_else(|| self.chunk_size.load(Ordering::Relaxed).min(max_chunk_size)))
+    }
+
+    /// Rejects dataset and tensor uploads larger than `max_dataset_size` bytes, and model
+    /// uploads larger than `max_model_size` bytes, as soon as they grow past the limit.
+    pub fn with_max_upload_sizes(
+        mut self,
+        max_dataset_size: Option<usize>,
+        max_model_size: Option<usize>,
+    ) -> Self {
+        self.max_dataset_upload_size = max_dataset_size;
+        self.max_model_upload_size = max_model_size;
+        self
+    }
+
+    /// Receives the raw artifact streamed in `request`, either in one go or as part of
+    /// the resumable upload given in the request metadata.
+    async fn receive(
+        &self,
+        request: Request<Streaming<Chunk>>,
+        max_size: Option<usize>,
+    ) -> Result<Artifact<SizedObjectsBytes>, Status> {
+        let encoding = ChunkEncoding::of_request(&request)?;
+        let token = self.sess_manager.get_token(&request)?;
+        let owner = self.sess_manager.get_user_id(token)?;
+        let mut artifact = match upload_id(&request)? {
+            Some(identifier) => {
+                self.uploads
+                    .receive(
+                        request.into_inner(),
+                        encoding,
+                        &identifier,
+                        &owner,
+                        max_size,
+                    )
+                    .await?
             }
+            None => unstream_data(request.into_inner(), encoding, max_size).await?,
         };
+        artifact.owner = Some(owner);
+        Ok(artifact)
+    }
+
+    /// Persists binaries, checkpoints and datasets to `storage` so that they survive restarts.
+    ///
+    /// Persisted artifacts are lazily reloaded in memory the first time they are accessed.
+    pub fn with_storage(mut self, storage: Arc<dyn StorageBackend>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
 
-        let tensor_ref = create_tensor_ref(tensor.as_ref(), &identifier);
+    /// Runs trainings and tests on `threads` dedicated threads instead of one per CPU.
+    pub fn with_training_threads(mut self, threads: usize) -> Self {
+        self.training_threads = Some(threads);
+        self
+    }
 
-        self.tensors
-            .write()
+    /// Records runs and their metrics in `store` so that they can be queried after
+    /// the run is over, including after a restart.
+    pub fn with_run_store(mut self, store: RunStore) -> Self {
+        self.run_store = Some(store);
+        self
+    }
+
+    /// Sends notifications about the outcome of runs and the privacy budgets they expend.
+    pub fn with_notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Signs fetched models, checkpoint archives and model cards with `key`, so that their
+    /// consumers can check that they come from this server.
+    /// Records the trainings and tests in `log`, for the usage reports of data owners.
+    pub fn with_usage_log(mut self, log: UsageLog) -> Self {
+        self.usage_log = Some(log);
+        self
+    }
+
+    /// Records the datasets, runs and checkpoints derived from other artifacts in `graph`.
+    pub fn with_provenance(mut self, graph: ProvenanceGraph) -> Self {
+        self.provenance = graph;
+        self
+    }
+
+    pub fn with_signing_key(mut self, key: ServerSigningKey) -> Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    /// Records the start of `run` in the run store, if any, and returns the callbacks
+    /// recording its metrics and outcome, and notifying it.
+    fn record_run(
+        &self,
+        run: Uuid,
+        record: RunRecord,
+    ) -> (
+        impl Fn(&Metric) + Send + 'static,
+        impl Fn(&Run, ResourceUsage) + Send + 'static,
+    ) {
+        let notifier = self.notifier.clone();
+        let dataset = self
+            .datasets
+            .read()
             .unwrap()
-            .insert(identifier.to_string(), tensor);
+            .get(&record.dataset)
+            .map(|artifact| (Arc::clone(&artifact.data), artifact.owner.clone()));
+        let expended_before = dataset
+            .as_ref()
+            .and_then(|(data, _)| expended_fraction(&data.read().unwrap()));
+        let eps_before = dataset
+            .as_ref()
+            .and_then(|(data, _)| expended_eps(&data.read().unwrap()));
+        let usage_log = self.usage_log.clone();
+        self.provenance.record(
+            Node::run(run.to_string()),
+            vec![Node::dataset(&record.dataset), Node::model(&record.model)],
+            match record.kind {
+                RunKind::Train => "training",
+                RunKind::Test => "test",
+            },
+        );
+        let run_records = Arc::clone(&self.run_records);
+        run_records.write().unwrap().insert(run, record.clone());
+        let metric_streams = Arc::clone(&self.metric_streams);
+        let (metric_sender, _) = broadcast::channel(METRIC_STREAM_CAPACITY);
+        metric_streams.write().unwrap().insert(run, metric_sender);
+        let store = self.run_store.clone();
+        if let Some(store) = &store {
+            if let Err(e) = store.start(run, &record) {
+                error!("Could not record run {}: {}", run, e);
+            }
+        }
+        let dataset_owner = dataset.as_ref().and_then(|(_, owner)| owner.as_deref());
+        if let (Some(notifier), RunKind::Train, Some(owner)) =
+            (&notifier, record.kind, dataset_owner)
+        {
+            notifier.notify_user(
+                NotificationKind::DatasetUsed,
+                &record.dataset,
+                format!(
+                    "User {} started training model {} on dataset {} in run {}",
+                    record.user_id, record.model, record.dataset, run
+                ),
+                owner,
+            );
+        }
+        // Last metric of the current epoch of a training, with the budget expended so far.
+        let last_of_epoch: Arc<Mutex<Option<(Metric, f32)>>> = Arc::default();
+        let push_epoch = {
+            let store = store.clone();
+            let learning_rate = record
+                .config
+                .as_ref()
+                .map(|config| config.learning_rate)
+                .unwrap_or_default();
+            move |(metric, eps): (Metric, f32)| {
+                if let Some(store) = &store {
+                    let summary = EpochSummary {
+                        epoch: metric.epoch,
+                        metric: metric.value,
+                        uncertainty: metric.uncertainty,
+                        nb_batches: metric.batch + 1,
+                        learning_rate,
+                        eps,
+                        validation: metric.validation.clone(),
+                    };
+                    if let Err(e) = store.push_epoch(run, &summary) {
+                        error!("Could not record epoch of run {}: {}", run, e);
+                    }
+                }
+            }
+        };
+        let on_metric = {
+            let store = store.clone();
+            let dataset = dataset.as_ref().map(|(data, _)| Arc::clone(data));
+            let last_of_epoch = Arc::clone(&last_of_epoch);
+            let push_epoch = push_epoch.clone();
+            let is_training = record.kind == RunKind::Train;
+            let metric_streams = Arc::clone(&metric_streams);
+            move |metric: &Metric| {
+                if let Some(store) = &store {
+                    if let Err(e) = store.push_metric(run, metric) {
+                        error!("Could not record metric of run {}: {}", run, e);
+                    }
+                }
+                if let Some(sender) = metric_streams.read().unwrap().get(&run) {
+                    // Fails when no client streams the metrics.
+                    let _ignored = sender.send(metric.clone());
+                }
+                if !is_training {
+                    return;
+                }
+                // Metrics are running averages over the epoch, so the last one summarizes it.
+                let eps = match (&dataset, eps_before) {
+                    (Some(data), Some(before)) => expended_eps(&data.read().unwrap())
+                        .map(|eps| eps - before)
+                        .unwrap_or(-1.),
+                    _ => -1.,
+                };
+                let mut last = last_of_epoch.lock().unwrap();
+                if let Some(previous) = last.take() {
+                    if previous.0.epoch != metric.epoch {
+                        push_epoch(previous);
+                    }
+                }
+                *last = Some((metric.clone(), eps));
+            }
+        };
+        let on_finish = move |outcome: &Run, resources: ResourceUsage| {
+            // Dropping the only sender ends the streams of the metrics of the run.
+            metric_streams.write().unwrap().remove(&run);
+            if let Some(last) = last_of_epoch.lock().unwrap().take() {
+                push_epoch(last);
+            }
+            if let Some(record) = run_records.write().unwrap().get_mut(&run) {
+                record.finish(outcome, resources);
+            }
+            if let Some(store) = &store {
+                if let Err(e) = store.finish(run, outcome, resources) {
+                    error!("Could not record outcome of run {}: {}", run, e);
+                }
+            }
+            if let Some(log) = &usage_log {
+                let kind = match record.kind {
+                    RunKind::Train => UsageKind::Training,
+                    RunKind::Test => UsageKind::Test,
+                };
+                // Non-private uses of private datasets expend an infinite budget, which is
+                // recorded as 0 on an event that is not private.
+                let eps = match (&dataset, eps_before) {
+                    (Some((data, _)), Some(before)) => expended_eps(&data.read().unwrap())
+                        .map(|eps| eps - before)
+                        .filter(|eps| eps.is_finite())
+                        .unwrap_or_default(),
+                    _ => 0.,
+                };
+                let private = match &record.config {
+                    Some(config) => config.eps > 0.,
+                    None => eps > 0.,
+                };
+                let event = UsageEvent::new(&record.dataset, &record.user_id, kind)
+                    .with_owner(dataset.as_ref().and_then(|(_, owner)| owner.as_deref()))
+                    .with_eps(eps as f64)
+                    .with_private(private);
+                log.record(match record.kind {
+                    RunKind::Train => event.with_derived(&record.model),
+                    RunKind::Test => event,
+                });
+            }
+            let notifier = match &notifier {
+                Some(notifier) => notifier,
+                None => return,
+            };
+            let (kind, message) = match outcome {
+                Run::Ok(_) => (
+                    NotificationKind::RunCompleted,
+                    format!(
+                        "Run of model {} on dataset {} completed",
+                        record.model, record.dataset
+                    ),
+                ),
+                Run::Error(e) => (
+                    NotificationKind::RunFailed,
+                    format!(
+                        "Run of model {} on dataset {} failed: {}",
+                        record.model,
+                        record.dataset,
+                        e.message()
+                    ),
+                ),
+                Run::Pending => (
+                    NotificationKind::RunFailed,
+                    format!(
+                        "Run of model {} on dataset {} did not produce any metric",
+                        record.model, record.dataset
+                    ),
+                ),
+                Run::Cancelled => (
+                    NotificationKind::RunFailed,
+                    format!(
+                        "Run of model {} on dataset {} was cancelled",
+                        record.model, record.dataset
+                    ),
+                ),
+            };
+            notifier.notify(kind, &run.to_string(), message, Some(&record.user_id));
 
-        info!("Successfully inserted tensor {}", identifier);
-        (identifier.to_string(), tensor_ref)
+            if let (Some((data, Some(owner))), Some(before)) = (&dataset, expended_before) {
+                let after = match expended_fraction(&data.read().unwrap()) {
+                    Some(after) => after,
+                    None => return,
+                };
+                for threshold in PRIVACY_BUDGET_THRESHOLDS {
+                    if before < threshold && after >= threshold {
+                        notifier.notify_user(
+                            NotificationKind::PrivacyBudgetThreshold,
+                            &record.dataset,
+                            format!(
+                                "Run {} expended {:.0}% of the privacy budget of dataset {}",
+                                run,
+                                threshold * 100.,
+                                record.dataset
+                            ),
+                            owner,
+                        );
+                    }
+                }
+            }
+        };
+        (on_metric, on_finish)
     }
 
-    fn insert_dataset(&self, dataset: Artifact<Dataset>) -> RemoteDatasetReference {
-        let identifier = Uuid::new_v4().to_string();
-        let mut datasets = self.datasets.write().unwrap();
+    /// Evicts the least recently used persisted artifacts from memory when they hold more than
+    /// `budget` bytes. Evicted artifacts are reloaded from storage when accessed again.
+    ///
+    /// This has no effect unless a storage backend is set with [`BastionLabTorch::with_storage`].
+    pub fn with_memory_budget(mut self, budget: usize) -> Self {
+        self.memory = Some(Arc::new(Mutex::new(MemoryAccountant::new(budget))));
+        self
+    }
+
+    /// Changes the memory budget of a running service. Returns `false` if the service
+    /// was not started with a budget, in which case it cannot be set.
+    pub fn set_memory_budget(&self, budget: usize) -> bool {
+        match &self.memory {
+            Some(memory) => {
+                memory.lock().unwrap().set_budget(budget);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records the size of a persisted artifact and evicts artifacts if the memory budget is exceeded.
+    fn account(&self, kind: ArtifactKind, identifier: &str, size: usize) {
+        let memory = match &self.memory {
+            Some(memory) => memory,
+            None => return,
+        };
+        let mut memory = memory.lock().unwrap();
+        memory.record(kind, identifier, size);
+
+        // Artifacts whose data is shared with a run or a pending request are in use.
+        let in_use = |kind: ArtifactKind, identifier: &str| match kind {
+            ArtifactKind::Binary => is_shared(&self.binaries, identifier),
+            ArtifactKind::CheckPoint => is_shared(&self.checkpoints, identifier),
+            ArtifactKind::Dataset => is_shared(&self.datasets, identifier),
+        };
+        for (kind, identifier) in memory.select_evictions(in_use) {
+            match kind {
+                ArtifactKind::Binary => {
+                    self.binaries.write().unwrap().remove(&identifier);
+                }
+                ArtifactKind::CheckPoint => {
+                    self.checkpoints.write().unwrap().remove(&identifier);
+                }
+                ArtifactKind::Dataset => {
+                    // The registered tensors would keep the data in memory. They are
+                    // registered again with the next reference to the reloaded dataset.
+                    self.release_dataset_tensors(&identifier);
+                    self.datasets.write().unwrap().remove(&identifier);
+                }
+            }
+            info!(
+                "Evicted {} {} from memory ({} bytes still in use)",
+                kind.as_str(),
+                identifier,
+                memory.used()
+            );
+        }
+    }
+
+    /// Reloads the artifact `identifier` from the storage backend into `store`
+    /// if it is not already in memory.
+    fn restore<T>(
+        &self,
+        store: &RwLock<HashMap<String, Artifact<T>>>,
+        kind: ArtifactKind,
+        identifier: &str,
+    ) -> Result<(), Status>
+    where
+        T: TryFrom<SizedObjectsBytes, Error = TchError> + std::fmt::Debug,
+    {
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Ok(()),
+        };
+        if store.read().unwrap().contains_key(identifier) {
+            if let Some(memory) = &self.memory {
+                memory.lock().unwrap().touch(kind, identifier);
+            }
+            return Ok(());
+        }
+        if let Some(artifact) = storage.load(kind, identifier)? {
+            // Expired artifacts are left for the reaper to delete.
+            if artifact.is_expired(SystemTime::now()) {
+                return Ok(());
+            }
+            let size = artifact.data.read().unwrap().get().len();
+            let artifact = tcherror_to_status(artifact.deserialize())?;
+            store
+                .write()
+                .unwrap()
+                .entry(identifier.to_string())
+                .or_insert(artifact);
+            info!("Reloaded {} {} from storage", kind.as_str(), identifier);
+            self.account(kind, identifier, size);
+        }
+        Ok(())
+    }
+
+    /// Reloads all the persisted artifacts of the given kind that are not already in memory.
+    fn restore_all<T>(
+        &self,
+        store: &RwLock<HashMap<String, Artifact<T>>>,
+        kind: ArtifactKind,
+    ) -> Result<(), Status>
+    where
+        T: TryFrom<SizedObjectsBytes, Error = TchError> + std::fmt::Debug,
+    {
+        if let Some(storage) = &self.storage {
+            for identifier in storage.list(kind)? {
+                self.restore(store, kind, &identifier)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the in-memory artifact `identifier` to the storage backend, if any.
+    fn persist<T>(
+        &self,
+        store: &RwLock<HashMap<String, Artifact<T>>>,
+        kind: ArtifactKind,
+        identifier: &str,
+    ) -> Result<(), Status>
+    where
+        for<'a> &'a T: TryInto<SizedObjectsBytes, Error = TchError>,
+    {
+        if let Some(storage) = &self.storage {
+            let serialized = {
+                let store = store.read().unwrap();
+                let artifact = store
+                    .get(identifier)
+                    .ok_or_else(|| Status::not_found("Artifact not found"))?;
+                tcherror_to_status(artifact.serialize())?
+            };
+            storage.store(kind, identifier, &serialized)?;
+            let size = serialized.data.read().unwrap().get().len();
+            self.account(kind, identifier, size);
+        }
+        Ok(())
+    }
+
+    /// Removes the artifact `identifier` from the storage backend, if any.
+    fn unpersist(&self, kind: ArtifactKind, identifier: &str) -> Result<(), Status> {
+        if let Some(memory) = &self.memory {
+            memory.lock().unwrap().forget(kind, identifier);
+        }
+        match &self.storage {
+            Some(storage) => storage.remove(kind, identifier),
+            None => Ok(()),
+        }
+    }
+
+    /// Deletes the binaries, checkpoints and datasets whose time-to-live has elapsed,
+    /// from memory and from the storage backend.
+    ///
+    /// This is meant to be called periodically.
+    pub fn reap_expired(&self) -> Result<(), Status> {
+        let now = SystemTime::now();
+        let mut expired = Vec::new();
+        collect_expired(&self.binaries, ArtifactKind::Binary, now, &mut expired);
+        collect_expired(
+            &self.checkpoints,
+            ArtifactKind::CheckPoint,
+            now,
+            &mut expired,
+        );
+        collect_expired(&self.datasets, ArtifactKind::Dataset, now, &mut expired);
+        if let Some(storage) = &self.storage {
+            for kind in [
+                ArtifactKind::Binary,
+                ArtifactKind::CheckPoint,
+                ArtifactKind::Dataset,
+            ] {
+                for identifier in storage.list(kind)? {
+                    let is_expired = storage
+                        .expiry(kind, &identifier)?
+                        .map(|t| t <= now)
+                        .unwrap_or(false);
+                    if is_expired && !expired.contains(&(kind, identifier.clone())) {
+                        expired.push((kind, identifier));
+                    }
+                }
+            }
+        }
+
+        for (kind, identifier) in expired {
+            let name = match kind {
+                ArtifactKind::Binary => self
+                    .binaries
+                    .write()
+                    .unwrap()
+                    .remove(&identifier)
+                    .map(|artifact| artifact.name),
+                ArtifactKind::CheckPoint => {
+                    self.metrics_history.write().unwrap().remove(&identifier);
+                    self.checkpoints
+                        .write()
+                        .unwrap()
+                        .remove(&identifier)
+                        .map(|artifact| artifact.name)
+                }
+                ArtifactKind::Dataset => self
+                    .remove_dataset(&identifier)
+                    .map(|artifact| artifact.name),
+            };
+            self.unpersist(kind, &identifier)?;
+            info!("Deleted expired {} {}", kind.as_str(), identifier);
+            telemetry::add_event(
+                TelemetryEventProps::ArtifactExpired {
+                    artifact_kind: kind.as_str().to_string(),
+                    artifact_name: name,
+                },
+                None,
+            );
+        }
+        Ok(())
+    }
 
-        let (inputs, labels) = {
-            let mut inputs = vec![];
-            let dataset = dataset.data.read().unwrap();
-            for sample in dataset.samples_inputs.iter() {
-                let (_, tensor_ref) = self.insert_tensor(Arc::clone(sample));
-                inputs.push(tensor_ref);
+    /// Stops accepting training and testing runs, and interrupts active trainings after
+    /// their current step so that their progress is checkpointed and persisted.
+    ///
+    /// Returns `false` if some trainings are still running after `grace_period`.
+    pub async fn shutdown(&self, grace_period: Duration) -> bool {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + grace_period;
+        while self.active_trainings.load(Ordering::SeqCst) > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        true
+    }
+
+    /// Takes the artifact, run and tensor locks once. This blocks if they are deadlocked.
+    pub fn probe(&self) {
+        drop(self.binaries.read().unwrap());
+        drop(self.checkpoints.read().unwrap());
+        drop(self.datasets.read().unwrap());
+        drop(self.runs.read().unwrap());
+        self.tensors.probe();
+    }
+
+    /// Cancels the trainings and releases the tensors of a session that expired or whose
+    /// client went silent. Trainings are checkpointed before they stop.
+    pub fn release_session_resources(&self, resources: &[SessionResource]) {
+        for resource in resources {
+            match resource {
+                SessionResource::Run(run) => {
+                    let cancelled = Uuid::parse_str(run)
+                        .ok()
+                        .and_then(|run| self.cancelled_runs.read().unwrap().get(&run).cloned());
+                    if let Some((_, cancelled)) = cancelled {
+                        cancelled.store(true, Ordering::SeqCst);
+                        info!("Cancelling run {} of an ended session", run);
+                    }
+                }
+                SessionResource::Tensor(identifier) => {
+                    self.tensors.remove(identifier);
+                    info!("Released tensor {} of an ended session", identifier);
+                }
+                SessionResource::DataFrame(_) => (),
             }
+        }
+    }
+
+    fn check_accepting_runs(&self) -> Result<(), Status> {
+        if self.shutting_down.load(Ordering::SeqCst) {
+            return Err(Status::unavailable("Server is shutting down"));
+        }
+        Ok(())
+    }
+
+    fn training_pool(&self) -> &ThreadPool {
+        self.training_pool
+            .get_or_init(|| build_training_pool(self.training_threads))
+    }
 
-            let (_, labels_ref) = self.insert_tensor(Arc::clone(&dataset.labels));
-            (inputs, labels_ref)
+    pub fn insert_tensor(&self, tensor: Arc<Mutex<Tensor>>) -> (String, Reference) {
+        let meta = create_tensor_meta(&tensor.lock().unwrap());
+        let identifier = self.tensors.insert_tensor(tensor);
+
+        info!("Successfully inserted tensor {}", identifier);
+        let tensor_ref = Reference {
+            identifier: identifier.clone(),
+            meta: meta.encode_to_vec(),
+            ..Default::default()
         };
+        (identifier, tensor_ref)
+    }
+
+    /// Returns a reference to the array `identifier` used as a tensor, whichever service
+    /// registered it.
+    pub fn tensor_reference(&self, identifier: &str) -> Result<Reference, Status> {
+        let tensor = self.get_tensor(identifier)?;
+        let meta = create_tensor_meta(&tensor.lock().unwrap());
+        Ok(Reference {
+            identifier: identifier.to_string(),
+            meta: meta.encode_to_vec(),
+            ..Default::default()
+        })
+    }
+
+    fn insert_dataset(
+        &self,
+        identifier: String,
+        dataset: Artifact<Dataset>,
+    ) -> RemoteDatasetReference {
+        let reference = self.dataset_reference(identifier.clone(), &dataset.data.read().unwrap());
+        self.datasets.write().unwrap().insert(identifier, dataset);
+        reference
+    }
+
+    /// Registers the inputs and labels of `dataset` as tensors and returns a reference to the dataset.
+    fn dataset_reference(&self, identifier: String, dataset: &Dataset) -> RemoteDatasetReference {
+        let mut inputs = vec![];
+        for sample in dataset.samples_inputs.iter() {
+            let (_, tensor_ref) = self.insert_tensor(Arc::clone(sample));
+            inputs.push(tensor_ref);
+        }
+        let (_, labels) = self.insert_tensor(Arc::clone(&dataset.labels));
+        for tensor in inputs.iter().chain([&labels]) {
+            self.provenance.record(
+                Node::array(&tensor.identifier),
+                vec![Node::dataset(&identifier)],
+                "dataset tensor",
+            );
+        }
+        self.dataset_tensors
+            .write()
+            .unwrap()
+            .entry(identifier.clone())
+            .or_default()
+            .extend(
+                inputs
+                    .iter()
+                    .chain([&labels])
+                    .map(|tensor| tensor.identifier.clone()),
+            );
 
-        datasets.insert(identifier.clone(), dataset);
         RemoteDatasetReference {
             identifier,
             inputs,
@@ -111,13 +975,56 @@ impl BastionLabTorch {
         }
     }
 
-    pub fn get_tensor(&self, identifier: &str) -> Result<Arc<Mutex<Tensor>>, Status> {
-        let tensors = self.tensors.read().unwrap();
-        let tensor = tensors
-            .get(identifier)
-            .ok_or(Status::aborted("Could not find tensor on BastionLab Torch"))?;
+    /// Removes the dataset `identifier` from memory, along with its watermark and the tensors
+    /// registered for it, and returns it.
+    fn remove_dataset(&self, identifier: &str) -> Option<Artifact<Dataset>> {
+        self.watermarks.write().unwrap().remove(identifier);
+        self.release_dataset_tensors(identifier);
+        self.datasets.write().unwrap().remove(identifier)
+    }
+
+    /// Removes the tensors registered for the dataset `identifier` from the array registry,
+    /// which would keep its data in memory otherwise.
+    fn release_dataset_tensors(&self, identifier: &str) {
+        let tensors = self.dataset_tensors.write().unwrap().remove(identifier);
+        for tensor in tensors.into_iter().flatten() {
+            self.tensors.remove(&tensor);
+        }
+    }
+
+    /// Keeps the artifact `identifier` at least until `expires_at`, when it is uploaded again.
+    fn extend_expiry<T>(
+        &self,
+        store: &RwLock<HashMap<String, Artifact<T>>>,
+        kind: ArtifactKind,
+        identifier: &str,
+        expires_at: Option<SystemTime>,
+    ) -> Result<(), Status>
+    where
+        for<'a> &'a T: TryInto<SizedObjectsBytes, Error = TchError>,
+    {
+        let changed = {
+            let mut store = store.write().unwrap();
+            let artifact = match store.get_mut(identifier) {
+                Some(artifact) => artifact,
+                None => return Ok(()),
+            };
+            let extended = match (artifact.expires_at, expires_at) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                _ => None,
+            };
+            let changed = extended != artifact.expires_at;
+            artifact.expires_at = extended;
+            changed
+        };
+        if changed {
+            self.persist(store, kind, identifier)?;
+        }
+        Ok(())
+    }
 
-        Ok(Arc::clone(tensor))
+    pub fn get_tensor(&self, identifier: &str) -> Result<Arc<Mutex<Tensor>>, Status> {
+        self.tensors.get_tensor(identifier)
     }
 
     fn convert_from_remote_dataset_to_dataset(
@@ -128,7 +1035,7 @@ impl BastionLabTorch {
             let labels = dataset
                 .labels
                 .clone()
-                .ok_or(Status::aborted(format!("Labels not found")))?;
+                .ok_or_else(|| ErrorCode::LabelsNotFound.status("Labels not found"))?;
             let description = labels.description.clone();
             let meta = labels.meta.clone();
             let name = labels.name.clone();
@@ -139,69 +1046,688 @@ impl BastionLabTorch {
         let dataset: RemoteDataset = dataset.into();
 
         let mut samples_inputs = vec![];
+        let mut sources = vec![];
 
         for input in dataset.inputs {
             samples_inputs.push(self.get_tensor(&input.identifier)?);
+            sources.push(Node::array(input.identifier));
         }
 
         let labels = self.get_tensor(&dataset.labels.identifier)?;
+        sources.push(Node::array(dataset.labels.identifier));
         let limit = dataset.privacy_limit;
 
         let data = Dataset::new(samples_inputs, labels, limit);
 
+        let reference =
+            self.insert_dataset_data(data, name, description, meta, None, License::default());
+        self.provenance
+            .record(Node::dataset(&reference.identifier), sources, "conversion");
+        Ok(reference)
+    }
+
+    /// Registers `data`, built on the server from registered tensors, under `license` and
+    /// returns a reference to the dataset.
+    pub fn insert_dataset_data(
+        &self,
+        data: Dataset,
+        name: String,
+        description: String,
+        meta: Vec<u8>,
+        owner: Option<String>,
+        license: License,
+    ) -> RemoteDatasetReference {
         let artifact = Artifact {
             client_info: None,
             data: Arc::new(RwLock::new(data)),
             description,
             name,
             meta,
-            secret: hmac::Key::new(ring::hmac::HMAC_SHA256, &[0]),
+            secret: Vec::new(),
+            expires_at: None,
+            tags: HashMap::new(),
+            owner,
+            created_at: Some(SystemTime::now()),
+            license,
         };
 
-        let dataset = self.insert_dataset(artifact);
-        Ok(dataset)
+        self.insert_dataset(Uuid::new_v4().to_string(), artifact)
     }
-}
 
-#[tonic::async_trait]
-impl TorchService for BastionLabTorch {
-    type FetchDatasetStream = ReceiverStream<Result<Chunk, Status>>;
-    type FetchModuleStream = ReceiverStream<Result<Chunk, Status>>;
+    /// Fails with `NotFound` unless `user_id` may see the run `run`: like in list_runs, data
+    /// owners see every run and other users their own.
+    fn check_run_visible(&self, run: Uuid, user_id: &str) -> Result<(), Status> {
+        if !self.sess_manager.auth_enabled() || self.sess_manager.verify_if_owner(user_id)? {
+            return Ok(());
+        }
+        let run_user = match self.run_records.read().unwrap().get(&run) {
+            Some(record) => Some(record.user_id.clone()),
+            // Runs of a previous server process are only known to the run store
+            None => match &self.run_store {
+                Some(store) => store.record(run)?.map(|record| record.user_id),
+                None => None,
+            },
+        };
+        if run_user.as_deref() == Some(user_id) {
+            Ok(())
+        } else {
+            Err(Status::not_found("Run not found"))
+        }
+    }
 
-    async fn send_dataset(
-        &self,
-        request: Request<Streaming<Chunk>>,
-    ) -> Result<Response<RemoteDatasetReference>, Status> {
-        let token = self.sess_manager.get_token(&request)?;
-        let client_info = self.sess_manager.get_client_info(token)?;
+    fn experiment_store(&self) -> Result<&RunStore, Status> {
+        self.run_store.as_ref().ok_or_else(|| {
+            Status::failed_precondition("Experiments require the run database to be enabled")
+        })
+    }
 
-        let start_time = Instant::now();
+    /// Returns the experiment `identifier` if it belongs to `user_id`.
+    fn owned_experiment(&self, identifier: &str, user_id: &str) -> Result<Uuid, Status> {
+        let identifier = Uuid::parse_str(identifier)
+            .map_err(|_| Status::invalid_argument("Invalid experiment reference"))?;
+        match self.experiment_store()?.experiment(identifier)? {
+            Some(experiment) if experiment.owner == user_id => Ok(identifier),
+            _ => Err(Status::not_found("Experiment not found")),
+        }
+    }
 
-        let artifact: Artifact<SizedObjectsBytes> = unstream_data(request.into_inner()).await?;
+    /// Returns the experiment a run of `user_id` is added to, `None` if `experiment` is empty.
+    fn run_experiment(&self, experiment: &str, user_id: &str) -> Result<Option<Uuid>, Status> {
+        if experiment.is_empty() {
+            return Ok(None);
+        }
+        self.owned_experiment(experiment, user_id).map(Some)
+    }
 
-        let (dataset_hash, dataset_size) = {
-            let lock = artifact.data.read().unwrap();
-            let data = lock.get();
-            let hash = hex::encode(digest::digest(&digest::SHA256, &data).as_ref());
-            (hash, data.len())
+    /// Returns the binary `binary_id` and the checkpoint a training of `user_id` writes to,
+    /// the existing one when resuming or a new one otherwise.
+    fn training_artifacts(
+        &self,
+        binary_id: &str,
+        user_id: &str,
+        private: bool,
+        resume: bool,
+        client_info: &ClientInfo,
+        encrypt_to: Option<String>,
+    ) -> Result<(Arc<RwLock<BinaryModule>>, Arc<RwLock<CheckPoint>>), Status> {
+        let binaries = self.binaries.read().unwrap();
+        let binary: &Artifact<BinaryModule> = binaries
+            .get(binary_id)
+            .ok_or_else(|| Status::not_found("Module binary not found"))?;
+        binary
+            .license
+            .verify_train(user_id, binary.owner.as_deref(), private)?;
+        let mut checkpoints = self.checkpoints.write().unwrap();
+        let chkpt = if resume {
+            let chkpt = checkpoints
+                .get_mut(binary_id)
+                .ok_or_else(|| Status::not_found("CheckPoint not found!"))?;
+            chkpt
+        } else {
+            let chkpt = Artifact {
+                data: Arc::new(RwLock::new(CheckPoint::new(private))),
+                name: binary.name.clone(),
+                client_info: Some(client_info.clone()),
+                secret: binary.secret.clone(),
+                description: binary.description.clone(),
+                meta: binary.meta.clone(),
+                expires_at: binary.expires_at,
+                tags: binary.tags.clone(),
+                owner: binary.owner.clone(),
+                created_at: Some(SystemTime::now()),
+                license: binary.license.clone(),
+            };
+            checkpoints.insert(binary_id.to_string(), chkpt);
+            let chkpt = checkpoints
+                .get_mut(binary_id)
+                .ok_or_else(|| Status::not_found("Module binary not found"))?;
+            chkpt
         };
+        // Weights trained on a dataset whose owner requires encryption stay encrypted
+        // to that owner's recipient, whatever the license of the binary says.
+        if encrypt_to.is_some() {
+            chkpt.license.encrypt_to = encrypt_to;
+        }
+        Ok((Arc::clone(&binary.data), Arc::clone(&chkpt.data)))
+    }
 
-        let dataset: Artifact<Dataset> = tcherror_to_status((artifact).deserialize())?;
-        let name = dataset.name.clone();
+    /// Returns the dataset `identifier` if it belongs to `user_id`.
+    fn owned_dataset(
+        &self,
+        identifier: &str,
+        user_id: &str,
+    ) -> Result<Arc<RwLock<Dataset>>, Status> {
+        self.restore(&self.datasets, ArtifactKind::Dataset, identifier)?;
+        match self.datasets.read().unwrap().get(identifier) {
+            Some(dataset) if dataset.owner.as_deref() == Some(user_id) => {
+                Ok(Arc::clone(&dataset.data))
+            }
+            _ => Err(Status::not_found("Dataset not found")),
+        }
+    }
 
-        let dataset = self.insert_dataset(dataset);
+    /// Returns the trigger set and number of epochs of the watermark of `dataset`, if any.
+    fn watermark_of(&self, dataset: &str) -> Result<Option<(Arc<RwLock<Dataset>>, usize)>, Status> {
+        let watermark = match self.watermarks.read().unwrap().get(dataset) {
+            Some(watermark) => watermark.clone(),
+            None => return Ok(None),
+        };
+        self.restore(
+            &self.datasets,
+            ArtifactKind::Dataset,
+            &watermark.trigger_set,
+        )?;
+        let trigger_set = self
+            .datasets
+            .read()
+            .unwrap()
+            .get(&watermark.trigger_set)
+            .map(|trigger_set| Arc::clone(&trigger_set.data))
+            .ok_or_else(|| {
+                Status::failed_precondition(
+                    "The trigger set of the dataset's watermark was deleted",
+                )
+            })?;
+        Ok(Some((trigger_set, watermark.epochs as usize)))
+    }
 
-        let elapsed = start_time.elapsed();
-        info!(
-            "Successfully uploaded Dataset {} in {}ms",
-            dataset.identifier,
-            elapsed.as_millis()
-        );
+    /// Returns the runs of the experiment targeted by `query` that pass its filters,
+    /// with their final metric.
+    fn experiment_runs(
+        &self,
+        query: &RunQuery,
+        user_id: &str,
+    ) -> Result<Vec<(Uuid, RunRecord, Option<Metric>)>, Status> {
+        let experiment = self.owned_experiment(&query.experiment, user_id)?;
+        let store = self.experiment_store()?;
+        store
+            .records_of_experiment(experiment)?
+            .into_iter()
+            .filter(|(_, record)| matches(query, record))
+            .map(|(run, record)| Ok((run, record, store.metrics(run)?.pop())))
+            .collect()
+    }
 
-        telemetry::add_event(
-            TelemetryEventProps::SendDataset {
-                dataset_name: Some(name.clone()),
-                dataset_size,
+    /// Summarizes what is still known of the dataset `identifier`.
+    fn dataset_summary(&self, identifier: &str) -> Result<DatasetSummary, Status> {
+        self.restore(&self.datasets, ArtifactKind::Dataset, identifier)?;
+        let datasets = self.datasets.read().unwrap();
+        let artifact = datasets.get(identifier);
+        let budget = |budget| match budget {
+            PrivacyBudget::NotPrivate => None,
+            PrivacyBudget::Private(eps) => Some(eps),
+        };
+        let (samples, privacy) = match artifact {
+            Some(artifact) => {
+                let data = artifact.data.read().unwrap();
+                (Some(data.len()), Some(data.privacy_context()))
+            }
+            None => (None, None),
+        };
+        Ok(DatasetSummary {
+            identifier: identifier.to_string(),
+            name: artifact.map(|artifact| artifact.name.clone()),
+            samples,
+            owner: artifact.and_then(|artifact| artifact.owner.clone()),
+            license: artifact.map(|artifact| artifact.license.clone()),
+            privacy_limit: privacy.and_then(|privacy| budget(privacy.limit())),
+            privacy_expended: privacy.and_then(|privacy| budget(privacy.expended())),
+        })
+    }
+
+    /// Assembles the model card of the model `identifier` for `user_id`, who must be allowed
+    /// to either fetch or test the model.
+    fn model_card(&self, identifier: &str, user_id: &str) -> Result<ModelCard, Status> {
+        self.restore(&self.binaries, ArtifactKind::Binary, identifier)?;
+        self.restore(&self.checkpoints, ArtifactKind::CheckPoint, identifier)?;
+
+        let mut card = {
+            let binaries = self.binaries.read().unwrap();
+            let binary = binaries
+                .get(identifier)
+                .ok_or_else(|| Status::not_found("Module not found"))?;
+            let owner = binary.owner.as_deref();
+            binary
+                .license
+                .verify_fetch(user_id, owner)
+                .or_else(|_| binary.license.verify_test(user_id, owner))?;
+
+            let module: Module = tcherror_to_status((&*binary.data.read().unwrap()).try_into())?;
+            let parameters: Vec<_> = module
+                .parameter_shapes()
+                .into_iter()
+                .map(|(name, shape)| ParameterSummary { name, shape })
+                .collect();
+            let total_parameters = parameters
+                .iter()
+                .map(|parameter| parameter.shape.iter().product::<i64>())
+                .sum();
+            let (differentially_private, checkpoints) =
+                match self.checkpoints.read().unwrap().get(identifier) {
+                    Some(artifact) => {
+                        let checkpoint = artifact.data.read().unwrap();
+                        (checkpoint.private, checkpoint.data.len())
+                    }
+                    None => (false, 0),
+                };
+
+            ModelCard {
+                identifier: identifier.to_string(),
+                name: binary.name.clone(),
+                description: binary.description.clone(),
+                owner: binary.owner.clone(),
+                created_at: binary.created_at.map(storage::to_unix_secs),
+                tags: binary.tags.clone().into_iter().collect(),
+                license: binary.license.clone(),
+                parameters,
+                total_parameters,
+                differentially_private,
+                checkpoints,
+                trainings: Vec::new(),
+                final_metrics: self
+                    .metrics_history
+                    .read()
+                    .unwrap()
+                    .get(identifier)
+                    .map(|metrics| metrics.iter().map(MetricSummary::from).collect())
+                    .unwrap_or_default(),
+            }
+        };
+
+        if let Some(store) = &self.run_store {
+            for (run, record) in store.records_of_model(identifier)? {
+                if record.kind != RunKind::Train {
+                    continue;
+                }
+                card.trainings.push(TrainingSummary {
+                    run: run.to_string(),
+                    dataset: self.dataset_summary(&record.dataset)?,
+                    final_metric: store.metrics(run)?.last().map(MetricSummary::from),
+                    user_id: record.user_id,
+                    started_at: record.started_at,
+                    finished_at: record.finished_at,
+                    status: record.status,
+                    config: record.config,
+                });
+            }
+        }
+        Ok(card)
+    }
+
+    /// Streams the module `model`, in `format`, with the weights of its checkpoint at `index`,
+    /// or its latest weights if `None`.
+    async fn stream_module<T: Sync>(
+        &self,
+        request: &Request<T>,
+        model: Option<Reference>,
+        format: i32,
+        index: Option<i32>,
+    ) -> Result<Response<ReceiverStream<Result<Chunk, Status>>>, Status> {
+        let token = self.sess_manager.get_token(request)?;
+
+        let client_info = self.sess_manager.get_client_info(token.clone())?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let encoding = ChunkEncoding::accepted_by(request);
+        let chunk_size = self.chunk_size(request)?;
+        let cancellation = Cancellation::of_request(request)?;
+        let identifier = model
+            .ok_or_else(|| Status::invalid_argument("Invalid model reference"))?
+            .identifier;
+        let format = match ModuleFetchFormat::from_i32(format) {
+            Some(ModuleFetchFormat::Internal) => None,
+            Some(ModuleFetchFormat::Torchscript) => Some(ModuleFormat::TorchScript),
+            Some(ModuleFetchFormat::StateDict) => Some(ModuleFormat::StateDict),
+            Some(ModuleFetchFormat::Safetensors) => Some(ModuleFormat::SafeTensors),
+            None => return Err(Status::invalid_argument("Unknown module format")),
+        };
+        self.restore(&self.checkpoints, ArtifactKind::CheckPoint, &identifier)?;
+        self.restore(&self.binaries, ArtifactKind::Binary, &identifier)?;
+
+        if let Some(format) = format {
+            let (binary, binary_metadata) = {
+                let binaries = self.binaries.read().unwrap();
+                let binary = binaries
+                    .get(&identifier)
+                    .ok_or_else(|| Status::not_found("Module not found!"))?;
+                (Arc::clone(&binary.data), binary.with_data(()))
+            };
+            let chkpt = self
+                .checkpoints
+                .read()
+                .unwrap()
+                .get(&identifier)
+                .map(|chkpt| (Arc::clone(&chkpt.data), chkpt.with_data(())));
+            // Trained weights are fetched under the license of the checkpoint.
+            let (chkpt, metadata) = match chkpt {
+                Some((chkpt, metadata)) => (Some(chkpt), metadata),
+                None if index.is_some() => {
+                    return Err(Status::not_found("Model has no checkpoint"))
+                }
+                None => (None, binary_metadata),
+            };
+            metadata
+                .license
+                .verify_fetch(&user_id, metadata.owner.as_deref())?;
+
+            let mut exported = cancellation
+                .run_blocking(move |_| {
+                    let binary = binary.read().unwrap();
+                    let chkpt = chkpt.as_ref().map(|chkpt| chkpt.read().unwrap());
+                    let weights = match &chkpt {
+                        Some(chkpt) => Some(checkpoint_weights(chkpt, index)?),
+                        None => None,
+                    };
+                    tcherror_to_status(export_module(&binary, weights, format))
+                })
+                .await?;
+            if let Some(recipient) = metadata.license.recipient()? {
+                exported = recipient.seal(exported)?;
+            }
+            let mut artifact = metadata.with_data(SizedObjectsBytes::from(exported));
+            artifact.client_info = Some(client_info);
+            return Ok(stream_data(
+                artifact,
+                chunk_size,
+                "Model".to_string(),
+                encoding,
+                self.signing_key.clone(),
+            )
+            .await);
+        }
+
+        let serialized = {
+            let checkpoints = self.checkpoints.read().unwrap();
+
+            let checkpoint = checkpoints.get(&identifier);
+            match checkpoint {
+                Some(chkpt) => {
+                    let artifact = chkpt;
+                    artifact
+                        .license
+                        .verify_fetch(&user_id, artifact.owner.as_deref())?;
+                    let mut weights =
+                        checkpoint_weights(&artifact.data.read().unwrap(), index)?.to_vec();
+                    if let Some(recipient) = artifact.license.recipient()? {
+                        weights = recipient.seal(weights)?;
+                    }
+
+                    let mut chkpt_bytes = SizedObjectsBytes::new();
+                    chkpt_bytes.append_back(weights);
+
+                    Artifact {
+                        data: Arc::new(RwLock::new(chkpt_bytes)),
+                        name: artifact.name.clone(),
+                        client_info: Some(client_info),
+                        secret: artifact.secret.clone(),
+                        description: artifact.description.clone(),
+                        meta: artifact.meta.clone(),
+                        expires_at: artifact.expires_at,
+                        tags: artifact.tags.clone(),
+                        owner: artifact.owner.clone(),
+                        created_at: artifact.created_at,
+                        license: artifact.license.clone(),
+                    }
+                }
+                None if index.is_some() => {
+                    return Err(Status::not_found("Model has no checkpoint"));
+                }
+                None => {
+                    let binaries = self.binaries.read().unwrap();
+                    let binary = binaries
+                        .get(&identifier)
+                        .ok_or_else(|| Status::not_found("Module not found!"))?;
+                    binary
+                        .license
+                        .verify_fetch(&user_id, binary.owner.as_deref())?;
+                    let module: Module = (&*binary.data.read().unwrap()).try_into().unwrap();
+                    let module = Artifact {
+                        data: Arc::new(RwLock::new(module)),
+                        name: binary.name.clone(),
+                        client_info: Some(client_info),
+                        secret: binary.secret.clone(),
+                        description: binary.description.clone(),
+                        meta: binary.meta.clone(),
+                        expires_at: binary.expires_at,
+                        tags: binary.tags.clone(),
+                        owner: binary.owner.clone(),
+                        created_at: binary.created_at,
+                        license: binary.license.clone(),
+                    };
+                    tcherror_to_status(module.serialize())?
+                }
+            }
+        };
+
+        Ok(stream_data(
+            serialized,
+            chunk_size,
+            "Model".to_string(),
+            encoding,
+            self.signing_key.clone(),
+        )
+        .await)
+    }
+}
+
+/// Applies `update` to the artifact it targets in `store` and returns the updated reference,
+/// or `None` if `store` does not hold the artifact.
+fn update_metadata<T>(
+    store: &RwLock<HashMap<String, Artifact<T>>>,
+    update: &ArtifactMetadataUpdate,
+) -> Option<Reference> {
+    let mut store = store.write().unwrap();
+    let artifact = store.get_mut(&update.identifier)?;
+    if !update.name.is_empty() {
+        artifact.name = update.name.clone();
+    }
+    if !update.description.is_empty() {
+        artifact.description = update.description.clone();
+    }
+    for key in update.removed_tags.iter() {
+        artifact.tags.remove(key);
+    }
+    artifact.tags.extend(update.tags.clone());
+    Some(artifact.reference(&update.identifier))
+}
+
+/// Adds the grant to the license of the artifact `grant.identifier` of `store`, or removes the
+/// grants of its user if `grant.revoke` is set. Returns `None` if `store` has no such artifact.
+///
+/// Only the owner of the artifact may change its grants, unless `user_id` is `None`.
+fn update_grants<T>(
+    store: &RwLock<HashMap<String, Artifact<T>>>,
+    grant: &AccessGrant,
+    user_id: Option<&str>,
+) -> Option<Result<Reference, Status>> {
+    let mut store = store.write().unwrap();
+    let artifact = store.get_mut(&grant.identifier)?;
+    if let Some(user_id) = user_id {
+        if artifact.owner.as_deref() != Some(user_id) {
+            return Some(Err(Status::permission_denied(
+                "Only the owner of an artifact can grant access to it",
+            )));
+        }
+    }
+    let res = if grant.revoke {
+        artifact.license.revoke(&grant.user_id);
+        Ok(())
+    } else {
+        artifact.license.grant(Grant {
+            user_id: grant.user_id.clone(),
+            start: grant.start,
+            end: grant.end,
+        })
+    };
+    Some(res.map(|()| artifact.reference(&grant.identifier)))
+}
+
+/// Returns the references of the artifacts of `store` that match `query`, sorted by identifier.
+///
+/// At most `query.page_size` references are returned (all of them if it is 0), starting after the
+/// identifier given in `query.page_token`. The returned `next_page_token` is empty on the last page.
+fn query_references<T>(
+    store: &RwLock<HashMap<String, Artifact<T>>>,
+    query: &ArtifactQuery,
+) -> References {
+    let created_after = UNIX_EPOCH + Duration::from_secs(query.created_after);
+    let store = store.read().unwrap();
+    let mut matches: Vec<(&String, &Artifact<T>)> = store
+        .iter()
+        .filter(|(identifier, artifact)| {
+            identifier.as_str() > query.page_token.as_str()
+                && artifact.name.contains(&query.name_contains)
+                && query
+                    .tags
+                    .iter()
+                    .all(|(key, value)| artifact.tags.get(key) == Some(value))
+                && (query.owner.is_empty() || artifact.owner.as_ref() == Some(&query.owner))
+                && (query.created_after == 0
+                    || artifact
+                        .created_at
+                        .map(|t| t > created_after)
+                        .unwrap_or(false))
+        })
+        .collect();
+    matches.sort_by(|a, b| a.0.cmp(b.0));
+
+    let page_size = match query.page_size {
+        0 => matches.len(),
+        page_size => page_size as usize,
+    };
+    let next_page_token = if matches.len() > page_size {
+        matches[page_size - 1].0.clone()
+    } else {
+        String::new()
+    };
+    let list = matches
+        .into_iter()
+        .take(page_size)
+        .map(|(identifier, artifact)| artifact.reference(identifier))
+        .collect();
+
+    References {
+        list,
+        next_page_token,
+    }
+}
+
+/// Returns the weights of the checkpoint at `index` of `chkpt`, counted from the oldest
+/// checkpoint kept, or from the latest one when negative, or the latest weights if `None`.
+fn checkpoint_weights(chkpt: &CheckPoint, index: Option<i32>) -> Result<&[u8], Status> {
+    let len = chkpt.data.len() as i64;
+    let position = match index {
+        Some(index) if index < 0 => len + index as i64,
+        Some(index) => index as i64,
+        None => len - 1,
+    };
+    if position < 0 || position >= len {
+        return Err(Status::not_found(format!(
+            "No checkpoint at index {}, the model has {} checkpoints",
+            index.unwrap_or(-1),
+            len
+        )));
+    }
+    Ok(&chkpt.data[position as usize])
+}
+
+/// Appends the identifiers of the expired artifacts of `store` to `expired`.
+fn collect_expired<T>(
+    store: &RwLock<HashMap<String, Artifact<T>>>,
+    kind: ArtifactKind,
+    now: SystemTime,
+    expired: &mut Vec<(ArtifactKind, String)>,
+) {
+    for (identifier, artifact) in store.read().unwrap().iter() {
+        if artifact.is_expired(now) {
+            expired.push((kind, identifier.clone()));
+        }
+    }
+}
+
+/// Returns true if the data of artifact `identifier` is referenced outside of `store`.
+fn is_shared<T>(store: &RwLock<HashMap<String, Artifact<T>>>, identifier: &str) -> bool {
+    store
+        .read()
+        .unwrap()
+        .get(identifier)
+        .map(|artifact| Arc::strong_count(&artifact.data) > 1)
+        .unwrap_or(false)
+}
+
+#[tonic::async_trait]
+impl TorchService for BastionLabTorch {
+    type FetchDatasetStream = ReceiverStream<Result<Chunk, Status>>;
+    type FetchModuleStream = ReceiverStream<Result<Chunk, Status>>;
+    type FetchCheckpointStream = ReceiverStream<Result<Chunk, Status>>;
+    type ExportCheckpointsStream = ReceiverStream<Result<Chunk, Status>>;
+    type SplitTrainStream = ReceiverStream<Result<SplitTrainResponse, Status>>;
+    type StreamMetricsStream = ReceiverStream<Result<MetricUpdate, Status>>;
+
+    async fn send_dataset(
+        &self,
+        request: Request<Streaming<Chunk>>,
+    ) -> Result<Response<RemoteDatasetReference>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let client_info = self.sess_manager.get_client_info(token)?;
+        let cancellation = Cancellation::of_request(&request)?;
+
+        let start_time = Instant::now();
+
+        let artifact: Artifact<SizedObjectsBytes> =
+            self.receive(request, self.max_dataset_upload_size).await?;
+        cancellation.check()?;
+
+        let (dataset_hash, dataset_size) = {
+            let lock = artifact.data.read().unwrap();
+            let data = lock.get();
+            let hash = hex::encode(digest::digest(&digest::SHA256, &data).as_ref());
+            (hash, data.len())
+        };
+
+        // Datasets are keyed by content, license and owner so that uploading the same data
+        // twice with the same terms does not duplicate it.
+        let identifier = upload_identifier(&artifact)?;
+        self.restore(&self.datasets, ArtifactKind::Dataset, &identifier)?;
+        let existing = self
+            .datasets
+            .read()
+            .unwrap()
+            .get(&identifier)
+            .map(|dataset| {
+                self.dataset_reference(identifier.clone(), &dataset.data.read().unwrap())
+            });
+        if let Some(dataset) = existing {
+            self.extend_expiry(
+                &self.datasets,
+                ArtifactKind::Dataset,
+                &identifier,
+                artifact.expires_at,
+            )?;
+            info!("Dataset {} was already uploaded", identifier);
+            return Ok(Response::new(dataset));
+        }
+
+        let dataset: Artifact<Dataset> = cancellation
+            .run_blocking(move |_| tcherror_to_status(artifact.deserialize()))
+            .await?;
+        let name = dataset.name.clone();
+
+        let dataset = self.insert_dataset(identifier, dataset);
+        self.persist(&self.datasets, ArtifactKind::Dataset, &dataset.identifier)?;
+        self.provenance
+            .record(Node::dataset(&dataset.identifier), Vec::new(), "upload");
+
+        let elapsed = start_time.elapsed();
+        info!(
+            "Successfully uploaded Dataset {} in {}ms",
+            dataset.identifier,
+            elapsed.as_millis()
+        );
+
+        telemetry::add_event(
+            TelemetryEventProps::SendDataset {
+                dataset_name: Some(name.clone()),
+                dataset_size,
                 time_taken: elapsed.as_millis() as f64,
                 dataset_hash: Some(dataset_hash.clone()),
             },
@@ -211,6 +1737,125 @@ impl TorchService for BastionLabTorch {
         Ok(Response::new(dataset))
     }
 
+    async fn send_image_dataset(
+        &self,
+        request: Request<Streaming<ImageDatasetChunk>>,
+    ) -> Result<Response<RemoteDatasetReference>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let client_info = self.sess_manager.get_client_info(token.clone())?;
+        let owner = self.sess_manager.get_user_id(token)?;
+        let cancellation = Cancellation::of_request(&request)?;
+
+        let start_time = Instant::now();
+
+        let (images, dataset_size) =
+            receive_images(request.into_inner(), self.max_dataset_upload_size).await?;
+        cancellation.check()?;
+
+        let (width, height, privacy_limit) = (images.width, images.height, images.privacy_limit);
+        let license = License::parse(&images.license)?;
+        let samples = images.samples;
+        let data = cancellation
+            .run_blocking(move |cancellation| {
+                image_dataset(&samples, width, height, privacy_limit, cancellation)
+            })
+            .await?;
+
+        let name = images.name.clone();
+        let dataset = self.insert_dataset_data(
+            data,
+            images.name,
+            images.description,
+            Vec::new(),
+            Some(owner),
+            license,
+        );
+        self.persist(&self.datasets, ArtifactKind::Dataset, &dataset.identifier)?;
+        self.provenance
+            .record(Node::dataset(&dataset.identifier), Vec::new(), "upload");
+
+        let elapsed = start_time.elapsed();
+        info!(
+            "Successfully decoded image Dataset {} in {}ms",
+            dataset.identifier,
+            elapsed.as_millis()
+        );
+
+        telemetry::add_event(
+            TelemetryEventProps::SendDataset {
+                dataset_name: Some(name),
+                dataset_size,
+                time_taken: elapsed.as_millis() as f64,
+                dataset_hash: None,
+            },
+            Some(client_info),
+        );
+
+        Ok(Response::new(dataset))
+    }
+
+    async fn send_audio_dataset(
+        &self,
+        request: Request<Streaming<AudioDatasetChunk>>,
+    ) -> Result<Response<RemoteDatasetReference>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let client_info = self.sess_manager.get_client_info(token.clone())?;
+        let owner = self.sess_manager.get_user_id(token)?;
+        let cancellation = Cancellation::of_request(&request)?;
+
+        let start_time = Instant::now();
+
+        let (recordings, dataset_size) =
+            receive_recordings(request.into_inner(), self.max_dataset_upload_size).await?;
+        cancellation.check()?;
+
+        let (sample_rate, privacy_limit) = (recordings.sample_rate, recordings.privacy_limit);
+        let (samples, features) = (recordings.samples, recordings.features);
+        let data = cancellation
+            .run_blocking(move |cancellation| {
+                audio_dataset(
+                    &samples,
+                    sample_rate,
+                    features.as_ref(),
+                    privacy_limit,
+                    cancellation,
+                )
+            })
+            .await?;
+
+        let name = recordings.name.clone();
+        let dataset = self.insert_dataset_data(
+            data,
+            recordings.name,
+            recordings.description,
+            Vec::new(),
+            Some(owner),
+            License::default(),
+        );
+        self.persist(&self.datasets, ArtifactKind::Dataset, &dataset.identifier)?;
+        self.provenance
+            .record(Node::dataset(&dataset.identifier), Vec::new(), "upload");
+
+        let elapsed = start_time.elapsed();
+        info!(
+            "Successfully decoded audio Dataset {} in {}ms",
+            dataset.identifier,
+            elapsed.as_millis()
+        );
+
+        telemetry::add_event(
+            TelemetryEventProps::SendDataset {
+                dataset_name: Some(name),
+                dataset_size,
+                time_taken: elapsed.as_millis() as f64,
+                dataset_hash: None,
+            },
+            Some(client_info),
+        );
+
+        Ok(Response::new(dataset))
+    }
+
     async fn send_model(
         &self,
         request: Request<Streaming<Chunk>>,
@@ -219,30 +1864,55 @@ impl TorchService for BastionLabTorch {
         let token = self.sess_manager.get_token(&request)?;
 
         let client_info = self.sess_manager.get_client_info(token)?;
-        let artifact: Artifact<SizedObjectsBytes> = unstream_data(request.into_inner()).await?;
+        let artifact: Artifact<SizedObjectsBytes> =
+            self.receive(request, self.max_model_upload_size).await?;
 
         let (model_hash, model_size) = {
             let lock = artifact.data.read().unwrap();
             let data = lock.get();
-            let model_hash = Uuid::new_v4().to_string();
+            let model_hash = hex::encode(digest::digest(&digest::SHA256, &data).as_ref());
             (model_hash, data.len())
         };
 
+        // Binaries are keyed like datasets, see upload_identifier.
+        let identifier = upload_identifier(&artifact)?;
+        self.restore(&self.binaries, ArtifactKind::Binary, &identifier)?;
+        let existing = self
+            .binaries
+            .read()
+            .unwrap()
+            .get(&identifier)
+            .map(|binary| binary.reference(&identifier));
+        if let Some(reference) = existing {
+            self.extend_expiry(
+                &self.binaries,
+                ArtifactKind::Binary,
+                &identifier,
+                artifact.expires_at,
+            )?;
+            info!("Model {} was already uploaded", identifier);
+            return Ok(Response::new(reference));
+        }
+
         let binary = tcherror_to_status(artifact.deserialize())?;
 
         let name = binary.name.clone();
         let description = binary.description.clone();
         let meta = binary.meta.clone();
+        let tags = binary.tags.clone();
 
         self.binaries
             .write()
             .unwrap()
-            .insert(model_hash.clone(), binary);
+            .insert(identifier.clone(), binary);
+        self.persist(&self.binaries, ArtifactKind::Binary, &identifier)?;
+        self.provenance
+            .record(Node::model(&identifier), Vec::new(), "upload");
         let elapsed = start_time.elapsed();
 
         info!(
             "Successfully uploaded Model {} in {}ms",
-            model_hash.clone(),
+            identifier,
             elapsed.as_millis()
         );
 
@@ -256,10 +1926,11 @@ impl TorchService for BastionLabTorch {
             Some(client_info),
         );
         Ok(Response::new(Reference {
-            identifier: format!("{}", model_hash),
+            identifier,
             name,
             description,
             meta,
+            tags,
         }))
     }
 
@@ -267,132 +1938,247 @@ impl TorchService for BastionLabTorch {
         &self,
         request: Request<Reference>,
     ) -> Result<Response<Self::FetchDatasetStream>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let encoding = ChunkEncoding::accepted_by(&request);
+        let chunk_size = self.chunk_size(&request)?;
         let identifier = request.into_inner().identifier;
+        self.restore(&self.datasets, ArtifactKind::Dataset, &identifier)?;
         let serialized = {
             let datasets = self.datasets.read().unwrap();
             let artifact = datasets
                 .get(&identifier)
                 .ok_or(Status::not_found("Dataset not found"))?;
+            artifact
+                .license
+                .verify_fetch(&user_id, artifact.owner.as_deref())?;
             tcherror_to_status(artifact.serialize())?
         };
 
-        Ok(stream_data(serialized, 4_194_285, "Dataset".to_string()).await)
+        Ok(stream_data(
+            serialized,
+            chunk_size,
+            "Dataset".to_string(),
+            encoding,
+            None,
+        )
+        .await)
     }
 
     async fn fetch_module(
         &self,
-        request: Request<Reference>,
+        request: Request<ModuleFetchRequest>,
     ) -> Result<Response<Self::FetchModuleStream>, Status> {
-        let token = self.sess_manager.get_token(&request)?;
+        let ModuleFetchRequest { model, format } = request.get_ref().clone();
+        self.stream_module(&request, model, format, None).await
+    }
 
-        let client_info = self.sess_manager.get_client_info(token)?;
+    async fn fetch_checkpoint(
+        &self,
+        request: Request<CheckpointFetchRequest>,
+    ) -> Result<Response<Self::FetchCheckpointStream>, Status> {
+        let CheckpointFetchRequest {
+            model,
+            index,
+            format,
+        } = request.get_ref().clone();
+        self.stream_module(&request, model, format, Some(index))
+            .await
+    }
+
+    async fn export_checkpoints(
+        &self,
+        request: Request<Reference>,
+    ) -> Result<Response<Self::ExportCheckpointsStream>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let encoding = ChunkEncoding::accepted_by(&request);
+        let chunk_size = self.chunk_size(&request)?;
         let identifier = request.into_inner().identifier;
+        self.restore(&self.checkpoints, ArtifactKind::CheckPoint, &identifier)?;
 
-        let serialized = {
+        let archive = {
             let checkpoints = self.checkpoints.read().unwrap();
-
-            let checkpoint = checkpoints.get(&identifier);
-            match checkpoint {
-                Some(chkpt) => {
-                    let artifact = chkpt;
-                    let checkpoints = &artifact.data.read().unwrap().data;
-                    let last_chkpt = &checkpoints[checkpoints.len() - 1];
-
-                    let mut chkpt_bytes = SizedObjectsBytes::new();
-                    chkpt_bytes.append_back(last_chkpt.clone());
-
-                    Artifact {
-                        data: Arc::new(RwLock::new(chkpt_bytes)),
-                        name: artifact.name.clone(),
-                        client_info: Some(client_info),
-                        secret: artifact.secret.clone(),
-                        description: artifact.description.clone(),
-                        meta: artifact.meta.clone(),
-                    }
-                }
-                None => {
-                    let binaries = self.binaries.read().unwrap();
-                    let binary = binaries
-                        .get(&identifier)
-                        .ok_or_else(|| Status::not_found("Module not found!"))?;
-                    let module: Module = (&*binary.data.read().unwrap()).try_into().unwrap();
-                    let module = Artifact {
-                        data: Arc::new(RwLock::new(module)),
-                        name: binary.name.clone(),
-                        client_info: Some(client_info),
-                        secret: binary.secret.clone(),
-                        description: binary.description.clone(),
-                        meta: binary.meta.clone(),
-                    };
-                    tcherror_to_status(module.serialize())?
-                }
+            let artifact = checkpoints
+                .get(&identifier)
+                .ok_or_else(|| Status::not_found("CheckPoint not found!"))?;
+            artifact
+                .license
+                .verify_fetch(&user_id, artifact.owner.as_deref())?;
+            let metrics = self
+                .metrics_history
+                .read()
+                .unwrap()
+                .get(&identifier)
+                .cloned()
+                .unwrap_or_default();
+            let mut archive = checkpoint_archive(artifact, &metrics)?;
+            if let Some(recipient) = artifact.license.recipient()? {
+                archive = recipient.seal(archive)?;
+            }
+            Artifact {
+                data: Arc::new(RwLock::new(archive.into())),
+                name: format!("{}.tar", artifact.name),
+                description: artifact.description.clone(),
+                secret: artifact.secret.clone(),
+                meta: Vec::new(),
+                client_info: None,
+                expires_at: artifact.expires_at,
+                tags: artifact.tags.clone(),
+                owner: artifact.owner.clone(),
+                created_at: artifact.created_at,
+                license: artifact.license.clone(),
             }
         };
 
-        Ok(stream_data(serialized, 4_194_285, "Model".to_string()).await)
+        Ok(stream_data(
+            archive,
+            chunk_size,
+            "Checkpoint archive".to_string(),
+            encoding,
+            self.signing_key.clone(),
+        )
+        .await)
     }
 
     async fn delete_dataset(&self, request: Request<Reference>) -> Result<Response<Empty>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
         let identifier = request.into_inner().identifier;
-        self.datasets.write().unwrap().remove(&identifier);
+        self.owned_dataset(&identifier, &user_id)?;
+        self.remove_dataset(&identifier);
+        self.unpersist(ArtifactKind::Dataset, &identifier)?;
         Ok(Response::new(Empty {}))
     }
     async fn delete_module(&self, request: Request<Reference>) -> Result<Response<Empty>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
         let identifier = request.into_inner().identifier;
+        self.restore(&self.binaries, ArtifactKind::Binary, &identifier)?;
+        match self.binaries.read().unwrap().get(&identifier) {
+            Some(binary) if binary.owner.as_deref() == Some(user_id.as_str()) => (),
+            _ => return Err(Status::not_found("Module not found")),
+        }
         self.binaries.write().unwrap().remove(&identifier);
         self.checkpoints.write().unwrap().remove(&identifier);
+        self.metrics_history.write().unwrap().remove(&identifier);
+        self.unpersist(ArtifactKind::Binary, &identifier)?;
+        self.unpersist(ArtifactKind::CheckPoint, &identifier)?;
         Ok(Response::new(Empty {}))
     }
 
     async fn train(&self, request: Request<TrainConfig>) -> Result<Response<Reference>, Status> {
         let token = self.sess_manager.get_token(&request)?;
 
-        let client_info = self.sess_manager.get_client_info(token)?;
+        let client_info = self.sess_manager.get_client_info(token.clone())?;
+        let user_id = self.sess_manager.get_user_id(token.clone())?;
+        let cancellation = Cancellation::of_request(&request)?;
         let config = request.into_inner();
+        let private = config.eps >= 0.0;
+        if config.canaries < 0 || config.canaries == 1 {
+            return Err(Status::invalid_argument(
+                "Auditing requires at least two canaries",
+            ));
+        }
+        check_metric(&config.metric, true)?;
+        check_optimizer(&config)?;
+        if config.early_stopping_patience < 0 {
+            return Err(Status::invalid_argument(
+                "Early stopping patience cannot be negative",
+            ));
+        }
+        if !config.early_stopping_metric.is_empty() {
+            check_metric(&config.early_stopping_metric, false)?;
+        }
+        if config.accumulation_steps < 0 {
+            return Err(Status::invalid_argument(
+                "Gradient accumulation steps cannot be negative",
+            ));
+        }
+        if config.max_checkpoints_kept < 0 {
+            return Err(Status::invalid_argument(
+                "The number of checkpoints kept cannot be negative",
+            ));
+        }
+        if !(0.0..1.0).contains(&config.validation_ratio) {
+            return Err(Status::invalid_argument(
+                "The validation ratio must be at least 0 and less than 1",
+            ));
+        }
+        self.check_accepting_runs()?;
+        let experiment = self.run_experiment(&config.experiment, &user_id)?;
 
         let dataset_id = config.dataset.clone();
-        let dataset = {
+        self.restore(&self.datasets, ArtifactKind::Dataset, &dataset_id)?;
+        let (dataset, encrypt_to, dataset_owner) = {
             let datasets = self.datasets.read().unwrap();
             let dataset = datasets
                 .get(&dataset_id)
                 .ok_or(Status::not_found("Dataset not found"))?;
-            Arc::clone(&dataset.data)
+            dataset
+                .license
+                .verify_train(&user_id, dataset.owner.as_deref(), private)?;
+            (
+                Arc::clone(&dataset.data),
+                dataset.license.encrypt_to.clone(),
+                dataset.owner.clone(),
+            )
         };
+        let watermark = self.watermark_of(&dataset_id)?;
         let binary_id = config
             .model
             .clone()
             .ok_or_else(|| Status::invalid_argument("Invalid module reference"))?
             .identifier;
+        self.restore(&self.binaries, ArtifactKind::Binary, &binary_id)?;
+        self.restore(&self.checkpoints, ArtifactKind::CheckPoint, &binary_id)?;
         let device = parse_device(&config.device)?;
+        let reservation = self.devices.reserve(device, config.device_exclusive)?;
 
-        let (binary, chkpt) = {
-            let binaries = self.binaries.read().unwrap();
-            let binary: &Artifact<BinaryModule> = binaries
-                .get(&binary_id)
-                .ok_or_else(|| Status::not_found("Module binary not found"))?;
-            let mut checkpoints = self.checkpoints.write().unwrap();
-            let chkpt = if config.resume {
-                let chkpt = checkpoints
-                    .get(&binary_id)
-                    .ok_or_else(|| Status::not_found("CheckPoint not found!"))?;
-                chkpt
-            } else {
-                let chkpt = Artifact {
-                    data: Arc::new(RwLock::new(CheckPoint::new(config.eps >= 0.0))),
-                    name: binary.name.clone(),
-                    client_info: Some(client_info.clone()),
-                    secret: binary.secret.clone(),
-                    description: binary.description.clone(),
-                    meta: binary.meta.clone(),
-                };
-                checkpoints.insert(binary_id.clone(), chkpt);
-                let chkpt = checkpoints
+        // Shape mismatches would otherwise fail deep inside the training loop. This is
+        // checked before the checkpoint of the model is reset for the training.
+        {
+            let binary = {
+                let binaries = self.binaries.read().unwrap();
+                let binary = binaries
                     .get(&binary_id)
                     .ok_or_else(|| Status::not_found("Module binary not found"))?;
-                chkpt
+                binary
+                    .license
+                    .verify_test(&user_id, binary.owner.as_deref())?;
+                Arc::clone(&binary.data)
             };
-            (Arc::clone(&binary.data), Arc::clone(&chkpt.data))
-        };
+            let dataset = Arc::clone(&dataset);
+            let metric = config.metric.clone();
+            let monitored = config.early_stopping_metric.clone();
+            let nb_canaries = config.canaries as usize;
+            cancellation
+                .run_blocking(move |_| {
+                    let binary = binary.read().unwrap();
+                    let dataset = dataset.read().unwrap();
+                    if nb_canaries > dataset.len() {
+                        return Err(Status::invalid_argument(format!(
+                            "Cannot audit with more canaries than the {} samples of the dataset",
+                            dataset.len()
+                        )));
+                    }
+                    check_shapes(&binary, &dataset, &metric)?;
+                    if !monitored.is_empty() {
+                        check_shapes(&binary, &dataset, &monitored)?;
+                    }
+                    Ok(())
+                })
+                .await?;
+        }
+
+        let (binary, chkpt) = self.training_artifacts(
+            &binary_id,
+            &user_id,
+            private,
+            config.resume,
+            &client_info,
+            encrypt_to,
+        )?;
 
         let identifier = Uuid::new_v4();
         self.runs
@@ -400,7 +2186,108 @@ impl TorchService for BastionLabTorch {
             .unwrap()
             .insert(identifier, Arc::new(RwLock::new(Run::Pending)));
         let run = Arc::clone(self.runs.read().unwrap().get(&identifier).unwrap());
+        let (on_metric, record_outcome) = self.record_run(
+            identifier,
+            RunRecord::new(
+                RunKind::Train,
+                &binary_id,
+                &dataset_id,
+                &user_id,
+                &config.metric,
+            )
+            .with_config(RunConfig::from(&config))
+            .with_experiment(experiment),
+        );
+        if config.resume {
+            self.provenance.record(
+                Node::run(identifier.to_string()),
+                vec![Node::checkpoint(&binary_id)],
+                "training",
+            );
+        }
+        let on_finish = {
+            let torch = self.clone();
+            let binary_id = binary_id.clone();
+            let run = Arc::clone(&run);
+            move |resources: ResourceUsage| {
+                record_outcome(&run.read().unwrap(), resources);
+                if let Run::Ok(m) = &*run.read().unwrap() {
+                    torch
+                        .metrics_history
+                        .write()
+                        .unwrap()
+                        .entry(binary_id.clone())
+                        .or_default()
+                        .push(m.clone());
+                }
+                if let Err(e) =
+                    torch.persist(&torch.checkpoints, ArtifactKind::CheckPoint, &binary_id)
+                {
+                    error!("Could not persist checkpoint {}: {}", binary_id, e);
+                }
+                // Interrupted trainings checkpoint their progress too.
+                torch.provenance.record(
+                    Node::checkpoint(&binary_id),
+                    vec![Node::run(identifier.to_string())],
+                    "training",
+                );
+                torch.cancelled_runs.write().unwrap().remove(&identifier);
+                torch
+                    .sess_manager
+                    .untrack(&SessionResource::Run(identifier.to_string()));
+                torch.active_trainings.fetch_sub(1, Ordering::SeqCst);
+                drop(reservation);
+            }
+        };
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.cancelled_runs
+            .write()
+            .unwrap()
+            .insert(identifier, (user_id.clone(), Arc::clone(&cancelled)));
+        let on_audit = {
+            let leakage_audits = Arc::clone(&self.leakage_audits);
+            let (nb_canaries, eps) = (config.canaries, config.eps);
+            move |score: LeakageScore| {
+                info!(
+                    "Run {} has an empirical epsilon of {} on its canaries",
+                    identifier, score.empirical_epsilon
+                );
+                leakage_audits.write().unwrap().insert(
+                    identifier,
+                    AuditRecord {
+                        audit: LeakageAudit {
+                            auc: score.auc,
+                            empirical_epsilon: score.empirical_epsilon,
+                            nb_canaries,
+                            eps,
+                        },
+                        user_id,
+                        dataset_owner,
+                    },
+                );
+            }
+        };
+        self.sess_manager
+            .track(&token, SessionResource::Run(identifier.to_string()));
+        let interrupt = {
+            let shutting_down = Arc::clone(&self.shutting_down);
+            move || {
+                if shutting_down.load(Ordering::SeqCst) {
+                    Some(Status::unavailable(
+                        "Training interrupted by server shutdown, progress was checkpointed",
+                    ))
+                } else if cancelled.load(Ordering::SeqCst) {
+                    Some(Status::cancelled(
+                        "Training cancelled, progress was checkpointed",
+                    ))
+                } else {
+                    None
+                }
+            }
+        };
+        self.active_trainings.fetch_add(1, Ordering::SeqCst);
         module_train(
+            self.training_pool(),
             binary,
             dataset,
             run,
@@ -410,27 +2297,41 @@ impl TorchService for BastionLabTorch {
             dataset_id,
             Some(client_info),
             chkpt,
+            watermark,
+            interrupt,
+            on_metric,
+            on_audit,
+            on_finish,
         );
         Ok(Response::new(Reference {
             identifier: format!("{}", identifier),
             name: format!("Run #{}", identifier),
             description: String::from(""),
             meta: Vec::new(),
+            tags: HashMap::new(),
         }))
     }
 
     async fn test(&self, request: Request<TestConfig>) -> Result<Response<Reference>, Status> {
         let token = self.sess_manager.get_token(&request)?;
 
-        let client_info = self.sess_manager.get_client_info(token)?;
+        let client_info = self.sess_manager.get_client_info(token.clone())?;
+        let user_id = self.sess_manager.get_user_id(token)?;
         let config = request.into_inner();
+        check_metric(&config.metric, false)?;
+        self.check_accepting_runs()?;
+        let experiment = self.run_experiment(&config.experiment, &user_id)?;
 
         let dataset_id = config.dataset.clone();
+        self.restore(&self.datasets, ArtifactKind::Dataset, &dataset_id)?;
         let dataset = {
             let datasets = self.datasets.read().unwrap();
             let dataset = datasets
                 .get(&dataset_id)
                 .ok_or(Status::not_found("Dataset not found"))?;
+            dataset
+                .license
+                .verify_test(&user_id, dataset.owner.as_deref())?;
             Arc::clone(&dataset.data)
         };
 
@@ -439,7 +2340,10 @@ impl TorchService for BastionLabTorch {
             .clone()
             .ok_or_else(|| Status::invalid_argument("Invalid dataset reference"))?
             .identifier;
+        self.restore(&self.binaries, ArtifactKind::Binary, &module_id)?;
+        self.restore(&self.checkpoints, ArtifactKind::CheckPoint, &module_id)?;
         let device = parse_device(&config.device)?;
+        let reservation = self.devices.reserve(device, config.device_exclusive)?;
         let (module, binary) = {
             let chkpts_store = self.checkpoints.read().unwrap();
             let artifact = chkpts_store
@@ -447,6 +2351,9 @@ impl TorchService for BastionLabTorch {
                 .ok_or_else(|| Status::not_found("Module not found"))?;
             let binaries = self.binaries.read().unwrap();
             let binary = binaries.get(&module_id).unwrap();
+            binary
+                .license
+                .verify_test(&user_id, binary.owner.as_deref())?;
 
             (Arc::clone(&artifact.data), Arc::clone(&binary.data))
         };
@@ -457,7 +2364,31 @@ impl TorchService for BastionLabTorch {
             .unwrap()
             .insert(identifier, Arc::new(RwLock::new(Run::Pending)));
         let run = Arc::clone(self.runs.read().unwrap().get(&identifier).unwrap());
+        let (on_metric, record_outcome) = self.record_run(
+            identifier,
+            RunRecord::new(
+                RunKind::Test,
+                &module_id,
+                &dataset_id,
+                &user_id,
+                &config.metric,
+            )
+            .with_experiment(experiment),
+        );
+        self.provenance.record(
+            Node::run(identifier.to_string()),
+            vec![Node::checkpoint(&module_id)],
+            "test",
+        );
+        let on_finish = {
+            let run = Arc::clone(&run);
+            move |resources: ResourceUsage| {
+                record_outcome(&run.read().unwrap(), resources);
+                drop(reservation);
+            }
+        };
         module_test(
+            self.training_pool(),
             module,
             binary,
             dataset,
@@ -467,53 +2398,38 @@ impl TorchService for BastionLabTorch {
             module_id,
             dataset_id,
             Some(client_info),
+            on_metric,
+            on_finish,
         );
         Ok(Response::new(Reference {
             identifier: format!("{}", identifier),
             name: format!("Run #{}", identifier),
             description: String::from(""),
             meta: Vec::new(),
+            tags: HashMap::new(),
         }))
     }
 
     async fn available_models(
         &self,
-        _request: Request<Empty>,
+        request: Request<ArtifactQuery>,
     ) -> Result<Response<References>, Status> {
-        let list: Vec<Reference> = self
-            .binaries
-            .read()
-            .unwrap()
-            .iter()
-            .map(|(k, v)| Reference {
-                identifier: format!("{}", k),
-                name: v.name.clone(),
-                description: v.description.clone(),
-                meta: v.meta.clone(),
-            })
-            .collect();
-
-        Ok(Response::new(References { list }))
+        self.restore_all(&self.binaries, ArtifactKind::Binary)?;
+        Ok(Response::new(query_references(
+            &self.binaries,
+            request.get_ref(),
+        )))
     }
 
     async fn available_datasets(
         &self,
-        _request: Request<Empty>,
+        request: Request<ArtifactQuery>,
     ) -> Result<Response<References>, Status> {
-        let list: Vec<Reference> = self
-            .datasets
-            .read()
-            .unwrap()
-            .iter()
-            .map(|(k, v)| Reference {
-                identifier: format!("{}", k),
-                name: v.name.clone(),
-                description: v.description.clone(),
-                meta: v.meta.clone(),
-            })
-            .collect();
-
-        Ok(Response::new(References { list }))
+        self.restore_all(&self.datasets, ArtifactKind::Dataset)?;
+        Ok(Response::new(query_references(
+            &self.datasets,
+            request.get_ref(),
+        )))
     }
 
     async fn available_devices(
@@ -528,53 +2444,314 @@ impl TorchService for BastionLabTorch {
             }
         }
 
-        Ok(Response::new(Devices { list }))
+        Ok(Response::new(Devices {
+            list,
+            reserved: self.devices.reserved(),
+        }))
     }
 
     async fn available_optimizers(
         &self,
         _request: Request<Empty>,
     ) -> Result<Response<Optimizers>, Status> {
-        let list = vec!["SGD", "Adam"].iter().map(|v| v.to_string()).collect();
-        Ok(Response::new(Optimizers { list }))
+        Ok(Response::new(Optimizers {
+            list: OPTIMIZERS
+                .iter()
+                .map(|info| info.name.to_string())
+                .collect(),
+            descriptions: OPTIMIZERS
+                .iter()
+                .map(|info| OptimizerDescription {
+                    name: info.name.to_string(),
+                    parameters: info.schema.iter().map(|p| p.name.to_string()).collect(),
+                    schema: info
+                        .schema
+                        .iter()
+                        .map(|p| OptimizerParameterSchema {
+                            name: p.name.to_string(),
+                            kind: match p.kind {
+                                ParameterKind::Float => optimizer_parameter_schema::Kind::Float,
+                                ParameterKind::Bool => optimizer_parameter_schema::Kind::Bool,
+                            } as i32,
+                            default: p.default.map(|value| OptimizerParameter {
+                                value: Some(match value {
+                                    ParameterValue::Float(f) => {
+                                        optimizer_parameter::Value::FloatValue(f)
+                                    }
+                                    ParameterValue::Bool(b) => {
+                                        optimizer_parameter::Value::BoolValue(b)
+                                    }
+                                }),
+                            }),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn available_losses(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<MetricDescriptions>, Status> {
+        Ok(Response::new(metric_descriptions(true)))
+    }
+
+    async fn available_metrics(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<MetricDescriptions>, Status> {
+        Ok(Response::new(metric_descriptions(false)))
     }
 
     async fn get_metric(&self, request: Request<Reference>) -> Result<Response<Metric>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
         let identifier = Uuid::parse_str(&request.into_inner().identifier)
             .map_err(|_| Status::invalid_argument("Invalid run reference"))?;
+        self.check_run_visible(identifier, &user_id)?;
+
+        let run = self.runs.read().unwrap().get(&identifier).cloned();
+        match run {
+            Some(run) => match &*run.read().unwrap() {
+                Run::Pending => Err(Status::out_of_range("Run has not started.")),
+                Run::Ok(m) => Ok(Response::new(m.clone())),
+                Run::Error(e) => Err(Status::internal(e.message())),
+                Run::Cancelled => Err(Status::cancelled("Run was cancelled.")),
+            },
+            // Runs of a previous server process are only known to the run store
+            None => {
+                let store = self
+                    .run_store
+                    .as_ref()
+                    .ok_or_else(|| Status::not_found("Run not found"))?;
+                let record = store
+                    .record(identifier)?
+                    .ok_or_else(|| Status::not_found("Run not found"))?;
+                match (record.status, store.metrics(identifier)?.pop()) {
+                    (RunStatus::Error(message), _) => Err(Status::internal(message)),
+                    (RunStatus::Cancelled, _) => Err(Status::cancelled("Run was cancelled.")),
+                    (_, Some(metric)) => Ok(Response::new(metric)),
+                    (_, None) => Err(Status::out_of_range("Run has not started.")),
+                }
+            }
+        }
+    }
 
-        match &*self
+    async fn stream_metrics(
+        &self,
+        request: Request<Reference>,
+    ) -> Result<Response<Self::StreamMetricsStream>, Status> {
+        let identifier = Uuid::parse_str(&request.into_inner().identifier)
+            .map_err(|_| Status::invalid_argument("Invalid run reference"))?;
+        let run = self
             .runs
             .read()
             .unwrap()
             .get(&identifier)
+            .cloned()
+            .ok_or_else(|| Status::not_found("Run not found"))?;
+        // Subscribed before reading the latest metric, so that none is missed.
+        let receiver = self
+            .metric_streams
+            .read()
             .unwrap()
+            .get(&identifier)
+            .map(|sender| sender.subscribe());
+        let latest = match &*run.read().unwrap() {
+            Run::Ok(m) => Some(m.clone()),
+            _ => None,
+        };
+
+        let (tx, rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            let update = |body| MetricUpdate { body: Some(body) };
+            if let Some(metric) = latest {
+                let body = metric_update::Body::Metric(metric);
+                if tx.send(Ok(update(body))).await.is_err() {
+                    return;
+                }
+            }
+            if let Some(mut receiver) = receiver {
+                loop {
+                    let body = match tokio::time::timeout(METRIC_KEEPALIVE, receiver.recv()).await {
+                        Ok(Ok(metric)) => metric_update::Body::Metric(metric),
+                        // Slow clients skip metrics rather than slowing the run down.
+                        Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+                        Ok(Err(broadcast::error::RecvError::Closed)) => break,
+                        Err(_) => metric_update::Body::Keepalive(Empty {}),
+                    };
+                    // The client stopped listening.
+                    if tx.send(Ok(update(body))).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            let error = match &*run.read().unwrap() {
+                Run::Error(e) => Some(Status::internal(e.message())),
+                Run::Cancelled => Some(Status::cancelled("Run was cancelled.")),
+                _ => None,
+            };
+            if let Some(error) = error {
+                let _ignored = tx.send(Err(error)).await;
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn cancel_run(&self, request: Request<Reference>) -> Result<Response<Empty>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let identifier = Uuid::parse_str(&request.into_inner().identifier)
+            .map_err(|_| Status::invalid_argument("Invalid run reference"))?;
+
+        if !self.runs.read().unwrap().contains_key(&identifier) {
+            return Err(Status::not_found("Run not found"));
+        }
+        let (owner, cancelled) = self
+            .cancelled_runs
             .read()
             .unwrap()
-        {
-            Run::Pending => Err(Status::out_of_range("Run has not started.")),
-            Run::Ok(m) => Ok(Response::new(m.clone())),
-            Run::Error(e) => Err(Status::internal(e.message())),
+            .get(&identifier)
+            .cloned()
+            .ok_or_else(|| Status::failed_precondition("Run is not in progress"))?;
+        if self.sess_manager.auth_enabled() && owner != user_id {
+            return Err(Status::permission_denied(
+                "Only the user who started a run can cancel it",
+            ));
         }
+
+        cancelled.store(true, Ordering::SeqCst);
+        info!(
+            "Cancelling run {} at the request of {}",
+            identifier, user_id
+        );
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn get_run_history(
+        &self,
+        request: Request<Reference>,
+    ) -> Result<Response<RunHistory>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        // Like in list_runs, data owners see every run and other users their own.
+        let is_owner =
+            !self.sess_manager.auth_enabled() || self.sess_manager.verify_if_owner(&user_id)?;
+        let identifier = Uuid::parse_str(&request.into_inner().identifier)
+            .map_err(|_| Status::invalid_argument("Invalid run reference"))?;
+        let store = self.run_store.as_ref().ok_or_else(|| {
+            Status::failed_precondition("Run history requires the run database to be enabled")
+        })?;
+        let record = store
+            .record(identifier)?
+            .filter(|record| is_owner || record.user_id == user_id)
+            .ok_or_else(|| Status::not_found("Run not found"))?;
+        Ok(Response::new(RunHistory {
+            metric: record.metric,
+            epochs: store.epochs(identifier)?,
+            resources: record.resources.map(run_resources),
+        }))
+    }
+
+    async fn list_runs(&self, request: Request<Empty>) -> Result<Response<RunSummaries>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        // Data owners see every run, so that they can attribute the load of the server.
+        let is_owner =
+            !self.sess_manager.auth_enabled() || self.sess_manager.verify_if_owner(&user_id)?;
+
+        let runs = self.runs.read().unwrap().clone();
+        // Runs that have not reported a metric yet are pending.
+        let summarize = |run: Uuid, record: RunRecord, final_metric: Option<Metric>| {
+            let mut summary = run_summary(run, record, final_metric);
+            let pending = runs
+                .get(&run)
+                .map(|state| matches!(*state.read().unwrap(), Run::Pending))
+                .unwrap_or(false);
+            if pending && summary.status == "running" {
+                summary.status = String::from("pending");
+            }
+            summary
+        };
+        let list = match &self.run_store {
+            Some(store) => store
+                .all_records()?
+                .into_iter()
+                .filter(|(_, record)| is_owner || record.user_id == user_id)
+                .map(|(run, record)| Ok(summarize(run, record, store.metrics(run)?.pop())))
+                .collect::<Result<_, Status>>()?,
+            None => {
+                let mut records: Vec<_> = self
+                    .run_records
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .filter(|(_, record)| is_owner || record.user_id == user_id)
+                    .map(|(run, record)| (*run, record.clone()))
+                    .collect();
+                records.sort_by_key(|(_, record)| record.started_at);
+                records
+                    .into_iter()
+                    .map(|(run, record)| {
+                        let final_metric =
+                            runs.get(&run)
+                                .and_then(|state| match &*state.read().unwrap() {
+                                    Run::Ok(metric) => Some(metric.clone()),
+                                    _ => None,
+                                });
+                        summarize(run, record, final_metric)
+                    })
+                    .collect()
+            }
+        };
+        Ok(Response::new(RunSummaries { list }))
+    }
+
+    async fn get_metric_history(
+        &self,
+        request: Request<Reference>,
+    ) -> Result<Response<Metrics>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let identifier = Uuid::parse_str(&request.into_inner().identifier)
+            .map_err(|_| Status::invalid_argument("Invalid run reference"))?;
+        let store = self.run_store.as_ref().ok_or_else(|| {
+            Status::failed_precondition("Metric history requires the run database to be enabled")
+        })?;
+        self.check_run_visible(identifier, &user_id)?;
+        if store.record(identifier)?.is_none() {
+            return Err(Status::not_found("Run not found"));
+        }
+        Ok(Response::new(Metrics {
+            list: store.metrics(identifier)?,
+        }))
     }
 
     async fn send_tensor(
         &self,
         request: Request<Streaming<Chunk>>,
     ) -> Result<Response<Reference>, Status> {
-        let res = unstream_data(request.into_inner()).await?;
+        let token = self.sess_manager.get_token(&request)?;
+        let encoding = ChunkEncoding::of_request(&request)?;
+        let res =
+            unstream_data(request.into_inner(), encoding, self.max_dataset_upload_size).await?;
 
         let tensor = {
             let data = res.data.read().unwrap();
             let data: Tensor = (&*data).try_into().map_err(|e| {
-                Status::aborted(format!(
+                ErrorCode::ConversionFailed.status(format!(
                     "Could not convert SizedObjectBytes into Tensor: {e}"
                 ))
             })?;
             data
         };
 
-        let (_, reference) = self.insert_tensor(Arc::new(Mutex::new(tensor)));
+        let (identifier, reference) = self.insert_tensor(Arc::new(Mutex::new(tensor)));
+        self.provenance
+            .record(Node::array(&identifier), Vec::new(), "upload");
+        self.sess_manager
+            .track(&token, SessionResource::Tensor(identifier));
         Ok(Response::new(reference))
     }
 
@@ -582,30 +2759,120 @@ impl TorchService for BastionLabTorch {
         &self,
         request: Request<UpdateTensor>,
     ) -> Result<Response<Reference>, Status> {
-        let mut tensors = self.tensors.write().unwrap();
-
         let (identifier, dtype) = (&request.get_ref().identifier, &request.get_ref().dtype);
 
-        let tensor = tensors
-            .get_mut(identifier)
-            .ok_or(Status::not_found("Could not find tensor"))?;
-
-        let mut locked_tensor = tensor.lock().unwrap();
+        let kind = get_kind(&dtype)?;
+        let meta = self.tensors.update_tensor(identifier, |tensor| {
+            *tensor = tensor.to_dtype(kind, true, true);
+            Ok(create_tensor_meta(tensor))
+        })?;
 
-        *locked_tensor = locked_tensor.to_dtype(get_kind(&dtype)?, true, true);
-
-        let meta = TensorMetaData {
-            input_dtype: vec![format!("{:?}", locked_tensor.kind())],
-            input_shape: locked_tensor.size(),
-        };
         Ok(Response::new(Reference {
             identifier: identifier.clone(),
             name: String::new(),
             description: String::new(),
             meta: meta.encode_to_vec(),
+            tags: HashMap::new(),
+        }))
+    }
+
+    async fn create_upload(
+        &self,
+        request: Request<Empty>,
+    ) -> Result<Response<UploadStatus>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let owner = self.sess_manager.get_user_id(token)?;
+        let identifier = self.uploads.create(owner);
+        Ok(Response::new(UploadStatus {
+            identifier,
+            offset: 0,
         }))
     }
 
+    async fn get_upload_status(
+        &self,
+        request: Request<UploadReference>,
+    ) -> Result<Response<UploadStatus>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let owner = self.sess_manager.get_user_id(token)?;
+        let identifier = request.into_inner().identifier;
+        let offset = self.uploads.offset(&identifier, &owner)?;
+        Ok(Response::new(UploadStatus { identifier, offset }))
+    }
+
+    async fn update_artifact_metadata(
+        &self,
+        request: Request<ArtifactMetadataUpdate>,
+    ) -> Result<Response<Reference>, Status> {
+        let update = request.into_inner();
+        let identifier = update.identifier.clone();
+        self.restore(&self.binaries, ArtifactKind::Binary, &identifier)?;
+        self.restore(&self.checkpoints, ArtifactKind::CheckPoint, &identifier)?;
+        self.restore(&self.datasets, ArtifactKind::Dataset, &identifier)?;
+
+        let reference = if let Some(reference) = update_metadata(&self.binaries, &update) {
+            self.persist(&self.binaries, ArtifactKind::Binary, &identifier)?;
+            // Checkpoints carry the metadata of the binary they were trained from.
+            if update_metadata(&self.checkpoints, &update).is_some() {
+                self.persist(&self.checkpoints, ArtifactKind::CheckPoint, &identifier)?;
+            }
+            reference
+        } else if let Some(reference) = update_metadata(&self.datasets, &update) {
+            self.persist(&self.datasets, ArtifactKind::Dataset, &identifier)?;
+            reference
+        } else {
+            return Err(Status::not_found("Artifact not found"));
+        };
+
+        info!("Updated metadata of artifact {}", identifier);
+        Ok(Response::new(reference))
+    }
+
+    async fn grant_access(
+        &self,
+        request: Request<AccessGrant>,
+    ) -> Result<Response<Reference>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        // Without authentication, artifacts cannot be told apart by owner.
+        let owner = self.sess_manager.auth_enabled().then_some(user_id.as_str());
+        let grant = request.into_inner();
+        let identifier = grant.identifier.clone();
+        self.restore(&self.binaries, ArtifactKind::Binary, &identifier)?;
+        self.restore(&self.checkpoints, ArtifactKind::CheckPoint, &identifier)?;
+        self.restore(&self.datasets, ArtifactKind::Dataset, &identifier)?;
+
+        let reference = if let Some(reference) = update_grants(&self.binaries, &grant, owner) {
+            let reference = reference?;
+            self.persist(&self.binaries, ArtifactKind::Binary, &identifier)?;
+            // Checkpoints carry the license of the binary they were trained from.
+            if let Some(res) = update_grants(&self.checkpoints, &grant, owner) {
+                res?;
+                self.persist(&self.checkpoints, ArtifactKind::CheckPoint, &identifier)?;
+            }
+            reference
+        } else if let Some(reference) = update_grants(&self.datasets, &grant, owner) {
+            let reference = reference?;
+            self.persist(&self.datasets, ArtifactKind::Dataset, &identifier)?;
+            reference
+        } else {
+            return Err(Status::not_found("Artifact not found"));
+        };
+
+        if grant.revoke {
+            info!(
+                "Revoked the access grants of {} to artifact {}",
+                grant.user_id, identifier
+            );
+        } else {
+            info!(
+                "Granted {} access to artifact {} from {} to {}",
+                grant.user_id, identifier, grant.start, grant.end
+            );
+        }
+        Ok(Response::new(reference))
+    }
+
     async fn conv_to_dataset(
         &self,
         request: Request<RemoteDatasetReference>,
@@ -616,4 +2883,732 @@ impl TorchService for BastionLabTorch {
 
         Ok(Response::new(res))
     }
+
+    async fn create_experiment(
+        &self,
+        request: Request<ExperimentConfig>,
+    ) -> Result<Response<torch_proto::Experiment>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let config = request.into_inner();
+
+        let experiment = Experiment::new(config.name, config.description, &user_id);
+        let identifier = self.experiment_store()?.create_experiment(&experiment)?;
+        info!("Created experiment {}", identifier);
+        Ok(Response::new(experiment.to_proto(identifier)))
+    }
+
+    async fn available_experiments(
+        &self,
+        request: Request<Empty>,
+    ) -> Result<Response<Experiments>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+
+        let list = self
+            .experiment_store()?
+            .experiments_of(&user_id)?
+            .into_iter()
+            .map(|(identifier, experiment)| experiment.to_proto(identifier))
+            .collect();
+        Ok(Response::new(Experiments { list }))
+    }
+
+    async fn get_experiment_runs(
+        &self,
+        request: Request<RunQuery>,
+    ) -> Result<Response<RunSummaries>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+
+        let list = self
+            .experiment_runs(request.get_ref(), &user_id)?
+            .into_iter()
+            .map(|(run, record, metric)| run_summary(run, record, metric))
+            .collect();
+        Ok(Response::new(RunSummaries { list }))
+    }
+
+    async fn get_best_run(
+        &self,
+        request: Request<BestRunQuery>,
+    ) -> Result<Response<RunSummary>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let query = request.into_inner();
+        let runs = query
+            .runs
+            .ok_or_else(|| Status::invalid_argument("Missing run query"))?;
+
+        let (run, record, metric) =
+            best_run(self.experiment_runs(&runs, &user_id)?, query.minimize)
+                .ok_or_else(|| Status::not_found("No successful run matches the query"))?;
+        Ok(Response::new(run_summary(run, record, Some(metric))))
+    }
+
+    async fn get_model_card(
+        &self,
+        request: Request<ModelCardRequest>,
+    ) -> Result<Response<torch_proto::ModelCard>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let request = request.into_inner();
+        let identifier = request
+            .model
+            .ok_or_else(|| Status::invalid_argument("Invalid model reference"))?
+            .identifier;
+
+        let card = self.model_card(&identifier, &user_id)?;
+        let content = match Format::from_i32(request.format) {
+            Some(Format::Json) => serde_json::to_string_pretty(&card)
+                .map_err(|e| Status::internal(format!("Could not serialize model card: {}", e)))?,
+            Some(Format::Markdown) => card.to_markdown(),
+            None => return Err(Status::invalid_argument("Unknown model card format")),
+        };
+        let signature = match &self.signing_key {
+            Some(key) => key.sign(SIGNED_MODEL_CARD_CONTEXT, content.as_bytes())?,
+            None => Vec::new(),
+        };
+        Ok(Response::new(torch_proto::ModelCard { content, signature }))
+    }
+
+    async fn set_watermark(&self, request: Request<Watermark>) -> Result<Response<Empty>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let watermark = request.into_inner();
+        if watermark.epochs <= 0 {
+            return Err(Status::invalid_argument(
+                "Watermarks must be embedded in at least one epoch",
+            ));
+        }
+        if watermark.trigger_set == watermark.dataset {
+            return Err(Status::invalid_argument(
+                "A dataset cannot be its own trigger set",
+            ));
+        }
+
+        self.owned_dataset(&watermark.dataset, &user_id)?;
+        let trigger_set = self.owned_dataset(&watermark.trigger_set, &user_id)?;
+        let test_only = self
+            .datasets
+            .read()
+            .unwrap()
+            .get(&watermark.trigger_set)
+            .map_or(false, |dataset| dataset.license.test_only);
+        // Trigger sets are trained on.
+        if test_only {
+            return Err(Status::invalid_argument(
+                "Evaluation datasets cannot be trigger sets",
+            ));
+        }
+        {
+            let trigger_set = trigger_set.read().unwrap();
+            if trigger_set.len() == 0 {
+                return Err(Status::invalid_argument("Trigger sets must not be empty"));
+            }
+            // Trigger samples are trained on and predicted as is.
+            if trigger_set.privacy_context().limit() != PrivacyBudget::NotPrivate {
+                return Err(Status::invalid_argument(
+                    "Trigger sets must be uploaded without a privacy limit",
+                ));
+            }
+        }
+
+        info!("Set watermark of dataset {}", watermark.dataset);
+        self.watermarks
+            .write()
+            .unwrap()
+            .insert(watermark.dataset.clone(), watermark);
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn verify_watermark(
+        &self,
+        request: Request<WatermarkVerification>,
+    ) -> Result<Response<WatermarkReport>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let cancellation = Cancellation::of_request(&request)?;
+        let verification = request.into_inner();
+
+        self.owned_dataset(&verification.dataset, &user_id)?;
+        let (trigger_set, _) = self
+            .watermark_of(&verification.dataset)?
+            .ok_or_else(|| Status::not_found("Dataset has no watermark"))?;
+
+        let identifier = verification
+            .model
+            .ok_or_else(|| Status::invalid_argument("Invalid model reference"))?
+            .identifier;
+        self.restore(&self.binaries, ArtifactKind::Binary, &identifier)?;
+        self.restore(&self.checkpoints, ArtifactKind::CheckPoint, &identifier)?;
+        let binary = {
+            let binaries = self.binaries.read().unwrap();
+            let binary = binaries
+                .get(&identifier)
+                .ok_or_else(|| Status::not_found("Module not found"))?;
+            binary
+                .license
+                .verify_test(&user_id, binary.owner.as_deref())?;
+            Arc::clone(&binary.data)
+        };
+        let chkpt = self
+            .checkpoints
+            .read()
+            .unwrap()
+            .get(&identifier)
+            .map(|chkpt| Arc::clone(&chkpt.data));
+
+        let report = cancellation
+            .run_blocking(move |_| {
+                let trigger_set = trigger_set.read().unwrap();
+                let chkpt = chkpt.as_ref().map(|chkpt| chkpt.read().unwrap());
+                let trigger_accuracy = tcherror_to_status(trigger_set_accuracy(
+                    &binary.read().unwrap(),
+                    chkpt.as_deref(),
+                    &trigger_set,
+                ))?;
+                Ok(WatermarkReport {
+                    trigger_accuracy,
+                    nb_samples: trigger_set.len() as i32,
+                })
+            })
+            .await?;
+        info!(
+            "Model {} matches the watermark of dataset {} on {:.1}% of the trigger set",
+            identifier,
+            verification.dataset,
+            report.trigger_accuracy * 100.0
+        );
+        Ok(Response::new(report))
+    }
+
+    async fn split_train(
+        &self,
+        request: Request<Streaming<SplitTrainRequest>>,
+    ) -> Result<Response<Self::SplitTrainStream>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let client_info = self.sess_manager.get_client_info(token.clone())?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        self.check_accepting_runs()?;
+        let mut stream = request.into_inner();
+        let config = match stream.next().await {
+            Some(Ok(SplitTrainRequest {
+                message: Some(split_train_request::Message::Config(config)),
+            })) => config,
+            Some(Err(e)) => return Err(e),
+            _ => {
+                return Err(Status::invalid_argument(
+                    "The stream must start with the training configuration",
+                ))
+            }
+        };
+        if config.eps >= 0.0 {
+            return Err(Status::invalid_argument(
+                "Split learning cannot be differentially private",
+            ));
+        }
+        if config.canaries != 0 {
+            return Err(Status::invalid_argument(
+                "Split learning cannot be audited with canaries",
+            ));
+        }
+        if config.batch_size <= 0 {
+            return Err(Status::invalid_argument("Invalid batch size"));
+        }
+        check_optimizer(&config)?;
+        let device = parse_device(&config.device)?;
+        let reservation = self.devices.reserve(device, config.device_exclusive)?;
+
+        let dataset_id = config.dataset.clone();
+        self.restore(&self.datasets, ArtifactKind::Dataset, &dataset_id)?;
+        let (dataset, encrypt_to) = {
+            let datasets = self.datasets.read().unwrap();
+            let dataset = datasets
+                .get(&dataset_id)
+                .ok_or(Status::not_found("Dataset not found"))?;
+            // Activations and labels leave the server, as if the dataset was fetched.
+            let owner = dataset.owner.as_deref();
+            dataset.license.verify_train(&user_id, owner, false)?;
+            dataset.license.verify_fetch(&user_id, owner)?;
+            if dataset.data.read().unwrap().privacy_context().limit() != PrivacyBudget::NotPrivate {
+                return Err(Status::failed_precondition(
+                    "Split learning releases activations and labels and requires a dataset without privacy limit",
+                ));
+            }
+            (
+                Arc::clone(&dataset.data),
+                dataset.license.encrypt_to.clone(),
+            )
+        };
+        let binary_id = config
+            .model
+            .clone()
+            .ok_or_else(|| Status::invalid_argument("Invalid module reference"))?
+            .identifier;
+        self.restore(&self.binaries, ArtifactKind::Binary, &binary_id)?;
+        self.restore(&self.checkpoints, ArtifactKind::CheckPoint, &binary_id)?;
+        let (binary, chkpt) = self.training_artifacts(
+            &binary_id,
+            &user_id,
+            false,
+            config.resume,
+            &client_info,
+            encrypt_to,
+        )?;
+
+        let (tx, rx) = mpsc::channel(1);
+        let (gradients_tx, gradients_rx) = mpsc::channel(1);
+        tokio::spawn(async move {
+            while let Some(message) = stream.next().await {
+                let gradients = match message {
+                    Ok(SplitTrainRequest {
+                        message: Some(split_train_request::Message::Gradients(gradients)),
+                    }) => Ok(gradients),
+                    Ok(_) => Err(Status::invalid_argument(
+                        "Expected the gradients of the last batch",
+                    )),
+                    Err(e) => Err(e),
+                };
+                let failed = gradients.is_err();
+                if gradients_tx.send(gradients).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+        let on_finish = {
+            let torch = self.clone();
+            let tx = tx.clone();
+            let binary_id = binary_id.clone();
+            move |res: Result<(), Status>| {
+                if let Err(e) =
+                    torch.persist(&torch.checkpoints, ArtifactKind::CheckPoint, &binary_id)
+                {
+                    error!("Could not persist checkpoint {}: {}", binary_id, e);
+                }
+                // Split trainings are not runs, so the checkpoint derives from their inputs.
+                torch.provenance.record(
+                    Node::checkpoint(&binary_id),
+                    vec![Node::dataset(&dataset_id), Node::model(&binary_id)],
+                    "split training",
+                );
+                torch.active_trainings.fetch_sub(1, Ordering::SeqCst);
+                drop(reservation);
+                let res = res.map(|()| SplitTrainResponse {
+                    message: Some(split_train_response::Message::Model(Reference {
+                        identifier: binary_id,
+                        name: String::new(),
+                        description: String::new(),
+                        meta: Vec::new(),
+                        tags: HashMap::new(),
+                    })),
+                });
+                // ignore send() error: the client dropped the request
+                let _ignored = tx.blocking_send(res);
+            }
+        };
+        let interrupt = {
+            let shutting_down = Arc::clone(&self.shutting_down);
+            move || {
+                shutting_down.load(Ordering::SeqCst).then(|| {
+                    Status::unavailable(
+                        "Training interrupted by server shutdown, progress was checkpointed",
+                    )
+                })
+            }
+        };
+        self.active_trainings.fetch_add(1, Ordering::SeqCst);
+        module_split_train(
+            self.training_pool(),
+            binary,
+            dataset,
+            chkpt,
+            config,
+            device,
+            tx,
+            gradients_rx,
+            interrupt,
+            on_finish,
+        );
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn get_signing_key(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<SigningPublicKey>, Status> {
+        let key = self
+            .signing_key
+            .as_ref()
+            .ok_or_else(|| Status::unimplemented("This server does not sign artifacts"))?;
+        Ok(Response::new(SigningPublicKey {
+            public_key: key.public_key().to_vec(),
+        }))
+    }
+
+    async fn get_leakage_audit(
+        &self,
+        request: Request<Reference>,
+    ) -> Result<Response<LeakageAudit>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let identifier = Uuid::parse_str(&request.get_ref().identifier)
+            .map_err(|_| Status::invalid_argument("Invalid run reference"))?;
+
+        match self.leakage_audits.read().unwrap().get(&identifier) {
+            Some(record)
+                if record.user_id == user_id
+                    || record.dataset_owner.as_deref() == Some(user_id.as_str()) =>
+            {
+                Ok(Response::new(record.audit.clone()))
+            }
+            _ => Err(Status::not_found(
+                "No leakage audit for this run, it may still be training or have no canaries",
+            )),
+        }
+    }
+
+    async fn preview_dataset(
+        &self,
+        request: Request<DatasetPreviewRequest>,
+    ) -> Result<Response<DatasetPreview>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let cancellation = Cancellation::of_request(&request)?;
+        let config = request.into_inner();
+        if config.nb_classes < 0 {
+            return Err(Status::invalid_argument("Invalid number of classes"));
+        }
+
+        self.restore(&self.datasets, ArtifactKind::Dataset, &config.dataset)?;
+        let dataset = {
+            let datasets = self.datasets.read().unwrap();
+            let dataset = datasets
+                .get(&config.dataset)
+                .ok_or_else(|| Status::not_found("Dataset not found"))?;
+            dataset
+                .license
+                .verify_test(&user_id, dataset.owner.as_deref())?;
+            Arc::clone(&dataset.data)
+        };
+
+        let (eps, nb_classes) = (config.eps, config.nb_classes as usize);
+        let bounds = (config.lower_bound as f64, config.upper_bound as f64);
+        let preview = cancellation
+            .run_blocking(move |_| {
+                tcherror_to_status(dataset.read().unwrap().preview(eps, bounds, nb_classes))
+            })
+            .await?;
+        info!(
+            "Previewed dataset {} with a budget of {}",
+            config.dataset, eps
+        );
+        Ok(Response::new(DatasetPreview {
+            inputs: preview.inputs.iter().map(Into::into).collect(),
+            labels: Some((&preview.labels).into()),
+            label_distribution: preview.label_distribution,
+        }))
+    }
+
+    async fn cache_activations(
+        &self,
+        request: Request<ActivationCacheRequest>,
+    ) -> Result<Response<RemoteDatasetReference>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let cancellation = Cancellation::of_request(&request)?;
+        let config = request.into_inner();
+        if config.batch_size <= 0 {
+            return Err(Status::invalid_argument("Invalid batch size"));
+        }
+        let device = parse_device(&config.device)?;
+
+        self.restore(&self.datasets, ArtifactKind::Dataset, &config.dataset)?;
+        let (dataset, owner, license) = {
+            let datasets = self.datasets.read().unwrap();
+            let dataset = datasets
+                .get(&config.dataset)
+                .ok_or_else(|| Status::not_found("Dataset not found"))?;
+            dataset
+                .license
+                .verify_test(&user_id, dataset.owner.as_deref())?;
+            (
+                Arc::clone(&dataset.data),
+                dataset.owner.clone(),
+                dataset.license.clone(),
+            )
+        };
+        let binary_id = config
+            .model
+            .ok_or_else(|| Status::invalid_argument("Invalid module reference"))?
+            .identifier;
+        self.restore(&self.binaries, ArtifactKind::Binary, &binary_id)?;
+        self.restore(&self.checkpoints, ArtifactKind::CheckPoint, &binary_id)?;
+        let binary = {
+            let binaries = self.binaries.read().unwrap();
+            let binary = binaries
+                .get(&binary_id)
+                .ok_or_else(|| Status::not_found("Module not found"))?;
+            binary
+                .license
+                .verify_test(&user_id, binary.owner.as_deref())?;
+            Arc::clone(&binary.data)
+        };
+        let chkpt = self
+            .checkpoints
+            .read()
+            .unwrap()
+            .get(&binary_id)
+            .map(|chkpt| Arc::clone(&chkpt.data));
+
+        // The cache is keyed by the weights of the backbone, so that it is computed again
+        // once the backbone is trained further.
+        let mut key = digest::Context::new(&digest::SHA256);
+        key.update(binary_id.as_bytes());
+        key.update(config.dataset.as_bytes());
+        if let Some(chkpt) = &chkpt {
+            if let Some(last_chkpt) = chkpt.read().unwrap().data.last() {
+                key.update(last_chkpt);
+            }
+        }
+        let identifier = hex::encode(key.finish().as_ref());
+        let mut sources = vec![Node::dataset(&config.dataset), Node::model(&binary_id)];
+        if chkpt.is_some() {
+            sources.push(Node::checkpoint(&binary_id));
+        }
+        let cached = self
+            .datasets
+            .read()
+            .unwrap()
+            .get(&identifier)
+            .map(|dataset| {
+                self.dataset_reference(identifier.clone(), &dataset.data.read().unwrap())
+            });
+        if let Some(reference) = cached {
+            info!("Activations {} were already cached", identifier);
+            return Ok(Response::new(reference));
+        }
+
+        let batch_size = config.batch_size as usize;
+        // The device is shared with the runs using it, but not with a run reserving it.
+        let reservation = self.devices.reserve(device, false)?;
+        let activations = cancellation
+            .run_blocking(move |_| {
+                let chkpt = chkpt.as_ref().map(|chkpt| chkpt.read().unwrap());
+                tcherror_to_status(backbone_activations(
+                    &binary.read().unwrap(),
+                    chkpt.as_deref(),
+                    &dataset.read().unwrap(),
+                    batch_size,
+                    device,
+                ))
+            })
+            .await;
+        drop(reservation);
+        let activations = activations?;
+        info!(
+            "Cached activations of model {} on dataset {} as dataset {}",
+            binary_id, config.dataset, identifier
+        );
+        // Derived datasets share the privacy budget of their source, which storage cannot
+        // express, so they are kept in memory only.
+        let reference = self.insert_dataset(
+            identifier,
+            Artifact {
+                data: Arc::new(RwLock::new(activations)),
+                name: format!("Activations of {} on {}", binary_id, config.dataset),
+                description: String::new(),
+                secret: Vec::new(),
+                meta: Vec::new(),
+                client_info: None,
+                expires_at: None,
+                tags: HashMap::new(),
+                owner,
+                created_at: Some(SystemTime::now()),
+                license,
+            },
+        );
+        self.provenance.record(
+            Node::dataset(&reference.identifier),
+            sources,
+            "activation caching",
+        );
+        Ok(Response::new(reference))
+    }
+
+    async fn concat_datasets(
+        &self,
+        request: Request<DatasetConcatRequest>,
+    ) -> Result<Response<RemoteDatasetReference>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let cancellation = Cancellation::of_request(&request)?;
+        let request = request.into_inner();
+        if request.datasets.len() < 2 {
+            return Err(Status::invalid_argument(
+                "At least two datasets must be concatenated",
+            ));
+        }
+        let mut identifiers = HashSet::new();
+        if !request.datasets.iter().all(|id| identifiers.insert(id)) {
+            return Err(Status::invalid_argument(
+                "A dataset cannot be concatenated with itself",
+            ));
+        }
+
+        let mut datasets = Vec::with_capacity(request.datasets.len());
+        let mut sources = Vec::with_capacity(request.datasets.len());
+        for identifier in request.datasets.iter() {
+            self.restore(&self.datasets, ArtifactKind::Dataset, identifier)?;
+            let artifacts = self.datasets.read().unwrap();
+            let dataset = artifacts
+                .get(identifier)
+                .ok_or_else(|| Status::not_found(format!("Dataset {} not found", identifier)))?;
+            dataset
+                .license
+                .verify_test(&user_id, dataset.owner.as_deref())?;
+            datasets.push(Arc::clone(&dataset.data));
+            sources.push((dataset.license.clone(), dataset.owner.clone()));
+        }
+        let license = License::combine(
+            &sources
+                .iter()
+                .map(|(license, owner)| (license, owner.as_deref()))
+                .collect::<Vec<_>>(),
+        )?;
+        let owner = match sources.split_first() {
+            Some(((_, owner), rest)) if rest.iter().all(|(_, other)| other == owner) => {
+                owner.clone()
+            }
+            _ => None,
+        };
+
+        let concatenated = cancellation
+            .run_blocking(move |_| {
+                let guards: Vec<_> = datasets.iter().map(|d| d.read().unwrap()).collect();
+                let datasets: Vec<&Dataset> = guards.iter().map(|d| &**d).collect();
+                match Dataset::concat(&datasets) {
+                    Err(TchError::Shape(message)) => Err(Status::invalid_argument(message)),
+                    res => tcherror_to_status(res),
+                }
+            })
+            .await?;
+        let identifier = Uuid::new_v4().to_string();
+        info!(
+            "Concatenated datasets {} as dataset {}",
+            request.datasets.join(", "),
+            identifier
+        );
+        // Like other derived datasets, concatenations are kept in memory only.
+        let name = if request.name.is_empty() {
+            format!("Concatenation of {}", request.datasets.join(", "))
+        } else {
+            request.name
+        };
+        let reference = self.insert_dataset(
+            identifier,
+            Artifact {
+                data: Arc::new(RwLock::new(concatenated)),
+                name,
+                description: String::new(),
+                secret: Vec::new(),
+                meta: Vec::new(),
+                client_info: None,
+                expires_at: None,
+                tags: HashMap::new(),
+                owner,
+                created_at: Some(SystemTime::now()),
+                license,
+            },
+        );
+        self.provenance.record(
+            Node::dataset(&reference.identifier),
+            request.datasets.iter().map(Node::dataset).collect(),
+            "concatenation",
+        );
+        Ok(Response::new(reference))
+    }
+
+    async fn split_dataset(
+        &self,
+        request: Request<DatasetSplitRequest>,
+    ) -> Result<Response<RemoteDatasetReferences>, Status> {
+        let token = self.sess_manager.get_token(&request)?;
+        let user_id = self.sess_manager.get_user_id(token)?;
+        let cancellation = Cancellation::of_request(&request)?;
+        let request = request.into_inner();
+        let fractions = request.fractions;
+        if fractions.is_empty()
+            || fractions.iter().any(|f| f.is_nan() || *f <= 0.0)
+            || fractions.iter().sum::<f32>() > 1.0 + 1e-6
+        {
+            return Err(Status::invalid_argument(
+                "Fractions must be positive and sum to at most 1",
+            ));
+        }
+
+        self.restore(&self.datasets, ArtifactKind::Dataset, &request.dataset)?;
+        let (dataset, source) = {
+            let datasets = self.datasets.read().unwrap();
+            let dataset = datasets
+                .get(&request.dataset)
+                .ok_or_else(|| Status::not_found("Dataset not found"))?;
+            // Splitting expends the budget of the dataset for every user, so only its owner
+            // and the users allowed to train on it may do it. The parts keep its license,
+            // which their trainings check.
+            if dataset.owner.as_deref() != Some(user_id.as_str()) {
+                dataset
+                    .license
+                    .verify_train(&user_id, dataset.owner.as_deref(), true)?;
+            }
+            (Arc::clone(&dataset.data), dataset.with_data(()))
+        };
+
+        let shuffle = request.shuffle;
+        let parts = cancellation
+            .run_blocking(move |_| {
+                let dataset = dataset.read().unwrap();
+                let nb_samples = dataset.len() as f64;
+                // Parts end at the rounded cumulative fractions, so that they never hold
+                // more samples than the dataset.
+                let mut sizes = Vec::with_capacity(fractions.len());
+                let (mut cumulated, mut end) = (0.0, 0);
+                for fraction in fractions {
+                    cumulated += fraction as f64;
+                    let next = ((cumulated.min(1.0) * nb_samples).round() as usize).max(end);
+                    sizes.push(next - end);
+                    end = next;
+                }
+                if sizes.contains(&0) {
+                    return Err(Status::invalid_argument(
+                        "Every part must hold at least one sample",
+                    ));
+                }
+                tcherror_to_status(dataset.split(&sizes, shuffle))
+            })
+            .await?;
+        info!(
+            "Split dataset {} into {} parts",
+            request.dataset,
+            parts.len()
+        );
+        // Like other derived datasets, parts are kept in memory only.
+        let list = parts
+            .into_iter()
+            .enumerate()
+            .map(|(i, part)| {
+                let mut artifact = source.with_data(part);
+                artifact.name = format!("{} (part {})", source.name, i + 1);
+                artifact.created_at = Some(SystemTime::now());
+                let reference = self.insert_dataset(Uuid::new_v4().to_string(), artifact);
+                self.provenance.record(
+                    Node::dataset(&reference.identifier),
+                    vec![Node::dataset(&request.dataset)],
+                    "split",
+                );
+                reference
+            })
+            .collect();
+        Ok(Response::new(RemoteDatasetReferences { list }))
+    }
 }
@@ -0,0 +1,467 @@
+use bastionlab_common::encryption::RecipientKey;
+use bastionlab_common::session_proto::ErrorCode;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tonic::Status;
+
+/// Users allowed to perform an operation on an artifact.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Rule {
+    /// Any user with a valid session.
+    Anyone,
+    /// Only the user who uploaded the artifact.
+    Owner,
+    /// Only the listed users.
+    UserIds {
+        ids: Vec<String>,
+    },
+    Nobody,
+}
+
+impl Rule {
+    fn allows(&self, user_id: &str, owner: Option<&str>) -> bool {
+        match self {
+            Rule::Anyone => true,
+            Rule::Owner => owner == Some(user_id),
+            Rule::UserIds { ids } => ids.iter().any(|id| id == user_id),
+            Rule::Nobody => false,
+        }
+    }
+
+    /// Returns the rule with `Owner` replaced by the users it designates.
+    fn resolve(&self, owner: Option<&str>) -> Rule {
+        match (self, owner) {
+            (Rule::Owner, Some(owner)) => Rule::UserIds {
+                ids: vec![owner.to_string()],
+            },
+            (Rule::Owner, None) => Rule::Nobody,
+            (rule, _) => rule.clone(),
+        }
+    }
+
+    /// Returns the rule allowing the users both resolved rules allow.
+    fn intersect(&self, other: &Rule) -> Rule {
+        match (self, other) {
+            (Rule::Anyone, rule) | (rule, Rule::Anyone) => rule.clone(),
+            (Rule::UserIds { ids }, Rule::UserIds { ids: others }) => Rule::UserIds {
+                ids: ids
+                    .iter()
+                    .filter(|id| others.contains(id))
+                    .cloned()
+                    .collect(),
+            },
+            _ => Rule::Nobody,
+        }
+    }
+}
+
+/// Access of a user to an artifact, limited to the time window from `start` included to `end`
+/// excluded, in seconds since the Unix epoch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Grant {
+    pub user_id: String,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Grant {
+    fn is_active(&self, now: u64) -> bool {
+        self.start <= now && now < self.end
+    }
+}
+
+/// Usage terms set by the uploader of an artifact, checked on every access.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct License {
+    /// Who may fetch the artifact.
+    pub fetch: Rule,
+    /// Who may train or test models with the artifact.
+    pub train: Rule,
+    /// Whether training with the artifact must be differentially private.
+    #[serde(default)]
+    pub require_dp: bool,
+    /// Whether the artifact is an evaluation dataset, which models may only be tested on.
+    #[serde(default)]
+    pub test_only: bool,
+    /// Hex-encoded DER public key to which fetched checkpoints must be encrypted.
+    #[serde(default)]
+    pub encrypt_to: Option<String>,
+    /// Time windows within which the listed users may fetch, train and test with the artifact,
+    /// as far as the rules allow. Users with grants may not use the artifact outside of them.
+    #[serde(default)]
+    pub grants: Vec<Grant>,
+}
+
+impl Default for License {
+    /// Artifacts uploaded without a license may be used by anyone.
+    fn default() -> Self {
+        License {
+            fetch: Rule::Anyone,
+            train: Rule::Anyone,
+            require_dp: false,
+            test_only: false,
+            encrypt_to: None,
+            grants: Vec::new(),
+        }
+    }
+}
+
+impl License {
+    /// Parses a JSON-encoded license sent by a client. An empty string yields the default license.
+    pub fn parse(license: &str) -> Result<Self, Status> {
+        if license.is_empty() {
+            return Ok(License::default());
+        }
+        let license: License = serde_json::from_str(license)
+            .map_err(|e| Status::invalid_argument(format!("Could not parse license: {}", e)))?;
+        license.recipient()?;
+        Ok(license)
+    }
+
+    /// Returns the key checkpoints must be encrypted to before leaving the server, if any.
+    pub fn recipient(&self) -> Result<Option<RecipientKey>, Status> {
+        self.encrypt_to
+            .as_ref()
+            .map(|key| {
+                let der = hex::decode(key).map_err(|_| {
+                    Status::invalid_argument("Could not parse license: invalid recipient key")
+                })?;
+                RecipientKey::from_der(&der)
+            })
+            .transpose()
+    }
+
+    /// Returns the license of an artifact made from artifacts with the given licenses and
+    /// owners, which only allows what all of them allow.
+    pub fn combine(licenses: &[(&License, Option<&str>)]) -> Result<Self, Status> {
+        let mut combined = License::default();
+        for (license, owner) in licenses {
+            combined.fetch = combined.fetch.intersect(&license.fetch.resolve(*owner));
+            combined.train = combined.train.intersect(&license.train.resolve(*owner));
+            combined.require_dp |= license.require_dp;
+            combined.test_only |= license.test_only;
+            match (&combined.encrypt_to, &license.encrypt_to) {
+                (Some(key), Some(other)) if key != other => {
+                    return Err(Status::invalid_argument(
+                        "Cannot combine artifacts whose licenses encrypt to different keys",
+                    ))
+                }
+                (None, Some(key)) => combined.encrypt_to = Some(key.clone()),
+                _ => (),
+            }
+        }
+        combined.grants = combine_grants(licenses);
+        Ok(combined)
+    }
+
+    /// Adds a grant, keeping the other grants of its user.
+    pub fn grant(&mut self, grant: Grant) -> Result<(), Status> {
+        if grant.end <= grant.start {
+            return Err(Status::invalid_argument(
+                "Access grants must end after they start",
+            ));
+        }
+        self.grants.push(grant);
+        Ok(())
+    }
+
+    /// Removes the grants of `user_id`, who is then only subject to the rules.
+    pub fn revoke(&mut self, user_id: &str) {
+        self.grants.retain(|grant| grant.user_id != user_id);
+    }
+
+    /// Checks `rule` for `user_id`, one of whose grants must also be active at `now` if the
+    /// user has any.
+    fn allows(&self, rule: &Rule, user_id: &str, owner: Option<&str>, now: u64) -> bool {
+        if !rule.allows(user_id, owner) {
+            return false;
+        }
+        let mut grants = self
+            .grants
+            .iter()
+            .filter(|grant| grant.user_id == user_id)
+            .peekable();
+        grants.peek().is_none() || grants.any(|grant| grant.is_active(now))
+    }
+
+    /// Checks that `user_id` may fetch the artifact. Evaluation datasets may only be fetched
+    /// by their owner, so that they cannot be uploaded again to train on.
+    pub fn verify_fetch(&self, user_id: &str, owner: Option<&str>) -> Result<(), Status> {
+        if self.test_only && owner != Some(user_id) {
+            return Err(ErrorCode::PolicyDenied
+                .status("Cannot fetch this artifact: it is reserved for evaluation"));
+        }
+        if !self.allows(&self.fetch, user_id, owner, now()) {
+            return Err(ErrorCode::PolicyDenied
+                .status("Cannot fetch this artifact: operation denied by its license"));
+        }
+        Ok(())
+    }
+
+    /// Checks that `user_id` may train with the artifact, privately if `private` is true.
+    pub fn verify_train(
+        &self,
+        user_id: &str,
+        owner: Option<&str>,
+        private: bool,
+    ) -> Result<(), Status> {
+        self.verify_test(user_id, owner)?;
+        if self.test_only {
+            return Err(ErrorCode::PolicyDenied
+                .status("Cannot train on this artifact: it is reserved for evaluation"));
+        }
+        if self.require_dp && !private {
+            return Err(ErrorCode::PolicyDenied.status(
+                "Cannot train on this artifact: its license requires differential privacy",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn verify_test(&self, user_id: &str, owner: Option<&str>) -> Result<(), Status> {
+        if !self.allows(&self.train, user_id, owner, now()) {
+            return Err(ErrorCode::PolicyDenied
+                .status("Cannot use this artifact: operation denied by its license"));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the grants of an artifact made from artifacts with the given licenses and owners.
+///
+/// Users with grants on some of the artifacts are only allowed where their windows on these
+/// artifacts overlap, the combined rules applying as well.
+fn combine_grants(licenses: &[(&License, Option<&str>)]) -> Vec<Grant> {
+    let mut users: Vec<&str> = licenses
+        .iter()
+        .flat_map(|(license, _)| license.grants.iter().map(|grant| grant.user_id.as_str()))
+        .collect();
+    users.sort();
+    users.dedup();
+
+    let mut grants = Vec::new();
+    for user_id in users {
+        let mut windows = vec![(0, u64::MAX)];
+        for (license, _) in licenses {
+            let own: Vec<_> = license
+                .grants
+                .iter()
+                .filter(|grant| grant.user_id == user_id)
+                .collect();
+            if own.is_empty() {
+                continue;
+            }
+            windows = windows
+                .iter()
+                .flat_map(|&(start, end)| {
+                    own.iter()
+                        .map(move |grant| (start.max(grant.start), end.min(grant.end)))
+                })
+                .filter(|(start, end)| start < end)
+                .collect();
+        }
+        // An empty grant still restricts the user, who is then never allowed.
+        if windows.is_empty() {
+            windows.push((0, 0));
+        }
+        grants.extend(windows.into_iter().map(|(start, end)| Grant {
+            user_id: user_id.to_string(),
+            start,
+            end,
+        }));
+    }
+    grants
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    fn license(fetch: Rule, train: Rule, require_dp: bool) -> License {
+        License {
+            fetch,
+            train,
+            require_dp,
+            test_only: false,
+            encrypt_to: None,
+            grants: Vec::new(),
+        }
+    }
+
+    fn grant(user_id: &str, start: u64, end: u64) -> Grant {
+        Grant {
+            user_id: user_id.to_string(),
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn default_license_allows_everything() {
+        let license = License::parse("").unwrap();
+        assert!(license.verify_fetch("alice", None).is_ok());
+        assert!(license.verify_train("alice", None, false).is_ok());
+    }
+
+    #[test]
+    fn owner_rule_denies_other_users() {
+        let license = license(Rule::Owner, Rule::Owner, false);
+        assert!(license.verify_fetch("alice", Some("alice")).is_ok());
+        let err = license.verify_fetch("bob", Some("alice")).unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+        let err = license
+            .verify_train("bob", Some("alice"), true)
+            .unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+    }
+
+    #[test]
+    fn owner_rule_denies_artifacts_without_owner() {
+        let license = license(Rule::Owner, Rule::Anyone, false);
+        assert!(license.verify_fetch("alice", None).is_err());
+    }
+
+    #[test]
+    fn user_ids_rule_only_allows_listed_users() {
+        let ids = vec![String::from("alice"), String::from("carol")];
+        let license = license(Rule::UserIds { ids }, Rule::Nobody, false);
+        assert!(license.verify_fetch("carol", Some("alice")).is_ok());
+        assert!(license.verify_fetch("bob", Some("alice")).is_err());
+        assert!(license.verify_test("alice", Some("alice")).is_err());
+    }
+
+    #[test]
+    fn require_dp_denies_non_private_training() {
+        let license = license(Rule::Anyone, Rule::Anyone, true);
+        assert!(license.verify_train("bob", None, true).is_ok());
+        let err = license.verify_train("bob", None, false).unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+        assert!(license.verify_test("bob", None).is_ok());
+    }
+
+    #[test]
+    fn test_only_denies_training() {
+        let mut license = license(Rule::Anyone, Rule::Anyone, false);
+        license.test_only = true;
+        let err = license.verify_train("bob", None, true).unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+        assert!(license.verify_test("bob", None).is_ok());
+    }
+
+    #[test]
+    fn test_only_denies_fetch_to_other_users() {
+        let mut license = license(Rule::Anyone, Rule::Anyone, false);
+        license.test_only = true;
+        assert!(license.verify_fetch("alice", Some("alice")).is_ok());
+        let err = license.verify_fetch("bob", Some("alice")).unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+        assert!(license.verify_fetch("bob", None).is_err());
+    }
+
+    #[test]
+    fn combined_licenses_allow_what_all_allow() {
+        let ids = vec![String::from("alice"), String::from("carol")];
+        let a = license(Rule::Owner, Rule::UserIds { ids }, false);
+        let mut b = license(Rule::Anyone, Rule::Anyone, true);
+        b.test_only = true;
+        let combined = License::combine(&[(&a, Some("alice")), (&b, Some("bob"))]).unwrap();
+        assert!(combined.verify_fetch("alice", None).is_ok());
+        assert!(combined.verify_fetch("bob", None).is_err());
+        assert!(combined.verify_test("carol", None).is_ok());
+        assert!(combined.verify_test("bob", None).is_err());
+        assert!(combined.require_dp && combined.test_only);
+
+        let combined = License::combine(&[(&a, Some("alice")), (&a, Some("bob"))]).unwrap();
+        assert!(combined.verify_fetch("alice", None).is_err());
+    }
+
+    #[test]
+    fn grants_only_allow_their_users_within_their_windows() {
+        let ids = vec![String::from("bob"), String::from("carol")];
+        let mut license = license(
+            Rule::UserIds { ids: ids.clone() },
+            Rule::UserIds { ids },
+            false,
+        );
+        let now = now();
+        license.grant(grant("bob", now - 10, now + 10)).unwrap();
+        license.grant(grant("carol", now + 10, now + 20)).unwrap();
+        assert!(license.verify_fetch("bob", Some("alice")).is_ok());
+        assert!(license.verify_test("bob", Some("alice")).is_ok());
+        let err = license.verify_fetch("carol", Some("alice")).unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+
+        license.revoke("bob");
+        assert!(license.verify_fetch("bob", Some("alice")).is_err());
+        let err = license.grant(grant("bob", now, now)).unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
+
+    #[test]
+    fn grants_do_not_bypass_the_rules() {
+        let mut license = license(Rule::Nobody, Rule::Anyone, false);
+        let now = now();
+        license.grant(grant("bob", now - 10, now + 10)).unwrap();
+        let err = license.verify_fetch("bob", Some("alice")).unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+        assert!(license.verify_train("bob", Some("alice"), false).is_ok());
+    }
+
+    #[test]
+    fn expired_grants_deny_users_the_rules_allow() {
+        let mut license = license(Rule::Anyone, Rule::Anyone, false);
+        let now = now();
+        license.grant(grant("bob", now - 20, now - 10)).unwrap();
+        assert!(license.verify_train("bob", None, false).is_err());
+        assert!(license.verify_train("carol", None, false).is_ok());
+    }
+
+    #[test]
+    fn combined_grants_only_allow_overlapping_windows() {
+        let now = now();
+        let mut a = license(Rule::Anyone, Rule::Anyone, false);
+        a.grant(grant("bob", now - 20, now + 5)).unwrap();
+        a.grant(grant("carol", now - 10, now + 10)).unwrap();
+        let mut b = license(Rule::Anyone, Rule::Anyone, false);
+        b.grant(grant("bob", now + 5, now + 20)).unwrap();
+        let combined = License::combine(&[(&a, None), (&b, None)]).unwrap();
+        assert!(combined.verify_fetch("bob", None).is_err());
+        assert!(combined.verify_fetch("carol", None).is_ok());
+        assert!(combined.verify_fetch("dave", None).is_ok());
+
+        let c = license(Rule::Anyone, Rule::Nobody, false);
+        let combined = License::combine(&[(&a, None), (&c, None)]).unwrap();
+        assert!(combined.verify_fetch("carol", None).is_ok());
+        assert!(combined.verify_test("carol", None).is_err());
+    }
+
+    #[test]
+    fn parses_client_licenses() {
+        let license = License::parse(
+            r#"{"fetch": {"type": "Nobody"}, "train": {"type": "UserIds", "ids": ["bob"]}, "require_dp": true}"#,
+        )
+        .unwrap();
+        assert_eq!(license.fetch, Rule::Nobody);
+        assert!(license.require_dp);
+        let err = License::parse("{").unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
+
+    #[test]
+    fn rejects_invalid_recipient_keys() {
+        let err = License::parse(
+            r#"{"fetch": {"type": "Anyone"}, "train": {"type": "Anyone"}, "encrypt_to": "00ff"}"#,
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
+}
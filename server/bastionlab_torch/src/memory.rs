@@ -0,0 +1,98 @@
+use crate::storage::ArtifactKind;
+use std::collections::HashMap;
+
+/// Keeps track of the memory held by artifacts that can be reloaded from storage
+/// and selects the least recently used ones to evict when a memory budget is exceeded.
+///
+/// Sizes are those of the serialized artifacts, which is a good estimate of the memory
+/// they use once deserialized.
+#[derive(Debug)]
+pub struct MemoryAccountant {
+    budget: usize,
+    used: usize,
+    clock: u64,
+    entries: HashMap<(ArtifactKind, String), Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    size: usize,
+    last_used: u64,
+}
+
+impl MemoryAccountant {
+    pub fn new(budget: usize) -> Self {
+        MemoryAccountant {
+            budget,
+            used: 0,
+            clock: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+    }
+
+    /// Records that artifact `identifier` holds `size` bytes and marks it as used.
+    pub fn record(&mut self, kind: ArtifactKind, identifier: &str, size: usize) {
+        self.clock += 1;
+        let entry = Entry {
+            size,
+            last_used: self.clock,
+        };
+        if let Some(old) = self.entries.insert((kind, identifier.to_string()), entry) {
+            self.used -= old.size;
+        }
+        self.used += size;
+    }
+
+    /// Marks artifact `identifier` as used.
+    pub fn touch(&mut self, kind: ArtifactKind, identifier: &str) {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(&(kind, identifier.to_string())) {
+            entry.last_used = self.clock;
+        }
+    }
+
+    /// Stops tracking artifact `identifier`.
+    pub fn forget(&mut self, kind: ArtifactKind, identifier: &str) {
+        if let Some(entry) = self.entries.remove(&(kind, identifier.to_string())) {
+            self.used -= entry.size;
+        }
+    }
+
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Returns the artifacts to evict, least recently used first, to get back under budget.
+    ///
+    /// Artifacts for which `in_use` returns true are never selected. Selected artifacts
+    /// are no longer tracked.
+    pub fn select_evictions(
+        &mut self,
+        in_use: impl Fn(ArtifactKind, &str) -> bool,
+    ) -> Vec<(ArtifactKind, String)> {
+        if self.used <= self.budget {
+            return Vec::new();
+        }
+        let mut candidates: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|((kind, identifier), _)| !in_use(*kind, identifier))
+            .map(|(key, entry)| (entry.last_used, key.clone()))
+            .collect();
+        candidates.sort_by_key(|(last_used, _)| *last_used);
+
+        let mut res = Vec::new();
+        for (_, key) in candidates {
+            if self.used <= self.budget {
+                break;
+            }
+            self.forget(key.0, &key.1);
+            res.push(key);
+        }
+        res
+    }
+}
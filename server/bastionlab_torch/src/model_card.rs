@@ -0,0 +1,198 @@
+use crate::license::License;
+use crate::runs::{RunConfig, RunStatus};
+use crate::torch_proto::Metric;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ParameterSummary {
+    pub name: String,
+    pub shape: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricSummary {
+    pub value: f32,
+    pub uncertainty: f32,
+}
+
+impl From<&Metric> for MetricSummary {
+    fn from(metric: &Metric) -> Self {
+        MetricSummary {
+            value: metric.value,
+            uncertainty: metric.uncertainty,
+        }
+    }
+}
+
+/// What is still known of a dataset a model was trained on.
+#[derive(Debug, Clone, Serialize)]
+pub struct DatasetSummary {
+    pub identifier: String,
+    /// The fields below are unknown when the dataset was deleted.
+    pub name: Option<String>,
+    pub samples: Option<usize>,
+    pub owner: Option<String>,
+    pub license: Option<License>,
+    /// Epsilons, `None` when the dataset is not private.
+    pub privacy_limit: Option<f32>,
+    pub privacy_expended: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrainingSummary {
+    pub run: String,
+    pub dataset: DatasetSummary,
+    pub user_id: String,
+    /// Unix timestamps, in seconds.
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub status: RunStatus,
+    pub config: Option<RunConfig>,
+    pub final_metric: Option<MetricSummary>,
+}
+
+/// Documentation of a model assembled from the metadata stored by the server.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelCard {
+    pub identifier: String,
+    pub name: String,
+    pub description: String,
+    pub owner: Option<String>,
+    pub created_at: Option<u64>,
+    pub tags: BTreeMap<String, String>,
+    pub license: License,
+    pub parameters: Vec<ParameterSummary>,
+    pub total_parameters: i64,
+    /// Whether the weights were trained with DP-SGD.
+    pub differentially_private: bool,
+    pub checkpoints: usize,
+    /// Only known when the run database is enabled.
+    pub trainings: Vec<TrainingSummary>,
+    /// Final metric of every training run since the server started.
+    pub final_metrics: Vec<MetricSummary>,
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map(ToString::to_string)
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+fn budget(eps: Option<f32>) -> String {
+    eps.map(|eps| eps.to_string())
+        .unwrap_or_else(|| String::from("not private"))
+}
+
+impl ModelCard {
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+        // Writing to a `String` cannot fail.
+        let _ = self.write_markdown(&mut md);
+        md
+    }
+
+    fn write_markdown(&self, md: &mut String) -> std::fmt::Result {
+        writeln!(md, "# Model card: {}\n", self.name)?;
+        if !self.description.is_empty() {
+            writeln!(md, "{}\n", self.description)?;
+        }
+        writeln!(md, "- Identifier: `{}`", self.identifier)?;
+        writeln!(md, "- Owner: {}", optional(&self.owner))?;
+        writeln!(md, "- Created at: {}", optional(&self.created_at))?;
+        writeln!(
+            md,
+            "- Differentially private training: {}",
+            self.differentially_private
+        )?;
+        writeln!(md, "- Checkpoints: {}", self.checkpoints)?;
+        for (key, value) in self.tags.iter() {
+            writeln!(md, "- Tag `{}`: {}", key, value)?;
+        }
+
+        writeln!(md, "\n## Usage terms\n")?;
+        writeln!(md, "- Fetch: {:?}", self.license.fetch)?;
+        writeln!(md, "- Train: {:?}", self.license.train)?;
+        writeln!(md, "- Requires DP: {}", self.license.require_dp)?;
+        writeln!(md, "- Encrypted to: {}", optional(&self.license.encrypt_to))?;
+
+        writeln!(md, "\n## Architecture\n")?;
+        writeln!(md, "{} parameters.\n", self.total_parameters)?;
+        writeln!(md, "| Parameter | Shape |\n| --- | --- |")?;
+        for parameter in self.parameters.iter() {
+            writeln!(md, "| `{}` | {:?} |", parameter.name, parameter.shape)?;
+        }
+
+        writeln!(md, "\n## Trainings\n")?;
+        if self.trainings.is_empty() {
+            writeln!(md, "No training recorded.")?;
+        }
+        for training in self.trainings.iter() {
+            let dataset = &training.dataset;
+            writeln!(md, "### Run `{}`\n", training.run)?;
+            writeln!(md, "- User: {}", training.user_id)?;
+            writeln!(md, "- Status: {:?}", training.status)?;
+            writeln!(
+                md,
+                "- Started at: {}, finished at: {}",
+                training.started_at,
+                optional(&training.finished_at)
+            )?;
+            writeln!(
+                md,
+                "- Dataset: `{}` ({}), {} samples, owner: {}",
+                dataset.identifier,
+                optional(&dataset.name),
+                optional(&dataset.samples),
+                optional(&dataset.owner)
+            )?;
+            if dataset.name.is_some() {
+                writeln!(
+                    md,
+                    "- Dataset privacy budget: {} expended of {}",
+                    budget(dataset.privacy_expended),
+                    budget(dataset.privacy_limit)
+                )?;
+            }
+            if let Some(license) = &dataset.license {
+                writeln!(
+                    md,
+                    "- Dataset usage terms: train {:?}, requires DP: {}",
+                    license.train, license.require_dp
+                )?;
+            }
+            if let Some(config) = &training.config {
+                writeln!(
+                    md,
+                    "- Hyperparameters: {} epochs, batch size {}, {} optimizer with learning rate {}, device {}",
+                    config.epochs, config.batch_size, config.optimizer, config.learning_rate, config.device
+                )?;
+                if config.eps >= 0. {
+                    writeln!(
+                        md,
+                        "- DP-SGD: epsilon {}, max gradient norm {}",
+                        config.eps, config.max_grad_norm
+                    )?;
+                }
+            }
+            if let Some(metric) = &training.final_metric {
+                writeln!(
+                    md,
+                    "- Final metric: {} ± {}",
+                    metric.value, metric.uncertainty
+                )?;
+            }
+            writeln!(md)?;
+        }
+
+        if !self.final_metrics.is_empty() {
+            writeln!(md, "\n## Final metrics since the server started\n")?;
+            for metric in self.final_metrics.iter() {
+                writeln!(md, "- {} ± {}", metric.value, metric.uncertainty)?;
+            }
+        }
+        Ok(())
+    }
+}
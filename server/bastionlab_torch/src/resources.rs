@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Resources consumed by a run, so that operators can attribute the load of the server
+/// to users and runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// CPU time of the thread driving the run. Work that libtorch spreads over its own
+    /// thread pool is not included.
+    pub cpu_time_ms: u64,
+    /// Bytes of samples loaded by the run, counted again at every epoch.
+    pub bytes_read: u64,
+}
+
+/// Measures the resources used by a run. It must be created and finished on the thread
+/// running it.
+#[derive(Debug)]
+pub struct ResourceMeter {
+    cpu_start: Duration,
+    bytes_read: u64,
+}
+
+impl ResourceMeter {
+    pub fn start() -> Self {
+        ResourceMeter {
+            cpu_start: thread_cpu_time(),
+            bytes_read: 0,
+        }
+    }
+
+    pub fn add_read(&mut self, bytes: u64) {
+        self.bytes_read += bytes;
+    }
+
+    pub fn finish(&self) -> ResourceUsage {
+        ResourceUsage {
+            cpu_time_ms: thread_cpu_time().saturating_sub(self.cpu_start).as_millis() as u64,
+            bytes_read: self.bytes_read,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn rusage(who: libc::c_int) -> Option<libc::rusage> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // Safety: getrusage only writes to `usage`, which is initialized when it succeeds.
+    unsafe {
+        if libc::getrusage(who, usage.as_mut_ptr()) == 0 {
+            Some(usage.assume_init())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn thread_cpu_time() -> Duration {
+    rusage(libc::RUSAGE_THREAD)
+        .map(|usage| {
+            let time = |t: libc::timeval| {
+                Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+            };
+            time(usage.ru_utime) + time(usage.ru_stime)
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn thread_cpu_time() -> Duration {
+    Duration::ZERO
+}
@@ -0,0 +1,453 @@
+use crate::experiments::Experiment;
+use crate::learning::{optimizer_choice, Run};
+use crate::resources::ResourceUsage;
+use crate::storage::to_unix_secs;
+use crate::torch_proto::{EpochSummary, Metric, TrainConfig};
+use bastionlab_common::encryption::AtRestKey;
+use prost::Message;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::path::Path;
+use std::time::SystemTime;
+use tonic::Status;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RunKind {
+    Train,
+    Test,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RunStatus {
+    Running,
+    Ok,
+    Error(String),
+    Cancelled,
+}
+
+/// Hyperparameters of a training run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunConfig {
+    pub batch_size: i32,
+    pub epochs: i32,
+    pub metric: String,
+    pub device: String,
+    /// Negative when the training is not differentially private.
+    pub eps: f32,
+    pub max_grad_norm: f32,
+    pub optimizer: String,
+    pub learning_rate: f32,
+}
+
+impl From<&TrainConfig> for RunConfig {
+    fn from(config: &TrainConfig) -> Self {
+        let (optimizer, learning_rate) = match optimizer_choice(config) {
+            Ok((info, values)) => (
+                info.name,
+                values.float("learning_rate").unwrap_or(0.) as f32,
+            ),
+            Err(_) => ("", 0.),
+        };
+        RunConfig {
+            batch_size: config.batch_size,
+            epochs: config.epochs,
+            metric: config.metric.clone(),
+            device: config.device.clone(),
+            eps: config.eps,
+            max_grad_norm: config.max_grad_norm,
+            optimizer: optimizer.to_string(),
+            learning_rate,
+        }
+    }
+}
+
+/// Metadata of a training or testing run, kept after the run is over.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub kind: RunKind,
+    pub model: String,
+    pub dataset: String,
+    pub user_id: String,
+    /// Unix timestamps, in seconds.
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub status: RunStatus,
+    /// Name of the metric reported by the run.
+    #[serde(default)]
+    pub metric: String,
+    /// Only set for trainings.
+    #[serde(default)]
+    pub config: Option<RunConfig>,
+    #[serde(default)]
+    pub experiment: Option<Uuid>,
+    /// Set once the run is over.
+    #[serde(default)]
+    pub resources: Option<ResourceUsage>,
+}
+
+impl RunRecord {
+    pub fn new(kind: RunKind, model: &str, dataset: &str, user_id: &str, metric: &str) -> Self {
+        RunRecord {
+            kind,
+            model: model.to_string(),
+            dataset: dataset.to_string(),
+            user_id: user_id.to_string(),
+            started_at: to_unix_secs(SystemTime::now()),
+            finished_at: None,
+            status: RunStatus::Running,
+            metric: metric.to_string(),
+            config: None,
+            experiment: None,
+            resources: None,
+        }
+    }
+
+    pub fn with_config(mut self, config: RunConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Records the end of the run with `outcome`.
+    pub fn finish(&mut self, outcome: &Run, resources: ResourceUsage) {
+        self.finished_at = Some(to_unix_secs(SystemTime::now()));
+        self.status = match outcome {
+            Run::Ok(_) => RunStatus::Ok,
+            Run::Error(e) => RunStatus::Error(e.message().to_string()),
+            Run::Pending => RunStatus::Error(String::from("Run did not produce any metric")),
+            Run::Cancelled => RunStatus::Cancelled,
+        };
+        self.resources = Some(resources);
+    }
+
+    pub fn with_experiment(mut self, experiment: Option<Uuid>) -> Self {
+        self.experiment = experiment;
+        self
+    }
+}
+
+/// Embedded database holding the records and the full metric time series of runs,
+/// so that they can be queried after the run is over or the server restarted.
+///
+/// Metrics are keyed by run identifier followed by a big-endian id from the database's
+/// monotonic counter, which keeps the series of a run contiguous and in order.
+///
+/// Summaries of the epochs of trainings are keyed by run identifier followed by the
+/// big-endian epoch.
+///
+/// The experiments grouping runs are kept in the same database.
+///
+/// With an at-rest key, values are encrypted and bound to their tree and key.
+#[derive(Debug, Clone)]
+pub struct RunStore {
+    db: sled::Db,
+    records: sled::Tree,
+    metrics: sled::Tree,
+    epochs: sled::Tree,
+    experiments: sled::Tree,
+    key: Option<AtRestKey>,
+}
+
+fn db_error(err: sled::Error) -> Status {
+    Status::internal(format!("Run database error: {}", err))
+}
+
+impl RunStore {
+    /// Opens the database at `path`, creating it if needed, encrypted with `key` if any.
+    /// Runs that were still going when the server stopped are marked as failed.
+    pub fn open(path: &Path, key: Option<AtRestKey>) -> Result<Self, Status> {
+        Self::from_db(sled::open(path).map_err(db_error)?, key)
+    }
+
+    fn from_db(db: sled::Db, key: Option<AtRestKey>) -> Result<Self, Status> {
+        let store = RunStore {
+            records: db.open_tree("records").map_err(db_error)?,
+            metrics: db.open_tree("metrics").map_err(db_error)?,
+            epochs: db.open_tree("epochs").map_err(db_error)?,
+            experiments: db.open_tree("experiments").map_err(db_error)?,
+            db,
+            key,
+        };
+        for entry in store.records.iter() {
+            let (key, value) = entry.map_err(db_error)?;
+            let mut record: RunRecord =
+                parse_record(&store.open_value(&store.records, &key, &value)?)?;
+            if record.status == RunStatus::Running {
+                record.status =
+                    RunStatus::Error(String::from("Run interrupted by a server restart"));
+                store.insert(&store.records, &key, serialize_record(&record)?)?;
+            }
+        }
+        Ok(store)
+    }
+
+    /// Binds the values of `tree` to their `key`.
+    fn aad(tree: &sled::Tree, key: &[u8]) -> Vec<u8> {
+        let mut aad = tree.name().to_vec();
+        aad.push(b'/');
+        aad.extend_from_slice(key);
+        aad
+    }
+
+    fn insert(&self, tree: &sled::Tree, key: &[u8], value: Vec<u8>) -> Result<(), Status> {
+        let value = match &self.key {
+            Some(at_rest_key) => at_rest_key.seal(&Self::aad(tree, key), value)?,
+            None => value,
+        };
+        tree.insert(key, value).map_err(db_error)?;
+        Ok(())
+    }
+
+    fn open_value(&self, tree: &sled::Tree, key: &[u8], value: &[u8]) -> Result<Vec<u8>, Status> {
+        match &self.key {
+            Some(at_rest_key) => at_rest_key.open(&Self::aad(tree, key), value.to_vec()),
+            None => Ok(value.to_vec()),
+        }
+    }
+
+    fn get(&self, tree: &sled::Tree, key: &[u8]) -> Result<Option<Vec<u8>>, Status> {
+        tree.get(key)
+            .map_err(db_error)?
+            .map(|value| self.open_value(tree, key, &value))
+            .transpose()
+    }
+
+    /// Returns the values of `tree` whose keys start with `prefix`, in the order of the keys.
+    fn scan(&self, tree: &sled::Tree, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Status> {
+        tree.scan_prefix(prefix)
+            .map(|entry| {
+                let (key, value) = entry.map_err(db_error)?;
+                let value = self.open_value(tree, &key, &value)?;
+                Ok((key.to_vec(), value))
+            })
+            .collect()
+    }
+
+    pub fn start(&self, run: Uuid, record: &RunRecord) -> Result<(), Status> {
+        self.insert(&self.records, run.as_bytes(), serialize_record(record)?)
+    }
+
+    pub fn push_metric(&self, run: Uuid, metric: &Metric) -> Result<(), Status> {
+        let seq = self.db.generate_id().map_err(db_error)?;
+        let mut key = run.as_bytes().to_vec();
+        key.extend_from_slice(&seq.to_be_bytes());
+        self.insert(&self.metrics, &key, metric.encode_to_vec())
+    }
+
+    pub fn push_epoch(&self, run: Uuid, summary: &EpochSummary) -> Result<(), Status> {
+        let mut key = run.as_bytes().to_vec();
+        key.extend_from_slice(&(summary.epoch as u32).to_be_bytes());
+        self.insert(&self.epochs, &key, summary.encode_to_vec())
+    }
+
+    /// Records the outcome of `run` and the resources it used.
+    pub fn finish(&self, run: Uuid, outcome: &Run, resources: ResourceUsage) -> Result<(), Status> {
+        let mut record = match self.record(run)? {
+            Some(record) => record,
+            None => return Ok(()),
+        };
+        record.finish(outcome, resources);
+        self.insert(&self.records, run.as_bytes(), serialize_record(&record)?)?;
+        self.records.flush().map_err(db_error)?;
+        Ok(())
+    }
+
+    pub fn record(&self, run: Uuid) -> Result<Option<RunRecord>, Status> {
+        self.get(&self.records, run.as_bytes())?
+            .map(|value| parse_record(&value))
+            .transpose()
+    }
+
+    /// Returns the records of every run, oldest first.
+    pub fn all_records(&self) -> Result<Vec<(Uuid, RunRecord)>, Status> {
+        self.records_where(|_| true)
+    }
+
+    /// Returns the records of the runs of `model`, oldest first.
+    pub fn records_of_model(&self, model: &str) -> Result<Vec<(Uuid, RunRecord)>, Status> {
+        self.records_where(|record| record.model == model)
+    }
+
+    /// Returns the records of the runs of `experiment`, oldest first.
+    pub fn records_of_experiment(
+        &self,
+        experiment: Uuid,
+    ) -> Result<Vec<(Uuid, RunRecord)>, Status> {
+        self.records_where(|record| record.experiment == Some(experiment))
+    }
+
+    fn records_where(
+        &self,
+        filter: impl Fn(&RunRecord) -> bool,
+    ) -> Result<Vec<(Uuid, RunRecord)>, Status> {
+        let mut records = Vec::new();
+        for (key, value) in self.scan(&self.records, &[])? {
+            let record = parse_record(&value)?;
+            if filter(&record) {
+                records.push((parse_uuid(&key)?, record));
+            }
+        }
+        records.sort_by_key(|(_, record)| record.started_at);
+        Ok(records)
+    }
+
+    pub fn create_experiment(&self, experiment: &Experiment) -> Result<Uuid, Status> {
+        let identifier = Uuid::new_v4();
+        self.insert(
+            &self.experiments,
+            identifier.as_bytes(),
+            serialize_record(experiment)?,
+        )?;
+        self.experiments.flush().map_err(db_error)?;
+        Ok(identifier)
+    }
+
+    pub fn experiment(&self, identifier: Uuid) -> Result<Option<Experiment>, Status> {
+        self.get(&self.experiments, identifier.as_bytes())?
+            .map(|value| parse_record(&value))
+            .transpose()
+    }
+
+    /// Returns the experiments of `owner`, oldest first.
+    pub fn experiments_of(&self, owner: &str) -> Result<Vec<(Uuid, Experiment)>, Status> {
+        let mut experiments = Vec::new();
+        for (key, value) in self.scan(&self.experiments, &[])? {
+            let experiment: Experiment = parse_record(&value)?;
+            if experiment.owner == owner {
+                experiments.push((parse_uuid(&key)?, experiment));
+            }
+        }
+        experiments.sort_by_key(|(_, experiment)| experiment.created_at);
+        Ok(experiments)
+    }
+
+    /// Returns every metric reported by `run`, oldest first.
+    pub fn metrics(&self, run: Uuid) -> Result<Vec<Metric>, Status> {
+        self.scan(&self.metrics, run.as_bytes())?
+            .into_iter()
+            .map(|(_, value)| {
+                Metric::decode(&value[..])
+                    .map_err(|e| Status::internal(format!("Could not parse stored metric: {}", e)))
+            })
+            .collect()
+    }
+
+    /// Returns the summaries of the epochs of `run`, in order.
+    pub fn epochs(&self, run: Uuid) -> Result<Vec<EpochSummary>, Status> {
+        self.scan(&self.epochs, run.as_bytes())?
+            .into_iter()
+            .map(|(_, value)| {
+                EpochSummary::decode(&value[..]).map_err(|e| {
+                    Status::internal(format!("Could not parse stored epoch summary: {}", e))
+                })
+            })
+            .collect()
+    }
+}
+
+fn serialize_record<T: Serialize>(record: &T) -> Result<Vec<u8>, Status> {
+    serde_json::to_vec(record)
+        .map_err(|e| Status::internal(format!("Could not serialize run record: {}", e)))
+}
+
+fn parse_record<T: DeserializeOwned>(value: &[u8]) -> Result<T, Status> {
+    serde_json::from_slice(value)
+        .map_err(|e| Status::internal(format!("Could not parse run record: {}", e)))
+}
+
+fn parse_uuid(key: &[u8]) -> Result<Uuid, Status> {
+    Uuid::from_slice(key).map_err(|e| Status::internal(format!("Invalid identifier: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_store(key: Option<AtRestKey>) -> RunStore {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        RunStore::from_db(db, key).unwrap()
+    }
+
+    fn metric(batch: i32) -> Metric {
+        Metric {
+            value: batch as f32 / 10.,
+            batch,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn store_keeps_records_and_metrics_in_order() {
+        let store = temporary_store(None);
+        let (run, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let record = RunRecord::new(RunKind::Train, "model", "dataset", "alice", "loss");
+        store.start(run, &record).unwrap();
+        store
+            .start(
+                other,
+                &RunRecord::new(RunKind::Test, "other", "dataset", "bob", "acc"),
+            )
+            .unwrap();
+        for batch in 0..3 {
+            store.push_metric(run, &metric(batch)).unwrap();
+        }
+        store.push_metric(other, &metric(7)).unwrap();
+
+        assert_eq!(store.record(run).unwrap(), Some(record));
+        let batches: Vec<i32> = store
+            .metrics(run)
+            .unwrap()
+            .iter()
+            .map(|m| m.batch)
+            .collect();
+        assert_eq!(batches, vec![0, 1, 2]);
+        let runs: Vec<Uuid> = store
+            .records_of_model("model")
+            .unwrap()
+            .into_iter()
+            .map(|(run, _)| run)
+            .collect();
+        assert_eq!(runs, vec![run]);
+        assert_eq!(store.record(Uuid::new_v4()).unwrap(), None);
+    }
+
+    #[test]
+    fn reopening_fails_interrupted_runs() {
+        let store = temporary_store(None);
+        let run = Uuid::new_v4();
+        let record = RunRecord::new(RunKind::Train, "model", "dataset", "alice", "loss");
+        store.start(run, &record).unwrap();
+
+        let store = RunStore::from_db(store.db.clone(), None).unwrap();
+        assert!(matches!(
+            store.record(run).unwrap().unwrap().status,
+            RunStatus::Error(_)
+        ));
+    }
+
+    #[test]
+    fn values_are_encrypted_at_rest() {
+        let key = AtRestKey::from_bytes(&[7; 32]).unwrap();
+        let store = temporary_store(Some(key.clone()));
+        let run = Uuid::new_v4();
+        let record = RunRecord::new(RunKind::Train, "model", "dataset", "alice", "loss");
+        store.start(run, &record).unwrap();
+        store.push_metric(run, &metric(1)).unwrap();
+
+        let raw = store.records.get(run.as_bytes()).unwrap().unwrap();
+        assert!(serde_json::from_slice::<RunRecord>(&raw).is_err());
+        assert_eq!(store.record(run).unwrap(), Some(record.clone()));
+        assert_eq!(store.metrics(run).unwrap(), vec![metric(1)]);
+
+        // Values cannot be moved to another key.
+        let other = Uuid::new_v4();
+        store.records.insert(other.as_bytes(), raw).unwrap();
+        assert!(store.record(other).is_err());
+
+        let other_key = AtRestKey::from_bytes(&[8; 32]).unwrap();
+        let reopened = RunStore {
+            key: Some(other_key),
+            ..store
+        };
+        assert!(reopened.record(run).is_err());
+    }
+}
@@ -1,31 +1,43 @@
 use super::Chunk;
+use crate::license::License;
 use crate::storage::Artifact;
+use bastionlab_common::compression::{check_upload_size, ChunkEncoding};
+use bastionlab_common::encryption::{ServerSigningKey, SIGNED_ARTIFACT_CONTEXT};
 use bastionlab_learning::serialization::SizedObjectsBytes;
 use log::info;
-use ring::hmac;
+use ring::digest;
+use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime};
 use tch::Device;
 use tokio::sync::mpsc;
 use tokio_stream::{wrappers::ReceiverStream, StreamExt};
-use tonic::{Response, Status};
+use tonic::{Request, Response, Status};
 
 /// Returns a raw artifact from a stream of chunks received over gRPC.
 ///
 /// This function only parses header data such as the name and description
 /// of the artifact. The actual objects remains in binary format.
+///
+/// The reassembled data is decoded with `encoding`.
 pub async fn unstream_data(
     mut stream: tonic::Streaming<Chunk>,
+    encoding: ChunkEncoding,
+    max_size: Option<usize>,
 ) -> Result<Artifact<SizedObjectsBytes>, Status> {
     let mut data_bytes: Vec<u8> = Vec::new();
     let mut name: String = String::new();
     let mut description: String = String::new();
     let mut secret: Vec<u8> = Vec::new();
     let mut meta: Vec<u8> = Vec::new();
+    let mut sha256 = String::new();
+    let mut ttl_seconds = 0;
+    let mut license = String::new();
 
     let mut first = true;
     while let Some(chunk) = stream.next().await {
         let mut chunk = chunk?;
+        check_upload_size(data_bytes.len() + chunk.data.len(), max_size)?;
         data_bytes.append(&mut chunk.data);
         if first {
             first = false;
@@ -33,36 +45,131 @@ pub async fn unstream_data(
             description = chunk.description;
             secret = chunk.secret;
             meta = chunk.meta;
+            sha256 = chunk.sha256;
+            ttl_seconds = chunk.ttl_seconds;
+            license = chunk.license;
         }
     }
 
+    let data_bytes = encoding.decode(data_bytes, max_size)?;
+    verify_sha256(&data_bytes, &sha256)?;
+    let license = License::parse(&license)?;
+
     Ok(Artifact {
         data: Arc::new(RwLock::new(data_bytes.into())),
         name,
         description,
-        secret: hmac::Key::new(hmac::HMAC_SHA256, &secret),
+        secret,
         meta,
         client_info: Default::default(),
+        expires_at: expiry_from_ttl(ttl_seconds),
+        tags: HashMap::new(),
+        owner: None,
+        created_at: Some(SystemTime::now()),
+        license,
     })
 }
 
+/// Converts the time-to-live sent by a client into an expiry time. A zero TTL means no expiry.
+pub fn expiry_from_ttl(ttl_seconds: u64) -> Option<SystemTime> {
+    if ttl_seconds == 0 {
+        None
+    } else {
+        Some(SystemTime::now() + Duration::from_secs(ttl_seconds))
+    }
+}
+
+/// Checks that `data` hashes to `expected`, a hex-encoded SHA-256. An empty `expected` is not checked.
+pub fn verify_sha256(data: &[u8], expected: &str) -> Result<(), Status> {
+    if expected.is_empty() {
+        return Ok(());
+    }
+    let actual = hex::encode(digest::digest(&digest::SHA256, data));
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(Status::data_loss(format!(
+            "Integrity check failed: expected SHA-256 {} but received data hashes to {}",
+            expected, actual
+        )));
+    }
+    Ok(())
+}
+
+/// Default size of the chunks sent by the server, chosen to fit in gRPC's default 4MB message limit.
+pub const DEFAULT_CHUNK_SIZE: usize = 4_194_285;
+
+/// Request metadata key giving the size of the chunks the client would like to receive.
+pub const CHUNK_SIZE_KEY: &str = "chunk-size";
+
+/// Returns the chunk size requested in the metadata of `request`, if any.
+pub fn requested_chunk_size<T>(request: &Request<T>) -> Result<Option<usize>, Status> {
+    request
+        .metadata()
+        .get(CHUNK_SIZE_KEY)
+        .map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .filter(|&size| size > 0)
+                .ok_or_else(|| Status::invalid_argument("Invalid chunk size"))
+        })
+        .transpose()
+}
+
+/// Number of chunks buffered ahead of the client. Producers wait when it is full, so
+/// that a slow client does not make the server hold the whole artifact in chunks.
+const STREAM_BUFFER_LEN: usize = 4;
+
 /// Converts a raw artifact (a header and a binary object) into a stream of chunks to be sent over gRPC.
+///
+/// The binary object is encoded with `encoding` before being split into chunks. Encoding and
+/// chunking run on the blocking thread pool, concurrently with the transmission of the first chunks.
+///
+/// If a `signing_key` is given, the SHA-256 of the binary object is signed with it.
 pub async fn stream_data(
     artifact: Artifact<SizedObjectsBytes>,
     chunk_size: usize,
     stream_type: String,
+    encoding: ChunkEncoding,
+    signing_key: Option<ServerSigningKey>,
 ) -> Response<ReceiverStream<Result<Chunk, Status>>> {
-    let (tx, rx) = mpsc::channel(4);
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER_LEN);
 
-    let raw_bytes: Vec<u8> = Arc::try_unwrap(artifact.data)
-        .unwrap()
-        .into_inner()
-        .unwrap()
-        .into();
     let start_time = Instant::now();
-    tokio::spawn(async move {
+    tokio::task::spawn_blocking(move || {
+        let raw_bytes: Vec<u8> = match Arc::try_unwrap(artifact.data) {
+            Ok(data) => data.into_inner().unwrap_or_else(|e| e.into_inner()).into(),
+            // The data is still referenced elsewhere, send a copy
+            Err(data) => data.read().unwrap().get().clone(),
+        };
+        // Encoding is costly, skip it if the client already went away
+        if tx.is_closed() {
+            return;
+        }
+        let sha256 = hex::encode(digest::digest(&digest::SHA256, &raw_bytes));
+        let signature = match signing_key
+            .map(|key| key.sign(SIGNED_ARTIFACT_CONTEXT, sha256.as_bytes()))
+            .transpose()
+        {
+            Ok(signature) => signature.unwrap_or_default(),
+            Err(e) => {
+                // ignore send() error: the client dropped the request
+                let _ignored = tx.blocking_send(Err(e));
+                return;
+            }
+        };
+        let raw_bytes = match encoding.encode(raw_bytes) {
+            Ok(raw_bytes) => raw_bytes,
+            Err(e) => {
+                // ignore send() error: the client dropped the request
+                let _ignored = tx.blocking_send(Err(e));
+                return;
+            }
+        };
+        let total_size = raw_bytes.len() as u64;
+
         for (i, bytes) in raw_bytes.chunks(chunk_size).enumerate() {
-            tx.send(Ok(Chunk {
+            let chunk = Chunk {
                 // Chunks always contain one object -> fix this
                 data: bytes.to_vec(),
                 name: if i == 0 {
@@ -81,22 +188,37 @@ pub async fn stream_data(
                 } else {
                     Vec::new()
                 },
-            }))
-            .await
-            .unwrap(); // Fix this
+                offset: (i * chunk_size) as u64,
+                sha256: if i == 0 {
+                    sha256.clone()
+                } else {
+                    String::new()
+                },
+                total_size,
+                ttl_seconds: 0,
+                license: String::new(),
+                signature: if i == 0 {
+                    signature.clone()
+                } else {
+                    Vec::new()
+                },
+            };
+            if let Err(_ignored) = tx.blocking_send(Ok(chunk)) {
+                // the client is not listening anymore
+                return;
+            }
         }
-    });
 
-    info!(
-        "{}",
-        format!(
+        info!(
             "{} fetched successfully in {}ms",
             stream_type,
             start_time.elapsed().as_millis()
-        )
-    );
+        );
+    });
 
-    Response::new(ReceiverStream::new(rx))
+    let mut response = Response::new(ReceiverStream::new(rx));
+    encoding.set_on(&mut response);
+    response
 }
 
 /// Parses a device string and returns a [`tch::Device`] object if the string is a valid device name.
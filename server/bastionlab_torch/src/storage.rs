@@ -1,9 +1,21 @@
+use crate::bastionlab::Reference;
+use crate::license::License;
+use bastionlab_common::encryption::AtRestKey;
 use bastionlab_common::session_proto::ClientInfo;
 use bastionlab_learning::serialization::SizedObjectsBytes;
-use ring::hmac;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::convert::TryInto;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tch::TchError;
+use tonic::Status;
+
+pub mod s3;
 
 /// Stored object with name, description and owner key
 #[derive(Debug)]
@@ -11,9 +23,19 @@ pub struct Artifact<T> {
     pub data: Arc<RwLock<T>>,
     pub name: String,
     pub description: String,
-    pub secret: hmac::Key,
+    /// Key sent by the uploader to authenticate the artifact, empty if none.
+    pub secret: Vec<u8>,
     pub meta: Vec<u8>,
     pub client_info: Option<ClientInfo>,
+    /// Time after which the artifact is deleted by [`crate::BastionLabTorch::reap_expired`].
+    pub expires_at: Option<SystemTime>,
+    /// Free-form key/value tags attached by users.
+    pub tags: HashMap<String, String>,
+    /// Identifier of the user who uploaded the artifact.
+    pub owner: Option<String>,
+    pub created_at: Option<SystemTime>,
+    /// Usage terms checked on every access to the artifact.
+    pub license: License,
 }
 
 impl<T: Default> Default for Artifact<T> {
@@ -21,10 +43,50 @@ impl<T: Default> Default for Artifact<T> {
         Self {
             description: String::default(),
             name: String::default(),
-            secret: hmac::Key::new(ring::hmac::HMAC_SHA256, &[0]),
+            secret: Vec::new(),
             meta: Vec::default(),
             client_info: None,
             data: Arc::default(),
+            expires_at: None,
+            tags: HashMap::new(),
+            owner: None,
+            created_at: None,
+            license: License::default(),
+        }
+    }
+}
+
+impl<T> Artifact<T> {
+    /// Returns true if the artifact has a time-to-live that elapsed at `now`.
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at.map(|t| t <= now).unwrap_or(false)
+    }
+
+    /// Returns a gRPC reference to this artifact.
+    pub fn reference(&self, identifier: &str) -> Reference {
+        Reference {
+            identifier: identifier.to_string(),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            meta: self.meta.clone(),
+            tags: self.tags.clone(),
+        }
+    }
+
+    /// Returns an artifact holding `data` with the same metadata and license as this one.
+    pub fn with_data<U>(&self, data: U) -> Artifact<U> {
+        Artifact {
+            data: Arc::new(RwLock::new(data)),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            secret: self.secret.clone(),
+            meta: self.meta.clone(),
+            client_info: self.client_info.clone(),
+            expires_at: self.expires_at,
+            tags: self.tags.clone(),
+            owner: self.owner.clone(),
+            created_at: self.created_at,
+            license: self.license.clone(),
         }
     }
 }
@@ -55,6 +117,11 @@ where
             secret: self.secret.clone(),
             meta: self.meta.clone(),
             client_info: self.client_info.clone(),
+            expires_at: self.expires_at,
+            tags: self.tags.clone(),
+            owner: self.owner.clone(),
+            created_at: self.created_at,
+            license: self.license.clone(),
         })
     }
 }
@@ -76,6 +143,391 @@ impl Artifact<SizedObjectsBytes> {
             secret: self.secret,
             meta: self.meta,
             client_info: self.client_info,
+            expires_at: self.expires_at,
+            tags: self.tags,
+            owner: self.owner,
+            created_at: self.created_at,
+            license: self.license,
         })
     }
 }
+
+/// The different kinds of artifacts held by the Torch service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArtifactKind {
+    Binary,
+    CheckPoint,
+    Dataset,
+}
+
+impl ArtifactKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArtifactKind::Binary => "binaries",
+            ArtifactKind::CheckPoint => "checkpoints",
+            ArtifactKind::Dataset => "datasets",
+        }
+    }
+}
+
+/// Header of a persisted artifact, stored alongside its serialized data.
+#[derive(Debug, Serialize, Deserialize)]
+struct ArtifactHeader {
+    name: String,
+    description: String,
+    meta: Vec<u8>,
+    // Seconds since the Unix epoch
+    #[serde(default)]
+    expires_at: Option<u64>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+    #[serde(default)]
+    owner: Option<String>,
+    // Seconds since the Unix epoch
+    #[serde(default)]
+    created_at: Option<u64>,
+    #[serde(default)]
+    license: License,
+    /// Sealed with the at-rest key by [`EncryptedStorage`].
+    #[serde(default)]
+    secret: Vec<u8>,
+    /// Protobuf encoding of the [`ClientInfo`] of the uploader.
+    #[serde(default)]
+    client_info: Option<Vec<u8>>,
+}
+
+impl ArtifactHeader {
+    fn new<T>(artifact: &Artifact<T>) -> Self {
+        ArtifactHeader {
+            name: artifact.name.clone(),
+            description: artifact.description.clone(),
+            meta: artifact.meta.clone(),
+            expires_at: artifact.expires_at.map(to_unix_secs),
+            tags: artifact.tags.clone(),
+            owner: artifact.owner.clone(),
+            created_at: artifact.created_at.map(to_unix_secs),
+            license: artifact.license.clone(),
+            secret: artifact.secret.clone(),
+            client_info: artifact.client_info.as_ref().map(|info| {
+                let mut buf = Vec::with_capacity(info.encoded_len());
+                // Encoding into a vector cannot fail.
+                info.encode(&mut buf).unwrap();
+                buf
+            }),
+        }
+    }
+
+    fn to_json(&self) -> Result<Vec<u8>, Status> {
+        serde_json::to_vec(self)
+            .map_err(|e| Status::internal(format!("Could not serialize artifact header: {}", e)))
+    }
+
+    fn from_json(header: &[u8]) -> Result<Self, Status> {
+        serde_json::from_slice(header)
+            .map_err(|e| Status::internal(format!("Could not parse artifact header: {}", e)))
+    }
+
+    fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at.map(from_unix_secs)
+    }
+
+    fn into_artifact(self, data: Vec<u8>) -> Result<Artifact<SizedObjectsBytes>, Status> {
+        let client_info = self
+            .client_info
+            .map(|info| ClientInfo::decode(&info[..]))
+            .transpose()
+            .map_err(|e| {
+                Status::internal(format!("Could not parse artifact client info: {}", e))
+            })?;
+        Ok(Artifact {
+            expires_at: self.expires_at(),
+            created_at: self.created_at.map(from_unix_secs),
+            license: self.license,
+            owner: self.owner,
+            tags: self.tags,
+            data: Arc::new(RwLock::new(data.into())),
+            name: self.name,
+            description: self.description,
+            meta: self.meta,
+            secret: self.secret,
+            client_info,
+        })
+    }
+}
+
+pub(crate) fn to_unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+}
+
+fn from_unix_secs(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// A place where serialized artifacts can be persisted and reloaded from.
+pub trait StorageBackend: Send + Sync {
+    /// Persists `artifact` under `identifier`, replacing any previous version.
+    fn store(
+        &self,
+        kind: ArtifactKind,
+        identifier: &str,
+        artifact: &Artifact<SizedObjectsBytes>,
+    ) -> Result<(), Status>;
+    /// Returns the artifact persisted under `identifier`, if any.
+    fn load(
+        &self,
+        kind: ArtifactKind,
+        identifier: &str,
+    ) -> Result<Option<Artifact<SizedObjectsBytes>>, Status>;
+    /// Removes the artifact persisted under `identifier`. Does nothing if there is none.
+    fn remove(&self, kind: ArtifactKind, identifier: &str) -> Result<(), Status>;
+    /// Lists the identifiers of all persisted artifacts of the given kind.
+    fn list(&self, kind: ArtifactKind) -> Result<Vec<String>, Status>;
+    /// Returns the expiry time of the artifact persisted under `identifier`, without loading its data.
+    fn expiry(&self, kind: ArtifactKind, identifier: &str) -> Result<Option<SystemTime>, Status>;
+}
+
+/// Stores artifacts on the local filesystem under a root directory.
+///
+/// Each artifact is stored as two files in `<root>/<kind>/`: `<identifier>.bin`
+/// contains the serialized object and `<identifier>.json` its header.
+#[derive(Debug)]
+pub struct FsStorage {
+    root: PathBuf,
+}
+
+impl FsStorage {
+    pub fn new(root: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let root = root.into();
+        for kind in [
+            ArtifactKind::Binary,
+            ArtifactKind::CheckPoint,
+            ArtifactKind::Dataset,
+        ] {
+            fs::create_dir_all(root.join(kind.as_str()))?;
+        }
+        Ok(FsStorage { root })
+    }
+
+    fn path(
+        &self,
+        kind: ArtifactKind,
+        identifier: &str,
+        extension: &str,
+    ) -> Result<PathBuf, Status> {
+        check_identifier(identifier)?;
+        Ok(self
+            .root
+            .join(kind.as_str())
+            .join(format!("{}.{}", identifier, extension)))
+    }
+
+    fn header(
+        &self,
+        kind: ArtifactKind,
+        identifier: &str,
+    ) -> Result<Option<ArtifactHeader>, Status> {
+        match fs::read(self.path(kind, identifier, "json")?) {
+            Ok(header) => Ok(Some(ArtifactHeader::from_json(&header)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(io_to_status(e)),
+        }
+    }
+}
+
+/// Rejects identifiers that could escape the storage location, such as `../x`.
+fn check_identifier(identifier: &str) -> Result<(), Status> {
+    if identifier.is_empty()
+        || !identifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(Status::invalid_argument(format!(
+            "Invalid artifact identifier: {}",
+            identifier
+        )));
+    }
+    Ok(())
+}
+
+fn io_to_status(err: std::io::Error) -> Status {
+    Status::internal(format!("Storage error: {}", err))
+}
+
+impl StorageBackend for FsStorage {
+    fn store(
+        &self,
+        kind: ArtifactKind,
+        identifier: &str,
+        artifact: &Artifact<SizedObjectsBytes>,
+    ) -> Result<(), Status> {
+        let header = ArtifactHeader::new(artifact).to_json()?;
+
+        fs::write(
+            self.path(kind, identifier, "bin")?,
+            artifact.data.read().unwrap().get(),
+        )
+        .map_err(io_to_status)?;
+        // The header is written last so that an interrupted write never yields a listed artifact.
+        fs::write(self.path(kind, identifier, "json")?, header).map_err(io_to_status)?;
+        Ok(())
+    }
+
+    fn load(
+        &self,
+        kind: ArtifactKind,
+        identifier: &str,
+    ) -> Result<Option<Artifact<SizedObjectsBytes>>, Status> {
+        let header = match self.header(kind, identifier)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let data = fs::read(self.path(kind, identifier, "bin")?).map_err(io_to_status)?;
+        Ok(Some(header.into_artifact(data)?))
+    }
+
+    fn remove(&self, kind: ArtifactKind, identifier: &str) -> Result<(), Status> {
+        for extension in ["json", "bin"] {
+            match fs::remove_file(self.path(kind, identifier, extension)?) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(io_to_status(e)),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn list(&self, kind: ArtifactKind) -> Result<Vec<String>, Status> {
+        let mut res = Vec::new();
+        for entry in fs::read_dir(self.root.join(kind.as_str())).map_err(io_to_status)? {
+            let path = entry.map_err(io_to_status)?.path();
+            if path.extension().map(|ext| ext == "json").unwrap_or(false) {
+                if let Some(identifier) = path.file_stem().and_then(|s| s.to_str()) {
+                    res.push(identifier.to_string());
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    fn expiry(&self, kind: ArtifactKind, identifier: &str) -> Result<Option<SystemTime>, Status> {
+        Ok(self
+            .header(kind, identifier)?
+            .and_then(|header| header.expires_at()))
+    }
+}
+
+/// Wraps another [`StorageBackend`] and encrypts the data of the artifacts it stores.
+///
+/// Artifact headers (name, description, metadata) are stored as is, apart from the secret
+/// of the artifact which is encrypted too.
+pub struct EncryptedStorage {
+    inner: Arc<dyn StorageBackend>,
+    key: AtRestKey,
+}
+
+impl EncryptedStorage {
+    pub fn new(inner: Arc<dyn StorageBackend>, key: AtRestKey) -> Self {
+        EncryptedStorage { inner, key }
+    }
+
+    fn aad(kind: ArtifactKind, identifier: &str) -> Vec<u8> {
+        format!("{}/{}", kind.as_str(), identifier).into_bytes()
+    }
+
+    fn secret_aad(kind: ArtifactKind, identifier: &str) -> Vec<u8> {
+        format!("{}/{}/secret", kind.as_str(), identifier).into_bytes()
+    }
+}
+
+impl StorageBackend for EncryptedStorage {
+    fn store(
+        &self,
+        kind: ArtifactKind,
+        identifier: &str,
+        artifact: &Artifact<SizedObjectsBytes>,
+    ) -> Result<(), Status> {
+        let data = artifact.data.read().unwrap().get().clone();
+        let sealed = Artifact {
+            data: Arc::new(RwLock::new(
+                self.key.seal(&Self::aad(kind, identifier), data)?.into(),
+            )),
+            name: artifact.name.clone(),
+            description: artifact.description.clone(),
+            secret: self
+                .key
+                .seal(&Self::secret_aad(kind, identifier), artifact.secret.clone())?,
+            meta: artifact.meta.clone(),
+            client_info: artifact.client_info.clone(),
+            expires_at: artifact.expires_at,
+            tags: artifact.tags.clone(),
+            owner: artifact.owner.clone(),
+            created_at: artifact.created_at,
+            license: artifact.license.clone(),
+        };
+        self.inner.store(kind, identifier, &sealed)
+    }
+
+    fn load(
+        &self,
+        kind: ArtifactKind,
+        identifier: &str,
+    ) -> Result<Option<Artifact<SizedObjectsBytes>>, Status> {
+        let mut artifact = match self.inner.load(kind, identifier)? {
+            Some(artifact) => artifact,
+            None => return Ok(None),
+        };
+        let data: Vec<u8> = Arc::try_unwrap(artifact.data)
+            .unwrap()
+            .into_inner()
+            .unwrap()
+            .into();
+        artifact.data = Arc::new(RwLock::new(
+            self.key.open(&Self::aad(kind, identifier), data)?.into(),
+        ));
+        // Artifacts stored before secrets were persisted have none.
+        if !artifact.secret.is_empty() {
+            artifact.secret = self
+                .key
+                .open(&Self::secret_aad(kind, identifier), artifact.secret)?;
+        }
+        Ok(Some(artifact))
+    }
+
+    fn remove(&self, kind: ArtifactKind, identifier: &str) -> Result<(), Status> {
+        self.inner.remove(kind, identifier)
+    }
+
+    fn list(&self, kind: ArtifactKind) -> Result<Vec<String>, Status> {
+        self.inner.list(kind)
+    }
+
+    fn expiry(&self, kind: ArtifactKind, identifier: &str) -> Result<Option<SystemTime>, Status> {
+        self.inner.expiry(kind, identifier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_keep_the_secret_and_client_info() {
+        let mut artifact =
+            Artifact::<()>::default().with_data(SizedObjectsBytes::from(vec![1, 2, 3]));
+        artifact.secret = vec![4, 5, 6];
+        artifact.client_info = Some(ClientInfo {
+            uid: String::from("uid"),
+            platform_name: String::from("Linux"),
+            ..Default::default()
+        });
+        let header = ArtifactHeader::new(&artifact).to_json().unwrap();
+        let restored = ArtifactHeader::from_json(&header)
+            .unwrap()
+            .into_artifact(vec![1, 2, 3])
+            .unwrap();
+        assert_eq!(restored.secret, artifact.secret);
+        assert_eq!(restored.client_info, artifact.client_info);
+        assert_eq!(restored.data.read().unwrap().get(), &vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::license::License;
+use crate::serialization::{expiry_from_ttl, verify_sha256};
+use crate::storage::Artifact;
+use crate::torch_proto::Chunk;
+use bastionlab_common::compression::{check_upload_size, ChunkEncoding};
+use bastionlab_common::prelude::*;
+use bastionlab_learning::serialization::SizedObjectsBytes;
+use std::time::{Duration, Instant, SystemTime};
+use tokio_stream::StreamExt;
+use tonic::{Request, Status};
+use uuid::Uuid;
+
+/// Request metadata key identifying the upload session a stream of chunks belongs to.
+pub const UPLOAD_ID_KEY: &str = "upload-id";
+
+/// Uploads that have not received any chunk for this long are dropped.
+const UPLOAD_EXPIRY: Duration = Duration::from_secs(3600);
+
+/// Data received so far for an upload session.
+///
+/// Every chunk of a resumable upload carries the offset of its data in the
+/// whole upload, which allows a client to resume an interrupted upload by
+/// sending the chunks starting from [`PartialUpload::offset`] again.
+#[derive(Debug)]
+pub struct PartialUpload {
+    owner: String,
+    data: Vec<u8>,
+    name: String,
+    description: String,
+    secret: Vec<u8>,
+    meta: Vec<u8>,
+    sha256: String,
+    ttl_seconds: u64,
+    license: String,
+    last_update: Instant,
+}
+
+impl PartialUpload {
+    fn new(owner: String) -> Self {
+        PartialUpload {
+            owner,
+            data: Vec::new(),
+            name: String::new(),
+            description: String::new(),
+            secret: Vec::new(),
+            meta: Vec::new(),
+            sha256: String::new(),
+            ttl_seconds: 0,
+            license: String::new(),
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Returns the number of bytes received so far.
+    pub fn offset(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    fn append(&mut self, chunk: Chunk, max_size: Option<usize>) -> Result<(), Status> {
+        let end = usize::try_from(chunk.offset)
+            .ok()
+            .and_then(|offset| offset.checked_add(chunk.data.len()))
+            .ok_or_else(|| Status::invalid_argument("Invalid upload offset"))?;
+        check_upload_size(end, max_size)?;
+        let offset = end - chunk.data.len();
+        if offset > self.data.len() {
+            return Err(Status::out_of_range(format!(
+                "Missing data between offsets {} and {}",
+                self.data.len(),
+                offset
+            )));
+        }
+        if offset == 0 {
+            self.name = chunk.name;
+            self.description = chunk.description;
+            self.secret = chunk.secret;
+            self.meta = chunk.meta;
+            self.sha256 = chunk.sha256;
+            self.ttl_seconds = chunk.ttl_seconds;
+            self.license = chunk.license;
+        }
+        // Chunks that were already received before an interruption may be sent again.
+        let already_received = self.data.len() - offset;
+        if already_received < chunk.data.len() {
+            self.data.extend_from_slice(&chunk.data[already_received..]);
+        }
+        self.last_update = Instant::now();
+        Ok(())
+    }
+
+    fn into_artifact(
+        self,
+        encoding: ChunkEncoding,
+        max_size: Option<usize>,
+    ) -> Result<Artifact<SizedObjectsBytes>, Status> {
+        let data = encoding.decode(self.data, max_size)?;
+        verify_sha256(&data, &self.sha256)?;
+        let license = License::parse(&self.license)?;
+        Ok(Artifact {
+            data: Arc::new(RwLock::new(data.into())),
+            name: self.name,
+            description: self.description,
+            secret: self.secret,
+            meta: self.meta,
+            client_info: Default::default(),
+            expires_at: expiry_from_ttl(self.ttl_seconds),
+            tags: HashMap::new(),
+            owner: None,
+            created_at: Some(SystemTime::now()),
+            license,
+        })
+    }
+}
+
+/// Keeps track of the resumable uploads in progress.
+#[derive(Debug, Default)]
+pub struct UploadManager {
+    uploads: Mutex<HashMap<String, PartialUpload>>,
+}
+
+impl UploadManager {
+    /// Opens a new upload session for `owner` and returns its identifier.
+    pub fn create(&self, owner: String) -> String {
+        let identifier = Uuid::new_v4().to_string();
+        let mut uploads = self.uploads.lock().unwrap();
+        uploads.retain(|_, upload| upload.last_update.elapsed() < UPLOAD_EXPIRY);
+        uploads.insert(identifier.clone(), PartialUpload::new(owner));
+        identifier
+    }
+
+    /// Returns the number of bytes received so far for upload `identifier`.
+    pub fn offset(&self, identifier: &str, owner: &str) -> Result<u64, Status> {
+        let uploads = self.uploads.lock().unwrap();
+        Ok(get_upload(&uploads, identifier, owner)?.offset())
+    }
+
+    /// Appends the chunks of `stream` to upload `identifier` and returns the complete artifact
+    /// once the stream ends.
+    ///
+    /// If the stream is interrupted, the data received so far is kept so that the upload can be resumed.
+    /// Uploads growing past `max_size` bytes are rejected.
+    pub async fn receive(
+        &self,
+        mut stream: tonic::Streaming<Chunk>,
+        encoding: ChunkEncoding,
+        identifier: &str,
+        owner: &str,
+        max_size: Option<usize>,
+    ) -> Result<Artifact<SizedObjectsBytes>, Status> {
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            let mut uploads = self.uploads.lock().unwrap();
+            uploads
+                .get_mut(identifier)
+                .filter(|upload| upload.owner == owner)
+                .ok_or_else(|| Status::not_found("Upload not found"))?
+                .append(chunk, max_size)?;
+        }
+
+        let upload = {
+            let mut uploads = self.uploads.lock().unwrap();
+            get_upload(&uploads, identifier, owner)?;
+            uploads.remove(identifier).unwrap()
+        };
+        upload.into_artifact(encoding, max_size)
+    }
+}
+
+fn get_upload<'a>(
+    uploads: &'a HashMap<String, PartialUpload>,
+    identifier: &str,
+    owner: &str,
+) -> Result<&'a PartialUpload, Status> {
+    uploads
+        .get(identifier)
+        .filter(|upload| upload.owner == owner)
+        .ok_or_else(|| Status::not_found("Upload not found"))
+}
+
+/// Returns the upload session identifier given in the metadata of `request`, if any.
+pub fn upload_id<T>(request: &Request<T>) -> Result<Option<String>, Status> {
+    request
+        .metadata()
+        .get(UPLOAD_ID_KEY)
+        .map(|value| {
+            value
+                .to_str()
+                .map(String::from)
+                .map_err(|_| Status::invalid_argument("Invalid upload identifier"))
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    fn chunk(offset: u64, data: &[u8]) -> Chunk {
+        Chunk {
+            offset,
+            data: data.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn chunks_resume_from_their_offset() {
+        let mut upload = PartialUpload::new(String::from("alice"));
+        upload.append(chunk(0, b"abc"), None).unwrap();
+        upload.append(chunk(1, b"bcde"), None).unwrap();
+        assert_eq!(upload.data, b"abcde");
+        let err = upload.append(chunk(7, b"x"), None).unwrap_err();
+        assert_eq!(err.code(), Code::OutOfRange);
+    }
+
+    #[test]
+    fn overflowing_offsets_are_rejected() {
+        let mut upload = PartialUpload::new(String::from("alice"));
+        upload.append(chunk(0, b"abc"), None).unwrap();
+        let err = upload.append(chunk(u64::MAX, b"abc"), None).unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert_eq!(upload.offset(), 3);
+    }
+}
@@ -1,9 +1,10 @@
-use bastionlab_common::session_proto::TensorMetaData;
+use bastionlab_common::session_proto::{ServerCapabilities, TensorMetaData};
 use serde::{Deserialize, Serialize};
-use tch::{Kind, TchError, Tensor};
+use tch::nn::{self, Module, OptimizerConfig};
+use tch::{Device, Kind, Reduction, TchError, Tensor};
 use tonic::Status;
 
-use crate::torch_proto::RemoteDatasetReference;
+use crate::torch_proto::{RemoteDatasetReference, TensorPreview};
 
 /// Converts a [`tch::TchError`]-based result into a [`tonic::Status`]-based one.
 pub fn tcherror_to_status<T>(input: Result<T, TchError>) -> Result<T, Status> {
@@ -44,31 +45,90 @@ impl From<RemoteDatasetReference> for RemoteDataset {
     }
 }
 
+/// Names of the dtypes accepted by the service, along with their kind.
+pub const KINDS: &[(&str, Kind)] = &[
+    ("Uint8", Kind::Uint8),
+    ("Int8", Kind::Int8),
+    ("Int16", Kind::Int16),
+    ("Int", Kind::Int),
+    ("Int64", Kind::Int64),
+    ("Half", Kind::Half),
+    ("Float", Kind::Float),
+    ("Float32", Kind::Float),
+    ("Float64", Kind::Double),
+    ("Double", Kind::Double),
+    ("ComplexHalf", Kind::ComplexHalf),
+    ("ComplexFloat", Kind::ComplexFloat),
+    ("ComplexDouble", Kind::ComplexDouble),
+    ("Bool", Kind::Bool),
+    ("QInt8", Kind::QInt8),
+    ("QUInt8", Kind::QUInt8),
+    ("QInt32", Kind::QInt32),
+    ("BFloat16", Kind::BFloat16),
+];
+
 pub fn get_kind(kind: &str) -> Result<Kind, Status> {
-    match kind {
-        "Uint8" => Ok(Kind::Uint8),
-        "Int8" => Ok(Kind::Int8),
-        "Int16" => Ok(Kind::Int16),
-        "Int" => Ok(Kind::Int),
-        "Int64" => Ok(Kind::Int64),
-        "Half" => Ok(Kind::Half),
-        "Float" => Ok(Kind::Float),
-        "Float32" => Ok(Kind::Float),
-        "Float64" => Ok(Kind::Double),
-        "Double" => Ok(Kind::Double),
-        "ComplexHalf" => Ok(Kind::ComplexHalf),
-        "ComplexFloat" => Ok(Kind::ComplexFloat),
-        "ComplexDouble" => Ok(Kind::ComplexDouble),
-        "Bool" => Ok(Kind::Bool),
-        "QInt8" => Ok(Kind::QInt8),
-        "QUInt8" => Ok(Kind::QUInt8),
-        "QInt32" => Ok(Kind::QInt32),
-        "BFloat16" => Ok(Kind::BFloat16),
-        &_ => {
-            return Err(Status::failed_precondition(format!(
-                "Unsupported Kind: {}",
-                kind
-            )));
+    KINDS
+        .iter()
+        .find(|(name, _)| *name == kind)
+        .map(|(_, kind)| *kind)
+        .ok_or_else(|| Status::failed_precondition(format!("Unsupported Kind: {}", kind)))
+}
+
+/// Version of LibTorch the service is built against, checked by `torch-sys` when building.
+pub const LIBTORCH_VERSION: &str = "1.13.0";
+
+/// Fills the LibTorch, CUDA and dtype fields of `capabilities`.
+pub fn torch_capabilities(capabilities: &mut ServerCapabilities) {
+    capabilities.torch_version = LIBTORCH_VERSION.to_string();
+    capabilities.cuda_available = tch::Cuda::is_available();
+    if capabilities.cuda_available {
+        capabilities.cuda_device_count = tch::Cuda::device_count() as i32;
+        capabilities.cuda_version = tch::utils::version_cudart();
+        capabilities.cudnn_version = tch::utils::version_cudnn();
+    }
+    capabilities.dtypes = KINDS.iter().map(|(name, _)| name.to_string()).collect();
+}
+
+/// Fits a tiny linear regression on a GPU if any, on the CPU otherwise, and fails if the loss
+/// does not decrease. Checks that LibTorch can run trainings before clients send any.
+pub fn training_self_test() -> Result<(), TchError> {
+    let device = Device::cuda_if_available();
+    let vs = nn::VarStore::new(device);
+    let linear = nn::linear(vs.root(), 2, 1, Default::default());
+    let mut optimizer = nn::Sgd::default().build(&vs, 0.1)?;
+    let inputs = Tensor::f_randn(&[16, 2], (Kind::Float, device))?;
+    let weights = Tensor::of_slice(&[1f32, -1.])
+        .f_view([2, 1])?
+        .to_device(device);
+    let targets = inputs.f_matmul(&weights)?;
+
+    let loss = || {
+        linear
+            .forward(&inputs)
+            .f_mse_loss(&targets, Reduction::Mean)
+    };
+    let initial_loss = loss()?.f_double_value(&[])?;
+    for _ in 0..20 {
+        optimizer.backward_step(&loss()?);
+    }
+    let final_loss = loss()?.f_double_value(&[])?;
+    if final_loss < initial_loss {
+        Ok(())
+    } else {
+        Err(TchError::Kind(format!(
+            "Training did not decrease the loss, from {initial_loss} to {final_loss}"
+        )))
+    }
+}
+
+impl From<&bastionlab_learning::data::TensorPreview> for TensorPreview {
+    fn from(preview: &bastionlab_learning::data::TensorPreview) -> Self {
+        TensorPreview {
+            shape: preview.shape.clone(),
+            dtype: format!("{:?}", preview.kind),
+            mean: preview.mean.clone(),
+            std: preview.std.clone(),
         }
     }
 }
@@ -0,0 +1,25 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=../protos");
+
+    // Only the file descriptors are used, to serve gRPC reflection.
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
+    tonic_build::configure()
+        .build_client(false)
+        .build_server(false)
+        .file_descriptor_set_path(out_dir.join("bastionlab_descriptor.bin"))
+        .compile(
+            &[
+                "../protos/bastionlab.proto",
+                "../protos/bastionlab_torch.proto",
+                "../protos/bastionlab_polars.proto",
+                "../protos/bastionlab_conversion.proto",
+            ],
+            &["../protos"],
+        )?;
+
+    Ok(())
+}
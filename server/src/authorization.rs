@@ -0,0 +1,65 @@
+use bastionlab_common::authorization::{method_name, method_of_path};
+use bastionlab_common::prelude::*;
+use http::Request;
+use prost::Message;
+use prost_types::FileDescriptorSet;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// gRPC method called by a request, in snake case, for the authorization policy checked by
+/// the interceptor of the services.
+#[derive(Clone, Debug)]
+pub struct GrpcMethod(pub String);
+
+/// Tags the requests to the wrapped services with the [`GrpcMethod`] they call.
+///
+/// Interceptors only see the metadata and extensions of requests, not their path.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GrpcMethodLayer;
+
+impl<S> Layer<S> for GrpcMethodLayer {
+    type Service = GrpcMethodTagger<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcMethodTagger { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GrpcMethodTagger<S> {
+    inner: S,
+}
+
+impl<S, B> Service<Request<B>> for GrpcMethodTagger<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        if let Some(method) = method_of_path(req.uri().path()) {
+            req.extensions_mut().insert(GrpcMethod(method));
+        }
+        self.inner.call(req)
+    }
+}
+
+/// Returns the names of the methods of the services described by the encoded file
+/// `descriptors`, in snake case like the authorization policy.
+pub fn served_methods(descriptors: &[u8]) -> Result<Vec<String>> {
+    let set = FileDescriptorSet::decode(descriptors).context("Decoding the file descriptors")?;
+    Ok(set
+        .file
+        .iter()
+        .flat_map(|file| &file.service)
+        .flat_map(|service| &service.method)
+        .filter_map(|method| method.name.as_deref())
+        .map(method_name)
+        .collect())
+}
@@ -0,0 +1,104 @@
+use bastionlab_common::capabilities::run_self_test;
+use bastionlab_common::config::{BastionLabConfig, OPTIONAL_SERVICES};
+use bastionlab_common::prelude::*;
+use bastionlab_common::session_proto::ServerCapabilities;
+
+/// Describes the server as set up by `config`, with the results of the self-tests of the
+/// enabled services, which are run on the spot.
+pub fn server_capabilities(config: &BastionLabConfig) -> ServerCapabilities {
+    let mut capabilities = ServerCapabilities {
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        services: OPTIONAL_SERVICES
+            .iter()
+            .filter(|service| config.service_enabled(service))
+            .map(|service| service.to_string())
+            .collect(),
+        authentication: String::from(if config.authentication_enabled() {
+            "public_keys"
+        } else {
+            "none"
+        }),
+        authorization_policy: config.authentication_enabled()
+            && !config.authorization_policy().is_empty(),
+        // The server does not run in an attested enclave yet.
+        attestation: String::from("none"),
+        limits: limits(config),
+        ..Default::default()
+    };
+    bastionlab_torch::torch_capabilities(&mut capabilities);
+
+    if config.service_enabled("torch") {
+        capabilities
+            .self_tests
+            .push(run_self_test("torch_training", || {
+                Ok(bastionlab_torch::training_self_test()?)
+            }));
+    }
+    if config.service_enabled("polars") {
+        capabilities
+            .self_tests
+            .push(run_self_test("polars_query", || {
+                Ok(bastionlab_polars::utils::query_self_test()?)
+            }));
+    }
+    capabilities
+}
+
+/// Limits set in `config`, by name, with their unit in the name unless they are counts.
+fn limits(config: &BastionLabConfig) -> HashMap<String, u64> {
+    let limits = [
+        (
+            "grpc_max_receive_message_size_in_bytes",
+            config
+                .grpc_max_receive_message_size()
+                .map(|size| size as u64),
+        ),
+        (
+            "grpc_max_send_message_size_in_bytes",
+            config.grpc_max_send_message_size().map(|size| size as u64),
+        ),
+        (
+            "grpc_max_concurrent_streams",
+            config.grpc_max_concurrent_streams().map(u64::from),
+        ),
+        (
+            "grpc_request_timeout_in_secs",
+            config
+                .grpc_request_timeout()
+                .map(|timeout| timeout.as_secs()),
+        ),
+        (
+            "max_dataset_upload_size_in_bytes",
+            config.max_dataset_upload_size().map(|size| size as u64),
+        ),
+        (
+            "max_model_upload_size_in_bytes",
+            config.max_model_upload_size().map(|size| size as u64),
+        ),
+        (
+            "max_dataframe_upload_size_in_bytes",
+            config.max_dataframe_upload_size().map(|size| size as u64),
+        ),
+        (
+            "chunk_size_in_bytes",
+            config.chunk_size().map(|size| size as u64),
+        ),
+        (
+            "max_chunk_size_in_bytes",
+            config.max_chunk_size().map(|size| size as u64),
+        ),
+        (
+            "torch_memory_budget_in_bytes",
+            config.torch_memory_budget().map(|budget| budget as u64),
+        ),
+        (
+            "training_threads",
+            config.training_threads().map(|threads| threads as u64),
+        ),
+        ("session_expiry_in_secs", config.session_expiry().ok()),
+    ];
+    limits
+        .into_iter()
+        .filter_map(|(name, limit)| Some((name.to_string(), limit?)))
+        .collect()
+}
@@ -0,0 +1,335 @@
+use crate::authorization::GrpcMethod;
+use crate::tls::{self, ReloadableIdentity};
+use crate::TokenValidator;
+use bastionlab_common::errors::error_code_name;
+use bastionlab_common::prelude::*;
+use bastionlab_common::session::SessionGrpcService;
+use bastionlab_common::session_proto::{session_service_server::SessionService, ClientInfo, Empty};
+use bastionlab_polars::polars_proto::{polars_service_server::PolarsService, DataFrameQuery};
+use bastionlab_polars::BastionLabPolars;
+use bastionlab_torch::bastionlab::Reference;
+use bastionlab_torch::torch_proto::{torch_service_server::TorchService, ArtifactQuery};
+use bastionlab_torch::BastionLabTorch;
+use hyper::body::HttpBody;
+use hyper::header::{HeaderMap, AUTHORIZATION, CONTENT_TYPE};
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Method, StatusCode};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use tokio_stream::StreamExt;
+use tonic::metadata::{BinaryMetadataKey, MetadataMap, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::transport::server::Connected;
+use tonic::{Code, Request, Status};
+
+/// HTTP/JSON front of the session, listing and run status APIs, for tools without a gRPC client.
+///
+/// Endpoints:
+/// - `GET /v1/challenge`
+/// - `POST /v1/sessions` with a JSON `ClientInfo` body, and the `challenge` and
+///   `signature-<key hash>` headers (hex-encoded) when authentication is enabled
+/// - `GET /v1/models` and `GET /v1/datasets`, filtered with the `name_contains`, `owner`,
+///   `created_after`, `tag.<key>`, `page_size` and `page_token` query parameters
+/// - `GET /v1/dataframes`, filtered with `owner`, `created_after`, `page_size` and `page_token`
+/// - `GET /v1/runs/<identifier>`, for the runs of the caller, or any run for data owners
+///
+/// Authenticated endpoints expect the hex-encoded session token in an
+/// `Authorization: Bearer <token>` header. Request bodies are limited to [`MAX_BODY_SIZE`]
+/// bytes.
+pub struct Gateway {
+    pub(crate) session: SessionGrpcService,
+    pub(crate) torch: BastionLabTorch,
+    pub(crate) polars: BastionLabPolars,
+    pub(crate) token_validator: TokenValidator,
+}
+
+/// Largest request body accepted by the gateway, far above the size of a `ClientInfo`.
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// Serves `gateway` over HTTPS on `addr` until the process exits.
+pub async fn serve(
+    addr: SocketAddr,
+    identity: Arc<ReloadableIdentity>,
+    gateway: Arc<Gateway>,
+) -> Result<()> {
+    let mut incoming = tls::incoming(addr, identity, vec![b"http/1.1".to_vec()]).await?;
+    info!("REST gateway listening on {addr:?}.");
+    while let Some(stream) = incoming.next().await {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                debug!("Could not accept gateway connection: {e}");
+                continue;
+            }
+        };
+        let connect_info = stream.connect_info();
+        let gateway = Arc::clone(&gateway);
+        tokio::spawn(async move {
+            let svc = service_fn(move |req| {
+                let gateway = Arc::clone(&gateway);
+                let connect_info = connect_info.clone();
+                async move {
+                    let res = match gateway.route(req, connect_info).await {
+                        Ok(body) => json_response(StatusCode::OK, body),
+                        Err(status) => json_response(
+                            http_status(status.code()),
+                            json!({
+                                "code": format!("{:?}", status.code()),
+                                "error_code": error_code_name(&status),
+                                "message": status.message(),
+                            }),
+                        ),
+                    };
+                    Ok::<_, Infallible>(res)
+                }
+            });
+            if let Err(e) = Http::new()
+                .http1_only(true)
+                .serve_connection(stream, svc)
+                .await
+            {
+                debug!("Gateway connection closed: {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
+impl Gateway {
+    async fn route<C: Clone + Send + Sync + 'static>(
+        &self,
+        req: hyper::Request<Body>,
+        connect_info: C,
+    ) -> Result<Value, Status> {
+        let (parts, body) = req.into_parts();
+        let metadata = metadata_from_headers(&parts.headers)?;
+        let query: HashMap<String, String> = parts
+            .uri
+            .query()
+            .map(|q| form_urlencoded::parse(q.as_bytes()).into_owned().collect())
+            .unwrap_or_default();
+        let request = |message| {
+            let mut request = Request::new(message);
+            *request.metadata_mut() = metadata.clone();
+            request.extensions_mut().insert(connect_info.clone());
+            request
+        };
+        // Same checks as the interceptor of the gRPC services, for the method the endpoint calls
+        let authenticate = |method: &str| {
+            let mut request = request(());
+            request
+                .extensions_mut()
+                .insert(GrpcMethod(method.to_string()));
+            self.token_validator.clone().call(request).map(|_| ())
+        };
+
+        let segments: Vec<&str> = parts.uri.path().trim_matches('/').split('/').collect();
+        match (&parts.method, &segments[..]) {
+            (&Method::GET, ["v1", "challenge"]) => {
+                let challenge = self.session.get_challenge(request(Empty {})).await?;
+                Ok(json!({ "challenge": hex::encode(challenge.into_inner().value) }))
+            }
+            (&Method::POST, ["v1", "sessions"]) => {
+                let body = read_body(body).await?;
+                let client_info = parse_client_info(&body)?;
+                let session = self
+                    .session
+                    .create_session(request(client_info))
+                    .await?
+                    .into_inner();
+                Ok(json!({
+                    "token": hex::encode(session.token),
+                    "expiry_time": session.expiry_time,
+                }))
+            }
+            (&Method::GET, ["v1", kind @ ("models" | "datasets")]) => {
+                authenticate(if *kind == "models" {
+                    "available_models"
+                } else {
+                    "available_datasets"
+                })?;
+                let query = artifact_query(&query)?;
+                let refs = if *kind == "models" {
+                    self.torch.available_models(request(query)).await?
+                } else {
+                    self.torch.available_datasets(request(query)).await?
+                }
+                .into_inner();
+                Ok(json!({
+                    "list": refs.list.iter().map(reference_json).collect::<Vec<_>>(),
+                    "next_page_token": refs.next_page_token,
+                }))
+            }
+            (&Method::GET, ["v1", "dataframes"]) => {
+                authenticate("list_data_frames")?;
+                let query = DataFrameQuery {
+                    owner: query.get("owner").cloned().unwrap_or_default(),
+                    created_after: parse_param(&query, "created_after")?,
+                    page_size: parse_param(&query, "page_size")?,
+                    page_token: query.get("page_token").cloned().unwrap_or_default(),
+                };
+                let refs = self
+                    .polars
+                    .list_data_frames(request(query))
+                    .await?
+                    .into_inner();
+                let list: Vec<_> = refs
+                    .list
+                    .iter()
+                    .map(|r| {
+                        json!({
+                            "identifier": r.identifier,
+                            "header": serde_json::from_str::<Value>(&r.header)
+                                .unwrap_or_else(|_| Value::String(r.header.clone())),
+                        })
+                    })
+                    .collect();
+                Ok(json!({ "list": list, "next_page_token": refs.next_page_token }))
+            }
+            (&Method::GET, ["v1", "runs", identifier]) => {
+                authenticate("get_metric")?;
+                // GetMetric only shows other users' runs to data owners.
+                let run = Reference {
+                    identifier: identifier.to_string(),
+                    ..Default::default()
+                };
+                let metric = self.torch.get_metric(request(run)).await?.into_inner();
+                Ok(json!({
+                    "value": metric.value,
+                    "uncertainty": metric.uncertainty,
+                    "batch": metric.batch,
+                    "epoch": metric.epoch,
+                    "nb_epochs": metric.nb_epochs,
+                    "nb_batches": metric.nb_batches,
+                }))
+            }
+            _ => Err(Status::not_found("No such endpoint")),
+        }
+    }
+}
+
+/// Maps the gateway headers to the metadata expected by the gRPC services.
+fn metadata_from_headers(headers: &HeaderMap) -> Result<MetadataMap, Status> {
+    let mut metadata = MetadataMap::new();
+    for (name, value) in headers {
+        let name = name.as_str();
+        let key = if name == AUTHORIZATION.as_str() {
+            String::from("accesstoken-bin")
+        } else if name == "challenge" || name.starts_with("signature-") {
+            format!("{name}-bin")
+        } else {
+            continue;
+        };
+        let value = value
+            .to_str()
+            .map_err(|_| Status::invalid_argument(format!("Invalid {name} header")))?;
+        let value = value.strip_prefix("Bearer ").unwrap_or(value);
+        let value = hex::decode(value.trim())
+            .map_err(|_| Status::invalid_argument(format!("{name} header must be hex-encoded")))?;
+        let key = BinaryMetadataKey::from_bytes(key.as_bytes())
+            .map_err(|_| Status::invalid_argument(format!("Invalid {name} header")))?;
+        metadata.insert_bin(key, MetadataValue::from_bytes(&value));
+    }
+    Ok(metadata)
+}
+
+/// Reads `body`, failing once it grows past [`MAX_BODY_SIZE`].
+async fn read_body(mut body: Body) -> Result<Vec<u8>, Status> {
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk =
+            chunk.map_err(|e| Status::invalid_argument(format!("Could not read body: {e}")))?;
+        if data.len() + chunk.len() > MAX_BODY_SIZE {
+            return Err(Status::resource_exhausted(format!(
+                "Request body is larger than the limit of {MAX_BODY_SIZE} bytes"
+            )));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+fn parse_client_info(body: &[u8]) -> Result<ClientInfo, Status> {
+    let body: Value = if body.is_empty() {
+        json!({})
+    } else {
+        serde_json::from_slice(body)
+            .map_err(|e| Status::invalid_argument(format!("Invalid JSON body: {e}")))?
+    };
+    let field = |name: &str| body[name].as_str().unwrap_or_default().to_string();
+    Ok(ClientInfo {
+        uid: field("uid"),
+        platform_name: field("platform_name"),
+        platform_arch: field("platform_arch"),
+        platform_version: field("platform_version"),
+        platform_release: field("platform_release"),
+        user_agent: field("user_agent"),
+        user_agent_version: field("user_agent_version"),
+        is_colab: body["is_colab"].as_bool().unwrap_or(false),
+    })
+}
+
+fn artifact_query(query: &HashMap<String, String>) -> Result<ArtifactQuery, Status> {
+    Ok(ArtifactQuery {
+        name_contains: query.get("name_contains").cloned().unwrap_or_default(),
+        tags: query
+            .iter()
+            .filter_map(|(k, v)| Some((k.strip_prefix("tag.")?.to_string(), v.clone())))
+            .collect(),
+        owner: query.get("owner").cloned().unwrap_or_default(),
+        created_after: parse_param(query, "created_after")?,
+        page_size: parse_param(query, "page_size")?,
+        page_token: query.get("page_token").cloned().unwrap_or_default(),
+    })
+}
+
+fn parse_param<T: std::str::FromStr + Default>(
+    query: &HashMap<String, String>,
+    name: &str,
+) -> Result<T, Status> {
+    match query.get(name) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| Status::invalid_argument(format!("Invalid {name} parameter"))),
+        None => Ok(T::default()),
+    }
+}
+
+fn reference_json(reference: &Reference) -> Value {
+    json!({
+        "identifier": reference.identifier,
+        "name": reference.name,
+        "description": reference.description,
+        "tags": reference.tags,
+    })
+}
+
+fn json_response(status: StatusCode, body: Value) -> hyper::Response<Body> {
+    hyper::Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// HTTP status of a gRPC error, as in the gRPC-HTTP mapping used by transcoding gateways.
+fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+            StatusCode::BAD_REQUEST
+        }
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::Cancelled => StatusCode::from_u16(499).unwrap(),
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
@@ -0,0 +1,191 @@
+use bytes::Bytes;
+use http::{HeaderMap, Request, Response};
+use http_body::Body as HttpBody;
+use hyper::Body;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio_stream::StreamExt;
+use tonic::Status;
+use tower::{Layer, Service};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Length of the prefix of each gRPC message: a compression flag and a big-endian length.
+const MESSAGE_PREFIX_LEN: usize = 5;
+
+/// Follows the length-prefixed messages of a gRPC body, frame by frame.
+struct MessageSizeCheck {
+    max_size: usize,
+    prefix: Vec<u8>,
+    remaining: usize,
+}
+
+impl MessageSizeCheck {
+    fn new(max_size: usize) -> Self {
+        MessageSizeCheck {
+            max_size,
+            prefix: Vec::with_capacity(MESSAGE_PREFIX_LEN),
+            remaining: 0,
+        }
+    }
+
+    /// Returns the length of the first message of `data` larger than the limit, if any.
+    fn check(&mut self, mut data: &[u8]) -> Result<(), usize> {
+        while !data.is_empty() {
+            if self.remaining > 0 {
+                let n = self.remaining.min(data.len());
+                self.remaining -= n;
+                data = &data[n..];
+                continue;
+            }
+            let n = (MESSAGE_PREFIX_LEN - self.prefix.len()).min(data.len());
+            self.prefix.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.prefix.len() == MESSAGE_PREFIX_LEN {
+                let len = u32::from_be_bytes([
+                    self.prefix[1],
+                    self.prefix[2],
+                    self.prefix[3],
+                    self.prefix[4],
+                ]) as usize;
+                self.prefix.clear();
+                if len > self.max_size {
+                    return Err(len);
+                }
+                self.remaining = len;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn too_large(direction: &str, len: usize, max_size: usize) -> Status {
+    Status::resource_exhausted(format!(
+        "{direction} message of {len} bytes is larger than the limit of {max_size} bytes"
+    ))
+}
+
+/// Rejects the gRPC messages received or sent by the wrapped services above the given sizes.
+///
+/// Tonic does not bound message sizes, so this is done on the raw HTTP/2 bodies.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessageSizeLimitLayer {
+    max_receive: Option<usize>,
+    max_send: Option<usize>,
+}
+
+impl MessageSizeLimitLayer {
+    pub fn new(max_receive: Option<usize>, max_send: Option<usize>) -> Self {
+        MessageSizeLimitLayer {
+            max_receive,
+            max_send,
+        }
+    }
+}
+
+impl<S> Layer<S> for MessageSizeLimitLayer {
+    type Service = MessageSizeLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MessageSizeLimit {
+            inner,
+            limits: *self,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MessageSizeLimit<S> {
+    inner: S,
+    limits: MessageSizeLimitLayer,
+}
+
+impl<S, B> Service<Request<Body>> for MessageSizeLimit<S>
+where
+    S: Service<Request<Body>, Response = Response<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<LimitedBody<B>>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let req = match self.limits.max_receive {
+            Some(max_size) => {
+                let (parts, body) = req.into_parts();
+                let mut check = MessageSizeCheck::new(max_size);
+                let body = Body::wrap_stream(body.map(move |chunk| {
+                    let chunk = chunk?;
+                    check
+                        .check(&chunk)
+                        .map_err(|len| too_large("Received", len, max_size))?;
+                    Ok::<_, BoxError>(chunk)
+                }));
+                Request::from_parts(parts, body)
+            }
+            None => req,
+        };
+        let max_send = self.limits.max_send;
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            Ok(res.map(|inner| LimitedBody {
+                inner,
+                check: max_send.map(MessageSizeCheck::new),
+            }))
+        })
+    }
+}
+
+/// Response body failing with `RESOURCE_EXHAUSTED` on the first message above the limit.
+pub struct LimitedBody<B> {
+    inner: B,
+    check: Option<MessageSizeCheck>,
+}
+
+impl<B> HttpBody for LimitedBody<B>
+where
+    B: HttpBody<Data = Bytes> + Unpin,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.get_mut();
+        let data = match Pin::new(&mut this.inner).poll_data(cx) {
+            Poll::Ready(Some(Ok(data))) => data,
+            Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e.into()))),
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+        if let Some(check) = &mut this.check {
+            if let Err(len) = check.check(&data) {
+                let max_size = check.max_size;
+                return Poll::Ready(Some(Err(too_large("Sent", len, max_size).into())));
+            }
+        }
+        Poll::Ready(Some(Ok(data)))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.get_mut().inner)
+            .poll_trailers(cx)
+            .map_err(Into::into)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
@@ -1,27 +1,122 @@
-use bastionlab_common::config::BastionLabConfig;
+use bastionlab_common::config::{BastionLabConfig, OPTIONAL_SERVICES};
 use bastionlab_common::prelude::*;
 use bastionlab_common::{
     auth::KeyManagement,
-    session::SessionManager,
+    authorization::{AuthorizationPolicy, Role},
+    capabilities::CapabilitiesGrpcService,
+    encryption::{AtRestKey, ServerSigningKey},
+    notifications::{NotificationGrpcService, Notifier},
+    provenance::{ProvenanceGraph, ProvenanceGrpcService},
+    remote_array::RemoteArrayRegistry,
+    session::{SessionGrpcService, SessionManager},
+    session_proto::ErrorCode,
     telemetry::{self, TelemetryEventProps},
+    usage::UsageLog,
 };
 use bastionlab_polars::BastionLabPolars;
 use bastionlab_torch::BastionLabTorch;
 use std::collections::hash_map::DefaultHasher;
-use std::fs;
 use std::path::Path;
-use std::time::SystemTime;
-use tonic::transport::{Identity, Server, ServerTlsConfig};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+use tonic::transport::Server;
 use tonic::Status;
+use tonic_health::{server::HealthReporter, ServingStatus};
+
+mod tls;
+use tls::ReloadableIdentity;
+
+mod gateway;
+use gateway::Gateway;
+
+mod limits;
+use limits::MessageSizeLimitLayer;
+
+mod authorization;
+use authorization::{GrpcMethod, GrpcMethodLayer};
+
+mod capabilities;
+use capabilities::server_capabilities;
+
+/// How often each service is checked for deadlocks, and how long a check may take.
+const HEALTH_PROBE_INTERVAL: Duration = Duration::from_secs(10);
+const HEALTH_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often scheduled queries are checked, often enough not to skip any minute.
+const SCHEDULED_QUERIES_INTERVAL: Duration = Duration::from_secs(20);
+
+type Probe = Arc<dyn Fn() + Send + Sync>;
+
+/// Descriptors of all BastionLab protos, compiled by the build script.
+const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("bastionlab_descriptor");
+
+/// Periodically runs `probes` and reports the services whose probe does not return in time
+/// as not serving. The overall status (empty service name) is serving only if all are.
+async fn report_health(
+    mut reporter: HealthReporter,
+    probes: Vec<(&'static str, Probe)>,
+    shutting_down: Arc<AtomicBool>,
+) {
+    // A probe stuck on a deadlock is awaited again instead of piling up blocking threads.
+    let mut pending: Vec<Option<tokio::task::JoinHandle<()>>> =
+        probes.iter().map(|_| None).collect();
+    let mut interval = tokio::time::interval(HEALTH_PROBE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let mut all_serving = true;
+        let mut statuses = Vec::with_capacity(probes.len());
+        for ((name, probe), pending) in probes.iter().zip(pending.iter_mut()) {
+            let mut handle = pending.take().unwrap_or_else(|| {
+                let probe = Arc::clone(probe);
+                tokio::task::spawn_blocking(move || probe())
+            });
+            let serving = match tokio::time::timeout(HEALTH_PROBE_TIMEOUT, &mut handle).await {
+                // The probe panics if a lock is poisoned
+                Ok(res) => res.is_ok(),
+                Err(_) => {
+                    *pending = Some(handle);
+                    false
+                }
+            };
+            if !serving {
+                warn!("Health check failed for {name}");
+            }
+            all_serving &= serving;
+            statuses.push((*name, serving));
+        }
+        if shutting_down.load(Ordering::SeqCst) {
+            return;
+        }
+        for (name, serving) in statuses {
+            reporter
+                .set_service_status(name, serving_status(serving))
+                .await;
+        }
+        reporter
+            .set_service_status("", serving_status(all_serving))
+            .await;
+    }
+}
+
+fn serving_status(serving: bool) -> ServingStatus {
+    if serving {
+        ServingStatus::Serving
+    } else {
+        ServingStatus::NotServing
+    }
+}
 
 #[derive(Clone)]
-struct TokenValidator {
+pub(crate) struct TokenValidator {
     sess_manager: Arc<SessionManager>,
+    policy: Arc<AuthorizationPolicy>,
 }
 
 impl tonic::service::Interceptor for TokenValidator {
     fn call(&mut self, req: tonic::Request<()>) -> std::result::Result<tonic::Request<()>, Status> {
         if !self.sess_manager.auth_enabled() {
+            // Every request counts as a heartbeat.
+            let _ = self.sess_manager.heartbeat(None);
             return Ok(req);
         }
         let meta = req
@@ -36,23 +131,36 @@ impl tonic::service::Interceptor for TokenValidator {
         let mut tokens = self.sess_manager.sessions.write().expect("Poisoned lock");
 
         let session = tokens
-            .get(access_token.as_ref())
-            .ok_or_else(|| Status::aborted("Session not found!"))?;
+            .get_mut(access_token.as_ref())
+            .ok_or_else(|| ErrorCode::SessionNotFound.status("Session not found!"))?;
 
         let recv_ip = &req
             .remote_addr()
-            .ok_or_else(|| Status::aborted("User IP unavailable"))?;
+            .ok_or_else(|| ErrorCode::ClientAddressUnavailable.status("User IP unavailable"))?;
 
         // ip verification
         if session.user_ip.ip() != recv_ip.ip() {
-            return Err(Status::aborted("Unknown IP Address!"));
+            return Err(ErrorCode::ClientAddressMismatch.status("Unknown IP Address!"));
         }
 
         // expiry verification
         let curr_time = SystemTime::now();
         if curr_time > session.expiry {
-            tokens.remove(access_token.as_ref());
-            return Err(Status::aborted("Session Expired"));
+            drop(tokens);
+            self.sess_manager.end_session(access_token.as_ref());
+            return Err(ErrorCode::SessionExpired.status("Session Expired"));
+        }
+        session.last_seen = curr_time;
+        let user_id = session.pubkey.clone();
+        drop(tokens);
+
+        if let Some(GrpcMethod(method)) = req.extensions().get::<GrpcMethod>() {
+            let role = if self.sess_manager.verify_if_owner(&user_id)? {
+                Role::DataOwner
+            } else {
+                Role::DataScientist
+            };
+            self.policy.check(method, role)?;
         }
 
         Ok(req)
@@ -62,13 +170,11 @@ impl tonic::service::Interceptor for TokenValidator {
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
-    let config: BastionLabConfig =
-        toml::from_str(&fs::read_to_string("config.toml").context("Reading the config.toml file")?)
-            .context("Parsing the config.toml file")?;
-
-    let disable_authentication = !std::env::var("DISABLE_AUTHENTICATION").is_err();
+    let config_file =
+        std::env::var("BASTIONLAB_CONFIG_FILE").unwrap_or_else(|_| String::from("config.toml"));
+    let config = BastionLabConfig::load(Path::new(&config_file))?;
 
-    let keys = if !disable_authentication {
+    let keys = if config.authentication_enabled() {
         match KeyManagement::load_from_dir(Path::new(
             &config
                 .public_keys_directory()
@@ -89,17 +195,20 @@ async fn main() -> Result<()> {
         None
     };
 
-    let sess_manager: Arc<SessionManager> = Arc::new(SessionManager::new(
-        keys,
-        config
-            .session_expiry()
-            .context("Parsing the public session_expiry config")?,
-    ));
-    let server_cert =
-        fs::read("tls/host_server.pem").context("Reading the tls/host_server.pem file")?;
-    let server_key =
-        fs::read("tls/host_server.key").context("Reading the tls/host_server.key file")?;
-    let server_identity = Identity::from_pem(&server_cert, &server_key);
+    // Notifications, also sent by the session manager on authentication lockouts
+    let notifier = Notifier::start(config.webhook_hosts());
+
+    let sess_manager: Arc<SessionManager> = Arc::new(
+        SessionManager::new(
+            keys,
+            config
+                .session_expiry()
+                .context("Parsing the public session_expiry config")?,
+        )
+        .with_notifier(notifier.clone()),
+    );
+    let identity = ReloadableIdentity::load(&config.tls_cert_file(), &config.tls_key_file())
+        .context("Setting up TLS")?;
 
     //TODO: Change it when specifying the TEE will be available
     let tee_mode = String::from("None");
@@ -112,7 +221,9 @@ async fn main() -> Result<()> {
         String::from(format!("{:X}", hasher.finish()))
     };
 
-    if std::env::var("BASTIONLAB_DISABLE_TELEMETRY").is_err() {
+    let telemetry_info = (platform, uid, tee_mode);
+    if config.telemetry_enabled() {
+        let (platform, uid, tee_mode) = telemetry_info.clone();
         telemetry::setup(platform, uid, tee_mode).context("Setting up telemetry")?;
         info!("Telemetry is enabled.")
     } else {
@@ -120,71 +231,472 @@ async fn main() -> Result<()> {
     }
     telemetry::add_event(TelemetryEventProps::Started {}, None);
 
+    let policy = config.authorization_policy();
+    let unknown = policy.unknown_methods(&authorization::served_methods(FILE_DESCRIPTOR_SET)?);
+    if !unknown.is_empty() {
+        bail!(
+            "Invalid configuration: authorization: unknown methods {}",
+            unknown.join(", ")
+        );
+    }
+    if !policy.is_empty() && !config.authentication_enabled() {
+        warn!("The authorization policy is ignored as authentication is disabled.");
+    }
     let token_validator = TokenValidator {
         sess_manager: sess_manager.clone(),
+        policy: Arc::new(policy),
     };
     let mut builder = Server::builder()
-        .tls_config(ServerTlsConfig::new().identity(server_identity))
-        .context("Setting up TLS")?;
+        .max_concurrent_streams(config.grpc_max_concurrent_streams())
+        .http2_keepalive_interval(config.grpc_keepalive_interval())
+        .http2_keepalive_timeout(config.grpc_keepalive_timeout())
+        .layer(MessageSizeLimitLayer::new(
+            config.grpc_max_receive_message_size(),
+            config.grpc_max_send_message_size(),
+        ))
+        .layer(GrpcMethodLayer);
+    if let Some(timeout) = config.grpc_request_timeout() {
+        builder = builder.timeout(timeout);
+    }
 
     // Session
     let builder = {
-        use bastionlab_common::{
-            session::SessionGrpcService,
-            session_proto::session_service_server::SessionServiceServer,
-        };
+        use bastionlab_common::session_proto::session_service_server::SessionServiceServer;
         let svc = SessionGrpcService::new(sess_manager.clone());
         builder.add_service(SessionServiceServer::new(svc))
     };
 
-    // Torch
-    let torch_svc = BastionLabTorch::new(sess_manager.clone());
+    let at_rest_key = match config.at_rest_key_file() {
+        Some(path) => {
+            let key = AtRestKey::load_from_file(Path::new(&path))
+                .context("Loading the at-rest encryption key")?;
+            info!("Persisted data is encrypted at rest.");
+            Some(key)
+        }
+        None => None,
+    };
+
+    let signing_key = match config.signing_key_file() {
+        Some(path) => {
+            ServerSigningKey::load_from_file(Path::new(&path)).context("Loading the signing key")?
+        }
+        None => {
+            warn!("No signing key configured, generating one for this run.");
+            ServerSigningKey::generate()?
+        }
+    };
+
+    // Arrays and tensors, shared by the services
+    let remote_arrays = RemoteArrayRegistry::new();
+
+    // Uses of datasets and dataframes, reported to their owners
+    let usage_log = match config.usage_log() {
+        Some(path) => {
+            let log = UsageLog::open(Path::new(&path))?;
+            info!("Uses of datasets are recorded in {path}.");
+            log
+        }
+        None => UsageLog::default(),
+    };
+
+    // Derivations of the artifacts, to trace the data models derive from
+    let provenance = match config.provenance_log() {
+        Some(path) => {
+            let graph = ProvenanceGraph::open(Path::new(&path))?;
+            info!("Provenance of artifacts is recorded in {path}.");
+            graph
+        }
+        None => ProvenanceGraph::default(),
+    };
     let builder = {
-        use bastionlab_torch::torch_proto::torch_service_server::TorchServiceServer;
-        builder.add_service(TorchServiceServer::with_interceptor(
-            torch_svc.clone(),
+        use bastionlab_common::session_proto::provenance_service_server::ProvenanceServiceServer;
+        let svc = ProvenanceGrpcService::new(sess_manager.clone(), provenance.clone());
+        builder.add_service(ProvenanceServiceServer::with_interceptor(
+            svc,
             token_validator.clone(),
         ))
     };
 
-    // Polars
-    let polars_svc = BastionLabPolars::new(sess_manager.clone());
+    // Capabilities, with the results of the self-tests run on startup
     let builder = {
-        use bastionlab_polars::{
-            polars_proto::polars_service_server::PolarsServiceServer, BastionLabPolars,
-        };
-        let svc = BastionLabPolars::new(sess_manager.clone());
-        match BastionLabPolars::load_dfs(&svc) {
-            Ok(_) => info!("Successfully loaded saved dataframes"),
-            Err(_) => info!("There was an error loading saved dataframes"),
-        };
-        builder.add_service(PolarsServiceServer::with_interceptor(
-            polars_svc.clone(),
+        use bastionlab_common::session_proto::capabilities_service_server::CapabilitiesServiceServer;
+        let svc = CapabilitiesGrpcService::new(server_capabilities(&config));
+        builder.add_service(CapabilitiesServiceServer::with_interceptor(
+            svc,
+            token_validator.clone(),
+        ))
+    };
+
+    // Notifications
+    let builder = {
+        use bastionlab_common::session_proto::notification_service_server::NotificationServiceServer;
+        let svc = NotificationGrpcService::new(sess_manager.clone(), notifier.clone());
+        builder.add_service(NotificationServiceServer::with_interceptor(
+            svc,
             token_validator.clone(),
         ))
     };
 
+    // Torch
+    let torch_svc = {
+        use bastionlab_torch::runs::RunStore;
+        use bastionlab_torch::storage::{
+            s3::S3Storage, EncryptedStorage, FsStorage, StorageBackend,
+        };
+        use bastionlab_torch::DEFAULT_CHUNK_SIZE;
+        let svc = BastionLabTorch::new(sess_manager.clone(), remote_arrays.clone())
+            .with_notifier(notifier.clone())
+            .with_usage_log(usage_log.clone())
+            .with_provenance(provenance.clone())
+            .with_signing_key(signing_key);
+        let storage: Option<Arc<dyn StorageBackend>> =
+            match (config.artifacts_s3(), config.artifacts_directory()) {
+                (Some(s3), _) => {
+                    let storage = S3Storage::from_config(&s3)
+                        .map_err(|e| anyhow!("Setting up S3 artifact storage: {}", e.message()))?;
+                    info!("Torch artifacts are persisted in S3 bucket {}.", s3.bucket);
+                    Some(Arc::new(storage))
+                }
+                (None, Some(path)) => {
+                    let storage = FsStorage::new(&path)
+                        .with_context(|| anyhow!("Setting up artifacts directory: {path}"))?;
+                    info!("Torch artifacts are persisted in {path}.");
+                    Some(Arc::new(storage))
+                }
+                (None, None) => None,
+            };
+        let svc = match (storage, &at_rest_key) {
+            (Some(storage), Some(key)) => {
+                svc.with_storage(Arc::new(EncryptedStorage::new(storage, key.clone())))
+            }
+            (Some(storage), None) => svc.with_storage(storage),
+            (None, _) => svc,
+        };
+        let svc = match config.runs_database() {
+            Some(path) => {
+                let store = RunStore::open(Path::new(&path), at_rest_key.clone())
+                    .map_err(|e| anyhow!("Opening the run database: {}", e.message()))?;
+                info!("Runs are recorded in {path}.");
+                svc.with_run_store(store)
+            }
+            None => svc,
+        };
+        let svc = svc.with_max_upload_sizes(
+            config.max_dataset_upload_size(),
+            config.max_model_upload_size(),
+        );
+        let svc = match config.training_threads() {
+            Some(threads) => svc.with_training_threads(threads),
+            None => svc,
+        };
+        let svc = match config.torch_memory_budget() {
+            Some(budget) => svc.with_memory_budget(budget),
+            None => svc,
+        };
+        match (config.chunk_size(), config.max_chunk_size()) {
+            (None, None) => svc,
+            (chunk_size, max_chunk_size) => {
+                let chunk_size = chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+                svc.with_chunk_size(chunk_size, max_chunk_size.unwrap_or(chunk_size))
+            }
+        }
+    };
+    let builder = {
+        use bastionlab_torch::torch_proto::torch_service_server::TorchServiceServer;
+        builder.add_optional_service(config.service_enabled("torch").then(|| {
+            TorchServiceServer::with_interceptor(torch_svc.clone(), token_validator.clone())
+        }))
+    };
+
+    // Polars
+    let polars_svc = match &at_rest_key {
+        Some(key) => BastionLabPolars::new(sess_manager.clone(), remote_arrays.clone())
+            .with_at_rest_key(key.clone()),
+        None => BastionLabPolars::new(sess_manager.clone(), remote_arrays.clone()),
+    };
+    let polars_svc = polars_svc
+        .with_notifier(notifier.clone())
+        .with_usage_log(usage_log)
+        .with_provenance(provenance.clone());
+    let polars_svc = match config.max_dataframe_upload_size() {
+        Some(max_size) => polars_svc.with_max_upload_size(max_size),
+        None => polars_svc,
+    };
+    let builder = {
+        use bastionlab_polars::polars_proto::polars_service_server::PolarsServiceServer;
+        let enabled = config.service_enabled("polars");
+        if enabled {
+            match BastionLabPolars::load_dfs(&polars_svc) {
+                Ok(_) => info!("Successfully loaded saved dataframes"),
+                Err(_) => info!("There was an error loading saved dataframes"),
+            };
+            if let Err(e) = polars_svc.load_pseudonym_keys() {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    error!("Could not load pseudonymization keys: {}", e);
+                }
+            }
+            if let Err(e) = polars_svc.load_query_schedules() {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    error!("Could not load scheduled queries: {}", e);
+                }
+            }
+            if let Err(e) = polars_svc.load_saved_queries() {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    error!("Could not load saved queries: {}", e);
+                }
+            }
+        }
+        builder.add_optional_service(enabled.then(|| {
+            PolarsServiceServer::with_interceptor(polars_svc.clone(), token_validator.clone())
+        }))
+    };
+
+    // Artifacts whose time-to-live elapsed
+    {
+        let torch_svc = torch_svc.clone();
+        let polars_svc = polars_svc.clone();
+        let mut interval = tokio::time::interval(config.artifact_reap_interval());
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+                if let Err(e) = torch_svc.reap_expired() {
+                    error!("Could not delete expired Torch artifacts: {}", e.message());
+                }
+                if let Err(e) = polars_svc.reap_expired() {
+                    error!("Could not delete expired dataframes: {}", e);
+                }
+            }
+        });
+    }
+
+    // Scheduled queries
+    if config.service_enabled("polars") {
+        let polars_svc = polars_svc.clone();
+        let mut interval = tokio::time::interval(SCHEDULED_QUERIES_INTERVAL);
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+                polars_svc.run_scheduled_queries().await;
+            }
+        });
+    }
+
+    // Resources of the sessions that expired or whose client went silent
+    {
+        let sess_manager = sess_manager.clone();
+        let torch_svc = torch_svc.clone();
+        let polars_svc = polars_svc.clone();
+        let orphaned_timeout = config.orphaned_session_timeout();
+        let mut interval = tokio::time::interval(config.artifact_reap_interval());
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+                let ended = sess_manager.reap_sessions(orphaned_timeout);
+                torch_svc.release_session_resources(&ended);
+                polars_svc.release_session_resources(&ended);
+            }
+        });
+    }
+
     // Conversion
     let builder = {
         use bastionlab_conversion::{
             conversion_proto::conversion_service_server::ConversionServiceServer,
             converter::Converter,
         };
-        builder.add_service(ConversionServiceServer::with_interceptor(
-            Converter::new(Arc::new(torch_svc.clone()), Arc::new(polars_svc.clone())),
-            token_validator.clone(),
-        ))
+        builder.add_optional_service(config.service_enabled("conversion").then(|| {
+            ConversionServiceServer::with_interceptor(
+                Converter::new(
+                    Arc::new(torch_svc.clone()),
+                    Arc::new(polars_svc.clone()),
+                    sess_manager.clone(),
+                )
+                .with_provenance(provenance),
+                token_validator.clone(),
+            )
+        }))
+    };
+
+    // Health
+    let shutting_down = Arc::new(AtomicBool::new(false));
+    let (health_reporter, builder) = {
+        let (reporter, health_svc) = tonic_health::server::health_reporter();
+        let torch_probe: Probe = {
+            let torch_svc = torch_svc.clone();
+            Arc::new(move || torch_svc.probe())
+        };
+        let polars_probe: Probe = {
+            let polars_svc = polars_svc.clone();
+            Arc::new(move || polars_svc.probe())
+        };
+        let session_probe: Probe = {
+            let sess_manager = sess_manager.clone();
+            Arc::new(move || sess_manager.probe())
+        };
+        let conversion_probe: Probe = {
+            let (torch_probe, polars_probe) = (torch_probe.clone(), polars_probe.clone());
+            Arc::new(move || {
+                torch_probe();
+                polars_probe();
+            })
+        };
+        let probes = [
+            ("session", "bastionlab.SessionService", session_probe),
+            ("torch", "bastionlab_torch.TorchService", torch_probe),
+            ("polars", "bastionlab_polars.PolarsService", polars_probe),
+            (
+                "conversion",
+                "bastionlab_conversion.ConversionService",
+                conversion_probe,
+            ),
+        ]
+        .into_iter()
+        .filter(|(service, _, _)| *service == "session" || config.service_enabled(service))
+        .map(|(_, name, probe)| (name, probe))
+        .collect();
+        tokio::spawn(report_health(
+            reporter.clone(),
+            probes,
+            shutting_down.clone(),
+        ));
+        (reporter, builder.add_service(health_svc))
+    };
+
+    // Reflection
+    let builder = {
+        let svc = tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+            .build()
+            .context("Setting up gRPC reflection")?;
+        builder.add_service(svc)
     };
 
     let addr = config
         .client_to_enclave_untrusted_socket()
         .context("Parsing the client_to_enclave_untrusted_socket config")?;
 
+    let incoming = tls::incoming(addr, identity.clone(), vec![b"h2".to_vec()]).await?;
+
+    // REST gateway
+    if let Some(gateway_addr) = config.rest_gateway_socket()? {
+        let gateway = Arc::new(Gateway {
+            session: SessionGrpcService::new(sess_manager.clone()),
+            torch: torch_svc.clone(),
+            polars: polars_svc.clone(),
+            token_validator: token_validator.clone(),
+        });
+        let identity = identity.clone();
+        tokio::spawn(async move {
+            if let Err(e) = gateway::serve(gateway_addr, identity, gateway).await {
+                error!("REST gateway stopped: {e:#}");
+            }
+        });
+    }
+
     info!("BastionLab server listening on {addr:?}.");
+    for service in OPTIONAL_SERVICES {
+        if !config.service_enabled(service) {
+            info!("The {service} service is disabled.");
+        }
+    }
     info!("Server ready to take requests");
 
+    tokio::spawn(reload_on_sighup(
+        config_file,
+        identity,
+        torch_svc.clone(),
+        sess_manager.clone(),
+        telemetry_info,
+    ));
+
     // serve!
-    builder.serve(addr).await?;
+    let grace_period = config.shutdown_grace_period();
+    builder
+        .serve_with_incoming_shutdown(incoming, async move {
+            shutdown_signal().await;
+            // Load balancers stop routing new requests to the server while it drains
+            shutting_down.store(true, Ordering::SeqCst);
+            let mut health_reporter = health_reporter;
+            health_reporter
+                .set_service_status("", ServingStatus::NotServing)
+                .await;
+            info!("Shutting down, waiting up to {grace_period:?} for active trainings.");
+            if torch_svc.shutdown(grace_period).await {
+                info!("All trainings were checkpointed.");
+            } else {
+                warn!("Grace period elapsed with trainings still running, exiting anyway.");
+            }
+        })
+        .await?;
 
     Ok(())
 }
+
+/// Re-reads the config file and the TLS identity on every SIGHUP, and applies the values that
+/// can change while the server runs: TLS identity, chunk sizes, memory budget, session expiry
+/// (of new sessions) and telemetry. Other values only take effect after a restart.
+async fn reload_on_sighup(
+    config_file: String,
+    identity: Arc<ReloadableIdentity>,
+    torch_svc: BastionLabTorch,
+    sess_manager: Arc<SessionManager>,
+    telemetry_info: (String, String, String),
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut sighup = match signal(SignalKind::hangup()) {
+        Ok(sighup) => sighup,
+        Err(e) => {
+            error!("Could not listen for SIGHUP, configuration reloading is disabled: {e}");
+            return;
+        }
+    };
+    while sighup.recv().await.is_some() {
+        info!("Reloading {config_file}.");
+        let config = match BastionLabConfig::load(Path::new(&config_file)) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Keeping the current configuration: {e:#}");
+                continue;
+            }
+        };
+
+        if let Err(e) = identity.reload(&config.tls_cert_file(), &config.tls_key_file()) {
+            error!("Keeping the current TLS identity: {e:#}");
+        }
+        let chunk_size = config
+            .chunk_size()
+            .unwrap_or(bastionlab_torch::DEFAULT_CHUNK_SIZE);
+        torch_svc.set_chunk_size(chunk_size, config.max_chunk_size().unwrap_or(chunk_size));
+        if let Some(budget) = config.torch_memory_budget() {
+            if !torch_svc.set_memory_budget(budget) {
+                warn!("Enabling torch_memory_budget_in_mb requires a restart.");
+            }
+        }
+        sess_manager.set_session_expiry(config.session_expiry_in_secs);
+        if config.telemetry_enabled() && !telemetry::is_setup() {
+            let (platform, uid, tee_mode) = telemetry_info.clone();
+            if let Err(e) = telemetry::setup(platform, uid, tee_mode) {
+                error!("Could not set up telemetry: {e:#}");
+            }
+        }
+        telemetry::set_enabled(config.telemetry_enabled());
+        info!("Configuration reloaded.");
+    }
+}
+
+/// Resolves on SIGTERM (sent by orchestrators) or Ctrl-C.
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(sigterm) => sigterm,
+        Err(e) => {
+            error!("Could not listen for SIGTERM: {e}");
+            let _ignored = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = sigterm.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
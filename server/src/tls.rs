@@ -0,0 +1,109 @@
+use bastionlab_common::prelude::*;
+use rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use rustls::sign::{self, CertifiedKey};
+use rustls::{ClientHello, NoClientAuth, ResolvesServerCert, ServerConfig};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Time a client has to complete the TLS handshake, after which its connection is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// TLS identity of the server that can be replaced while it runs.
+///
+/// New connections use the current identity, established ones are left untouched.
+pub struct ReloadableIdentity(RwLock<CertifiedKey>);
+
+impl ReloadableIdentity {
+    pub fn load(cert_file: &str, key_file: &str) -> Result<Arc<Self>> {
+        Ok(Arc::new(ReloadableIdentity(RwLock::new(read_identity(
+            cert_file, key_file,
+        )?))))
+    }
+
+    /// Reads the identity from the files again, keeping the current one if they are invalid.
+    pub fn reload(&self, cert_file: &str, key_file: &str) -> Result<()> {
+        let identity = read_identity(cert_file, key_file)?;
+        *self.0.write().unwrap() = identity;
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for ReloadableIdentity {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<CertifiedKey> {
+        Some(self.0.read().unwrap().clone())
+    }
+}
+
+fn read_identity(cert_file: &str, key_file: &str) -> Result<CertifiedKey> {
+    let open = |path: &str| {
+        File::open(path)
+            .map(BufReader::new)
+            .with_context(|| anyhow!("Reading the {path} file"))
+    };
+    let chain =
+        certs(&mut open(cert_file)?).map_err(|_| anyhow!("Invalid certificate in {cert_file}"))?;
+    if chain.is_empty() {
+        bail!("No certificate in {cert_file}");
+    }
+    let mut keys = pkcs8_private_keys(&mut open(key_file)?)
+        .map_err(|_| anyhow!("Invalid private key in {key_file}"))?;
+    if keys.is_empty() {
+        keys = rsa_private_keys(&mut open(key_file)?)
+            .map_err(|_| anyhow!("Invalid private key in {key_file}"))?;
+    }
+    let key = keys
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("No private key in {key_file}"))?;
+    let key = sign::any_supported_type(&key)
+        .map_err(|_| anyhow!("Unsupported private key type in {key_file}"))?;
+    Ok(CertifiedKey::new(chain, Arc::new(key)))
+}
+
+/// Accepts TLS connections on `addr`, presenting the current `identity` to each new client
+/// and negotiating one of the application `protocols` (ALPN).
+pub async fn incoming(
+    addr: SocketAddr,
+    identity: Arc<ReloadableIdentity>,
+    protocols: Vec<Vec<u8>>,
+) -> Result<ReceiverStream<io::Result<TlsStream<TcpStream>>>> {
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config.cert_resolver = identity;
+    config.set_protocols(&protocols);
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| anyhow!("Listening on {addr}"))?;
+    let (tx, rx) = mpsc::channel(64);
+    tokio::spawn(async move {
+        while !tx.is_closed() {
+            let (stream, peer) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    error!("Could not accept connection: {e}");
+                    continue;
+                }
+            };
+            // Handshakes run concurrently so that a slow client does not hold the others
+            let (acceptor, tx) = (acceptor.clone(), tx.clone());
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        // ignore send() error: the server is shutting down
+                        let _ignored = tx.send(Ok(stream)).await;
+                    }
+                    Ok(Err(e)) => debug!("TLS handshake with {peer} failed: {e}"),
+                    Err(_) => debug!("TLS handshake with {peer} timed out"),
+                }
+            });
+        }
+    });
+    Ok(ReceiverStream::new(rx))
+}
@@ -303,6 +303,30 @@ class TestingConnection(unittest.TestCase):
 
         connection.close()
 
+    def test_cache_activations(self):
+        connection = Connection("localhost")
+        client = connection.client
+        X = torch.tensor([[0.0], [1.0], [0.5], [0.2]])
+        Y = torch.tensor([[0.0], [2.0], [1.0], [0.4]])
+
+        dataset = client.torch.RemoteDataset(
+            TensorDataset([X], Y),
+            name="1D Linear Regression",
+            description="Dummy 1D Linear Regression Dataset (param is 2)",
+        )
+        model = make_model(in_features=X.shape[-1], dtype=X.dtype)()
+        model_ref = client.torch.send_model(model, name="Linear 1x1")
+
+        activations = client.torch.cache_activations(model_ref, dataset, batch_size=2)
+
+        inputs = activations.inputs[0].to(torch.float64)
+        self.assertEqual(inputs.dtype, torch.float64)
+        self.assertEqual(list(inputs.shape), [4, 1])
+
+        client.torch.delete_dataset(activations)
+        client.torch.delete_dataset(dataset)
+        connection.close()
+
 
 if __name__ == "__main__":
     unittest.main()